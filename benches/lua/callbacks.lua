@@ -0,0 +1,15 @@
+-- Calls into builtin functions in a tight loop, where the cost of each call is dominated by passing
+-- arguments and results between Lua and the callback, or back through a continuation.
+local assert, pcall, max, type = assert, pcall, math.max, type
+local function pair(a, b)
+    return a, b
+end
+local sum = 0
+for i = 1, 1000000 do
+    local ok, a, b = pcall(pair, i, 1)
+    sum = sum + assert(a, b) + max(b, 3)
+    if type(i) == "number" then
+        sum = sum + 1
+    end
+end
+assert(sum == 500000500000 + 4000000)
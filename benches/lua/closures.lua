@@ -0,0 +1,36 @@
+-- Creates closures over loop locals while outer frames keep upvalues of their own open, so that
+-- opening and closing upvalues happens with many others already open further down the stack.
+local function counter()
+    local n = 0
+    return function()
+        n = n + 1
+        return n
+    end
+end
+
+local function inner(depth)
+    local a, b, c = depth, depth * 2, depth * 3
+    local function sum()
+        return a + b + c
+    end
+    if depth > 0 then
+        return sum() + inner(depth - 1)
+    end
+
+    local total = 0
+    for i = 1, 20000 do
+        local next = counter()
+        local x = i
+        local get = function() return x end
+        local set = function(v) x = v end
+        set(next() + get())
+        total = total + get()
+    end
+    return total + sum()
+end
+
+local result = 0
+for _ = 1, 20 do
+    result = inner(50)
+end
+assert(result == 200037650)
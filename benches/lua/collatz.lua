@@ -0,0 +1,19 @@
+-- Branch heavy integer loops, where every iteration ends in a comparison and a jump.
+local longest, start = 0, 0
+local n = 1
+while n < 300000 do
+    local x, steps = n, 0
+    while x ~= 1 do
+        if x % 2 == 0 then
+            x = x // 2
+        else
+            x = 3 * x + 1
+        end
+        steps = steps + 1
+    end
+    if steps > longest then
+        longest, start = steps, n
+    end
+    n = n + 1
+end
+assert(start == 230631 and longest == 442)
@@ -0,0 +1,45 @@
+//! Interpreter micro-benchmarks, run with `cargo bench --bench vm [NAME...]`.
+//!
+//! Each script is run several times on a fresh `Lua` instance, and the fastest run is reported.
+
+use std::env;
+use std::time::{Duration, Instant};
+
+use luster::Lua;
+
+const RUNS: usize = 3;
+
+const BENCHMARKS: &[(&str, &str)] = &[
+    ("callbacks", include_str!("lua/callbacks.lua")),
+    ("closures", include_str!("lua/closures.lua")),
+    ("collatz", include_str!("lua/collatz.lua")),
+    ("fib", include_str!("lua/fib.lua")),
+    ("loop", include_str!("lua/loop.lua")),
+    ("nbody", include_str!("lua/nbody.lua")),
+    ("spectral_norm", include_str!("lua/spectral_norm.lua")),
+];
+
+fn main() {
+    // Cargo passes `--bench` along with any filters given after `--`
+    let filters = env::args()
+        .skip(1)
+        .filter(|arg| !arg.starts_with("--"))
+        .collect::<Vec<_>>();
+
+    for &(name, source) in BENCHMARKS {
+        if !filters.is_empty() && !filters.iter().any(|filter| name.contains(filter.as_str())) {
+            continue;
+        }
+
+        let mut best = Duration::from_secs(u64::max_value());
+        for _ in 0..RUNS {
+            let mut lua = Lua::new();
+            let start = Instant::now();
+            if let Err(err) = lua.exec(source) {
+                panic!("benchmark {} failed: {}", name, err);
+            }
+            best = best.min(start.elapsed());
+        }
+        println!("{:<16}{:>10.2} ms", name, best.as_secs_f64() * 1000.0);
+    }
+}
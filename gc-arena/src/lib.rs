@@ -0,0 +1,18 @@
+#[doc(hidden)]
+pub use gc_arena_derive::*;
+
+mod arena;
+mod collect;
+mod collect_impl;
+mod context;
+mod gc;
+mod gc_cell;
+mod static_collect;
+mod types;
+
+pub use self::arena::*;
+pub use self::collect::*;
+pub use self::context::*;
+pub use self::gc::*;
+pub use self::gc_cell::*;
+pub use self::static_collect::*;
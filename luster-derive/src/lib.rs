@@ -0,0 +1,143 @@
+extern crate proc_macro;
+
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use syn::{
+    parse_macro_input, parse_quote, Data, DeriveInput, Fields, GenericParam, Generics, Index,
+    Lifetime, LifetimeDef, LitByteStr,
+};
+
+/// Derives `ToLua` for a struct, converting it into a table.  A struct with named fields becomes a
+/// table with a string key for each field, and a tuple struct becomes a sequence.
+#[proc_macro_derive(ToLua)]
+pub fn derive_to_lua(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let name = &input.ident;
+    let (gc, generics) = gc_generics(&input.generics, quote!(::luster::ToLua));
+    let (impl_generics, _, where_clause) = generics.split_for_impl();
+    let (_, ty_generics, _) = input.generics.split_for_impl();
+
+    let sets = match struct_fields(&input) {
+        Ok(fields) => fields
+            .iter()
+            .map(|(member, key)| {
+                quote! {
+                    table.set(mc, #key, ::luster::ToLua::to_lua(self.#member, mc)?)?;
+                }
+            })
+            .collect::<Vec<_>>(),
+        Err(err) => return err.into(),
+    };
+
+    let expanded = quote! {
+        impl #impl_generics ::luster::ToLua<#gc> for #name #ty_generics #where_clause {
+            fn to_lua(
+                self,
+                mc: ::gc_arena::MutationContext<#gc, '_>,
+            ) -> ::std::result::Result<::luster::Value<#gc>, ::luster::Error<#gc>> {
+                let table = ::luster::Table::new(mc);
+                #(#sets)*
+                ::std::result::Result::Ok(::luster::Value::Table(table))
+            }
+        }
+    };
+    expanded.into()
+}
+
+/// Derives `FromLua` for a struct, reading it from a table in the same form that `ToLua` writes.
+#[proc_macro_derive(FromLua)]
+pub fn derive_from_lua(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let name = &input.ident;
+    let (gc, generics) = gc_generics(&input.generics, quote!(::luster::FromLua));
+    let (impl_generics, _, where_clause) = generics.split_for_impl();
+    let (_, ty_generics, _) = input.generics.split_for_impl();
+
+    let gets = match struct_fields(&input) {
+        Ok(fields) => fields
+            .iter()
+            .map(|(member, key)| {
+                quote! {
+                    #member: ::luster::FromLua::from_lua(mc, table.get(#key))?,
+                }
+            })
+            .collect::<Vec<_>>(),
+        Err(err) => return err.into(),
+    };
+
+    let expanded = quote! {
+        impl #impl_generics ::luster::FromLua<#gc> for #name #ty_generics #where_clause {
+            fn from_lua(
+                mc: ::gc_arena::MutationContext<#gc, '_>,
+                value: ::luster::Value<#gc>,
+            ) -> ::std::result::Result<Self, ::luster::Error<#gc>> {
+                let table = <::luster::Table as ::luster::FromLua>::from_lua(mc, value)?;
+                ::std::result::Result::Ok(#name { #(#gets)* })
+            }
+        }
+    };
+    expanded.into()
+}
+
+// Returns the lifetime to use as `'gc`, which is the first lifetime parameter of the type or a new
+// one, along with the generics for the impl.  Every type parameter is bound by the derived trait.
+fn gc_generics(generics: &Generics, bound: TokenStream) -> (Lifetime, Generics) {
+    let mut generics = generics.clone();
+    let existing = generics.lifetimes().next().map(|def| def.lifetime.clone());
+    let gc = match existing {
+        Some(gc) => gc,
+        None => {
+            let gc = Lifetime::new("'gc", Span::call_site());
+            generics
+                .params
+                .insert(0, GenericParam::Lifetime(LifetimeDef::new(gc.clone())));
+            gc
+        }
+    };
+
+    let type_params = generics
+        .type_params()
+        .map(|param| param.ident.clone())
+        .collect::<Vec<_>>();
+    let where_clause = generics.make_where_clause();
+    for ident in type_params {
+        where_clause
+            .predicates
+            .push(parse_quote!(#ident: #bound<#gc>));
+    }
+    (gc, generics)
+}
+
+// Returns each field of a struct, along with the key of the table field it is stored in.
+fn struct_fields(input: &DeriveInput) -> Result<Vec<(TokenStream, TokenStream)>, TokenStream> {
+    let fields = match &input.data {
+        Data::Struct(data) => &data.fields,
+        _ => {
+            return Err(quote! {
+                compile_error!("ToLua and FromLua can only be derived for structs");
+            })
+        }
+    };
+
+    Ok(match fields {
+        Fields::Named(fields) => fields
+            .named
+            .iter()
+            .map(|field| {
+                let ident = field.ident.as_ref().unwrap();
+                let name = ident.to_string();
+                let name = name.trim_start_matches("r#");
+                let key = LitByteStr::new(name.as_bytes(), Span::call_site());
+                (quote!(#ident), quote!(::luster::String::new_static(#key)))
+            })
+            .collect(),
+        Fields::Unnamed(fields) => (0..fields.unnamed.len())
+            .map(|i| {
+                let index = Index::from(i);
+                let key = i as i64 + 1;
+                (quote!(#index), quote!(#key))
+            })
+            .collect(),
+        Fields::Unit => Vec::new(),
+    })
+}
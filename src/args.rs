@@ -0,0 +1,98 @@
+use gc_arena::MutationContext;
+
+use crate::stdlib::{
+    bad_argument, check_any, check_integer, check_number, check_string, check_table,
+};
+use crate::{BadArgument, Error, FromLua, String, Table, Value};
+
+/// The arguments given to a callback, along with the name of the callback, for converting each
+/// argument with an error message in the same form as PUC-Rio Lua, such as
+/// "bad argument #2 to 'insert' (number expected, got no value)".
+///
+/// Arguments are numbered from 0, but from 1 in error messages.
+pub struct Args<'gc, 'a> {
+    mc: MutationContext<'gc, 'a>,
+    function: &'a str,
+    values: Vec<Value<'gc>>,
+}
+
+impl<'gc, 'a> Args<'gc, 'a> {
+    pub fn new(
+        mc: MutationContext<'gc, 'a>,
+        function: &'a str,
+        values: Vec<Value<'gc>>,
+    ) -> Args<'gc, 'a> {
+        Args {
+            mc,
+            function,
+            values,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    pub fn values(&self) -> &[Value<'gc>] {
+        &self.values
+    }
+
+    pub fn into_values(self) -> Vec<Value<'gc>> {
+        self.values
+    }
+
+    /// Converts argument `n` with `FromLua`, converting `nil` if the argument is absent.
+    pub fn get<T: FromLua<'gc>>(&self, n: usize) -> Result<T, Error<'gc>> {
+        let value = self.values.get(n).cloned();
+        T::from_lua(self.mc, value.unwrap_or(Value::Nil)).map_err(|error| {
+            BadArgument {
+                index: n,
+                missing: value.is_none(),
+                error: Box::new(error),
+            }
+            .into_error(self.mc, self.function)
+        })
+    }
+
+    /// Converts argument `n` with `FromLua`, or returns `None` if it is `nil` or absent.
+    pub fn opt<T: FromLua<'gc>>(&self, n: usize) -> Result<Option<T>, Error<'gc>> {
+        match self.values.get(n) {
+            None | Some(Value::Nil) => Ok(None),
+            Some(_) => self.get(n).map(Some),
+        }
+    }
+
+    /// Returns argument `n`, which may be any value (including nil) but must be present.
+    pub fn check_any(&self, n: usize) -> Result<Value<'gc>, Error<'gc>> {
+        check_any(self.mc, &self.values, n, self.function)
+    }
+
+    pub fn check_table(&self, n: usize) -> Result<Table<'gc>, Error<'gc>> {
+        check_table(self.mc, &self.values, n, self.function)
+    }
+
+    /// Returns argument `n`, which must be a number or a string convertible to one.
+    pub fn check_number(&self, n: usize) -> Result<f64, Error<'gc>> {
+        check_number(self.mc, &self.values, n, self.function)
+    }
+
+    /// Returns argument `n`, which must be a number or string with an integer representation.
+    pub fn check_integer(&self, n: usize) -> Result<i64, Error<'gc>> {
+        check_integer(self.mc, &self.values, n, self.function)
+    }
+
+    /// Returns argument `n`, which must be a string or a number converted to a string.
+    pub fn check_string(&self, n: usize) -> Result<String<'gc>, Error<'gc>> {
+        check_string(self.mc, &self.values, n, self.function)
+    }
+
+    /// An error for argument `n` with the given message, in the same form as PUC-Rio Lua's
+    /// `luaL_argerror`.
+    pub fn error(&self, n: usize, message: &str) -> Error<'gc> {
+        bad_argument(self.mc, n, self.function, message)
+    }
+}
@@ -61,7 +61,8 @@ fn main() -> Result<(), Box<StdError>> {
         )
         .get_matches();
 
-    let file = io::buffered_read(File::open(matches.value_of("file").unwrap())?)?;
+    let file_name = matches.value_of("file").unwrap();
+    let file = io::buffered_read(File::open(file_name)?)?;
 
     if matches.is_present("parse") {
         let chunk = parser::parse_chunk(file, |s| s.as_ref().to_vec().into_boxed_slice())?;
@@ -69,7 +70,8 @@ fn main() -> Result<(), Box<StdError>> {
     } else {
         let mut lua = Lua::new();
         lua.mutate(|mc, root| -> Result<(), StaticError> {
-            let function = compile(mc, root.interned_strings, file).map_err(|e| e.to_static())?;
+            let function = compile(mc, root.interned_strings, &format!("@{}", file_name), file)
+                .map_err(|e| e.to_static())?;
             print_function_proto(&function);
             Ok(())
         })?;
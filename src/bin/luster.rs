@@ -6,9 +6,10 @@ use clap::{crate_authors, crate_description, crate_name, crate_version, App, Arg
 use rustyline::Editor;
 
 use gc_sequence::{self as sequence, SequenceExt, SequenceResultExt};
-use luster::{
-    compile, io, Closure, Error, Function, Lua, ParserError, StaticError, ThreadSequence,
-};
+use luster::{compile, io, Closure, Error, Function, Lua, StaticError, ThreadSequence};
+
+// How deeply nested tables are written out in full when printing the results of REPL lines.
+const REPL_DISPLAY_DEPTH: usize = 4;
 
 fn run_repl(lua: &mut Lua) {
     let mut editor = Editor::<()>::new();
@@ -27,15 +28,17 @@ fn run_repl(lua: &mut Lua) {
 
             match lua.sequence(move |root| {
                 sequence::from_fn_with(root, move |mc, root| {
-                    let result = compile(mc, root.interned_strings, line_clone.as_bytes());
+                    let result =
+                        compile(mc, root.interned_strings, "=stdin", line_clone.as_bytes());
                     let result = match result {
                         Ok(res) => Ok(res),
-                        err @ Err(Error::ParserError(ParserError::EndOfStream { expected: _ })) => {
-                            err
+                        Err(Error::SyntaxError(err)) if err.is_incomplete() => {
+                            Err(Error::SyntaxError(err))
                         }
                         Err(_) => compile(
                             mc,
                             root.interned_strings,
+                            "=stdin",
                             (String::new() + "return " + &line_clone).as_bytes(),
                         ),
                     };
@@ -51,23 +54,25 @@ fn run_repl(lua: &mut Lua) {
                 })
                 .map(|values| match values {
                     Ok(values) => {
-                        let output = values
-                            .iter()
-                            .map(|value| format!("{:?}", value))
-                            .collect::<Vec<_>>()
-                            .join("\t");
-                        Ok(output)
+                        let mut output = Vec::new();
+                        for (i, value) in values.iter().enumerate() {
+                            if i > 0 {
+                                output.push(b'\t');
+                            }
+                            value.display_deep(&mut output, REPL_DISPLAY_DEPTH).unwrap();
+                        }
+                        Ok(String::from_utf8_lossy(&output).into_owned())
                     }
                     Err(e) => Err(e.to_static()),
                 })
                 .boxed()
             }) {
-                err @ Err(StaticError::ParserError(ParserError::EndOfStream { expected: _ })) => {
+                Err(StaticError::SyntaxError(err)) if err.is_incomplete() => {
                     match line.chars().last() {
                         Some(c) => {
                             if c == '\n' {
                                 editor.add_history_entry(line);
-                                eprintln!("error: {}", err.err().unwrap());
+                                eprintln!("error: {}", StaticError::SyntaxError(err));
                                 break;
                             }
                             prompt = ">> ";
@@ -112,13 +117,15 @@ fn main() -> Result<(), Box<StdError>> {
         return Ok(());
     }
 
-    let file = io::buffered_read(File::open(matches.value_of("file").unwrap())?)?;
+    let file_name = matches.value_of("file").unwrap();
+    let chunk_name = format!("@{}", file_name);
+    let file = io::buffered_read(File::open(file_name)?)?;
 
     lua.sequence(|root| {
-        sequence::from_fn_with(root, |mc, root| {
+        sequence::from_fn_with(root, move |mc, root| {
             Ok(Closure::new(
                 mc,
-                compile(mc, root.interned_strings, file)?,
+                compile(mc, root.interned_strings, &chunk_name, file)?,
                 Some(root.globals),
             )?)
         })
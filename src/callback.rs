@@ -1,10 +1,16 @@
+use std::cell::RefCell;
 use std::fmt::{self, Debug};
+use std::future::Future;
 use std::hash::{Hash, Hasher};
+use std::rc::Rc;
 
 use gc_arena::{Collect, Gc, MutationContext, StaticCollect};
-use gc_sequence::{Sequence, SequenceExt};
+use gc_sequence::{self as sequence, Sequence, SequenceExt, SequenceResultExt};
 
-use crate::{Error, Function, Value};
+use crate::{
+    Error, FromMultiValue, Function, Root, RuntimeError, StaticError, String, Thread, ToMultiValue,
+    Value,
+};
 
 // Safe, does not implement drop
 #[derive(Collect)]
@@ -17,6 +23,15 @@ pub enum CallbackResult<'gc> {
         args: Vec<Value<'gc>>,
         continuation: Continuation<'gc>,
     },
+    /// Like `TailCall`, but if `function` raises an error, `handler` is called with the error value
+    /// before the call stack is unwound.  The first value returned by the handler replaces the error
+    /// given to the continuation.
+    TailCallWithHandler {
+        function: Function<'gc>,
+        args: Vec<Value<'gc>>,
+        handler: Function<'gc>,
+        continuation: Continuation<'gc>,
+    },
 }
 
 pub enum CallbackReturn<'gc> {
@@ -139,7 +154,9 @@ impl<'gc> Continuation<'gc> {
 }
 
 pub trait CallbackFn<'gc>: Collect {
-    fn call(&self, res: Vec<Value<'gc>>) -> CallbackReturn<'gc>;
+    /// Calls the callback from the given thread.  The thread is borrowed until the callback
+    /// returns, so it can only be inspected from a returned sequence.
+    fn call(&self, thread: Thread<'gc>, args: Vec<Value<'gc>>) -> CallbackReturn<'gc>;
 }
 
 #[derive(Clone, Copy, Collect)]
@@ -159,8 +176,8 @@ impl<'gc> Callback<'gc> {
         where
             F: 'static + Fn(Vec<Value<'gc>>) -> CallbackReturn<'gc>,
         {
-            fn call(&self, res: Vec<Value<'gc>>) -> CallbackReturn<'gc> {
-                self.0(res)
+            fn call(&self, _: Thread<'gc>, args: Vec<Value<'gc>>) -> CallbackReturn<'gc> {
+                self.0(args)
             }
         }
 
@@ -171,6 +188,16 @@ impl<'gc> Callback<'gc> {
     where
         C: 'gc + Collect,
         F: 'static + Fn(&C, Vec<Value<'gc>>) -> CallbackReturn<'gc>,
+    {
+        Callback::new_with_thread(mc, c, move |c, _, args| f(c, args))
+    }
+
+    /// Like `new_with`, but `f` is also given the thread that called the callback.  The thread
+    /// cannot be inspected until `f` returns, so this is most useful with `new_sequence_with_thread`.
+    pub fn new_with_thread<C, F>(mc: MutationContext<'gc, '_>, c: C, f: F) -> Callback<'gc>
+    where
+        C: 'gc + Collect,
+        F: 'static + Fn(&C, Thread<'gc>, Vec<Value<'gc>>) -> CallbackReturn<'gc>,
     {
         #[derive(Collect)]
         #[collect(empty_drop)]
@@ -179,10 +206,10 @@ impl<'gc> Callback<'gc> {
         impl<'gc, C, F> CallbackFn<'gc> for ContextCallbackFn<C, F>
         where
             C: 'gc + Collect,
-            F: 'static + Fn(&C, Vec<Value<'gc>>) -> CallbackReturn<'gc>,
+            F: 'static + Fn(&C, Thread<'gc>, Vec<Value<'gc>>) -> CallbackReturn<'gc>,
         {
-            fn call(&self, args: Vec<Value<'gc>>) -> CallbackReturn<'gc> {
-                (self.1).0(&self.0, args)
+            fn call(&self, thread: Thread<'gc>, args: Vec<Value<'gc>>) -> CallbackReturn<'gc> {
+                (self.1).0(&self.0, thread, args)
             }
         }
 
@@ -230,8 +257,116 @@ impl<'gc> Callback<'gc> {
         })
     }
 
-    pub fn call(&self, args: Vec<Value<'gc>>) -> CallbackReturn<'gc> {
-        self.0.call(args)
+    /// Like `new_sequence_with`, but `f` is also given the thread that called the callback, which
+    /// may be inspected (for example with `Thread::call_stack`) once the sequence is running.
+    pub fn new_sequence_with_thread<C, S, F>(
+        mc: MutationContext<'gc, '_>,
+        c: C,
+        f: F,
+    ) -> Callback<'gc>
+    where
+        C: 'gc + Collect,
+        S: 'gc + Sequence<'gc, Output = Result<CallbackResult<'gc>, Error<'gc>>>,
+        F: 'static + Fn(&C, Thread<'gc>, Vec<Value<'gc>>) -> Result<S, Error<'gc>>,
+    {
+        Callback::new_with_thread(mc, c, move |c, thread, args| match f(c, thread, args) {
+            Ok(seq) => CallbackReturn::Sequence(seq.boxed()),
+            Err(err) => CallbackReturn::Immediate(Err(err)),
+        })
+    }
+
+    /// Creates a callback from a function with typed arguments and results, such as
+    /// `Fn(MutationContext, (i64, Option<String>)) -> Result<(bool, i64), Error>`.
+    ///
+    /// The arguments are converted with `FromMultiValue`, and an argument that cannot be converted
+    /// raises a "bad argument" error naming its position and the function `name`.  The results
+    /// are converted with `ToMultiValue`.
+    pub fn new_typed<A, R, F>(
+        mc: MutationContext<'gc, '_>,
+        name: &'static str,
+        f: F,
+    ) -> Callback<'gc>
+    where
+        A: FromMultiValue<'gc>,
+        R: ToMultiValue<'gc>,
+        F: 'static + for<'a> Fn(MutationContext<'gc, 'a>, A) -> Result<R, Error<'gc>>,
+    {
+        let f = Rc::new(f);
+        Callback::new_sequence(mc, move |args| {
+            let f = f.clone();
+            Ok(sequence::from_fn_with(args, move |mc, args| {
+                let args = A::from_multi_value(mc, args).map_err(|err| err.into_error(mc, name))?;
+                Ok(CallbackResult::Return(f(mc, args)?.to_multi_value(mc)?))
+            }))
+        })
+    }
+
+    /// Like `new_typed`, but `f` returns a future, and the callback returns the results of the
+    /// future once it completes.  While it is waiting, the future is polled by `Lua::sequence` or
+    /// `Lua::resume_async`, so a script may wait on host IO without blocking other work done by
+    /// an async executor.
+    ///
+    /// An error from the future is raised as a `RuntimeError` with the error's message.
+    pub fn new_async<A, R, Fut, F>(
+        mc: MutationContext<'gc, '_>,
+        root: Root<'gc>,
+        name: &'static str,
+        f: F,
+    ) -> Callback<'gc>
+    where
+        A: FromMultiValue<'gc>,
+        R: 'static + ToMultiValue<'gc>,
+        Fut: 'static + Future<Output = Result<R, StaticError>>,
+        F: 'static + for<'a> Fn(MutationContext<'gc, 'a>, A) -> Result<Fut, Error<'gc>>,
+    {
+        let f = Rc::new(f);
+        Callback::new_sequence_with(mc, root.pending_future, move |pending_future, args| {
+            let f = f.clone();
+            Ok(sequence::from_fn_with(
+                (*pending_future, args),
+                move |mc, (pending_future, args)| {
+                    let args =
+                        A::from_multi_value(mc, args).map_err(|err| err.into_error(mc, name))?;
+                    let future = f(mc, args)?;
+                    let result = Rc::new(RefCell::new(None));
+                    let output = result.clone();
+                    *(pending_future.0).0.borrow_mut() = Some(Box::pin(async move {
+                        *output.borrow_mut() = Some(future.await);
+                    }));
+                    Ok(AwaitFuture(StaticCollect(result)))
+                },
+            )
+            .flatten_ok())
+        })
+    }
+
+    /// Calls the callback from the given thread, see `CallbackFn::call`.
+    pub fn call(&self, thread: Thread<'gc>, args: Vec<Value<'gc>>) -> CallbackReturn<'gc> {
+        self.0.call(thread, args)
+    }
+}
+
+// Waits for the result of the future started by an async callback, which is stored once the future
+// completes.
+#[derive(Collect)]
+#[collect(require_static)]
+struct AwaitFuture<R>(StaticCollect<Rc<RefCell<Option<Result<R, StaticError>>>>>);
+
+impl<'gc, R: 'static + ToMultiValue<'gc>> Sequence<'gc> for AwaitFuture<R> {
+    type Output = Result<CallbackResult<'gc>, Error<'gc>>;
+
+    fn step(&mut self, mc: MutationContext<'gc, '_>) -> Option<Self::Output> {
+        let result = (self.0).0.borrow_mut().take()?;
+        Some(match result {
+            Ok(results) => results.to_multi_value(mc).map(CallbackResult::Return),
+            Err(err) => {
+                let message = match err {
+                    StaticError::RuntimeError(message) => message,
+                    err => err.to_string(),
+                };
+                Err(RuntimeError(Value::String(String::new(mc, message.as_bytes()))).into())
+            }
+        })
     }
 }
 
@@ -1,10 +1,13 @@
 use std::error::Error as StdError;
 use std::fmt;
 use std::hash::{Hash, Hasher};
+use std::rc::Rc;
 
-use gc_arena::{Collect, Gc, GcCell, MutationContext};
+use gc_arena::{Collect, Gc, GcCell, MutationContext, StaticCollect};
 
-use crate::{Constant, OpCode, RegisterIndex, Table, Thread, UpValueIndex, Value};
+use crate::{
+    Constant, FunctionCoverage, OpCode, RegisterIndex, String, Table, Thread, UpValueIndex, Value,
+};
 
 #[derive(Debug, Collect, Clone, Copy, PartialEq, Eq)]
 #[collect(require_static)]
@@ -23,7 +26,46 @@ pub struct FunctionProto<'gc> {
     pub constants: Vec<Constant<'gc>>,
     pub opcodes: Vec<OpCode>,
     pub upvalues: Vec<UpValueDescriptor>,
+    /// The name of each upvalue, or empty if they have been stripped.
+    pub upvalue_names: Vec<String<'gc>>,
+    /// The local variables declared in this function in the order they were declared, or empty if
+    /// they have been stripped.
+    pub local_variables: Vec<LocalVariable<'gc>>,
     pub prototypes: Vec<Gc<'gc, FunctionProto<'gc>>>,
+    /// The name of the chunk this function was defined in, or `None` if it has been stripped.
+    pub chunk_name: Option<String<'gc>>,
+    /// The source line (starting at 1) of each opcode, or empty if it has been stripped.
+    pub line_info: Vec<u64>,
+    /// The source line where this function was defined, or 0 for the main function of a chunk.
+    pub line_defined: u64,
+    /// The source line where the definition of this function ended, or 0 for the main function of a
+    /// chunk.
+    pub last_line_defined: u64,
+    /// Where runs of each opcode are counted, if the function was compiled with a `Coverage`.
+    pub coverage: Option<StaticCollect<Rc<FunctionCoverage>>>,
+}
+
+impl<'gc> FunctionProto<'gc> {
+    /// Returns the `n`th local variable (starting at 1) in scope at the given opcode, counting in the
+    /// order they were declared.  The parameters are the locals in scope at the first opcode.
+    pub fn local_variable(&self, n: usize, pc: usize) -> Option<&LocalVariable<'gc>> {
+        self.local_variables
+            .iter()
+            .filter(|variable| variable.start_pc <= pc && pc < variable.end_pc)
+            .nth(n.checked_sub(1)?)
+    }
+}
+
+/// The name and scope of a local variable, for debugging.
+#[derive(Debug, Collect, Copy, Clone, PartialEq, Eq)]
+#[collect(require_copy)]
+pub struct LocalVariable<'gc> {
+    pub name: String<'gc>,
+    pub register: RegisterIndex,
+    /// The variable is in scope from the opcode at `start_pc` up to but not including the one at
+    /// `end_pc`.
+    pub start_pc: usize,
+    pub end_pc: usize,
 }
 
 #[derive(Debug, Collect, Copy, Clone)]
@@ -37,6 +79,29 @@ pub enum UpValueState<'gc> {
 #[collect(require_copy)]
 pub struct UpValue<'gc>(pub GcCell<'gc, UpValueState<'gc>>);
 
+impl<'gc> UpValue<'gc> {
+    /// The current value of this upvalue.
+    ///
+    /// Panics if the upvalue is open and its thread is currently borrowed, such as by a callback
+    /// running on it that has not returned yet.
+    pub fn get(self) -> Value<'gc> {
+        match *self.0.read() {
+            UpValueState::Open(thread, index) => thread.stack_value(index),
+            UpValueState::Closed(value) => value,
+        }
+    }
+
+    /// Sets the value of this upvalue.
+    ///
+    /// Panics in the same cases as `UpValue::get`.
+    pub fn set(self, mc: MutationContext<'gc, '_>, value: Value<'gc>) {
+        match &mut *self.0.write(mc) {
+            UpValueState::Open(thread, index) => thread.set_stack_value(mc, *index, value),
+            UpValueState::Closed(closed) => *closed = value,
+        }
+    }
+}
+
 #[derive(Debug, Collect)]
 #[collect(empty_drop)]
 pub struct ClosureState<'gc> {
@@ -111,4 +176,93 @@ impl<'gc> Closure<'gc> {
 
         Ok(Closure(Gc::allocate(mc, ClosureState { proto, upvalues })))
     }
+
+    /// Create a top-level closure from a prototype loaded from a precompiled chunk, which may have
+    /// been dumped from an inner function with any upvalues.  Like PUC-Rio Lua, the first upvalue
+    /// is set to the given environment and the rest are initialized to nil.
+    pub fn new_precompiled<E: Into<Value<'gc>>>(
+        mc: MutationContext<'gc, '_>,
+        proto: FunctionProto<'gc>,
+        environment: E,
+    ) -> Closure<'gc> {
+        let proto = Gc::allocate(mc, proto);
+        let environment = environment.into();
+        let upvalues = (0..proto.upvalues.len())
+            .map(|i| {
+                let value = if i == 0 { environment } else { Value::Nil };
+                UpValue(GcCell::allocate(mc, UpValueState::Closed(value)))
+            })
+            .collect();
+
+        Closure(Gc::allocate(mc, ClosureState { proto, upvalues }))
+    }
+
+    /// The current value of this closure's `_ENV` upvalue, which global variables are looked up
+    /// in, or `None` if it does not reference `_ENV`.
+    pub fn environment(self) -> Option<Value<'gc>> {
+        self.environment_upvalue().map(UpValue::get)
+    }
+
+    /// Sets the value of this closure's `_ENV` upvalue, returning false if it does not reference
+    /// `_ENV`.  The environment need not be a table, though indexing it must then go through its
+    /// metatable.  Like `debug.setupvalue`, other closures sharing the upvalue see the change too.
+    pub fn set_environment<E: Into<Value<'gc>>>(
+        self,
+        mc: MutationContext<'gc, '_>,
+        environment: E,
+    ) -> bool {
+        match self.environment_upvalue() {
+            Some(upvalue) => {
+                upvalue.set(mc, environment.into());
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Creates another closure of the same prototype with its own `_ENV` upvalue set to the given
+    /// environment, sharing every other upvalue with this closure.  This runs the same compiled
+    /// chunk against different globals without compiling it again, and without affecting this
+    /// closure.
+    pub fn with_environment<E: Into<Value<'gc>>>(
+        self,
+        mc: MutationContext<'gc, '_>,
+        environment: E,
+    ) -> Closure<'gc> {
+        let mut upvalues = self.0.upvalues.clone();
+        if let Some(index) = self.environment_index() {
+            upvalues[index] = UpValue(GcCell::allocate(
+                mc,
+                UpValueState::Closed(environment.into()),
+            ));
+        }
+        Closure(Gc::allocate(
+            mc,
+            ClosureState {
+                proto: self.0.proto,
+                upvalues,
+            },
+        ))
+    }
+
+    fn environment_upvalue(self) -> Option<UpValue<'gc>> {
+        self.environment_index().map(|index| self.0.upvalues[index])
+    }
+
+    // A top-level chunk marks its `_ENV` upvalue with its descriptor, but an inner function's can
+    // only be found by name, and so not at all once upvalue names are stripped.
+    fn environment_index(self) -> Option<usize> {
+        let proto = &self.0.proto;
+        proto
+            .upvalues
+            .iter()
+            .position(|&upvalue| upvalue == UpValueDescriptor::Environment)
+            .or_else(|| {
+                proto
+                    .upvalue_names
+                    .iter()
+                    .position(|name| name.as_bytes() == b"_ENV")
+            })
+            .filter(|&index| index < self.0.upvalues.len())
+    }
 }
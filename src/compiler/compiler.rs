@@ -1,22 +1,24 @@
 use std::collections::{HashMap, VecDeque};
 use std::error::Error as StdError;
+use std::string::String as StdString;
 use std::{fmt, iter, mem};
 
 use num_traits::cast;
 
-use gc_arena::{Collect, Gc, MutationContext};
+use gc_arena::{Collect, Gc, MutationContext, StaticCollect};
 
 use crate::parser::{
     AssignmentStatement, AssignmentTarget, BinaryOperator, Block, CallSuffix, Chunk,
     ConstructorField, Expression, FieldSuffix, ForStatement, FunctionCallStatement,
-    FunctionDefinition, FunctionStatement, HeadExpression, IfStatement, LocalFunctionStatement,
-    LocalStatement, PrimaryExpression, RecordKey, RepeatStatement, ReturnStatement,
-    SimpleExpression, Statement, SuffixPart, SuffixedExpression, TableConstructor, UnaryOperator,
-    WhileStatement,
+    FunctionDefinition, FunctionStatement, HeadExpression, IfStatement, LocalAttribute,
+    LocalFunctionStatement, LocalStatement, PrimaryExpression, RecordKey, RepeatStatement,
+    ReturnStatement, SimpleExpression, Statement, SuffixPart, SuffixedExpression, TableConstructor,
+    UnaryOperator, WhileStatement,
 };
 use crate::{
-    Constant, ConstantIndex16, ConstantIndex8, FunctionProto, OpCode, Opt254, PrototypeIndex,
-    RegisterIndex, String, UpValueDescriptor, UpValueIndex, VarCount,
+    Constant, ConstantIndex16, ConstantIndex8, Coverage, FunctionProto, LocalVariable, OpCode,
+    Opt254, PrototypeIndex, RegisterIndex, SizeHint, Span, String, SyntaxError, SyntaxErrorKind,
+    UpValueDescriptor, UpValueIndex, VarCount,
 };
 
 use super::operators::{
@@ -24,6 +26,7 @@ use super::operators::{
     simple_binop_const_fold, simple_binop_opcode, unop_const_fold, unop_opcode, BinOpCategory,
     ComparisonBinOp, RegisterOrConstant, ShortCircuitBinOp, SimpleBinOp,
 };
+use super::optimizer::optimize_opcodes;
 use super::register_allocator::RegisterAllocator;
 
 #[derive(Debug, Collect)]
@@ -35,10 +38,12 @@ pub enum CompilerError {
     Functions,
     Constants,
     OpCodes,
-    DuplicateLabel,
-    GotoInvalid,
-    JumpLocal,
+    DuplicateLabel(StdString),
+    GotoInvalid(StdString),
+    BreakOutsideLoop,
+    JumpLocal { label: StdString, local: StdString },
     JumpOverflow,
+    AssignToConst,
 }
 
 impl StdError for CompilerError {}
@@ -52,31 +57,69 @@ impl fmt::Display for CompilerError {
             CompilerError::Functions => write!(fmt, "too many inner functions"),
             CompilerError::Constants => write!(fmt, "too many constants"),
             CompilerError::OpCodes => write!(fmt, "too many opcodes"),
-            CompilerError::DuplicateLabel => write!(fmt, "label defined multiple times"),
-            CompilerError::GotoInvalid => write!(fmt, "goto target label not found"),
-            CompilerError::JumpLocal => write!(fmt, "jump into scope of new local variable"),
+            CompilerError::DuplicateLabel(ref label) => {
+                write!(fmt, "label '{}' already defined", label)
+            }
+            CompilerError::GotoInvalid(ref label) => {
+                write!(fmt, "no visible label '{}' for goto", label)
+            }
+            CompilerError::BreakOutsideLoop => write!(fmt, "break outside a loop"),
+            CompilerError::JumpLocal {
+                ref label,
+                ref local,
+            } => write!(
+                fmt,
+                "goto '{}' jumps into the scope of local '{}'",
+                label, local
+            ),
             CompilerError::JumpOverflow => write!(fmt, "jump offset overflow"),
+            CompilerError::AssignToConst => write!(fmt, "cannot assign to const variable"),
         }
     }
 }
 
+/// Compiles a parsed chunk.  The chunk name is recorded in every compiled prototype along with the
+/// line of each opcode.  Errors are located at the statement being compiled when they occurred,
+/// and the returned `SyntaxError` never has a chunk name.
 pub fn compile_chunk<'gc>(
     mc: MutationContext<'gc, '_>,
+    chunk_name: String<'gc>,
     chunk: &Chunk<String<'gc>>,
-) -> Result<FunctionProto<'gc>, CompilerError> {
+) -> Result<FunctionProto<'gc>, SyntaxError> {
+    compile_chunk_with(mc, chunk_name, chunk, None)
+}
+
+// Compiles a parsed chunk like `compile_chunk`, adding every compiled function to the given
+// coverage.
+pub(crate) fn compile_chunk_with<'gc>(
+    mc: MutationContext<'gc, '_>,
+    chunk_name: String<'gc>,
+    chunk: &Chunk<String<'gc>>,
+    coverage: Option<&Coverage>,
+) -> Result<FunctionProto<'gc>, SyntaxError> {
     let mut compiler = Compiler {
         mutation_context: mc,
-        current_function: CompilerFunction::start(&[], true)?,
+        chunk_name,
+        coverage,
+        current_function: CompilerFunction::default(),
         upper_functions: Vec::new(),
+        current_span: Span::default(),
     };
-    compiler.block(&chunk.block)?;
-    compiler.current_function.finish(mc)
+    compiler.chunk(chunk).map_err(|error| SyntaxError {
+        chunk_name: None,
+        span: compiler.current_span,
+        kind: SyntaxErrorKind::Compiler(error),
+    })
 }
 
 struct Compiler<'gc, 'a> {
     mutation_context: MutationContext<'gc, 'a>,
+    chunk_name: String<'gc>,
+    coverage: Option<&'a Coverage>,
     current_function: CompilerFunction<'gc>,
     upper_functions: Vec<CompilerFunction<'gc>>,
+    // The span of the innermost statement being compiled
+    current_span: Span,
 }
 
 #[derive(Default)]
@@ -91,7 +134,10 @@ struct CompilerFunction<'gc> {
 
     has_varargs: bool,
     fixed_params: u8,
-    locals: Vec<(String<'gc>, RegisterIndex)>,
+    locals: Vec<(String<'gc>, RegisterIndex, Option<LocalAttribute>)>,
+    // Debug information for every local declared so far, the `end_pc` of locals still in scope is
+    // not known yet
+    local_variables: Vec<LocalVariable<'gc>>,
 
     blocks: Vec<BlockDescriptor>,
     unique_jump_id: u64,
@@ -99,6 +145,9 @@ struct CompilerFunction<'gc> {
     pending_jumps: Vec<PendingJump<'gc>>,
 
     opcodes: Vec<OpCode>,
+    line_info: Vec<u64>,
+    // The line of the statement the function was defined in, or 0 for the main chunk
+    line_defined: u64,
 }
 
 #[derive(Debug)]
@@ -125,7 +174,14 @@ enum ExprDescriptor<'gc> {
         op: ShortCircuitBinOp,
         right: Box<ExprDescriptor<'gc>>,
     },
-    TableConstructor(Vec<(ExprDescriptor<'gc>, ExprDescriptor<'gc>)>),
+    // The fields of a table constructor and how many of them are positional array fields, along
+    // with a final multi-value array field which is expanded to all of its values, starting at the
+    // given array index.
+    TableConstructor(
+        Vec<(ExprDescriptor<'gc>, ExprDescriptor<'gc>)>,
+        i64,
+        Option<(i64, Box<ExprDescriptor<'gc>>)>,
+    ),
     TableField {
         table: Box<ExprDescriptor<'gc>>,
         key: Box<ExprDescriptor<'gc>>,
@@ -141,6 +197,9 @@ enum ExprDescriptor<'gc> {
         args: Vec<ExprDescriptor<'gc>>,
     },
     Concat(VecDeque<ExprDescriptor<'gc>>),
+    // A multi-value expression (a function call or `...`) in parentheses, which always produces
+    // exactly one value.
+    Truncated(Box<ExprDescriptor<'gc>>),
 }
 
 #[derive(Debug)]
@@ -177,6 +236,15 @@ struct BlockDescriptor {
     bottom_jump_target: usize,
     // True if any lower function has an upvalue reference to variables in this block
     owns_upvalues: bool,
+    // True if this block declares any to-be-closed variables
+    has_to_be_closed: bool,
+}
+
+impl BlockDescriptor {
+    // Exiting this block, or jumping out of it, must close upvalues and to-be-closed variables
+    fn needs_close(&self) -> bool {
+        self.owns_upvalues || self.has_to_be_closed
+    }
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -200,11 +268,29 @@ struct PendingJump<'gc> {
     // blocks are exited.
     block_index: usize,
     stack_top: u16,
-    // Whether there are any upvalues that will go out of scope when the jump takes place.
+    // Whether there are any upvalues or to-be-closed variables that will go out of scope when the
+    // jump takes place.
     close_upvalues: bool,
+    // The span of the statement that jumps, for reporting unresolved jumps
+    span: Span,
 }
 
 impl<'gc, 'a> Compiler<'gc, 'a> {
+    fn chunk(&mut self, chunk: &Chunk<String<'gc>>) -> Result<FunctionProto<'gc>, CompilerError> {
+        self.current_function = CompilerFunction::start(&[], true)?;
+        self.block(&chunk.block)?;
+
+        // The implicit return of the chunk is on its last line
+        let last_span = match &chunk.block.return_statement {
+            Some((_, span)) => Some(span),
+            None => chunk.block.statements.last().map(|(_, span)| span),
+        };
+        if let Some(last_span) = last_span {
+            self.set_span(Span::at(last_span.end));
+        }
+        self.finish_function(CompilerFunction::default())
+    }
+
     fn block(&mut self, block: &Block<String<'gc>>) -> Result<(), CompilerError> {
         self.enter_block();
         self.block_statements(block)?;
@@ -216,16 +302,17 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
             stack_bottom: self.current_function.register_allocator.stack_top(),
             bottom_jump_target: self.current_function.jump_targets.len(),
             owns_upvalues: false,
+            has_to_be_closed: false,
         });
     }
 
     fn exit_block(&mut self) -> Result<(), CompilerError> {
         let last_block = self.current_function.blocks.pop().unwrap();
 
-        while let Some((_, last)) = self.current_function.locals.last() {
+        while let Some((_, last, _)) = self.current_function.locals.last() {
             if last.0 as u16 >= last_block.stack_bottom {
                 self.current_function.register_allocator.free(*last);
-                self.current_function.locals.pop();
+                self.current_function.pop_local();
             } else {
                 break;
             }
@@ -234,7 +321,7 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
             .jump_targets
             .drain(last_block.bottom_jump_target..);
 
-        if last_block.owns_upvalues && !self.current_function.blocks.is_empty() {
+        if last_block.needs_close() && !self.current_function.blocks.is_empty() {
             self.current_function.opcodes.push(OpCode::Jump {
                 offset: 0,
                 close_upvalues: cast(last_block.stack_bottom)
@@ -244,7 +331,7 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
         }
 
         // Bring all the pending jumps outward one level, and mark them to close upvalues if this
-        // block owned any.
+        // block owned any or declared any to-be-closed variables.
         if !self.current_function.blocks.is_empty() {
             for pending_jump in self.current_function.pending_jumps.iter_mut().rev() {
                 if pending_jump.block_index < self.current_function.blocks.len() {
@@ -255,7 +342,7 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
                     pending_jump.stack_top >= self.current_function.register_allocator.stack_top()
                 );
                 pending_jump.stack_top = self.current_function.register_allocator.stack_top();
-                pending_jump.close_upvalues |= last_block.owns_upvalues;
+                pending_jump.close_upvalues |= last_block.needs_close();
             }
         }
 
@@ -276,7 +363,7 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
         } else {
             let mut last = block.statements.len();
             for i in (0..block.statements.len()).rev() {
-                match &block.statements[i] {
+                match &block.statements[i].0 {
                     Statement::Label(_) => {}
                     _ => break,
                 }
@@ -297,7 +384,11 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
         Ok(())
     }
 
-    fn statement(&mut self, statement: &Statement<String<'gc>>) -> Result<(), CompilerError> {
+    fn statement(
+        &mut self,
+        (statement, span): &(Statement<String<'gc>>, Span),
+    ) -> Result<(), CompilerError> {
+        let outer_span = self.set_span(*span);
         match statement {
             Statement::If(if_statement) => self.if_statement(if_statement),
             Statement::While(while_statement) => self.while_statement(while_statement),
@@ -316,22 +407,26 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
             Statement::Goto(goto_statement) => self.jump(JumpLabel::Named(goto_statement.name)),
             Statement::FunctionCall(function_call) => self.function_call_statement(function_call),
             Statement::Assignment(assignment) => self.assignment_statement(assignment),
-        }
+        }?;
+        self.set_span(outer_span);
+        Ok(())
     }
 
     fn return_statement(
         &mut self,
-        return_statement: &ReturnStatement<String<'gc>>,
+        (return_statement, span): &(ReturnStatement<String<'gc>>, Span),
     ) -> Result<(), CompilerError> {
+        let outer_span = self.set_span(*span);
         let mut returns = return_statement
             .returns
             .iter()
             .map(|arg| self.expression(arg))
             .collect::<Result<Vec<_>, CompilerError>>()?;
 
-        // A return of a single function call is a tail call, and this is the only thing
-        // in Lua that is considered a tail call
-        if returns.len() == 1 {
+        // A return of a single function or method call is a tail call, and this is the only thing
+        // in Lua that is considered a tail call.  To-be-closed variables must be closed after the
+        // call returns, so no tail call is possible while any are in scope.
+        if returns.len() == 1 && !self.to_be_closed_in_scope() {
             match returns.pop().unwrap() {
                 ExprDescriptor::FunctionCall { func, args } => {
                     let func = self.expr_discharge(*func, ExprDestination::PushNew)?;
@@ -341,6 +436,23 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
                         .push(OpCode::TailCall { func, args });
                     self.current_function.register_allocator.free(func);
 
+                    self.set_span(outer_span);
+                    return Ok(());
+                }
+                ExprDescriptor::MethodCall {
+                    table,
+                    method,
+                    args,
+                } => {
+                    let (base, args) = self.push_method_call(*table, *method, args)?;
+                    self.current_function
+                        .opcodes
+                        .push(OpCode::TailCall { func: base, args });
+                    self.current_function
+                        .register_allocator
+                        .pop_to(base.0 as u16);
+
+                    self.set_span(outer_span);
                     return Ok(());
                 }
                 other => {
@@ -357,6 +469,7 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
             count,
         });
 
+        self.set_span(outer_span);
         Ok(())
     }
 
@@ -434,7 +547,7 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
                     .register_allocator
                     .push(1)
                     .ok_or(CompilerError::Registers)?;
-                self.current_function.locals.push((*name, loop_var));
+                self.current_function.declare_local((*name, loop_var, None));
 
                 self.block_statements(body)?;
                 self.exit_block()?;
@@ -510,9 +623,11 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
                     .push(name_count)
                     .ok_or(CompilerError::Registers)?;
                 for i in 0..name_count {
-                    self.current_function
-                        .locals
-                        .push((names[i as usize], RegisterIndex(names_reg.0 + i)));
+                    self.current_function.declare_local((
+                        names[i as usize],
+                        RegisterIndex(names_reg.0 + i),
+                        None,
+                    ));
                 }
 
                 self.jump(loop_label)?;
@@ -621,12 +736,6 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
             name = *field;
         }
 
-        let table = if let Some(table) = table {
-            table
-        } else {
-            self.get_environment()?
-        };
-
         let proto = if function_statement.method.is_some() {
             let mut parameters = vec![String::new_static(b"self")];
             parameters.extend(&function_statement.definition.parameters);
@@ -644,11 +753,15 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
             )?
         };
 
-        self.set_table(
-            table,
-            ExprDescriptor::Constant(Constant::String(name)),
-            ExprDescriptor::Closure(proto),
-        )?;
+        if let Some(table) = table {
+            self.set_table(
+                table,
+                ExprDescriptor::Constant(Constant::String(name)),
+                ExprDescriptor::Closure(proto),
+            )?;
+        } else {
+            self.assign_variable(name, ExprDescriptor::Closure(proto))?;
+        }
 
         Ok(())
     }
@@ -659,6 +772,13 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
     ) -> Result<(), CompilerError> {
         let name_len = local_statement.names.len();
         let val_len = local_statement.values.len();
+        let local = |i: usize, register| {
+            (
+                local_statement.names[i],
+                register,
+                local_statement.attributes[i],
+            )
+        };
 
         if local_statement.values.is_empty() {
             let count = cast(name_len).ok_or(CompilerError::Registers)?;
@@ -672,8 +792,7 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
                 .push(OpCode::LoadNil { dest, count });
             for i in 0..name_len {
                 self.current_function
-                    .locals
-                    .push((local_statement.names[i], RegisterIndex(dest.0 + i as u8)));
+                    .declare_local(local(i, RegisterIndex(dest.0 + i as u8)));
             }
         } else {
             for i in 0..val_len {
@@ -688,20 +807,32 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
                     let dest = self.expr_push_count(expr, names_left)?;
 
                     for j in 0..names_left {
-                        self.current_function.locals.push((
-                            local_statement.names[val_len - 1 + j as usize],
+                        self.current_function.declare_local(local(
+                            val_len - 1 + j as usize,
                             RegisterIndex(dest.0 + j),
                         ));
                     }
                 } else {
                     let reg = self.expr_discharge(expr, ExprDestination::PushNew)?;
-                    self.current_function
-                        .locals
-                        .push((local_statement.names[i], reg));
+                    self.current_function.declare_local(local(i, reg));
                 }
             }
         }
 
+        let new_locals = self.current_function.locals.len() - name_len;
+        for &(_, register, attribute) in &self.current_function.locals[new_locals..] {
+            if attribute == Some(LocalAttribute::Close) {
+                self.current_function
+                    .opcodes
+                    .push(OpCode::ToBeClosed { value: register });
+                self.current_function
+                    .blocks
+                    .last_mut()
+                    .unwrap()
+                    .has_to_be_closed = true;
+            }
+        }
+
         Ok(())
     }
 
@@ -748,25 +879,7 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
             expr: ExprDescriptor<'gc>,
         ) -> Result<(), CompilerError> {
             match target {
-                AssignmentTarget::Name(name) => match this.find_variable(*name)? {
-                    VariableDescriptor::Local(dest) => {
-                        this.expr_discharge(expr, ExprDestination::Register(dest))?;
-                    }
-                    VariableDescriptor::UpValue(dest) => {
-                        let (source, source_is_temp) = this.expr_any_register(expr)?;
-                        this.current_function
-                            .opcodes
-                            .push(OpCode::SetUpValue { source, dest });
-                        if source_is_temp {
-                            this.current_function.register_allocator.free(source);
-                        }
-                    }
-                    VariableDescriptor::Global(name) => {
-                        let env = this.get_environment()?;
-                        let key = ExprDescriptor::Constant(Constant::String(name));
-                        this.set_table(env, key, expr)?;
-                    }
-                },
+                AssignmentTarget::Name(name) => this.assign_variable(*name, expr)?,
 
                 AssignmentTarget::Field(table, field) => {
                     let table = this.suffixed_expression(table)?;
@@ -788,7 +901,7 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
             if i >= target_len {
                 let reg = self.expr_discharge(expr, ExprDestination::AllocateNew)?;
                 self.current_function.register_allocator.free(reg);
-            } else if i == val_len - 1 {
+            } else if i == val_len - 1 && target_len > val_len {
                 let top = self.current_function.register_allocator.stack_top();
 
                 let targets_left =
@@ -815,23 +928,25 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
         &mut self,
         local_function: &LocalFunctionStatement<String<'gc>>,
     ) -> Result<(), CompilerError> {
+        // The local is in scope inside its own function body, so that local functions may be
+        // recursive.
+        let dest = self
+            .current_function
+            .register_allocator
+            .push(1)
+            .ok_or(CompilerError::Registers)?;
+        self.current_function
+            .declare_local((local_function.name, dest, None));
+
         let proto = self.new_prototype(
             &local_function.definition.parameters,
             local_function.definition.has_varargs,
             &local_function.definition.body,
         )?;
 
-        let dest = self
-            .current_function
-            .register_allocator
-            .push(1)
-            .ok_or(CompilerError::Registers)?;
         self.current_function
             .opcodes
             .push(OpCode::Closure { proto, dest });
-        self.current_function
-            .locals
-            .push((local_function.name, dest));
 
         Ok(())
     }
@@ -887,13 +1002,25 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
     ) -> Result<ExprDescriptor<'gc>, CompilerError> {
         let mut array_index = 0;
         let mut fields = Vec::new();
-        for field in &table_constructor.fields {
+        let mut multi_field = None;
+        for (i, field) in table_constructor.fields.iter().enumerate() {
             fields.push(match field {
                 ConstructorField::Array(value) => {
+                    let value = self.expression(value)?;
+                    if i == table_constructor.fields.len() - 1 {
+                        if let ExprDescriptor::FunctionCall { .. }
+                        | ExprDescriptor::MethodCall { .. }
+                        | ExprDescriptor::VarArgs = value
+                        {
+                            multi_field = Some((array_index + 1, Box::new(value)));
+                            break;
+                        }
+                    }
+
                     array_index += 1;
                     (
                         ExprDescriptor::Constant(Constant::Integer(array_index)),
-                        self.expression(value)?,
+                        value,
                     )
                 }
                 ConstructorField::Record(key, value) => (
@@ -905,7 +1032,11 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
                 ),
             });
         }
-        Ok(ExprDescriptor::TableConstructor(fields))
+        Ok(ExprDescriptor::TableConstructor(
+            fields,
+            array_index,
+            multi_field,
+        ))
     }
 
     fn function_expression(
@@ -972,7 +1103,12 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
             PrimaryExpression::Name(name) => {
                 Ok(ExprDescriptor::Variable(self.find_variable(*name)?))
             }
-            PrimaryExpression::GroupedExpression(expr) => self.expression(expr),
+            PrimaryExpression::GroupedExpression(expr) => Ok(match self.expression(expr)? {
+                expr @ ExprDescriptor::FunctionCall { .. }
+                | expr @ ExprDescriptor::MethodCall { .. }
+                | expr @ ExprDescriptor::VarArgs => ExprDescriptor::Truncated(Box::new(expr)),
+                expr => expr,
+            }),
         }
     }
 
@@ -1065,23 +1201,55 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
         has_varargs: bool,
         body: &Block<String<'gc>>,
     ) -> Result<PrototypeIndex, CompilerError> {
-        let old_current = mem::replace(
-            &mut self.current_function,
-            CompilerFunction::start(parameters, has_varargs)?,
-        );
+        self.mark_lines();
+        let mut function = CompilerFunction::start(parameters, has_varargs)?;
+        function.line_defined = self.current_span.start.line + 1;
+        let old_current = mem::replace(&mut self.current_function, function);
         self.upper_functions.push(old_current);
         self.block(body)?;
-        let proto = mem::replace(
-            &mut self.current_function,
-            self.upper_functions.pop().unwrap(),
-        )
-        .finish(self.mutation_context)?;
+        let upper_function = self.upper_functions.pop().unwrap();
+        let proto = self.finish_function(upper_function)?;
         self.current_function.prototypes.push(proto);
         Ok(PrototypeIndex(
             cast(self.current_function.prototypes.len() - 1).ok_or(CompilerError::Functions)?,
         ))
     }
 
+    // Finishes the current function and replaces it with the given one.
+    fn finish_function(
+        &mut self,
+        next_function: CompilerFunction<'gc>,
+    ) -> Result<FunctionProto<'gc>, CompilerError> {
+        self.mark_lines();
+        let end_line = self.current_span.end.line + 1;
+        // Jumps still pending at the end of the function have no target, so they are reported at
+        // the location of the jump.
+        if let Some(pending_jump) = self.current_function.pending_jumps.first() {
+            self.current_span = pending_jump.span;
+        }
+        mem::replace(&mut self.current_function, next_function).finish(
+            self.mutation_context,
+            self.chunk_name,
+            end_line,
+            self.coverage,
+        )
+    }
+
+    // Sets the span of the statement being compiled, returning the previous one.  Any opcodes
+    // emitted under the previous span are first marked with its line.
+    fn set_span(&mut self, span: Span) -> Span {
+        self.mark_lines();
+        mem::replace(&mut self.current_span, span)
+    }
+
+    // Marks every opcode of the current function that does not yet have a line with the line of
+    // the current statement.
+    fn mark_lines(&mut self) {
+        let line = self.current_span.start.line + 1;
+        let function = &mut self.current_function;
+        function.line_info.resize(function.opcodes.len(), line);
+    }
+
     fn find_variable(
         &mut self,
         name: String<'gc>,
@@ -1102,7 +1270,7 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
 
         for i in (0..=current_function).rev() {
             for j in (0..get_function(self, i).locals.len()).rev() {
-                let (local_name, register) = get_function(self, i).locals[j];
+                let (local_name, register, _) = get_function(self, i).locals[j];
                 if name == local_name {
                     if i == current_function {
                         return Ok(VariableDescriptor::Local(register));
@@ -1171,6 +1339,64 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
         Ok(VariableDescriptor::Global(name))
     }
 
+    // Assigns the given expression to the variable with the given name, which may be a local, an
+    // upvalue, or a global.
+    fn assign_variable(
+        &mut self,
+        name: String<'gc>,
+        expr: ExprDescriptor<'gc>,
+    ) -> Result<(), CompilerError> {
+        match self.find_assignable_variable(name)? {
+            VariableDescriptor::Local(dest) => {
+                self.expr_discharge(expr, ExprDestination::Register(dest))?;
+            }
+            VariableDescriptor::UpValue(dest) => {
+                let (source, source_is_temp) = self.expr_any_register(expr)?;
+                self.current_function
+                    .opcodes
+                    .push(OpCode::SetUpValue { source, dest });
+                if source_is_temp {
+                    self.current_function.register_allocator.free(source);
+                }
+            }
+            VariableDescriptor::Global(name) => {
+                let env = self.get_environment()?;
+                let key = ExprDescriptor::Constant(Constant::String(name));
+                self.set_table(env, key, expr)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Find a variable that is the target of an assignment, `<const>` and `<close>` locals may not be
+    // assigned to.
+    fn find_assignable_variable(
+        &mut self,
+        name: String<'gc>,
+    ) -> Result<VariableDescriptor<'gc>, CompilerError> {
+        let functions = self
+            .upper_functions
+            .iter()
+            .chain(iter::once(&self.current_function));
+        for function in functions.rev() {
+            if let Some((_, _, attribute)) = function.locals.iter().rev().find(|l| l.0 == name) {
+                if attribute.is_some() {
+                    return Err(CompilerError::AssignToConst);
+                }
+                break;
+            }
+        }
+        self.find_variable(name)
+    }
+
+    // Returns true if any to-be-closed variables are in scope in the current function
+    fn to_be_closed_in_scope(&self) -> bool {
+        self.current_function
+            .blocks
+            .iter()
+            .any(|block| block.has_to_be_closed)
+    }
+
     // Get a reference to the variable _ENV in scope, or if that is not in scope, the implicit chunk
     // _ENV.
     fn get_environment(&mut self) -> Result<ExprDescriptor<'gc>, CompilerError> {
@@ -1200,7 +1426,7 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
                 assert!(jump_target.block_index <= current_block_index);
                 let needs_close_upvalues = jump_target.stack_top < current_stack_top
                     && (jump_target.block_index..=current_block_index)
-                        .any(|i| self.current_function.blocks[i].owns_upvalues);
+                        .any(|i| self.current_function.blocks[i].needs_close());
 
                 self.current_function.opcodes.push(OpCode::Jump {
                     offset: jump_offset(jmp_inst, jump_target.instruction)
@@ -1230,6 +1456,7 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
                 block_index: current_block_index,
                 stack_top: current_stack_top,
                 close_upvalues: false,
+                span: self.current_span,
             });
         }
 
@@ -1241,11 +1468,22 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
         let current_stack_top = self.current_function.register_allocator.stack_top();
         let current_block_index = self.current_function.blocks.len().checked_sub(1).unwrap();
 
+        // Named labels may not shadow any visible label with the same name, even one in an
+        // enclosing block.  Internal labels are only unique within their own block.
         for jump_target in self.current_function.jump_targets.iter().rev() {
             if jump_target.block_index < current_block_index {
+                if let JumpLabel::Named(name) = jump_label {
+                    if jump_target.label == jump_label {
+                        return Err(CompilerError::DuplicateLabel(label_name(name)));
+                    }
+                    continue;
+                }
                 break;
             } else if jump_target.label == jump_label {
-                return Err(CompilerError::DuplicateLabel);
+                return Err(match jump_label {
+                    JumpLabel::Named(name) => CompilerError::DuplicateLabel(label_name(name)),
+                    _ => panic!("duplicate internal jump label"),
+                });
             }
         }
 
@@ -1273,7 +1511,20 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
         for pending_jump in resolving_jumps {
             assert!(pending_jump.stack_top <= current_stack_top);
             if pending_jump.stack_top < current_stack_top {
-                return Err(CompilerError::JumpLocal);
+                let local = self
+                    .current_function
+                    .locals
+                    .iter()
+                    .find(|(_, r, _)| r.0 as u16 >= pending_jump.stack_top)
+                    .map(|(name, _, _)| label_name(*name))
+                    .unwrap_or_default();
+                return Err(match jump_label {
+                    JumpLabel::Named(name) => CompilerError::JumpLocal {
+                        label: label_name(name),
+                        local,
+                    },
+                    _ => panic!("internal jump into the scope of a local"),
+                });
             }
 
             match &mut self.current_function.opcodes[pending_jump.instruction] {
@@ -1431,6 +1682,29 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
         args: Vec<ExprDescriptor<'gc>>,
         returns: VarCount,
     ) -> Result<RegisterIndex, CompilerError> {
+        let (base, args) = self.push_method_call(table, method, args)?;
+        self.current_function.opcodes.push(OpCode::Call {
+            func: base,
+            args,
+            returns,
+        });
+
+        self.current_function
+            .register_allocator
+            .pop_to(base.0 as u16);
+
+        Ok(base)
+    }
+
+    // Places the method, its `self` argument and the rest of the given arguments at the top of the
+    // stack, ready to be called.  Returns the register of the method and the count of arguments
+    // following it.  The method and `self` registers are left allocated.
+    fn push_method_call(
+        &mut self,
+        table: ExprDescriptor<'gc>,
+        method: ExprDescriptor<'gc>,
+        args: Vec<ExprDescriptor<'gc>>,
+    ) -> Result<(RegisterIndex, VarCount), CompilerError> {
         let (table, table_is_temp) = self.expr_any_register(table)?;
         let (method, method_to_free) = self.expr_any_register_or_constant(method)?;
 
@@ -1460,17 +1734,8 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
                 .ok_or(CompilerError::Registers)?,
             None => VarCount::variable(),
         };
-        self.current_function.opcodes.push(OpCode::Call {
-            func: base,
-            args,
-            returns,
-        });
-
-        self.current_function
-            .register_allocator
-            .pop_to(base.0 as u16);
 
-        Ok(base)
+        Ok((base, args))
     }
 
     // Pushes the given arguments to the top of the stack in preparation for a function call or
@@ -1494,6 +1759,14 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
                     self.call_function(*func, args, VarCount::variable())?;
                     VarCount::variable()
                 }
+                ExprDescriptor::MethodCall {
+                    table,
+                    method,
+                    args,
+                } => {
+                    self.call_method(*table, *method, args, VarCount::variable())?;
+                    VarCount::variable()
+                }
                 ExprDescriptor::VarArgs => {
                     self.current_function.opcodes.push(OpCode::VarArgs {
                         dest: RegisterIndex(
@@ -1781,17 +2054,53 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
                 dest
             }
 
-            ExprDescriptor::TableConstructor(fields) => {
-                let dest = new_destination(self, dest)?;
-                self.current_function
-                    .opcodes
-                    .push(OpCode::NewTable { dest });
+            ExprDescriptor::TableConstructor(fields, array_count, multi_field) => {
+                // The fields may refer to an existing destination register, so the table must be
+                // constructed in a new register and moved there afterwards.
+                let final_dest = match dest {
+                    ExprDestination::Register(dest) => Some(dest),
+                    ExprDestination::AllocateNew | ExprDestination::PushNew => None,
+                };
+                let dest = match final_dest {
+                    Some(_) => new_destination(self, ExprDestination::AllocateNew)?,
+                    None => new_destination(self, dest)?,
+                };
+                let array_size = array_count as usize + multi_field.is_some() as usize;
+                let map_size = fields.len() - array_count as usize;
+                self.current_function.opcodes.push(OpCode::NewTable {
+                    dest,
+                    array_size: SizeHint::new(array_size),
+                    map_size: SizeHint::new(map_size),
+                });
 
                 for (key, value) in fields {
                     self.set_rtable(dest, key, value)?;
                 }
 
-                dest
+                if let Some((start, multi_field)) = multi_field {
+                    let base = self.expr_discharge(
+                        ExprDescriptor::Constant(Constant::Integer(start)),
+                        ExprDestination::PushNew,
+                    )?;
+                    let count = self.push_arguments(vec![*multi_field])?;
+                    self.current_function.opcodes.push(OpCode::SetList {
+                        table: dest,
+                        base,
+                        count,
+                    });
+                    self.current_function.register_allocator.free(base);
+                }
+
+                if let Some(final_dest) = final_dest {
+                    self.current_function.register_allocator.free(dest);
+                    self.current_function.opcodes.push(OpCode::Move {
+                        dest: final_dest,
+                        source: dest,
+                    });
+                    final_dest
+                } else {
+                    dest
+                }
             }
 
             ExprDescriptor::TableField { table, key } => get_table(self, *table, *key, dest)?,
@@ -1854,9 +2163,10 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
                 }
             }
 
+            ExprDescriptor::Truncated(expr) => self.expr_discharge(*expr, dest)?,
+
             ExprDescriptor::Concat(mut exprs) => {
                 assert!(!exprs.is_empty());
-                let dest = new_destination(self, dest)?;
                 let source =
                     self.expr_discharge(exprs.pop_front().unwrap(), ExprDestination::PushNew)?;
                 let mut count = 1;
@@ -1877,14 +2187,17 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
                         count = 1;
                     }
                 }
+                // Concat reads all of its operands before writing the result, so the destination
+                // may be allocated over the operand registers.
+                self.current_function
+                    .register_allocator
+                    .pop_to(source.0 as u16);
+                let dest = new_destination(self, dest)?;
                 self.current_function.opcodes.push(OpCode::Concat {
                     dest,
                     source,
                     count,
                 });
-                self.current_function
-                    .register_allocator
-                    .pop_to(source.0 as u16);
                 dest
             }
         };
@@ -1913,6 +2226,23 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
                     .ok_or(CompilerError::Registers)?;
                 dest
             }
+            ExprDescriptor::MethodCall {
+                table,
+                method,
+                args,
+            } => {
+                let dest = self.call_method(
+                    *table,
+                    *method,
+                    args,
+                    VarCount::try_constant(count).ok_or(CompilerError::Registers)?,
+                )?;
+                self.current_function
+                    .register_allocator
+                    .push(count)
+                    .ok_or(CompilerError::Registers)?;
+                dest
+            }
             ExprDescriptor::VarArgs => {
                 let dest = self
                     .current_function
@@ -2038,32 +2368,76 @@ impl<'gc> CompilerFunction<'gc> {
         function.has_varargs = has_varargs;
         function.fixed_params = fixed_params;
         for i in 0..fixed_params {
-            function
-                .locals
-                .push((parameters[i as usize], RegisterIndex(i)));
+            function.declare_local((parameters[i as usize], RegisterIndex(i), None));
         }
         Ok(function)
     }
 
-    fn finish(mut self, mc: MutationContext<'gc, '_>) -> Result<FunctionProto<'gc>, CompilerError> {
+    // Brings a new local into scope, starting from the next opcode
+    fn declare_local(&mut self, local: (String<'gc>, RegisterIndex, Option<LocalAttribute>)) {
+        self.local_variables.push(LocalVariable {
+            name: local.0,
+            register: local.1,
+            start_pc: self.opcodes.len(),
+            end_pc: usize::MAX,
+        });
+        self.locals.push(local);
+    }
+
+    // Takes the most recently declared local out of scope, ending before the next opcode
+    fn pop_local(&mut self) -> Option<(String<'gc>, RegisterIndex, Option<LocalAttribute>)> {
+        let local = self.locals.pop()?;
+        let end_pc = self.opcodes.len();
+        if let Some(variable) = self
+            .local_variables
+            .iter_mut()
+            .rev()
+            .find(|variable| variable.end_pc == usize::MAX)
+        {
+            variable.end_pc = end_pc;
+        }
+        Some(local)
+    }
+
+    fn finish(
+        mut self,
+        mc: MutationContext<'gc, '_>,
+        chunk_name: String<'gc>,
+        end_line: u64,
+        coverage: Option<&Coverage>,
+    ) -> Result<FunctionProto<'gc>, CompilerError> {
         self.opcodes.push(OpCode::Return {
             start: RegisterIndex(0),
             count: VarCount::constant(0),
         });
+        self.line_info.push(end_line);
         assert!(self.locals.len() == self.fixed_params as usize);
-        for (_, r) in self.locals.drain(..) {
+        while let Some((_, r, _)) = self.pop_local() {
             self.register_allocator.free(r);
         }
+        optimize_opcodes(
+            &mut self.opcodes,
+            &mut self.line_info,
+            &mut self.local_variables,
+        );
         assert_eq!(
             self.register_allocator.stack_top(),
             0,
             "register leak detected"
         );
 
-        if !self.pending_jumps.is_empty() {
-            return Err(CompilerError::GotoInvalid);
+        if let Some(pending_jump) = self.pending_jumps.first() {
+            return Err(match pending_jump.target {
+                JumpLabel::Named(name) => CompilerError::GotoInvalid(label_name(name)),
+                JumpLabel::Break => CompilerError::BreakOutsideLoop,
+                JumpLabel::Unique(_) => panic!("unresolved internal jump"),
+            });
         }
 
+        let coverage = coverage
+            .and_then(|coverage| coverage.add_function(chunk_name.as_bytes(), &self.line_info))
+            .map(StaticCollect);
+
         Ok(FunctionProto {
             fixed_params: self.fixed_params,
             has_varargs: self.has_varargs,
@@ -2071,11 +2445,18 @@ impl<'gc> CompilerFunction<'gc> {
             constants: self.constants,
             opcodes: self.opcodes,
             upvalues: self.upvalues.iter().map(|(_, d)| *d).collect(),
+            upvalue_names: self.upvalues.iter().map(|(n, _)| *n).collect(),
+            local_variables: self.local_variables,
             prototypes: self
                 .prototypes
                 .into_iter()
                 .map(|f| Gc::allocate(mc, f))
                 .collect(),
+            chunk_name: Some(chunk_name),
+            line_info: self.line_info,
+            line_defined: self.line_defined,
+            last_line_defined: if self.line_defined == 0 { 0 } else { end_line },
+            coverage,
         })
     }
 }
@@ -2087,3 +2468,8 @@ fn jump_offset(source: usize, target: usize) -> Option<i16> {
         cast((source + 1) - target).map(|i: i16| -i)
     }
 }
+
+// Label and local names for use in error messages
+fn label_name(name: String) -> StdString {
+    StdString::from_utf8_lossy(name.as_bytes()).into_owned()
+}
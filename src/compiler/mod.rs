@@ -2,21 +2,52 @@ use std::io::Read;
 
 use gc_arena::MutationContext;
 
-use crate::{parse_chunk, Error, FunctionProto, InternedStringSet};
+use crate::{parse_chunk, Coverage, Error, FunctionProto, InternedStringSet, SyntaxError};
 
 mod compiler;
 mod operators;
+mod optimizer;
 mod register_allocator;
 
+use self::compiler::compile_chunk_with;
+
 pub use self::compiler::{compile_chunk, CompilerError};
+pub use self::optimizer::optimize_opcodes;
 
+/// Parses and compiles a chunk of Lua source.  The chunk name is used to prefix any syntax error
+/// and is recorded in the compiled prototypes to locate runtime errors, following the Lua
+/// convention of `@filename` for chunks loaded from files and `=name` for other chunks.
 pub fn compile<'gc, R: Read>(
     mc: MutationContext<'gc, '_>,
     interned_strings: InternedStringSet<'gc>,
+    chunk_name: &str,
+    source: R,
+) -> Result<FunctionProto<'gc>, Error<'gc>> {
+    compile_with_coverage(mc, interned_strings, chunk_name, source, None)
+}
+
+/// Parses and compiles a chunk of Lua source like `compile`, counting runs of every line of the
+/// chunk in the given `Coverage`, if any.
+pub fn compile_with_coverage<'gc, R: Read>(
+    mc: MutationContext<'gc, '_>,
+    interned_strings: InternedStringSet<'gc>,
+    chunk_name: &str,
     source: R,
+    coverage: Option<&Coverage>,
 ) -> Result<FunctionProto<'gc>, Error<'gc>> {
-    Ok(compile_chunk(
-        mc,
-        &parse_chunk(source, |s| interned_strings.new_string(mc, s))?,
-    )?)
+    parse_chunk(source, |s| interned_strings.new_string(mc, s))
+        .and_then(|chunk| {
+            compile_chunk_with(
+                mc,
+                interned_strings.new_string(mc, chunk_name.as_bytes()),
+                &chunk,
+                coverage,
+            )
+        })
+        .map_err(|error| {
+            Error::SyntaxError(SyntaxError {
+                chunk_name: Some(chunk_name.to_owned()),
+                ..error
+            })
+        })
 }
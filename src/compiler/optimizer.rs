@@ -0,0 +1,454 @@
+use std::convert::TryFrom;
+
+use crate::{LocalVariable, OpCode, Opt254, RegisterIndex};
+
+/// Runs peephole optimizations over the opcodes of a single function, until none of them apply:
+///
+/// * Jumps to unconditional jumps are redirected to the final target.
+/// * Unreachable opcodes are removed.
+/// * Moves from a register to itself, moves that undo the previous move, and jumps to the next
+///   opcode are removed.
+/// * Stores to a register that is overwritten before being read are removed.
+///
+/// Once none of these apply, each comparison followed by a short jump that closes no upvalues is
+/// fused with the jump into a single opcode, such as `JumpIfLessRR`.
+///
+/// Opcodes are only removed when this cannot change behavior.  In particular, an opcode that may be
+/// skipped by the opcode before it is never removed, and a store is only considered dead if every
+/// opcode up to the overwrite is a simple register operation that cannot run any Lua code.
+///
+/// The line info must either be empty or hold the line of each opcode, and is kept in step with the
+/// opcodes as they are removed, as are the scopes of the local variables.
+pub fn optimize_opcodes(
+    opcodes: &mut Vec<OpCode>,
+    line_info: &mut Vec<u64>,
+    local_variables: &mut [LocalVariable],
+) {
+    assert!(line_info.is_empty() || line_info.len() == opcodes.len());
+    loop {
+        let mut changed = thread_jumps(opcodes);
+        for pass in &[
+            unreachable_opcodes as fn(&[OpCode]) -> Vec<bool>,
+            redundant_opcodes,
+            dead_stores,
+        ] {
+            let removed = pass(opcodes);
+            if removed.iter().any(|&r| r) {
+                remove_marked(opcodes, line_info, local_variables, &removed);
+                changed = true;
+            }
+        }
+
+        if !changed {
+            break;
+        }
+    }
+
+    let fused = fuse_compare_jumps(opcodes);
+    if fused.iter().any(|&f| f) {
+        remove_marked(opcodes, line_info, local_variables, &fused);
+    }
+}
+
+// Removes the marked opcodes along with their line info, and moves the scopes of local variables
+// to match.
+fn remove_marked(
+    opcodes: &mut Vec<OpCode>,
+    line_info: &mut Vec<u64>,
+    local_variables: &mut [LocalVariable],
+    removed: &[bool],
+) {
+    remove_opcodes(opcodes, removed);
+    if !line_info.is_empty() {
+        let mut i = 0;
+        line_info.retain(|_| {
+            i += 1;
+            !removed[i - 1]
+        });
+    }
+    // A scope boundary moves back by the number of opcodes removed before it
+    let new_pc = |pc: usize| pc - removed[..pc].iter().filter(|&&r| r).count();
+    for variable in local_variables.iter_mut() {
+        variable.start_pc = new_pc(variable.start_pc);
+        variable.end_pc = new_pc(variable.end_pc);
+    }
+}
+
+// Redirect jumps whose target is an unconditional jump to that jump's target.  Returns true if any
+// jumps were changed.
+fn thread_jumps(opcodes: &mut [OpCode]) -> bool {
+    let mut changed = false;
+    for i in 0..opcodes.len() {
+        let close_upvalues = match opcodes[i] {
+            OpCode::Jump { close_upvalues, .. } => close_upvalues,
+            _ => continue,
+        };
+
+        let mut target = jump_target(opcodes, i).unwrap();
+        // Bound the number of hops to avoid looping forever on jump cycles
+        for _ in 0..opcodes.len() {
+            match opcodes.get(target) {
+                Some(&OpCode::Jump {
+                    close_upvalues: target_close,
+                    ..
+                }) if target != i && closes_at_least(close_upvalues, target_close) => {
+                    target = jump_target(opcodes, target).unwrap();
+                }
+                _ => break,
+            }
+        }
+
+        if target != jump_target(opcodes, i).unwrap() {
+            if let Some(offset) = offset_to(i, target) {
+                set_jump_offset(&mut opcodes[i], offset);
+                changed = true;
+            }
+        }
+    }
+    changed
+}
+
+// Returns true if closing upvalues at `close` also closes everything that `other` would.
+fn closes_at_least(close: Opt254, other: Opt254) -> bool {
+    match (close.to_u8(), other.to_u8()) {
+        (_, None) => true,
+        (Some(close), Some(other)) => close <= other,
+        (None, Some(_)) => false,
+    }
+}
+
+// Marks every opcode that cannot be reached from the start of the function.
+fn unreachable_opcodes(opcodes: &[OpCode]) -> Vec<bool> {
+    let mut unreachable = vec![true; opcodes.len()];
+    let mut stack = vec![0];
+    while let Some(i) = stack.pop() {
+        if i >= opcodes.len() || !unreachable[i] {
+            continue;
+        }
+        unreachable[i] = false;
+
+        match opcodes[i] {
+            OpCode::Return { .. } | OpCode::TailCall { .. } => {}
+            OpCode::Jump { .. } | OpCode::NumericForPrep { .. } => {
+                stack.push(jump_target(opcodes, i).unwrap());
+            }
+            // Loops and fused comparisons, which may either jump or continue
+            _ if jump_target(opcodes, i).is_some() => {
+                stack.push(i + 1);
+                stack.push(jump_target(opcodes, i).unwrap());
+            }
+            op => {
+                stack.push(i + 1);
+                if skips_next(op) {
+                    stack.push(i + 2);
+                }
+            }
+        }
+    }
+    unreachable
+}
+
+// Marks opcodes that have no effect: moves of a register to itself, moves that repeat or undo the
+// previous move, and jumps to the next opcode.
+fn redundant_opcodes(opcodes: &[OpCode]) -> Vec<bool> {
+    let targets = jump_targets(opcodes);
+    let mut redundant = vec![false; opcodes.len()];
+    for i in 0..opcodes.len() {
+        if i > 0 && skips_next(opcodes[i - 1]) {
+            continue;
+        }
+
+        redundant[i] = match opcodes[i] {
+            OpCode::Move { dest, source } if dest == source => true,
+            OpCode::Move { dest, source }
+                if i > 0
+                    && !targets[i]
+                    && !redundant[i - 1]
+                    && !(i > 1 && skips_next(opcodes[i - 2])) =>
+            {
+                match opcodes[i - 1] {
+                    OpCode::Move {
+                        dest: prev_dest,
+                        source: prev_source,
+                    } => {
+                        (dest == prev_dest && source == prev_source)
+                            || (dest == prev_source && source == prev_dest)
+                    }
+                    _ => false,
+                }
+            }
+            OpCode::Jump {
+                offset: 0,
+                close_upvalues,
+            } => close_upvalues.is_none(),
+            _ => false,
+        };
+    }
+    redundant
+}
+
+// Marks stores to a register which are always overwritten before the register is read.
+fn dead_stores(opcodes: &[OpCode]) -> Vec<bool> {
+    let mut dead = vec![false; opcodes.len()];
+    for i in 0..opcodes.len() {
+        if i > 0 && skips_next(opcodes[i - 1]) {
+            continue;
+        }
+
+        let dest = match simple_store(opcodes[i]) {
+            Some((_, (dest, 1))) => dest,
+            _ => continue,
+        };
+
+        // Every path from the store runs straight through the following opcodes, so it is dead if
+        // they only do simple register operations until one of them overwrites it.
+        for &op in &opcodes[i + 1..] {
+            match simple_store(op) {
+                Some((read, (start, count))) => {
+                    if read == Some(dest) {
+                        break;
+                    }
+                    if dest.0 >= start.0 && (dest.0 as usize) < start.0 as usize + count as usize {
+                        dead[i] = true;
+                        break;
+                    }
+                }
+                None => break,
+            }
+        }
+    }
+    dead
+}
+
+// If the opcode only stores to registers, cannot call any Lua code, and never skips the next
+// opcode, returns the register that it reads (if any) and the range of registers that it writes.
+fn simple_store(op: OpCode) -> Option<(Option<RegisterIndex>, (RegisterIndex, u8))> {
+    match op {
+        OpCode::Move { dest, source } | OpCode::Not { dest, source } => {
+            Some((Some(source), (dest, 1)))
+        }
+        OpCode::LoadConstant { dest, .. }
+        | OpCode::NewTable { dest, .. }
+        | OpCode::GetUpValue { dest, .. }
+        | OpCode::LoadBool {
+            dest,
+            skip_next: false,
+            ..
+        } => Some((None, (dest, 1))),
+        OpCode::LoadNil { dest, count } => Some((None, (dest, count))),
+        _ => None,
+    }
+}
+
+// Returns true if the opcode may skip the opcode after it.
+fn skips_next(op: OpCode) -> bool {
+    match op {
+        OpCode::LoadBool { skip_next, .. } => skip_next,
+        OpCode::Test { .. }
+        | OpCode::TestSet { .. }
+        | OpCode::EqRR { .. }
+        | OpCode::EqRC { .. }
+        | OpCode::EqCR { .. }
+        | OpCode::EqCC { .. }
+        | OpCode::LessRR { .. }
+        | OpCode::LessRC { .. }
+        | OpCode::LessCR { .. }
+        | OpCode::LessCC { .. }
+        | OpCode::LessEqRR { .. }
+        | OpCode::LessEqRC { .. }
+        | OpCode::LessEqCR { .. }
+        | OpCode::LessEqCC { .. } => true,
+        _ => false,
+    }
+}
+
+// Replaces each comparison that is followed by a jump with the fused form of the pair, where this
+// cannot change behavior, and marks the jumps that were folded into the comparison before them.
+//
+// A comparison skips the jump after it when its result equals `skip_if`, so the fused opcode
+// jumps when the result differs.  The pair is left alone if the comparison may itself be skipped
+// (which would land on the jump), if anything else jumps to the jump, or if the jump closes
+// upvalues or is too long to fit in a fused opcode.
+fn fuse_compare_jumps(opcodes: &mut [OpCode]) -> Vec<bool> {
+    let targets = jump_targets(opcodes);
+    let mut fused = vec![false; opcodes.len()];
+    let mut i = 0;
+    while i + 1 < opcodes.len() {
+        if (i > 0 && skips_next(opcodes[i - 1])) || targets[i + 1] {
+            i += 1;
+            continue;
+        }
+        let target = match opcodes[i + 1] {
+            OpCode::Jump { close_upvalues, .. } if close_upvalues.is_none() => {
+                jump_target(opcodes, i + 1).unwrap()
+            }
+            _ => {
+                i += 1;
+                continue;
+            }
+        };
+        // The offset from the comparison, while the jump it replaces is still in place
+        let jump = match offset_to(i, target).and_then(|offset| i8::try_from(offset).ok()) {
+            Some(jump) if target != i + 1 => jump,
+            _ => {
+                i += 1;
+                continue;
+            }
+        };
+
+        let fused_op = match opcodes[i] {
+            OpCode::EqRR {
+                skip_if,
+                left,
+                right,
+            } => {
+                if skip_if {
+                    OpCode::JumpIfNotEqRR { left, right, jump }
+                } else {
+                    OpCode::JumpIfEqRR { left, right, jump }
+                }
+            }
+            OpCode::EqRC {
+                skip_if,
+                left,
+                right,
+            } => {
+                if skip_if {
+                    OpCode::JumpIfNotEqRC { left, right, jump }
+                } else {
+                    OpCode::JumpIfEqRC { left, right, jump }
+                }
+            }
+            OpCode::LessRR {
+                skip_if,
+                left,
+                right,
+            } => {
+                if skip_if {
+                    OpCode::JumpIfNotLessRR { left, right, jump }
+                } else {
+                    OpCode::JumpIfLessRR { left, right, jump }
+                }
+            }
+            OpCode::LessRC {
+                skip_if,
+                left,
+                right,
+            } => {
+                if skip_if {
+                    OpCode::JumpIfNotLessRC { left, right, jump }
+                } else {
+                    OpCode::JumpIfLessRC { left, right, jump }
+                }
+            }
+            OpCode::LessEqRR {
+                skip_if,
+                left,
+                right,
+            } => {
+                if skip_if {
+                    OpCode::JumpIfNotLessEqRR { left, right, jump }
+                } else {
+                    OpCode::JumpIfLessEqRR { left, right, jump }
+                }
+            }
+            OpCode::LessEqRC {
+                skip_if,
+                left,
+                right,
+            } => {
+                if skip_if {
+                    OpCode::JumpIfNotLessEqRC { left, right, jump }
+                } else {
+                    OpCode::JumpIfLessEqRC { left, right, jump }
+                }
+            }
+            _ => {
+                i += 1;
+                continue;
+            }
+        };
+
+        opcodes[i] = fused_op;
+        fused[i + 1] = true;
+        i += 2;
+    }
+    fused
+}
+
+// Marks every opcode that is the target of a jump.
+fn jump_targets(opcodes: &[OpCode]) -> Vec<bool> {
+    let mut targets = vec![false; opcodes.len() + 1];
+    for i in 0..opcodes.len() {
+        if let Some(target) = jump_target(opcodes, i) {
+            targets[target] = true;
+        }
+    }
+    targets
+}
+
+// Removes the marked opcodes, adjusting jump offsets to match.  Jumps to a removed opcode are
+// redirected to the next opcode that is kept.
+fn remove_opcodes(opcodes: &mut Vec<OpCode>, removed: &[bool]) {
+    let mut new_indexes = Vec::with_capacity(opcodes.len() + 1);
+    let mut kept = 0;
+    for &r in removed {
+        new_indexes.push(kept);
+        if !r {
+            kept += 1;
+        }
+    }
+    new_indexes.push(kept);
+
+    for i in 0..opcodes.len() {
+        if removed[i] {
+            continue;
+        }
+        if let Some(target) = jump_target(opcodes, i) {
+            // Removing opcodes only shortens jumps, so the new offset always fits, even for fused
+            // comparisons
+            let offset = offset_to(new_indexes[i], new_indexes[target]).unwrap();
+            set_jump_offset(&mut opcodes[i], offset);
+        }
+    }
+
+    let mut i = 0;
+    opcodes.retain(|_| {
+        i += 1;
+        !removed[i - 1]
+    });
+}
+
+// Returns the index of the opcode that the opcode at `i` may jump to, if it is a jump.
+fn jump_target(opcodes: &[OpCode], i: usize) -> Option<usize> {
+    let offset = match opcodes[i] {
+        OpCode::Jump { offset, .. } => offset,
+        OpCode::NumericForPrep { jump, .. }
+        | OpCode::NumericForLoop { jump, .. }
+        | OpCode::GenericForLoop { jump, .. } => jump,
+        mut op => *op.fused_jump()? as i16,
+    };
+    Some((i as isize + 1 + offset as isize) as usize)
+}
+
+fn set_jump_offset(op: &mut OpCode, new_offset: i16) {
+    match op {
+        OpCode::Jump { offset, .. } => *offset = new_offset,
+        OpCode::NumericForPrep { jump, .. }
+        | OpCode::NumericForLoop { jump, .. }
+        | OpCode::GenericForLoop { jump, .. } => *jump = new_offset,
+        op => {
+            *op.fused_jump().expect("opcode is not a jump") =
+                i8::try_from(new_offset).expect("fused jump offset out of range")
+        }
+    }
+}
+
+fn offset_to(source: usize, target: usize) -> Option<i16> {
+    let offset = target as isize - (source as isize + 1);
+    if offset < i16::min_value() as isize || offset > i16::max_value() as isize {
+        None
+    } else {
+        Some(offset as i16)
+    }
+}
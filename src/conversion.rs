@@ -0,0 +1,479 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::hash::{BuildHasher, Hash};
+use std::string::String as StdString;
+
+use gc_arena::MutationContext;
+
+use crate::{
+    AnyUserData, Callback, Closure, Error, Function, RuntimeError, String, Table, Thread,
+    TypeError, Value,
+};
+
+/// A Rust type that can be converted into a Lua value.
+///
+/// Collections become tables: a `Vec` becomes a sequence starting at 1, and a `HashMap` becomes a
+/// table with the same keys and values.  Structs can implement this with
+/// `#[derive(ToLua)]` when the `derive` feature is enabled, which converts them into a table with
+/// a field for each struct field.
+pub trait ToLua<'gc> {
+    fn to_lua(self, mc: MutationContext<'gc, '_>) -> Result<Value<'gc>, Error<'gc>>;
+}
+
+/// A Rust type that can be converted from a Lua value.
+///
+/// Numbers and strings are converted with the same coercions that Lua uses for arithmetic and
+/// concatenation, and any value converts into a `bool` by its truthiness.  The collections that
+/// implement `ToLua` are read back from tables the same way they are written, and `#[derive(FromLua)]`
+/// reads each field of a struct from the field of a table with the same name.
+pub trait FromLua<'gc>: Sized {
+    fn from_lua(mc: MutationContext<'gc, '_>, value: Value<'gc>) -> Result<Self, Error<'gc>>;
+}
+
+impl<'gc> ToLua<'gc> for Value<'gc> {
+    fn to_lua(self, _: MutationContext<'gc, '_>) -> Result<Value<'gc>, Error<'gc>> {
+        Ok(self)
+    }
+}
+
+impl<'gc> FromLua<'gc> for Value<'gc> {
+    fn from_lua(_: MutationContext<'gc, '_>, value: Value<'gc>) -> Result<Self, Error<'gc>> {
+        Ok(value)
+    }
+}
+
+impl<'gc> ToLua<'gc> for bool {
+    fn to_lua(self, _: MutationContext<'gc, '_>) -> Result<Value<'gc>, Error<'gc>> {
+        Ok(Value::Boolean(self))
+    }
+}
+
+impl<'gc> FromLua<'gc> for bool {
+    fn from_lua(_: MutationContext<'gc, '_>, value: Value<'gc>) -> Result<Self, Error<'gc>> {
+        Ok(value.to_bool())
+    }
+}
+
+macro_rules! impl_integer {
+    ($($i:ty),*) => {
+        $(
+            impl<'gc> ToLua<'gc> for $i {
+                fn to_lua(self, _: MutationContext<'gc, '_>) -> Result<Value<'gc>, Error<'gc>> {
+                    // Integers too large for a Lua integer become floats
+                    Ok(match i64::try_from(self) {
+                        Ok(i) => Value::Integer(i),
+                        Err(_) => Value::Number(self as f64),
+                    })
+                }
+            }
+
+            impl<'gc> FromLua<'gc> for $i {
+                fn from_lua(
+                    _: MutationContext<'gc, '_>,
+                    value: Value<'gc>,
+                ) -> Result<Self, Error<'gc>> {
+                    value
+                        .to_integer()
+                        .and_then(|i| <$i>::try_from(i).ok())
+                        .ok_or_else(|| {
+                            TypeError {
+                                expected: stringify!($i),
+                                found: value.type_name(),
+                            }
+                            .into()
+                        })
+                }
+            }
+        )*
+    };
+}
+
+impl_integer!(i8, u8, i16, u16, i32, u32, i64, u64, isize, usize);
+
+macro_rules! impl_float {
+    ($($f:ty),*) => {
+        $(
+            impl<'gc> ToLua<'gc> for $f {
+                fn to_lua(self, _: MutationContext<'gc, '_>) -> Result<Value<'gc>, Error<'gc>> {
+                    Ok(Value::Number(self as f64))
+                }
+            }
+
+            impl<'gc> FromLua<'gc> for $f {
+                fn from_lua(
+                    _: MutationContext<'gc, '_>,
+                    value: Value<'gc>,
+                ) -> Result<Self, Error<'gc>> {
+                    match value.to_number() {
+                        Some(n) => Ok(n as $f),
+                        None => Err(TypeError {
+                            expected: "number",
+                            found: value.type_name(),
+                        }
+                        .into()),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_float!(f32, f64);
+
+impl<'gc> ToLua<'gc> for String<'gc> {
+    fn to_lua(self, _: MutationContext<'gc, '_>) -> Result<Value<'gc>, Error<'gc>> {
+        Ok(Value::String(self))
+    }
+}
+
+impl<'gc> FromLua<'gc> for String<'gc> {
+    fn from_lua(mc: MutationContext<'gc, '_>, value: Value<'gc>) -> Result<Self, Error<'gc>> {
+        value.to_string(mc).ok_or_else(|| {
+            TypeError {
+                expected: "string",
+                found: value.type_name(),
+            }
+            .into()
+        })
+    }
+}
+
+impl<'gc, 'a> ToLua<'gc> for &'a str {
+    fn to_lua(self, mc: MutationContext<'gc, '_>) -> Result<Value<'gc>, Error<'gc>> {
+        Ok(Value::String(String::new(mc, self.as_bytes())))
+    }
+}
+
+impl<'gc> ToLua<'gc> for StdString {
+    fn to_lua(self, mc: MutationContext<'gc, '_>) -> Result<Value<'gc>, Error<'gc>> {
+        self.as_str().to_lua(mc)
+    }
+}
+
+impl<'gc> FromLua<'gc> for StdString {
+    fn from_lua(mc: MutationContext<'gc, '_>, value: Value<'gc>) -> Result<Self, Error<'gc>> {
+        let s = String::from_lua(mc, value)?;
+        StdString::from_utf8(s.as_bytes().to_vec()).map_err(|_| {
+            TypeError {
+                expected: "UTF-8 string",
+                found: "string",
+            }
+            .into()
+        })
+    }
+}
+
+macro_rules! impl_reference {
+    ($($t:ident => $expected:literal),*) => {
+        $(
+            impl<'gc> ToLua<'gc> for $t<'gc> {
+                fn to_lua(self, _: MutationContext<'gc, '_>) -> Result<Value<'gc>, Error<'gc>> {
+                    Ok(Value::$t(self))
+                }
+            }
+
+            impl<'gc> FromLua<'gc> for $t<'gc> {
+                fn from_lua(
+                    _: MutationContext<'gc, '_>,
+                    value: Value<'gc>,
+                ) -> Result<Self, Error<'gc>> {
+                    match value {
+                        Value::$t(v) => Ok(v),
+                        value => Err(TypeError {
+                            expected: $expected,
+                            found: value.type_name(),
+                        }
+                        .into()),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_reference!(Table => "table", Function => "function", Thread => "thread");
+
+impl<'gc> ToLua<'gc> for AnyUserData<'gc> {
+    fn to_lua(self, _: MutationContext<'gc, '_>) -> Result<Value<'gc>, Error<'gc>> {
+        Ok(Value::UserData(self))
+    }
+}
+
+impl<'gc> FromLua<'gc> for AnyUserData<'gc> {
+    fn from_lua(_: MutationContext<'gc, '_>, value: Value<'gc>) -> Result<Self, Error<'gc>> {
+        match value {
+            Value::UserData(u) => Ok(u),
+            value => Err(TypeError {
+                expected: "userdata",
+                found: value.type_name(),
+            }
+            .into()),
+        }
+    }
+}
+
+impl<'gc> ToLua<'gc> for Closure<'gc> {
+    fn to_lua(self, _: MutationContext<'gc, '_>) -> Result<Value<'gc>, Error<'gc>> {
+        Ok(self.into())
+    }
+}
+
+impl<'gc> ToLua<'gc> for Callback<'gc> {
+    fn to_lua(self, _: MutationContext<'gc, '_>) -> Result<Value<'gc>, Error<'gc>> {
+        Ok(self.into())
+    }
+}
+
+impl<'gc, T: ToLua<'gc>> ToLua<'gc> for Option<T> {
+    fn to_lua(self, mc: MutationContext<'gc, '_>) -> Result<Value<'gc>, Error<'gc>> {
+        match self {
+            Some(v) => v.to_lua(mc),
+            None => Ok(Value::Nil),
+        }
+    }
+}
+
+impl<'gc, T: FromLua<'gc>> FromLua<'gc> for Option<T> {
+    fn from_lua(mc: MutationContext<'gc, '_>, value: Value<'gc>) -> Result<Self, Error<'gc>> {
+        match value {
+            Value::Nil => Ok(None),
+            value => Ok(Some(T::from_lua(mc, value)?)),
+        }
+    }
+}
+
+impl<'gc, T: ToLua<'gc>> ToLua<'gc> for Vec<T> {
+    fn to_lua(self, mc: MutationContext<'gc, '_>) -> Result<Value<'gc>, Error<'gc>> {
+        Ok(Value::Table(Table::from_iter(mc, self)?))
+    }
+}
+
+impl<'gc, T: FromLua<'gc>> FromLua<'gc> for Vec<T> {
+    fn from_lua(mc: MutationContext<'gc, '_>, value: Value<'gc>) -> Result<Self, Error<'gc>> {
+        Table::from_lua(mc, value)?.to_vec(mc)
+    }
+}
+
+impl<'gc, K, V, S> ToLua<'gc> for HashMap<K, V, S>
+where
+    K: ToLua<'gc>,
+    V: ToLua<'gc>,
+{
+    fn to_lua(self, mc: MutationContext<'gc, '_>) -> Result<Value<'gc>, Error<'gc>> {
+        Ok(Value::Table(Table::from_pairs(mc, self)?))
+    }
+}
+
+impl<'gc, K, V, S> FromLua<'gc> for HashMap<K, V, S>
+where
+    K: FromLua<'gc> + Eq + Hash,
+    V: FromLua<'gc>,
+    S: BuildHasher + Default,
+{
+    fn from_lua(mc: MutationContext<'gc, '_>, value: Value<'gc>) -> Result<Self, Error<'gc>> {
+        let table = Table::from_lua(mc, value)?;
+        let mut map = HashMap::default();
+        let mut key = Value::Nil;
+        while let Some((k, v)) = table.next(key)? {
+            map.insert(K::from_lua(mc, k)?, V::from_lua(mc, v)?);
+            key = k;
+        }
+        Ok(map)
+    }
+}
+
+/// A Rust type that can be converted into a list of Lua values, such as the results of a callback.
+///
+/// A single `ToLua` value becomes one value, `()` becomes no values, and `Variadic` becomes each of
+/// its values.  A tuple becomes the values of its elements, where the last element may be any
+/// `ToMultiValue` type and is expanded in place.
+pub trait ToMultiValue<'gc> {
+    fn to_multi_value(self, mc: MutationContext<'gc, '_>) -> Result<Vec<Value<'gc>>, Error<'gc>>;
+}
+
+/// A Rust type that can be converted from a list of Lua values, such as the arguments of a
+/// callback.
+///
+/// A single `FromLua` value is converted from the first value, or from `nil` if there are none,
+/// `()` accepts any values, and `Variadic` converts every value.  A tuple converts each of its
+/// elements from the value at the same position, and its last element may be any
+/// `FromMultiValue` type, which is given all the remaining values.  Values left over are ignored,
+/// as they are by Lua functions.
+pub trait FromMultiValue<'gc>: Sized {
+    fn from_multi_value(
+        mc: MutationContext<'gc, '_>,
+        values: Vec<Value<'gc>>,
+    ) -> Result<Self, BadArgument<'gc>>;
+}
+
+/// An error converting one of a list of values with `FromMultiValue`.
+#[derive(Debug)]
+pub struct BadArgument<'gc> {
+    /// The position of the value that could not be converted, starting from 0.
+    pub index: usize,
+    /// Whether the value was missing, because there were not enough values.
+    pub missing: bool,
+    pub error: Box<Error<'gc>>,
+}
+
+impl<'gc> BadArgument<'gc> {
+    /// Converts into an error about an argument of the named function, with the same message as
+    /// PUC-Rio Lua's `luaL_argerror`.
+    pub fn into_error(self, mc: MutationContext<'gc, '_>, function: &str) -> Error<'gc> {
+        let message = match *self.error {
+            Error::TypeError(error) => format!(
+                "{} expected, got {}",
+                error.expected,
+                if self.missing {
+                    "no value"
+                } else {
+                    error.found
+                }
+            ),
+            error => error.to_string(),
+        };
+        let message = format!(
+            "bad argument #{} to '{}' ({})",
+            self.index + 1,
+            function,
+            message
+        );
+        RuntimeError(Value::String(String::new(mc, message.as_bytes()))).into()
+    }
+
+    // The same error for the value at `offset` positions later in a longer list.
+    fn offset(self, offset: usize) -> BadArgument<'gc> {
+        BadArgument {
+            index: self.index + offset,
+            ..self
+        }
+    }
+}
+
+/// Any number of values of the same type, as a `ToMultiValue` or `FromMultiValue` type.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Variadic<T>(pub Vec<T>);
+
+impl<'gc, T: ToLua<'gc>> ToMultiValue<'gc> for T {
+    fn to_multi_value(self, mc: MutationContext<'gc, '_>) -> Result<Vec<Value<'gc>>, Error<'gc>> {
+        Ok(vec![self.to_lua(mc)?])
+    }
+}
+
+impl<'gc, T: FromLua<'gc>> FromMultiValue<'gc> for T {
+    fn from_multi_value(
+        mc: MutationContext<'gc, '_>,
+        values: Vec<Value<'gc>>,
+    ) -> Result<Self, BadArgument<'gc>> {
+        let missing = values.is_empty();
+        let value = values.into_iter().next().unwrap_or(Value::Nil);
+        T::from_lua(mc, value).map_err(|error| BadArgument {
+            index: 0,
+            missing,
+            error: Box::new(error),
+        })
+    }
+}
+
+impl<'gc> ToMultiValue<'gc> for () {
+    fn to_multi_value(self, _: MutationContext<'gc, '_>) -> Result<Vec<Value<'gc>>, Error<'gc>> {
+        Ok(Vec::new())
+    }
+}
+
+impl<'gc> FromMultiValue<'gc> for () {
+    fn from_multi_value(
+        _: MutationContext<'gc, '_>,
+        _: Vec<Value<'gc>>,
+    ) -> Result<Self, BadArgument<'gc>> {
+        Ok(())
+    }
+}
+
+impl<'gc, T: ToLua<'gc>> ToMultiValue<'gc> for Variadic<T> {
+    fn to_multi_value(self, mc: MutationContext<'gc, '_>) -> Result<Vec<Value<'gc>>, Error<'gc>> {
+        self.0.into_iter().map(|v| v.to_lua(mc)).collect()
+    }
+}
+
+impl<'gc, T: FromLua<'gc>> FromMultiValue<'gc> for Variadic<T> {
+    fn from_multi_value(
+        mc: MutationContext<'gc, '_>,
+        values: Vec<Value<'gc>>,
+    ) -> Result<Self, BadArgument<'gc>> {
+        values
+            .into_iter()
+            .enumerate()
+            .map(|(index, value)| {
+                T::from_lua(mc, value).map_err(|error| BadArgument {
+                    index,
+                    missing: false,
+                    error: Box::new(error),
+                })
+            })
+            .collect::<Result<_, _>>()
+            .map(Variadic)
+    }
+}
+
+macro_rules! impl_tuple {
+    ($($name:ident),* ; $last:ident) => {
+        impl<'gc, $($name,)* $last> ToMultiValue<'gc> for ($($name,)* $last,)
+        where
+            $($name: ToLua<'gc>,)*
+            $last: ToMultiValue<'gc>,
+        {
+            #[allow(non_snake_case)]
+            fn to_multi_value(
+                self,
+                mc: MutationContext<'gc, '_>,
+            ) -> Result<Vec<Value<'gc>>, Error<'gc>> {
+                let ($($name,)* $last,) = self;
+                let mut values = Vec::new();
+                $(values.push($name.to_lua(mc)?);)*
+                values.extend($last.to_multi_value(mc)?);
+                Ok(values)
+            }
+        }
+
+        impl<'gc, $($name,)* $last> FromMultiValue<'gc> for ($($name,)* $last,)
+        where
+            $($name: FromLua<'gc>,)*
+            $last: FromMultiValue<'gc>,
+        {
+            #[allow(unused_mut, unused_variables)]
+            fn from_multi_value(
+                mc: MutationContext<'gc, '_>,
+                values: Vec<Value<'gc>>,
+            ) -> Result<Self, BadArgument<'gc>> {
+                let mut values = values.into_iter();
+                let mut index = 0;
+                Ok((
+                    $({
+                        let value = values.next();
+                        let missing = value.is_none();
+                        let converted = $name::from_lua(mc, value.unwrap_or(Value::Nil))
+                            .map_err(|error| BadArgument {
+                                index,
+                                missing,
+                                error: Box::new(error),
+                            })?;
+                        index += 1;
+                        converted
+                    },)*
+                    $last::from_multi_value(mc, values.collect())
+                        .map_err(|err| err.offset(index))?,
+                ))
+            }
+        }
+    };
+}
+
+impl_tuple!(; A);
+impl_tuple!(A; B);
+impl_tuple!(A, B; C);
+impl_tuple!(A, B, C; D);
+impl_tuple!(A, B, C, D; E);
+impl_tuple!(A, B, C, D, E; F);
+impl_tuple!(A, B, C, D, E, F; G);
+impl_tuple!(A, B, C, D, E, F, G; H);
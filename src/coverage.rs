@@ -0,0 +1,127 @@
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::rc::Rc;
+use std::string::String as StdString;
+
+/// Collects line coverage for the chunks compiled with it, by counting how many times each of
+/// their instructions runs.
+///
+/// A `Coverage` is passed to `compile_with_coverage`, or set with `Lua::set_coverage` to cover
+/// every chunk that `Lua` compiles from source afterwards, including those loaded by `load` and
+/// `require`.  Clones share the same counts.
+#[derive(Clone, Default)]
+pub struct Coverage(Rc<RefCell<Vec<Rc<FunctionCoverage>>>>);
+
+/// The number of times each instruction of one compiled function has run, held by its
+/// `FunctionProto`.
+#[derive(Debug)]
+pub struct FunctionCoverage {
+    chunk_name: StdString,
+    line_info: Vec<u64>,
+    hits: Vec<Cell<u64>>,
+}
+
+impl FunctionCoverage {
+    // Counts a run of the instruction at the given pc.
+    pub(crate) fn hit(&self, pc: usize) {
+        if let Some(hits) = self.hits.get(pc) {
+            hits.set(hits.get() + 1);
+        }
+    }
+}
+
+impl Coverage {
+    pub fn new() -> Coverage {
+        Coverage::default()
+    }
+
+    // Adds a newly compiled function with the given line of each instruction.  Returns `None` if
+    // the function has no line information to cover.
+    pub(crate) fn add_function(
+        &self,
+        chunk_name: &[u8],
+        line_info: &[u64],
+    ) -> Option<Rc<FunctionCoverage>> {
+        if line_info.is_empty() {
+            return None;
+        }
+        let function = Rc::new(FunctionCoverage {
+            chunk_name: StdString::from_utf8_lossy(chunk_name).into_owned(),
+            line_info: line_info.to_vec(),
+            hits: line_info.iter().map(|_| Cell::new(0)).collect(),
+        });
+        self.0.borrow_mut().push(function.clone());
+        Some(function)
+    }
+
+    /// Returns the coverage counted so far.
+    pub fn report(&self) -> CoverageReport {
+        let mut chunks = BTreeMap::new();
+        for function in self.0.borrow().iter() {
+            let lines = &mut chunks
+                .entry(function.chunk_name.clone())
+                .or_insert_with(ChunkCoverage::default)
+                .lines;
+            for (&line, hits) in function.line_info.iter().zip(&function.hits) {
+                // A line with a loop runs some of its instructions more often than others, so it
+                // is counted as running as often as any of its instructions
+                let count = lines.entry(line).or_insert(0);
+                *count = (*count).max(hits.get());
+            }
+        }
+        CoverageReport { chunks }
+    }
+
+    /// Sets every count back to zero, keeping the functions compiled so far.
+    pub fn reset(&self) {
+        for function in self.0.borrow().iter() {
+            for hits in &function.hits {
+                hits.set(0);
+            }
+        }
+    }
+}
+
+/// The line coverage of every chunk compiled with a `Coverage`, by chunk name.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CoverageReport {
+    pub chunks: BTreeMap<StdString, ChunkCoverage>,
+}
+
+/// The line coverage of one chunk.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChunkCoverage {
+    /// Each line that has code, with the number of times it has run, which is 0 for lines that
+    /// were never run.  Lines without code, such as comments and blank lines, are not included.
+    pub lines: BTreeMap<u64, u64>,
+}
+
+impl ChunkCoverage {
+    /// The lines that have run at least once.
+    pub fn covered_lines(&self) -> Vec<u64> {
+        self.lines
+            .iter()
+            .filter(|(_, &hits)| hits > 0)
+            .map(|(&line, _)| line)
+            .collect()
+    }
+
+    /// The lines with code that have never run.
+    pub fn uncovered_lines(&self) -> Vec<u64> {
+        self.lines
+            .iter()
+            .filter(|(_, &hits)| hits == 0)
+            .map(|(&line, _)| line)
+            .collect()
+    }
+
+    /// The fraction of the lines with code that have run, from 0 to 1, or 1 for a chunk without
+    /// any code.
+    pub fn ratio(&self) -> f64 {
+        if self.lines.is_empty() {
+            1.0
+        } else {
+            self.covered_lines().len() as f64 / self.lines.len() as f64
+        }
+    }
+}
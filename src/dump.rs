@@ -0,0 +1,791 @@
+//! A binary format for precompiled chunks, used by `string.dump` and by `load` when given a binary
+//! chunk.
+//!
+//! Loaded chunks are checked so that lengths, operands and jump targets that are out of range are
+//! rejected rather than causing a panic or a huge allocation, but like in PUC-Rio Lua, bytecode
+//! that the compiler would never produce may still behave strangely.  Only load precompiled chunks
+//! from trusted sources.
+
+use std::error::Error as StdError;
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use gc_arena::{Collect, Gc, MutationContext};
+
+use crate::{
+    luac53::{undump_luac53, LUAC_VERSION},
+    Constant, ConstantIndex16, ConstantIndex8, Error, FunctionProto, InternedStringSet,
+    LocalVariable, OpCode, Opt254, PrototypeIndex, RegisterIndex, SizeHint, String,
+    UpValueDescriptor, UpValueIndex, VarCount,
+};
+
+/// All precompiled chunks start with the same signature as PUC-Rio Lua's.
+pub const BINARY_CHUNK_SIGNATURE: &[u8] = b"\x1bLua";
+
+// Follows the signature, and distinguishes luster chunks from PUC-Rio Lua chunks, whose version
+// byte is never zero.
+const FORMAT_NAME: &[u8] = b"\x00luster";
+// Must be changed whenever the format of precompiled chunks changes
+const FORMAT_VERSION: u8 = 6;
+// Used to check that integers and floats survived the round trip intact
+const CHECK_INTEGER: i64 = 0x5678;
+const CHECK_NUMBER: f64 = 370.5;
+
+#[derive(Debug, Clone, Copy, Collect)]
+#[collect(require_static)]
+pub enum UndumpError {
+    BadSignature,
+    VersionMismatch,
+    FormatMismatch,
+    BadConstant,
+    BadUpValue,
+    BadOpCode,
+    LimitExceeded,
+    Truncated,
+}
+
+impl StdError for UndumpError {}
+
+impl fmt::Display for UndumpError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UndumpError::BadSignature => write!(fmt, "not a precompiled chunk"),
+            UndumpError::VersionMismatch => write!(fmt, "precompiled chunk version mismatch"),
+            UndumpError::FormatMismatch => write!(fmt, "precompiled chunk format mismatch"),
+            UndumpError::BadConstant => write!(fmt, "bad constant in precompiled chunk"),
+            UndumpError::BadUpValue => write!(fmt, "bad upvalue in precompiled chunk"),
+            UndumpError::BadOpCode => write!(fmt, "bad opcode in precompiled chunk"),
+            UndumpError::LimitExceeded => write!(fmt, "precompiled chunk exceeds luster limits"),
+            UndumpError::Truncated => write!(fmt, "truncated precompiled chunk"),
+        }
+    }
+}
+
+/// Writes the given function prototype and all of its inner prototypes as a precompiled chunk.  If
+/// `strip` is true, the chunk name, line info, upvalue names and local variables are left out.
+pub fn dump_function<W: Write>(
+    proto: &FunctionProto,
+    strip: bool,
+    w: &mut W,
+) -> Result<(), io::Error> {
+    w.write_all(BINARY_CHUNK_SIGNATURE)?;
+    w.write_all(FORMAT_NAME)?;
+    w.write_all(&[FORMAT_VERSION, 8, 8])?;
+    w.write_all(&CHECK_INTEGER.to_le_bytes())?;
+    w.write_all(&CHECK_NUMBER.to_bits().to_le_bytes())?;
+    dump_proto(proto, strip, w)
+}
+
+/// Reads a precompiled chunk written by `dump_function`, or by PUC-Rio Lua 5.3's `luac`.  Strings in
+/// the chunk are interned in the given string set.
+pub fn undump_function<'gc, R: Read>(
+    mc: MutationContext<'gc, '_>,
+    interned_strings: InternedStringSet<'gc>,
+    r: R,
+) -> Result<FunctionProto<'gc>, Error<'gc>> {
+    undump_chunk(mc, interned_strings, r).map_err(|err| match err {
+        Error::IoError(err) if err.0.kind() == io::ErrorKind::UnexpectedEof => {
+            UndumpError::Truncated.into()
+        }
+        err => err,
+    })
+}
+
+fn undump_chunk<'gc, R: Read>(
+    mc: MutationContext<'gc, '_>,
+    interned_strings: InternedStringSet<'gc>,
+    mut r: R,
+) -> Result<FunctionProto<'gc>, Error<'gc>> {
+    let mut signature = [0; 4];
+    r.read_exact(&mut signature)?;
+    if signature != BINARY_CHUNK_SIGNATURE {
+        return Err(UndumpError::BadSignature.into());
+    }
+
+    let mut name = [0; 7];
+    r.read_exact(&mut name[..1])?;
+    if name[0] == LUAC_VERSION {
+        return undump_luac53(mc, interned_strings, r);
+    }
+
+    r.read_exact(&mut name[1..])?;
+    if name != FORMAT_NAME || read_u8(&mut r)? != FORMAT_VERSION {
+        return Err(UndumpError::VersionMismatch.into());
+    }
+
+    if read_u8(&mut r)? != 8
+        || read_u8(&mut r)? != 8
+        || read_i64(&mut r)? != CHECK_INTEGER
+        || read_f64(&mut r)? != CHECK_NUMBER
+    {
+        return Err(UndumpError::FormatMismatch.into());
+    }
+
+    undump_proto(mc, interned_strings, &mut r)
+}
+
+pub(crate) fn dump_proto<W: Write>(
+    proto: &FunctionProto,
+    strip: bool,
+    w: &mut W,
+) -> Result<(), io::Error> {
+    w.write_all(&[proto.fixed_params, proto.has_varargs as u8])?;
+    w.write_all(&proto.stack_size.to_le_bytes())?;
+
+    write_len(proto.constants.len(), w)?;
+    for constant in &proto.constants {
+        match *constant {
+            Constant::Nil => w.write_all(&[0])?,
+            Constant::Boolean(b) => w.write_all(&[1, b as u8])?,
+            Constant::Integer(i) => {
+                w.write_all(&[2])?;
+                w.write_all(&i.to_le_bytes())?;
+            }
+            Constant::Number(n) => {
+                w.write_all(&[3])?;
+                w.write_all(&n.to_bits().to_le_bytes())?;
+            }
+            Constant::String(s) => {
+                w.write_all(&[4])?;
+                write_len(s.as_bytes().len(), w)?;
+                w.write_all(s.as_bytes())?;
+            }
+        }
+    }
+
+    write_len(proto.opcodes.len(), w)?;
+    for opcode in &proto.opcodes {
+        dump_opcode(*opcode, w)?;
+    }
+
+    write_len(proto.upvalues.len(), w)?;
+    for upvalue in &proto.upvalues {
+        match *upvalue {
+            UpValueDescriptor::Environment => w.write_all(&[0])?,
+            UpValueDescriptor::ParentLocal(r) => w.write_all(&[1, r.0])?,
+            UpValueDescriptor::Outer(u) => w.write_all(&[2, u.0])?,
+        }
+    }
+
+    write_len(proto.prototypes.len(), w)?;
+    for proto in &proto.prototypes {
+        dump_proto(proto, strip, w)?;
+    }
+
+    match proto.chunk_name {
+        Some(chunk_name) if !strip => {
+            w.write_all(&[1])?;
+            write_len(chunk_name.as_bytes().len(), w)?;
+            w.write_all(chunk_name.as_bytes())?;
+        }
+        _ => w.write_all(&[0])?,
+    }
+
+    let line_info: &[u64] = if strip { &[] } else { &proto.line_info };
+    write_len(line_info.len(), w)?;
+    for line in line_info {
+        w.write_all(&line.to_le_bytes())?;
+    }
+    w.write_all(&proto.line_defined.to_le_bytes())?;
+    w.write_all(&proto.last_line_defined.to_le_bytes())?;
+
+    let upvalue_names: &[String] = if strip { &[] } else { &proto.upvalue_names };
+    write_len(upvalue_names.len(), w)?;
+    for name in upvalue_names {
+        write_len(name.as_bytes().len(), w)?;
+        w.write_all(name.as_bytes())?;
+    }
+
+    let local_variables: &[LocalVariable] = if strip { &[] } else { &proto.local_variables };
+    write_len(local_variables.len(), w)?;
+    for variable in local_variables {
+        write_len(variable.name.as_bytes().len(), w)?;
+        w.write_all(variable.name.as_bytes())?;
+        w.write_all(&[variable.register.0])?;
+        write_len(variable.start_pc, w)?;
+        write_len(variable.end_pc, w)?;
+    }
+
+    Ok(())
+}
+
+pub(crate) fn undump_proto<'gc, R: Read>(
+    mc: MutationContext<'gc, '_>,
+    interned_strings: InternedStringSet<'gc>,
+    r: &mut R,
+) -> Result<FunctionProto<'gc>, Error<'gc>> {
+    let fixed_params = read_u8(r)?;
+    let has_varargs = read_u8(r)? != 0;
+    let stack_size = read_u16(r)?;
+
+    let mut constants = Vec::new();
+    for _ in 0..read_len(r)? {
+        constants.push(match read_u8(r)? {
+            0 => Constant::Nil,
+            1 => Constant::Boolean(read_u8(r)? != 0),
+            2 => Constant::Integer(read_i64(r)?),
+            3 => Constant::Number(read_f64(r)?),
+            4 => Constant::String(interned_strings.new_string(mc, &read_bytes(r)?)),
+            _ => return Err(UndumpError::BadConstant.into()),
+        });
+    }
+
+    let mut opcodes = Vec::new();
+    for _ in 0..read_len(r)? {
+        opcodes.push(undump_opcode(r)?);
+    }
+
+    let mut upvalues = Vec::new();
+    for _ in 0..read_len(r)? {
+        upvalues.push(match read_u8(r)? {
+            0 => UpValueDescriptor::Environment,
+            1 => UpValueDescriptor::ParentLocal(RegisterIndex(read_u8(r)?)),
+            2 => UpValueDescriptor::Outer(UpValueIndex(read_u8(r)?)),
+            _ => return Err(UndumpError::BadUpValue.into()),
+        });
+    }
+
+    let mut prototypes = Vec::new();
+    for _ in 0..read_len(r)? {
+        prototypes.push(Gc::allocate(mc, undump_proto(mc, interned_strings, r)?));
+    }
+
+    let chunk_name = if read_u8(r)? != 0 {
+        Some(interned_strings.new_string(mc, &read_bytes(r)?))
+    } else {
+        None
+    };
+
+    let mut line_info = Vec::new();
+    for _ in 0..read_len(r)? {
+        line_info.push(read_u64(r)?);
+    }
+    let line_defined = read_u64(r)?;
+    let last_line_defined = read_u64(r)?;
+
+    let mut upvalue_names = Vec::new();
+    for _ in 0..read_len(r)? {
+        upvalue_names.push(interned_strings.new_string(mc, &read_bytes(r)?));
+    }
+
+    let mut local_variables = Vec::new();
+    for _ in 0..read_len(r)? {
+        local_variables.push(LocalVariable {
+            name: interned_strings.new_string(mc, &read_bytes(r)?),
+            register: RegisterIndex(read_u8(r)?),
+            start_pc: read_len(r)?,
+            end_pc: read_len(r)?,
+        });
+    }
+
+    let proto = FunctionProto {
+        fixed_params,
+        has_varargs,
+        stack_size,
+        constants,
+        opcodes,
+        upvalues,
+        upvalue_names,
+        local_variables,
+        prototypes,
+        chunk_name,
+        line_info,
+        line_defined,
+        last_line_defined,
+        coverage: None,
+    };
+    verify_proto(&proto)?;
+    Ok(proto)
+}
+
+/// Checks that every operand of the opcodes of a loaded prototype refers to a register, constant,
+/// upvalue or inner prototype that exists, that no jump or skip leaves the function, and that the
+/// upvalues of inner prototypes can be found when their closures are created.  The inner
+/// prototypes themselves must have been checked already.
+pub(crate) fn verify_proto(proto: &FunctionProto) -> Result<(), UndumpError> {
+    let stack_size = proto.stack_size as usize;
+    let opcodes_len = proto.opcodes.len();
+
+    let reg = |r: RegisterIndex| (r.0 as usize) < stack_size;
+    // Whether the `count` registers starting at `r` exist
+    let regs = |r: RegisterIndex, count: usize| r.0 as usize + count <= stack_size;
+    // Variable counts extend to the top of the stack, which is not bounded by the stack size
+    let var_regs = |r: RegisterIndex, extra: usize, count: VarCount| {
+        regs(r, extra + count.to_constant().unwrap_or(0) as usize)
+    };
+    let cons = |c: ConstantIndex8| (c.0 as usize) < proto.constants.len();
+    let up = |u: UpValueIndex| (u.0 as usize) < proto.upvalues.len();
+    let jump = |pc: usize, offset: i16| {
+        let target = pc as isize + 1 + offset as isize;
+        target >= 0 && (target as usize) < opcodes_len
+    };
+    let skip = |pc: usize| pc + 2 < opcodes_len;
+
+    if proto.fixed_params as usize > stack_size {
+        return Err(UndumpError::LimitExceeded);
+    }
+
+    // The VM never runs past the last opcode, so it must not continue to the next one
+    match proto.opcodes.last() {
+        Some(OpCode::Return { .. }) | Some(OpCode::TailCall { .. }) | Some(OpCode::Jump { .. }) => {
+        }
+        _ => return Err(UndumpError::BadOpCode),
+    }
+
+    for (pc, &opcode) in proto.opcodes.iter().enumerate() {
+        let valid = match opcode {
+            OpCode::Move { dest, source }
+            | OpCode::Length { dest, source }
+            | OpCode::Not { dest, source }
+            | OpCode::Minus { dest, source }
+            | OpCode::BitNot { dest, source } => reg(dest) && reg(source),
+            OpCode::LoadConstant { dest, constant } => {
+                reg(dest) && (constant.0 as usize) < proto.constants.len()
+            }
+            OpCode::LoadBool {
+                dest, skip_next, ..
+            } => reg(dest) && (!skip_next || skip(pc)),
+            OpCode::LoadNil { dest, count } => regs(dest, count as usize),
+            OpCode::NewTable { dest, .. } => reg(dest),
+            OpCode::SetList { table, base, count } => reg(table) && var_regs(base, 1, count),
+            OpCode::GetTableR { dest, table, key } => reg(dest) && reg(table) && reg(key),
+            OpCode::GetTableC { dest, table, key } => reg(dest) && reg(table) && cons(key),
+            OpCode::SetTableRR { table, key, value } => reg(table) && reg(key) && reg(value),
+            OpCode::SetTableRC { table, key, value } => reg(table) && reg(key) && cons(value),
+            OpCode::SetTableCR { table, key, value } => reg(table) && cons(key) && reg(value),
+            OpCode::SetTableCC { table, key, value } => reg(table) && cons(key) && cons(value),
+            OpCode::GetUpTableR { dest, table, key } => reg(dest) && up(table) && reg(key),
+            OpCode::GetUpTableC { dest, table, key } => reg(dest) && up(table) && cons(key),
+            OpCode::SetUpTableRR { table, key, value } => up(table) && reg(key) && reg(value),
+            OpCode::SetUpTableRC { table, key, value } => up(table) && reg(key) && cons(value),
+            OpCode::SetUpTableCR { table, key, value } => up(table) && cons(key) && reg(value),
+            OpCode::SetUpTableCC { table, key, value } => up(table) && cons(key) && cons(value),
+            OpCode::Call {
+                func,
+                args,
+                returns,
+            } => var_regs(func, 1, args) && var_regs(func, 0, returns),
+            OpCode::TailCall { func, args } => var_regs(func, 1, args),
+            OpCode::Return { start, count } | OpCode::VarArgs { dest: start, count } => {
+                var_regs(start, 0, count)
+            }
+            OpCode::Jump { offset, .. } => jump(pc, offset),
+            OpCode::Test { value, .. } => reg(value) && skip(pc),
+            OpCode::TestSet { dest, value, .. } => reg(dest) && reg(value) && skip(pc),
+            OpCode::Closure { dest, proto: index } => {
+                reg(dest) && (index.0 as usize) < proto.prototypes.len()
+            }
+            OpCode::ToBeClosed { value } => reg(value),
+            OpCode::NumericForPrep { base, jump: offset }
+            | OpCode::NumericForLoop { base, jump: offset } => regs(base, 4) && jump(pc, offset),
+            OpCode::GenericForCall { base, var_count } => regs(base, 3 + var_count as usize),
+            OpCode::GenericForLoop { base, jump: offset } => regs(base, 2) && jump(pc, offset),
+            OpCode::SelfR { base, table, key } => regs(base, 2) && reg(table) && reg(key),
+            OpCode::SelfC { base, table, key } => regs(base, 2) && reg(table) && cons(key),
+            OpCode::Concat {
+                dest,
+                source,
+                count,
+            } => reg(dest) && regs(source, count as usize),
+            OpCode::GetUpValue { dest, source } => reg(dest) && up(source),
+            OpCode::SetUpValue { dest, source } => up(dest) && reg(source),
+            OpCode::EqRR { left, right, .. }
+            | OpCode::LessRR { left, right, .. }
+            | OpCode::LessEqRR { left, right, .. } => reg(left) && reg(right) && skip(pc),
+            OpCode::EqRC { left, right, .. }
+            | OpCode::LessRC { left, right, .. }
+            | OpCode::LessEqRC { left, right, .. } => reg(left) && cons(right) && skip(pc),
+            OpCode::EqCR { left, right, .. }
+            | OpCode::LessCR { left, right, .. }
+            | OpCode::LessEqCR { left, right, .. } => cons(left) && reg(right) && skip(pc),
+            OpCode::EqCC { left, right, .. }
+            | OpCode::LessCC { left, right, .. }
+            | OpCode::LessEqCC { left, right, .. } => cons(left) && cons(right) && skip(pc),
+            OpCode::AddRR { dest, left, right }
+            | OpCode::SubRR { dest, left, right }
+            | OpCode::MulRR { dest, left, right }
+            | OpCode::DivRR { dest, left, right }
+            | OpCode::IDivRR { dest, left, right }
+            | OpCode::ModRR { dest, left, right }
+            | OpCode::PowRR { dest, left, right }
+            | OpCode::BitAndRR { dest, left, right }
+            | OpCode::BitOrRR { dest, left, right }
+            | OpCode::BitXorRR { dest, left, right }
+            | OpCode::ShiftLeftRR { dest, left, right }
+            | OpCode::ShiftRightRR { dest, left, right } => reg(dest) && reg(left) && reg(right),
+            OpCode::AddRC { dest, left, right }
+            | OpCode::SubRC { dest, left, right }
+            | OpCode::MulRC { dest, left, right }
+            | OpCode::DivRC { dest, left, right }
+            | OpCode::IDivRC { dest, left, right }
+            | OpCode::ModRC { dest, left, right }
+            | OpCode::PowRC { dest, left, right }
+            | OpCode::BitAndRC { dest, left, right }
+            | OpCode::BitOrRC { dest, left, right }
+            | OpCode::BitXorRC { dest, left, right }
+            | OpCode::ShiftLeftRC { dest, left, right }
+            | OpCode::ShiftRightRC { dest, left, right } => reg(dest) && reg(left) && cons(right),
+            OpCode::AddCR { dest, left, right }
+            | OpCode::SubCR { dest, left, right }
+            | OpCode::MulCR { dest, left, right }
+            | OpCode::DivCR { dest, left, right }
+            | OpCode::IDivCR { dest, left, right }
+            | OpCode::ModCR { dest, left, right }
+            | OpCode::PowCR { dest, left, right }
+            | OpCode::BitAndCR { dest, left, right }
+            | OpCode::BitOrCR { dest, left, right }
+            | OpCode::BitXorCR { dest, left, right }
+            | OpCode::ShiftLeftCR { dest, left, right }
+            | OpCode::ShiftRightCR { dest, left, right } => reg(dest) && cons(left) && reg(right),
+            OpCode::AddCC { dest, left, right }
+            | OpCode::SubCC { dest, left, right }
+            | OpCode::MulCC { dest, left, right }
+            | OpCode::DivCC { dest, left, right }
+            | OpCode::IDivCC { dest, left, right }
+            | OpCode::ModCC { dest, left, right }
+            | OpCode::PowCC { dest, left, right }
+            | OpCode::BitAndCC { dest, left, right }
+            | OpCode::BitOrCC { dest, left, right }
+            | OpCode::BitXorCC { dest, left, right }
+            | OpCode::ShiftLeftCC { dest, left, right }
+            | OpCode::ShiftRightCC { dest, left, right } => reg(dest) && cons(left) && cons(right),
+            OpCode::JumpIfEqRR {
+                left,
+                right,
+                jump: offset,
+            }
+            | OpCode::JumpIfNotEqRR {
+                left,
+                right,
+                jump: offset,
+            }
+            | OpCode::JumpIfLessRR {
+                left,
+                right,
+                jump: offset,
+            }
+            | OpCode::JumpIfNotLessRR {
+                left,
+                right,
+                jump: offset,
+            }
+            | OpCode::JumpIfLessEqRR {
+                left,
+                right,
+                jump: offset,
+            }
+            | OpCode::JumpIfNotLessEqRR {
+                left,
+                right,
+                jump: offset,
+            } => reg(left) && reg(right) && jump(pc, offset as i16),
+            OpCode::JumpIfEqRC {
+                left,
+                right,
+                jump: offset,
+            }
+            | OpCode::JumpIfNotEqRC {
+                left,
+                right,
+                jump: offset,
+            }
+            | OpCode::JumpIfLessRC {
+                left,
+                right,
+                jump: offset,
+            }
+            | OpCode::JumpIfNotLessRC {
+                left,
+                right,
+                jump: offset,
+            }
+            | OpCode::JumpIfLessEqRC {
+                left,
+                right,
+                jump: offset,
+            }
+            | OpCode::JumpIfNotLessEqRC {
+                left,
+                right,
+                jump: offset,
+            } => reg(left) && cons(right) && jump(pc, offset as i16),
+        };
+        if !valid {
+            return Err(UndumpError::BadOpCode);
+        }
+    }
+
+    for inner in &proto.prototypes {
+        for &upvalue in &inner.upvalues {
+            let valid = match upvalue {
+                UpValueDescriptor::Environment => false,
+                UpValueDescriptor::ParentLocal(r) => reg(r),
+                UpValueDescriptor::Outer(u) => up(u),
+            };
+            if !valid {
+                return Err(UndumpError::BadUpValue);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Every opcode is written as its index in this list followed by each of its fields in order.  New
+// opcodes must be added at the end, or `FORMAT_VERSION` must be changed.
+macro_rules! opcode_format {
+    ($($name:ident { $($field:ident),* },)*) => {
+        fn dump_opcode<W: Write>(opcode: OpCode, w: &mut W) -> Result<(), io::Error> {
+            let mut index: u8 = 0;
+            $(
+                if let OpCode::$name { $($field),* } = opcode {
+                    w.write_all(&[index])?;
+                    $(OpCodeField::dump($field, w)?;)*
+                    return Ok(());
+                }
+                index += 1;
+            )*
+            unreachable!("opcode {:?} missing from opcode format (index {})", opcode, index)
+        }
+
+        fn undump_opcode<'gc, R: Read>(r: &mut R) -> Result<OpCode, Error<'gc>> {
+            let opcode = read_u8(r)?;
+            let mut index: u8 = 0;
+            $(
+                if opcode == index {
+                    return Ok(OpCode::$name { $($field: OpCodeField::undump(r)?),* });
+                }
+                index += 1;
+            )*
+            let _ = index;
+            Err(UndumpError::BadOpCode.into())
+        }
+    };
+}
+
+opcode_format! {
+    Move { dest, source },
+    LoadConstant { dest, constant },
+    LoadBool { dest, value, skip_next },
+    LoadNil { dest, count },
+    NewTable { dest, array_size, map_size },
+    SetList { table, base, count },
+    GetTableR { dest, table, key },
+    GetTableC { dest, table, key },
+    SetTableRR { table, key, value },
+    SetTableRC { table, key, value },
+    SetTableCR { table, key, value },
+    SetTableCC { table, key, value },
+    GetUpTableR { dest, table, key },
+    GetUpTableC { dest, table, key },
+    SetUpTableRR { table, key, value },
+    SetUpTableRC { table, key, value },
+    SetUpTableCR { table, key, value },
+    SetUpTableCC { table, key, value },
+    Call { func, args, returns },
+    TailCall { func, args },
+    Return { start, count },
+    VarArgs { dest, count },
+    Jump { offset, close_upvalues },
+    Test { value, is_true },
+    TestSet { dest, value, is_true },
+    Closure { dest, proto },
+    ToBeClosed { value },
+    NumericForPrep { base, jump },
+    NumericForLoop { base, jump },
+    GenericForCall { base, var_count },
+    GenericForLoop { base, jump },
+    SelfR { base, table, key },
+    SelfC { base, table, key },
+    Concat { dest, source, count },
+    GetUpValue { dest, source },
+    SetUpValue { dest, source },
+    Length { dest, source },
+    EqRR { skip_if, left, right },
+    EqRC { skip_if, left, right },
+    EqCR { skip_if, left, right },
+    EqCC { skip_if, left, right },
+    LessRR { skip_if, left, right },
+    LessRC { skip_if, left, right },
+    LessCR { skip_if, left, right },
+    LessCC { skip_if, left, right },
+    LessEqRR { skip_if, left, right },
+    LessEqRC { skip_if, left, right },
+    LessEqCR { skip_if, left, right },
+    LessEqCC { skip_if, left, right },
+    Not { dest, source },
+    Minus { dest, source },
+    AddRR { dest, left, right },
+    AddRC { dest, left, right },
+    AddCR { dest, left, right },
+    AddCC { dest, left, right },
+    SubRR { dest, left, right },
+    SubRC { dest, left, right },
+    SubCR { dest, left, right },
+    SubCC { dest, left, right },
+    MulRR { dest, left, right },
+    MulRC { dest, left, right },
+    MulCR { dest, left, right },
+    MulCC { dest, left, right },
+    DivRR { dest, left, right },
+    DivRC { dest, left, right },
+    DivCR { dest, left, right },
+    DivCC { dest, left, right },
+    IDivRR { dest, left, right },
+    IDivRC { dest, left, right },
+    IDivCR { dest, left, right },
+    IDivCC { dest, left, right },
+    ModRR { dest, left, right },
+    ModRC { dest, left, right },
+    ModCR { dest, left, right },
+    ModCC { dest, left, right },
+    PowRR { dest, left, right },
+    PowRC { dest, left, right },
+    PowCR { dest, left, right },
+    PowCC { dest, left, right },
+    BitAndRR { dest, left, right },
+    BitAndRC { dest, left, right },
+    BitAndCR { dest, left, right },
+    BitAndCC { dest, left, right },
+    BitOrRR { dest, left, right },
+    BitOrRC { dest, left, right },
+    BitOrCR { dest, left, right },
+    BitOrCC { dest, left, right },
+    BitXorRR { dest, left, right },
+    BitXorRC { dest, left, right },
+    BitXorCR { dest, left, right },
+    BitXorCC { dest, left, right },
+    ShiftLeftRR { dest, left, right },
+    ShiftLeftRC { dest, left, right },
+    ShiftLeftCR { dest, left, right },
+    ShiftLeftCC { dest, left, right },
+    ShiftRightRR { dest, left, right },
+    ShiftRightRC { dest, left, right },
+    ShiftRightCR { dest, left, right },
+    ShiftRightCC { dest, left, right },
+    BitNot { dest, source },
+    JumpIfEqRR { left, right, jump },
+    JumpIfEqRC { left, right, jump },
+    JumpIfNotEqRR { left, right, jump },
+    JumpIfNotEqRC { left, right, jump },
+    JumpIfLessRR { left, right, jump },
+    JumpIfLessRC { left, right, jump },
+    JumpIfNotLessRR { left, right, jump },
+    JumpIfNotLessRC { left, right, jump },
+    JumpIfLessEqRR { left, right, jump },
+    JumpIfLessEqRC { left, right, jump },
+    JumpIfNotLessEqRR { left, right, jump },
+    JumpIfNotLessEqRC { left, right, jump },
+}
+
+trait OpCodeField: Sized {
+    fn dump<W: Write>(self, w: &mut W) -> Result<(), io::Error>;
+    fn undump<R: Read>(r: &mut R) -> Result<Self, io::Error>;
+}
+
+macro_rules! byte_field {
+    ($ty:ty, |$s:ident| $to_byte:expr, |$b:ident| $from_byte:expr) => {
+        impl OpCodeField for $ty {
+            fn dump<W: Write>(self, w: &mut W) -> Result<(), io::Error> {
+                let $s = self;
+                w.write_all(&[$to_byte])
+            }
+
+            fn undump<R: Read>(r: &mut R) -> Result<Self, io::Error> {
+                let $b = read_u8(r)?;
+                Ok($from_byte)
+            }
+        }
+    };
+}
+
+byte_field!(u8, |s| s, |b| b);
+byte_field!(i8, |s| s as u8, |b| b as i8);
+byte_field!(bool, |s| s as u8, |b| b != 0);
+byte_field!(RegisterIndex, |s| s.0, |b| RegisterIndex(b));
+byte_field!(ConstantIndex8, |s| s.0, |b| ConstantIndex8(b));
+byte_field!(SizeHint, |s| s.0, |b| SizeHint(b));
+byte_field!(UpValueIndex, |s| s.0, |b| UpValueIndex(b));
+byte_field!(PrototypeIndex, |s| s.0, |b| PrototypeIndex(b));
+byte_field!(Opt254, |s| s.to_u8().unwrap_or(255), |b| {
+    Opt254::new(if b == 255 { None } else { Some(b) })
+});
+byte_field!(VarCount, |s| s.to_constant().unwrap_or(255), |b| {
+    if b == 255 {
+        VarCount::variable()
+    } else {
+        VarCount::constant(b)
+    }
+});
+
+impl OpCodeField for ConstantIndex16 {
+    fn dump<W: Write>(self, w: &mut W) -> Result<(), io::Error> {
+        w.write_all(&self.0.to_le_bytes())
+    }
+
+    fn undump<R: Read>(r: &mut R) -> Result<Self, io::Error> {
+        Ok(ConstantIndex16(read_u16(r)?))
+    }
+}
+
+impl OpCodeField for i16 {
+    fn dump<W: Write>(self, w: &mut W) -> Result<(), io::Error> {
+        w.write_all(&self.to_le_bytes())
+    }
+
+    fn undump<R: Read>(r: &mut R) -> Result<Self, io::Error> {
+        Ok(read_u16(r)? as i16)
+    }
+}
+
+pub(crate) fn write_len<W: Write>(len: usize, w: &mut W) -> Result<(), io::Error> {
+    w.write_all(&(len as u64).to_le_bytes())
+}
+
+pub(crate) fn read_len<R: Read>(r: &mut R) -> Result<usize, io::Error> {
+    let mut buf = [0; 8];
+    r.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf) as usize)
+}
+
+// Reads a string of bytes after its length.  The length is not trusted for an allocation up front,
+// so a chunk claiming a huge string fails as truncated rather than running out of memory.
+pub(crate) fn read_bytes<R: Read>(r: &mut R) -> Result<Vec<u8>, io::Error> {
+    let len = read_len(r)?;
+    let mut buf = Vec::new();
+    r.take(len as u64).read_to_end(&mut buf)?;
+    if buf.len() != len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(buf)
+}
+
+pub(crate) fn read_u8<R: Read>(r: &mut R) -> Result<u8, io::Error> {
+    let mut buf = [0; 1];
+    r.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+fn read_u16<R: Read>(r: &mut R) -> Result<u16, io::Error> {
+    let mut buf = [0; 2];
+    r.read_exact(&mut buf)?;
+    Ok(u16::from_le_bytes(buf))
+}
+
+fn read_u64<R: Read>(r: &mut R) -> Result<u64, io::Error> {
+    let mut buf = [0; 8];
+    r.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+pub(crate) fn read_i64<R: Read>(r: &mut R) -> Result<i64, io::Error> {
+    let mut buf = [0; 8];
+    r.read_exact(&mut buf)?;
+    Ok(i64::from_le_bytes(buf))
+}
+
+pub(crate) fn read_f64<R: Read>(r: &mut R) -> Result<f64, io::Error> {
+    let mut buf = [0; 8];
+    r.read_exact(&mut buf)?;
+    Ok(f64::from_bits(u64::from_le_bytes(buf)))
+}
@@ -6,9 +6,118 @@ use gc_arena::{Collect, MutationContext, StaticCollect};
 
 use crate::{
     BadThreadMode, BinaryOperatorError, ClosureError, CompilerError, InternedStringSet,
-    InvalidTableKey, ParserError, StringError, ThreadError, Value,
+    Interrupted, InvalidTableKey, MetaOperatorError, ParserError, SnapshotError, Span, StringError,
+    ThreadError, Traceback, UndumpError, Value,
 };
 
+/// An error found while parsing or compiling Lua source, along with where in the source it
+/// occurred.
+///
+/// Displays as `chunkname:line:column: message`, with the line and column 1-indexed and the chunk
+/// name shortened like in a `Location`, or without the chunk name if none was given.
+#[derive(Debug, Collect)]
+#[collect(require_static)]
+pub struct SyntaxError {
+    pub chunk_name: Option<StdString>,
+    pub span: Span,
+    pub kind: SyntaxErrorKind,
+}
+
+#[derive(Debug, Collect)]
+#[collect(require_static)]
+pub enum SyntaxErrorKind {
+    Parser(ParserError),
+    Compiler(CompilerError),
+}
+
+impl SyntaxError {
+    /// Returns true if this error was caused by the source ending early, see
+    /// `ParserError::is_incomplete`.
+    pub fn is_incomplete(&self) -> bool {
+        match &self.kind {
+            SyntaxErrorKind::Parser(error) => error.is_incomplete(),
+            SyntaxErrorKind::Compiler(_) => false,
+        }
+    }
+}
+
+impl StdError for SyntaxError {}
+
+impl fmt::Display for SyntaxError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(chunk_name) = &self.chunk_name {
+            write!(fmt, "{}:", chunk_id(chunk_name))?;
+        }
+        write!(fmt, "{}: ", self.span.start)?;
+        match &self.kind {
+            SyntaxErrorKind::Parser(error) => write!(fmt, "{}", error),
+            SyntaxErrorKind::Compiler(error) => write!(fmt, "{}", error),
+        }
+    }
+}
+
+/// The location of the instruction that raised a runtime error.
+///
+/// Displays as `chunkname:line` like PUC-Rio Lua, where the chunk name is shortened the same way:
+/// `@file.lua` becomes `file.lua`, `=name` becomes `name`, and the source text of any other chunk
+/// becomes `[string "..."]`.  Either part is shown as `?` if it was stripped.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Collect)]
+#[collect(require_static)]
+pub struct Location {
+    pub chunk_name: Option<StdString>,
+    pub line: Option<u64>,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match &self.chunk_name {
+            Some(chunk_name) => write!(fmt, "{}:", chunk_id(chunk_name))?,
+            None => write!(fmt, "?:")?,
+        }
+        match self.line {
+            Some(line) => write!(fmt, "{}", line),
+            None => write!(fmt, "?"),
+        }
+    }
+}
+
+// The maximum length of a shortened chunk name, from PUC-Rio Lua's `LUA_IDSIZE`
+const CHUNK_ID_SIZE: usize = 60;
+
+// Shortens a chunk name for use in error messages, following PUC-Rio Lua's `luaO_chunkid`
+pub(crate) fn chunk_id(chunk_name: &str) -> StdString {
+    let bytes = chunk_name.as_bytes();
+    let id = match bytes.first() {
+        Some(b'=') => bytes[1..bytes.len().min(CHUNK_ID_SIZE)].to_vec(),
+        Some(b'@') => {
+            if bytes.len() <= CHUNK_ID_SIZE {
+                bytes[1..].to_vec()
+            } else {
+                let mut id = b"...".to_vec();
+                id.extend(&bytes[bytes.len() - (CHUNK_ID_SIZE - 4)..]);
+                id
+            }
+        }
+        _ => {
+            const PREFIX: &[u8] = b"[string \"";
+            const SUFFIX: &[u8] = b"\"]";
+            let max_len = CHUNK_ID_SIZE - PREFIX.len() - SUFFIX.len() - 4;
+            let mut id = PREFIX.to_vec();
+            match bytes.iter().position(|&b| b == b'\n') {
+                None if bytes.len() < max_len => id.extend(bytes),
+                newline => {
+                    let len = newline.unwrap_or(bytes.len()).min(max_len);
+                    id.extend(&bytes[..len]);
+                    id.extend(b"...");
+                }
+            }
+            id.extend(SUFFIX);
+            id
+        }
+    };
+    StdString::from_utf8_lossy(&id).into_owned()
+}
+
 #[derive(Debug, Clone, Copy, Collect)]
 #[collect(require_static)]
 pub struct TypeError {
@@ -48,8 +157,7 @@ impl<'gc> fmt::Display for RuntimeError<'gc> {
 #[collect(unsafe_drop)]
 pub enum Error<'gc> {
     IoError(StaticCollect<io::Error>),
-    ParserError(ParserError),
-    CompilerError(CompilerError),
+    SyntaxError(SyntaxError),
     ClosureError(ClosureError),
     InvalidTableKey(InvalidTableKey),
     StringError(StringError),
@@ -57,7 +165,21 @@ pub enum Error<'gc> {
     BadThreadMode(BadThreadMode),
     TypeError(TypeError),
     BinaryOperatorError(BinaryOperatorError),
+    MetaOperatorError(MetaOperatorError),
+    UndumpError(UndumpError),
+    SnapshotError(SnapshotError),
     RuntimeError(RuntimeError<'gc>),
+    Interrupted(Interrupted),
+    /// An error raised by a Lua function, along with where in the function it was raised.
+    LocatedError(Location, Box<Error<'gc>>),
+    /// An error raised by `error` with a string message, which should be prefixed with the location
+    /// of the function at the given level of the call stack.  Level 1 is the function that called
+    /// `error`, level 2 is the function that called that one, and so on.  The thread that receives
+    /// this error from a callback turns it into a `RuntimeError` with the prefixed message.
+    LeveledError(RuntimeError<'gc>, usize),
+    /// An error that escaped a thread, along with the traceback of the thread's call stack when it
+    /// was raised.  Displays as the error followed by the traceback on the next line.
+    TracedError(Traceback, Box<Error<'gc>>),
 }
 
 impl<'gc> StdError for Error<'gc> {}
@@ -66,16 +188,26 @@ impl<'gc> fmt::Display for Error<'gc> {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Error::IoError(error) => write!(fmt, "i/o error: {}", error.0),
-            Error::ParserError(error) => write!(fmt, "parser error: {}", error),
-            Error::CompilerError(error) => write!(fmt, "compiler error: {}", error),
+            Error::SyntaxError(error) => write!(fmt, "{}", error),
             Error::ClosureError(error) => write!(fmt, "closure error: {}", error),
-            Error::InvalidTableKey(error) => write!(fmt, "invalid table key: {}", error),
+            Error::InvalidTableKey(InvalidTableKey::ReadOnly) => {
+                write!(fmt, "{}", InvalidTableKey::ReadOnly)
+            }
+            Error::InvalidTableKey(error) => write!(fmt, "{}", error),
+            Error::StringError(error @ StringError::Concat { .. }) => write!(fmt, "{}", error),
             Error::StringError(error) => write!(fmt, "string error: {}", error),
             Error::ThreadError(error) => write!(fmt, "thread error: {}", error),
             Error::BadThreadMode(error) => write!(fmt, "bad thread mode: {}", error),
             Error::TypeError(error) => write!(fmt, "type error: {}", error),
             Error::BinaryOperatorError(error) => write!(fmt, "operator error: {}", error),
+            Error::MetaOperatorError(error) => write!(fmt, "metamethod error: {}", error),
+            Error::UndumpError(error) => write!(fmt, "undump error: {}", error),
+            Error::SnapshotError(error) => write!(fmt, "snapshot error: {}", error),
             Error::RuntimeError(error) => write!(fmt, "runtime error: {}", error),
+            Error::Interrupted(error) => write!(fmt, "{}", error),
+            Error::LocatedError(location, error) => write!(fmt, "{}: {}", location, error),
+            Error::LeveledError(error, _) => write!(fmt, "runtime error: {}", error),
+            Error::TracedError(traceback, error) => write!(fmt, "{}\n{}", error, traceback),
         }
     }
 }
@@ -86,15 +218,9 @@ impl<'gc> From<io::Error> for Error<'gc> {
     }
 }
 
-impl<'gc> From<ParserError> for Error<'gc> {
-    fn from(error: ParserError) -> Error<'gc> {
-        Error::ParserError(error)
-    }
-}
-
-impl<'gc> From<CompilerError> for Error<'gc> {
-    fn from(error: CompilerError) -> Error<'gc> {
-        Error::CompilerError(error)
+impl<'gc> From<SyntaxError> for Error<'gc> {
+    fn from(error: SyntaxError) -> Error<'gc> {
+        Error::SyntaxError(error)
     }
 }
 
@@ -140,18 +266,41 @@ impl<'gc> From<BinaryOperatorError> for Error<'gc> {
     }
 }
 
+impl<'gc> From<MetaOperatorError> for Error<'gc> {
+    fn from(error: MetaOperatorError) -> Error<'gc> {
+        Error::MetaOperatorError(error)
+    }
+}
+
+impl<'gc> From<UndumpError> for Error<'gc> {
+    fn from(error: UndumpError) -> Error<'gc> {
+        Error::UndumpError(error)
+    }
+}
+
+impl<'gc> From<SnapshotError> for Error<'gc> {
+    fn from(error: SnapshotError) -> Error<'gc> {
+        Error::SnapshotError(error)
+    }
+}
+
 impl<'gc> From<RuntimeError<'gc>> for Error<'gc> {
     fn from(error: RuntimeError<'gc>) -> Error<'gc> {
         Error::RuntimeError(error)
     }
 }
 
+impl<'gc> From<Interrupted> for Error<'gc> {
+    fn from(error: Interrupted) -> Error<'gc> {
+        Error::Interrupted(error)
+    }
+}
+
 impl<'gc> Error<'gc> {
     pub fn to_static(self) -> StaticError {
         match self {
             Error::IoError(error) => StaticError::IoError(error.0),
-            Error::ParserError(error) => StaticError::ParserError(error),
-            Error::CompilerError(error) => StaticError::CompilerError(error),
+            Error::SyntaxError(error) => StaticError::SyntaxError(error),
             Error::ClosureError(error) => StaticError::ClosureError(error),
             Error::InvalidTableKey(error) => StaticError::InvalidTableKey(error),
             Error::StringError(error) => StaticError::StringError(error),
@@ -159,11 +308,48 @@ impl<'gc> Error<'gc> {
             Error::BadThreadMode(error) => StaticError::BadThreadMode(error),
             Error::TypeError(error) => StaticError::TypeError(error),
             Error::BinaryOperatorError(error) => StaticError::BinaryOperatorError(error),
-            Error::RuntimeError(error) => {
+            Error::MetaOperatorError(error) => StaticError::MetaOperatorError(error),
+            Error::UndumpError(error) => StaticError::UndumpError(error),
+            Error::SnapshotError(error) => StaticError::SnapshotError(error),
+            Error::RuntimeError(error) | Error::LeveledError(error, _) => {
                 let mut buf = Vec::new();
                 error.0.display(&mut buf).unwrap();
                 StaticError::RuntimeError(StdString::from_utf8_lossy(&buf).to_owned().to_string())
             }
+            Error::Interrupted(error) => StaticError::Interrupted(error),
+            Error::LocatedError(location, error) => {
+                StaticError::LocatedError(location, Box::new(error.to_static()))
+            }
+            Error::TracedError(traceback, error) => {
+                StaticError::TracedError(traceback, Box::new(error.to_static()))
+            }
+        }
+    }
+
+    /// Returns the traceback of an error that escaped a thread.
+    pub fn traceback(&self) -> Option<&Traceback> {
+        match self {
+            Error::TracedError(traceback, _) => Some(traceback),
+            _ => None,
+        }
+    }
+
+    /// Removes the traceback from an error that escaped a thread, returning the error as it was
+    /// raised.
+    pub fn without_traceback(self) -> Error<'gc> {
+        match self {
+            Error::TracedError(_, error) => *error,
+            error => error,
+        }
+    }
+
+    /// Whether Lua code may catch this error, which is true of every error except an uncatchable
+    /// interrupt.
+    pub fn is_catchable(&self) -> bool {
+        match self {
+            Error::Interrupted(error) => error.catchable,
+            Error::LocatedError(_, error) | Error::TracedError(_, error) => error.is_catchable(),
+            _ => true,
         }
     }
 
@@ -173,7 +359,8 @@ impl<'gc> Error<'gc> {
         interned_strings: InternedStringSet<'gc>,
     ) -> Value<'gc> {
         match self {
-            Error::RuntimeError(error) => error.0,
+            Error::RuntimeError(error) | Error::LeveledError(error, _) => error.0,
+            Error::TracedError(_, error) => error.to_value(mc, interned_strings),
             other => {
                 let s = other.to_string();
                 Value::String(interned_strings.new_string(mc, s.as_ref()))
@@ -186,8 +373,7 @@ impl<'gc> Error<'gc> {
 #[collect(require_static)]
 pub enum StaticError {
     IoError(io::Error),
-    ParserError(ParserError),
-    CompilerError(CompilerError),
+    SyntaxError(SyntaxError),
     ClosureError(ClosureError),
     InvalidTableKey(InvalidTableKey),
     StringError(StringError),
@@ -195,7 +381,13 @@ pub enum StaticError {
     BadThreadMode(BadThreadMode),
     TypeError(TypeError),
     BinaryOperatorError(BinaryOperatorError),
+    MetaOperatorError(MetaOperatorError),
+    UndumpError(UndumpError),
+    SnapshotError(SnapshotError),
     RuntimeError(String),
+    Interrupted(Interrupted),
+    LocatedError(Location, Box<StaticError>),
+    TracedError(Traceback, Box<StaticError>),
 }
 
 impl StdError for StaticError {}
@@ -204,16 +396,57 @@ impl fmt::Display for StaticError {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         match self {
             StaticError::IoError(error) => write!(fmt, "i/o error: {}", error),
-            StaticError::ParserError(error) => write!(fmt, "parser error: {}", error),
-            StaticError::CompilerError(error) => write!(fmt, "compiler error: {}", error),
+            StaticError::SyntaxError(error) => write!(fmt, "{}", error),
             StaticError::ClosureError(error) => write!(fmt, "closure error: {}", error),
-            StaticError::InvalidTableKey(error) => write!(fmt, "invalid table key: {}", error),
+            StaticError::InvalidTableKey(InvalidTableKey::ReadOnly) => {
+                write!(fmt, "{}", InvalidTableKey::ReadOnly)
+            }
+            StaticError::InvalidTableKey(error) => write!(fmt, "{}", error),
+            StaticError::StringError(error @ StringError::Concat { .. }) => {
+                write!(fmt, "{}", error)
+            }
             StaticError::StringError(error) => write!(fmt, "string error: {}", error),
             StaticError::ThreadError(error) => write!(fmt, "thread error: {}", error),
             StaticError::BadThreadMode(error) => write!(fmt, "bad thread mode: {}", error),
             StaticError::TypeError(error) => write!(fmt, "type error: {}", error),
             StaticError::BinaryOperatorError(error) => write!(fmt, "operator error: {}", error),
+            StaticError::MetaOperatorError(error) => write!(fmt, "metamethod error: {}", error),
+            StaticError::UndumpError(error) => write!(fmt, "undump error: {}", error),
+            StaticError::SnapshotError(error) => write!(fmt, "snapshot error: {}", error),
             StaticError::RuntimeError(error) => write!(fmt, "runtime error: {}", error),
+            StaticError::Interrupted(error) => write!(fmt, "{}", error),
+            StaticError::LocatedError(location, error) => write!(fmt, "{}: {}", location, error),
+            StaticError::TracedError(traceback, error) => write!(fmt, "{}\n{}", error, traceback),
+        }
+    }
+}
+
+impl StaticError {
+    /// Returns the traceback of an error that escaped a thread.
+    pub fn traceback(&self) -> Option<&Traceback> {
+        match self {
+            StaticError::TracedError(traceback, _) => Some(traceback),
+            _ => None,
+        }
+    }
+
+    /// Removes the traceback from an error that escaped a thread, returning the error as it was
+    /// raised.
+    pub fn without_traceback(self) -> StaticError {
+        match self {
+            StaticError::TracedError(_, error) => *error,
+            error => error,
+        }
+    }
+
+    /// Whether Lua code could have caught this error, see `Error::is_catchable`.
+    pub fn is_catchable(&self) -> bool {
+        match self {
+            StaticError::Interrupted(error) => error.catchable,
+            StaticError::LocatedError(_, error) | StaticError::TracedError(_, error) => {
+                error.is_catchable()
+            }
+            _ => true,
         }
     }
 }
@@ -0,0 +1,172 @@
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+
+use gc_arena::{Collect, CollectionContext, Gc, GcCell, MutationContext};
+
+use crate::{table::WeakMode, Function, InternedStringSet, String, Table, Value};
+
+/// The tables of a Lua instance that are finalized by their `__gc` metamethod once they become
+/// unreachable.
+///
+/// Like in PUC-Rio Lua, a table is registered when `setmetatable` gives it a metatable with a
+/// `__gc` field, and adding the field to its metatable afterwards does nothing.  When the collector
+/// finds that a registered table is unreachable, the table is kept alive along with everything it
+/// references and waits to be finalized.  The next thread to run Lua code then calls the `__gc`
+/// metamethod the table has at that point with the table as its only argument, discarding its
+/// results and any error it raises.  Finalizers of tables found unreachable together are called in
+/// the reverse order that the tables were registered.
+///
+/// A finalized table is no longer registered, so it is freed by a later collection unless the
+/// finalizer made it reachable again or it is registered again with `setmetatable`.
+///
+/// This also clears the weak tables of the instance, which are registered when `setmetatable` gives
+/// a table a metatable with a `__mode` field.  Entries with weak keys are ephemerons, their values
+/// are only reachable through the table while their keys are reachable.  Like in PUC-Rio Lua, the
+/// weak values of unreachable objects are cleared before the objects are kept alive to be
+/// finalized, and their weak keys only after.  The unreachable strings of the instance's
+/// `InternedStringSet` are removed along with the weak keys.
+#[derive(Copy, Clone, Collect)]
+#[collect(require_copy)]
+pub(crate) struct Finalizers<'gc>(Gc<'gc, FinalizersState<'gc>>);
+
+struct FinalizersState<'gc> {
+    // Registered tables in the order they were registered, which are not traced so that they may
+    // become unreachable
+    registered: RefCell<Vec<Table<'gc>>>,
+    // Unreachable tables waiting for their finalizers to be called, in the order to call them
+    pending: RefCell<VecDeque<Table<'gc>>>,
+    // Tables that have had weak keys or values, which are not traced either
+    weak: RefCell<Vec<Table<'gc>>>,
+    // Whether the tables found unreachable by this collection have been kept alive to be finalized
+    resurrected: Cell<bool>,
+    interned_strings: InternedStringSet<'gc>,
+}
+
+unsafe impl<'gc> Collect for FinalizersState<'gc> {
+    fn trace(&self, cc: CollectionContext) {
+        for table in self.pending.borrow().iter() {
+            table.trace(cc);
+        }
+    }
+
+    fn finish_trace(&self, cc: CollectionContext) {
+        let mut weak = self.weak.borrow_mut();
+
+        // Values of reachable weak keys are traced first, and whatever they reference is traced
+        // before this is called again
+        let mut traced = false;
+        for table in weak.iter() {
+            if !GcCell::is_dead(cc, table.0) {
+                traced |= table.0.read().trace_ephemerons(cc);
+            }
+        }
+        if traced {
+            return;
+        }
+
+        if !self.resurrected.get() {
+            for table in weak.iter() {
+                if !GcCell::is_dead(cc, table.0) {
+                    // Safe because nothing borrows a table during collection, and removing
+                    // references needs no write barrier
+                    unsafe { (*table.0.as_ptr()).clear_weak(cc, false) };
+                }
+            }
+
+            let mut registered = self.registered.borrow_mut();
+            let mut dead = Vec::new();
+            registered.retain(|&table| {
+                let is_dead = GcCell::is_dead(cc, table.0);
+                if is_dead {
+                    dead.push(table);
+                }
+                !is_dead
+            });
+
+            // Resurrecting the tables traces everything they reference, after which this is
+            // called again and finds nothing else to finalize
+            if !dead.is_empty() {
+                let mut pending = self.pending.borrow_mut();
+                for table in dead.into_iter().rev() {
+                    table.trace(cc);
+                    pending.push_back(table);
+                }
+                self.resurrected.set(true);
+                return;
+            }
+        }
+
+        // Nothing else is traced by this collection, so whatever is unreachable now is freed
+        self.resurrected.set(false);
+        weak.retain(|&table| {
+            let is_dead = GcCell::is_dead(cc, table.0);
+            if !is_dead {
+                unsafe { (*table.0.as_ptr()).clear_weak(cc, true) };
+            }
+            !is_dead
+        });
+        self.interned_strings.clear_dead(cc);
+    }
+}
+
+impl<'gc> Finalizers<'gc> {
+    /// Creates the finalizers of the arena, there must be only one.
+    pub(crate) fn new(
+        mc: MutationContext<'gc, '_>,
+        interned_strings: InternedStringSet<'gc>,
+    ) -> Finalizers<'gc> {
+        let finalizers = Gc::allocate(
+            mc,
+            FinalizersState {
+                registered: RefCell::new(Vec::new()),
+                pending: RefCell::new(VecDeque::new()),
+                weak: RefCell::new(Vec::new()),
+                resurrected: Cell::new(false),
+                interned_strings,
+            },
+        );
+        Gc::set_finisher(mc, finalizers);
+        Finalizers(finalizers)
+    }
+
+    /// Registers the table to be finalized if its metatable has a `__gc` field and it is not
+    /// registered already, and sets its weak mode from the `__mode` field, for `setmetatable`.
+    pub(crate) fn register(self, mc: MutationContext<'gc, '_>, table: Table<'gc>) {
+        let (has_gc, mode) = match table.metatable() {
+            Some(metatable) => (
+                metatable.get(String::new_static(b"__gc")) != Value::Nil,
+                WeakMode::from_mode(metatable.get(String::new_static(b"__mode"))),
+            ),
+            None => (false, WeakMode::default()),
+        };
+        if has_gc && !table.set_finalize(mc, true) {
+            self.0.registered.borrow_mut().push(table);
+        }
+        if !table.set_weak_mode(mc, mode) && (mode.keys || mode.values) {
+            self.0.weak.borrow_mut().push(table);
+        }
+    }
+
+    /// The interned strings of the instance, which are shared by the threads that share these
+    /// finalizers.
+    pub(crate) fn interned_strings(self) -> InternedStringSet<'gc> {
+        self.0.interned_strings
+    }
+
+    /// Takes the next unreachable table waiting to be finalized along with its `__gc` metamethod.
+    /// Tables whose metatable no longer has a `__gc` function are skipped.
+    pub(crate) fn take_pending(
+        self,
+        mc: MutationContext<'gc, '_>,
+    ) -> Option<(Function<'gc>, Table<'gc>)> {
+        loop {
+            let table = self.0.pending.borrow_mut().pop_front()?;
+            table.set_finalize(mc, false);
+            if let Some(metatable) = table.metatable() {
+                if let Value::Function(finalizer) = metatable.get(String::new_static(b"__gc")) {
+                    return Some((finalizer, table));
+                }
+            }
+        }
+    }
+}
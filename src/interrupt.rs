@@ -0,0 +1,83 @@
+use std::error::Error as StdError;
+use std::fmt;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Arc;
+
+use gc_arena::Collect;
+
+const NOT_TRIGGERED: u8 = 0;
+const CATCHABLE: u8 = 1;
+const UNCATCHABLE: u8 = 2;
+
+/// A handle that stops the Lua code running in the threads it is set on with
+/// `Thread::set_interrupt` or `Lua::set_interrupt`, which may be sent to another OS thread, such as
+/// one that triggers it on a timer to time out scripts without limiting their fuel.
+///
+/// A thread checks its interrupt in-between every few hundred VM instructions, and once it has
+/// been triggered raises an `Interrupted` error in the running Lua function.  Coroutines created by
+/// code running in the thread share its interrupt.  Callbacks are not interrupted, so a thread
+/// only stops once it is back to running Lua code.  Clones share the same state.
+#[derive(Debug, Clone, Default)]
+pub struct Interrupt(Arc<AtomicU8>);
+
+/// The error raised in a thread whose `Interrupt` has been triggered.
+///
+/// A catchable interrupt is an ordinary error that `pcall` and `coroutine.resume` may catch, but
+/// an uncatchable one passes through them without calling any `xpcall` message handler, and always
+/// escapes to the host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Collect)]
+#[collect(require_static)]
+pub struct Interrupted {
+    pub catchable: bool,
+}
+
+impl StdError for Interrupted {}
+
+impl fmt::Display for Interrupted {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "interrupted")
+    }
+}
+
+impl Interrupt {
+    pub fn new() -> Interrupt {
+        Interrupt::default()
+    }
+
+    /// Interrupts the running code with an error that Lua code may catch.
+    pub fn trigger(&self) {
+        let _ = self.0.compare_exchange(
+            NOT_TRIGGERED,
+            CATCHABLE,
+            Ordering::Relaxed,
+            Ordering::Relaxed,
+        );
+    }
+
+    /// Interrupts the running code with an error that Lua code cannot catch.  This overrides a
+    /// catchable interrupt that has not been raised yet.
+    pub fn trigger_uncatchable(&self) {
+        self.0.store(UNCATCHABLE, Ordering::Relaxed);
+    }
+
+    /// Whether the interrupt has been triggered and not yet raised in a thread.
+    pub fn is_triggered(&self) -> bool {
+        self.0.load(Ordering::Relaxed) != NOT_TRIGGERED
+    }
+
+    /// Cancels a trigger that has not been raised yet.
+    pub fn reset(&self) {
+        self.0.store(NOT_TRIGGERED, Ordering::Relaxed);
+    }
+
+    // Takes the error to raise if the interrupt has been triggered, so that it is only raised once
+    // for every trigger.
+    pub(crate) fn take(&self) -> Option<Interrupted> {
+        match self.0.swap(NOT_TRIGGERED, Ordering::Relaxed) {
+            NOT_TRIGGERED => None,
+            state => Some(Interrupted {
+                catchable: state == CATCHABLE,
+            }),
+        }
+    }
+}
@@ -4,6 +4,7 @@ use std::{char, fmt, i32, i64, str};
 use gc_arena::Collect;
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Token<S> {
     Break,
     Do,
@@ -68,10 +69,81 @@ pub enum Token<S> {
     String(S),
 }
 
+/// Displays a token as it would be written in Lua source.
+impl<S: AsRef<[u8]>> fmt::Display for Token<S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let symbol = match self {
+            Token::Break => "break",
+            Token::Do => "do",
+            Token::Else => "else",
+            Token::ElseIf => "elseif",
+            Token::End => "end",
+            Token::Function => "function",
+            Token::Goto => "goto",
+            Token::If => "if",
+            Token::In => "in",
+            Token::Local => "local",
+            Token::Nil => "nil",
+            Token::For => "for",
+            Token::While => "while",
+            Token::Repeat => "repeat",
+            Token::Until => "until",
+            Token::Return => "return",
+            Token::Then => "then",
+            Token::True => "true",
+            Token::False => "false",
+            Token::Not => "not",
+            Token::And => "and",
+            Token::Or => "or",
+            Token::Minus => "-",
+            Token::Add => "+",
+            Token::Mul => "*",
+            Token::Div => "/",
+            Token::IDiv => "//",
+            Token::Pow => "^",
+            Token::Mod => "%",
+            Token::Len => "#",
+            Token::BitNotXor => "~",
+            Token::BitAnd => "&",
+            Token::BitOr => "|",
+            Token::ShiftRight => ">>",
+            Token::ShiftLeft => "<<",
+            Token::Concat => "..",
+            Token::Dots => "...",
+            Token::Assign => "=",
+            Token::LessThan => "<",
+            Token::LessEqual => "<=",
+            Token::GreaterThan => ">",
+            Token::GreaterEqual => ">=",
+            Token::Equal => "==",
+            Token::NotEqual => "~=",
+            Token::Dot => ".",
+            Token::SemiColon => ";",
+            Token::Colon => ":",
+            Token::DoubleColon => "::",
+            Token::Comma => ",",
+            Token::LeftParen => "(",
+            Token::RightParen => ")",
+            Token::LeftBracket => "[",
+            Token::RightBracket => "]",
+            Token::LeftBrace => "{",
+            Token::RightBrace => "}",
+            Token::Integer(i) => return write!(f, "{}", i),
+            Token::Float(n) => return write!(f, "{}", crate::value::number_to_string(*n)),
+            Token::Name(name) => return write!(f, "{}", String::from_utf8_lossy(name.as_ref())),
+            Token::String(string) => {
+                return write!(f, "\"{}\"", String::from_utf8_lossy(string.as_ref()));
+            }
+        };
+        write!(f, "{}", symbol)
+    }
+}
+
 #[derive(Debug, Collect)]
 #[collect(require_static)]
 pub enum LexerError {
     UnfinishedShortString(u8),
+    EndOfStreamInShortString(u8),
     UnexpectedCharacter(u8),
     HexDigitExpected,
     EscapeUnicodeStart,
@@ -85,6 +157,17 @@ pub enum LexerError {
     IOError(io::Error),
 }
 
+impl LexerError {
+    /// Returns true if this error was caused by the source ending in the middle of a token, so
+    /// that more input could complete it.
+    pub fn is_incomplete(&self) -> bool {
+        match self {
+            LexerError::EndOfStreamInShortString(_) | LexerError::UnfinishedLongString => true,
+            _ => false,
+        }
+    }
+}
+
 impl fmt::Display for LexerError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         fn print_char(c: u8) -> char {
@@ -97,6 +180,11 @@ impl fmt::Display for LexerError {
                 "short string not finished, expected matching {}",
                 print_char(*c)
             ),
+            LexerError::EndOfStreamInShortString(c) => write!(
+                f,
+                "unexpected end of stream in short string, expected matching {}",
+                print_char(*c)
+            ),
             LexerError::UnexpectedCharacter(c) => {
                 write!(f, "unexpected character: '{}'", print_char(*c))
             }
@@ -116,12 +204,49 @@ impl fmt::Display for LexerError {
     }
 }
 
+/// A location in source code, as a 0-indexed line and a 0-indexed column in bytes.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Collect)]
+#[collect(require_static)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Position {
+    pub line: u64,
+    pub column: u64,
+}
+
+impl fmt::Display for Position {
+    // Displays the position 1-indexed as `line:column`, the way editors and compilers report it.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line + 1, self.column + 1)
+    }
+}
+
+/// The range of source code covered by a token or syntax tree node, from the position of its first
+/// character up to the position just after its last.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default, Collect)]
+#[collect(require_static)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Span {
+    pub start: Position,
+    pub end: Position,
+}
+
+impl Span {
+    /// An empty span at the given position
+    pub fn at(position: Position) -> Span {
+        Span {
+            start: position,
+            end: position,
+        }
+    }
+}
+
 pub struct Lexer<R, CS> {
     source: Option<R>,
     create_string: CS,
     peek_buffer: Vec<u8>,
     string_buffer: Vec<u8>,
     line_number: u64,
+    column_number: u64,
 }
 
 impl<R, S, CS> Lexer<R, CS>
@@ -136,6 +261,7 @@ where
             peek_buffer: Vec::new(),
             string_buffer: Vec::new(),
             line_number: 0,
+            column_number: 0,
         }
     }
 
@@ -144,6 +270,20 @@ where
         self.line_number
     }
 
+    /// Current column of the source file in bytes, 0-indexed.  After an error, this is the position
+    /// of the character that caused it.
+    pub fn column_number(&self) -> u64 {
+        self.column_number
+    }
+
+    /// The current line and column together
+    pub fn position(&self) -> Position {
+        Position {
+            line: self.line_number,
+            column: self.column_number,
+        }
+    }
+
     pub fn skip_whitespace(&mut self) -> Result<(), LexerError> {
         let mut do_skip_whitespace = || {
             while let Some(c) = self.peek(0)? {
@@ -390,6 +530,7 @@ where
         }
 
         self.line_number += 1;
+        self.column_number = 0;
         Ok(())
     }
 
@@ -406,7 +547,7 @@ where
             let c = if let Some(c) = self.peek(0)? {
                 c
             } else {
-                return Err(LexerError::UnfinishedShortString(start_quote));
+                return Err(LexerError::EndOfStreamInShortString(start_quote));
             };
 
             if is_newline(c) {
@@ -417,7 +558,7 @@ where
             if c == b'\\' {
                 match self
                     .peek(0)?
-                    .ok_or_else(|| LexerError::UnfinishedShortString(start_quote))?
+                    .ok_or_else(|| LexerError::EndOfStreamInShortString(start_quote))?
                 {
                     b'a' => {
                         self.advance(1);
@@ -475,46 +616,51 @@ where
 
                     b'x' => {
                         self.advance(1);
-                        let first = self
-                            .peek(0)?
-                            .and_then(from_hex_digit)
-                            .ok_or(LexerError::HexDigitExpected)?;
-                        let second = self
-                            .peek(1)?
-                            .and_then(from_hex_digit)
-                            .ok_or(LexerError::HexDigitExpected)?;
-                        self.string_buffer.push(first << 4 | second);
-                        self.advance(2);
+                        let mut x = 0;
+                        for _ in 0..2 {
+                            let d = self
+                                .peek(0)?
+                                .and_then(from_hex_digit)
+                                .ok_or(LexerError::HexDigitExpected)?;
+                            x = x << 4 | d;
+                            self.advance(1);
+                        }
+                        self.string_buffer.push(x);
                     }
 
                     b'u' => {
-                        if self.peek(1)? != Some(b'{') {
+                        self.advance(1);
+                        if self.peek(0)? != Some(b'{') {
                             return Err(LexerError::EscapeUnicodeStart);
                         }
-                        self.advance(2);
+                        self.advance(1);
 
-                        let mut u: u32 = 0;
+                        let mut u: u32 = self
+                            .peek(0)?
+                            .and_then(from_hex_digit)
+                            .ok_or(LexerError::HexDigitExpected)?
+                            as u32;
+                        self.advance(1);
                         loop {
-                            if let Some(c) = self.peek(0)? {
-                                if c == b'}' {
+                            match self.peek(0)? {
+                                Some(b'}') => {
                                     self.advance(1);
                                     break;
-                                } else if let Some(h) = from_hex_digit(c) {
-                                    u = (u << 4) | h as u32;
+                                }
+                                Some(c) if is_hex_digit(c) => {
+                                    // Like PUC-Rio Lua, allow values up to 2^31 - 1, which are
+                                    // encoded with the original, up to 6 byte, UTF-8 scheme.
+                                    if u > 0x7FF_FFFF {
+                                        return Err(LexerError::EscapeUnicodeInvalid);
+                                    }
+                                    u = (u << 4) | from_hex_digit(c).unwrap() as u32;
                                     self.advance(1);
-                                } else {
-                                    return Err(LexerError::EscapeUnicodeEnd);
                                 }
-                            } else {
-                                return Err(LexerError::EscapeUnicodeEnd);
+                                _ => return Err(LexerError::EscapeUnicodeEnd),
                             }
                         }
 
-                        let c = char::from_u32(u).ok_or(LexerError::EscapeUnicodeInvalid)?;
-                        let mut buf = [0; 4];
-                        for &b in c.encode_utf8(&mut buf).as_bytes() {
-                            self.string_buffer.push(b);
-                        }
+                        encode_utf8(u, &mut self.string_buffer);
                     }
 
                     b'z' => {
@@ -739,6 +885,7 @@ where
             "cannot advance over un-peeked characters"
         );
         self.peek_buffer.drain(0..n);
+        self.column_number += n as u64;
     }
 
     fn take_string(&mut self) -> S {
@@ -768,6 +915,8 @@ pub fn read_integer(s: &[u8]) -> Option<i64> {
     }
 }
 
+/// Reads a hex integer, which wraps around on overflow like in PUC-Rio Lua rather than becoming a
+/// float.
 pub fn read_hex_integer(s: &[u8]) -> Option<i64> {
     let (is_neg, s) = read_neg(s);
 
@@ -778,18 +927,14 @@ pub fn read_hex_integer(s: &[u8]) -> Option<i64> {
     let mut i: i64 = 0;
     for &c in &s[2..] {
         let d = from_hex_digit(c)? as i64;
-        i = i.checked_mul(16)?.checked_add(d)?;
+        i = i.wrapping_mul(16).wrapping_add(d);
     }
 
     if is_neg {
-        i = i.checked_neg()?;
+        i = i.wrapping_neg();
     }
 
-    if i == i64::MIN {
-        None
-    } else {
-        Some(i)
-    }
+    Some(i)
 }
 
 pub fn read_float(s: &[u8]) -> Option<f64> {
@@ -910,6 +1055,11 @@ fn get_char_token<S>(c: u8) -> Option<Token<S>> {
     }
 }
 
+// Whether the word is reserved, and so cannot be used as a name.
+pub(crate) fn is_reserved_word(word: &[u8]) -> bool {
+    get_reserved_word_token::<()>(word).is_some()
+}
+
 fn get_reserved_word_token<S>(word: &[u8]) -> Option<Token<S>> {
     match word {
         b"break" => Some(Token::Break),
@@ -975,6 +1125,29 @@ fn from_hex_digit(c: u8) -> Option<u8> {
     }
 }
 
+// Appends the UTF-8 encoding of the given value, which may be a surrogate or any value up to 2^31 - 1
+// using the original 5 and 6 byte forms.
+fn encode_utf8(mut u: u32, buf: &mut Vec<u8>) {
+    if u < 0x80 {
+        buf.push(u as u8);
+        return;
+    }
+
+    let mut tail = [0; 5];
+    let mut tail_len = 0;
+    // The largest value that fits in the first byte, given the number of continuation bytes
+    let mut first_max = 0x3f;
+    while u > first_max {
+        tail[tail_len] = 0x80 | (u & 0x3f) as u8;
+        tail_len += 1;
+        u >>= 6;
+        first_max >>= 1;
+    }
+
+    buf.push((!first_max << 1) as u8 | u as u8);
+    buf.extend(tail[0..tail_len].iter().rev());
+}
+
 fn is_hex_digit(c: u8) -> bool {
     from_hex_digit(c).is_some()
 }
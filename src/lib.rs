@@ -1,40 +1,83 @@
+mod args;
 #[macro_use]
 mod callback;
 mod closure;
 mod compiler;
 mod constant;
+mod conversion;
+mod coverage;
+mod dump;
 mod error;
+mod finalizers;
+mod interrupt;
 pub mod io;
 mod lexer;
+mod luac53;
 #[macro_use]
 mod lua;
+pub mod meta_ops;
 mod opcode;
 pub mod parser;
+mod profiler;
+mod registry;
+mod scope;
+#[cfg(feature = "serde")]
+mod serde_value;
+mod snapshot;
 mod string;
 mod table;
 mod thread;
 mod types;
+mod userdata;
 mod value;
 
 mod stdlib;
 
+pub use args::Args;
 pub use callback::{Callback, CallbackResult, CallbackReturn, Continuation};
 pub use closure::{
-    Closure, ClosureError, ClosureState, FunctionProto, UpValue, UpValueDescriptor, UpValueState,
+    Closure, ClosureError, ClosureState, FunctionProto, LocalVariable, UpValue, UpValueDescriptor,
+    UpValueState,
+};
+pub use compiler::{
+    compile, compile_chunk, compile_with_coverage, optimize_opcodes, CompilerError,
 };
-pub use compiler::{compile, compile_chunk, CompilerError};
 pub use constant::Constant;
-pub use error::{Error, RuntimeError, StaticError, TypeError};
-pub use lexer::{Lexer, LexerError, Token};
-pub use lua::{Lua, Root};
+pub use conversion::{BadArgument, FromLua, FromMultiValue, ToLua, ToMultiValue, Variadic};
+pub use coverage::{ChunkCoverage, Coverage, CoverageReport, FunctionCoverage};
+pub use dump::{dump_function, undump_function, UndumpError, BINARY_CHUNK_SIGNATURE};
+pub use error::{
+    Error, Location, RuntimeError, StaticError, SyntaxError, SyntaxErrorKind, TypeError,
+};
+pub use interrupt::{Interrupt, Interrupted};
+pub use lexer::{Lexer, LexerError, Position, Span, Token};
+pub use lua::{GcParameters, GcPolicy, Generator, Lua, ResumeAsync, Root};
+pub use meta_ops::MetaOperatorError;
 pub use opcode::OpCode;
 pub use parser::{parse_chunk, ParserError};
+pub use profiler::{FunctionSamples, ProfileFrame, ProfileReport, Profiler, ProfilerSignal};
+pub use registry::{Registry, RegistryKey};
+pub use scope::Scope;
+#[cfg(feature = "serde")]
+pub use serde_value::{
+    from_value, to_value, SerdeError, SerializeTable, SerializeVariant, ValueDeserializer,
+    ValueSerializer,
+};
+pub use snapshot::{restore_snapshot, save_snapshot, SnapshotError};
+pub use stdlib::{Searcher, StdlibSet};
 pub use string::{InternedStringSet, String, StringError};
-pub use table::{InvalidTableKey, Table, TableState};
+pub use table::{ArrayIter, InvalidTableKey, Table, TableIter, TableState};
 pub use thread::{
-    BadThreadMode, BinaryOperatorError, Thread, ThreadError, ThreadMode, ThreadSequence,
+    BadThreadMode, BinaryOperatorError, FunctionName, Hook, HookContext, HookEvent, HostHook,
+    ResumeResult, ResumeSequence, StackFrame, Thread, ThreadError, ThreadMode, ThreadSequence,
+    ThreadStatus, Traceback, TracebackFrame,
 };
 pub use types::{
-    ConstantIndex16, ConstantIndex8, Opt254, PrototypeIndex, RegisterIndex, UpValueIndex, VarCount,
+    ConstantIndex16, ConstantIndex8, Opt254, PrototypeIndex, RegisterIndex, SizeHint, UpValueIndex,
+    VarCount,
 };
+pub use userdata::{AnyUserData, UserData, UserDataMetatables, UserDataState};
 pub use value::{Function, Value};
+
+#[cfg(feature = "derive")]
+pub use luster_derive::{FromLua, ToLua};
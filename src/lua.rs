@@ -1,9 +1,36 @@
-use gc_arena::{ArenaParameters, Collect, MutationContext};
-use gc_sequence::{make_sequencable_arena, Sequence};
+use std::cell::{Cell, RefCell};
+use std::future::Future;
+use std::io::{self, Write};
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, Thread as StdThread};
 
+use gc_arena::{ArenaParameters, Collect, Gc, MutationContext, StaticCollect};
+use gc_sequence::{
+    self as sequence, make_sequencable_arena, Sequence, SequenceExt, SequenceResultExt,
+};
+use rand::{FromEntropy, SeedableRng};
+use rand_xoshiro::Xoshiro256StarStar;
+
+#[cfg(feature = "json")]
+use crate::stdlib::load_json;
+#[cfg(feature = "os")]
+use crate::stdlib::load_os;
 use crate::{
-    stdlib::{load_base, load_coroutine, load_math, load_string},
-    InternedStringSet, Table, Thread,
+    compile_with_coverage,
+    finalizers::Finalizers,
+    restore_snapshot, save_snapshot,
+    snapshot::name_host_values,
+    stdlib::{
+        load_base, load_coroutine, load_debug, load_io, load_math, load_package, load_string,
+        load_table, load_table_extensions, load_utf8, searcher_callback, Searcher, StdlibSet,
+    },
+    AnyUserData, Callback, Closure, Coverage, Error, FromMultiValue, Function, InternedStringSet,
+    Interrupt, Registry, RegistryKey, ResumeResult, ResumeSequence, Scope, StaticError, String,
+    Table, Thread, ThreadMode, ThreadSequence, ToMultiValue, TypeError, UserDataMetatables, Value,
 };
 
 #[derive(Collect, Clone, Copy)]
@@ -12,23 +39,163 @@ pub struct Root<'gc> {
     pub main_thread: Thread<'gc>,
     pub globals: Table<'gc>,
     pub interned_strings: InternedStringSet<'gc>,
+    /// The metatable shared by all string values, its `__index` field is the `string` library table.
+    pub string_metatable: Table<'gc>,
+    /// Where the `print` function writes to, standard output unless changed with
+    /// `Lua::set_output`.
+    pub output: Gc<'gc, StaticCollect<RefCell<Box<dyn Write>>>>,
+    /// The `package` library table, whose `searchers` field `Lua::add_searcher` adds to.
+    pub package: Table<'gc>,
+    /// The metatables used by `AnyUserData::new_typed`, one for each `UserData` type.
+    pub userdata_metatables: UserDataMetatables<'gc>,
+    /// Values stashed by the host to be fetched again in later mutations.
+    pub registry: Registry<'gc>,
+    pub(crate) collector: Gc<'gc, StaticCollect<Rc<Collector>>>,
+    /// The tables to call the `__gc` metamethod of once they are unreachable.
+    pub(crate) finalizers: Finalizers<'gc>,
+    /// The generator used by `math.random`, seeded by `math.randomseed` or `Lua::seed_random`.
+    pub(crate) rng: Gc<'gc, StaticCollect<RefCell<Xoshiro256StarStar>>>,
+    pub(crate) pending_future: Gc<'gc, StaticCollect<Rc<PendingFuture>>>,
+    /// The coverage that chunks compiled from source count their lines in, set with
+    /// `Lua::set_coverage`.
+    pub(crate) coverage: Gc<'gc, StaticCollect<RefCell<Option<Coverage>>>>,
+    // The callbacks and userdata that may be saved in a snapshot, by name
+    pub(crate) host_names: Table<'gc>,
+}
+
+/// Garbage collector state shared between `Lua` and the `collectgarbage` function.  Functions
+/// running inside the arena cannot control the collector, so `collectgarbage` makes requests that
+/// `Lua` carries out between sequence steps.
+#[derive(Default)]
+pub(crate) struct Collector {
+    pub(crate) stopped: Cell<bool>,
+    pub(crate) collect_requested: Cell<bool>,
+    pub(crate) total_allocated: Cell<usize>,
 }
 
+/// The host future that an async callback is waiting on, shared between `Lua` and the callback.  A
+/// sequence step has no way to poll a future, so `Lua` polls it in-between sequence steps until it
+/// completes.
+#[derive(Default)]
+pub(crate) struct PendingFuture(pub(crate) RefCell<Option<Pin<Box<dyn Future<Output = ()>>>>>);
+
 impl<'gc> Root<'gc> {
     pub fn new(mc: MutationContext<'gc, '_>) -> Root<'gc> {
+        Root::with_stdlib(mc, StdlibSet::ALL)
+    }
+
+    /// Creates a root with only the given standard libraries loaded into its globals.
+    pub fn with_stdlib(mc: MutationContext<'gc, '_>, libs: StdlibSet) -> Root<'gc> {
+        let string_metatable = Table::new(mc);
+        let interned_strings = InternedStringSet::new(mc);
+        let finalizers = Finalizers::new(mc, interned_strings);
+        let main_thread = Thread::new(mc, string_metatable, false);
+        main_thread.set_finalizers(mc, finalizers);
         let root = Root {
-            main_thread: Thread::new(mc, false),
+            main_thread,
             globals: Table::new(mc),
-            interned_strings: InternedStringSet::new(mc),
+            interned_strings,
+            string_metatable,
+            output: Gc::allocate(
+                mc,
+                StaticCollect(RefCell::new(Box::new(io::stdout()) as Box<dyn Write>)),
+            ),
+            package: Table::new(mc),
+            userdata_metatables: UserDataMetatables::new(mc),
+            registry: Registry::new(mc),
+            collector: Gc::allocate(mc, StaticCollect(Rc::new(Collector::default()))),
+            finalizers,
+            rng: Gc::allocate(
+                mc,
+                StaticCollect(RefCell::new(Xoshiro256StarStar::from_entropy())),
+            ),
+            pending_future: Gc::allocate(mc, StaticCollect(Rc::new(PendingFuture::default()))),
+            coverage: Gc::allocate(mc, StaticCollect(RefCell::new(None))),
+            host_names: Table::new(mc),
         };
 
-        load_base(mc, root, root.globals);
-        load_coroutine(mc, root, root.globals);
-        load_math(mc, root, root.globals);
-        load_string(mc, root, root.globals);
+        if libs.contains(StdlibSet::BASE) {
+            load_base(mc, root, root.globals);
+            if !libs.contains(StdlibSet::IO) {
+                for name in &[&b"dofile"[..], &b"loadfile"[..]] {
+                    root.globals
+                        .set(mc, String::new_static(name), Value::Nil)
+                        .unwrap();
+                }
+            }
+        }
+        if libs.contains(StdlibSet::COROUTINE) {
+            load_coroutine(mc, root, root.globals);
+        }
+        if libs.contains(StdlibSet::MATH) {
+            load_math(mc, root, root.globals);
+        }
+        if libs.contains(StdlibSet::STRING) {
+            load_string(mc, root, root.globals);
+        }
+        if libs.contains(StdlibSet::TABLE) {
+            load_table(mc, root, root.globals);
+        }
+        if libs.contains(StdlibSet::TABLE_EXTENSIONS) {
+            load_table_extensions(mc, root, root.globals);
+        }
+        if libs.contains(StdlibSet::UTF8) {
+            load_utf8(mc, root, root.globals);
+        }
+        if libs.contains(StdlibSet::PACKAGE) {
+            load_package(mc, root, root.globals);
+        }
+        if libs.contains(StdlibSet::IO) {
+            load_io(mc, root, root.globals);
+        }
+        if libs.contains(StdlibSet::DEBUG) {
+            load_debug(mc, root, root.globals);
+        }
+        #[cfg(feature = "os")]
+        {
+            if libs.contains(StdlibSet::OS) {
+                load_os(mc, root, root.globals);
+            }
+        }
+        #[cfg(feature = "json")]
+        {
+            if libs.contains(StdlibSet::JSON) {
+                load_json(mc, root, root.globals);
+            }
+        }
+        name_host_values(mc, root);
 
         root
     }
+
+    /// Gives a callback the name it is saved by in snapshots, which must be the same name that it
+    /// is given in the root a snapshot is restored into.  This replaces any value that already had
+    /// the name.
+    pub fn name_callback(self, mc: MutationContext<'gc, '_>, name: &str, callback: Callback<'gc>) {
+        self.host_names
+            .set(mc, String::new(mc, name.as_bytes()), callback)
+            .unwrap();
+    }
+
+    /// Gives userdata the name it is saved by in snapshots, like `Root::name_callback`.
+    pub fn name_userdata(
+        self,
+        mc: MutationContext<'gc, '_>,
+        name: &str,
+        userdata: AnyUserData<'gc>,
+    ) {
+        self.host_names
+            .set(mc, String::new(mc, name.as_bytes()), userdata)
+            .unwrap();
+    }
+
+    /// Names every callback and userdata that can be reached from the globals through tables and
+    /// has no name yet by its path from the globals, like `string.format` or `io.stdout`.  This is
+    /// done for the standard libraries when the root is created, and may be done again once the
+    /// host has added its own values to the globals.
+    pub fn name_host_values(self, mc: MutationContext<'gc, '_>) {
+        name_host_values(mc, self);
+    }
 }
 
 make_sequencable_arena!(pub lua_arena, Root);
@@ -37,15 +204,387 @@ pub use lua_arena::Arena;
 pub use lua_arena::Sequencer;
 
 /// Simpler wrapper for `Arena` that automatically garbage collects at reasonable intervals.
-pub struct Lua(Option<lua_arena::Arena>);
+pub struct Lua {
+    arena: Option<lua_arena::Arena>,
+    collector: Rc<Collector>,
+    pending_future: Rc<PendingFuture>,
+    gc_parameters: GcParameters,
+    gc_step_size: usize,
+    gc_policy: Option<Box<dyn GcPolicy>>,
+}
+
+const DEFAULT_GC_STEP_SIZE: usize = 1024;
+
+/// How the incremental garbage collector of a `Lua` is paced, given to `Lua::with_gc_parameters`
+/// and changed afterwards with `Lua::set_gc_pause` and `Lua::set_step_multiplier`.
+///
+/// How much work is done at once is set separately, with `Lua::set_gc_step_size` and
+/// `Lua::set_gc_policy`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct GcParameters {
+    pause: f64,
+    step_multiplier: f64,
+}
+
+/// Creates parameters with a pause of 1.5 and a step multiplier of 5/3, the pacing used by
+/// `Lua::new`.
+impl Default for GcParameters {
+    fn default() -> GcParameters {
+        GcParameters {
+            pause: 1.5,
+            step_multiplier: 1.0 + 1.0 / 1.5,
+        }
+    }
+}
+
+impl GcParameters {
+    /// How large the heap may grow after a collection before the next one starts, as a multiple of
+    /// the memory that survived it.  Must be at least 1.0, with 1.0 starting a new collection as
+    /// soon as one finishes.  Larger values collect less often, using more memory for less work.
+    pub fn set_pause(mut self, pause: f64) -> GcParameters {
+        assert!(pause >= 1.0, "gc pause must be at least 1.0");
+        self.pause = pause;
+        self
+    }
+
+    /// How many bytes of collection work are done for each byte allocated while a collection is
+    /// running.  Must be more than 1.0 so that collections always finish, and may be infinite to
+    /// do a whole collection at once.  Larger values finish collections sooner, with each step
+    /// taking longer.
+    pub fn set_step_multiplier(mut self, step_multiplier: f64) -> GcParameters {
+        assert!(
+            step_multiplier > 1.0,
+            "gc step multiplier must be more than 1.0"
+        );
+        self.step_multiplier = step_multiplier;
+        self
+    }
+
+    pub fn pause(&self) -> f64 {
+        self.pause
+    }
+
+    pub fn step_multiplier(&self) -> f64 {
+        self.step_multiplier
+    }
 
-const COLLECTOR_GRANULARITY: f64 = 1024.0;
+    fn to_arena_parameters(self) -> ArenaParameters {
+        // The arena owes `1 + 1 / timing_factor` bytes of work for every byte allocated
+        ArenaParameters::default()
+            .set_pause_factor(self.pause - 1.0)
+            .set_timing_factor(1.0 / (self.step_multiplier - 1.0))
+    }
+}
+
+/// Decides when a `Lua` does the garbage collection work it owes, set with `Lua::set_gc_policy`.
+///
+/// Collection work is owed in proportion to the memory allocated while a collection is running,
+/// and paying it pauses the host for about as long as it takes to trace that many bytes.  By
+/// default it is paid whenever more than the step size is owed.  A host that must not pause at
+/// certain times, such as a game during a frame or an audio callback, can defer the work and pay
+/// it with `Lua::collect_debt` when it has time to spare.  Deferring work never loses it, but the
+/// longer it is deferred the longer paying it takes and the larger the heap grows meanwhile.
+pub trait GcPolicy {
+    /// Called after every mutation and in-between sequence steps while the collector is running
+    /// and some work is owed, with the bytes currently allocated and the bytes of work owed.
+    /// Returns whether to do that work now.
+    fn should_collect(&mut self, total_allocated: usize, allocation_debt: f64) -> bool;
+}
 
 impl Lua {
     pub fn new() -> Lua {
-        Lua(Some(Arena::new(ArenaParameters::default(), |mc| {
-            Root::new(mc)
-        })))
+        Lua::with_stdlib(StdlibSet::ALL)
+    }
+
+    /// Creates a `Lua` with only the given standard libraries loaded, see `StdlibSet`.
+    pub fn with_stdlib(libs: StdlibSet) -> Lua {
+        Lua::with_gc_parameters(libs, GcParameters::default())
+    }
+
+    /// Creates a `Lua` with only the given standard libraries loaded, whose garbage collector is
+    /// paced by `parameters`.
+    pub fn with_gc_parameters(libs: StdlibSet, parameters: GcParameters) -> Lua {
+        let mut arena = Arena::new(parameters.to_arena_parameters(), |mc| {
+            Root::with_stdlib(mc, libs)
+        });
+        let (collector, pending_future) =
+            arena.mutate(|_, root| (root.collector.0.clone(), root.pending_future.0.clone()));
+        collector.total_allocated.set(arena.total_allocated());
+        Lua {
+            arena: Some(arena),
+            collector,
+            pending_future,
+            gc_parameters: parameters,
+            gc_step_size: DEFAULT_GC_STEP_SIZE,
+            gc_policy: None,
+        }
+    }
+
+    /// The number of bytes currently allocated by the arena.
+    pub fn total_allocated(&self) -> usize {
+        self.arena.as_ref().unwrap().total_allocated()
+    }
+
+    /// Runs a full garbage collection cycle, freeing everything that is unreachable.
+    pub fn collect_all(&mut self) {
+        let arena = self.arena.as_mut().unwrap();
+        // A collection that was already in progress may keep objects that have since become
+        // unreachable, so a second cycle is needed to be sure to free everything
+        arena.collect_all();
+        arena.collect_all();
+        self.collector.total_allocated.set(arena.total_allocated());
+    }
+
+    /// The parameters currently pacing the garbage collector.
+    pub fn gc_parameters(&self) -> GcParameters {
+        self.gc_parameters
+    }
+
+    /// Sets the pause of the garbage collector, see `GcParameters::set_pause`.  It takes effect
+    /// once the current collection finishes.
+    pub fn set_gc_pause(&mut self, pause: f64) {
+        self.set_gc_parameters(self.gc_parameters.set_pause(pause));
+    }
+
+    /// Sets the step multiplier of the garbage collector, see `GcParameters::set_step_multiplier`.
+    /// It takes effect from the next allocation.
+    pub fn set_step_multiplier(&mut self, step_multiplier: f64) {
+        self.set_gc_parameters(self.gc_parameters.set_step_multiplier(step_multiplier));
+    }
+
+    fn set_gc_parameters(&mut self, parameters: GcParameters) {
+        self.gc_parameters = parameters;
+        self.arena
+            .as_mut()
+            .unwrap()
+            .mutate(|mc, _| mc.set_arena_parameters(parameters.to_arena_parameters()));
+    }
+
+    /// The bytes of garbage collection work currently owed, see `GcPolicy`.
+    pub fn allocation_debt(&self) -> f64 {
+        self.arena.as_ref().unwrap().allocation_debt()
+    }
+
+    /// Does all of the garbage collection work currently owed, even while the collector is stopped.
+    /// This is how a `GcPolicy` that defers work gets it done at a time of the host's choosing.
+    pub fn collect_debt(&mut self) {
+        let arena = self.arena.as_mut().unwrap();
+        arena.collect_debt();
+        self.collector.total_allocated.set(arena.total_allocated());
+    }
+
+    /// How many bytes of garbage collection work may be owed before it is done, when no
+    /// `GcPolicy` is set.
+    pub fn gc_step_size(&self) -> usize {
+        self.gc_step_size
+    }
+
+    /// Sets how many bytes of garbage collection work may be owed before it is done, 1024 by
+    /// default.  Smaller steps make each pause shorter at the cost of more time spent collecting
+    /// overall, and larger steps the opposite.
+    pub fn set_gc_step_size(&mut self, step_size: usize) {
+        self.gc_step_size = step_size;
+    }
+
+    /// Sets or removes the policy deciding when garbage collection work is done, returning the
+    /// previous one.  Without a policy work is done whenever more than the step size is owed.
+    pub fn set_gc_policy(
+        &mut self,
+        policy: Option<Box<dyn GcPolicy>>,
+    ) -> Option<Box<dyn GcPolicy>> {
+        std::mem::replace(&mut self.gc_policy, policy)
+    }
+
+    /// Whether garbage is collected automatically, which can also be changed with
+    /// `collectgarbage("stop")` and `collectgarbage("restart")`.
+    pub fn is_collector_running(&self) -> bool {
+        !self.collector.stopped.get()
+    }
+
+    /// Stops or restarts automatic garbage collection.  While it is stopped, memory is only freed
+    /// by `Lua::collect_all` or by `collectgarbage("collect")`.
+    pub fn set_collector_running(&mut self, running: bool) {
+        self.collector.stopped.set(!running);
+    }
+
+    /// Seeds the generator used by `math.random`, which is otherwise seeded randomly.  Seeding with
+    /// the same value always produces the same sequence of numbers, the same as calling
+    /// `math.randomseed` with the seed as an integer.
+    pub fn seed_random(&mut self, seed: u64) {
+        self.mutate(move |_, root| {
+            *root.rng.0.borrow_mut() = Xoshiro256StarStar::seed_from_u64(seed);
+        })
+    }
+
+    /// Sets where the `print` function writes to, returning the previous output.  This can be used
+    /// to capture the output of scripts rather than writing it to standard output.
+    pub fn set_output(&mut self, output: Box<dyn Write>) -> Box<dyn Write> {
+        self.mutate(move |_, root| root.output.0.replace(output))
+    }
+
+    /// Sets or removes the interrupt of the main thread, which coroutines created afterwards share.
+    /// Triggering it from another OS thread stops the script that is running, see `Interrupt`.
+    pub fn set_interrupt(&mut self, interrupt: Option<Interrupt>) {
+        self.mutate(move |mc, root| root.main_thread.set_interrupt(mc, interrupt))
+    }
+
+    /// Sets the coverage that every chunk compiled from source afterwards counts its lines in,
+    /// whether by `Lua::exec`, `Lua::eval`, `load` or `require`, returning the previous coverage.
+    /// Functions compiled before keep counting in the coverage they were compiled with.
+    pub fn set_coverage(&mut self, coverage: Option<Coverage>) -> Option<Coverage> {
+        self.mutate(move |_, root| root.coverage.0.replace(coverage))
+    }
+
+    /// Saves the state of the globals and everything reachable from them in a snapshot, see
+    /// `save_snapshot`.
+    pub fn snapshot(&mut self) -> Result<Vec<u8>, StaticError> {
+        self.mutate(|_, root| {
+            let mut snapshot = Vec::new();
+            save_snapshot(root, &mut snapshot).map_err(Error::to_static)?;
+            Ok(snapshot)
+        })
+    }
+
+    /// Restores a snapshot taken with `Lua::snapshot`, replacing the globals, see
+    /// `restore_snapshot`.
+    pub fn restore(&mut self, snapshot: &[u8]) -> Result<(), StaticError> {
+        self.mutate(|mc, root| restore_snapshot(mc, root, snapshot).map_err(Error::to_static))
+    }
+
+    /// Makes the globals table read-only, so that scripts can no longer assign or remove globals or
+    /// change its metatable.  This is meant to be called once the host has finished setting up the
+    /// API that scripts may use, and cannot be undone.
+    pub fn freeze_globals(&mut self) {
+        self.mutate(|mc, root| root.globals.freeze(mc))
+    }
+
+    /// Adds a searcher to the end of `package.searchers`, which `require` tries after looking in
+    /// `package.preload` and on `package.path`.
+    pub fn add_searcher<S: Searcher + 'static>(&mut self, searcher: S) {
+        self.mutate(move |mc, root| {
+            if let Value::Table(searchers) = root.package.get(String::new_static(b"searchers")) {
+                let searcher = searcher_callback(mc, root, Box::new(searcher));
+                searchers.set(mc, searchers.length() + 1, searcher).unwrap();
+            }
+        })
+    }
+
+    /// Compiles and runs a chunk of Lua source on the main thread, discarding its results.
+    pub fn exec(&mut self, source: &str) -> Result<(), StaticError> {
+        self.eval::<()>(source)
+    }
+
+    /// Compiles and runs a chunk of Lua source on the main thread, converting the values it returns
+    /// with `FromMultiValue`.  As with `luaL_loadstring`, the source is also used as the chunk name.
+    pub fn eval<T>(&mut self, source: &str) -> Result<T, StaticError>
+    where
+        T: 'static + for<'gc> FromMultiValue<'gc>,
+    {
+        let source = source.to_owned();
+        self.sequence(move |root| {
+            sequence::from_fn_with(root, move |mc, root| {
+                Ok(Closure::new(
+                    mc,
+                    compile_with_coverage(
+                        mc,
+                        root.interned_strings,
+                        &source,
+                        source.as_bytes(),
+                        root.coverage.0.borrow().as_ref(),
+                    )?,
+                    Some(root.globals),
+                )?)
+            })
+            .and_chain_with(root, |mc, root, closure| {
+                Ok(ThreadSequence::call_function(
+                    mc,
+                    root.main_thread,
+                    Function::Closure(closure),
+                    &[],
+                )?)
+            })
+            .and_then(|mc, res| T::from_multi_value(mc, res).map_err(|err| *err.error))
+            .map_err(Error::to_static)
+            .boxed()
+        })
+    }
+
+    /// Calls a function stashed in the registry with the given arguments, converting them with
+    /// `ToMultiValue` and its results with `FromMultiValue`.  The function is called on a new
+    /// thread, see `Function::call`.
+    pub fn call<A, R>(&mut self, function: &RegistryKey, args: A) -> Result<R, StaticError>
+    where
+        A: 'static + for<'gc> ToMultiValue<'gc>,
+        R: 'static + for<'gc> FromMultiValue<'gc>,
+    {
+        self.sequence(move |root| {
+            let function = root.registry.fetch(function);
+            sequence::from_fn_with(
+                (root, function),
+                move |mc, (root, function)| match function {
+                    Value::Function(function) => function.call_typed(mc, root, args),
+                    value => Err(TypeError {
+                        expected: "function",
+                        found: value.type_name(),
+                    }
+                    .into()),
+                },
+            )
+            .flatten_ok()
+            .map_err(Error::to_static)
+            .boxed()
+        })
+    }
+
+    /// Starts the function stashed in the registry on a new coroutine with the given arguments, and
+    /// returns an iterator that resumes the coroutine each time an item is requested.  Each item is
+    /// the values the coroutine yielded, converted with `FromMultiValue`.
+    ///
+    /// The iterator ends once the coroutine returns, and its return values are discarded.  If the
+    /// coroutine raises an error, or its yielded values cannot be converted, the error is the last
+    /// item.
+    pub fn generator<A, T>(
+        &mut self,
+        function: &RegistryKey,
+        args: A,
+    ) -> Result<Generator<'_, T>, StaticError>
+    where
+        A: 'static + for<'gc> ToMultiValue<'gc>,
+        T: 'static + for<'gc> FromMultiValue<'gc>,
+    {
+        let thread = self.mutate(move |mc, root| {
+            let function = match root.registry.fetch(function) {
+                Value::Function(function) => function,
+                value => {
+                    return Err(Error::from(TypeError {
+                        expected: "function",
+                        found: value.type_name(),
+                    })
+                    .to_static());
+                }
+            };
+            let args = args.to_multi_value(mc).map_err(Error::to_static)?;
+            let thread = Thread::new(mc, root.string_metatable, true);
+            thread.inherit_from(mc, root.main_thread);
+            thread.start(mc, function, &args).unwrap();
+            Ok(root.registry.stash(mc, Value::Thread(thread)))
+        })?;
+        Ok(Generator {
+            lua: self,
+            thread: Some(thread),
+            _marker: PhantomData,
+        })
+    }
+
+    /// Calls `f` with a `Scope` for creating callbacks that borrow data living only as long as
+    /// `'scope`, rather than data that must be `'static`.  The callbacks are invalidated when `f`
+    /// returns, so calling one afterwards raises an error.
+    pub fn scope<'scope, F, R>(&mut self, f: F) -> R
+    where
+        F: FnOnce(&mut Lua, &Scope<'scope>) -> R,
+    {
+        let scope = Scope::new();
+        f(self, &scope)
     }
 
     /// Runs a single action inside the Lua arena, during which no garbage collection may take place.
@@ -54,35 +593,204 @@ impl Lua {
         R: 'static,
         F: for<'gc> FnOnce(MutationContext<'gc, '_>, Root<'gc>) -> R,
     {
-        let arena = self.0.as_mut().unwrap();
-        let r = arena.mutate(move |mc, root| f(mc, *root));
-        if arena.allocation_debt() > COLLECTOR_GRANULARITY {
-            arena.collect_debt();
+        let r = self
+            .arena
+            .as_mut()
+            .unwrap()
+            .mutate(move |mc, root| f(mc, *root));
+        let arena = self.arena.as_ref().unwrap();
+        if self.should_collect_debt(arena.total_allocated(), arena.allocation_debt()) {
+            self.arena.as_mut().unwrap().collect_debt();
         }
+        let arena = self.arena.as_ref().unwrap();
+        self.collector.total_allocated.set(arena.total_allocated());
         r
     }
 
     /// Runs a sequence of actions inside the Lua arena and return the result.  Garbage collection
     /// may take place in-between sequence steps.
+    ///
+    /// If an async callback is waiting on a host future, this blocks the current thread until the
+    /// future completes.  Use `Lua::resume_async` to wait on it from an async executor instead.
     pub fn sequence<F, R>(&mut self, f: F) -> R
     where
         R: 'static,
         F: for<'gc> FnOnce(Root<'gc>) -> Box<dyn Sequence<'gc, Output = R> + 'gc>,
     {
-        let mut sequencer = self.0.take().unwrap().sequence(move |root| f(*root));
+        let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+        let mut context = Context::from_waker(&waker);
+        let mut sequencer = Box::new(self.arena.take().unwrap().sequence(move |root| f(*root)));
         loop {
-            match sequencer.step() {
-                Ok((arena, output)) => {
-                    self.0 = Some(arena);
-                    return output;
-                }
-                Err(s) => {
-                    sequencer = s;
-                    if sequencer.allocation_debt() > COLLECTOR_GRANULARITY {
-                        sequencer.collect_debt();
-                    }
+            while self.poll_pending_future(&mut context).is_pending() {
+                thread::park();
+            }
+            match self.step(sequencer) {
+                Ok(output) => return output,
+                Err(s) => sequencer = s,
+            }
+        }
+    }
+
+    /// Like `Lua::sequence`, but returns a future that resumes the sequence each time it is
+    /// polled.  The future is pending while an async callback is waiting on a host future that is
+    /// not ready, so scripts calling async callbacks can be run by any executor.
+    ///
+    /// If the returned future is dropped before it completes, the sequence is aborted and the main
+    /// thread is reset with `Thread::reset`.
+    pub fn resume_async<F, R>(&mut self, f: F) -> ResumeAsync<'_, R>
+    where
+        R: 'static,
+        F: for<'gc> FnOnce(Root<'gc>) -> Box<dyn Sequence<'gc, Output = R> + 'gc>,
+    {
+        let sequencer = Box::new(self.arena.take().unwrap().sequence(move |root| f(*root)));
+        ResumeAsync {
+            lua: self,
+            sequencer: Some(sequencer),
+        }
+    }
+
+    // Runs a single step of the sequencer, returning the arena to `self` if the sequence is
+    // finished, and otherwise collecting garbage if it is needed.  The sequencer is boxed, as it
+    // holds the whole arena and is too large to return by value.
+    fn step<R: 'static>(&mut self, sequencer: Box<Sequencer<R>>) -> Result<R, Box<Sequencer<R>>> {
+        match (*sequencer).step() {
+            Ok((arena, output)) => {
+                self.collector.total_allocated.set(arena.total_allocated());
+                self.arena = Some(arena);
+                Ok(output)
+            }
+            Err(mut sequencer) => {
+                if self.collector.collect_requested.replace(false) {
+                    sequencer.collect_all();
+                    sequencer.collect_all();
+                } else if self
+                    .should_collect_debt(sequencer.total_allocated(), sequencer.allocation_debt())
+                {
+                    sequencer.collect_debt();
                 }
+                self.collector
+                    .total_allocated
+                    .set(sequencer.total_allocated());
+                Err(Box::new(sequencer))
+            }
+        }
+    }
+
+    // Whether automatic collection should pay the debt owed now, asking the policy if there is one.
+    fn should_collect_debt(&mut self, total_allocated: usize, allocation_debt: f64) -> bool {
+        if self.collector.stopped.get() || allocation_debt <= 0.0 {
+            return false;
+        }
+        match &mut self.gc_policy {
+            Some(policy) => policy.should_collect(total_allocated, allocation_debt),
+            None => allocation_debt > self.gc_step_size as f64,
+        }
+    }
+
+    // Polls the future an async callback is waiting on, if there is one, and is ready once there is
+    // no such future.
+    fn poll_pending_future(&mut self, context: &mut Context) -> Poll<()> {
+        let future = self.pending_future.0.borrow_mut().take();
+        if let Some(mut future) = future {
+            if future.as_mut().poll(context).is_pending() {
+                *self.pending_future.0.borrow_mut() = Some(future);
+                return Poll::Pending;
+            }
+        }
+        Poll::Ready(())
+    }
+}
+
+/// The future returned by `Lua::resume_async`.
+pub struct ResumeAsync<'a, R: 'static> {
+    lua: &'a mut Lua,
+    sequencer: Option<Box<Sequencer<R>>>,
+}
+
+// The sequencer is never pinned, it is only moved in and out of the option
+impl<'a, R: 'static> Unpin for ResumeAsync<'a, R> {}
+
+impl<'a, R: 'static> Future for ResumeAsync<'a, R> {
+    type Output = R;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<R> {
+        let this = self.get_mut();
+        loop {
+            if this.lua.poll_pending_future(context).is_pending() {
+                return Poll::Pending;
+            }
+            let sequencer = this
+                .sequencer
+                .take()
+                .expect("`ResumeAsync` polled after completion");
+            match this.lua.step(sequencer) {
+                Ok(output) => return Poll::Ready(output),
+                Err(sequencer) => this.sequencer = Some(sequencer),
             }
         }
     }
 }
+
+impl<'a, R: 'static> Drop for ResumeAsync<'a, R> {
+    fn drop(&mut self) {
+        if let Some(sequencer) = self.sequencer.take() {
+            self.lua.pending_future.0.borrow_mut().take();
+            self.lua.arena = Some(sequencer.abort());
+            self.lua.mutate(|mc, root| root.main_thread.reset(mc));
+        }
+    }
+}
+
+/// The iterator returned by `Lua::generator`.
+pub struct Generator<'a, T> {
+    lua: &'a mut Lua,
+    // The coroutine, until it has returned or raised an error
+    thread: Option<RegistryKey>,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<'a, T> Iterator for Generator<'a, T>
+where
+    T: 'static + for<'gc> FromMultiValue<'gc>,
+{
+    type Item = Result<T, StaticError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let thread = self.thread.as_ref()?;
+        let item = self.lua.sequence(move |root| {
+            let thread = match root.registry.fetch(thread) {
+                Value::Thread(thread) => thread,
+                _ => unreachable!("generator coroutine is not a thread"),
+            };
+            sequence::from_fn_with(thread, |mc, thread| {
+                // The coroutine is running rather than suspended before its first yield
+                if thread.mode() == ThreadMode::Suspended {
+                    thread.resume(mc, &[]).unwrap();
+                }
+                ResumeSequence(thread)
+            })
+            .flatten()
+            .then(|mc, res| match res {
+                ResumeResult::Yielded(values) => {
+                    Some(T::from_multi_value(mc, values).map_err(|err| err.error.to_static()))
+                }
+                ResumeResult::Returned(_) => None,
+                ResumeResult::Errored(error) => Some(Err(error.to_static())),
+            })
+            .boxed()
+        });
+        if let None | Some(Err(_)) = item {
+            self.thread = None;
+        }
+        item
+    }
+}
+
+// Wakes a thread blocked in `Lua::sequence` waiting for a host future.
+struct ThreadWaker(StdThread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
@@ -0,0 +1,777 @@
+//! Loading of precompiled chunks produced by PUC-Rio Lua 5.3's `luac`.
+//!
+//! The instructions of each function are translated to luster opcodes.  Every PUC-Rio register is
+//! moved up by one, so that register 0 is always free to use as a temporary by the translation.
+
+use std::io::{self, Read};
+
+use gc_arena::{Gc, MutationContext};
+
+use crate::{
+    dump::verify_proto, Constant, ConstantIndex16, ConstantIndex8, Error, FunctionProto,
+    InternedStringSet, LocalVariable, OpCode, Opt254, PrototypeIndex, RegisterIndex, SizeHint,
+    String, UndumpError, UpValueDescriptor, UpValueIndex, VarCount,
+};
+
+/// The version byte following the signature of PUC-Rio Lua 5.3 precompiled chunks.
+pub const LUAC_VERSION: u8 = 0x53;
+
+const LUAC_FORMAT: u8 = 0;
+const LUAC_DATA: &[u8] = b"\x19\x93\r\n\x1a\n";
+const LUAC_INT: i64 = 0x5678;
+const LUAC_NUM: f64 = 370.5;
+
+// The number of list items set by each SETLIST instruction
+const FIELDS_PER_FLUSH: i64 = 50;
+
+// Register used as a temporary by translated instructions
+const TEMPORARY: RegisterIndex = RegisterIndex(0);
+
+/// Reads the rest of a PUC-Rio Lua 5.3 precompiled chunk, after the signature and version byte.
+/// Chunks of either endianness and with either 32 or 64 bit `size_t` are accepted, but they must
+/// use 32 bit `int`s and 64 bit integers and floats.
+pub fn undump_luac53<'gc, R: Read>(
+    mc: MutationContext<'gc, '_>,
+    interned_strings: InternedStringSet<'gc>,
+    r: R,
+) -> Result<FunctionProto<'gc>, Error<'gc>> {
+    let mut reader = ChunkReader {
+        r,
+        big_endian: false,
+        size_t: 8,
+    };
+
+    if reader.byte()? != LUAC_FORMAT || reader.bytes(LUAC_DATA.len())? != LUAC_DATA {
+        return Err(UndumpError::FormatMismatch.into());
+    }
+
+    let mut sizes = [0; 5];
+    reader.r.read_exact(&mut sizes)?;
+    let [int_size, size_t_size, instruction_size, integer_size, number_size] = sizes;
+    if int_size != 4
+        || (size_t_size != 4 && size_t_size != 8)
+        || instruction_size != 4
+        || integer_size != 8
+        || number_size != 8
+    {
+        return Err(UndumpError::FormatMismatch.into());
+    }
+    reader.size_t = size_t_size as usize;
+
+    let mut check_integer = [0; 8];
+    reader.r.read_exact(&mut check_integer)?;
+    if i64::from_le_bytes(check_integer) == LUAC_INT {
+        reader.big_endian = false;
+    } else if i64::from_be_bytes(check_integer) == LUAC_INT {
+        reader.big_endian = true;
+    } else {
+        return Err(UndumpError::FormatMismatch.into());
+    }
+    if reader.number()? != LUAC_NUM {
+        return Err(UndumpError::FormatMismatch.into());
+    }
+
+    // The number of upvalues of the main function, which is repeated in the function itself
+    reader.byte()?;
+
+    load_function(mc, interned_strings, &mut reader, None)
+}
+
+struct ChunkReader<R> {
+    r: R,
+    big_endian: bool,
+    size_t: usize,
+}
+
+impl<R: Read> ChunkReader<R> {
+    fn byte(&mut self) -> Result<u8, io::Error> {
+        let mut buf = [0; 1];
+        self.r.read_exact(&mut buf)?;
+        Ok(buf[0])
+    }
+
+    // The length may come from the chunk, so it is not trusted for an allocation up front
+    fn bytes(&mut self, len: usize) -> Result<Vec<u8>, io::Error> {
+        let mut buf = Vec::new();
+        self.r.by_ref().take(len as u64).read_to_end(&mut buf)?;
+        if buf.len() != len {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(buf)
+    }
+
+    fn unsigned(&mut self, size: usize) -> Result<u64, io::Error> {
+        let mut buf = [0; 8];
+        if self.big_endian {
+            self.r.read_exact(&mut buf[8 - size..])?;
+            Ok(u64::from_be_bytes(buf))
+        } else {
+            self.r.read_exact(&mut buf[..size])?;
+            Ok(u64::from_le_bytes(buf))
+        }
+    }
+
+    fn int(&mut self) -> Result<i32, io::Error> {
+        Ok(self.unsigned(4)? as u32 as i32)
+    }
+
+    // Reads an `int` count of following items
+    fn count<'gc>(&mut self) -> Result<usize, Error<'gc>> {
+        let count = self.int()?;
+        if count < 0 {
+            Err(UndumpError::FormatMismatch.into())
+        } else {
+            Ok(count as usize)
+        }
+    }
+
+    fn integer(&mut self) -> Result<i64, io::Error> {
+        Ok(self.unsigned(8)? as i64)
+    }
+
+    fn number(&mut self) -> Result<f64, io::Error> {
+        Ok(f64::from_bits(self.unsigned(8)?))
+    }
+
+    // Strings are stored with their size plus one, so that a size of zero can mean no string
+    fn string(&mut self) -> Result<Option<Vec<u8>>, io::Error> {
+        let size = match self.byte()? {
+            0xff => self.unsigned(self.size_t)? as usize,
+            size => size as usize,
+        };
+        if size == 0 {
+            Ok(None)
+        } else {
+            Ok(Some(self.bytes(size - 1)?))
+        }
+    }
+}
+
+// Inner functions only have a source if it differs from the source of their parent.
+fn load_function<'gc, R: Read>(
+    mc: MutationContext<'gc, '_>,
+    interned_strings: InternedStringSet<'gc>,
+    reader: &mut ChunkReader<R>,
+    parent_source: Option<String<'gc>>,
+) -> Result<FunctionProto<'gc>, Error<'gc>> {
+    let source = match reader.string()? {
+        Some(source) => Some(interned_strings.new_string(mc, &source)),
+        None => parent_source,
+    };
+    let line_defined = reader.int()?;
+    let last_line_defined = reader.int()?;
+    let fixed_params = reader.byte()?;
+    let has_varargs = reader.byte()? != 0;
+    let max_stack_size = reader.byte()?;
+
+    let mut code = Vec::new();
+    for _ in 0..reader.count()? {
+        code.push(reader.unsigned(4)? as u32);
+    }
+
+    let mut constants = Vec::new();
+    for _ in 0..reader.count()? {
+        constants.push(match reader.byte()? {
+            0 => Constant::Nil,
+            1 => Constant::Boolean(reader.byte()? != 0),
+            3 => Constant::Number(reader.number()?),
+            19 => Constant::Integer(reader.integer()?),
+            4 | 20 => match reader.string()? {
+                Some(s) => Constant::String(interned_strings.new_string(mc, &s)),
+                None => return Err(UndumpError::BadConstant.into()),
+            },
+            _ => return Err(UndumpError::BadConstant.into()),
+        });
+    }
+
+    let mut upvalues = Vec::new();
+    for _ in 0..reader.count()? {
+        let in_stack = reader.byte()? != 0;
+        let index = reader.byte()?;
+        upvalues.push(if in_stack {
+            UpValueDescriptor::ParentLocal(register(index as u32)?)
+        } else {
+            UpValueDescriptor::Outer(UpValueIndex(index))
+        });
+    }
+
+    let mut prototypes = Vec::new();
+    for _ in 0..reader.count()? {
+        prototypes.push(Gc::allocate(
+            mc,
+            load_function(mc, interned_strings, reader, source)?,
+        ));
+    }
+    if prototypes.len() > 256 {
+        return Err(UndumpError::LimitExceeded.into());
+    }
+
+    let mut lines = Vec::new();
+    for _ in 0..reader.count()? {
+        lines.push(reader.int()?);
+    }
+    let mut locals = Vec::new();
+    for _ in 0..reader.count()? {
+        let name = reader.string()?.unwrap_or_default();
+        let start_pc = reader.int()?;
+        let end_pc = reader.int()?;
+        locals.push((name, start_pc, end_pc));
+    }
+    let mut upvalue_names = Vec::new();
+    for _ in 0..reader.count()? {
+        let name = reader.string()?.unwrap_or_default();
+        upvalue_names.push(interned_strings.new_string(mc, &name));
+    }
+
+    let mut translator = Translator {
+        constant_count: constants.len(),
+        upvalue_count: upvalues.len(),
+        prototype_count: prototypes.len(),
+        constants,
+        opcodes: Vec::new(),
+        jumps: Vec::new(),
+    };
+
+    // Move the parameters up to their translated registers, highest first so that none are
+    // overwritten before they are moved.
+    for i in (0..fixed_params).rev() {
+        translator.opcodes.push(OpCode::Move {
+            dest: register(i as u32)?,
+            source: RegisterIndex(i),
+        });
+    }
+
+    let (opcodes, starts) = translator.translate(&code)?;
+
+    // Locals are not given registers in the chunk, each one is in the register after those of the
+    // locals already in scope when it is declared.
+    let mut local_variables = Vec::new();
+    for (i, (name, start_pc, end_pc)) in locals.iter().enumerate() {
+        let active = locals[..i]
+            .iter()
+            .filter(|(_, start, end)| start <= start_pc && start_pc < end)
+            .count();
+        let pc = |pc: i32| starts[(pc.max(0) as usize).min(code.len())];
+        local_variables.push(LocalVariable {
+            name: interned_strings.new_string(mc, name),
+            register: register(active as u32)?,
+            start_pc: pc(*start_pc),
+            end_pc: pc(*end_pc),
+        });
+    }
+
+    // Every opcode translated from an instruction has its line, and the parameter moves have the
+    // line of the first instruction.  Stripped chunks have no lines.
+    let mut line_info = Vec::new();
+    if lines.len() == code.len() && !lines.is_empty() {
+        line_info.resize(starts[0], lines[0] as u64);
+        for (pc, &line) in lines.iter().enumerate() {
+            line_info.resize(starts[pc + 1], line as u64);
+        }
+    }
+
+    let proto = FunctionProto {
+        fixed_params,
+        has_varargs,
+        stack_size: max_stack_size as u16 + 1,
+        constants: translator.constants,
+        opcodes,
+        upvalues,
+        upvalue_names,
+        local_variables,
+        prototypes,
+        chunk_name: source,
+        line_info,
+        line_defined: line_defined.max(0) as u64,
+        last_line_defined: last_line_defined.max(0) as u64,
+        coverage: None,
+    };
+    // Registers and jump targets are only checked once the whole function is translated
+    verify_proto(&proto)?;
+    Ok(proto)
+}
+
+// A PUC-Rio "RK" operand, which is either a register or a constant
+enum RegisterOrConstant {
+    Register(RegisterIndex),
+    Constant(ConstantIndex8),
+}
+
+// Picks the opcode variant matching whether each operand is a register or a constant
+macro_rules! rk_opcode {
+    (
+        $rr:ident, $rc:ident, $cr:ident, $cc:ident,
+        $left:ident: $left_rk:expr, $right:ident: $right_rk:expr
+        $(, $field:ident: $value:expr)*
+    ) => {
+        match ($left_rk, $right_rk) {
+            (RegisterOrConstant::Register(l), RegisterOrConstant::Register(r)) => {
+                OpCode::$rr { $left: l, $right: r $(, $field: $value)* }
+            }
+            (RegisterOrConstant::Register(l), RegisterOrConstant::Constant(r)) => {
+                OpCode::$rc { $left: l, $right: r $(, $field: $value)* }
+            }
+            (RegisterOrConstant::Constant(l), RegisterOrConstant::Register(r)) => {
+                OpCode::$cr { $left: l, $right: r $(, $field: $value)* }
+            }
+            (RegisterOrConstant::Constant(l), RegisterOrConstant::Constant(r)) => {
+                OpCode::$cc { $left: l, $right: r $(, $field: $value)* }
+            }
+        }
+    };
+}
+
+struct Translator<'gc> {
+    // The number of constants, upvalues and inner prototypes loaded from the chunk, which operands
+    // must be less than
+    constant_count: usize,
+    upvalue_count: usize,
+    prototype_count: usize,
+    constants: Vec<Constant<'gc>>,
+    opcodes: Vec<OpCode>,
+    // Jumps to patch once all instructions are translated, as the index of the jumping opcode and
+    // the index of the PUC-Rio instruction it targets.
+    jumps: Vec<(usize, usize)>,
+}
+
+impl<'gc> Translator<'gc> {
+    // Returns the translated opcodes along with the index of the first opcode translated from each
+    // instruction, followed by the total number of opcodes.
+    fn translate(&mut self, code: &[u32]) -> Result<(Vec<OpCode>, Vec<usize>), Error<'gc>> {
+        // The index of the first translated opcode of each instruction
+        let mut starts = vec![0; code.len() + 1];
+
+        let mut pc = 0;
+        while pc < code.len() {
+            starts[pc] = self.opcodes.len();
+
+            let i = code[pc];
+            let a = (i >> 6) & 0xff;
+            let c = (i >> 14) & 0x1ff;
+            let b = (i >> 23) & 0x1ff;
+            let bx = i >> 14;
+            let target = pc as i64 + 1 + bx as i64 - 0x1ffff;
+
+            // Instructions with an `EXTRAARG` instruction following them
+            let extra_arg = |pc: usize| -> Result<u32, Error<'gc>> {
+                match code.get(pc + 1) {
+                    Some(&extra) if extra & 0x3f == 46 => Ok(extra >> 6),
+                    _ => Err(UndumpError::BadOpCode.into()),
+                }
+            };
+
+            let op = match i & 0x3f {
+                // MOVE
+                0 => OpCode::Move {
+                    dest: register(a)?,
+                    source: register(b)?,
+                },
+                // LOADK
+                1 => OpCode::LoadConstant {
+                    dest: register(a)?,
+                    constant: self.constant16(bx)?,
+                },
+                // LOADKX
+                2 => {
+                    let constant = self.constant16(extra_arg(pc)?)?;
+                    pc += 1;
+                    starts[pc] = self.opcodes.len();
+                    OpCode::LoadConstant {
+                        dest: register(a)?,
+                        constant,
+                    }
+                }
+                // LOADBOOL
+                3 => OpCode::LoadBool {
+                    dest: register(a)?,
+                    value: b != 0,
+                    skip_next: c != 0,
+                },
+                // LOADNIL
+                4 => OpCode::LoadNil {
+                    dest: register(a)?,
+                    count: byte(b + 1)?,
+                },
+                // GETUPVAL
+                5 => OpCode::GetUpValue {
+                    dest: register(a)?,
+                    source: self.upvalue(b)?,
+                },
+                // GETTABUP
+                6 => match self.register_or_constant(c)? {
+                    RegisterOrConstant::Register(key) => OpCode::GetUpTableR {
+                        dest: register(a)?,
+                        table: self.upvalue(b)?,
+                        key,
+                    },
+                    RegisterOrConstant::Constant(key) => OpCode::GetUpTableC {
+                        dest: register(a)?,
+                        table: self.upvalue(b)?,
+                        key,
+                    },
+                },
+                // GETTABLE
+                7 => match self.register_or_constant(c)? {
+                    RegisterOrConstant::Register(key) => OpCode::GetTableR {
+                        dest: register(a)?,
+                        table: register(b)?,
+                        key,
+                    },
+                    RegisterOrConstant::Constant(key) => OpCode::GetTableC {
+                        dest: register(a)?,
+                        table: register(b)?,
+                        key,
+                    },
+                },
+                // SETTABUP
+                8 => rk_opcode!(
+                    SetUpTableRR, SetUpTableRC, SetUpTableCR, SetUpTableCC,
+                    key: self.register_or_constant(b)?, value: self.register_or_constant(c)?,
+                    table: self.upvalue(a)?
+                ),
+                // SETUPVAL
+                9 => OpCode::SetUpValue {
+                    dest: self.upvalue(b)?,
+                    source: register(a)?,
+                },
+                // SETTABLE
+                10 => rk_opcode!(
+                    SetTableRR, SetTableRC, SetTableCR, SetTableCC,
+                    key: self.register_or_constant(b)?, value: self.register_or_constant(c)?,
+                    table: register(a)?
+                ),
+                // NEWTABLE
+                11 => OpCode::NewTable {
+                    dest: register(a)?,
+                    array_size: SizeHint(byte(b)?),
+                    map_size: SizeHint(byte(c)?),
+                },
+                // SELF
+                12 => match self.register_or_constant(c)? {
+                    RegisterOrConstant::Register(key) => OpCode::SelfR {
+                        base: register(a)?,
+                        table: register(b)?,
+                        key,
+                    },
+                    RegisterOrConstant::Constant(key) => OpCode::SelfC {
+                        base: register(a)?,
+                        table: register(b)?,
+                        key,
+                    },
+                },
+                // ADD, SUB, MUL, MOD, POW, DIV, IDIV, BAND, BOR, BXOR, SHL, SHR
+                13..=24 => {
+                    let dest = register(a)?;
+                    let left = self.register_or_constant(b)?;
+                    let right = self.register_or_constant(c)?;
+                    match i & 0x3f {
+                        13 => rk_opcode!(AddRR, AddRC, AddCR, AddCC,
+                            left: left, right: right, dest: dest),
+                        14 => rk_opcode!(SubRR, SubRC, SubCR, SubCC,
+                            left: left, right: right, dest: dest),
+                        15 => rk_opcode!(MulRR, MulRC, MulCR, MulCC,
+                            left: left, right: right, dest: dest),
+                        16 => rk_opcode!(ModRR, ModRC, ModCR, ModCC,
+                            left: left, right: right, dest: dest),
+                        17 => rk_opcode!(PowRR, PowRC, PowCR, PowCC,
+                            left: left, right: right, dest: dest),
+                        18 => rk_opcode!(DivRR, DivRC, DivCR, DivCC,
+                            left: left, right: right, dest: dest),
+                        19 => rk_opcode!(IDivRR, IDivRC, IDivCR, IDivCC,
+                            left: left, right: right, dest: dest),
+                        20 => rk_opcode!(BitAndRR, BitAndRC, BitAndCR, BitAndCC,
+                            left: left, right: right, dest: dest),
+                        21 => rk_opcode!(BitOrRR, BitOrRC, BitOrCR, BitOrCC,
+                            left: left, right: right, dest: dest),
+                        22 => rk_opcode!(BitXorRR, BitXorRC, BitXorCR, BitXorCC,
+                            left: left, right: right, dest: dest),
+                        23 => rk_opcode!(ShiftLeftRR, ShiftLeftRC, ShiftLeftCR, ShiftLeftCC,
+                            left: left, right: right, dest: dest),
+                        _ => rk_opcode!(ShiftRightRR, ShiftRightRC, ShiftRightCR, ShiftRightCC,
+                            left: left, right: right, dest: dest),
+                    }
+                }
+                // UNM
+                25 => OpCode::Minus {
+                    dest: register(a)?,
+                    source: register(b)?,
+                },
+                // BNOT
+                26 => OpCode::BitNot {
+                    dest: register(a)?,
+                    source: register(b)?,
+                },
+                // NOT
+                27 => OpCode::Not {
+                    dest: register(a)?,
+                    source: register(b)?,
+                },
+                // LEN
+                28 => OpCode::Length {
+                    dest: register(a)?,
+                    source: register(b)?,
+                },
+                // CONCAT
+                29 => OpCode::Concat {
+                    dest: register(a)?,
+                    source: register(b)?,
+                    count: byte((c + 1).saturating_sub(b))?,
+                },
+                // JMP
+                30 => {
+                    self.jump_to(target, code.len())?;
+                    OpCode::Jump {
+                        offset: 0,
+                        close_upvalues: if a == 0 {
+                            Opt254::none()
+                        } else {
+                            // Upvalues are closed from register A - 1, which is translated to A
+                            Opt254::try_some(byte(a)?).ok_or(UndumpError::LimitExceeded)?
+                        },
+                    }
+                }
+                // EQ, LT, LE
+                31..=33 => {
+                    let skip_if = a == 0;
+                    let left = self.register_or_constant(b)?;
+                    let right = self.register_or_constant(c)?;
+                    match i & 0x3f {
+                        31 => rk_opcode!(EqRR, EqRC, EqCR, EqCC,
+                            left: left, right: right, skip_if: skip_if),
+                        32 => rk_opcode!(LessRR, LessRC, LessCR, LessCC,
+                            left: left, right: right, skip_if: skip_if),
+                        _ => rk_opcode!(LessEqRR, LessEqRC, LessEqCR, LessEqCC,
+                            left: left, right: right, skip_if: skip_if),
+                    }
+                }
+                // TEST
+                34 => OpCode::Test {
+                    value: register(a)?,
+                    is_true: c == 0,
+                },
+                // TESTSET
+                35 => OpCode::TestSet {
+                    dest: register(a)?,
+                    value: register(b)?,
+                    is_true: c == 0,
+                },
+                // CALL
+                36 => OpCode::Call {
+                    func: register(a)?,
+                    args: var_count(b)?,
+                    returns: var_count(c)?,
+                },
+                // TAILCALL
+                37 => OpCode::TailCall {
+                    func: register(a)?,
+                    args: var_count(b)?,
+                },
+                // RETURN
+                38 => OpCode::Return {
+                    start: register(a)?,
+                    count: var_count(b)?,
+                },
+                // FORLOOP
+                39 => {
+                    self.jump_to(target, code.len())?;
+                    OpCode::NumericForLoop {
+                        base: register(a)?,
+                        jump: 0,
+                    }
+                }
+                // FORPREP
+                40 => {
+                    self.jump_to(target, code.len())?;
+                    OpCode::NumericForPrep {
+                        base: register(a)?,
+                        jump: 0,
+                    }
+                }
+                // TFORCALL
+                41 => OpCode::GenericForCall {
+                    base: register(a)?,
+                    var_count: byte(c)?,
+                },
+                // TFORLOOP
+                42 => {
+                    self.jump_to(target, code.len())?;
+                    OpCode::GenericForLoop {
+                        base: register(a)?,
+                        jump: 0,
+                    }
+                }
+                // SETLIST
+                43 => {
+                    let previous_start = pc.checked_sub(1).map(|p| starts[p]);
+                    let batch = if c == 0 {
+                        let batch = extra_arg(pc)?;
+                        pc += 1;
+                        starts[pc] = self.opcodes.len();
+                        batch
+                    } else {
+                        c
+                    };
+                    let start = (batch as i64 - 1) * FIELDS_PER_FLUSH + 1;
+                    self.set_list(register(a)?, b, start, previous_start)?;
+                    pc += 1;
+                    continue;
+                }
+                // CLOSURE
+                44 => OpCode::Closure {
+                    dest: register(a)?,
+                    proto: self.prototype(bx)?,
+                },
+                // VARARG
+                45 => OpCode::VarArgs {
+                    dest: register(a)?,
+                    count: var_count(b)?,
+                },
+                _ => return Err(UndumpError::BadOpCode.into()),
+            };
+            self.opcodes.push(op);
+            pc += 1;
+        }
+        starts[code.len()] = self.opcodes.len();
+
+        let mut opcodes = self.opcodes.split_off(0);
+        for &(index, target) in &self.jumps {
+            let offset = starts[target] as i64 - (index as i64 + 1);
+            if offset < i16::MIN as i64 || offset > i16::MAX as i64 {
+                return Err(UndumpError::LimitExceeded.into());
+            }
+            match &mut opcodes[index] {
+                OpCode::Jump { offset: jump, .. }
+                | OpCode::NumericForLoop { jump, .. }
+                | OpCode::NumericForPrep { jump, .. }
+                | OpCode::GenericForLoop { jump, .. } => *jump = offset as i16,
+                _ => unreachable!(),
+            }
+        }
+        Ok((opcodes, starts))
+    }
+
+    fn register_or_constant(&self, rk: u32) -> Result<RegisterOrConstant, Error<'gc>> {
+        if rk & 0x100 != 0 {
+            let index = rk & 0xff;
+            if index as usize >= self.constant_count {
+                return Err(UndumpError::BadOpCode.into());
+            }
+            Ok(RegisterOrConstant::Constant(ConstantIndex8(index as u8)))
+        } else {
+            Ok(RegisterOrConstant::Register(register(rk)?))
+        }
+    }
+
+    fn constant16(&self, index: u32) -> Result<ConstantIndex16, Error<'gc>> {
+        if index as usize >= self.constant_count {
+            Err(UndumpError::BadOpCode.into())
+        } else if index > u16::MAX as u32 {
+            Err(UndumpError::LimitExceeded.into())
+        } else {
+            Ok(ConstantIndex16(index as u16))
+        }
+    }
+
+    fn upvalue(&self, index: u32) -> Result<UpValueIndex, Error<'gc>> {
+        if index as usize >= self.upvalue_count {
+            Err(UndumpError::BadOpCode.into())
+        } else {
+            Ok(UpValueIndex(byte(index)?))
+        }
+    }
+
+    fn prototype(&self, index: u32) -> Result<PrototypeIndex, Error<'gc>> {
+        if index as usize >= self.prototype_count {
+            Err(UndumpError::BadOpCode.into())
+        } else {
+            Ok(PrototypeIndex(byte(index)?))
+        }
+    }
+
+    // Records that the next opcode jumps to the given instruction
+    fn jump_to(&mut self, target: i64, code_len: usize) -> Result<(), Error<'gc>> {
+        if target < 0 || target > code_len as i64 {
+            return Err(UndumpError::BadOpCode.into());
+        }
+        self.jumps.push((self.opcodes.len(), target as usize));
+        Ok(())
+    }
+
+    // SETLIST sets R(A)[start + i - 1] = R(A + i) for 1 <= i <= count, but a luster `SetList` needs
+    // the start key in the register just before the values.  The table is moved to the temporary
+    // register while the start key takes its place.  If the count comes from a preceding variable
+    // results instruction, the table must be moved before that instruction.
+    fn set_list(
+        &mut self,
+        table: RegisterIndex,
+        count: u32,
+        start: i64,
+        previous_start: Option<usize>,
+    ) -> Result<(), Error<'gc>> {
+        if self.constants.len() > u16::MAX as usize {
+            return Err(UndumpError::LimitExceeded.into());
+        }
+        let constant = ConstantIndex16(self.constants.len() as u16);
+        self.constants.push(Constant::Integer(start));
+
+        let setup = [
+            OpCode::Move {
+                dest: TEMPORARY,
+                source: table,
+            },
+            OpCode::LoadConstant {
+                dest: table,
+                constant,
+            },
+        ];
+
+        let count = if count == 0 {
+            match self.opcodes.last() {
+                Some(OpCode::Call { returns, .. }) if returns.is_variable() => {}
+                Some(OpCode::VarArgs { count, .. }) if count.is_variable() => {}
+                _ => return Err(UndumpError::BadOpCode.into()),
+            }
+            if previous_start != Some(self.opcodes.len() - 1) {
+                return Err(UndumpError::BadOpCode.into());
+            }
+            let index = self.opcodes.len() - 1;
+            self.opcodes.splice(index..index, setup.iter().cloned());
+            VarCount::variable()
+        } else {
+            self.opcodes.extend_from_slice(&setup);
+            VarCount::try_constant(byte(count)?).ok_or(UndumpError::LimitExceeded)?
+        };
+
+        self.opcodes.push(OpCode::SetList {
+            table: TEMPORARY,
+            base: table,
+            count,
+        });
+        self.opcodes.push(OpCode::Move {
+            dest: table,
+            source: TEMPORARY,
+        });
+        Ok(())
+    }
+}
+
+fn register<'gc>(r: u32) -> Result<RegisterIndex, Error<'gc>> {
+    Ok(RegisterIndex(byte(r + 1)?))
+}
+
+// PUC-Rio Lua encodes variable counts as 0 and constant counts as count + 1
+fn var_count<'gc>(count: u32) -> Result<VarCount, Error<'gc>> {
+    if count == 0 {
+        Ok(VarCount::variable())
+    } else {
+        VarCount::try_constant(byte(count - 1)?).ok_or_else(|| UndumpError::LimitExceeded.into())
+    }
+}
+
+fn byte<'gc>(v: u32) -> Result<u8, Error<'gc>> {
+    if v > u8::MAX as u32 {
+        Err(UndumpError::LimitExceeded.into())
+    } else {
+        Ok(v as u8)
+    }
+}
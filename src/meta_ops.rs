@@ -0,0 +1,542 @@
+use std::error::Error as StdError;
+use std::fmt;
+
+use gc_arena::{Collect, MutationContext};
+use gc_sequence as sequence;
+
+use crate::{
+    BinaryOperatorError, Callback, CallbackResult, Continuation, Error, Function,
+    InternedStringSet, RuntimeError, String, StringError, Table, TypeError, Value,
+};
+
+/// The result of an operation that may need to call a metamethod.  Either the operation could be
+/// performed directly and produced a value, or the given function must be called with the given
+/// arguments, and the first value it returns is the result of the operation.
+#[derive(Debug)]
+pub enum MetaResult<'gc> {
+    Value(Value<'gc>),
+    Call(Function<'gc>, Vec<Value<'gc>>),
+}
+
+#[derive(Debug, Clone, Copy, Collect)]
+#[collect(require_static)]
+pub enum MetaOperatorError {
+    IndexLoop,
+    NewIndexLoop,
+    ToStringNotString,
+    NotClosable,
+}
+
+impl StdError for MetaOperatorError {}
+
+impl fmt::Display for MetaOperatorError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MetaOperatorError::IndexLoop => write!(fmt, "'__index' chain too long; possible loop"),
+            MetaOperatorError::NewIndexLoop => {
+                write!(fmt, "'__newindex' chain too long; possible loop")
+            }
+            MetaOperatorError::ToStringNotString => {
+                write!(fmt, "'__tostring' must return a string")
+            }
+            MetaOperatorError::NotClosable => {
+                write!(fmt, "to-be-closed variable has a non-closable value")
+            }
+        }
+    }
+}
+
+// The maximum number of tables that will be followed through `__index` and `__newindex` chains
+// before giving up, so that metatable loops produce an error rather than hanging.
+const MAX_META_CHAIN: usize = 100;
+
+/// Index the given value with the given key, following the `__index` metamethod if the key is not
+/// present.  Strings have no fields of their own and are indexed through the `__index` field of the
+/// given string metatable, and userdata are only indexed through their `__index` metamethod.
+pub fn index<'gc>(
+    string_metatable: Table<'gc>,
+    table: Value<'gc>,
+    key: Value<'gc>,
+) -> Result<MetaResult<'gc>, Error<'gc>> {
+    let mut table = table;
+    for _ in 0..MAX_META_CHAIN {
+        let index = match table {
+            Value::Table(t) => {
+                let v = t.get(key);
+                if v != Value::Nil {
+                    return Ok(MetaResult::Value(v));
+                }
+
+                match t.metatable() {
+                    Some(mt) => mt.get(String::new_static(b"__index")),
+                    None => Value::Nil,
+                }
+            }
+            Value::UserData(_) => match get_metamethod(table, b"__index") {
+                Value::Nil => {
+                    return Err(TypeError {
+                        expected: "table",
+                        found: table.type_name(),
+                    }
+                    .into());
+                }
+                index => index,
+            },
+            Value::String(_) => match string_metatable.get(String::new_static(b"__index")) {
+                Value::Nil => {
+                    return Err(TypeError {
+                        expected: "table",
+                        found: table.type_name(),
+                    }
+                    .into());
+                }
+                index => index,
+            },
+            val => {
+                return Err(TypeError {
+                    expected: "table",
+                    found: val.type_name(),
+                }
+                .into());
+            }
+        };
+
+        match index {
+            Value::Nil => return Ok(MetaResult::Value(Value::Nil)),
+            Value::Function(f) => return Ok(MetaResult::Call(f, vec![table, key])),
+            v => table = v,
+        }
+    }
+
+    Err(MetaOperatorError::IndexLoop.into())
+}
+
+/// Assign the given value to the given key, following the `__newindex` metamethod if the key is not
+/// already present.  If a `__newindex` function must be called to perform the assignment, returns
+/// the function along with the arguments it should be called with.
+pub fn new_index<'gc>(
+    mc: MutationContext<'gc, '_>,
+    table: Value<'gc>,
+    key: Value<'gc>,
+    value: Value<'gc>,
+) -> Result<Option<(Function<'gc>, Vec<Value<'gc>>)>, Error<'gc>> {
+    let mut table = table;
+    for _ in 0..MAX_META_CHAIN {
+        let new_index = match table {
+            Value::Table(t) => {
+                let new_index = if t.get(key) == Value::Nil {
+                    match t.metatable() {
+                        Some(mt) => mt.get(String::new_static(b"__newindex")),
+                        None => Value::Nil,
+                    }
+                } else {
+                    Value::Nil
+                };
+
+                if new_index == Value::Nil {
+                    t.set(mc, key, value)?;
+                    return Ok(None);
+                }
+                new_index
+            }
+            Value::UserData(_) => match get_metamethod(table, b"__newindex") {
+                Value::Nil => {
+                    return Err(TypeError {
+                        expected: "table",
+                        found: table.type_name(),
+                    }
+                    .into());
+                }
+                new_index => new_index,
+            },
+            val => {
+                return Err(TypeError {
+                    expected: "table",
+                    found: val.type_name(),
+                }
+                .into());
+            }
+        };
+
+        match new_index {
+            Value::Function(f) => return Ok(Some((f, vec![table, key, value]))),
+            v => table = v,
+        }
+    }
+
+    Err(MetaOperatorError::NewIndexLoop.into())
+}
+
+/// Returns the function that should be called when calling the given value.  Functions are called
+/// directly, any other value is called through its `__call` metamethod, which must be passed the
+/// called value as an extra first argument.
+pub fn call<'gc>(value: Value<'gc>) -> Result<Function<'gc>, TypeError> {
+    match value {
+        Value::Function(f) => Ok(f),
+        value => match get_metamethod(value, b"__call") {
+            Value::Function(f) => Ok(f),
+            _ => Err(TypeError {
+                expected: "function",
+                found: value.type_name(),
+            }),
+        },
+    }
+}
+
+/// Converts any value to a string, calling the `__tostring` metamethod if present.  Values without a
+/// `__tostring` metamethod are formatted the same way as `Value::display`.
+pub fn tostring<'gc>(mc: MutationContext<'gc, '_>, value: Value<'gc>) -> MetaResult<'gc> {
+    match get_metamethod(value, b"__tostring") {
+        Value::Function(f) => MetaResult::Call(f, vec![value]),
+        _ => match value {
+            Value::String(_) => MetaResult::Value(value),
+            value => {
+                let mut buf = Vec::new();
+                value.display(&mut buf).unwrap();
+                MetaResult::Value(Value::String(String::new(mc, &buf)))
+            }
+        },
+    }
+}
+
+/// The Lua `#` operator.  Tables with a `__len` metamethod call it, other tables return their
+/// border.
+pub fn len<'gc>(value: Value<'gc>) -> Result<MetaResult<'gc>, Error<'gc>> {
+    if let Value::Function(f) = get_metamethod(value, b"__len") {
+        return Ok(MetaResult::Call(f, vec![value]));
+    }
+
+    match value {
+        Value::String(s) => Ok(MetaResult::Value(Value::Integer(s.len()))),
+        Value::Table(t) => Ok(MetaResult::Value(Value::Integer(t.length()))),
+        val => Err(TypeError {
+            expected: "table",
+            found: val.type_name(),
+        }
+        .into()),
+    }
+}
+
+/// Concatenates the given values.  If any value is not a string or number, the values are instead
+/// concatenated right to left as in PUC-Rio Lua, calling the `__concat` metamethod of either operand
+/// whenever a pair of values cannot be concatenated directly.  The resulting strings are interned
+/// in the given set, if any.
+pub fn concat<'gc>(
+    mc: MutationContext<'gc, '_>,
+    interned_strings: Option<InternedStringSet<'gc>>,
+    values: &[Value<'gc>],
+) -> Result<MetaResult<'gc>, Error<'gc>> {
+    if values.iter().all(|&v| is_concatable(v)) {
+        return Ok(MetaResult::Value(Value::String(concat_strings(
+            mc,
+            interned_strings,
+            values,
+        )?)));
+    }
+
+    // Without any metamethod to call, the first pair of values that cannot be concatenated is
+    // found here, so that the error is raised with the location of the instruction.
+    if values
+        .iter()
+        .all(|&v| is_concatable(v) || get_metamethod(v, b"__concat") == Value::Nil)
+    {
+        let last = values.len() - 1;
+        let bad = values.iter().rposition(|&v| !is_concatable(v)).unwrap();
+        let (lhs, rhs) = if bad == last {
+            (values[last - 1], values[last])
+        } else {
+            (values[bad], values[bad + 1])
+        };
+        return Err(concat_error(lhs, rhs).into());
+    }
+
+    let callback = Callback::new_sequence_with(mc, interned_strings, |interned_strings, args| {
+        Ok(sequence::from_fn_with(
+            (*interned_strings, args),
+            |mc, (interned_strings, args)| concat_right(mc, interned_strings, args),
+        ))
+    });
+    Ok(MetaResult::Call(
+        Function::Callback(callback),
+        values.to_vec(),
+    ))
+}
+
+/// Lua equality, calling the `__eq` metamethod of either operand if both operands are tables or
+/// both are userdata that are not primitively equal.
+pub fn equal<'gc>(lhs: Value<'gc>, rhs: Value<'gc>) -> MetaResult<'gc> {
+    if lhs == rhs {
+        return MetaResult::Value(Value::Boolean(true));
+    }
+
+    match (lhs, rhs) {
+        (Value::Table(_), Value::Table(_)) | (Value::UserData(_), Value::UserData(_)) => {
+            let mut mm = get_metamethod(lhs, b"__eq");
+            if mm == Value::Nil {
+                mm = get_metamethod(rhs, b"__eq");
+            }
+
+            match mm {
+                Value::Function(f) => MetaResult::Call(f, vec![lhs, rhs]),
+                _ => MetaResult::Value(Value::Boolean(false)),
+            }
+        }
+        _ => MetaResult::Value(Value::Boolean(false)),
+    }
+}
+
+/// Lua `<`, falls back to the `__lt` metamethod.
+pub fn less_than<'gc>(lhs: Value<'gc>, rhs: Value<'gc>) -> Result<MetaResult<'gc>, Error<'gc>> {
+    binary_operator(
+        lhs,
+        rhs,
+        b"__lt",
+        |a, b| a.less_than(b).map(Value::Boolean),
+        BinaryOperatorError::LessThan,
+    )
+}
+
+/// Lua `<=`, falls back to the `__le` metamethod.  Like Lua 5.4, and unlike Lua 5.3, this does not
+/// try `not (rhs < lhs)` if `__le` is missing.
+pub fn less_equal<'gc>(lhs: Value<'gc>, rhs: Value<'gc>) -> Result<MetaResult<'gc>, Error<'gc>> {
+    binary_operator(
+        lhs,
+        rhs,
+        b"__le",
+        |a, b| a.less_equal(b).map(Value::Boolean),
+        BinaryOperatorError::LessEqual,
+    )
+}
+
+/// Returns the `__close` metamethod to call when a to-be-closed variable holding the given value
+/// goes out of scope.  `nil` and `false` values do not need to be closed, any other value must have
+/// a `__close` metamethod.
+pub fn close<'gc>(value: Value<'gc>) -> Result<Option<Function<'gc>>, MetaOperatorError> {
+    match value {
+        Value::Nil | Value::Boolean(false) => Ok(None),
+        value => match get_metamethod(value, b"__close") {
+            Value::Function(f) => Ok(Some(f)),
+            _ => Err(MetaOperatorError::NotClosable),
+        },
+    }
+}
+
+/// Returns the metamethod with the given name for the given value, or `Nil` if the value has no
+/// metatable or the metatable has no such field.
+pub fn get_metamethod<'gc>(value: Value<'gc>, name: &'static [u8]) -> Value<'gc> {
+    match value {
+        Value::Table(t) => match t.metatable() {
+            Some(mt) => mt.get(String::new_static(name)),
+            None => Value::Nil,
+        },
+        Value::UserData(u) => match u.metatable() {
+            Some(mt) => mt.get(String::new_static(name)),
+            None => Value::Nil,
+        },
+        _ => Value::Nil,
+    }
+}
+
+// Performs a binary operation, falling back to the named metamethod of the left then right operand
+// if the primitive operation fails.
+// The operator itself is inlined into the VM, and the metamethod lookup is kept out of line so that
+// it doesn't prevent that.
+#[inline(always)]
+fn binary_operator<'gc>(
+    lhs: Value<'gc>,
+    rhs: Value<'gc>,
+    metamethod: &'static [u8],
+    operator: fn(Value<'gc>, Value<'gc>) -> Option<Value<'gc>>,
+    error: BinaryOperatorError,
+) -> Result<MetaResult<'gc>, Error<'gc>> {
+    if let Some(v) = operator(lhs, rhs) {
+        Ok(MetaResult::Value(v))
+    } else {
+        binary_metamethod(lhs, rhs, metamethod, error)
+    }
+}
+
+#[cold]
+#[inline(never)]
+fn binary_metamethod<'gc>(
+    lhs: Value<'gc>,
+    rhs: Value<'gc>,
+    metamethod: &'static [u8],
+    error: BinaryOperatorError,
+) -> Result<MetaResult<'gc>, Error<'gc>> {
+    let mut mm = get_metamethod(lhs, metamethod);
+    if mm == Value::Nil {
+        mm = get_metamethod(rhs, metamethod);
+    }
+
+    match mm {
+        Value::Function(f) => Ok(MetaResult::Call(f, vec![lhs, rhs])),
+        _ => Err(error.into()),
+    }
+}
+
+pub fn add<'gc>(lhs: Value<'gc>, rhs: Value<'gc>) -> Result<MetaResult<'gc>, Error<'gc>> {
+    binary_operator(lhs, rhs, b"__add", Value::add, BinaryOperatorError::Add)
+}
+
+pub fn subtract<'gc>(lhs: Value<'gc>, rhs: Value<'gc>) -> Result<MetaResult<'gc>, Error<'gc>> {
+    binary_operator(
+        lhs,
+        rhs,
+        b"__sub",
+        Value::subtract,
+        BinaryOperatorError::Subtract,
+    )
+}
+
+pub fn multiply<'gc>(lhs: Value<'gc>, rhs: Value<'gc>) -> Result<MetaResult<'gc>, Error<'gc>> {
+    binary_operator(
+        lhs,
+        rhs,
+        b"__mul",
+        Value::multiply,
+        BinaryOperatorError::Multiply,
+    )
+}
+
+pub fn float_divide<'gc>(lhs: Value<'gc>, rhs: Value<'gc>) -> Result<MetaResult<'gc>, Error<'gc>> {
+    binary_operator(
+        lhs,
+        rhs,
+        b"__div",
+        Value::float_divide,
+        BinaryOperatorError::FloatDivide,
+    )
+}
+
+pub fn floor_divide<'gc>(lhs: Value<'gc>, rhs: Value<'gc>) -> Result<MetaResult<'gc>, Error<'gc>> {
+    binary_operator(
+        lhs,
+        rhs,
+        b"__idiv",
+        Value::floor_divide,
+        BinaryOperatorError::FloorDivide,
+    )
+}
+
+pub fn modulo<'gc>(lhs: Value<'gc>, rhs: Value<'gc>) -> Result<MetaResult<'gc>, Error<'gc>> {
+    binary_operator(
+        lhs,
+        rhs,
+        b"__mod",
+        Value::modulo,
+        BinaryOperatorError::Modulo,
+    )
+}
+
+pub fn exponentiate<'gc>(lhs: Value<'gc>, rhs: Value<'gc>) -> Result<MetaResult<'gc>, Error<'gc>> {
+    binary_operator(
+        lhs,
+        rhs,
+        b"__pow",
+        Value::exponentiate,
+        BinaryOperatorError::Exponentiate,
+    )
+}
+
+/// Unary minus, falls back to the `__unm` metamethod.  As in PUC-Rio Lua, the metamethod is called
+/// with the operand repeated as both arguments.
+pub fn negate<'gc>(value: Value<'gc>) -> Result<MetaResult<'gc>, Error<'gc>> {
+    binary_operator(
+        value,
+        value,
+        b"__unm",
+        |v, _| v.negate(),
+        BinaryOperatorError::UnaryNegate,
+    )
+}
+
+fn is_concatable<'gc>(value: Value<'gc>) -> bool {
+    match value {
+        Value::String(_) | Value::Integer(_) | Value::Number(_) => true,
+        _ => false,
+    }
+}
+
+// The error for a pair of values that cannot be concatenated, which names the operand that is not
+// a string or number like PUC-Rio Lua.
+fn concat_error<'gc>(lhs: Value<'gc>, rhs: Value<'gc>) -> StringError {
+    let bad = if is_concatable(lhs) { rhs } else { lhs };
+    StringError::Concat {
+        bad_type: bad.type_name(),
+    }
+}
+
+// Concatenates values that are all strings or numbers.
+fn concat_strings<'gc>(
+    mc: MutationContext<'gc, '_>,
+    interned_strings: Option<InternedStringSet<'gc>>,
+    values: &[Value<'gc>],
+) -> Result<String<'gc>, StringError> {
+    match interned_strings {
+        Some(interned_strings) => interned_strings.concat(mc, values),
+        None => String::concat(mc, values),
+    }
+}
+
+// Concatenates the given values from right to left until a metamethod must be called, then calls
+// it with a continuation that resumes the concatenation with its result.
+fn concat_right<'gc>(
+    mc: MutationContext<'gc, '_>,
+    interned_strings: Option<InternedStringSet<'gc>>,
+    mut values: Vec<Value<'gc>>,
+) -> Result<CallbackResult<'gc>, Error<'gc>> {
+    while values.len() > 1 {
+        let total = values.len();
+        let (lhs, rhs) = (values[total - 2], values[total - 1]);
+
+        if is_concatable(lhs) && is_concatable(rhs) {
+            let mut n = 2;
+            while n < total && is_concatable(values[total - n - 1]) {
+                n += 1;
+            }
+            let s = concat_strings(mc, interned_strings, &values[total - n..])?;
+            values.truncate(total - n);
+            values.push(Value::String(s));
+        } else {
+            let mut mm = get_metamethod(lhs, b"__concat");
+            if mm == Value::Nil {
+                mm = get_metamethod(rhs, b"__concat");
+            }
+
+            let function = match mm {
+                Value::Function(f) => f,
+                _ => {
+                    // Raised from the function that is concatenating, rather than from this
+                    // callback which has no location
+                    let message = concat_error(lhs, rhs).to_string();
+                    return Err(Error::LeveledError(
+                        RuntimeError(Value::String(String::new(mc, message.as_bytes()))),
+                        1,
+                    ));
+                }
+            };
+
+            values.truncate(total - 2);
+            return Ok(CallbackResult::TailCall {
+                function,
+                args: vec![lhs, rhs],
+                continuation: Continuation::new_sequence_with(
+                    (interned_strings, values),
+                    |(interned_strings, values), res| {
+                        let res = res?.get(0).cloned().unwrap_or(Value::Nil);
+                        Ok(sequence::from_fn_with(
+                            (interned_strings, values, res),
+                            |mc, (interned_strings, mut values, res)| {
+                                values.push(res);
+                                concat_right(mc, interned_strings, values)
+                            },
+                        ))
+                    },
+                ),
+            });
+        }
+    }
+
+    Ok(CallbackResult::Return(values))
+}
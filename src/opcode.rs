@@ -1,7 +1,8 @@
 use gc_arena::Collect;
 
 use crate::{
-    ConstantIndex16, ConstantIndex8, Opt254, PrototypeIndex, RegisterIndex, UpValueIndex, VarCount,
+    ConstantIndex16, ConstantIndex8, Opt254, PrototypeIndex, RegisterIndex, SizeHint, UpValueIndex,
+    VarCount,
 };
 
 #[derive(Debug, Copy, Clone, Collect)]
@@ -26,8 +27,20 @@ pub enum OpCode {
         dest: RegisterIndex,
         count: u8,
     },
+    // Create a new table in R(dest), preallocating space for the given number of array and map
+    // entries.
     NewTable {
         dest: RegisterIndex,
+        array_size: SizeHint,
+        map_size: SizeHint,
+    },
+    // Set the values in R(base + 1) .. R(base + count) to consecutive integer keys of the table in
+    // R(table), starting at the integer key in R(base).  If `count` is variable, the values extend
+    // to the top of the stack.
+    SetList {
+        table: RegisterIndex,
+        base: RegisterIndex,
+        count: VarCount,
     },
     GetTableR {
         dest: RegisterIndex,
@@ -108,7 +121,7 @@ pub enum OpCode {
     },
     Jump {
         offset: i16,
-        // If set, close upvalues >= `close_upvalues`
+        // If set, close upvalues and to-be-closed variables >= `close_upvalues`
         close_upvalues: Opt254,
     },
     // Test the register as a boolean, if its boolean value matches `is_true`, skip the next
@@ -129,6 +142,12 @@ pub enum OpCode {
         dest: RegisterIndex,
         proto: PrototypeIndex,
     },
+    // Mark the local variable in the given register as to-be-closed.  Its `__close` metamethod will
+    // be called when the variable goes out of scope, either through a closing `Jump`, a `Return`,
+    // or an error.  `nil` and `false` values are ignored.
+    ToBeClosed {
+        value: RegisterIndex,
+    },
     // Used to set up for a numeric for loop:
     //
     // R(base) -= R(base + 2)
@@ -514,4 +533,93 @@ pub enum OpCode {
         dest: RegisterIndex,
         source: RegisterIndex,
     },
+    // Fused forms of a comparison followed by a `Jump` that does not close any upvalues, which the
+    // optimizer produces when the jump is short enough.  For example, `JumpIfLessRR` is:
+    //
+    // if R(left) < R(right) then
+    //     pc += jump
+    // end
+    //
+    // and `JumpIfNotLessRR` jumps when the comparison is false instead.
+    JumpIfEqRR {
+        left: RegisterIndex,
+        right: RegisterIndex,
+        jump: i8,
+    },
+    JumpIfEqRC {
+        left: RegisterIndex,
+        right: ConstantIndex8,
+        jump: i8,
+    },
+    JumpIfNotEqRR {
+        left: RegisterIndex,
+        right: RegisterIndex,
+        jump: i8,
+    },
+    JumpIfNotEqRC {
+        left: RegisterIndex,
+        right: ConstantIndex8,
+        jump: i8,
+    },
+    JumpIfLessRR {
+        left: RegisterIndex,
+        right: RegisterIndex,
+        jump: i8,
+    },
+    JumpIfLessRC {
+        left: RegisterIndex,
+        right: ConstantIndex8,
+        jump: i8,
+    },
+    JumpIfNotLessRR {
+        left: RegisterIndex,
+        right: RegisterIndex,
+        jump: i8,
+    },
+    JumpIfNotLessRC {
+        left: RegisterIndex,
+        right: ConstantIndex8,
+        jump: i8,
+    },
+    JumpIfLessEqRR {
+        left: RegisterIndex,
+        right: RegisterIndex,
+        jump: i8,
+    },
+    JumpIfLessEqRC {
+        left: RegisterIndex,
+        right: ConstantIndex8,
+        jump: i8,
+    },
+    JumpIfNotLessEqRR {
+        left: RegisterIndex,
+        right: RegisterIndex,
+        jump: i8,
+    },
+    JumpIfNotLessEqRC {
+        left: RegisterIndex,
+        right: ConstantIndex8,
+        jump: i8,
+    },
+}
+
+impl OpCode {
+    // Returns the jump offset of a fused comparison and jump, or None for any other opcode.
+    pub(crate) fn fused_jump(&mut self) -> Option<&mut i8> {
+        match self {
+            OpCode::JumpIfEqRR { jump, .. }
+            | OpCode::JumpIfEqRC { jump, .. }
+            | OpCode::JumpIfNotEqRR { jump, .. }
+            | OpCode::JumpIfNotEqRC { jump, .. }
+            | OpCode::JumpIfLessRR { jump, .. }
+            | OpCode::JumpIfLessRC { jump, .. }
+            | OpCode::JumpIfNotLessRR { jump, .. }
+            | OpCode::JumpIfNotLessRC { jump, .. }
+            | OpCode::JumpIfLessEqRR { jump, .. }
+            | OpCode::JumpIfLessEqRC { jump, .. }
+            | OpCode::JumpIfNotLessEqRR { jump, .. }
+            | OpCode::JumpIfNotLessEqRC { jump, .. } => Some(jump),
+            _ => None,
+        }
+    }
 }
@@ -0,0 +1,302 @@
+use crate::Span;
+
+/// A parsed Lua source file, which is the body of the implicit main function.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Chunk<S> {
+    pub block: Block<S>,
+}
+
+/// A list of statements, optionally ending in a `return` statement.  Each statement is paired with
+/// the span of source that it was parsed from.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Block<S> {
+    pub statements: Vec<(Statement<S>, Span)>,
+    pub return_statement: Option<(ReturnStatement<S>, Span)>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Statement<S> {
+    If(IfStatement<S>),
+    While(WhileStatement<S>),
+    Do(Block<S>),
+    For(ForStatement<S>),
+    Repeat(RepeatStatement<S>),
+    Function(FunctionStatement<S>),
+    LocalFunction(LocalFunctionStatement<S>),
+    LocalStatement(LocalStatement<S>),
+    Label(LabelStatement<S>),
+    Break,
+    Goto(GotoStatement<S>),
+    FunctionCall(FunctionCallStatement<S>),
+    Assignment(AssignmentStatement<S>),
+}
+
+/// `return exp1, exp2, ...`
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ReturnStatement<S> {
+    pub returns: Vec<Expression<S>>,
+}
+
+/// `if exp then block elseif exp then block ... else block end`
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct IfStatement<S> {
+    pub if_part: (Expression<S>, Block<S>),
+    pub else_if_parts: Vec<(Expression<S>, Block<S>)>,
+    pub else_part: Option<Block<S>>,
+}
+
+/// `while exp do block end`
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WhileStatement<S> {
+    pub condition: Expression<S>,
+    pub block: Block<S>,
+}
+
+/// Either a numeric `for name = initial, limit, step do body end` or a generic
+/// `for name1, name2, ... in exp1, exp2, ... do body end` loop.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ForStatement<S> {
+    Numeric {
+        name: S,
+        initial: Expression<S>,
+        limit: Expression<S>,
+        step: Option<Expression<S>>,
+        body: Block<S>,
+    },
+    Generic {
+        names: Vec<S>,
+        arguments: Vec<Expression<S>>,
+        body: Block<S>,
+    },
+}
+
+/// `repeat body until exp`
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RepeatStatement<S> {
+    pub body: Block<S>,
+    pub until: Expression<S>,
+}
+
+/// `::name::`
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LabelStatement<S> {
+    pub name: S,
+}
+
+/// `goto name`
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GotoStatement<S> {
+    pub name: S,
+}
+
+/// `function name.field1.field2:method() body end`, where the fields and method are optional.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FunctionStatement<S> {
+    pub name: S,
+    pub fields: Vec<S>,
+    pub method: Option<S>,
+    pub definition: FunctionDefinition<S>,
+}
+
+/// `local function name() body end`
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LocalFunctionStatement<S> {
+    pub name: S,
+    pub definition: FunctionDefinition<S>,
+}
+
+/// The attribute of a local variable, `<const>` or `<close>`.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum LocalAttribute {
+    Const,
+    Close,
+}
+
+/// `local name1 <attrib>, name2, ... = exp1, exp2, ...`
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LocalStatement<S> {
+    pub names: Vec<S>,
+    // The attribute given to each name, always the same length as `names`
+    pub attributes: Vec<Option<LocalAttribute>>,
+    pub values: Vec<Expression<S>>,
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum BinaryOperator {
+    Add,
+    Sub,
+    Mul,
+    Mod,
+    Pow,
+    Div,
+    IDiv,
+    BitAnd,
+    BitOr,
+    BitXor,
+    ShiftLeft,
+    ShiftRight,
+    Concat,
+    NotEqual,
+    Equal,
+    LessThan,
+    LessEqual,
+    GreaterThan,
+    GreaterEqual,
+    And,
+    Or,
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum UnaryOperator {
+    Not,
+    Minus,
+    BitNot,
+    Len,
+}
+
+/// An expression followed by a list of binary operators and their right hand operands.
+///
+/// Operator priority is already resolved by the parser: operators in `tail` are applied from left
+/// to right, and each right hand expression holds any following operators that bind more tightly
+/// or are right associative.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Expression<S> {
+    pub head: Box<HeadExpression<S>>,
+    pub tail: Vec<(BinaryOperator, Expression<S>)>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum HeadExpression<S> {
+    Simple(SimpleExpression<S>),
+    UnaryOperator(UnaryOperator, Expression<S>),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SimpleExpression<S> {
+    Float(f64),
+    Integer(i64),
+    String(S),
+    Nil,
+    True,
+    False,
+    VarArgs,
+    TableConstructor(TableConstructor<S>),
+    Function(FunctionDefinition<S>),
+    Suffixed(SuffixedExpression<S>),
+}
+
+/// A name or a parenthesized expression, which begins a suffixed expression.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PrimaryExpression<S> {
+    Name(S),
+    GroupedExpression(Expression<S>),
+}
+
+/// `.name` or `[exp]`
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FieldSuffix<S> {
+    Named(S),
+    Indexed(Expression<S>),
+}
+
+/// `:name(args)` or `(args)`.  Calls with a single string or table constructor argument are
+/// parsed as calls with that single argument.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CallSuffix<S> {
+    Method(S, Vec<Expression<S>>),
+    Function(Vec<Expression<S>>),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SuffixPart<S> {
+    Field(FieldSuffix<S>),
+    Call(CallSuffix<S>),
+}
+
+/// A primary expression followed by any number of field accesses and calls, such as
+/// `a.b[c](d):e()`.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SuffixedExpression<S> {
+    pub primary: PrimaryExpression<S>,
+    pub suffixes: Vec<SuffixPart<S>>,
+}
+
+/// The parameters and body of a function, shared by function statements and expressions.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FunctionDefinition<S> {
+    pub parameters: Vec<S>,
+    pub has_varargs: bool,
+    pub body: Block<S>,
+}
+
+/// A function call used as a statement, split into the called expression and the final call.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FunctionCallStatement<S> {
+    pub head: SuffixedExpression<S>,
+    pub call: CallSuffix<S>,
+}
+
+/// `target1, target2, ... = exp1, exp2, ...`
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AssignmentStatement<S> {
+    pub targets: Vec<AssignmentTarget<S>>,
+    pub values: Vec<Expression<S>>,
+}
+
+/// A name, or a field of a suffixed expression.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AssignmentTarget<S> {
+    Name(S),
+    Field(SuffixedExpression<S>, FieldSuffix<S>),
+}
+
+/// `{field1, field2, ...}`
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TableConstructor<S> {
+    pub fields: Vec<ConstructorField<S>>,
+}
+
+/// `exp` or `key = exp`
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ConstructorField<S> {
+    Array(Expression<S>),
+    Record(RecordKey<S>, Expression<S>),
+}
+
+/// `name` or `[exp]`
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RecordKey<S> {
+    Named(S),
+    Indexed(Expression<S>),
+}
@@ -0,0 +1,31 @@
+//! The luster parser, which produces an abstract syntax tree from Lua source.
+//!
+//! The syntax tree types are generic over the string type `S` used for names and string literals,
+//! which is produced by the `create_string` function given to `parse_chunk`.  The `Visitor` trait
+//! can be used to walk a parsed syntax tree.
+//!
+//! With the `serde` feature enabled, the syntax tree types and the lexer's `Token` implement
+//! `serde::Serialize` and `serde::Deserialize`.
+
+mod ast;
+mod parser;
+mod visit;
+
+pub use self::ast::{
+    AssignmentStatement, AssignmentTarget, BinaryOperator, Block, CallSuffix, Chunk,
+    ConstructorField, Expression, FieldSuffix, ForStatement, FunctionCallStatement,
+    FunctionDefinition, FunctionStatement, GotoStatement, HeadExpression, IfStatement,
+    LabelStatement, LocalAttribute, LocalFunctionStatement, LocalStatement, PrimaryExpression,
+    RecordKey, RepeatStatement, ReturnStatement, SimpleExpression, Statement, SuffixPart,
+    SuffixedExpression, TableConstructor, UnaryOperator, WhileStatement,
+};
+pub use self::parser::{parse_chunk, ParserError};
+pub use self::visit::{
+    walk_assignment_statement, walk_block, walk_call_suffix, walk_chunk, walk_constructor_field,
+    walk_expression, walk_field_suffix, walk_for_statement, walk_function_call_statement,
+    walk_function_definition, walk_function_statement, walk_head_expression, walk_if_statement,
+    walk_local_function_statement, walk_local_statement, walk_primary_expression, walk_record_key,
+    walk_repeat_statement, walk_return_statement, walk_simple_expression, walk_statement,
+    walk_suffix_part, walk_suffixed_expression, walk_table_constructor, walk_while_statement,
+    Visitor,
+};
@@ -5,236 +5,16 @@ use std::rc::Rc;
 
 use gc_arena::Collect;
 
-use crate::{Lexer, LexerError, Token};
+use crate::{Lexer, LexerError, Span, SyntaxError, SyntaxErrorKind, Token};
 
-#[derive(Debug, PartialEq, Clone)]
-pub struct Chunk<S> {
-    pub block: Block<S>,
-}
-
-#[derive(Debug, PartialEq, Clone)]
-pub struct Block<S> {
-    pub statements: Vec<Statement<S>>,
-    pub return_statement: Option<ReturnStatement<S>>,
-}
-
-#[derive(Debug, PartialEq, Clone)]
-pub enum Statement<S> {
-    If(IfStatement<S>),
-    While(WhileStatement<S>),
-    Do(Block<S>),
-    For(ForStatement<S>),
-    Repeat(RepeatStatement<S>),
-    Function(FunctionStatement<S>),
-    LocalFunction(LocalFunctionStatement<S>),
-    LocalStatement(LocalStatement<S>),
-    Label(LabelStatement<S>),
-    Break,
-    Goto(GotoStatement<S>),
-    FunctionCall(FunctionCallStatement<S>),
-    Assignment(AssignmentStatement<S>),
-}
-
-#[derive(Debug, PartialEq, Clone)]
-pub struct ReturnStatement<S> {
-    pub returns: Vec<Expression<S>>,
-}
-
-#[derive(Debug, PartialEq, Clone)]
-pub struct IfStatement<S> {
-    pub if_part: (Expression<S>, Block<S>),
-    pub else_if_parts: Vec<(Expression<S>, Block<S>)>,
-    pub else_part: Option<Block<S>>,
-}
-
-#[derive(Debug, PartialEq, Clone)]
-pub struct WhileStatement<S> {
-    pub condition: Expression<S>,
-    pub block: Block<S>,
-}
-
-#[derive(Debug, PartialEq, Clone)]
-pub enum ForStatement<S> {
-    Numeric {
-        name: S,
-        initial: Expression<S>,
-        limit: Expression<S>,
-        step: Option<Expression<S>>,
-        body: Block<S>,
-    },
-    Generic {
-        names: Vec<S>,
-        arguments: Vec<Expression<S>>,
-        body: Block<S>,
-    },
-}
-
-#[derive(Debug, PartialEq, Clone)]
-pub struct RepeatStatement<S> {
-    pub body: Block<S>,
-    pub until: Expression<S>,
-}
-
-#[derive(Debug, PartialEq, Clone)]
-pub struct LabelStatement<S> {
-    pub name: S,
-}
-
-#[derive(Debug, PartialEq, Clone)]
-pub struct GotoStatement<S> {
-    pub name: S,
-}
-
-#[derive(Debug, PartialEq, Clone)]
-pub struct FunctionStatement<S> {
-    pub name: S,
-    pub fields: Vec<S>,
-    pub method: Option<S>,
-    pub definition: FunctionDefinition<S>,
-}
-
-#[derive(Debug, PartialEq, Clone)]
-pub struct LocalFunctionStatement<S> {
-    pub name: S,
-    pub definition: FunctionDefinition<S>,
-}
-
-#[derive(Debug, PartialEq, Clone)]
-pub struct LocalStatement<S> {
-    pub names: Vec<S>,
-    pub values: Vec<Expression<S>>,
-}
-
-#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
-pub enum BinaryOperator {
-    Add,
-    Sub,
-    Mul,
-    Mod,
-    Pow,
-    Div,
-    IDiv,
-    BitAnd,
-    BitOr,
-    BitXor,
-    ShiftLeft,
-    ShiftRight,
-    Concat,
-    NotEqual,
-    Equal,
-    LessThan,
-    LessEqual,
-    GreaterThan,
-    GreaterEqual,
-    And,
-    Or,
-}
-
-#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
-pub enum UnaryOperator {
-    Not,
-    Minus,
-    BitNot,
-    Len,
-}
-
-#[derive(Debug, PartialEq, Clone)]
-pub struct Expression<S> {
-    pub head: Box<HeadExpression<S>>,
-    pub tail: Vec<(BinaryOperator, Expression<S>)>,
-}
-
-#[derive(Debug, PartialEq, Clone)]
-pub enum HeadExpression<S> {
-    Simple(SimpleExpression<S>),
-    UnaryOperator(UnaryOperator, Expression<S>),
-}
-
-#[derive(Debug, PartialEq, Clone)]
-pub enum SimpleExpression<S> {
-    Float(f64),
-    Integer(i64),
-    String(S),
-    Nil,
-    True,
-    False,
-    VarArgs,
-    TableConstructor(TableConstructor<S>),
-    Function(FunctionDefinition<S>),
-    Suffixed(SuffixedExpression<S>),
-}
-
-#[derive(Debug, PartialEq, Clone)]
-pub enum PrimaryExpression<S> {
-    Name(S),
-    GroupedExpression(Expression<S>),
-}
-
-#[derive(Debug, PartialEq, Clone)]
-pub enum FieldSuffix<S> {
-    Named(S),
-    Indexed(Expression<S>),
-}
-
-#[derive(Debug, PartialEq, Clone)]
-pub enum CallSuffix<S> {
-    Method(S, Vec<Expression<S>>),
-    Function(Vec<Expression<S>>),
-}
-
-#[derive(Debug, PartialEq, Clone)]
-pub enum SuffixPart<S> {
-    Field(FieldSuffix<S>),
-    Call(CallSuffix<S>),
-}
-
-#[derive(Debug, PartialEq, Clone)]
-pub struct SuffixedExpression<S> {
-    pub primary: PrimaryExpression<S>,
-    pub suffixes: Vec<SuffixPart<S>>,
-}
-
-#[derive(Debug, PartialEq, Clone)]
-pub struct FunctionDefinition<S> {
-    pub parameters: Vec<S>,
-    pub has_varargs: bool,
-    pub body: Block<S>,
-}
-
-#[derive(Debug, PartialEq, Clone)]
-pub struct FunctionCallStatement<S> {
-    pub head: SuffixedExpression<S>,
-    pub call: CallSuffix<S>,
-}
-
-#[derive(Debug, PartialEq, Clone)]
-pub struct AssignmentStatement<S> {
-    pub targets: Vec<AssignmentTarget<S>>,
-    pub values: Vec<Expression<S>>,
-}
-
-#[derive(Debug, PartialEq, Clone)]
-pub enum AssignmentTarget<S> {
-    Name(S),
-    Field(SuffixedExpression<S>, FieldSuffix<S>),
-}
-
-#[derive(Debug, PartialEq, Clone)]
-pub struct TableConstructor<S> {
-    pub fields: Vec<ConstructorField<S>>,
-}
-
-#[derive(Debug, PartialEq, Clone)]
-pub enum ConstructorField<S> {
-    Array(Expression<S>),
-    Record(RecordKey<S>, Expression<S>),
-}
-
-#[derive(Debug, PartialEq, Clone)]
-pub enum RecordKey<S> {
-    Named(S),
-    Indexed(Expression<S>),
-}
+use super::ast::{
+    AssignmentStatement, AssignmentTarget, BinaryOperator, Block, CallSuffix, Chunk,
+    ConstructorField, Expression, FieldSuffix, ForStatement, FunctionCallStatement,
+    FunctionDefinition, FunctionStatement, GotoStatement, HeadExpression, IfStatement,
+    LabelStatement, LocalAttribute, LocalFunctionStatement, LocalStatement, PrimaryExpression,
+    RecordKey, RepeatStatement, ReturnStatement, SimpleExpression, Statement, SuffixPart,
+    SuffixedExpression, TableConstructor, UnaryOperator, WhileStatement,
+};
 
 #[derive(Debug, Collect)]
 #[collect(require_static)]
@@ -248,79 +28,110 @@ pub enum ParserError {
     },
     AssignToExpression,
     ExpressionNotStatement,
+    UnknownAttribute(String),
+    MultipleToBeClosed,
     RecursionLimit,
     LexerError(LexerError),
 }
 
+impl ParserError {
+    /// Returns true if this error was caused by the source ending in the middle of a statement or
+    /// token, such as an unclosed block or long string.  A REPL can use this to read another line
+    /// of input rather than reporting the error.
+    pub fn is_incomplete(&self) -> bool {
+        match self {
+            ParserError::EndOfStream { .. } => true,
+            ParserError::LexerError(lexer_error) => lexer_error.is_incomplete(),
+            _ => false,
+        }
+    }
+}
+
 impl StdError for ParserError {}
 
 impl fmt::Display for ParserError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let write_expected = |f: &mut fmt::Formatter, expected: &Option<String>| {
-            match expected {
-                Some(expected) => {
-                    write!(f, ", expected {}", expected)?;
-                }
-                None => {}
-            }
-            Ok(())
-        };
+        // Like PUC-Rio Lua, errors name the token they were found at, with `<eof>` standing in for
+        // the end of the stream.
+        let write_near =
+            |f: &mut fmt::Formatter, expected: &Option<String>, near: &str| match expected {
+                Some(expected) => write!(f, "{} expected near {}", expected, near),
+                None => write!(f, "unexpected symbol near {}", near),
+            };
 
         match self {
             ParserError::Unexpected {
                 unexpected,
                 expected,
-            } => {
-                write!(f, "found {:?}", unexpected)?;
-                write_expected(f, expected)
-            }
-            ParserError::EndOfStream { expected } => {
-                write!(f, "unexpected end of token stream")?;
-                write_expected(f, expected)
-            }
+            } => write_near(f, expected, unexpected),
+            ParserError::EndOfStream { expected } => write_near(f, expected, "<eof>"),
             ParserError::AssignToExpression => write!(f, "cannot assign to expression"),
             ParserError::ExpressionNotStatement => write!(f, "expression is not a statement"),
+            ParserError::UnknownAttribute(name) => write!(f, "unknown attribute '{}'", name),
+            ParserError::MultipleToBeClosed => {
+                write!(f, "multiple to-be-closed variables in local list")
+            }
             ParserError::RecursionLimit => write!(f, "recursion limit reached"),
             ParserError::LexerError(lexer_error) => write!(f, "{}", lexer_error),
         }
     }
 }
 
-pub fn parse_chunk<R, S, CS>(source: R, create_string: CS) -> Result<Chunk<S>, ParserError>
+/// Parses a chunk of Lua source.  The returned `SyntaxError` never has a chunk name, the caller may
+/// fill one in.
+pub fn parse_chunk<R, S, CS>(source: R, create_string: CS) -> Result<Chunk<S>, SyntaxError>
 where
     R: Read,
-    S: fmt::Debug + PartialEq,
+    S: fmt::Debug + PartialEq + AsRef<[u8]>,
     CS: FnMut(&[u8]) -> S,
 {
-    Parser {
+    let mut parser = Parser {
         lexer: Lexer::new(source, create_string),
         read_buffer: Vec::new(),
+        last_span: Span::default(),
         recursion_guard: Rc::new(()),
-    }
-    .parse_chunk()
+    };
+    parser.parse_chunk().map_err(|error| SyntaxError {
+        chunk_name: None,
+        span: parser.error_span(&error),
+        kind: SyntaxErrorKind::Parser(error),
+    })
 }
 
 struct Parser<R, S, CS> {
     lexer: Lexer<R, CS>,
-    read_buffer: Vec<Token<S>>,
+    read_buffer: Vec<(Token<S>, Span)>,
+    // The span of the last token taken from the read buffer
+    last_span: Span,
     recursion_guard: Rc<()>,
 }
 
 impl<R, S, CS> Parser<R, S, CS>
 where
     R: Read,
-    S: fmt::Debug + PartialEq,
+    S: fmt::Debug + PartialEq + AsRef<[u8]>,
     CS: FnMut(&[u8]) -> S,
 {
     fn parse_chunk(&mut self) -> Result<Chunk<S>, ParserError> {
         let block = self.parse_block()?;
-        if self.look_ahead(0)? != None {
-            Err(ParserError::EndOfStream { expected: None })
+        if self.look_ahead(0)?.is_some() {
+            Err(self.unexpected_next("'<eof>'"))
         } else {
             Ok(Chunk { block })
         }
     }
 
+    // Errors from the lexer and errors at the end of the stream are located at the lexer's current
+    // position, every other error is located at the last token taken.
+    fn error_span(&self, error: &ParserError) -> Span {
+        match error {
+            ParserError::EndOfStream { .. } | ParserError::LexerError(_) => {
+                Span::at(self.lexer.position())
+            }
+            _ => self.last_span,
+        }
+    }
+
     fn parse_block(&mut self) -> Result<Block<S>, ParserError> {
         let mut statements = Vec::new();
         let mut return_statement = None;
@@ -333,12 +144,12 @@ where
                     self.take_next()?;
                 }
                 Some(&Token::Return) => {
-                    return_statement = Some(self.parse_return_statement()?);
+                    return_statement = Some(self.spanned(Self::parse_return_statement)?);
                     break;
                 }
                 None => break,
                 _ => {
-                    statements.push(self.parse_statement()?);
+                    statements.push(self.spanned(Self::parse_statement)?);
                 }
             }
         }
@@ -491,10 +302,7 @@ where
                 })
             }
 
-            token => Err(ParserError::Unexpected {
-                unexpected: format!("{:?}", token),
-                expected: Some("'=' or 'in'".to_owned()),
-            }),
+            _ => Err(self.unexpected_next("'=' or 'in'")),
         }
     }
 
@@ -549,10 +357,22 @@ where
     fn parse_local_statement(&mut self) -> Result<LocalStatement<S>, ParserError> {
         self.expect_next(Token::Local)?;
         let mut names = Vec::new();
-        names.push(self.expect_name()?);
-        while self.check_ahead(0, Token::Comma)? {
-            self.take_next()?;
+        let mut attributes = Vec::new();
+        loop {
             names.push(self.expect_name()?);
+            let attribute = self.parse_local_attribute()?;
+            if attribute == Some(LocalAttribute::Close)
+                && attributes.contains(&Some(LocalAttribute::Close))
+            {
+                return Err(ParserError::MultipleToBeClosed);
+            }
+            attributes.push(attribute);
+
+            if self.check_ahead(0, Token::Comma)? {
+                self.take_next()?;
+            } else {
+                break;
+            }
         }
 
         let values = if self.check_ahead(0, Token::Assign)? {
@@ -562,7 +382,33 @@ where
             Vec::new()
         };
 
-        Ok(LocalStatement { names, values })
+        Ok(LocalStatement {
+            names,
+            attributes,
+            values,
+        })
+    }
+
+    // Parses an optional `<const>` or `<close>` attribute following a local variable name.
+    fn parse_local_attribute(&mut self) -> Result<Option<LocalAttribute>, ParserError> {
+        if !self.check_ahead(0, Token::LessThan)? {
+            return Ok(None);
+        }
+        self.take_next()?;
+
+        let name = self.expect_name()?;
+        let attribute = match name.as_ref() {
+            b"const" => LocalAttribute::Const,
+            b"close" => LocalAttribute::Close,
+            other => {
+                return Err(ParserError::UnknownAttribute(
+                    String::from_utf8_lossy(other).into_owned(),
+                ));
+            }
+        };
+
+        self.expect_next(Token::GreaterThan)?;
+        Ok(Some(attribute))
     }
 
     fn parse_label_statement(&mut self) -> Result<LabelStatement<S>, ParserError> {
@@ -717,8 +563,8 @@ where
             }
             Token::Name(n) => Ok(PrimaryExpression::Name(n)),
             token => Err(ParserError::Unexpected {
-                unexpected: format!("{:?}", token),
-                expected: Some("grouped expression or name".to_owned()),
+                unexpected: format!("'{}'", token),
+                expected: None,
             }),
         }
     }
@@ -735,10 +581,7 @@ where
                 self.expect_next(Token::RightBracket)?;
                 Ok(FieldSuffix::Indexed(expr))
             }
-            token => Err(ParserError::Unexpected {
-                unexpected: format!("{:?}", token),
-                expected: Some("field or suffix".to_owned()),
-            }),
+            _ => Err(self.unexpected_next("field or suffix")),
         }
     }
 
@@ -774,12 +617,7 @@ where
                 ))),
                 tail: vec![],
             }],
-            token => {
-                return Err(ParserError::Unexpected {
-                    unexpected: format!("{:?}", token),
-                    expected: Some("function arguments".to_owned()),
-                });
-            }
+            _ => return Err(self.unexpected_next("function arguments")),
         };
 
         Ok(if let Some(method_name) = method_name {
@@ -795,10 +633,7 @@ where
             Token::Colon | Token::LeftParen | Token::LeftBrace | Token::String(_) => {
                 Ok(SuffixPart::Call(self.parse_call_suffix()?))
             }
-            token => Err(ParserError::Unexpected {
-                unexpected: format!("{:?}", token),
-                expected: Some("expression suffix".to_owned()),
-            }),
+            _ => Err(self.unexpected_next("expression suffix")),
         }
     }
 
@@ -837,8 +672,8 @@ where
                     }
                     token => {
                         return Err(ParserError::Unexpected {
-                            unexpected: format!("{:?}", token),
-                            expected: Some("parameter name or '...'".to_owned()),
+                            unexpected: format!("'{}'", token),
+                            expected: Some("<name> or '...'".to_owned()),
                         });
                     }
                 }
@@ -917,7 +752,7 @@ where
     // Return a reference to the next token in the stream, erroring if we are at the end.
     fn get_next(&mut self) -> Result<&Token<S>, ParserError> {
         self.read_ahead(1)?;
-        if let Some(token) = self.read_buffer.get(0) {
+        if let Some((token, _)) = self.read_buffer.get(0) {
             Ok(token)
         } else {
             Err(ParserError::EndOfStream { expected: None })
@@ -929,16 +764,16 @@ where
         self.read_ahead(1)?;
         if self.read_buffer.is_empty() {
             Err(ParserError::EndOfStream {
-                expected: Some(format!("{:?}", token)),
+                expected: Some(format!("'{}'", token)),
             })
         } else {
-            let next_token = self.read_buffer.remove(0);
+            let next_token = self.pop_token();
             if next_token == token {
                 Ok(())
             } else {
                 Err(ParserError::Unexpected {
-                    unexpected: format!("{:?}", next_token),
-                    expected: Some(format!("{:?}", token)),
+                    unexpected: format!("'{}'", next_token),
+                    expected: Some(format!("'{}'", token)),
                 })
             }
         }
@@ -949,14 +784,14 @@ where
         self.read_ahead(1)?;
         if self.read_buffer.is_empty() {
             Err(ParserError::EndOfStream {
-                expected: Some("name".to_owned()),
+                expected: Some("<name>".to_owned()),
             })
         } else {
-            match self.read_buffer.remove(0) {
+            match self.pop_token() {
                 Token::Name(name) => Ok(name),
                 token => Err(ParserError::Unexpected {
-                    unexpected: format!("{:?}", token),
-                    expected: Some("name".to_owned()),
+                    unexpected: format!("'{}'", token),
+                    expected: Some("<name>".to_owned()),
                 }),
             }
         }
@@ -967,14 +802,14 @@ where
         self.read_ahead(1)?;
         if self.read_buffer.is_empty() {
             Err(ParserError::EndOfStream {
-                expected: Some("string".to_owned()),
+                expected: Some("<string>".to_owned()),
             })
         } else {
-            match self.read_buffer.remove(0) {
+            match self.pop_token() {
                 Token::String(string) => Ok(string),
                 token => Err(ParserError::Unexpected {
-                    unexpected: format!("{:?}", token),
-                    expected: Some("string".to_owned()),
+                    unexpected: format!("'{}'", token),
+                    expected: Some("<string>".to_owned()),
                 }),
             }
         }
@@ -986,21 +821,21 @@ where
         if self.read_buffer.is_empty() {
             Err(ParserError::EndOfStream { expected: None })
         } else {
-            Ok(self.read_buffer.remove(0))
+            Ok(self.pop_token())
         }
     }
 
     // Return the nth token ahead in the stream, if it is not past the end.
     fn look_ahead(&mut self, n: usize) -> Result<Option<&Token<S>>, ParserError> {
         self.read_ahead(n + 1)?;
-        Ok(self.read_buffer.get(n))
+        Ok(self.read_buffer.get(n).map(|(token, _)| token))
     }
 
     // Return true if the nth token ahead in the stream matches the given token.  If this would read
     // past the end of the stream, this will simply return false.
     fn check_ahead(&mut self, n: usize, token: Token<S>) -> Result<bool, ParserError> {
         self.read_ahead(n)?;
-        Ok(if let Some(t) = self.read_buffer.get(n) {
+        Ok(if let Some((t, _)) = self.read_buffer.get(n) {
             *t == token
         } else {
             false
@@ -1011,14 +846,58 @@ where
     // possible).
     fn read_ahead(&mut self, n: usize) -> Result<(), ParserError> {
         while self.read_buffer.len() <= n {
+            self.lexer
+                .skip_whitespace()
+                .map_err(ParserError::LexerError)?;
+            let start = self.lexer.position();
             if let Some(token) = self.lexer.read_token().map_err(ParserError::LexerError)? {
-                self.read_buffer.push(token);
+                let end = self.lexer.position();
+                self.read_buffer.push((token, Span { start, end }));
             } else {
                 break;
             }
         }
         Ok(())
     }
+
+    // Remove the next token from the read buffer, which must not be empty.
+    fn pop_token(&mut self) -> Token<S> {
+        let (token, span) = self.read_buffer.remove(0);
+        self.last_span = span;
+        token
+    }
+
+    // Consume the next token and return an error that it was not the expected one.
+    fn unexpected_next(&mut self, expected: &str) -> ParserError {
+        match self.take_next() {
+            Ok(token) => ParserError::Unexpected {
+                unexpected: format!("'{}'", token),
+                expected: Some(expected.to_owned()),
+            },
+            Err(err) => err,
+        }
+    }
+
+    // Parse a syntax tree node with the given function, returning it along with the span of the
+    // tokens that it was parsed from.
+    fn spanned<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<T, ParserError>,
+    ) -> Result<(T, Span), ParserError> {
+        self.read_ahead(0)?;
+        let start = match self.read_buffer.get(0) {
+            Some((_, span)) => span.start,
+            None => self.lexer.position(),
+        };
+        let node = parse(self)?;
+        Ok((
+            node,
+            Span {
+                start,
+                end: self.last_span.end,
+            },
+        ))
+    }
 }
 
 const MAX_RECURSION: usize = 200;
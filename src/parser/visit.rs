@@ -0,0 +1,399 @@
+use super::ast::{
+    AssignmentStatement, AssignmentTarget, Block, CallSuffix, Chunk, ConstructorField, Expression,
+    FieldSuffix, ForStatement, FunctionCallStatement, FunctionDefinition, FunctionStatement,
+    HeadExpression, IfStatement, LocalFunctionStatement, LocalStatement, PrimaryExpression,
+    RecordKey, RepeatStatement, ReturnStatement, SimpleExpression, Statement, SuffixPart,
+    SuffixedExpression, TableConstructor, WhileStatement,
+};
+
+/// Walks a syntax tree, visiting every node in source order.
+///
+/// Each method by default calls the matching `walk_` function, which visits the children of the
+/// node.  Override a method to inspect that kind of node, and call the `walk_` function from it to
+/// keep visiting the nodes inside.
+pub trait Visitor<S> {
+    fn visit_chunk(&mut self, chunk: &Chunk<S>) {
+        walk_chunk(self, chunk)
+    }
+
+    fn visit_block(&mut self, block: &Block<S>) {
+        walk_block(self, block)
+    }
+
+    fn visit_statement(&mut self, statement: &Statement<S>) {
+        walk_statement(self, statement)
+    }
+
+    fn visit_return_statement(&mut self, return_statement: &ReturnStatement<S>) {
+        walk_return_statement(self, return_statement)
+    }
+
+    fn visit_if_statement(&mut self, if_statement: &IfStatement<S>) {
+        walk_if_statement(self, if_statement)
+    }
+
+    fn visit_while_statement(&mut self, while_statement: &WhileStatement<S>) {
+        walk_while_statement(self, while_statement)
+    }
+
+    fn visit_for_statement(&mut self, for_statement: &ForStatement<S>) {
+        walk_for_statement(self, for_statement)
+    }
+
+    fn visit_repeat_statement(&mut self, repeat_statement: &RepeatStatement<S>) {
+        walk_repeat_statement(self, repeat_statement)
+    }
+
+    fn visit_function_statement(&mut self, function_statement: &FunctionStatement<S>) {
+        walk_function_statement(self, function_statement)
+    }
+
+    fn visit_local_function_statement(
+        &mut self,
+        local_function_statement: &LocalFunctionStatement<S>,
+    ) {
+        walk_local_function_statement(self, local_function_statement)
+    }
+
+    fn visit_local_statement(&mut self, local_statement: &LocalStatement<S>) {
+        walk_local_statement(self, local_statement)
+    }
+
+    fn visit_function_call_statement(&mut self, function_call: &FunctionCallStatement<S>) {
+        walk_function_call_statement(self, function_call)
+    }
+
+    fn visit_assignment_statement(&mut self, assignment: &AssignmentStatement<S>) {
+        walk_assignment_statement(self, assignment)
+    }
+
+    fn visit_expression(&mut self, expression: &Expression<S>) {
+        walk_expression(self, expression)
+    }
+
+    fn visit_head_expression(&mut self, head_expression: &HeadExpression<S>) {
+        walk_head_expression(self, head_expression)
+    }
+
+    fn visit_simple_expression(&mut self, simple_expression: &SimpleExpression<S>) {
+        walk_simple_expression(self, simple_expression)
+    }
+
+    fn visit_suffixed_expression(&mut self, suffixed_expression: &SuffixedExpression<S>) {
+        walk_suffixed_expression(self, suffixed_expression)
+    }
+
+    fn visit_primary_expression(&mut self, primary_expression: &PrimaryExpression<S>) {
+        walk_primary_expression(self, primary_expression)
+    }
+
+    fn visit_suffix_part(&mut self, suffix_part: &SuffixPart<S>) {
+        walk_suffix_part(self, suffix_part)
+    }
+
+    fn visit_field_suffix(&mut self, field_suffix: &FieldSuffix<S>) {
+        walk_field_suffix(self, field_suffix)
+    }
+
+    fn visit_call_suffix(&mut self, call_suffix: &CallSuffix<S>) {
+        walk_call_suffix(self, call_suffix)
+    }
+
+    fn visit_function_definition(&mut self, function_definition: &FunctionDefinition<S>) {
+        walk_function_definition(self, function_definition)
+    }
+
+    fn visit_table_constructor(&mut self, table_constructor: &TableConstructor<S>) {
+        walk_table_constructor(self, table_constructor)
+    }
+
+    fn visit_constructor_field(&mut self, constructor_field: &ConstructorField<S>) {
+        walk_constructor_field(self, constructor_field)
+    }
+
+    fn visit_record_key(&mut self, record_key: &RecordKey<S>) {
+        walk_record_key(self, record_key)
+    }
+}
+
+pub fn walk_chunk<S, V: Visitor<S> + ?Sized>(visitor: &mut V, chunk: &Chunk<S>) {
+    visitor.visit_block(&chunk.block);
+}
+
+pub fn walk_block<S, V: Visitor<S> + ?Sized>(visitor: &mut V, block: &Block<S>) {
+    for (statement, _) in &block.statements {
+        visitor.visit_statement(statement);
+    }
+    if let Some((return_statement, _)) = &block.return_statement {
+        visitor.visit_return_statement(return_statement);
+    }
+}
+
+pub fn walk_statement<S, V: Visitor<S> + ?Sized>(visitor: &mut V, statement: &Statement<S>) {
+    match statement {
+        Statement::If(if_statement) => visitor.visit_if_statement(if_statement),
+        Statement::While(while_statement) => visitor.visit_while_statement(while_statement),
+        Statement::Do(block) => visitor.visit_block(block),
+        Statement::For(for_statement) => visitor.visit_for_statement(for_statement),
+        Statement::Repeat(repeat_statement) => visitor.visit_repeat_statement(repeat_statement),
+        Statement::Function(function_statement) => {
+            visitor.visit_function_statement(function_statement)
+        }
+        Statement::LocalFunction(local_function_statement) => {
+            visitor.visit_local_function_statement(local_function_statement)
+        }
+        Statement::LocalStatement(local_statement) => {
+            visitor.visit_local_statement(local_statement)
+        }
+        Statement::Label(_) | Statement::Break | Statement::Goto(_) => {}
+        Statement::FunctionCall(function_call) => {
+            visitor.visit_function_call_statement(function_call)
+        }
+        Statement::Assignment(assignment) => visitor.visit_assignment_statement(assignment),
+    }
+}
+
+pub fn walk_return_statement<S, V: Visitor<S> + ?Sized>(
+    visitor: &mut V,
+    return_statement: &ReturnStatement<S>,
+) {
+    for expression in &return_statement.returns {
+        visitor.visit_expression(expression);
+    }
+}
+
+pub fn walk_if_statement<S, V: Visitor<S> + ?Sized>(
+    visitor: &mut V,
+    if_statement: &IfStatement<S>,
+) {
+    visitor.visit_expression(&if_statement.if_part.0);
+    visitor.visit_block(&if_statement.if_part.1);
+    for (condition, block) in &if_statement.else_if_parts {
+        visitor.visit_expression(condition);
+        visitor.visit_block(block);
+    }
+    if let Some(block) = &if_statement.else_part {
+        visitor.visit_block(block);
+    }
+}
+
+pub fn walk_while_statement<S, V: Visitor<S> + ?Sized>(
+    visitor: &mut V,
+    while_statement: &WhileStatement<S>,
+) {
+    visitor.visit_expression(&while_statement.condition);
+    visitor.visit_block(&while_statement.block);
+}
+
+pub fn walk_for_statement<S, V: Visitor<S> + ?Sized>(
+    visitor: &mut V,
+    for_statement: &ForStatement<S>,
+) {
+    match for_statement {
+        ForStatement::Numeric {
+            initial,
+            limit,
+            step,
+            body,
+            ..
+        } => {
+            visitor.visit_expression(initial);
+            visitor.visit_expression(limit);
+            if let Some(step) = step {
+                visitor.visit_expression(step);
+            }
+            visitor.visit_block(body);
+        }
+        ForStatement::Generic {
+            arguments, body, ..
+        } => {
+            for argument in arguments {
+                visitor.visit_expression(argument);
+            }
+            visitor.visit_block(body);
+        }
+    }
+}
+
+pub fn walk_repeat_statement<S, V: Visitor<S> + ?Sized>(
+    visitor: &mut V,
+    repeat_statement: &RepeatStatement<S>,
+) {
+    visitor.visit_block(&repeat_statement.body);
+    visitor.visit_expression(&repeat_statement.until);
+}
+
+pub fn walk_function_statement<S, V: Visitor<S> + ?Sized>(
+    visitor: &mut V,
+    function_statement: &FunctionStatement<S>,
+) {
+    visitor.visit_function_definition(&function_statement.definition);
+}
+
+pub fn walk_local_function_statement<S, V: Visitor<S> + ?Sized>(
+    visitor: &mut V,
+    local_function_statement: &LocalFunctionStatement<S>,
+) {
+    visitor.visit_function_definition(&local_function_statement.definition);
+}
+
+pub fn walk_local_statement<S, V: Visitor<S> + ?Sized>(
+    visitor: &mut V,
+    local_statement: &LocalStatement<S>,
+) {
+    for value in &local_statement.values {
+        visitor.visit_expression(value);
+    }
+}
+
+pub fn walk_function_call_statement<S, V: Visitor<S> + ?Sized>(
+    visitor: &mut V,
+    function_call: &FunctionCallStatement<S>,
+) {
+    visitor.visit_suffixed_expression(&function_call.head);
+    visitor.visit_call_suffix(&function_call.call);
+}
+
+pub fn walk_assignment_statement<S, V: Visitor<S> + ?Sized>(
+    visitor: &mut V,
+    assignment: &AssignmentStatement<S>,
+) {
+    for target in &assignment.targets {
+        match target {
+            AssignmentTarget::Name(_) => {}
+            AssignmentTarget::Field(suffixed_expression, field_suffix) => {
+                visitor.visit_suffixed_expression(suffixed_expression);
+                visitor.visit_field_suffix(field_suffix);
+            }
+        }
+    }
+    for value in &assignment.values {
+        visitor.visit_expression(value);
+    }
+}
+
+pub fn walk_expression<S, V: Visitor<S> + ?Sized>(visitor: &mut V, expression: &Expression<S>) {
+    visitor.visit_head_expression(&expression.head);
+    for (_, right) in &expression.tail {
+        visitor.visit_expression(right);
+    }
+}
+
+pub fn walk_head_expression<S, V: Visitor<S> + ?Sized>(
+    visitor: &mut V,
+    head_expression: &HeadExpression<S>,
+) {
+    match head_expression {
+        HeadExpression::Simple(simple_expression) => {
+            visitor.visit_simple_expression(simple_expression)
+        }
+        HeadExpression::UnaryOperator(_, expression) => visitor.visit_expression(expression),
+    }
+}
+
+pub fn walk_simple_expression<S, V: Visitor<S> + ?Sized>(
+    visitor: &mut V,
+    simple_expression: &SimpleExpression<S>,
+) {
+    match simple_expression {
+        SimpleExpression::Float(_)
+        | SimpleExpression::Integer(_)
+        | SimpleExpression::String(_)
+        | SimpleExpression::Nil
+        | SimpleExpression::True
+        | SimpleExpression::False
+        | SimpleExpression::VarArgs => {}
+        SimpleExpression::TableConstructor(table_constructor) => {
+            visitor.visit_table_constructor(table_constructor)
+        }
+        SimpleExpression::Function(function_definition) => {
+            visitor.visit_function_definition(function_definition)
+        }
+        SimpleExpression::Suffixed(suffixed_expression) => {
+            visitor.visit_suffixed_expression(suffixed_expression)
+        }
+    }
+}
+
+pub fn walk_suffixed_expression<S, V: Visitor<S> + ?Sized>(
+    visitor: &mut V,
+    suffixed_expression: &SuffixedExpression<S>,
+) {
+    visitor.visit_primary_expression(&suffixed_expression.primary);
+    for suffix_part in &suffixed_expression.suffixes {
+        visitor.visit_suffix_part(suffix_part);
+    }
+}
+
+pub fn walk_primary_expression<S, V: Visitor<S> + ?Sized>(
+    visitor: &mut V,
+    primary_expression: &PrimaryExpression<S>,
+) {
+    match primary_expression {
+        PrimaryExpression::Name(_) => {}
+        PrimaryExpression::GroupedExpression(expression) => visitor.visit_expression(expression),
+    }
+}
+
+pub fn walk_suffix_part<S, V: Visitor<S> + ?Sized>(visitor: &mut V, suffix_part: &SuffixPart<S>) {
+    match suffix_part {
+        SuffixPart::Field(field_suffix) => visitor.visit_field_suffix(field_suffix),
+        SuffixPart::Call(call_suffix) => visitor.visit_call_suffix(call_suffix),
+    }
+}
+
+pub fn walk_field_suffix<S, V: Visitor<S> + ?Sized>(
+    visitor: &mut V,
+    field_suffix: &FieldSuffix<S>,
+) {
+    match field_suffix {
+        FieldSuffix::Named(_) => {}
+        FieldSuffix::Indexed(expression) => visitor.visit_expression(expression),
+    }
+}
+
+pub fn walk_call_suffix<S, V: Visitor<S> + ?Sized>(visitor: &mut V, call_suffix: &CallSuffix<S>) {
+    match call_suffix {
+        CallSuffix::Method(_, arguments) | CallSuffix::Function(arguments) => {
+            for argument in arguments {
+                visitor.visit_expression(argument);
+            }
+        }
+    }
+}
+
+pub fn walk_function_definition<S, V: Visitor<S> + ?Sized>(
+    visitor: &mut V,
+    function_definition: &FunctionDefinition<S>,
+) {
+    visitor.visit_block(&function_definition.body);
+}
+
+pub fn walk_table_constructor<S, V: Visitor<S> + ?Sized>(
+    visitor: &mut V,
+    table_constructor: &TableConstructor<S>,
+) {
+    for field in &table_constructor.fields {
+        visitor.visit_constructor_field(field);
+    }
+}
+
+pub fn walk_constructor_field<S, V: Visitor<S> + ?Sized>(
+    visitor: &mut V,
+    constructor_field: &ConstructorField<S>,
+) {
+    match constructor_field {
+        ConstructorField::Array(value) => visitor.visit_expression(value),
+        ConstructorField::Record(key, value) => {
+            visitor.visit_record_key(key);
+            visitor.visit_expression(value);
+        }
+    }
+}
+
+pub fn walk_record_key<S, V: Visitor<S> + ?Sized>(visitor: &mut V, record_key: &RecordKey<S>) {
+    match record_key {
+        RecordKey::Named(_) => {}
+        RecordKey::Indexed(expression) => visitor.visit_expression(expression),
+    }
+}
@@ -0,0 +1,260 @@
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::string::String as StdString;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use rustc_hash::FxHashMap;
+
+use crate::{error::chunk_id, HookContext, HostHook, Location, StackFrame};
+
+/// A sampling profiler, which records the call stack of the threads it is set on as their host
+/// hook with `Thread::set_host_hook`.
+///
+/// The call stack is sampled every `interval` instructions, or with `Profiler::on_signal`, only
+/// when the host asks for a sample with a `ProfilerSignal`.  Each sample stands for the time spent
+/// since the last one, so the number of samples of a function is proportional to the time spent
+/// in it.
+pub struct Profiler {
+    interval: u32,
+    signal: Option<ProfilerSignal>,
+    samples: Cell<u64>,
+    stacks: RefCell<FxHashMap<Vec<ProfileFrame>, u64>>,
+}
+
+/// Asks a `Profiler` created with `Profiler::on_signal` to take a sample, which it does the next
+/// time it checks for a signal.  This may be sent to another OS thread, such as one that triggers
+/// the signal on a timer to sample by time rather than by instruction count.
+#[derive(Debug, Clone, Default)]
+pub struct ProfilerSignal(Arc<AtomicBool>);
+
+impl ProfilerSignal {
+    pub fn trigger(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Profiler {
+    /// Creates a profiler that samples the call stack after every `interval` instructions.
+    pub fn new(interval: u32) -> Profiler {
+        Profiler {
+            interval: interval.max(1),
+            signal: None,
+            samples: Cell::new(0),
+            stacks: RefCell::new(FxHashMap::default()),
+        }
+    }
+
+    /// Creates a profiler that checks for a signal after every `interval` instructions, and only
+    /// samples the call stack when its signal has been triggered since the last check.
+    pub fn on_signal(interval: u32) -> Profiler {
+        Profiler {
+            signal: Some(ProfilerSignal::default()),
+            ..Profiler::new(interval)
+        }
+    }
+
+    /// The signal of a profiler created with `Profiler::on_signal`.
+    pub fn signal(&self) -> Option<ProfilerSignal> {
+        self.signal.clone()
+    }
+
+    /// Returns everything sampled so far.
+    pub fn report(&self) -> ProfileReport {
+        let mut stacks = self
+            .stacks
+            .borrow()
+            .iter()
+            .map(|(stack, &samples)| (stack.clone(), samples))
+            .collect::<Vec<_>>();
+        stacks.sort_by(|a, b| b.1.cmp(&a.1));
+        ProfileReport {
+            samples: self.samples.get(),
+            stacks,
+        }
+    }
+
+    /// Discards everything sampled so far.
+    pub fn reset(&self) {
+        self.samples.set(0);
+        self.stacks.borrow_mut().clear();
+    }
+}
+
+impl HostHook for Profiler {
+    fn count_interval(&self) -> u32 {
+        self.interval
+    }
+
+    fn count(&self, context: &HookContext) {
+        if let Some(signal) = &self.signal {
+            if !signal.0.swap(false, Ordering::Relaxed) {
+                return;
+            }
+        }
+
+        let mut stack = context
+            .call_stack()
+            .iter()
+            .map(ProfileFrame::new)
+            .collect::<Vec<_>>();
+        stack.reverse();
+        self.samples.set(self.samples.get() + 1);
+        *self.stacks.borrow_mut().entry(stack).or_insert(0) += 1;
+    }
+}
+
+/// The samples taken by a `Profiler`.
+#[derive(Debug, Clone, Default)]
+pub struct ProfileReport {
+    /// The total number of samples taken.
+    pub samples: u64,
+    /// Each distinct call stack that was sampled, outermost function first, with the number of
+    /// times it was sampled, most sampled first.  Stacks that only differ in the current line of
+    /// some function are counted separately.
+    pub stacks: Vec<(Vec<ProfileFrame>, u64)>,
+}
+
+/// A function in a call stack sampled by a `Profiler`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ProfileFrame {
+    /// How the function is shown in a traceback, like `function 'f'`, which depends on how it was
+    /// called.
+    pub description: StdString,
+    /// The chunk name of a Lua function and the line its definition starts on, which is 0 for the
+    /// main function of a chunk, or `None` for a callback.
+    pub defined: Option<Location>,
+    /// The chunk name and current line of a Lua function.
+    pub location: Option<Location>,
+}
+
+impl ProfileFrame {
+    fn new(frame: &StackFrame) -> ProfileFrame {
+        ProfileFrame {
+            description: frame.to_static().description,
+            defined: frame.closure.map(|closure| {
+                let proto = &closure.0.proto;
+                Location {
+                    chunk_name: proto
+                        .chunk_name
+                        .map(|name| StdString::from_utf8_lossy(name.as_bytes()).into_owned()),
+                    line: Some(proto.line_defined),
+                }
+            }),
+            location: frame.location.clone(),
+        }
+    }
+
+    // Describes the function by where it was defined rather than how it was called, so that it is
+    // the same for every call.
+    fn function(&self) -> StdString {
+        match &self.defined {
+            Some(Location { line: Some(0), .. }) => "main chunk".to_owned(),
+            Some(Location { chunk_name, line }) => format!(
+                "function <{}:{}>",
+                chunk_name
+                    .as_deref()
+                    .map(chunk_id)
+                    .unwrap_or_else(|| "?".to_owned()),
+                line.unwrap_or(0)
+            ),
+            None => self.description.clone(),
+        }
+    }
+}
+
+/// The samples of one function in a `ProfileReport`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionSamples {
+    /// Where the function was defined, like `function <file.lua:12>` or `main chunk`, or how a
+    /// callback is shown in a traceback.
+    pub function: StdString,
+    /// Where a Lua function was defined, as in `ProfileFrame::defined`.
+    pub defined: Option<Location>,
+    /// The number of samples taken while the function itself was running.
+    pub self_samples: u64,
+    /// The number of samples taken while the function was anywhere on the call stack, counting
+    /// recursive calls once.
+    pub total_samples: u64,
+}
+
+impl ProfileReport {
+    /// The samples of each function, with the functions that ran the longest themselves first.
+    pub fn functions(&self) -> Vec<FunctionSamples> {
+        let mut functions: FxHashMap<(StdString, Option<&Location>), (u64, u64)> =
+            FxHashMap::default();
+        for (stack, samples) in &self.stacks {
+            let mut seen = Vec::new();
+            for (i, frame) in stack.iter().enumerate() {
+                let key = (frame.function(), frame.defined.as_ref());
+                let entry = functions.entry(key.clone()).or_insert((0, 0));
+                if i + 1 == stack.len() {
+                    entry.0 += samples;
+                }
+                if !seen.contains(&key) {
+                    seen.push(key);
+                    entry.1 += samples;
+                }
+            }
+        }
+
+        let mut functions = functions
+            .into_iter()
+            .map(
+                |((function, defined), (self_samples, total_samples))| FunctionSamples {
+                    function,
+                    defined: defined.cloned(),
+                    self_samples,
+                    total_samples,
+                },
+            )
+            .collect::<Vec<_>>();
+        functions.sort_by(|a, b| {
+            (b.self_samples, b.total_samples)
+                .cmp(&(a.self_samples, a.total_samples))
+                .then_with(|| a.function.cmp(&b.function))
+        });
+        functions
+    }
+
+    /// The number of samples taken while each line was running, most sampled first.
+    pub fn lines(&self) -> Vec<(Location, u64)> {
+        let mut lines: FxHashMap<&Location, u64> = FxHashMap::default();
+        for (stack, samples) in &self.stacks {
+            if let Some(location) = stack.last().and_then(|frame| frame.location.as_ref()) {
+                *lines.entry(location).or_insert(0) += samples;
+            }
+        }
+
+        let mut lines = lines
+            .into_iter()
+            .map(|(location, samples)| (location.clone(), samples))
+            .collect::<Vec<_>>();
+        lines.sort_by(|a, b| {
+            b.1.cmp(&a.1)
+                .then_with(|| a.0.chunk_name.cmp(&b.0.chunk_name))
+                .then_with(|| a.0.line.cmp(&b.0.line))
+        });
+        lines
+    }
+
+    /// Formats the samples in the "folded stacks" format read by flamegraph tools, one line for
+    /// each call stack with the functions separated by semicolons followed by the number of
+    /// samples, like `main chunk;function 'f' 12`.
+    pub fn folded(&self) -> StdString {
+        let mut folded = BTreeMap::new();
+        for (stack, samples) in &self.stacks {
+            let names = stack
+                .iter()
+                .map(|frame| frame.description.replace(';', ","))
+                .collect::<Vec<_>>();
+            *folded.entry(names.join(";")).or_insert(0) += samples;
+        }
+
+        let mut output = StdString::new();
+        for (stack, samples) in folded {
+            output.push_str(&format!("{} {}\n", stack, samples));
+        }
+        output
+    }
+}
@@ -0,0 +1,124 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+use gc_arena::{Collect, GcCell, MutationContext, StaticCollect};
+
+use crate::Value;
+
+/// Values stashed by the host so that they can be kept across calls to `Lua::mutate` and
+/// `Lua::sequence`, which is otherwise impossible since values cannot leave the arena.
+///
+/// Each stashed value is kept alive until the `RegistryKey` returned for it is dropped or passed to
+/// `Registry::remove`.
+#[derive(Copy, Clone, Collect)]
+#[collect(require_copy)]
+pub struct Registry<'gc>(GcCell<'gc, RegistryState<'gc>>);
+
+#[derive(Collect)]
+#[collect(empty_drop)]
+struct RegistryState<'gc> {
+    values: Vec<Value<'gc>>,
+    // Indexes of `values` which are free to be reused
+    free: Vec<usize>,
+    // Indexes of keys which have been dropped outside of the arena, which are freed on the next
+    // mutation of the registry
+    dropped: StaticCollect<Rc<RefCell<Vec<usize>>>>,
+}
+
+/// A handle to a value in a `Registry`, which can be held outside of the arena.  Dropping the key
+/// removes the value from the registry.
+pub struct RegistryKey {
+    index: usize,
+    dropped: Rc<RefCell<Vec<usize>>>,
+}
+
+impl fmt::Debug for RegistryKey {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_tuple("RegistryKey").field(&self.index).finish()
+    }
+}
+
+impl Drop for RegistryKey {
+    fn drop(&mut self) {
+        self.dropped.borrow_mut().push(self.index);
+    }
+}
+
+impl<'gc> Registry<'gc> {
+    pub fn new(mc: MutationContext<'gc, '_>) -> Registry<'gc> {
+        Registry(GcCell::allocate(
+            mc,
+            RegistryState {
+                values: Vec::new(),
+                free: Vec::new(),
+                dropped: StaticCollect(Rc::new(RefCell::new(Vec::new()))),
+            },
+        ))
+    }
+
+    /// Stores a value in the registry, returning the key to fetch it with.
+    pub fn stash<V: Into<Value<'gc>>>(self, mc: MutationContext<'gc, '_>, value: V) -> RegistryKey {
+        let mut state = self.0.write(mc);
+        let state = &mut *state;
+        for index in state.dropped.0.borrow_mut().drain(..) {
+            state.values[index] = Value::Nil;
+            state.free.push(index);
+        }
+
+        let value = value.into();
+        let index = match state.free.pop() {
+            Some(index) => {
+                state.values[index] = value;
+                index
+            }
+            None => {
+                state.values.push(value);
+                state.values.len() - 1
+            }
+        };
+        RegistryKey {
+            index,
+            dropped: state.dropped.0.clone(),
+        }
+    }
+
+    /// Returns the value stored for the key.
+    ///
+    /// Panics if the key was returned by a different registry.
+    pub fn fetch(self, key: &RegistryKey) -> Value<'gc> {
+        let state = self.0.read();
+        check_key(&state, key);
+        state.values[key.index]
+    }
+
+    /// Replaces the value stored for the key.
+    ///
+    /// Panics if the key was returned by a different registry.
+    pub fn set<V: Into<Value<'gc>>>(
+        self,
+        mc: MutationContext<'gc, '_>,
+        key: &RegistryKey,
+        value: V,
+    ) {
+        let mut state = self.0.write(mc);
+        check_key(&state, key);
+        state.values[key.index] = value.into();
+    }
+
+    /// Removes the value stored for the key from the registry, returning it.
+    ///
+    /// Panics if the key was returned by a different registry.
+    pub fn remove(self, mc: MutationContext<'gc, '_>, key: RegistryKey) -> Value<'gc> {
+        let value = self.fetch(&key);
+        self.set(mc, &key, Value::Nil);
+        value
+    }
+}
+
+fn check_key<'gc>(state: &RegistryState<'gc>, key: &RegistryKey) {
+    assert!(
+        Rc::ptr_eq(&state.dropped.0, &key.dropped),
+        "registry key used with a different registry"
+    );
+}
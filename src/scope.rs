@@ -0,0 +1,71 @@
+use std::cell::{Cell, RefCell};
+use std::marker::PhantomData;
+use std::mem;
+use std::rc::Rc;
+
+use gc_arena::MutationContext;
+use gc_sequence as sequence;
+
+use crate::{Callback, CallbackResult, Error, RuntimeError, String, Value};
+
+type ScopedFn<'scope> = dyn 'scope
+    + for<'gc, 'a> Fn(
+        MutationContext<'gc, 'a>,
+        Vec<Value<'gc>>,
+    ) -> Result<Vec<Value<'gc>>, Error<'gc>>;
+
+/// Creates callbacks that may borrow data that only lives for `'scope`, see `Lua::scope`.
+///
+/// Every callback created by a scope is invalidated when the scope ends, after which calling it
+/// raises an error rather than calling the function it was created with.
+pub struct Scope<'scope> {
+    functions: RefCell<Vec<Rc<RefCell<Option<Box<ScopedFn<'static>>>>>>>,
+    _invariant: PhantomData<Cell<&'scope ()>>,
+}
+
+impl<'scope> Scope<'scope> {
+    pub(crate) fn new() -> Scope<'scope> {
+        Scope {
+            functions: RefCell::new(Vec::new()),
+            _invariant: PhantomData,
+        }
+    }
+
+    /// Creates a callback calling `f` with its arguments and returning the results of `f`.
+    pub fn callback<'gc, F>(&self, mc: MutationContext<'gc, '_>, f: F) -> Callback<'gc>
+    where
+        F: 'scope
+            + for<'g, 'a> Fn(
+                MutationContext<'g, 'a>,
+                Vec<Value<'g>>,
+            ) -> Result<Vec<Value<'g>>, Error<'g>>,
+    {
+        let f: Box<ScopedFn<'scope>> = Box::new(f);
+        // Safe, the function is dropped when the scope ends, and is never called afterwards
+        let f = unsafe { mem::transmute::<Box<ScopedFn<'scope>>, Box<ScopedFn<'static>>>(f) };
+        let function = Rc::new(RefCell::new(Some(f)));
+        self.functions.borrow_mut().push(function.clone());
+
+        Callback::new_sequence(mc, move |args| {
+            let function = function.clone();
+            Ok(sequence::from_fn_with(
+                args,
+                move |mc, args| match function.borrow().as_ref() {
+                    Some(f) => f(mc, args).map(CallbackResult::Return),
+                    None => Err(RuntimeError(Value::String(String::new_static(
+                        b"scoped callback called after its scope ended",
+                    )))
+                    .into()),
+                },
+            ))
+        })
+    }
+}
+
+impl<'scope> Drop for Scope<'scope> {
+    fn drop(&mut self) {
+        for function in self.functions.get_mut().drain(..) {
+            function.borrow_mut().take();
+        }
+    }
+}
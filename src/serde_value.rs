@@ -0,0 +1,850 @@
+//! Conversions between Lua values and any serde data format.
+//!
+//! Tables whose keys are exactly `1..=n` for some `n > 0` are sequences, and every other table,
+//! including an empty one, is a map.  Nil is the unit value and `None`, so a `None` element of a
+//! sequence leaves a hole in the table.  Enum variants follow serde's externally tagged
+//! representation: a unit variant is the string of its name, and any other variant is a table with
+//! the variant's name as its only key.
+//!
+//! Functions, threads and userdata have no serde equivalent and cannot be converted, nor can
+//! tables that contain themselves, directly or through other tables.
+
+use std::cell::RefCell;
+use std::error::Error as StdError;
+use std::fmt;
+use std::string::String as StdString;
+
+use gc_arena::MutationContext;
+use serde::de::{
+    self, DeserializeOwned, DeserializeSeed, EnumAccess, IntoDeserializer, MapAccess, SeqAccess,
+    VariantAccess, Visitor,
+};
+use serde::ser::{self, Serialize};
+
+use crate::{InvalidTableKey, String, Table, Value};
+
+#[derive(Debug, Clone)]
+pub enum SerdeError {
+    /// A table contains itself, directly or through other tables.
+    Cycle,
+    /// A function, thread or userdata, by type name.
+    Unsupported(&'static str),
+    Custom(StdString),
+}
+
+impl StdError for SerdeError {}
+
+impl fmt::Display for SerdeError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SerdeError::Cycle => write!(fmt, "cannot convert a table that contains itself"),
+            SerdeError::Unsupported(type_name) => {
+                write!(fmt, "cannot convert a value of type {}", type_name)
+            }
+            SerdeError::Custom(message) => write!(fmt, "{}", message),
+        }
+    }
+}
+
+impl From<InvalidTableKey> for SerdeError {
+    fn from(error: InvalidTableKey) -> SerdeError {
+        SerdeError::Custom(error.to_string())
+    }
+}
+
+impl ser::Error for SerdeError {
+    fn custom<T: fmt::Display>(message: T) -> SerdeError {
+        SerdeError::Custom(message.to_string())
+    }
+}
+
+impl de::Error for SerdeError {
+    fn custom<T: fmt::Display>(message: T) -> SerdeError {
+        SerdeError::Custom(message.to_string())
+    }
+}
+
+/// Converts any serializable Rust value into a Lua value.
+pub fn to_value<'gc, T: Serialize + ?Sized>(
+    mc: MutationContext<'gc, '_>,
+    value: &T,
+) -> Result<Value<'gc>, SerdeError> {
+    value.serialize(ValueSerializer { mc })
+}
+
+/// Converts a Lua value into any deserializable Rust value.
+pub fn from_value<'gc, T: DeserializeOwned>(value: Value<'gc>) -> Result<T, SerdeError> {
+    let stack = RefCell::new(Vec::new());
+    T::deserialize(ValueDeserializer {
+        value,
+        stack: &stack,
+    })
+}
+
+// Whether the given table is a sequence rather than a map, along with its length
+fn sequence_length<'gc>(table: Table<'gc>) -> Result<Option<i64>, SerdeError> {
+    let length = table.length();
+    if length == 0 {
+        return Ok(None);
+    }
+    let mut count = 0;
+    let mut key = Value::Nil;
+    while let Some((k, _)) = table.next(key)? {
+        count += 1;
+        if count > length {
+            return Ok(None);
+        }
+        key = k;
+    }
+    Ok(if count == length { Some(length) } else { None })
+}
+
+// Tracks the tables being converted, to detect tables that contain themselves.  Returns the depth
+// to truncate the stack to once the table has been converted.
+fn enter_table<'gc>(
+    stack: &RefCell<Vec<Table<'gc>>>,
+    table: Table<'gc>,
+) -> Result<usize, SerdeError> {
+    let mut stack = stack.borrow_mut();
+    if stack.contains(&table) {
+        return Err(SerdeError::Cycle);
+    }
+    stack.push(table);
+    Ok(stack.len() - 1)
+}
+
+/// Serializes a Lua value into any serde data format.
+impl<'gc> Serialize for Value<'gc> {
+    fn serialize<S: ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let stack = RefCell::new(Vec::new());
+        SerializeValue {
+            value: *self,
+            stack: &stack,
+        }
+        .serialize(serializer)
+    }
+}
+
+struct SerializeValue<'gc, 'a> {
+    value: Value<'gc>,
+    stack: &'a RefCell<Vec<Table<'gc>>>,
+}
+
+impl<'gc, 'a> Serialize for SerializeValue<'gc, 'a> {
+    fn serialize<S: ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::{Error as _, SerializeMap as _, SerializeSeq as _};
+
+        match self.value {
+            Value::Nil => serializer.serialize_unit(),
+            Value::Boolean(b) => serializer.serialize_bool(b),
+            Value::Integer(i) => serializer.serialize_i64(i),
+            Value::Number(n) => serializer.serialize_f64(n),
+            Value::String(s) => match std::str::from_utf8(s.as_bytes()) {
+                Ok(s) => serializer.serialize_str(s),
+                Err(_) => serializer.serialize_bytes(s.as_bytes()),
+            },
+            Value::Table(table) => {
+                let depth = enter_table(self.stack, table).map_err(S::Error::custom)?;
+                let element = |value| SerializeValue {
+                    value,
+                    stack: self.stack,
+                };
+                let result = match sequence_length(table).map_err(S::Error::custom)? {
+                    Some(length) => {
+                        let mut seq = serializer.serialize_seq(Some(length as usize))?;
+                        for i in 1..=length {
+                            seq.serialize_element(&element(table.get(i)))?;
+                        }
+                        seq.end()
+                    }
+                    None => {
+                        let mut map = serializer.serialize_map(None)?;
+                        let mut key = Value::Nil;
+                        while let Some((k, v)) = table.next(key).map_err(S::Error::custom)? {
+                            map.serialize_entry(&element(k), &element(v))?;
+                            key = k;
+                        }
+                        map.end()
+                    }
+                };
+                self.stack.borrow_mut().truncate(depth);
+                result
+            }
+            value => Err(S::Error::custom(SerdeError::Unsupported(value.type_name()))),
+        }
+    }
+}
+
+/// A serde `Serializer` that produces Lua values, used by `to_value`.
+#[derive(Clone, Copy)]
+pub struct ValueSerializer<'gc, 'a> {
+    pub mc: MutationContext<'gc, 'a>,
+}
+
+impl<'gc, 'a> ValueSerializer<'gc, 'a> {
+    fn table(self) -> SerializeTable<'gc, 'a> {
+        SerializeTable {
+            mc: self.mc,
+            table: Table::new(self.mc),
+            next_index: 1,
+            key: Value::Nil,
+        }
+    }
+
+    // A table with the variant name as its only key, which is how variants with data are
+    // represented
+    fn variant(self, variant: &'static str, value: Value<'gc>) -> Result<Value<'gc>, SerdeError> {
+        let table = Table::new(self.mc);
+        table.set(self.mc, String::new(self.mc, variant.as_bytes()), value)?;
+        Ok(Value::Table(table))
+    }
+}
+
+impl<'gc, 'a> ser::Serializer for ValueSerializer<'gc, 'a> {
+    type Ok = Value<'gc>;
+    type Error = SerdeError;
+    type SerializeSeq = SerializeTable<'gc, 'a>;
+    type SerializeTuple = SerializeTable<'gc, 'a>;
+    type SerializeTupleStruct = SerializeTable<'gc, 'a>;
+    type SerializeTupleVariant = SerializeVariant<'gc, 'a>;
+    type SerializeMap = SerializeTable<'gc, 'a>;
+    type SerializeStruct = SerializeTable<'gc, 'a>;
+    type SerializeStructVariant = SerializeVariant<'gc, 'a>;
+
+    fn serialize_bool(self, v: bool) -> Result<Value<'gc>, SerdeError> {
+        Ok(Value::Boolean(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Value<'gc>, SerdeError> {
+        Ok(Value::Integer(v.into()))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Value<'gc>, SerdeError> {
+        Ok(Value::Integer(v.into()))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Value<'gc>, SerdeError> {
+        Ok(Value::Integer(v.into()))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Value<'gc>, SerdeError> {
+        Ok(Value::Integer(v))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Value<'gc>, SerdeError> {
+        Ok(Value::Integer(v.into()))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Value<'gc>, SerdeError> {
+        Ok(Value::Integer(v.into()))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Value<'gc>, SerdeError> {
+        Ok(Value::Integer(v.into()))
+    }
+
+    // Integers too large for Lua become floats, like integer literals that are too large
+    fn serialize_u64(self, v: u64) -> Result<Value<'gc>, SerdeError> {
+        if v <= i64::max_value() as u64 {
+            Ok(Value::Integer(v as i64))
+        } else {
+            Ok(Value::Number(v as f64))
+        }
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Value<'gc>, SerdeError> {
+        Ok(Value::Number(v.into()))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Value<'gc>, SerdeError> {
+        Ok(Value::Number(v))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Value<'gc>, SerdeError> {
+        let mut buf = [0; 4];
+        self.serialize_str(v.encode_utf8(&mut buf))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Value<'gc>, SerdeError> {
+        Ok(Value::String(String::new(self.mc, v.as_bytes())))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Value<'gc>, SerdeError> {
+        Ok(Value::String(String::new(self.mc, v)))
+    }
+
+    fn serialize_none(self) -> Result<Value<'gc>, SerdeError> {
+        Ok(Value::Nil)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Value<'gc>, SerdeError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Value<'gc>, SerdeError> {
+        Ok(Value::Nil)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Value<'gc>, SerdeError> {
+        Ok(Value::Nil)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Value<'gc>, SerdeError> {
+        self.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Value<'gc>, SerdeError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Value<'gc>, SerdeError> {
+        let value = value.serialize(self)?;
+        self.variant(variant, value)
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<SerializeTable<'gc, 'a>, SerdeError> {
+        Ok(self.table())
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<SerializeTable<'gc, 'a>, SerdeError> {
+        Ok(self.table())
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<SerializeTable<'gc, 'a>, SerdeError> {
+        Ok(self.table())
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<SerializeVariant<'gc, 'a>, SerdeError> {
+        Ok(SerializeVariant {
+            serializer: self,
+            variant,
+            table: self.table(),
+        })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<SerializeTable<'gc, 'a>, SerdeError> {
+        Ok(self.table())
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<SerializeTable<'gc, 'a>, SerdeError> {
+        Ok(self.table())
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<SerializeVariant<'gc, 'a>, SerdeError> {
+        Ok(SerializeVariant {
+            serializer: self,
+            variant,
+            table: self.table(),
+        })
+    }
+}
+
+pub struct SerializeTable<'gc, 'a> {
+    mc: MutationContext<'gc, 'a>,
+    table: Table<'gc>,
+    // The index of the next element of a sequence
+    next_index: i64,
+    // The key of the map entry whose value is next
+    key: Value<'gc>,
+}
+
+impl<'gc, 'a> SerializeTable<'gc, 'a> {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        let value = value.serialize(ValueSerializer { mc: self.mc })?;
+        self.table.set(self.mc, self.next_index, value)?;
+        self.next_index += 1;
+        Ok(())
+    }
+
+    fn set<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), SerdeError> {
+        let value = value.serialize(ValueSerializer { mc: self.mc })?;
+        self.table
+            .set(self.mc, String::new(self.mc, key.as_bytes()), value)?;
+        Ok(())
+    }
+}
+
+impl<'gc, 'a> ser::SerializeSeq for SerializeTable<'gc, 'a> {
+    type Ok = Value<'gc>;
+    type Error = SerdeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value<'gc>, SerdeError> {
+        Ok(Value::Table(self.table))
+    }
+}
+
+impl<'gc, 'a> ser::SerializeTuple for SerializeTable<'gc, 'a> {
+    type Ok = Value<'gc>;
+    type Error = SerdeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value<'gc>, SerdeError> {
+        Ok(Value::Table(self.table))
+    }
+}
+
+impl<'gc, 'a> ser::SerializeTupleStruct for SerializeTable<'gc, 'a> {
+    type Ok = Value<'gc>;
+    type Error = SerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value<'gc>, SerdeError> {
+        Ok(Value::Table(self.table))
+    }
+}
+
+impl<'gc, 'a> ser::SerializeMap for SerializeTable<'gc, 'a> {
+    type Ok = Value<'gc>;
+    type Error = SerdeError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), SerdeError> {
+        self.key = key.serialize(ValueSerializer { mc: self.mc })?;
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        let value = value.serialize(ValueSerializer { mc: self.mc })?;
+        self.table.set(self.mc, self.key, value)?;
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value<'gc>, SerdeError> {
+        Ok(Value::Table(self.table))
+    }
+}
+
+impl<'gc, 'a> ser::SerializeStruct for SerializeTable<'gc, 'a> {
+    type Ok = Value<'gc>;
+    type Error = SerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), SerdeError> {
+        self.set(key, value)
+    }
+
+    fn end(self) -> Result<Value<'gc>, SerdeError> {
+        Ok(Value::Table(self.table))
+    }
+}
+
+pub struct SerializeVariant<'gc, 'a> {
+    serializer: ValueSerializer<'gc, 'a>,
+    variant: &'static str,
+    table: SerializeTable<'gc, 'a>,
+}
+
+impl<'gc, 'a> ser::SerializeTupleVariant for SerializeVariant<'gc, 'a> {
+    type Ok = Value<'gc>;
+    type Error = SerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        self.table.push(value)
+    }
+
+    fn end(self) -> Result<Value<'gc>, SerdeError> {
+        self.serializer
+            .variant(self.variant, Value::Table(self.table.table))
+    }
+}
+
+impl<'gc, 'a> ser::SerializeStructVariant for SerializeVariant<'gc, 'a> {
+    type Ok = Value<'gc>;
+    type Error = SerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), SerdeError> {
+        self.table.set(key, value)
+    }
+
+    fn end(self) -> Result<Value<'gc>, SerdeError> {
+        self.serializer
+            .variant(self.variant, Value::Table(self.table.table))
+    }
+}
+
+/// A serde `Deserializer` that reads a Lua value, used by `from_value`.
+pub struct ValueDeserializer<'gc, 'a> {
+    value: Value<'gc>,
+    // The tables being deserialized, from the outermost
+    stack: &'a RefCell<Vec<Table<'gc>>>,
+}
+
+impl<'gc, 'a> ValueDeserializer<'gc, 'a> {
+    fn with(&self, value: Value<'gc>) -> ValueDeserializer<'gc, 'a> {
+        ValueDeserializer {
+            value,
+            stack: self.stack,
+        }
+    }
+
+    fn visit_seq<'de, V: Visitor<'de>>(
+        self,
+        table: Table<'gc>,
+        length: i64,
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        let depth = enter_table(self.stack, table)?;
+        let result = visitor.visit_seq(SeqDeserializer {
+            deserializer: self.with(Value::Table(table)),
+            table,
+            index: 1,
+            length,
+        });
+        self.stack.borrow_mut().truncate(depth);
+        result
+    }
+
+    fn visit_map<'de, V: Visitor<'de>>(
+        self,
+        table: Table<'gc>,
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        let depth = enter_table(self.stack, table)?;
+        let result = visitor.visit_map(MapDeserializer {
+            deserializer: self.with(Value::Table(table)),
+            table,
+            key: Value::Nil,
+            value: None,
+        });
+        self.stack.borrow_mut().truncate(depth);
+        result
+    }
+
+    // Integral floats may be read as integers, as Lua converts them
+    fn deserialize_integer<'de, V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        match self.value {
+            Value::Number(n) if n.fract() == 0.0 && n.abs() < 9.2e18 => visitor.visit_i64(n as i64),
+            _ => de::Deserializer::deserialize_any(self, visitor),
+        }
+    }
+}
+
+macro_rules! deserialize_integer {
+    ($($method:ident)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+                self.deserialize_integer(visitor)
+            }
+        )*
+    };
+}
+
+impl<'de, 'gc, 'a> de::Deserializer<'de> for ValueDeserializer<'gc, 'a> {
+    type Error = SerdeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        match self.value {
+            Value::Nil => visitor.visit_unit(),
+            Value::Boolean(b) => visitor.visit_bool(b),
+            Value::Integer(i) => visitor.visit_i64(i),
+            Value::Number(n) => visitor.visit_f64(n),
+            Value::String(s) => match std::str::from_utf8(s.as_bytes()) {
+                Ok(s) => visitor.visit_str(s),
+                Err(_) => visitor.visit_bytes(s.as_bytes()),
+            },
+            Value::Table(table) => match sequence_length(table)? {
+                Some(length) => self.visit_seq(table, length, visitor),
+                None => self.visit_map(table, visitor),
+            },
+            value => Err(SerdeError::Unsupported(value.type_name())),
+        }
+    }
+
+    deserialize_integer! {
+        deserialize_i8 deserialize_i16 deserialize_i32 deserialize_i64
+        deserialize_u8 deserialize_u16 deserialize_u32 deserialize_u64
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        match self.value {
+            Value::Nil => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    // Any table can be read as a sequence, which stops at its length
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        match self.value {
+            Value::Table(table) => self.visit_seq(table, table.length(), visitor),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        self.deserialize_seq(visitor)
+    }
+
+    // Any table can be read as a map, including a sequence
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        match self.value {
+            Value::Table(table) => self.visit_map(table, visitor),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        match self.value {
+            Value::String(_) => visitor.visit_enum(EnumDeserializer {
+                variant: self.with(self.value),
+                value: None,
+            }),
+            Value::Table(table) => {
+                let entry = match table.next(Value::Nil)? {
+                    Some((variant, value)) if table.next(variant)?.is_none() => {
+                        Some((variant, value))
+                    }
+                    _ => None,
+                };
+                let (variant, value) = entry.ok_or_else(|| {
+                    SerdeError::Custom(
+                        "expected an enum variant table with exactly one entry".to_owned(),
+                    )
+                })?;
+                let depth = enter_table(self.stack, table)?;
+                let result = visitor.visit_enum(EnumDeserializer {
+                    variant: self.with(variant),
+                    value: Some(self.with(value)),
+                });
+                self.stack.borrow_mut().truncate(depth);
+                result
+            }
+            value => Err(de::Error::invalid_type(
+                de::Unexpected::Other(value.type_name()),
+                &"a string or a table",
+            )),
+        }
+    }
+
+    // Ignored values are skipped without being read, so they may be anything
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        visitor.visit_unit()
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool f32 f64 char str string bytes byte_buf unit unit_struct identifier
+    }
+}
+
+struct SeqDeserializer<'gc, 'a> {
+    deserializer: ValueDeserializer<'gc, 'a>,
+    table: Table<'gc>,
+    index: i64,
+    length: i64,
+}
+
+impl<'de, 'gc, 'a> SeqAccess<'de> for SeqDeserializer<'gc, 'a> {
+    type Error = SerdeError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, SerdeError> {
+        if self.index > self.length {
+            return Ok(None);
+        }
+        let value = self.table.get(self.index);
+        self.index += 1;
+        seed.deserialize(self.deserializer.with(value)).map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some((self.length - self.index + 1).max(0) as usize)
+    }
+}
+
+struct MapDeserializer<'gc, 'a> {
+    deserializer: ValueDeserializer<'gc, 'a>,
+    table: Table<'gc>,
+    key: Value<'gc>,
+    value: Option<Value<'gc>>,
+}
+
+impl<'de, 'gc, 'a> MapAccess<'de> for MapDeserializer<'gc, 'a> {
+    type Error = SerdeError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, SerdeError> {
+        match self.table.next(self.key)? {
+            Some((key, value)) => {
+                self.key = key;
+                self.value = Some(value);
+                seed.deserialize(self.deserializer.with(key)).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, SerdeError> {
+        let value = self
+            .value
+            .take()
+            .ok_or_else(|| SerdeError::Custom("map value requested before its key".to_owned()))?;
+        seed.deserialize(self.deserializer.with(value))
+    }
+}
+
+struct EnumDeserializer<'gc, 'a> {
+    variant: ValueDeserializer<'gc, 'a>,
+    value: Option<ValueDeserializer<'gc, 'a>>,
+}
+
+impl<'de, 'gc, 'a> EnumAccess<'de> for EnumDeserializer<'gc, 'a> {
+    type Error = SerdeError;
+    type Variant = VariantDeserializer<'gc, 'a>;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, VariantDeserializer<'gc, 'a>), SerdeError> {
+        let variant = seed.deserialize(self.variant)?;
+        Ok((variant, VariantDeserializer { value: self.value }))
+    }
+}
+
+struct VariantDeserializer<'gc, 'a> {
+    value: Option<ValueDeserializer<'gc, 'a>>,
+}
+
+impl<'de, 'gc, 'a> VariantAccess<'de> for VariantDeserializer<'gc, 'a> {
+    type Error = SerdeError;
+
+    fn unit_variant(self) -> Result<(), SerdeError> {
+        match self.value {
+            None => Ok(()),
+            Some(value) => de::Deserialize::deserialize(value),
+        }
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(
+        self,
+        seed: T,
+    ) -> Result<T::Value, SerdeError> {
+        match self.value {
+            Some(value) => seed.deserialize(value),
+            None => seed.deserialize(().into_deserializer()),
+        }
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        match self.value {
+            Some(value) => de::Deserializer::deserialize_seq(value, visitor),
+            None => Err(de::Error::invalid_type(
+                de::Unexpected::UnitVariant,
+                &"a tuple variant",
+            )),
+        }
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        match self.value {
+            Some(value) => de::Deserializer::deserialize_map(value, visitor),
+            None => Err(de::Error::invalid_type(
+                de::Unexpected::UnitVariant,
+                &"a struct variant",
+            )),
+        }
+    }
+}
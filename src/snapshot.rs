@@ -0,0 +1,806 @@
+//! A portable binary format for the state of a Lua instance, so that it can be saved and later
+//! restored into a fresh `Lua`.
+//!
+//! A snapshot holds everything reachable from the globals, the `package` table and the string
+//! metatable: tables, strings, closures with their upvalues and prototypes, and coroutines that are
+//! suspended in Lua code.  Callbacks and userdata belong to the host and cannot be saved, so each
+//! one is written by the name it was given with `Root::name_callback` or `Root::name_userdata`,
+//! and linked to the value with the same name when the snapshot is restored.  Values stashed in
+//! the registry and any hooks, fuel or interrupts set on threads are not saved.
+//!
+//! Like precompiled chunks, snapshots are not verified, and restoring a malformed snapshot may
+//! cause errors or panics while running it.  Only restore snapshots from trusted sources.
+
+use std::collections::VecDeque;
+use std::error::Error as StdError;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::string::String as StdString;
+
+use gc_arena::{Collect, Gc, GcCell, MutationContext};
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use crate::{
+    dump::{dump_proto, read_f64, read_i64, read_len, read_u8, undump_proto, write_len},
+    thread::{LuaReturn, MetaReturn, SavedFrame, SavedThread},
+    AnyUserData, Callback, Closure, ClosureState, Error, Function, FunctionProto, RegisterIndex,
+    Root, String, Table, TableState, Thread, UpValue, UpValueState, Value, VarCount,
+};
+
+const SIGNATURE: &[u8] = b"\x1bLuaSnapshot";
+// Must be changed whenever the format of snapshots changes
+const FORMAT_VERSION: u8 = 2;
+
+// The tables and thread of the root are always the first objects, in this order, so that they can
+// be restored into the root of the `Lua` the snapshot is restored into.
+const ROOT_OBJECTS: usize = 4;
+
+#[derive(Debug, Clone, Collect)]
+#[collect(require_static)]
+pub enum SnapshotError {
+    /// A callback that has not been given a name with `Root::name_callback` is reachable.
+    UnnamedCallback,
+    /// Userdata that has not been given a name with `Root::name_userdata` is reachable.
+    UnnamedUserData,
+    /// A thread is reachable that is running, or is suspended with a callback on its call stack,
+    /// such as a coroutine that yielded from inside `pcall`.
+    ThreadNotAtRest,
+    BadSignature,
+    VersionMismatch,
+    Malformed,
+    /// The snapshot refers to a callback or userdata by a name that nothing has been given.
+    UnknownName(StdString),
+}
+
+impl StdError for SnapshotError {}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SnapshotError::UnnamedCallback => write!(fmt, "cannot save a callback without a name"),
+            SnapshotError::UnnamedUserData => write!(fmt, "cannot save userdata without a name"),
+            SnapshotError::ThreadNotAtRest => write!(
+                fmt,
+                "cannot save a thread that is running or suspended in a callback"
+            ),
+            SnapshotError::BadSignature => write!(fmt, "not a snapshot"),
+            SnapshotError::VersionMismatch => write!(fmt, "snapshot version mismatch"),
+            SnapshotError::Malformed => write!(fmt, "malformed snapshot"),
+            SnapshotError::UnknownName(name) => {
+                write!(fmt, "snapshot refers to unknown host value '{}'", name)
+            }
+        }
+    }
+}
+
+/// Writes a snapshot of the state reachable from the given root.  The main thread must not be
+/// running, which it never is in-between calls to `Lua::mutate` or `Lua::sequence`.
+pub fn save_snapshot<'gc, W: Write>(root: Root<'gc>, w: &mut W) -> Result<(), Error<'gc>> {
+    let mut writer = Writer {
+        names: FxHashMap::default(),
+        ids: FxHashMap::default(),
+        objects: Vec::new(),
+        buf: Vec::new(),
+    };
+
+    let mut key = Value::Nil;
+    while let Some((name, value)) = root.host_names.next(key)? {
+        if let (Value::String(name), Some(object)) = (name, Object::host_value(value)) {
+            writer.names.entry(object.ptr()).or_insert(name);
+        }
+        key = name;
+    }
+
+    writer.object(Object::Table(root.globals));
+    writer.object(Object::Table(root.package));
+    writer.object(Object::Table(root.string_metatable));
+    writer.object(Object::Thread(root.main_thread));
+    let mut next = 0;
+    while next < writer.objects.len() {
+        writer.write_object(next)?;
+        next += 1;
+    }
+
+    w.write_all(SIGNATURE)?;
+    w.write_all(&[FORMAT_VERSION])?;
+    write_len(writer.objects.len(), w)?;
+    w.write_all(&writer.buf)?;
+    Ok(())
+}
+
+/// Restores a snapshot written by `save_snapshot` into the given root, replacing the contents of
+/// its globals, `package` table and string metatable and resetting its main thread.  Callbacks and
+/// userdata are linked by name to those named in the root, which is meant to be freshly created
+/// with the same standard libraries and host values as the one the snapshot was taken of.
+pub fn restore_snapshot<'gc, R: Read>(
+    mc: MutationContext<'gc, '_>,
+    root: Root<'gc>,
+    mut r: R,
+) -> Result<(), Error<'gc>> {
+    let mut signature = [0; 12];
+    r.read_exact(&mut signature)?;
+    if signature != SIGNATURE {
+        return Err(SnapshotError::BadSignature.into());
+    }
+    if read_u8(&mut r)? != FORMAT_VERSION {
+        return Err(SnapshotError::VersionMismatch.into());
+    }
+
+    let count = read_len(&mut r)?;
+    if count < ROOT_OBJECTS {
+        return Err(SnapshotError::Malformed.into());
+    }
+    let mut saved = Vec::new();
+    for _ in 0..count {
+        saved.push(read_object(mc, root, &mut r)?);
+    }
+
+    // Every object is allocated before any are filled in, since they may refer to each other in
+    // cycles.  Closures are allocated last, since they need their upvalues and prototype.
+    let mut objects = Vec::new();
+    for (id, object) in saved.iter_mut().enumerate() {
+        objects.push(match object {
+            SavedObject::Table { .. } => Some(Object::Table(match id {
+                0 => root.globals,
+                1 => root.package,
+                2 => root.string_metatable,
+                _ => Table::new(mc),
+            })),
+            SavedObject::Thread { allow_yield, .. } => Some(Object::Thread(match id {
+                3 => root.main_thread,
+                _ => {
+                    let thread = Thread::new(mc, root.string_metatable, *allow_yield);
+                    thread.set_finalizers(mc, root.finalizers);
+                    thread
+                }
+            })),
+            SavedObject::UpValue(_) => Some(Object::UpValue(UpValue(GcCell::allocate(
+                mc,
+                UpValueState::Closed(Value::Nil),
+            )))),
+            SavedObject::Proto(proto) => Some(Object::Proto(Gc::allocate(
+                mc,
+                proto.take().ok_or(SnapshotError::Malformed)?,
+            ))),
+            SavedObject::Named(name) => {
+                match Object::host_value(root.host_names.get(String::new(mc, name))) {
+                    Some(object) => Some(object),
+                    None => {
+                        return Err(SnapshotError::UnknownName(
+                            StdString::from_utf8_lossy(name).into_owned(),
+                        )
+                        .into())
+                    }
+                }
+            }
+            SavedObject::Closure { .. } => None,
+        });
+    }
+    match (&objects[0], &objects[1], &objects[2], &objects[3]) {
+        (
+            Some(Object::Table(_)),
+            Some(Object::Table(_)),
+            Some(Object::Table(_)),
+            Some(Object::Thread(_)),
+        ) => {}
+        _ => return Err(SnapshotError::Malformed.into()),
+    }
+
+    for id in 0..saved.len() {
+        if let SavedObject::Closure { proto, upvalues } = &saved[id] {
+            let proto = match objects.get(*proto) {
+                Some(Some(Object::Proto(proto))) => *proto,
+                _ => return Err(SnapshotError::Malformed.into()),
+            };
+            let upvalues = upvalues
+                .iter()
+                .map(|&upvalue| match objects.get(upvalue) {
+                    Some(Some(Object::UpValue(upvalue))) => Ok(*upvalue),
+                    _ => Err(SnapshotError::Malformed),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            if upvalues.len() != proto.upvalues.len() {
+                return Err(SnapshotError::Malformed.into());
+            }
+            objects[id] = Some(Object::Closure(Closure(Gc::allocate(
+                mc,
+                ClosureState { proto, upvalues },
+            ))));
+        }
+    }
+
+    let objects = objects
+        .into_iter()
+        .map(|object| object.expect("closure not allocated"))
+        .collect::<Vec<_>>();
+    let reader = Reader {
+        mc,
+        root,
+        objects: &objects,
+    };
+    for (object, saved) in objects.iter().zip(saved) {
+        match (*object, saved) {
+            (
+                Object::Table(table),
+                SavedObject::Table {
+                    entries,
+                    metatable,
+                    frozen,
+                },
+            ) => {
+                *table.0.write(mc) = TableState::default();
+                for (key, value) in entries {
+                    table.set(mc, reader.value(key)?, reader.value(value)?)?;
+                }
+                let metatable = match metatable {
+                    Some(metatable) => Some(reader.table(metatable)?),
+                    None => None,
+                };
+                table.set_metatable(mc, metatable);
+                root.finalizers.register(mc, table);
+                if frozen {
+                    table.freeze(mc);
+                }
+            }
+            (Object::UpValue(upvalue), SavedObject::UpValue(state)) => {
+                *upvalue.0.write(mc) = match state {
+                    SavedUpValue::Open(thread, index) => {
+                        UpValueState::Open(reader.thread(thread)?, index)
+                    }
+                    SavedUpValue::Closed(value) => UpValueState::Closed(reader.value(value)?),
+                };
+            }
+            (
+                Object::Thread(thread),
+                SavedObject::Thread {
+                    allow_yield,
+                    values,
+                    frames,
+                    open_upvalues,
+                    to_be_closed,
+                },
+            ) => {
+                let saved = SavedThread {
+                    allow_yield,
+                    values: values
+                        .into_iter()
+                        .map(|value| reader.value(value))
+                        .collect::<Result<_, _>>()?,
+                    frames: frames
+                        .into_iter()
+                        .map(|frame| {
+                            Ok(match frame {
+                                ReadFrame::Lua(frame) => frame,
+                                ReadFrame::StartCoroutine(function) => {
+                                    match reader.value(function)? {
+                                        Value::Function(function) => {
+                                            SavedFrame::StartCoroutine(function)
+                                        }
+                                        _ => return Err(SnapshotError::Malformed.into()),
+                                    }
+                                }
+                                ReadFrame::ResumeCoroutine => SavedFrame::ResumeCoroutine,
+                            })
+                        })
+                        .collect::<Result<_, Error>>()?,
+                    open_upvalues: open_upvalues
+                        .into_iter()
+                        .map(|(index, upvalue)| match objects.get(upvalue) {
+                            Some(Object::UpValue(upvalue)) => Ok((index, *upvalue)),
+                            _ => Err(SnapshotError::Malformed),
+                        })
+                        .collect::<Result<_, _>>()?,
+                    to_be_closed,
+                };
+                if saved
+                    .open_upvalues
+                    .windows(2)
+                    .any(|pair| pair[0].0 >= pair[1].0)
+                {
+                    return Err(SnapshotError::Malformed.into());
+                }
+                thread.restore(mc, saved);
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+// Gives every callback and userdata reachable from the globals through tables a name, if it does
+// not have one already, which is its path from the globals like `string.format`.  Tables are
+// searched breadth first, so that each value is named by its shortest path.
+pub(crate) fn name_host_values<'gc>(mc: MutationContext<'gc, '_>, root: Root<'gc>) {
+    let mut named = FxHashSet::default();
+    let mut key = Value::Nil;
+    while let Ok(Some((name, value))) = root.host_names.next(key) {
+        if let Some(object) = Object::host_value(value) {
+            named.insert(object.ptr());
+        }
+        key = name;
+    }
+
+    let mut visited = FxHashSet::default();
+    visited.insert(root.globals);
+    let mut queue = VecDeque::new();
+    queue.push_back((root.globals, StdString::new()));
+    while let Some((table, path)) = queue.pop_front() {
+        let mut key = Value::Nil;
+        while let Ok(Some((k, value))) = table.next(key) {
+            key = k;
+            let name = match k {
+                Value::String(name) => StdString::from_utf8_lossy(name.as_bytes()).into_owned(),
+                Value::Integer(i) => i.to_string(),
+                _ => continue,
+            };
+            let name = if path.is_empty() {
+                name
+            } else {
+                format!("{}.{}", path, name)
+            };
+
+            if let Value::Table(table) = value {
+                if visited.insert(table) {
+                    queue.push_back((table, name));
+                }
+            } else if let Some(object) = Object::host_value(value) {
+                let name = String::new(mc, name.as_bytes());
+                if !named.contains(&object.ptr()) && root.host_names.get(name) == Value::Nil {
+                    root.host_names.set(mc, name, value).unwrap();
+                    named.insert(object.ptr());
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Object<'gc> {
+    Table(Table<'gc>),
+    Closure(Closure<'gc>),
+    UpValue(UpValue<'gc>),
+    Thread(Thread<'gc>),
+    Proto(Gc<'gc, FunctionProto<'gc>>),
+    Callback(Callback<'gc>),
+    UserData(AnyUserData<'gc>),
+}
+
+impl<'gc> Object<'gc> {
+    fn ptr(self) -> *const () {
+        match self {
+            Object::Table(table) => table.0.as_ptr() as *const (),
+            Object::Closure(closure) => Gc::as_ptr(closure.0) as *const (),
+            Object::UpValue(upvalue) => upvalue.0.as_ptr() as *const (),
+            Object::Thread(thread) => thread.0.as_ptr() as *const (),
+            Object::Proto(proto) => Gc::as_ptr(proto) as *const (),
+            Object::Callback(callback) => Gc::as_ptr(callback.0) as *const (),
+            Object::UserData(userdata) => userdata.0.as_ptr() as *const (),
+        }
+    }
+
+    // The callback or userdata that the given value is, which are saved by name
+    fn host_value(value: Value<'gc>) -> Option<Object<'gc>> {
+        match value {
+            Value::Function(Function::Callback(callback)) => Some(Object::Callback(callback)),
+            Value::UserData(userdata) => Some(Object::UserData(userdata)),
+            _ => None,
+        }
+    }
+}
+
+struct Writer<'gc> {
+    names: FxHashMap<*const (), String<'gc>>,
+    ids: FxHashMap<*const (), usize>,
+    objects: Vec<Object<'gc>>,
+    buf: Vec<u8>,
+}
+
+impl<'gc> Writer<'gc> {
+    // Returns the id of the given object, adding it to the objects to write if it is new
+    fn object(&mut self, object: Object<'gc>) -> usize {
+        let objects = &mut self.objects;
+        *self.ids.entry(object.ptr()).or_insert_with(|| {
+            objects.push(object);
+            objects.len() - 1
+        })
+    }
+
+    fn len(&mut self, len: usize) {
+        write_len(len, &mut self.buf).unwrap();
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.len(bytes.len());
+        self.buf.extend(bytes);
+    }
+
+    fn value(&mut self, value: Value<'gc>) -> Result<(), SnapshotError> {
+        match value {
+            Value::Nil => self.buf.push(0),
+            Value::Boolean(b) => self.buf.extend(&[1, b as u8]),
+            Value::Integer(i) => {
+                self.buf.push(2);
+                self.buf.extend(&i.to_le_bytes());
+            }
+            Value::Number(n) => {
+                self.buf.push(3);
+                self.buf.extend(&n.to_bits().to_le_bytes());
+            }
+            Value::String(s) => {
+                self.buf.push(4);
+                self.bytes(s.as_bytes());
+            }
+            Value::Table(table) => self.reference(Object::Table(table)),
+            Value::Function(Function::Closure(closure)) => self.reference(Object::Closure(closure)),
+            Value::Function(Function::Callback(callback)) => {
+                let callback = Object::Callback(callback);
+                if !self.names.contains_key(&callback.ptr()) {
+                    return Err(SnapshotError::UnnamedCallback);
+                }
+                self.reference(callback)
+            }
+            Value::Thread(thread) => self.reference(Object::Thread(thread)),
+            Value::UserData(userdata) => {
+                let userdata = Object::UserData(userdata);
+                if !self.names.contains_key(&userdata.ptr()) {
+                    return Err(SnapshotError::UnnamedUserData);
+                }
+                self.reference(userdata)
+            }
+        }
+        Ok(())
+    }
+
+    fn reference(&mut self, object: Object<'gc>) {
+        let id = self.object(object);
+        self.buf.push(5);
+        self.len(id);
+    }
+
+    fn write_object(&mut self, id: usize) -> Result<(), Error<'gc>> {
+        match self.objects[id] {
+            Object::Table(table) => {
+                self.buf.push(0);
+                let mut entries = Vec::new();
+                let mut key = Value::Nil;
+                while let Some((k, value)) = table.next(key)? {
+                    entries.push((k, value));
+                    key = k;
+                }
+                self.len(entries.len());
+                for (key, value) in entries {
+                    self.value(key)?;
+                    self.value(value)?;
+                }
+                match table.metatable() {
+                    Some(metatable) => {
+                        let metatable = self.object(Object::Table(metatable));
+                        self.buf.push(1);
+                        self.len(metatable);
+                    }
+                    None => self.buf.push(0),
+                }
+                self.buf.push(table.is_frozen() as u8);
+            }
+            Object::Closure(closure) => {
+                self.buf.push(1);
+                let proto = self.object(Object::Proto(closure.0.proto));
+                self.len(proto);
+                self.len(closure.0.upvalues.len());
+                for &upvalue in &closure.0.upvalues {
+                    let upvalue = self.object(Object::UpValue(upvalue));
+                    self.len(upvalue);
+                }
+            }
+            Object::UpValue(upvalue) => {
+                self.buf.push(2);
+                match *upvalue.0.read() {
+                    UpValueState::Open(thread, index) => {
+                        let thread = self.object(Object::Thread(thread));
+                        self.buf.push(0);
+                        self.len(thread);
+                        self.len(index);
+                    }
+                    UpValueState::Closed(value) => {
+                        self.buf.push(1);
+                        self.value(value)?;
+                    }
+                }
+            }
+            Object::Thread(thread) => {
+                self.buf.push(3);
+                let saved = thread.save().ok_or(SnapshotError::ThreadNotAtRest)?;
+                self.buf.push(saved.allow_yield as u8);
+                self.len(saved.values.len());
+                for value in saved.values {
+                    self.value(value)?;
+                }
+                self.len(saved.frames.len());
+                for frame in saved.frames {
+                    self.frame(frame)?;
+                }
+                self.len(saved.open_upvalues.len());
+                for (index, upvalue) in saved.open_upvalues {
+                    let upvalue = self.object(Object::UpValue(upvalue));
+                    self.len(index);
+                    self.len(upvalue);
+                }
+                self.len(saved.to_be_closed.len());
+                for index in saved.to_be_closed {
+                    self.len(index);
+                }
+            }
+            Object::Proto(proto) => {
+                self.buf.push(4);
+                dump_proto(&proto, false, &mut self.buf)?;
+            }
+            object @ Object::Callback(_) | object @ Object::UserData(_) => {
+                self.buf.push(5);
+                let name = self.names[&object.ptr()];
+                self.bytes(name.as_bytes());
+            }
+        }
+        Ok(())
+    }
+
+    fn frame(&mut self, frame: SavedFrame<'gc>) -> Result<(), SnapshotError> {
+        match frame {
+            SavedFrame::Lua {
+                bottom,
+                base,
+                is_variable,
+                pc,
+                stack_size,
+                expected_returns,
+                is_tail_call,
+            } => {
+                self.buf.push(0);
+                self.len(bottom);
+                self.len(base);
+                self.buf.push(is_variable as u8);
+                self.len(pc);
+                self.len(stack_size);
+                self.buf.push(is_tail_call as u8);
+                match expected_returns {
+                    None => self.buf.push(0),
+                    Some(LuaReturn::Normal(count)) => {
+                        self.buf.extend(&[1, count.to_constant().unwrap_or(255)])
+                    }
+                    Some(LuaReturn::Meta(MetaReturn::None)) => self.buf.push(2),
+                    Some(LuaReturn::Meta(MetaReturn::Register(register))) => {
+                        self.buf.extend(&[3, register.0])
+                    }
+                    Some(LuaReturn::Meta(MetaReturn::SkipIf(skip))) => {
+                        self.buf.extend(&[4, skip as u8])
+                    }
+                    Some(LuaReturn::Meta(MetaReturn::JumpIf(jump_if, jump))) => {
+                        self.buf.extend(&[5, jump_if as u8, jump as u8])
+                    }
+                    Some(LuaReturn::TailCall) => self.buf.push(6),
+                }
+            }
+            SavedFrame::StartCoroutine(function) => {
+                self.buf.push(1);
+                self.value(Value::Function(function))?;
+            }
+            SavedFrame::ResumeCoroutine => self.buf.push(2),
+        }
+        Ok(())
+    }
+}
+
+enum SavedValue {
+    Nil,
+    Boolean(bool),
+    Integer(i64),
+    Number(f64),
+    String(Vec<u8>),
+    Object(usize),
+}
+
+enum SavedUpValue {
+    Open(usize, usize),
+    Closed(SavedValue),
+}
+
+enum ReadFrame<'gc> {
+    Lua(SavedFrame<'gc>),
+    StartCoroutine(SavedValue),
+    ResumeCoroutine,
+}
+
+enum SavedObject<'gc> {
+    Table {
+        entries: Vec<(SavedValue, SavedValue)>,
+        metatable: Option<usize>,
+        frozen: bool,
+    },
+    Closure {
+        proto: usize,
+        upvalues: Vec<usize>,
+    },
+    UpValue(SavedUpValue),
+    Thread {
+        allow_yield: bool,
+        values: Vec<SavedValue>,
+        frames: Vec<ReadFrame<'gc>>,
+        open_upvalues: Vec<(usize, usize)>,
+        to_be_closed: Vec<usize>,
+    },
+    Proto(Option<FunctionProto<'gc>>),
+    // A callback or userdata, by name
+    Named(Vec<u8>),
+}
+
+fn read_object<'gc, R: Read>(
+    mc: MutationContext<'gc, '_>,
+    root: Root<'gc>,
+    r: &mut R,
+) -> Result<SavedObject<'gc>, Error<'gc>> {
+    Ok(match read_u8(r)? {
+        0 => {
+            let mut entries = Vec::new();
+            for _ in 0..read_len(r)? {
+                entries.push((read_value(r)?, read_value(r)?));
+            }
+            let metatable = match read_u8(r)? {
+                0 => None,
+                _ => Some(read_len(r)?),
+            };
+            SavedObject::Table {
+                entries,
+                metatable,
+                frozen: read_u8(r)? != 0,
+            }
+        }
+        1 => {
+            let proto = read_len(r)?;
+            let mut upvalues = Vec::new();
+            for _ in 0..read_len(r)? {
+                upvalues.push(read_len(r)?);
+            }
+            SavedObject::Closure { proto, upvalues }
+        }
+        2 => SavedObject::UpValue(match read_u8(r)? {
+            0 => SavedUpValue::Open(read_len(r)?, read_len(r)?),
+            _ => SavedUpValue::Closed(read_value(r)?),
+        }),
+        3 => {
+            let allow_yield = read_u8(r)? != 0;
+            let mut values = Vec::new();
+            for _ in 0..read_len(r)? {
+                values.push(read_value(r)?);
+            }
+            let mut frames = Vec::new();
+            for _ in 0..read_len(r)? {
+                frames.push(read_frame(r)?);
+            }
+            let mut open_upvalues = Vec::new();
+            for _ in 0..read_len(r)? {
+                open_upvalues.push((read_len(r)?, read_len(r)?));
+            }
+            let mut to_be_closed = Vec::new();
+            for _ in 0..read_len(r)? {
+                to_be_closed.push(read_len(r)?);
+            }
+            SavedObject::Thread {
+                allow_yield,
+                values,
+                frames,
+                open_upvalues,
+                to_be_closed,
+            }
+        }
+        4 => SavedObject::Proto(Some(undump_proto(mc, root.interned_strings, r)?)),
+        5 => SavedObject::Named(read_bytes(r)?),
+        _ => return Err(SnapshotError::Malformed.into()),
+    })
+}
+
+fn read_frame<'gc, R: Read>(r: &mut R) -> Result<ReadFrame<'gc>, Error<'gc>> {
+    Ok(match read_u8(r)? {
+        0 => {
+            let bottom = read_len(r)?;
+            let base = read_len(r)?;
+            let is_variable = read_u8(r)? != 0;
+            let pc = read_len(r)?;
+            let stack_size = read_len(r)?;
+            let is_tail_call = read_u8(r)? != 0;
+            let expected_returns = match read_u8(r)? {
+                0 => None,
+                1 => Some(LuaReturn::Normal(match read_u8(r)? {
+                    255 => VarCount::variable(),
+                    count => VarCount::constant(count),
+                })),
+                2 => Some(LuaReturn::Meta(MetaReturn::None)),
+                3 => Some(LuaReturn::Meta(MetaReturn::Register(RegisterIndex(
+                    read_u8(r)?,
+                )))),
+                4 => Some(LuaReturn::Meta(MetaReturn::SkipIf(read_u8(r)? != 0))),
+                5 => Some(LuaReturn::Meta(MetaReturn::JumpIf(
+                    read_u8(r)? != 0,
+                    read_u8(r)? as i8,
+                ))),
+                6 => Some(LuaReturn::TailCall),
+                _ => return Err(SnapshotError::Malformed.into()),
+            };
+            ReadFrame::Lua(SavedFrame::Lua {
+                bottom,
+                base,
+                is_variable,
+                pc,
+                stack_size,
+                expected_returns,
+                is_tail_call,
+            })
+        }
+        1 => ReadFrame::StartCoroutine(read_value(r)?),
+        2 => ReadFrame::ResumeCoroutine,
+        _ => return Err(SnapshotError::Malformed.into()),
+    })
+}
+
+fn read_value<'gc, R: Read>(r: &mut R) -> Result<SavedValue, Error<'gc>> {
+    Ok(match read_u8(r)? {
+        0 => SavedValue::Nil,
+        1 => SavedValue::Boolean(read_u8(r)? != 0),
+        2 => SavedValue::Integer(read_i64(r)?),
+        3 => SavedValue::Number(read_f64(r)?),
+        4 => SavedValue::String(read_bytes(r)?),
+        5 => SavedValue::Object(read_len(r)?),
+        _ => return Err(SnapshotError::Malformed.into()),
+    })
+}
+
+fn read_bytes<R: Read>(r: &mut R) -> Result<Vec<u8>, io::Error> {
+    let len = read_len(r)?;
+    let mut bytes = Vec::new();
+    r.take(len as u64).read_to_end(&mut bytes)?;
+    if bytes.len() != len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(bytes)
+}
+
+struct Reader<'gc, 'a, 'b> {
+    mc: MutationContext<'gc, 'b>,
+    root: Root<'gc>,
+    objects: &'a [Object<'gc>],
+}
+
+impl<'gc, 'a, 'b> Reader<'gc, 'a, 'b> {
+    fn value(&self, value: SavedValue) -> Result<Value<'gc>, SnapshotError> {
+        Ok(match value {
+            SavedValue::Nil => Value::Nil,
+            SavedValue::Boolean(b) => Value::Boolean(b),
+            SavedValue::Integer(i) => Value::Integer(i),
+            SavedValue::Number(n) => Value::Number(n),
+            SavedValue::String(s) => {
+                Value::String(self.root.interned_strings.new_string(self.mc, &s))
+            }
+            SavedValue::Object(id) => match self.objects.get(id) {
+                Some(Object::Table(table)) => Value::Table(*table),
+                Some(Object::Closure(closure)) => Value::Function(Function::Closure(*closure)),
+                Some(Object::Callback(callback)) => Value::Function(Function::Callback(*callback)),
+                Some(Object::UserData(userdata)) => Value::UserData(*userdata),
+                Some(Object::Thread(thread)) => Value::Thread(*thread),
+                _ => return Err(SnapshotError::Malformed),
+            },
+        })
+    }
+
+    fn table(&self, id: usize) -> Result<Table<'gc>, SnapshotError> {
+        match self.objects.get(id) {
+            Some(Object::Table(table)) => Ok(*table),
+            _ => Err(SnapshotError::Malformed),
+        }
+    }
+
+    fn thread(&self, id: usize) -> Result<Thread<'gc>, SnapshotError> {
+        match self.objects.get(id) {
+            Some(Object::Thread(thread)) => Ok(*thread),
+            _ => Err(SnapshotError::Malformed),
+        }
+    }
+}
@@ -1,27 +1,92 @@
-use std::io::{self, Write};
+use std::cell::RefCell;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::string::String as StdString;
 
-use gc_arena::MutationContext;
-use gc_sequence as sequence;
+use gc_arena::{Gc, MutationContext, StaticCollect};
+use gc_sequence::{self as sequence, SequenceResultExt};
 
 use crate::{
-    Callback, CallbackResult, Continuation, Root, RuntimeError, String, Table, TypeError, Value,
+    compile_with_coverage,
+    meta_ops::{self, MetaResult},
+    undump_function,
+    value::trim_whitespace,
+    Callback, CallbackResult, Closure, Continuation, Error, Function, InternedStringSet,
+    MetaOperatorError, Root, RuntimeError, String, Table, TypeError, Value, BINARY_CHUNK_SIGNATURE,
 };
 
 pub fn load_base<'gc>(mc: MutationContext<'gc, '_>, root: Root<'gc>, env: Table<'gc>) {
+    env.set(mc, String::new_static(b"_G"), env).unwrap();
+
     env.set(
         mc,
         String::new_static(b"print"),
-        Callback::new_immediate(mc, |args| {
-            let mut stdout = io::stdout();
-            for i in 0..args.len() {
-                args[i].display(&mut stdout)?;
-                if i != args.len() - 1 {
-                    stdout.write_all(&b"\t"[..])?;
+        Callback::new_immediate_with(mc, root.output, |output, args| {
+            print_values(*output, args, 0)
+        }),
+    )
+    .unwrap();
+
+    env.set(
+        mc,
+        String::new_static(b"tostring"),
+        Callback::new_sequence(mc, |args| {
+            Ok(sequence::from_fn_with(args, |mc, args| {
+                if args.is_empty() {
+                    return Err(RuntimeError(Value::String(String::new_static(
+                        b"Missing argument to tostring",
+                    )))
+                    .into());
                 }
-            }
-            stdout.write_all(&b"\n"[..])?;
-            stdout.flush()?;
-            Ok(CallbackResult::Return(vec![]))
+
+                match meta_ops::tostring(mc, args[0]) {
+                    MetaResult::Value(v) => Ok(CallbackResult::Return(vec![v])),
+                    MetaResult::Call(function, args) => Ok(CallbackResult::TailCall {
+                        function,
+                        args,
+                        continuation: Continuation::new_immediate(|res| {
+                            match res?.get(0).cloned().unwrap_or(Value::Nil) {
+                                v @ Value::String(_) => Ok(CallbackResult::Return(vec![v])),
+                                _ => Err(MetaOperatorError::ToStringNotString.into()),
+                            }
+                        }),
+                    }),
+                }
+            }))
+        }),
+    )
+    .unwrap();
+
+    env.set(
+        mc,
+        String::new_static(b"tonumber"),
+        Callback::new_sequence(mc, |args| {
+            Ok(sequence::from_fn_with(args, |mc, args| {
+                let value = check_any(mc, &args, 0, "tonumber")?;
+                let base = match args.get(1).cloned().unwrap_or(Value::Nil) {
+                    Value::Nil => {
+                        return Ok(CallbackResult::Return(vec![value
+                            .to_numeric()
+                            .unwrap_or(Value::Nil)]));
+                    }
+                    base => base
+                        .to_integer()
+                        .ok_or_else(|| bad_argument_type(mc, &args, 1, "tonumber", "number"))?,
+                };
+                if base < 2 || base > 36 {
+                    return Err(bad_argument(mc, 1, "tonumber", "base out of range"));
+                }
+                let string = match value {
+                    Value::String(string) => string,
+                    _ => return Err(bad_argument_type(mc, &args, 0, "tonumber", "string")),
+                };
+                Ok(CallbackResult::Return(vec![read_integer_in_base(
+                    &string,
+                    base as u32,
+                )
+                .map(Value::Integer)
+                .unwrap_or(Value::Nil)]))
+            }))
         }),
     )
     .unwrap();
@@ -29,9 +94,23 @@ pub fn load_base<'gc>(mc: MutationContext<'gc, '_>, root: Root<'gc>, env: Table<
     env.set(
         mc,
         String::new_static(b"error"),
-        Callback::new_immediate(mc, |args| {
-            let err = args.get(0).cloned().unwrap_or(Value::Nil);
-            Err(RuntimeError(err).into())
+        Callback::new_sequence(mc, |args| {
+            Ok(sequence::from_fn_with(args, |mc, args| {
+                let err = args.get(0).cloned().unwrap_or(Value::Nil);
+                let level = match args.get(1).cloned().unwrap_or(Value::Nil) {
+                    Value::Nil => 1,
+                    level => level
+                        .to_integer()
+                        .ok_or_else(|| bad_argument_type(mc, &args, 1, "error", "number"))?,
+                };
+                // Only string messages are given a position
+                match err {
+                    Value::String(_) if level > 0 => {
+                        Err(Error::LeveledError(RuntimeError(err), level as usize))
+                    }
+                    _ => Err(RuntimeError(err).into()),
+                }
+            }))
         }),
     )
     .unwrap();
@@ -39,18 +118,21 @@ pub fn load_base<'gc>(mc: MutationContext<'gc, '_>, root: Root<'gc>, env: Table<
     env.set(
         mc,
         String::new_static(b"assert"),
-        Callback::new_immediate(mc, |args| {
-            let v = args.get(0).cloned().unwrap_or(Value::Nil);
-            let message = args
-                .get(1)
-                .cloned()
-                .unwrap_or(Value::String(String::new_static(b"assertion failed!")));
-
-            if v.to_bool() {
-                Ok(CallbackResult::Return(args))
-            } else {
-                Err(RuntimeError(message).into())
-            }
+        Callback::new_sequence(mc, |args| {
+            Ok(sequence::from_fn_with(args, |mc, args| {
+                let v = check_any(mc, &args, 0, "assert")?;
+                // An explicit nil message is raised as is
+                let message = args
+                    .get(1)
+                    .cloned()
+                    .unwrap_or(Value::String(String::new_static(b"assertion failed!")));
+
+                if v.to_bool() {
+                    Ok(CallbackResult::Return(args))
+                } else {
+                    Err(RuntimeError(message).into())
+                }
+            }))
         }),
     )
     .unwrap();
@@ -74,31 +156,47 @@ pub fn load_base<'gc>(mc: MutationContext<'gc, '_>, root: Root<'gc>, env: Table<
             Ok(CallbackResult::TailCall {
                 function,
                 args,
-                continuation: Continuation::new_sequence_with(
-                    *interned_strings,
-                    move |interned_strings, res| {
-                        Ok(sequence::from_fn_with(
-                            (res, interned_strings),
-                            |mc, (res, interned_strings)| {
-                                Ok(CallbackResult::Return(match res {
-                                    Ok(mut res) => {
-                                        res.insert(0, Value::Boolean(true));
-                                        res
-                                    }
-                                    Err(err) => vec![
-                                        Value::Boolean(false),
-                                        err.to_value(mc, interned_strings),
-                                    ],
-                                }))
-                            },
-                        ))
-                    },
-                ),
+                continuation: protected_continuation(*interned_strings),
             })
         }),
     )
     .unwrap();
 
+    env.set(
+        mc,
+        String::new_static(b"xpcall"),
+        Callback::new_sequence_with(mc, root.interned_strings, |interned_strings, args| {
+            Ok(sequence::from_fn_with(
+                (*interned_strings, args),
+                |mc, (interned_strings, mut args)| {
+                    let function = match args.get(0).cloned().unwrap_or(Value::Nil) {
+                        Value::Function(function) => function,
+                        value => {
+                            return Err(TypeError {
+                                expected: "function",
+                                found: value.type_name(),
+                            }
+                            .into());
+                        }
+                    };
+                    let handler = match args.get(1).cloned().unwrap_or(Value::Nil) {
+                        Value::Function(handler) => handler,
+                        _ => return Err(bad_argument_type(mc, &args, 1, "xpcall", "function")),
+                    };
+
+                    args.drain(0..2);
+                    Ok(CallbackResult::TailCallWithHandler {
+                        function,
+                        args,
+                        handler,
+                        continuation: protected_continuation(interned_strings),
+                    })
+                },
+            ))
+        }),
+    )
+    .unwrap();
+
     env.set(
         mc,
         String::new_static(b"type"),
@@ -119,20 +217,780 @@ pub fn load_base<'gc>(mc: MutationContext<'gc, '_>, root: Root<'gc>, env: Table<
     env.set(
         mc,
         String::new_static(b"select"),
-        Callback::new_immediate(mc, |args| {
-            match args.get(0).cloned().unwrap_or(Value::Nil).to_integer() {
-                Some(n) if n >= 1 && (n as usize) <= args.len() => Ok(CallbackResult::Return(
-                    args[n as usize..args.len()].to_vec(),
-                )),
-                // This is required because Rust will panic if the starting slice index is out of
-                // range by more than one
-                Some(n) if n as usize > args.len() => Ok(CallbackResult::Return(vec![])),
-                _ => Err(RuntimeError(Value::String(String::new_static(
-                    b"Bad argument to select",
+        Callback::new_sequence(mc, |args| {
+            Ok(sequence::from_fn_with(args, |mc, args| {
+                let n = args.len() as i64;
+                let index = match args.get(0).cloned().unwrap_or(Value::Nil) {
+                    Value::String(s) if s.as_bytes().first() == Some(&b'#') => {
+                        return Ok(CallbackResult::Return(vec![Value::Integer(n - 1)]));
+                    }
+                    v => v
+                        .to_integer()
+                        .ok_or_else(|| bad_argument_type(mc, &args, 0, "select", "number"))?,
+                };
+                // Negative indexes count back from the last argument
+                let index = if index < 0 { n + index } else { index.min(n) };
+                if index < 1 {
+                    return Err(bad_argument(mc, 0, "select", "index out of range"));
+                }
+                Ok(CallbackResult::Return(args[index as usize..].to_vec()))
+            }))
+        }),
+    )
+    .unwrap();
+
+    env.set(
+        mc,
+        String::new_static(b"getmetatable"),
+        Callback::new_immediate_with(mc, root.string_metatable, |string_metatable, args| {
+            let metatable = match args.get(0).cloned().unwrap_or(Value::Nil) {
+                Value::Table(table) => table.metatable(),
+                Value::UserData(userdata) => userdata.metatable(),
+                Value::String(_) => Some(*string_metatable),
+                _ => None,
+            };
+            let metatable = match metatable {
+                Some(metatable) => match metatable.get(String::new_static(b"__metatable")) {
+                    Value::Nil => Value::Table(metatable),
+                    protected => protected,
+                },
+                None => Value::Nil,
+            };
+            Ok(CallbackResult::Return(vec![metatable]))
+        }),
+    )
+    .unwrap();
+
+    env.set(
+        mc,
+        String::new_static(b"setmetatable"),
+        Callback::new_sequence_with(mc, root, |root, args| {
+            Ok(sequence::from_fn_with((*root, args), |mc, (root, args)| {
+                let table = check_table(mc, &args, 0, "setmetatable")?;
+                // Like PUC-Rio Lua, a missing metatable is an error rather than nil
+                let metatable = match args.get(1) {
+                    Some(Value::Table(metatable)) => Some(*metatable),
+                    Some(Value::Nil) => None,
+                    _ => {
+                        return Err(bad_argument(mc, 1, "setmetatable", "nil or table expected"));
+                    }
+                };
+
+                if table.is_frozen() {
+                    return Err(RuntimeError(Value::String(String::new_static(
+                        b"cannot change the metatable of a read-only table",
+                    )))
+                    .into());
+                }
+
+                if let Some(current) = table.metatable() {
+                    if current.get(String::new_static(b"__metatable")) != Value::Nil {
+                        return Err(RuntimeError(Value::String(String::new_static(
+                            b"cannot change a protected metatable",
+                        )))
+                        .into());
+                    }
+                }
+
+                table.set_metatable(mc, metatable);
+                root.finalizers.register(mc, table);
+                Ok(CallbackResult::Return(vec![Value::Table(table)]))
+            }))
+        }),
+    )
+    .unwrap();
+
+    let next = Callback::new_sequence(mc, |args| {
+        Ok(sequence::from_fn_with(args, |mc, args| {
+            let table = check_table(mc, &args, 0, "next")?;
+            let key = args.get(1).cloned().unwrap_or(Value::Nil);
+            Ok(CallbackResult::Return(match table.next(key)? {
+                Some((key, value)) => vec![key, value],
+                None => vec![Value::Nil],
+            }))
+        }))
+    });
+    env.set(mc, String::new_static(b"next"), next).unwrap();
+
+    env.set(
+        mc,
+        String::new_static(b"pairs"),
+        Callback::new_sequence_with(mc, next, |next, args| {
+            Ok(sequence::from_fn_with((*next, args), |mc, (next, args)| {
+                let value = check_any(mc, &args, 0, "pairs")?;
+                match meta_ops::get_metamethod(value, b"__pairs") {
+                    Value::Function(function) => Ok(CallbackResult::TailCall {
+                        function,
+                        args: vec![value],
+                        continuation: Continuation::new_immediate(|res| {
+                            let mut res = res?;
+                            res.resize(3, Value::Nil);
+                            Ok(CallbackResult::Return(res))
+                        }),
+                    }),
+                    _ => Ok(CallbackResult::Return(vec![
+                        Value::Function(Function::Callback(next)),
+                        value,
+                        Value::Nil,
+                    ])),
+                }
+            }))
+        }),
+    )
+    .unwrap();
+
+    // Like PUC-Rio Lua 5.3, `ipairs` goes through the `__index` metamethod and stops at the first
+    // nil value.
+    let ipairs_next =
+        Callback::new_sequence_with(mc, root.string_metatable, |string_metatable, args| {
+            Ok(sequence::from_fn_with(
+                (*string_metatable, args),
+                |mc, (string_metatable, args)| {
+                    let table = args.get(0).cloned().unwrap_or(Value::Nil);
+                    let index = match args.get(1).cloned().unwrap_or(Value::Nil) {
+                        Value::Integer(i) => i.wrapping_add(1),
+                        _ => return Err(bad_argument_type(mc, &args, 1, "ipairs", "integer")),
+                    };
+                    match meta_ops::index(string_metatable, table, Value::Integer(index))? {
+                        MetaResult::Value(value) => {
+                            Ok(CallbackResult::Return(ipairs_result(index, value)))
+                        }
+                        MetaResult::Call(function, args) => Ok(CallbackResult::TailCall {
+                            function,
+                            args,
+                            continuation: Continuation::new_immediate(move |res| {
+                                let value = res?.get(0).cloned().unwrap_or(Value::Nil);
+                                Ok(CallbackResult::Return(ipairs_result(index, value)))
+                            }),
+                        }),
+                    }
+                },
+            ))
+        });
+
+    env.set(
+        mc,
+        String::new_static(b"ipairs"),
+        Callback::new_sequence_with(mc, ipairs_next, |ipairs_next, args| {
+            Ok(sequence::from_fn_with(
+                (*ipairs_next, args),
+                |mc, (ipairs_next, args)| {
+                    let value = check_any(mc, &args, 0, "ipairs")?;
+                    Ok(CallbackResult::Return(vec![
+                        Value::Function(Function::Callback(ipairs_next)),
+                        value,
+                        Value::Integer(0),
+                    ]))
+                },
+            ))
+        }),
+    )
+    .unwrap();
+
+    env.set(
+        mc,
+        String::new_static(b"rawequal"),
+        Callback::new_sequence(mc, |args| {
+            Ok(sequence::from_fn_with(args, |mc, args| {
+                let a = check_any(mc, &args, 0, "rawequal")?;
+                let b = check_any(mc, &args, 1, "rawequal")?;
+                Ok(CallbackResult::Return(vec![Value::Boolean(a == b)]))
+            }))
+        }),
+    )
+    .unwrap();
+
+    env.set(
+        mc,
+        String::new_static(b"rawlen"),
+        Callback::new_sequence(mc, |args| {
+            Ok(sequence::from_fn_with(args, |mc, args| {
+                let len = match args.get(0).cloned() {
+                    Some(Value::Table(table)) => table.length(),
+                    Some(Value::String(string)) => string.len(),
+                    _ => {
+                        return Err(bad_argument_type(mc, &args, 0, "rawlen", "table or string"));
+                    }
+                };
+                Ok(CallbackResult::Return(vec![Value::Integer(len)]))
+            }))
+        }),
+    )
+    .unwrap();
+
+    env.set(
+        mc,
+        String::new_static(b"rawget"),
+        Callback::new_sequence(mc, |args| {
+            Ok(sequence::from_fn_with(args, |mc, args| {
+                let table = check_table(mc, &args, 0, "rawget")?;
+                let key = check_any(mc, &args, 1, "rawget")?;
+                Ok(CallbackResult::Return(vec![table.get(key)]))
+            }))
+        }),
+    )
+    .unwrap();
+
+    env.set(
+        mc,
+        String::new_static(b"rawset"),
+        Callback::new_sequence(mc, |args| {
+            Ok(sequence::from_fn_with(args, |mc, args| {
+                let table = check_table(mc, &args, 0, "rawset")?;
+                let key = check_any(mc, &args, 1, "rawset")?;
+                let value = check_any(mc, &args, 2, "rawset")?;
+                table.set(mc, key, value)?;
+                Ok(CallbackResult::Return(vec![Value::Table(table)]))
+            }))
+        }),
+    )
+    .unwrap();
+
+    env.set(
+        mc,
+        String::new_static(b"load"),
+        Callback::new_sequence_with(mc, root, |root, args| {
+            Ok(sequence::from_fn_with((*root, args), |mc, (root, args)| {
+                let chunk = check_any(mc, &args, 0, "load")?;
+                let name = match args.get(1).cloned().unwrap_or(Value::Nil) {
+                    Value::Nil => None,
+                    name => Some(
+                        name.to_string(mc)
+                            .ok_or_else(|| bad_argument_type(mc, &args, 1, "load", "string"))?,
+                    ),
+                };
+                let mode = match args.get(2).cloned().unwrap_or(Value::Nil) {
+                    Value::Nil => String::new_static(b"bt"),
+                    mode => mode
+                        .to_string(mc)
+                        .ok_or_else(|| bad_argument_type(mc, &args, 2, "load", "string"))?,
+                };
+                // Like PUC-Rio Lua, an explicit nil environment is used as is
+                let env = args.get(3).cloned().unwrap_or(Value::Table(root.globals));
+
+                match chunk {
+                    Value::Function(reader) => {
+                        let name = name.unwrap_or_else(|| String::new_static(b"=(load)"));
+                        Ok(read_chunk(root, reader, name, mode, env, Vec::new()))
+                    }
+                    // Like PUC-Rio Lua, a string chunk is named by its own source by default
+                    chunk => match chunk.to_string(mc) {
+                        Some(chunk) => Ok(CallbackResult::Return(load_chunk(
+                            mc,
+                            root,
+                            chunk.as_bytes(),
+                            name.unwrap_or(chunk),
+                            mode,
+                            env,
+                        ))),
+                        None => Err(bad_argument_type(mc, &args, 0, "load", "string")),
+                    },
+                }
+            }))
+        }),
+    )
+    .unwrap();
+
+    env.set(
+        mc,
+        String::new_static(b"collectgarbage"),
+        Callback::new_sequence_with(mc, root.collector, |collector, args| {
+            Ok(
+                sequence::from_fn_with((*collector, args), |mc, (collector, args)| {
+                    let collector = &collector.0;
+                    let option = match args.get(0).cloned().unwrap_or(Value::Nil) {
+                        Value::Nil => String::new_static(b"collect"),
+                        option => option.to_string(mc).ok_or_else(|| {
+                            bad_argument_type(mc, &args, 0, "collectgarbage", "string")
+                        })?,
+                    };
+
+                    // Collection happens between steps, so results are returned a step later
+                    Ok(match option.as_bytes() {
+                        b"collect" => {
+                            collector.collect_requested.set(true);
+                            vec![Value::Integer(0)]
+                        }
+                        // The end of a collection cycle cannot be observed, so a step is always a
+                        // full cycle
+                        b"step" => {
+                            collector.collect_requested.set(true);
+                            vec![Value::Boolean(true)]
+                        }
+                        b"count" => vec![Value::Number(
+                            collector.total_allocated.get() as f64 / 1024.0,
+                        )],
+                        b"isrunning" => vec![Value::Boolean(!collector.stopped.get())],
+                        b"stop" => {
+                            collector.stopped.set(true);
+                            vec![Value::Integer(0)]
+                        }
+                        b"restart" => {
+                            collector.stopped.set(false);
+                            vec![Value::Integer(0)]
+                        }
+                        _ => {
+                            let message = format!(
+                                "invalid option '{}'",
+                                StdString::from_utf8_lossy(option.as_bytes())
+                            );
+                            return Err(bad_argument(mc, 0, "collectgarbage", &message));
+                        }
+                    })
+                })
+                .and_then(|_, res| Ok(CallbackResult::Return(res))),
+            )
+        }),
+    )
+    .unwrap();
+
+    env.set(
+        mc,
+        String::new_static(b"loadfile"),
+        Callback::new_sequence_with(mc, root, |root, args| {
+            Ok(sequence::from_fn_with((*root, args), |mc, (root, args)| {
+                let filename = opt_filename(mc, &args, "loadfile")?;
+                let mode = match args.get(1).cloned().unwrap_or(Value::Nil) {
+                    Value::Nil => String::new_static(b"bt"),
+                    mode => mode
+                        .to_string(mc)
+                        .ok_or_else(|| bad_argument_type(mc, &args, 1, "loadfile", "string"))?,
+                };
+                let env = args.get(2).cloned().unwrap_or(Value::Table(root.globals));
+
+                Ok(CallbackResult::Return(load_file(
+                    mc,
+                    root,
+                    filename.as_ref().map(|f| f.as_str()),
+                    mode,
+                    env,
                 )))
-                .into()),
-            }
+            }))
         }),
     )
     .unwrap();
+
+    env.set(
+        mc,
+        String::new_static(b"dofile"),
+        Callback::new_sequence_with(mc, root, |root, args| {
+            Ok(sequence::from_fn_with((*root, args), |mc, (root, args)| {
+                let filename = opt_filename(mc, &args, "dofile")?;
+                let function = match load_file(
+                    mc,
+                    root,
+                    filename.as_ref().map(|f| f.as_str()),
+                    String::new_static(b"bt"),
+                    Value::Table(root.globals),
+                )
+                .as_slice()
+                {
+                    [Value::Function(function)] => *function,
+                    [_, error] => return Err(RuntimeError(*error).into()),
+                    _ => unreachable!(),
+                };
+
+                Ok(CallbackResult::TailCall {
+                    function,
+                    args: Vec::new(),
+                    continuation: Continuation::new_immediate(|res| {
+                        Ok(CallbackResult::Return(res?))
+                    }),
+                })
+            }))
+        }),
+    )
+    .unwrap();
+}
+
+// Calls the reader function given to `load` until it returns nil or an empty string, then loads the
+// concatenation of all the pieces it returned.
+fn read_chunk<'gc>(
+    root: Root<'gc>,
+    reader: Function<'gc>,
+    name: String<'gc>,
+    mode: String<'gc>,
+    env: Value<'gc>,
+    chunk: Vec<u8>,
+) -> CallbackResult<'gc> {
+    CallbackResult::TailCall {
+        function: reader,
+        args: Vec::new(),
+        continuation: Continuation::new_sequence_with(
+            (root, reader, name, mode, env, chunk),
+            |(root, reader, name, mode, env, chunk), res| {
+                Ok(sequence::from_fn_with(
+                    (root, reader, name, mode, env, chunk, res),
+                    |mc, (root, reader, name, mode, env, mut chunk, res)| {
+                        let piece = match res {
+                            Ok(res) => res.get(0).cloned().unwrap_or(Value::Nil),
+                            Err(err) if !err.is_catchable() => return Err(err),
+                            Err(err) => {
+                                return Ok(CallbackResult::Return(vec![
+                                    Value::Nil,
+                                    err.to_value(mc, root.interned_strings),
+                                ]));
+                            }
+                        };
+
+                        match piece {
+                            Value::Nil => {}
+                            Value::String(piece) if piece.as_bytes().is_empty() => {}
+                            Value::String(piece) => {
+                                chunk.extend_from_slice(piece.as_bytes());
+                                return Ok(read_chunk(root, reader, name, mode, env, chunk));
+                            }
+                            _ => {
+                                return Ok(CallbackResult::Return(vec![
+                                    Value::Nil,
+                                    Value::String(String::new_static(
+                                        b"reader function must return a string",
+                                    )),
+                                ]));
+                            }
+                        }
+
+                        Ok(CallbackResult::Return(load_chunk(
+                            mc, root, &chunk, name, mode, env,
+                        )))
+                    },
+                ))
+            },
+        ),
+    }
+}
+
+// Compiles a chunk given to `load` into a function with the given environment.  Like PUC-Rio Lua,
+// errors are returned as nil followed by the error message.
+pub(crate) fn load_chunk<'gc>(
+    mc: MutationContext<'gc, '_>,
+    root: Root<'gc>,
+    chunk: &[u8],
+    name: String<'gc>,
+    mode: String<'gc>,
+    env: Value<'gc>,
+) -> Vec<Value<'gc>> {
+    let binary = chunk.starts_with(BINARY_CHUNK_SIGNATURE);
+    let (kind, allowed) = if binary {
+        ("binary", mode.as_bytes().contains(&b'b'))
+    } else {
+        ("text", mode.as_bytes().contains(&b't'))
+    };
+
+    let res = if allowed && binary {
+        undump_function(mc, root.interned_strings, chunk)
+            .map(|proto| Closure::new_precompiled(mc, proto, env))
+    } else if allowed {
+        compile_with_coverage(
+            mc,
+            root.interned_strings,
+            &StdString::from_utf8_lossy(name.as_bytes()),
+            chunk,
+            root.coverage.0.borrow().as_ref(),
+        )
+        .and_then(|proto| {
+            let closure = Closure::new(mc, proto, Some(root.globals))?;
+            closure.set_environment(mc, env);
+            Ok(closure)
+        })
+    } else {
+        let message = format!(
+            "attempt to load a {} chunk (mode is '{}')",
+            kind,
+            StdString::from_utf8_lossy(mode.as_bytes())
+        );
+        Err(RuntimeError(Value::String(String::new(mc, message.as_bytes()))).into())
+    };
+
+    match res {
+        Ok(closure) => vec![Value::Function(Function::Closure(closure))],
+        Err(err) => vec![Value::Nil, err.to_value(mc, root.interned_strings)],
+    }
+}
+
+// Loads the file with the given name, or standard input if there is none, with the same results as
+// `load_chunk`.  Errors reading the file are returned the same way as compile errors.
+fn load_file<'gc>(
+    mc: MutationContext<'gc, '_>,
+    root: Root<'gc>,
+    filename: Option<&str>,
+    mode: String<'gc>,
+    env: Value<'gc>,
+) -> Vec<Value<'gc>> {
+    let (chunk, name) = match filename {
+        Some(filename) => (read_file(filename), format!("@{}", filename)),
+        None => {
+            let mut chunk = Vec::new();
+            let res = io::stdin()
+                .read_to_end(&mut chunk)
+                .map(|_| skip_comment(chunk));
+            (res, "=stdin".to_owned())
+        }
+    };
+
+    match chunk {
+        Ok(chunk) => load_chunk(
+            mc,
+            root,
+            &chunk,
+            String::new(mc, name.as_bytes()),
+            mode,
+            env,
+        ),
+        Err(err) => {
+            let message = format!("cannot open {}: {}", filename.unwrap_or("stdin"), err);
+            vec![
+                Value::Nil,
+                Value::String(String::new(mc, message.as_bytes())),
+            ]
+        }
+    }
+}
+
+// Reads a Lua source or precompiled file.  Like PUC-Rio Lua, a first line starting with '#' (such as
+// a Unix shebang line) is skipped.
+pub(crate) fn read_file(filename: &str) -> io::Result<Vec<u8>> {
+    fs::read(filename).map(skip_comment)
+}
+
+// Removes a first line starting with '#' from a chunk, keeping its newline so that line numbers are
+// unchanged.
+fn skip_comment(mut chunk: Vec<u8>) -> Vec<u8> {
+    if chunk.first() == Some(&b'#') {
+        let end = chunk
+            .iter()
+            .position(|&c| c == b'\n')
+            .unwrap_or(chunk.len());
+        chunk.drain(..end);
+    }
+    chunk
+}
+
+// Returns the optional file name argument of `loadfile` and `dofile`.
+fn opt_filename<'gc>(
+    mc: MutationContext<'gc, '_>,
+    args: &[Value<'gc>],
+    function: &str,
+) -> Result<Option<StdString>, Error<'gc>> {
+    match args.get(0).cloned().unwrap_or(Value::Nil) {
+        Value::Nil => Ok(None),
+        filename => match filename.to_string(mc) {
+            Some(filename) => Ok(Some(
+                StdString::from_utf8_lossy(filename.as_bytes()).into_owned(),
+            )),
+            None => Err(bad_argument_type(mc, args, 0, function, "string")),
+        },
+    }
+}
+
+// Prints the given values separated by tabs to the given output, calling the `__tostring`
+// metamethod of any value that has one.  Values before `start` have already been converted.
+fn print_values<'gc>(
+    output: Gc<'gc, StaticCollect<RefCell<Box<dyn Write>>>>,
+    values: Vec<Value<'gc>>,
+    start: usize,
+) -> Result<CallbackResult<'gc>, Error<'gc>> {
+    for i in start..values.len() {
+        if let Value::Function(function) = meta_ops::get_metamethod(values[i], b"__tostring") {
+            let value = values[i];
+            return Ok(CallbackResult::TailCall {
+                function,
+                args: vec![value],
+                continuation: Continuation::new_immediate_with(
+                    (output, values),
+                    move |(output, mut values), res| match res?
+                        .get(0)
+                        .cloned()
+                        .unwrap_or(Value::Nil)
+                    {
+                        v @ Value::String(_) => {
+                            values[i] = v;
+                            print_values(output, values, i + 1)
+                        }
+                        _ => Err(MetaOperatorError::ToStringNotString.into()),
+                    },
+                ),
+            });
+        }
+    }
+
+    let mut output = output.0.borrow_mut();
+    for i in 0..values.len() {
+        values[i].display(&mut *output)?;
+        if i != values.len() - 1 {
+            output.write_all(&b"\t"[..])?;
+        }
+    }
+    output.write_all(&b"\n"[..])?;
+    output.flush()?;
+    Ok(CallbackResult::Return(vec![]))
+}
+
+// The results of the `ipairs` iterator for the value at the given index, which end the loop if the
+// value is nil.
+fn ipairs_result<'gc>(index: i64, value: Value<'gc>) -> Vec<Value<'gc>> {
+    if value == Value::Nil {
+        vec![Value::Nil]
+    } else {
+        vec![Value::Integer(index), value]
+    }
+}
+
+// Reads an integer written in the given base, with digits above 9 written as letters in either case.
+// Like Lua numerals, the integer wraps around if it does not fit.
+fn read_integer_in_base(s: &[u8], base: u32) -> Option<i64> {
+    let s = trim_whitespace(s);
+    let (is_neg, s) = match s.first() {
+        Some(b'-') => (true, &s[1..]),
+        Some(b'+') => (false, &s[1..]),
+        _ => (false, s),
+    };
+    if s.is_empty() {
+        return None;
+    }
+
+    let mut i: i64 = 0;
+    for &c in s {
+        let d = (c as char).to_digit(base)?;
+        i = i.wrapping_mul(base as i64).wrapping_add(d as i64);
+    }
+    Some(if is_neg { i.wrapping_neg() } else { i })
+}
+
+// The continuation of `pcall` and `xpcall`, which returns true followed by the results of the
+// protected call, or false and the error value.
+fn protected_continuation<'gc>(interned_strings: InternedStringSet<'gc>) -> Continuation<'gc> {
+    Continuation::new_sequence_with(interned_strings, |interned_strings, res| {
+        Ok(sequence::from_fn_with(
+            (res, interned_strings),
+            |mc, (res, interned_strings)| {
+                Ok(CallbackResult::Return(match res {
+                    Ok(mut res) => {
+                        res.insert(0, Value::Boolean(true));
+                        res
+                    }
+                    Err(err) if !err.is_catchable() => return Err(err),
+                    Err(err) => vec![Value::Boolean(false), err.to_value(mc, interned_strings)],
+                }))
+            },
+        ))
+    })
+}
+
+// Returns the argument at index `n`, which may be any value (including nil) but must be present.
+pub(crate) fn check_any<'gc>(
+    mc: MutationContext<'gc, '_>,
+    args: &[Value<'gc>],
+    n: usize,
+    function: &str,
+) -> Result<Value<'gc>, Error<'gc>> {
+    args.get(n)
+        .cloned()
+        .ok_or_else(|| bad_argument(mc, n, function, "value expected"))
+}
+
+// Returns the argument at index `n`, which must be a table.
+pub(crate) fn check_table<'gc>(
+    mc: MutationContext<'gc, '_>,
+    args: &[Value<'gc>],
+    n: usize,
+    function: &str,
+) -> Result<Table<'gc>, Error<'gc>> {
+    match args.get(n) {
+        Some(Value::Table(table)) => Ok(*table),
+        _ => Err(bad_argument_type(mc, args, n, function, "table")),
+    }
+}
+
+// Returns the argument at index `n`, which must be a number or a string convertible to one.
+pub(crate) fn check_number<'gc>(
+    mc: MutationContext<'gc, '_>,
+    args: &[Value<'gc>],
+    n: usize,
+    function: &str,
+) -> Result<f64, Error<'gc>> {
+    args.get(n)
+        .and_then(|value| value.to_number())
+        .ok_or_else(|| bad_argument_type(mc, args, n, function, "number"))
+}
+
+// Returns the argument at index `n`, which must be a string or a number converted to a string.
+pub(crate) fn check_string<'gc>(
+    mc: MutationContext<'gc, '_>,
+    args: &[Value<'gc>],
+    n: usize,
+    function: &str,
+) -> Result<String<'gc>, Error<'gc>> {
+    args.get(n)
+        .and_then(|value| value.to_string(mc))
+        .ok_or_else(|| bad_argument_type(mc, args, n, function, "string"))
+}
+
+// An error for the argument at index `n` not being of the expected type, with the same message as
+// PUC-Rio Lua.
+pub(crate) fn bad_argument_type<'gc>(
+    mc: MutationContext<'gc, '_>,
+    args: &[Value<'gc>],
+    n: usize,
+    function: &str,
+    expected: &str,
+) -> Error<'gc> {
+    let found = match args.get(n) {
+        Some(value) => value.type_name(),
+        None => "no value",
+    };
+    bad_argument(
+        mc,
+        n,
+        function,
+        &format!("{} expected, got {}", expected, found),
+    )
+}
+
+// An error for the argument at index `n` with the same message as PUC-Rio Lua.  Arguments are
+// numbered from 1 in the message.
+pub(crate) fn bad_argument<'gc>(
+    mc: MutationContext<'gc, '_>,
+    n: usize,
+    function: &str,
+    message: &str,
+) -> Error<'gc> {
+    let message = format!("bad argument #{} to '{}' ({})", n + 1, function, message);
+    RuntimeError(Value::String(String::new(mc, message.as_bytes()))).into()
+}
+
+// Returns the argument at index `n`, which must be an integer.
+pub(crate) fn check_integer<'gc>(
+    mc: MutationContext<'gc, '_>,
+    args: &[Value<'gc>],
+    n: usize,
+    function: &str,
+) -> Result<i64, Error<'gc>> {
+    match args.get(n) {
+        None | Some(Value::Nil) => Err(bad_argument_type(mc, args, n, function, "number")),
+        Some(_) => opt_integer(mc, args, n, function, 0),
+    }
+}
+
+// Returns the argument at index `n` as an integer, or `default` if it is nil or absent.
+pub(crate) fn opt_integer<'gc>(
+    mc: MutationContext<'gc, '_>,
+    args: &[Value<'gc>],
+    n: usize,
+    function: &str,
+    default: i64,
+) -> Result<i64, Error<'gc>> {
+    match args.get(n).cloned().unwrap_or(Value::Nil) {
+        Value::Nil => Ok(default),
+        value => match value.to_integer() {
+            Some(i) => Ok(i),
+            None if value.to_number().is_some() => Err(bad_argument(
+                mc,
+                n,
+                function,
+                "number has no integer representation",
+            )),
+            None => Err(bad_argument_type(mc, args, n, function, "number")),
+        },
+    }
 }
@@ -2,8 +2,8 @@ use gc_arena::MutationContext;
 use gc_sequence::{self as sequence, SequenceExt, SequenceResultExt};
 
 use crate::{
-    Callback, CallbackResult, Root, RuntimeError, String, Table, Thread, ThreadMode,
-    ThreadSequence, TypeError, Value,
+    thread::CoroutineSequence, Callback, CallbackResult, Error, Root, RuntimeError, String, Table,
+    Thread, ThreadMode, ThreadStatus, TypeError, Value,
 };
 
 pub fn load_coroutine<'gc>(mc: MutationContext<'gc, '_>, root: Root<'gc>, env: Table<'gc>) {
@@ -13,22 +13,106 @@ pub fn load_coroutine<'gc>(mc: MutationContext<'gc, '_>, root: Root<'gc>, env: T
         .set(
             mc,
             String::new_static(b"create"),
-            Callback::new_sequence(mc, |args| {
-                let function = match args.get(0).cloned().unwrap_or(Value::Nil) {
-                    Value::Function(function) => function,
-                    value => {
-                        return Err(TypeError {
-                            expected: "function",
-                            found: value.type_name(),
+            Callback::new_sequence_with_thread(
+                mc,
+                root.string_metatable,
+                |string_metatable, current, args| {
+                    let function = match args.get(0).cloned().unwrap_or(Value::Nil) {
+                        Value::Function(function) => function,
+                        value => {
+                            return Err(TypeError {
+                                expected: "function",
+                                found: value.type_name(),
+                            }
+                            .into());
                         }
+                    };
+
+                    Ok(sequence::from_fn_with(
+                        (*string_metatable, current, function),
+                        |mc, (string_metatable, current, function)| {
+                            let thread = Thread::new(mc, string_metatable, true);
+                            thread.inherit_from(mc, current);
+                            thread.start_suspended(mc, function).unwrap();
+                            Ok(CallbackResult::Return(vec![Value::Thread(thread)]))
+                        },
+                    ))
+                },
+            ),
+        )
+        .unwrap();
+
+    coroutine
+        .set(
+            mc,
+            String::new_static(b"close"),
+            Callback::new_sequence_with_thread(
+                mc,
+                root.interned_strings,
+                |interned_strings, current, args| {
+                    let thread = match args.get(0).cloned().unwrap_or(Value::Nil) {
+                        Value::Thread(closure) => closure,
+                        value => {
+                            return Err(TypeError {
+                                expected: "thread",
+                                found: value.type_name(),
+                            }
+                            .into());
+                        }
+                    };
+
+                    if let ThreadMode::Running | ThreadMode::OutOfFuel = thread.mode() {
+                        return Err(RuntimeError(Value::String(String::new_static(
+                            if thread == current {
+                                b"cannot close a running coroutine"
+                            } else {
+                                b"cannot close a normal coroutine"
+                            },
+                        )))
                         .into());
                     }
-                };
 
-                Ok(sequence::from_fn_with(function, |mc, function| {
-                    let thread = Thread::new(mc, true);
-                    thread.start_suspended(mc, function).unwrap();
-                    Ok(CallbackResult::Return(vec![Value::Thread(thread)]))
+                    Ok(sequence::from_fn_with(thread, |mc, thread| {
+                        Ok(if thread.mode() == ThreadMode::Suspended {
+                            thread.close(mc).unwrap();
+                            CoroutineSequence(thread).boxed()
+                        } else {
+                            // Closing a dead coroutine only returns the error it died with, if any
+                            match thread.take_error(mc) {
+                                Some(error) => sequence::err(RuntimeError(error).into()).boxed(),
+                                None => sequence::ok(Vec::new()).boxed(),
+                            }
+                        })
+                    })
+                    .flatten_ok()
+                    .then_with(
+                        (*interned_strings, thread),
+                        |mc, (interned_strings, thread), res| {
+                            // An error raised while closing is only returned by this close
+                            thread.take_error(mc);
+                            Ok(CallbackResult::Return(match res {
+                                Ok(_) => vec![Value::Boolean(true)],
+                                Err(err) if !err.is_catchable() => return Err(err),
+                                Err(err) => {
+                                    vec![Value::Boolean(false), err.to_value(mc, interned_strings)]
+                                }
+                            }))
+                        },
+                    ))
+                },
+            ),
+        )
+        .unwrap();
+
+    coroutine
+        .set(
+            mc,
+            String::new_static(b"isyieldable"),
+            Callback::new_sequence_with_thread(mc, (), |_, thread, _| {
+                Ok(sequence::from_fn_with(thread, |_, thread| {
+                    Ok(CallbackResult::Return(vec![Value::Boolean(
+                        thread.is_yieldable(),
+                    )]))
                 }))
             }),
         )
@@ -54,12 +138,9 @@ pub fn load_coroutine<'gc>(mc: MutationContext<'gc, '_>, root: Root<'gc>, env: T
                 Ok(
                     sequence::from_fn_with((thread, args), |mc, (thread, args)| {
                         if let Ok(()) = thread.resume(mc, &args) {
-                            Ok(ThreadSequence(thread))
+                            Ok(CoroutineSequence(thread))
                         } else {
-                            Err(RuntimeError(Value::String(String::new_static(
-                                b"cannot resume thread",
-                            )))
-                            .into())
+                            Err(resume_error(thread))
                         }
                     })
                     .flatten_ok()
@@ -71,6 +152,7 @@ pub fn load_coroutine<'gc>(mc: MutationContext<'gc, '_>, root: Root<'gc>, env: T
                                     res.insert(0, Value::Boolean(true));
                                     res
                                 }
+                                Err(err) if !err.is_catchable() => return Err(err),
                                 Err(err) => {
                                     vec![Value::Boolean(false), err.to_value(mc, interned_strings)]
                                 }
@@ -82,11 +164,29 @@ pub fn load_coroutine<'gc>(mc: MutationContext<'gc, '_>, root: Root<'gc>, env: T
         )
         .unwrap();
 
+    coroutine
+        .set(
+            mc,
+            String::new_static(b"running"),
+            Callback::new_sequence_with_thread(mc, root.main_thread, |main_thread, thread, _| {
+                Ok(sequence::from_fn_with(
+                    (*main_thread, thread),
+                    |_, (main_thread, thread)| {
+                        Ok(CallbackResult::Return(vec![
+                            Value::Thread(thread),
+                            Value::Boolean(thread == main_thread),
+                        ]))
+                    },
+                ))
+            }),
+        )
+        .unwrap();
+
     coroutine
         .set(
             mc,
             String::new_static(b"status"),
-            Callback::new_immediate(mc, |args| {
+            Callback::new_sequence_with_thread(mc, (), |_, current, args| {
                 let thread = match args.get(0).cloned().unwrap_or(Value::Nil) {
                     Value::Thread(closure) => closure,
                     value => {
@@ -98,19 +198,76 @@ pub fn load_coroutine<'gc>(mc: MutationContext<'gc, '_>, root: Root<'gc>, env: T
                     }
                 };
 
-                Ok(CallbackResult::Return(vec![Value::String(
-                    // TODO: When the current thread is available again for callbacks, whether or
-                    // not the active thread matches will determine 'normal' from 'running'.
-                    String::new_static(match thread.mode() {
-                        ThreadMode::Stopped | ThreadMode::Results => b"dead",
-                        ThreadMode::Running => b"running",
-                        ThreadMode::Suspended => b"suspended",
-                    }),
-                )]))
+                Ok(sequence::from_fn_with(
+                    (current, thread),
+                    |_, (current, thread)| {
+                        Ok(CallbackResult::Return(vec![Value::String(
+                            String::new_static(match thread.status() {
+                                ThreadStatus::Dead => b"dead",
+                                // A running thread that is not the current one has resumed
+                                // another coroutine.
+                                ThreadStatus::Running if thread == current => b"running",
+                                ThreadStatus::Running => b"normal",
+                                ThreadStatus::Suspended => b"suspended",
+                            }),
+                        )]))
+                    },
+                ))
             }),
         )
         .unwrap();
 
+    coroutine
+        .set(
+            mc,
+            String::new_static(b"wrap"),
+            Callback::new_sequence_with_thread(
+                mc,
+                root.string_metatable,
+                |string_metatable, current, args| {
+                    let function = match args.get(0).cloned().unwrap_or(Value::Nil) {
+                        Value::Function(function) => function,
+                        value => {
+                            return Err(TypeError {
+                                expected: "function",
+                                found: value.type_name(),
+                            }
+                            .into());
+                        }
+                    };
+
+                    Ok(sequence::from_fn_with(
+                        (*string_metatable, current, function),
+                        |mc, (string_metatable, current, function)| {
+                            let thread = Thread::new(mc, string_metatable, true);
+                            thread.inherit_from(mc, current);
+                            thread.start_suspended(mc, function).unwrap();
+
+                            // Unlike `coroutine.resume`, errors from the thread are raised in the
+                            // caller rather than returned as a status.
+                            let wrapper =
+                                Callback::new_sequence_with(mc, thread, |thread, args| {
+                                    Ok(sequence::from_fn_with(
+                                        (*thread, args),
+                                        |mc, (thread, args)| {
+                                            if let Ok(()) = thread.resume(mc, &args) {
+                                                Ok(CoroutineSequence(thread))
+                                            } else {
+                                                Err(resume_error(thread))
+                                            }
+                                        },
+                                    )
+                                    .flatten_ok()
+                                    .map_ok(CallbackResult::Return))
+                                });
+                            Ok(CallbackResult::Return(vec![wrapper.into()]))
+                        },
+                    ))
+                },
+            ),
+        )
+        .unwrap();
+
     coroutine
         .set(
             mc,
@@ -122,3 +279,12 @@ pub fn load_coroutine<'gc>(mc: MutationContext<'gc, '_>, root: Root<'gc>, env: T
     env.set(mc, String::new_static(b"coroutine"), coroutine)
         .unwrap();
 }
+
+// The error for resuming a thread that cannot be resumed, like PUC-Rio Lua's.
+fn resume_error<'gc>(thread: Thread<'gc>) -> Error<'gc> {
+    RuntimeError(Value::String(String::new_static(match thread.status() {
+        ThreadStatus::Dead => b"cannot resume dead coroutine",
+        _ => b"cannot resume non-suspended coroutine",
+    })))
+    .into()
+}
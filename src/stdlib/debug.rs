@@ -0,0 +1,440 @@
+use std::convert::TryFrom;
+use std::string::String as StdString;
+
+use gc_arena::MutationContext;
+use gc_sequence as sequence;
+
+use crate::{
+    error::chunk_id, Callback, CallbackResult, Error, Function, Hook, Root, StackFrame, String,
+    Table, Thread, UpValue, Value,
+};
+
+use super::base::{
+    bad_argument, bad_argument_type, check_any, check_integer, check_string, opt_integer,
+};
+
+// The fields returned by `getinfo` when no options are given, all of those supported.
+const GETINFO_OPTIONS: &[u8] = b"flnStu";
+
+pub fn load_debug<'gc>(mc: MutationContext<'gc, '_>, _: Root<'gc>, env: Table<'gc>) {
+    let debug = Table::new(mc);
+
+    debug
+        .set(
+            mc,
+            String::new_static(b"traceback"),
+            Callback::new_sequence_with_thread(mc, (), |_, thread, args| {
+                Ok(sequence::from_fn_with(
+                    (thread, args),
+                    |mc, (thread, args)| {
+                        // An optional first argument selects another thread
+                        let (target, arg) = match args.get(0) {
+                            Some(&Value::Thread(target)) => (target, 1),
+                            _ => (thread, 0),
+                        };
+                        let message = match args.get(arg).cloned().unwrap_or(Value::Nil) {
+                            Value::Nil => None,
+                            value => match value.to_string(mc) {
+                                Some(message) => Some(message),
+                                // Any other message is returned untouched
+                                None => return Ok(CallbackResult::Return(vec![value])),
+                            },
+                        };
+                        // Level 0 of the current thread is `traceback` itself
+                        let default_level = if target == thread { 1 } else { 0 };
+                        let level = opt_integer(mc, &args, arg + 1, "traceback", default_level)?;
+
+                        let mut traceback = Vec::new();
+                        if let Some(message) = message {
+                            traceback.extend(message.as_bytes());
+                            traceback.push(b'\n');
+                        }
+                        match usize::try_from(level) {
+                            Ok(level) => traceback.extend(target.traceback(level).as_bytes()),
+                            Err(_) => traceback.extend(b"stack traceback:"),
+                        }
+                        Ok(CallbackResult::Return(vec![Value::String(String::new(
+                            mc, &traceback,
+                        ))]))
+                    },
+                ))
+            }),
+        )
+        .unwrap();
+
+    debug
+        .set(
+            mc,
+            String::new_static(b"getinfo"),
+            Callback::new_sequence_with_thread(mc, (), |_, thread, args| {
+                Ok(sequence::from_fn_with(
+                    (thread, args),
+                    |mc, (thread, args)| {
+                        let (target, arg) = match args.get(0) {
+                            Some(&Value::Thread(target)) => (target, 1),
+                            _ => (thread, 0),
+                        };
+                        let options = match args.get(arg + 1).cloned().unwrap_or(Value::Nil) {
+                            Value::Nil => String::new_static(GETINFO_OPTIONS),
+                            _ => check_string(mc, &args, arg + 1, "getinfo")?,
+                        };
+
+                        let info = match args.get(arg).cloned().unwrap_or(Value::Nil) {
+                            Value::Function(function) => {
+                                function_info(mc, options.as_bytes(), Some(function), None)
+                            }
+                            _ => {
+                                let level = check_integer(mc, &args, arg, "getinfo")?;
+                                // Level 0 of the current thread is `getinfo` itself
+                                let frame = usize::try_from(level)
+                                    .ok()
+                                    .and_then(|level| target.call_stack().into_iter().nth(level));
+                                match frame {
+                                    Some(frame) => function_info(
+                                        mc,
+                                        options.as_bytes(),
+                                        frame.closure.map(Function::Closure),
+                                        Some(&frame),
+                                    ),
+                                    None => return Ok(CallbackResult::Return(vec![Value::Nil])),
+                                }
+                            }
+                        };
+
+                        match info {
+                            Some(info) => Ok(CallbackResult::Return(vec![Value::Table(info)])),
+                            None => Err(bad_argument(mc, arg + 1, "getinfo", "invalid option")),
+                        }
+                    },
+                ))
+            }),
+        )
+        .unwrap();
+
+    debug
+        .set(
+            mc,
+            String::new_static(b"sethook"),
+            Callback::new_sequence_with_thread(mc, (), |_, thread, args| {
+                Ok(sequence::from_fn_with(
+                    (thread, args),
+                    |mc, (thread, args)| {
+                        let (target, arg) = match args.get(0) {
+                            Some(&Value::Thread(target)) => (target, 1),
+                            _ => (thread, 0),
+                        };
+                        let hook = match args.get(arg).cloned().unwrap_or(Value::Nil) {
+                            Value::Nil => None,
+                            Value::Function(function) => {
+                                let mask = check_string(mc, &args, arg + 1, "sethook")?;
+                                let mask = mask.as_bytes();
+                                let count = opt_integer(mc, &args, arg + 2, "sethook", 0)?;
+                                Some(Hook {
+                                    function,
+                                    call: mask.contains(&b'c'),
+                                    returns: mask.contains(&b'r'),
+                                    line: mask.contains(&b'l'),
+                                    count: count.max(0).min(u32::MAX as i64) as u32,
+                                })
+                            }
+                            _ => {
+                                return Err(bad_argument_type(
+                                    mc, &args, arg, "sethook", "function",
+                                ))
+                            }
+                        };
+                        target.set_hook(mc, hook);
+                        Ok(CallbackResult::Return(Vec::new()))
+                    },
+                ))
+            }),
+        )
+        .unwrap();
+
+    debug
+        .set(
+            mc,
+            String::new_static(b"gethook"),
+            Callback::new_sequence_with_thread(mc, (), |_, thread, args| {
+                Ok(sequence::from_fn_with(
+                    (thread, args),
+                    |mc, (thread, args)| {
+                        let target = match args.get(0) {
+                            Some(&Value::Thread(target)) => target,
+                            _ => thread,
+                        };
+                        Ok(CallbackResult::Return(match target.hook() {
+                            Some(hook) => {
+                                let mut mask = Vec::new();
+                                for &(set, c) in
+                                    &[(hook.call, b'c'), (hook.returns, b'r'), (hook.line, b'l')]
+                                {
+                                    if set {
+                                        mask.push(c);
+                                    }
+                                }
+                                vec![
+                                    Value::Function(hook.function),
+                                    Value::String(String::new(mc, &mask)),
+                                    Value::Integer(hook.count as i64),
+                                ]
+                            }
+                            None => vec![
+                                Value::Nil,
+                                Value::String(String::new_static(b"")),
+                                Value::Integer(0),
+                            ],
+                        }))
+                    },
+                ))
+            }),
+        )
+        .unwrap();
+
+    debug
+        .set(
+            mc,
+            String::new_static(b"getlocal"),
+            Callback::new_sequence_with_thread(mc, (), |_, thread, args| {
+                Ok(sequence::from_fn_with(
+                    (thread, args),
+                    |mc, (thread, args)| {
+                        let (target, arg) = match args.get(0) {
+                            Some(&Value::Thread(target)) => (target, 1),
+                            _ => (thread, 0),
+                        };
+                        let n = check_integer(mc, &args, arg + 1, "getlocal")?;
+
+                        // Only the names of the parameters of a function that is not running
+                        if let Some(Value::Function(function)) = args.get(arg) {
+                            let name = match function {
+                                Function::Closure(closure) if n > 0 => closure
+                                    .0
+                                    .proto
+                                    .local_variable(n as usize, 0)
+                                    .map(|variable| Value::String(variable.name)),
+                                _ => None,
+                            };
+                            return Ok(CallbackResult::Return(vec![name.unwrap_or(Value::Nil)]));
+                        }
+
+                        let level = check_level(mc, &args, arg, target, "getlocal")?;
+                        Ok(CallbackResult::Return(match target.local(level, n) {
+                            Some((name, value)) => vec![Value::String(name), value],
+                            None => vec![Value::Nil],
+                        }))
+                    },
+                ))
+            }),
+        )
+        .unwrap();
+
+    debug
+        .set(
+            mc,
+            String::new_static(b"setlocal"),
+            Callback::new_sequence_with_thread(mc, (), |_, thread, args| {
+                Ok(sequence::from_fn_with(
+                    (thread, args),
+                    |mc, (thread, args)| {
+                        let (target, arg) = match args.get(0) {
+                            Some(&Value::Thread(target)) => (target, 1),
+                            _ => (thread, 0),
+                        };
+                        let level = check_level(mc, &args, arg, target, "setlocal")?;
+                        let n = check_integer(mc, &args, arg + 1, "setlocal")?;
+                        let value = check_any(mc, &args, arg + 2, "setlocal")?;
+                        Ok(CallbackResult::Return(vec![
+                            match target.set_local(mc, level, n, value) {
+                                Some(name) => Value::String(name),
+                                None => Value::Nil,
+                            },
+                        ]))
+                    },
+                ))
+            }),
+        )
+        .unwrap();
+
+    debug
+        .set(
+            mc,
+            String::new_static(b"getupvalue"),
+            Callback::new_sequence(mc, |args| {
+                Ok(sequence::from_fn_with(args, |mc, args| {
+                    let (name, upvalue) = match check_upvalue(mc, &args, "getupvalue")? {
+                        Some(upvalue) => upvalue,
+                        None => return Ok(CallbackResult::Return(Vec::new())),
+                    };
+                    Ok(CallbackResult::Return(vec![
+                        Value::String(name),
+                        upvalue.get(),
+                    ]))
+                }))
+            }),
+        )
+        .unwrap();
+
+    debug
+        .set(
+            mc,
+            String::new_static(b"setupvalue"),
+            Callback::new_sequence(mc, |args| {
+                Ok(sequence::from_fn_with(args, |mc, args| {
+                    let value = check_any(mc, &args, 2, "setupvalue")?;
+                    let (name, upvalue) = match check_upvalue(mc, &args, "setupvalue")? {
+                        Some(upvalue) => upvalue,
+                        None => return Ok(CallbackResult::Return(Vec::new())),
+                    };
+                    upvalue.set(mc, value);
+                    Ok(CallbackResult::Return(vec![Value::String(name)]))
+                }))
+            }),
+        )
+        .unwrap();
+
+    env.set(mc, String::new_static(b"debug"), debug).unwrap();
+}
+
+// Checks for a level of the call stack of the given thread, where level 0 of the current thread is
+// the running library function itself.
+fn check_level<'gc>(
+    mc: MutationContext<'gc, '_>,
+    args: &[Value<'gc>],
+    n: usize,
+    thread: Thread<'gc>,
+    function: &str,
+) -> Result<usize, Error<'gc>> {
+    let level = check_integer(mc, args, n, function)?;
+    match usize::try_from(level) {
+        Ok(level) if level < thread.call_stack().len() => Ok(level),
+        _ => Err(bad_argument(mc, n, function, "level out of range")),
+    }
+}
+
+// Checks for a function and an upvalue index as the first two arguments, returning the name of the
+// upvalue and the upvalue itself if the function has one at that index.  Callbacks have no upvalues.
+fn check_upvalue<'gc>(
+    mc: MutationContext<'gc, '_>,
+    args: &[Value<'gc>],
+    function: &str,
+) -> Result<Option<(String<'gc>, UpValue<'gc>)>, Error<'gc>> {
+    let n = check_integer(mc, args, 1, function)?;
+    let closure = match args.get(0) {
+        Some(Value::Function(Function::Closure(closure))) => closure,
+        Some(Value::Function(Function::Callback(_))) => return Ok(None),
+        _ => return Err(bad_argument_type(mc, args, 0, function, "function")),
+    };
+    let index = match usize::try_from(n) {
+        Ok(n) if n >= 1 && n <= closure.0.upvalues.len() => n - 1,
+        _ => return Ok(None),
+    };
+    let name = match closure.0.proto.upvalue_names.get(index) {
+        Some(&name) => name,
+        None => String::new_static(b"(*no name)"),
+    };
+    Ok(Some((name, closure.0.upvalues[index])))
+}
+
+// Builds the table returned by `getinfo` for a function and the stack frame running it, if any,
+// or returns `None` if the options are invalid.  A frame without a function is a callback.
+fn function_info<'gc>(
+    mc: MutationContext<'gc, '_>,
+    options: &[u8],
+    function: Option<Function<'gc>>,
+    frame: Option<&StackFrame<'gc>>,
+) -> Option<Table<'gc>> {
+    let info = Table::new(mc);
+    let set = |key: &'static [u8], value: Value<'gc>| {
+        info.set(mc, String::new_static(key), value).unwrap();
+    };
+    let proto = match function {
+        Some(Function::Closure(closure)) => Some(closure.0.proto),
+        _ => None,
+    };
+
+    for option in options {
+        match option {
+            b'S' => match proto {
+                Some(proto) => {
+                    let source = match proto.chunk_name {
+                        Some(name) => name,
+                        None => String::new_static(b"=?"),
+                    };
+                    let short_src = chunk_id(&StdString::from_utf8_lossy(source.as_bytes()));
+                    set(b"source", Value::String(source));
+                    set(
+                        b"short_src",
+                        Value::String(String::new(mc, short_src.as_bytes())),
+                    );
+                    set(
+                        b"what",
+                        Value::String(String::new_static(if proto.line_defined == 0 {
+                            b"main"
+                        } else {
+                            b"Lua"
+                        })),
+                    );
+                    set(b"linedefined", Value::Integer(proto.line_defined as i64));
+                    set(
+                        b"lastlinedefined",
+                        Value::Integer(proto.last_line_defined as i64),
+                    );
+                }
+                None => {
+                    set(b"source", Value::String(String::new_static(b"=[C]")));
+                    set(b"short_src", Value::String(String::new_static(b"[C]")));
+                    set(b"what", Value::String(String::new_static(b"C")));
+                    set(b"linedefined", Value::Integer(-1));
+                    set(b"lastlinedefined", Value::Integer(-1));
+                }
+            },
+            b'l' => {
+                let line = frame
+                    .and_then(|frame| frame.location.as_ref())
+                    .and_then(|location| location.line);
+                set(
+                    b"currentline",
+                    Value::Integer(line.map(|line| line as i64).unwrap_or(-1)),
+                );
+            }
+            b't' => set(
+                b"istailcall",
+                Value::Boolean(frame.map(|frame| frame.is_tail_call).unwrap_or(false)),
+            ),
+            b'u' => match function {
+                Some(Function::Closure(closure)) => {
+                    let proto = &closure.0.proto;
+                    set(b"nups", Value::Integer(closure.0.upvalues.len() as i64));
+                    set(b"nparams", Value::Integer(proto.fixed_params as i64));
+                    set(b"isvararg", Value::Boolean(proto.has_varargs));
+                }
+                _ => {
+                    set(b"nups", Value::Integer(0));
+                    set(b"nparams", Value::Integer(0));
+                    set(b"isvararg", Value::Boolean(true));
+                }
+            },
+            b'n' => match frame.and_then(|frame| frame.name.as_ref()) {
+                Some(name) => {
+                    set(
+                        b"name",
+                        Value::String(String::new(mc, name.name().as_bytes())),
+                    );
+                    set(
+                        b"namewhat",
+                        Value::String(String::new_static(name.kind().as_bytes())),
+                    );
+                }
+                None => set(b"namewhat", Value::String(String::new_static(b""))),
+            },
+            b'f' => {
+                if let Some(function) = function {
+                    set(b"func", Value::Function(function));
+                }
+            }
+            _ => return None,
+        }
+    }
+    Some(info)
+}
@@ -0,0 +1,515 @@
+use std::string::String as StdString;
+
+use gc_arena::MutationContext;
+use gc_sequence as sequence;
+
+use crate::{
+    meta_ops, CallbackResult, Continuation, Error, MetaOperatorError, RuntimeError, String, Value,
+};
+
+use super::base::{bad_argument, check_any, check_integer, check_number, check_string};
+
+// Width and precision are limited to two digits, like in PUC-Rio Lua.
+const MAX_FORMAT_DIGITS: usize = 2;
+
+// The default precision of float conversions.
+const DEFAULT_PRECISION: usize = 6;
+
+/// Implements `string.format`.  Arguments formatted with `%s` that have a `__tostring` metamethod
+/// are converted to strings by calling it before anything is formatted.
+pub(crate) fn string_format<'gc>(
+    mc: MutationContext<'gc, '_>,
+    args: Vec<Value<'gc>>,
+) -> Result<CallbackResult<'gc>, Error<'gc>> {
+    let format = check_string(mc, &args, 0, "format")?;
+    let pieces = parse_format(format.as_bytes()).map_err(|spec| invalid_conversion(mc, spec))?;
+
+    let mut n = 0;
+    for piece in &pieces {
+        if let Piece::Spec(spec) = piece {
+            n += 1;
+            if spec.conversion != b's' {
+                continue;
+            }
+            let value = check_any(mc, &args, n, "format")?;
+            if let Value::Function(function) = meta_ops::get_metamethod(value, b"__tostring") {
+                return Ok(CallbackResult::TailCall {
+                    function,
+                    args: vec![value],
+                    continuation: Continuation::new_sequence_with(args, move |args, res| {
+                        let string = match res?.get(0).cloned().unwrap_or(Value::Nil) {
+                            v @ Value::String(_) => v,
+                            _ => return Err(MetaOperatorError::ToStringNotString.into()),
+                        };
+                        Ok(sequence::from_fn_with(
+                            (args, string),
+                            move |mc, (mut args, string)| {
+                                args[n] = string;
+                                string_format(mc, args)
+                            },
+                        ))
+                    }),
+                });
+            }
+        }
+    }
+
+    let mut output = Vec::new();
+    let mut n = 0;
+    for piece in &pieces {
+        match piece {
+            Piece::Literal(literal) => output.extend_from_slice(literal),
+            Piece::Spec(spec) => {
+                n += 1;
+                format_argument(mc, &mut output, spec, &args, n)?;
+            }
+        }
+    }
+    Ok(CallbackResult::Return(vec![Value::String(String::new(
+        mc, &output,
+    ))]))
+}
+
+enum Piece<'a> {
+    Literal(&'a [u8]),
+    Spec(Spec),
+}
+
+#[derive(Default)]
+struct Spec {
+    left: bool,
+    plus: bool,
+    space: bool,
+    alternate: bool,
+    zero: bool,
+    width: usize,
+    precision: Option<usize>,
+    conversion: u8,
+}
+
+// Splits a format string into literal text and conversion specifications.  An invalid specification
+// is returned as the error, starting from its '%'.
+fn parse_format(format: &[u8]) -> Result<Vec<Piece<'_>>, &[u8]> {
+    let mut pieces = Vec::new();
+    let mut i = 0;
+    while i < format.len() {
+        let literal_end = format[i..]
+            .iter()
+            .position(|&c| c == b'%')
+            .map_or(format.len(), |p| i + p);
+        if literal_end > i {
+            pieces.push(Piece::Literal(&format[i..literal_end]));
+        }
+        i = literal_end;
+        if i == format.len() {
+            break;
+        }
+
+        let start = i;
+        i += 1;
+        if format.get(i) == Some(&b'%') {
+            pieces.push(Piece::Literal(b"%"));
+            i += 1;
+            continue;
+        }
+
+        let mut spec = Spec::default();
+        while let Some(&c) = format.get(i) {
+            match c {
+                b'-' => spec.left = true,
+                b'+' => spec.plus = true,
+                b' ' => spec.space = true,
+                b'#' => spec.alternate = true,
+                b'0' => spec.zero = true,
+                _ => break,
+            }
+            i += 1;
+        }
+        let (width, len) = read_digits(&format[i..]);
+        spec.width = width;
+        i += len;
+        let mut digits_valid = len <= MAX_FORMAT_DIGITS;
+        if format.get(i) == Some(&b'.') {
+            i += 1;
+            let (precision, len) = read_digits(&format[i..]);
+            spec.precision = Some(precision);
+            i += len;
+            digits_valid = digits_valid && len <= MAX_FORMAT_DIGITS;
+        }
+
+        let conversion = match format.get(i) {
+            Some(&c) => c,
+            None => return Err(&format[start..]),
+        };
+        i += 1;
+        spec.conversion = conversion;
+
+        // The flags allowed for each conversion, and whether a precision is allowed
+        let (flags, precision): (&[u8], bool) = match conversion {
+            b'c' => (b"-", false),
+            b'd' | b'i' => (b"-+ 0", true),
+            b'u' => (b"-0", true),
+            b'o' | b'x' | b'X' => (b"-#0", true),
+            b'a' | b'A' | b'e' | b'E' | b'f' | b'F' | b'g' | b'G' => (b"-+ #0", true),
+            b's' => (b"-", true),
+            b'q' => (b"", false),
+            _ => return Err(&format[start..i]),
+        };
+        let flags_valid = (!spec.left || flags.contains(&b'-'))
+            && (!spec.plus || flags.contains(&b'+'))
+            && (!spec.space || flags.contains(&b' '))
+            && (!spec.alternate || flags.contains(&b'#'))
+            && (!spec.zero || flags.contains(&b'0'));
+        let modifiers_valid = conversion != b'q' || i - start == 2;
+        if !digits_valid
+            || !flags_valid
+            || !modifiers_valid
+            || (!precision && spec.precision.is_some())
+        {
+            return Err(&format[start..i]);
+        }
+
+        pieces.push(Piece::Spec(spec));
+    }
+    Ok(pieces)
+}
+
+// Reads a run of decimal digits, returning their value and how many there were.
+fn read_digits(s: &[u8]) -> (usize, usize) {
+    let len = s.iter().take_while(|c| c.is_ascii_digit()).count();
+    let value = s[..len].iter().fold(0usize, |v, &c| {
+        v.saturating_mul(10).saturating_add((c - b'0') as usize)
+    });
+    (value, len)
+}
+
+fn format_argument<'gc>(
+    mc: MutationContext<'gc, '_>,
+    output: &mut Vec<u8>,
+    spec: &Spec,
+    args: &[Value<'gc>],
+    n: usize,
+) -> Result<(), Error<'gc>> {
+    match spec.conversion {
+        b'c' => {
+            let c = check_integer(mc, args, n, "format")?;
+            pad(output, spec, b"", &[c as u8], false);
+        }
+        b'd' | b'i' => {
+            let i = check_integer(mc, args, n, "format")?;
+            let sign: &[u8] = if i < 0 {
+                b"-"
+            } else if spec.plus {
+                b"+"
+            } else if spec.space {
+                b" "
+            } else {
+                b""
+            };
+            let digits = integer_digits(spec, i.unsigned_abs().to_string());
+            pad(
+                output,
+                spec,
+                sign,
+                digits.as_bytes(),
+                spec.precision.is_none(),
+            );
+        }
+        b'u' | b'o' | b'x' | b'X' => {
+            let u = check_integer(mc, args, n, "format")? as u64;
+            let (prefix, digits): (&[u8], _) = match spec.conversion {
+                b'u' => (b"", integer_digits(spec, u.to_string())),
+                b'o' => {
+                    let mut digits = integer_digits(spec, format!("{:o}", u));
+                    // The alternate form makes sure that the first digit is a zero
+                    if spec.alternate && !digits.starts_with('0') {
+                        digits.insert(0, '0');
+                    }
+                    (b"", digits)
+                }
+                b'x' => (
+                    if spec.alternate && u != 0 { b"0x" } else { b"" },
+                    integer_digits(spec, format!("{:x}", u)),
+                ),
+                _ => (
+                    if spec.alternate && u != 0 { b"0X" } else { b"" },
+                    integer_digits(spec, format!("{:X}", u)),
+                ),
+            };
+            pad(
+                output,
+                spec,
+                prefix,
+                digits.as_bytes(),
+                spec.precision.is_none(),
+            );
+        }
+        b'a' | b'A' | b'e' | b'E' | b'f' | b'F' | b'g' | b'G' => {
+            let f = check_number(mc, args, n, "format")?;
+            let sign: &[u8] = if f.is_sign_negative() && !f.is_nan() {
+                b"-"
+            } else if spec.plus {
+                b"+"
+            } else if spec.space {
+                b" "
+            } else {
+                b""
+            };
+            let upper = spec.conversion.is_ascii_uppercase();
+            if f.is_finite() && spec.conversion.eq_ignore_ascii_case(&b'a') {
+                // Zeros from the '0' flag go between the "0x" and the digits
+                let mut prefix = sign.to_vec();
+                prefix.extend_from_slice(if upper { b"0X" } else { b"0x" });
+                let body = hex_float(f.abs(), spec.precision, spec.alternate);
+                let body = if upper {
+                    body.to_ascii_uppercase()
+                } else {
+                    body
+                };
+                pad(output, spec, &prefix, body.as_bytes(), true);
+            } else if f.is_finite() {
+                let body = format_float(spec, f.abs());
+                let body = if upper {
+                    body.to_ascii_uppercase()
+                } else {
+                    body
+                };
+                pad(output, spec, sign, body.as_bytes(), true);
+            } else {
+                let body: &[u8] = match (f.is_nan(), upper) {
+                    (true, false) => b"nan",
+                    (true, true) => b"NAN",
+                    (false, false) => b"inf",
+                    (false, true) => b"INF",
+                };
+                pad(output, spec, sign, body, false);
+            }
+        }
+        b's' => {
+            let value = check_any(mc, args, n, "format")?;
+            let mut string = Vec::new();
+            match value.to_string(mc) {
+                Some(s) => string.extend_from_slice(s.as_bytes()),
+                None => value.display(&mut string)?,
+            }
+            if let Some(precision) = spec.precision {
+                string.truncate(precision);
+            }
+            pad(output, spec, b"", &string, false);
+        }
+        b'q' => quote(mc, output, check_any(mc, args, n, "format")?, n)?,
+        _ => unreachable!(),
+    }
+    Ok(())
+}
+
+// Applies the precision of an integer conversion, which is the minimum number of digits.  Zero with
+// a precision of zero has no digits at all.
+fn integer_digits(spec: &Spec, digits: StdString) -> StdString {
+    match spec.precision {
+        Some(0) if digits == "0" => StdString::new(),
+        Some(precision) if digits.len() < precision => {
+            let mut padded = "0".repeat(precision - digits.len());
+            padded.push_str(&digits);
+            padded
+        }
+        _ => digits,
+    }
+}
+
+// Formats a finite, non-negative float with a 'e', 'f' or 'g' conversion, in lower case.
+fn format_float(spec: &Spec, f: f64) -> StdString {
+    let precision = spec.precision.unwrap_or(DEFAULT_PRECISION);
+    let mut body = match spec.conversion.to_ascii_lowercase() {
+        b'e' => exponent_form(f, precision),
+        b'f' => format!("{:.*}", precision, f),
+        _ => {
+            // Uses the shorter of the two other forms, with the precision as the number of
+            // significant digits, and without trailing zeros unless in the alternate form
+            let precision = precision.max(1);
+            let exponent = exponent_of(&exponent_form(f, precision - 1));
+            let mut body = if exponent < -4 || exponent >= precision as i32 {
+                exponent_form(f, precision - 1)
+            } else {
+                format!("{:.*}", (precision as i32 - 1 - exponent) as usize, f)
+            };
+            if !spec.alternate {
+                body = remove_trailing_zeros(body);
+            }
+            body
+        }
+    };
+    if spec.alternate && !body.contains('.') {
+        // The alternate form always has a decimal point
+        let point = body.find('e').unwrap_or_else(|| body.len());
+        body.insert(point, '.');
+    }
+    body
+}
+
+// Formats a float like the C '%e' conversion, with an exponent of at least two digits.
+fn exponent_form(f: f64, precision: usize) -> StdString {
+    let formatted = format!("{:.*e}", precision, f);
+    let (mantissa, exponent) = formatted.split_at(formatted.find('e').unwrap());
+    let exponent: i32 = exponent[1..].parse().unwrap();
+    format!(
+        "{}e{}{:02}",
+        mantissa,
+        if exponent < 0 { '-' } else { '+' },
+        exponent.abs()
+    )
+}
+
+// Formats a finite, non-negative float like the C '%a' conversion, without the "0x" prefix and in
+// lower case.  Without a precision, there are as many hex digits as are needed to represent the
+// float exactly.
+fn hex_float(f: f64, precision: Option<usize>, alternate: bool) -> StdString {
+    const MANTISSA_BITS: u32 = 52;
+    const MANTISSA_DIGITS: usize = 13;
+    const MANTISSA_MASK: u64 = (1 << MANTISSA_BITS) - 1;
+
+    let bits = f.to_bits();
+    let biased_exponent = (bits >> MANTISSA_BITS) as i32;
+    // Subnormal floats and zero have a leading zero, with the exponent of the smallest normal float
+    let (mut significand, exponent) = match biased_exponent {
+        0 if bits == 0 => (0, 0),
+        0 => (bits, -1022),
+        _ => (
+            bits & MANTISSA_MASK | 1 << MANTISSA_BITS,
+            biased_exponent - 1023,
+        ),
+    };
+
+    if let Some(precision) = precision.filter(|&p| p < MANTISSA_DIGITS) {
+        // Rounds to the nearest digit, or to an even digit if halfway between two, which may carry
+        // into the leading digit
+        let shift = 4 * (MANTISSA_DIGITS - precision) as u32;
+        let remainder = significand & ((1 << shift) - 1);
+        let half = 1 << (shift - 1);
+        significand >>= shift;
+        if remainder > half || (remainder == half && significand & 1 == 1) {
+            significand += 1;
+        }
+        significand <<= shift;
+    }
+
+    let mut digits = format!("{:01$x}", significand & MANTISSA_MASK, MANTISSA_DIGITS);
+    match precision {
+        Some(precision) if precision < MANTISSA_DIGITS => digits.truncate(precision),
+        Some(precision) => digits.push_str(&"0".repeat(precision - MANTISSA_DIGITS)),
+        None => digits.truncate(digits.trim_end_matches('0').len()),
+    }
+    let point = if digits.is_empty() && !alternate {
+        ""
+    } else {
+        "."
+    };
+    format!(
+        "{}{}{}p{:+}",
+        significand >> MANTISSA_BITS,
+        point,
+        digits,
+        exponent
+    )
+}
+
+fn exponent_of(exponent_form: &str) -> i32 {
+    exponent_form[exponent_form.find('e').unwrap() + 1..]
+        .parse()
+        .unwrap()
+}
+
+fn remove_trailing_zeros(body: StdString) -> StdString {
+    let (mantissa, exponent) = body.split_at(body.find('e').unwrap_or_else(|| body.len()));
+    if !mantissa.contains('.') {
+        return body;
+    }
+    let mantissa = mantissa.trim_end_matches('0').trim_end_matches('.');
+    format!("{}{}", mantissa, exponent)
+}
+
+// Writes the sign or prefix and body of a conversion padded to the width of the specification,
+// padding with zeros after the prefix if the '0' flag is set and `zero_allowed`.
+fn pad(output: &mut Vec<u8>, spec: &Spec, prefix: &[u8], body: &[u8], zero_allowed: bool) {
+    let len = prefix.len() + body.len();
+    let padding = spec.width.saturating_sub(len);
+    if spec.left {
+        output.extend_from_slice(prefix);
+        output.extend_from_slice(body);
+        output.extend(std::iter::repeat(b' ').take(padding));
+    } else if spec.zero && zero_allowed {
+        output.extend_from_slice(prefix);
+        output.extend(std::iter::repeat(b'0').take(padding));
+        output.extend_from_slice(body);
+    } else {
+        output.extend(std::iter::repeat(b' ').take(padding));
+        output.extend_from_slice(prefix);
+        output.extend_from_slice(body);
+    }
+}
+
+// Writes a value as Lua source that reads back as the same value, for the '%q' conversion.
+fn quote<'gc>(
+    mc: MutationContext<'gc, '_>,
+    output: &mut Vec<u8>,
+    value: Value<'gc>,
+    n: usize,
+) -> Result<(), Error<'gc>> {
+    match value {
+        Value::String(s) => {
+            let s = s.as_bytes();
+            output.push(b'"');
+            for (i, &c) in s.iter().enumerate() {
+                match c {
+                    b'"' | b'\\' | b'\n' => {
+                        output.push(b'\\');
+                        output.push(c);
+                    }
+                    b'\r' => output.extend_from_slice(b"\\r"),
+                    c if c.is_ascii_control() => {
+                        // A following digit would be read as part of a short escape
+                        if s.get(i + 1).map_or(false, |c| c.is_ascii_digit()) {
+                            output.extend_from_slice(format!("\\{:03}", c).as_bytes());
+                        } else {
+                            output.extend_from_slice(format!("\\{}", c).as_bytes());
+                        }
+                    }
+                    c => output.push(c),
+                }
+            }
+            output.push(b'"');
+        }
+        Value::Integer(i) => {
+            // The minimum integer cannot be written as a decimal literal, since its negation
+            // overflows
+            if i == i64::MIN {
+                output.extend_from_slice(b"0x8000000000000000");
+            } else {
+                output.extend_from_slice(i.to_string().as_bytes());
+            }
+        }
+        Value::Number(f) => {
+            let literal = if f.is_nan() {
+                "(0/0)".to_owned()
+            } else if f.is_infinite() {
+                (if f < 0.0 { "-1e9999" } else { "1e9999" }).to_owned()
+            } else {
+                // A hex float reads back as exactly the same float, and is never read as an integer
+                let sign = if f.is_sign_negative() { "-" } else { "" };
+                format!("{}0x{}", sign, hex_float(f.abs(), None, false))
+            };
+            output.extend_from_slice(literal.as_bytes());
+        }
+        Value::Nil | Value::Boolean(_) => value.display(output)?,
+        _ => return Err(bad_argument(mc, n, "format", "value has no literal form")),
+    }
+    Ok(())
+}
+
+fn invalid_conversion<'gc>(mc: MutationContext<'gc, '_>, spec: &[u8]) -> Error<'gc> {
+    let message = format!(
+        "invalid conversion '{}' to 'format'",
+        StdString::from_utf8_lossy(spec)
+    );
+    RuntimeError(Value::String(String::new(mc, message.as_bytes()))).into()
+}
@@ -0,0 +1,239 @@
+use gc_arena::{Collect, MutationContext};
+use gc_sequence as sequence;
+
+use crate::{
+    meta_ops::{self, MetaResult},
+    CallbackResult, Continuation, Error, Function, RuntimeError, String, Table, Value,
+};
+
+use super::base::{bad_argument, check_string, opt_integer};
+use super::pattern::{Capture, Matcher, PatternError};
+use super::string::{capture_value, pattern_error};
+
+// The progress of a `gsub` call, which is suspended whenever a replacement calls back into Lua.
+#[derive(Collect)]
+#[collect(empty_drop)]
+struct Gsub<'gc> {
+    string_metatable: Table<'gc>,
+    source: String<'gc>,
+    pattern: String<'gc>,
+    replacement: Value<'gc>,
+    max_replacements: i64,
+    replacements: i64,
+    position: usize,
+    last_match: Option<usize>,
+    output: Vec<u8>,
+}
+
+/// Implements `string.gsub`.  Replacements that need to call a function or an `__index` metamethod
+/// suspend the substitution and resume it from a continuation once the call returns.
+pub(crate) fn string_gsub<'gc>(
+    mc: MutationContext<'gc, '_>,
+    string_metatable: Table<'gc>,
+    args: Vec<Value<'gc>>,
+) -> Result<CallbackResult<'gc>, Error<'gc>> {
+    let source = check_string(mc, &args, 0, "gsub")?;
+    let pattern = check_string(mc, &args, 1, "gsub")?;
+    let replacement = match args.get(2).cloned().unwrap_or(Value::Nil) {
+        Value::Integer(_) | Value::Number(_) | Value::String(_) => {
+            Value::String(check_string(mc, &args, 2, "gsub")?)
+        }
+        v @ Value::Table(_) | v @ Value::Function(_) => v,
+        _ => {
+            return Err(bad_argument(
+                mc,
+                2,
+                "gsub",
+                "string/function/table expected",
+            ))
+        }
+    };
+    let max_replacements = opt_integer(mc, &args, 3, "gsub", source.len() + 1)?;
+
+    resume(
+        mc,
+        Gsub {
+            string_metatable,
+            source,
+            pattern,
+            replacement,
+            max_replacements,
+            replacements: 0,
+            position: 0,
+            last_match: None,
+            output: Vec::new(),
+        },
+    )
+}
+
+// Continues substituting until the source is exhausted or a replacement must call into Lua.
+fn resume<'gc>(
+    mc: MutationContext<'gc, '_>,
+    mut gsub: Gsub<'gc>,
+) -> Result<CallbackResult<'gc>, Error<'gc>> {
+    let (source, pattern) = (gsub.source.as_bytes(), gsub.pattern.as_bytes());
+    let mut matcher = Matcher::new(source, pattern);
+
+    // An anchored pattern is only tried once, at the start of the source
+    while gsub.replacements < gsub.max_replacements
+        && !(matcher.is_anchored() && (gsub.replacements > 0 || gsub.position > 0))
+    {
+        let start = gsub.position;
+        match matcher
+            .match_at(start)
+            .map_err(|e| pattern_error(mc, e))?
+            .filter(|&end| Some(end) != gsub.last_match)
+        {
+            Some(end) => {
+                gsub.replacements += 1;
+                gsub.position = end;
+                gsub.last_match = Some(end);
+                let lookup = match gsub.replacement {
+                    Value::String(replacement) => {
+                        add_string(&mut gsub.output, source, &matcher, start, end, replacement)
+                            .map_err(|message| runtime_error(mc, &message))?;
+                        None
+                    }
+                    Value::Table(table) => {
+                        let key = match matcher.capture_count() {
+                            0 => Value::String(String::new(mc, &source[start..end])),
+                            _ => capture_value(
+                                mc,
+                                source,
+                                matcher.capture(0).map_err(|e| pattern_error(mc, e))?,
+                            ),
+                        };
+                        match meta_ops::index(gsub.string_metatable, Value::Table(table), key)? {
+                            MetaResult::Value(value) => {
+                                add_value(mc, &mut gsub.output, &source[start..end], value)?;
+                                None
+                            }
+                            MetaResult::Call(function, args) => Some((function, args)),
+                        }
+                    }
+                    Value::Function(function) => {
+                        let args = matcher
+                            .captures(start, end, true)
+                            .map_err(|e| pattern_error(mc, e))?
+                            .into_iter()
+                            .map(|c| capture_value(mc, source, c))
+                            .collect();
+                        Some((function, args))
+                    }
+                    _ => unreachable!(),
+                };
+
+                if let Some((function, args)) = lookup {
+                    return Ok(call_replacement(gsub, function, args, start, end));
+                }
+            }
+            None if start < source.len() => {
+                gsub.output.push(source[start]);
+                gsub.position += 1;
+            }
+            None => break,
+        }
+    }
+
+    gsub.output.extend_from_slice(&source[gsub.position..]);
+    Ok(CallbackResult::Return(vec![
+        Value::String(String::new(mc, &gsub.output)),
+        Value::Integer(gsub.replacements),
+    ]))
+}
+
+// Calls a replacement function or `__index` metamethod for the match from `start` to `end`, then
+// continues the substitution with its result.
+fn call_replacement<'gc>(
+    gsub: Gsub<'gc>,
+    function: Function<'gc>,
+    args: Vec<Value<'gc>>,
+    start: usize,
+    end: usize,
+) -> CallbackResult<'gc> {
+    CallbackResult::TailCall {
+        function,
+        args,
+        continuation: Continuation::new_sequence_with(gsub, move |gsub, res| {
+            let value = res?.get(0).cloned().unwrap_or(Value::Nil);
+            Ok(sequence::from_fn_with(
+                (gsub, value),
+                move |mc, (mut gsub, value)| {
+                    let source = gsub.source;
+                    add_value(mc, &mut gsub.output, &source.as_bytes()[start..end], value)?;
+                    resume(mc, gsub)
+                },
+            ))
+        }),
+    }
+}
+
+// Appends a replacement string, in which `%0` to `%9` stand for captures and `%%` for a '%'.
+fn add_string(
+    output: &mut Vec<u8>,
+    source: &[u8],
+    matcher: &Matcher,
+    start: usize,
+    end: usize,
+    replacement: String,
+) -> Result<(), std::string::String> {
+    let mut replacement = replacement.as_bytes().iter();
+    while let Some(&c) = replacement.next() {
+        if c != b'%' {
+            output.push(c);
+            continue;
+        }
+        match replacement.next() {
+            Some(b'%') => output.push(b'%'),
+            Some(&d) if d.is_ascii_digit() => {
+                let capture = if d == b'0' {
+                    Capture::Range(start, end)
+                } else {
+                    let index = (d - b'1') as usize;
+                    if index < matcher.capture_count() {
+                        matcher.capture(index).map_err(|e| e.to_string())?
+                    } else if index == 0 {
+                        Capture::Range(start, end)
+                    } else {
+                        return Err(PatternError::InvalidCaptureIndex(index + 1).to_string());
+                    }
+                };
+                match capture {
+                    Capture::Range(start, end) => output.extend_from_slice(&source[start..end]),
+                    Capture::Position(position) => {
+                        output.extend_from_slice((position + 1).to_string().as_bytes())
+                    }
+                }
+            }
+            _ => return Err("invalid use of '%' in replacement string".to_owned()),
+        }
+    }
+    Ok(())
+}
+
+// Appends the result of a table or function replacement.  A false or nil result keeps the original
+// match.
+fn add_value<'gc>(
+    mc: MutationContext<'gc, '_>,
+    output: &mut Vec<u8>,
+    matched: &[u8],
+    value: Value<'gc>,
+) -> Result<(), Error<'gc>> {
+    match value {
+        Value::Nil | Value::Boolean(false) => output.extend_from_slice(matched),
+        Value::Integer(_) | Value::Number(_) | Value::String(_) => {
+            output.extend_from_slice(value.to_string(mc).unwrap().as_bytes())
+        }
+        value => {
+            return Err(runtime_error(
+                mc,
+                &format!("invalid replacement value (a {})", value.type_name()),
+            ))
+        }
+    }
+    Ok(())
+}
+
+fn runtime_error<'gc>(mc: MutationContext<'gc, '_>, message: &str) -> Error<'gc> {
+    RuntimeError(Value::String(String::new(mc, message.as_bytes()))).into()
+}
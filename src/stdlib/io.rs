@@ -0,0 +1,858 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::string::String as StdString;
+
+use gc_arena::{Collect, GcCell, MutationContext};
+use gc_sequence as sequence;
+
+use crate::{
+    AnyUserData, Callback, CallbackResult, Error, Root, RuntimeError, String, Table, Value,
+};
+
+use super::base::{bad_argument, bad_argument_type, check_string, opt_integer};
+
+// The longest numeral that `read("n")` will accept, the same limit as PUC-Rio Lua.
+const MAX_NUMERAL_LEN: usize = 200;
+
+// The data held by file handle userdata, `None` once the file has been closed.
+struct FileHandle(Option<Stream>);
+
+enum Stream {
+    File(BufReader<File>),
+    Stdin,
+    Stdout,
+    Stderr,
+}
+
+impl Stream {
+    fn reader<R>(&mut self, f: impl FnOnce(&mut dyn BufRead) -> io::Result<R>) -> io::Result<R> {
+        match self {
+            Stream::File(file) => f(file),
+            Stream::Stdin => f(&mut io::stdin().lock()),
+            Stream::Stdout | Stream::Stderr => Err(bad_file_descriptor()),
+        }
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        match self {
+            Stream::File(file) => {
+                // Reading may have buffered past the current position of the file, which must be
+                // discarded so that the write happens where the reads stopped.  Seeking to an
+                // absolute position always discards the buffer.
+                if !file.buffer().is_empty() {
+                    let position = file.stream_position()?;
+                    file.seek(SeekFrom::Start(position))?;
+                }
+                file.get_mut().write_all(bytes)
+            }
+            Stream::Stdin => Err(bad_file_descriptor()),
+            Stream::Stdout => io::stdout().write_all(bytes),
+            Stream::Stderr => io::stderr().write_all(bytes),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Stream::File(file) => file.get_mut().flush(),
+            Stream::Stdin => Ok(()),
+            Stream::Stdout => io::stdout().flush(),
+            Stream::Stderr => io::stderr().flush(),
+        }
+    }
+
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match self {
+            Stream::File(file) => file.seek(pos),
+            _ => Err(io::Error::other("Illegal seek")),
+        }
+    }
+}
+
+// The current default input and output files, along with the metatable shared by all file handles.
+#[derive(Collect)]
+#[collect(empty_drop)]
+struct IoState<'gc> {
+    file_metatable: Table<'gc>,
+    input: AnyUserData<'gc>,
+    output: AnyUserData<'gc>,
+}
+
+pub fn load_io<'gc>(mc: MutationContext<'gc, '_>, _: Root<'gc>, env: Table<'gc>) {
+    let io = Table::new(mc);
+    let methods = Table::new(mc);
+    let file_metatable = Table::new(mc);
+
+    let stdin = new_file(mc, file_metatable, Stream::Stdin);
+    let stdout = new_file(mc, file_metatable, Stream::Stdout);
+    let stderr = new_file(mc, file_metatable, Stream::Stderr);
+    let state = GcCell::allocate(
+        mc,
+        IoState {
+            file_metatable,
+            input: stdin,
+            output: stdout,
+        },
+    );
+
+    io.set(mc, String::new_static(b"stdin"), stdin).unwrap();
+    io.set(mc, String::new_static(b"stdout"), stdout).unwrap();
+    io.set(mc, String::new_static(b"stderr"), stderr).unwrap();
+
+    io.set(
+        mc,
+        String::new_static(b"open"),
+        Callback::new_sequence_with(mc, state, |state, args| {
+            Ok(sequence::from_fn_with(
+                (*state, args),
+                |mc, (state, args)| {
+                    let filename = check_string(mc, &args, 0, "open")?;
+                    let mode = match args.get(1).cloned().unwrap_or(Value::Nil) {
+                        Value::Nil => String::new_static(b"r"),
+                        _ => check_string(mc, &args, 1, "open")?,
+                    };
+                    let options = open_options(mode.as_bytes())
+                        .ok_or_else(|| bad_argument(mc, 1, "open", "invalid mode"))?;
+
+                    let filename = StdString::from_utf8_lossy(filename.as_bytes()).into_owned();
+                    Ok(CallbackResult::Return(match options.open(&filename) {
+                        Ok(file) => vec![Value::UserData(new_file(
+                            mc,
+                            state.read().file_metatable,
+                            Stream::File(BufReader::new(file)),
+                        ))],
+                        Err(err) => failure(mc, Some(&filename), &err),
+                    }))
+                },
+            ))
+        }),
+    )
+    .unwrap();
+
+    io.set(
+        mc,
+        String::new_static(b"input"),
+        Callback::new_sequence_with(mc, state, |state, args| {
+            Ok(sequence::from_fn_with(
+                (*state, args),
+                |mc, (state, args)| {
+                    if let Some(input) = default_file(mc, state, &args, "input", "r")? {
+                        state.write(mc).input = input;
+                    }
+                    Ok(CallbackResult::Return(vec![Value::UserData(
+                        state.read().input,
+                    )]))
+                },
+            ))
+        }),
+    )
+    .unwrap();
+
+    io.set(
+        mc,
+        String::new_static(b"output"),
+        Callback::new_sequence_with(mc, state, |state, args| {
+            Ok(sequence::from_fn_with(
+                (*state, args),
+                |mc, (state, args)| {
+                    if let Some(output) = default_file(mc, state, &args, "output", "w")? {
+                        state.write(mc).output = output;
+                    }
+                    Ok(CallbackResult::Return(vec![Value::UserData(
+                        state.read().output,
+                    )]))
+                },
+            ))
+        }),
+    )
+    .unwrap();
+
+    io.set(
+        mc,
+        String::new_static(b"read"),
+        Callback::new_sequence_with(mc, state, |state, args| {
+            Ok(sequence::from_fn_with(
+                (*state, args),
+                |mc, (state, args)| {
+                    let input = state.read().input;
+                    Ok(CallbackResult::Return(read(mc, input, &args, 0, "read")?))
+                },
+            ))
+        }),
+    )
+    .unwrap();
+
+    io.set(
+        mc,
+        String::new_static(b"write"),
+        Callback::new_sequence_with(mc, state, |state, args| {
+            Ok(sequence::from_fn_with(
+                (*state, args),
+                |mc, (state, args)| {
+                    let output = state.read().output;
+                    Ok(CallbackResult::Return(write(
+                        mc, output, &args, 0, "write",
+                    )?))
+                },
+            ))
+        }),
+    )
+    .unwrap();
+
+    io.set(
+        mc,
+        String::new_static(b"lines"),
+        Callback::new_sequence_with(mc, state, |state, args| {
+            Ok(sequence::from_fn_with(
+                (*state, args),
+                |mc, (state, args)| {
+                    let (file, close_at_eof) = match args.get(0).cloned().unwrap_or(Value::Nil) {
+                        Value::Nil => (state.read().input, false),
+                        _ => {
+                            let filename = check_string(mc, &args, 0, "lines")?;
+                            let filename =
+                                StdString::from_utf8_lossy(filename.as_bytes()).into_owned();
+                            match File::open(&filename) {
+                                Ok(file) => (
+                                    new_file(
+                                        mc,
+                                        state.read().file_metatable,
+                                        Stream::File(BufReader::new(file)),
+                                    ),
+                                    true,
+                                ),
+                                Err(err) => {
+                                    return Err(runtime_error(
+                                        mc,
+                                        &format!("{}: {}", filename, error_message(&err)),
+                                    ));
+                                }
+                            }
+                        }
+                    };
+                    check_formats(mc, &args, 1, "lines")?;
+                    Ok(CallbackResult::Return(vec![lines(
+                        mc,
+                        file,
+                        args.iter().skip(1).cloned().collect(),
+                        close_at_eof,
+                    )]))
+                },
+            ))
+        }),
+    )
+    .unwrap();
+
+    io.set(
+        mc,
+        String::new_static(b"close"),
+        Callback::new_sequence_with(mc, state, |state, args| {
+            Ok(sequence::from_fn_with(
+                (*state, args),
+                |mc, (state, args)| {
+                    let file = match args.get(0).cloned().unwrap_or(Value::Nil) {
+                        Value::Nil => state.read().output,
+                        _ => check_file(mc, &args, 0, "close")?,
+                    };
+                    Ok(CallbackResult::Return(close(mc, file)?))
+                },
+            ))
+        }),
+    )
+    .unwrap();
+
+    io.set(
+        mc,
+        String::new_static(b"flush"),
+        Callback::new_sequence_with(mc, state, |state, args| {
+            Ok(sequence::from_fn_with((*state, args), |mc, (state, _)| {
+                let output = state.read().output;
+                Ok(CallbackResult::Return(flush(mc, output)?))
+            }))
+        }),
+    )
+    .unwrap();
+
+    io.set(
+        mc,
+        String::new_static(b"type"),
+        Callback::new_sequence(mc, |args| {
+            Ok(sequence::from_fn_with(args, |mc, args| {
+                if args.is_empty() {
+                    return Err(bad_argument(mc, 0, "type", "value expected"));
+                }
+                let file_type = match args[0] {
+                    Value::UserData(u) => match u.borrow::<FileHandle>() {
+                        Some(handle) if handle.0.is_some() => {
+                            Value::String(String::new_static(b"file"))
+                        }
+                        Some(_) => Value::String(String::new_static(b"closed file")),
+                        None => Value::Nil,
+                    },
+                    _ => Value::Nil,
+                };
+                Ok(CallbackResult::Return(vec![file_type]))
+            }))
+        }),
+    )
+    .unwrap();
+
+    methods
+        .set(
+            mc,
+            String::new_static(b"read"),
+            Callback::new_sequence(mc, |args| {
+                Ok(sequence::from_fn_with(args, |mc, args| {
+                    let file = check_file(mc, &args, 0, "read")?;
+                    Ok(CallbackResult::Return(read(mc, file, &args, 1, "read")?))
+                }))
+            }),
+        )
+        .unwrap();
+
+    methods
+        .set(
+            mc,
+            String::new_static(b"write"),
+            Callback::new_sequence(mc, |args| {
+                Ok(sequence::from_fn_with(args, |mc, args| {
+                    let file = check_file(mc, &args, 0, "write")?;
+                    Ok(CallbackResult::Return(write(mc, file, &args, 1, "write")?))
+                }))
+            }),
+        )
+        .unwrap();
+
+    methods
+        .set(
+            mc,
+            String::new_static(b"lines"),
+            Callback::new_sequence(mc, |args| {
+                Ok(sequence::from_fn_with(args, |mc, args| {
+                    let file = check_file(mc, &args, 0, "lines")?;
+                    check_open(mc, file)?;
+                    check_formats(mc, &args, 1, "lines")?;
+                    Ok(CallbackResult::Return(vec![lines(
+                        mc,
+                        file,
+                        args[1..].to_vec(),
+                        false,
+                    )]))
+                }))
+            }),
+        )
+        .unwrap();
+
+    methods
+        .set(
+            mc,
+            String::new_static(b"seek"),
+            Callback::new_sequence(mc, |args| {
+                Ok(sequence::from_fn_with(args, |mc, args| {
+                    let file = check_file(mc, &args, 0, "seek")?;
+                    let whence = match args.get(1).cloned().unwrap_or(Value::Nil) {
+                        Value::Nil => String::new_static(b"cur"),
+                        _ => check_string(mc, &args, 1, "seek")?,
+                    };
+                    let offset = opt_integer(mc, &args, 2, "seek", 0)?;
+                    let pos = match whence.as_bytes() {
+                        b"set" if offset >= 0 => SeekFrom::Start(offset as u64),
+                        b"set" => {
+                            let err = io::Error::other("Invalid argument");
+                            return Ok(CallbackResult::Return(failure(mc, None, &err)));
+                        }
+                        b"cur" => SeekFrom::Current(offset),
+                        b"end" => SeekFrom::End(offset),
+                        _ => {
+                            let message = format!(
+                                "invalid option '{}'",
+                                StdString::from_utf8_lossy(whence.as_bytes())
+                            );
+                            return Err(bad_argument(mc, 1, "seek", &message));
+                        }
+                    };
+                    Ok(CallbackResult::Return(
+                        match with_stream(mc, file, |stream| stream.seek(pos))? {
+                            Ok(pos) => vec![Value::Integer(pos as i64)],
+                            Err(err) => failure(mc, None, &err),
+                        },
+                    ))
+                }))
+            }),
+        )
+        .unwrap();
+
+    methods
+        .set(
+            mc,
+            String::new_static(b"flush"),
+            Callback::new_sequence(mc, |args| {
+                Ok(sequence::from_fn_with(args, |mc, args| {
+                    let file = check_file(mc, &args, 0, "flush")?;
+                    Ok(CallbackResult::Return(flush(mc, file)?))
+                }))
+            }),
+        )
+        .unwrap();
+
+    let close_method = Callback::new_sequence(mc, |args| {
+        Ok(sequence::from_fn_with(args, |mc, args| {
+            let file = check_file(mc, &args, 0, "close")?;
+            Ok(CallbackResult::Return(close(mc, file)?))
+        }))
+    });
+    methods
+        .set(mc, String::new_static(b"close"), close_method)
+        .unwrap();
+
+    file_metatable
+        .set(mc, String::new_static(b"__index"), methods)
+        .unwrap();
+    file_metatable
+        .set(
+            mc,
+            String::new_static(b"__name"),
+            String::new_static(b"FILE*"),
+        )
+        .unwrap();
+    file_metatable
+        .set(mc, String::new_static(b"__close"), close_method)
+        .unwrap();
+    file_metatable
+        .set(
+            mc,
+            String::new_static(b"__tostring"),
+            Callback::new_sequence(mc, |args| {
+                Ok(sequence::from_fn_with(args, |mc, args| {
+                    let file = check_file(mc, &args, 0, "tostring")?;
+                    let name = if file.borrow::<FileHandle>().unwrap().0.is_some() {
+                        format!("file ({:?})", file.0.as_ptr())
+                    } else {
+                        "file (closed)".to_owned()
+                    };
+                    Ok(CallbackResult::Return(vec![Value::String(String::new(
+                        mc,
+                        name.as_bytes(),
+                    ))]))
+                }))
+            }),
+        )
+        .unwrap();
+
+    env.set(mc, String::new_static(b"io"), io).unwrap();
+}
+
+fn new_file<'gc>(
+    mc: MutationContext<'gc, '_>,
+    metatable: Table<'gc>,
+    stream: Stream,
+) -> AnyUserData<'gc> {
+    let file = AnyUserData::new(mc, FileHandle(Some(stream)));
+    file.set_metatable(mc, Some(metatable));
+    file
+}
+
+// Returns the argument at index `n`, which must be a file handle, open or closed.
+fn check_file<'gc>(
+    mc: MutationContext<'gc, '_>,
+    args: &[Value<'gc>],
+    n: usize,
+    function: &str,
+) -> Result<AnyUserData<'gc>, Error<'gc>> {
+    match args.get(n) {
+        Some(Value::UserData(u)) if u.is::<FileHandle>() => Ok(*u),
+        _ => Err(bad_argument_type(mc, args, n, function, "FILE*")),
+    }
+}
+
+fn check_open<'gc>(mc: MutationContext<'gc, '_>, file: AnyUserData<'gc>) -> Result<(), Error<'gc>> {
+    with_stream(mc, file, |_| ())
+}
+
+// Calls `f` with the stream of an open file handle, or errors if it has been closed.
+fn with_stream<'gc, R>(
+    mc: MutationContext<'gc, '_>,
+    file: AnyUserData<'gc>,
+    f: impl FnOnce(&mut Stream) -> R,
+) -> Result<R, Error<'gc>> {
+    let mut handle = file.borrow_mut::<FileHandle>(mc).unwrap();
+    match &mut handle.0 {
+        Some(stream) => Ok(f(stream)),
+        None => Err(runtime_error(mc, "attempt to use a closed file")),
+    }
+}
+
+// Handles the argument of `io.input` and `io.output`, which may be a file handle or the name of a
+// file to open with the given mode.  Returns the new default file, if one was given.
+fn default_file<'gc>(
+    mc: MutationContext<'gc, '_>,
+    state: GcCell<'gc, IoState<'gc>>,
+    args: &[Value<'gc>],
+    function: &str,
+    mode: &str,
+) -> Result<Option<AnyUserData<'gc>>, Error<'gc>> {
+    match args.get(0).cloned().unwrap_or(Value::Nil) {
+        Value::Nil => Ok(None),
+        Value::UserData(_) => {
+            let file = check_file(mc, args, 0, function)?;
+            check_open(mc, file)?;
+            Ok(Some(file))
+        }
+        _ => {
+            let filename = check_string(mc, args, 0, function)?;
+            let filename = StdString::from_utf8_lossy(filename.as_bytes()).into_owned();
+            match open_options(mode.as_bytes()).unwrap().open(&filename) {
+                Ok(file) => Ok(Some(new_file(
+                    mc,
+                    state.read().file_metatable,
+                    Stream::File(BufReader::new(file)),
+                ))),
+                Err(err) => Err(runtime_error(
+                    mc,
+                    &format!("cannot open file '{}' ({})", filename, error_message(&err)),
+                )),
+            }
+        }
+    }
+}
+
+// Parses a mode string for `io.open`, which must match the pattern `[rwa]%+?b*`.
+fn open_options(mode: &[u8]) -> Option<OpenOptions> {
+    let (kind, rest) = mode.split_first()?;
+    let (update, rest) = match rest.split_first() {
+        Some((b'+', rest)) => (true, rest),
+        _ => (false, rest),
+    };
+    if !rest.iter().all(|&c| c == b'b') {
+        return None;
+    }
+
+    let mut options = OpenOptions::new();
+    match kind {
+        b'r' => options.read(true).write(update),
+        b'w' => options.read(update).write(true).create(true).truncate(true),
+        b'a' => options.read(update).append(true).create(true),
+        _ => return None,
+    };
+    Some(options)
+}
+
+enum ReadFormat {
+    Number,
+    Line { keep_newline: bool },
+    All,
+    Count(u64),
+}
+
+fn read_format<'gc>(
+    mc: MutationContext<'gc, '_>,
+    args: &[Value<'gc>],
+    n: usize,
+    function: &str,
+) -> Result<ReadFormat, Error<'gc>> {
+    match args[n] {
+        Value::Integer(_) | Value::Number(_) => Ok(ReadFormat::Count(
+            opt_integer(mc, args, n, function, 0)?.max(0) as u64,
+        )),
+        Value::String(format) => {
+            // Formats may start with a '*' for compatibility with Lua 5.2
+            let format = format.as_bytes();
+            let format = format.strip_prefix(b"*").unwrap_or(format);
+            match format.first() {
+                Some(b'n') => Ok(ReadFormat::Number),
+                Some(b'l') => Ok(ReadFormat::Line {
+                    keep_newline: false,
+                }),
+                Some(b'L') => Ok(ReadFormat::Line { keep_newline: true }),
+                Some(b'a') => Ok(ReadFormat::All),
+                _ => Err(bad_argument(mc, n, function, "invalid format")),
+            }
+        }
+        _ => Err(bad_argument(mc, n, function, "invalid format")),
+    }
+}
+
+fn check_formats<'gc>(
+    mc: MutationContext<'gc, '_>,
+    args: &[Value<'gc>],
+    start: usize,
+    function: &str,
+) -> Result<(), Error<'gc>> {
+    for n in start..args.len() {
+        read_format(mc, args, n, function)?;
+    }
+    Ok(())
+}
+
+// Reads from the file with the formats given in `args` from index `start`, defaulting to reading a
+// line.  Reading stops at the first format that fails, which returns nil.
+fn read<'gc>(
+    mc: MutationContext<'gc, '_>,
+    file: AnyUserData<'gc>,
+    args: &[Value<'gc>],
+    start: usize,
+    function: &str,
+) -> Result<Vec<Value<'gc>>, Error<'gc>> {
+    Ok(match try_read(mc, file, args, start, function)? {
+        Ok(results) => results,
+        Err(err) => failure(mc, None, &err),
+    })
+}
+
+// Like `read`, but returns any io error rather than the results of a failed operation.
+fn try_read<'gc>(
+    mc: MutationContext<'gc, '_>,
+    file: AnyUserData<'gc>,
+    args: &[Value<'gc>],
+    start: usize,
+    function: &str,
+) -> Result<io::Result<Vec<Value<'gc>>>, Error<'gc>> {
+    let formats = if args.len() > start {
+        (start..args.len())
+            .map(|n| read_format(mc, args, n, function))
+            .collect::<Result<Vec<_>, _>>()?
+    } else {
+        vec![ReadFormat::Line {
+            keep_newline: false,
+        }]
+    };
+
+    let results = with_stream(mc, file, |stream| {
+        stream.reader(|reader| {
+            let mut results = Vec::new();
+            for format in formats {
+                let result = read_with_format(reader, format)?;
+                let done = result.is_none();
+                results.push(result);
+                if done {
+                    break;
+                }
+            }
+            Ok(results)
+        })
+    })?;
+
+    Ok(results.map(|results| {
+        let mut values = Vec::new();
+        for result in results {
+            let value = match result {
+                Some(Ok(bytes)) => Value::String(String::new(mc, &bytes)),
+                Some(Err(numeral)) => Value::String(String::new(mc, &numeral))
+                    .to_numeric()
+                    .unwrap_or(Value::Nil),
+                None => Value::Nil,
+            };
+            values.push(value);
+            if value == Value::Nil {
+                break;
+            }
+        }
+        values
+    }))
+}
+
+// Reads with a single format, returning `None` on end of file.  Numerals are returned as `Err` to be
+// converted to a number by the caller.
+fn read_with_format(
+    reader: &mut dyn BufRead,
+    format: ReadFormat,
+) -> io::Result<Option<Result<Vec<u8>, Vec<u8>>>> {
+    match format {
+        ReadFormat::Number => Ok(read_numeral(reader)?.map(Err)),
+        ReadFormat::Line { keep_newline } => {
+            let mut line = Vec::new();
+            if reader.read_until(b'\n', &mut line)? == 0 {
+                return Ok(None);
+            }
+            if !keep_newline && line.last() == Some(&b'\n') {
+                line.pop();
+            }
+            Ok(Some(Ok(line)))
+        }
+        ReadFormat::All => {
+            let mut all = Vec::new();
+            reader.read_to_end(&mut all)?;
+            Ok(Some(Ok(all)))
+        }
+        ReadFormat::Count(0) => {
+            // Reading zero bytes tests for end of file
+            if reader.fill_buf()?.is_empty() {
+                Ok(None)
+            } else {
+                Ok(Some(Ok(Vec::new())))
+            }
+        }
+        ReadFormat::Count(count) => {
+            let mut bytes = Vec::new();
+            reader.take(count).read_to_end(&mut bytes)?;
+            if bytes.is_empty() {
+                Ok(None)
+            } else {
+                Ok(Some(Ok(bytes)))
+            }
+        }
+    }
+}
+
+// Skips whitespace then reads the longest prefix that may be part of a numeral, leaving the
+// validation of the numeral itself to the caller.  Returns `None` if nothing could be read.
+fn read_numeral(reader: &mut dyn BufRead) -> io::Result<Option<Vec<u8>>> {
+    while let Some(c) = peek(reader)? {
+        if !c.is_ascii_whitespace() {
+            break;
+        }
+        reader.consume(1);
+    }
+
+    let mut numeral = Vec::new();
+    while let Some(c) = peek(reader)? {
+        let sign_allowed = match numeral.last() {
+            None => true,
+            Some(b'e') | Some(b'E') | Some(b'p') | Some(b'P') => true,
+            Some(_) => false,
+        };
+        let allowed = c.is_ascii_hexdigit()
+            || c == b'.'
+            || c == b'x'
+            || c == b'X'
+            || c == b'p'
+            || c == b'P'
+            || (sign_allowed && (c == b'+' || c == b'-'));
+        if !allowed || numeral.len() >= MAX_NUMERAL_LEN {
+            break;
+        }
+        numeral.push(c);
+        reader.consume(1);
+    }
+
+    Ok(if numeral.is_empty() {
+        None
+    } else {
+        Some(numeral)
+    })
+}
+
+fn peek(reader: &mut dyn BufRead) -> io::Result<Option<u8>> {
+    Ok(reader.fill_buf()?.first().cloned())
+}
+
+// Writes the strings or numbers in `args` from index `start` to the file, returning the file.
+fn write<'gc>(
+    mc: MutationContext<'gc, '_>,
+    file: AnyUserData<'gc>,
+    args: &[Value<'gc>],
+    start: usize,
+    function: &str,
+) -> Result<Vec<Value<'gc>>, Error<'gc>> {
+    let strings = (start..args.len())
+        .map(|n| check_string(mc, args, n, function))
+        .collect::<Result<Vec<_>, _>>()?;
+    let written = with_stream(mc, file, |stream| {
+        strings.iter().try_for_each(|s| stream.write(s.as_bytes()))
+    })?;
+    Ok(match written {
+        Ok(()) => vec![Value::UserData(file)],
+        Err(err) => failure(mc, None, &err),
+    })
+}
+
+fn flush<'gc>(
+    mc: MutationContext<'gc, '_>,
+    file: AnyUserData<'gc>,
+) -> Result<Vec<Value<'gc>>, Error<'gc>> {
+    Ok(match with_stream(mc, file, Stream::flush)? {
+        Ok(()) => vec![Value::UserData(file)],
+        Err(err) => failure(mc, None, &err),
+    })
+}
+
+// Closes the file, unless it is one of the standard files which cannot be closed.
+fn close<'gc>(
+    mc: MutationContext<'gc, '_>,
+    file: AnyUserData<'gc>,
+) -> Result<Vec<Value<'gc>>, Error<'gc>> {
+    let mut handle = file.borrow_mut::<FileHandle>(mc).unwrap();
+    match handle.0.take() {
+        Some(Stream::File(mut file)) => Ok(match file.get_mut().flush() {
+            Ok(()) => vec![Value::Boolean(true)],
+            Err(err) => failure(mc, None, &err),
+        }),
+        Some(stream) => {
+            handle.0 = Some(stream);
+            Ok(vec![
+                Value::Nil,
+                Value::String(String::new_static(b"cannot close standard file")),
+            ])
+        }
+        None => Err(runtime_error(mc, "attempt to use a closed file")),
+    }
+}
+
+// Returns an iterator function reading from the file with the given formats, which closes the file
+// at end of file if `close_at_eof` is set.
+fn lines<'gc>(
+    mc: MutationContext<'gc, '_>,
+    file: AnyUserData<'gc>,
+    formats: Vec<Value<'gc>>,
+    close_at_eof: bool,
+) -> Value<'gc> {
+    Callback::new_sequence_with(mc, (file, formats), move |(file, formats), _| {
+        Ok(sequence::from_fn_with(
+            (*file, formats.clone()),
+            move |mc, (file, formats)| {
+                if file.borrow::<FileHandle>().unwrap().0.is_none() {
+                    return Err(runtime_error(mc, "file is already closed"));
+                }
+
+                // The formats are the arguments after the file when `read` is called directly
+                let mut args = vec![Value::UserData(file)];
+                args.extend(formats);
+                let results = match try_read(mc, file, &args, 1, "lines")? {
+                    Ok(results) => results,
+                    Err(err) => return Err(runtime_error(mc, &error_message(&err))),
+                };
+                if close_at_eof && results.get(0).cloned().unwrap_or(Value::Nil) == Value::Nil {
+                    close(mc, file)?;
+                }
+                Ok(CallbackResult::Return(results))
+            },
+        ))
+    })
+    .into()
+}
+
+// The results of a failed io operation: nil, an error message optionally prefixed with the file
+// name, and the system error number if there is one.
+fn failure<'gc>(
+    mc: MutationContext<'gc, '_>,
+    filename: Option<&str>,
+    err: &io::Error,
+) -> Vec<Value<'gc>> {
+    let message = match filename {
+        Some(filename) => format!("{}: {}", filename, error_message(err)),
+        None => error_message(err),
+    };
+    vec![
+        Value::Nil,
+        Value::String(String::new(mc, message.as_bytes())),
+        Value::Integer(err.raw_os_error().unwrap_or(0) as i64),
+    ]
+}
+
+// Formats an io error like the C library does, without the " (os error N)" suffix added by Rust.
+fn error_message(err: &io::Error) -> StdString {
+    let message = err.to_string();
+    match err.raw_os_error() {
+        Some(code) => message
+            .trim_end_matches(&format!(" (os error {})", code))
+            .to_owned(),
+        None => message,
+    }
+}
+
+fn bad_file_descriptor() -> io::Error {
+    io::Error::other("Bad file descriptor")
+}
+
+fn runtime_error<'gc>(mc: MutationContext<'gc, '_>, message: &str) -> Error<'gc> {
+    RuntimeError(Value::String(String::new(mc, message.as_bytes()))).into()
+}
@@ -0,0 +1,500 @@
+use gc_arena::MutationContext;
+use gc_sequence as sequence;
+
+use crate::{Callback, CallbackResult, Error, Root, RuntimeError, String, Table, Value};
+
+use super::base::{check_any, check_string};
+
+// Arrays and objects nested deeper than this are an error rather than a stack overflow.
+const MAX_DEPTH: usize = 1000;
+
+/// Loads the `json` library, with `json.encode(value [, options])` and
+/// `json.decode(s [, options])`.
+///
+/// Tables whose keys are exactly `1..n` for some `n > 0` are arrays, and every other table,
+/// including an empty one, is an object whose keys must be strings or integers.  JSON `null`
+/// decodes as nil, or as the `null` option if one is given, which may be the `json.null`
+/// sentinel that encodes as `null` wherever it appears.  Encoding takes `pretty` and `indent`
+/// options to spread the output over several lines, and `sort_keys` for deterministic output.
+pub fn load_json<'gc>(mc: MutationContext<'gc, '_>, _: Root<'gc>, env: Table<'gc>) {
+    let json = Table::new(mc);
+    let null = Table::new(mc);
+
+    json.set(mc, String::new_static(b"null"), null).unwrap();
+
+    json.set(
+        mc,
+        String::new_static(b"encode"),
+        Callback::new_sequence_with(mc, null, |null, args| {
+            Ok(sequence::from_fn_with((*null, args), |mc, (null, args)| {
+                let value = check_any(mc, &args, 0, "encode")?;
+                let mut encoder = Encoder {
+                    null,
+                    indent: None,
+                    sort_keys: false,
+                    stack: Vec::new(),
+                    out: Vec::new(),
+                };
+                if let Some(Value::Table(options)) = args.get(1) {
+                    let indent = options.get(String::new_static(b"indent"));
+                    if let Value::String(indent) = indent {
+                        encoder.indent = Some(indent.as_bytes().to_vec());
+                    } else if options.get(String::new_static(b"pretty")).to_bool() {
+                        encoder.indent = Some(b"  ".to_vec());
+                    }
+                    encoder.sort_keys = options.get(String::new_static(b"sort_keys")).to_bool();
+                }
+                encoder
+                    .encode(value)
+                    .map_err(|message| runtime_error(mc, &message))?;
+                Ok(CallbackResult::Return(vec![Value::String(String::new(
+                    mc,
+                    &encoder.out,
+                ))]))
+            }))
+        }),
+    )
+    .unwrap();
+
+    json.set(
+        mc,
+        String::new_static(b"decode"),
+        Callback::new_sequence(mc, |args| {
+            Ok(sequence::from_fn_with(args, |mc, args| {
+                let s = check_string(mc, &args, 0, "decode")?;
+                let null = match args.get(1) {
+                    Some(Value::Table(options)) => options.get(String::new_static(b"null")),
+                    _ => Value::Nil,
+                };
+                let mut decoder = Decoder {
+                    input: s.as_bytes(),
+                    position: 0,
+                    null,
+                };
+                let value = decoder
+                    .decode(mc)
+                    .map_err(|message| runtime_error(mc, &message))?;
+                Ok(CallbackResult::Return(vec![value]))
+            }))
+        }),
+    )
+    .unwrap();
+
+    env.set(mc, String::new_static(b"json"), json).unwrap();
+}
+
+struct Encoder<'gc> {
+    null: Table<'gc>,
+    // The indentation for each level of nesting when pretty printing
+    indent: Option<Vec<u8>>,
+    sort_keys: bool,
+    // The tables being encoded, to detect tables that contain themselves
+    stack: Vec<Table<'gc>>,
+    out: Vec<u8>,
+}
+
+impl<'gc> Encoder<'gc> {
+    fn encode(&mut self, value: Value<'gc>) -> Result<(), std::string::String> {
+        match value {
+            Value::Nil => self.out.extend_from_slice(b"null"),
+            Value::Boolean(true) => self.out.extend_from_slice(b"true"),
+            Value::Boolean(false) => self.out.extend_from_slice(b"false"),
+            Value::Integer(i) => self.out.extend_from_slice(i.to_string().as_bytes()),
+            Value::Number(n) => {
+                if !n.is_finite() {
+                    return Err(format!("cannot encode the number {} as JSON", n));
+                }
+                // Debug formatting is the shortest representation that reads back as the same
+                // float, and keeps a fraction on integral floats
+                self.out.extend_from_slice(format!("{:?}", n).as_bytes());
+            }
+            Value::String(s) => self.encode_string(s.as_bytes())?,
+            Value::Table(table) if table == self.null => self.out.extend_from_slice(b"null"),
+            Value::Table(table) => {
+                if self.stack.contains(&table) {
+                    return Err("cannot encode a table that contains itself".to_owned());
+                }
+                if self.stack.len() >= MAX_DEPTH {
+                    return Err("cannot encode tables nested this deeply".to_owned());
+                }
+                self.stack.push(table);
+                match sequence_length(table) {
+                    Some(length) => self.encode_array(table, length)?,
+                    None => self.encode_object(table)?,
+                }
+                self.stack.pop();
+            }
+            value => {
+                return Err(format!(
+                    "cannot encode a value of type {} as JSON",
+                    value.type_name()
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn encode_array(&mut self, table: Table<'gc>, length: i64) -> Result<(), std::string::String> {
+        self.out.push(b'[');
+        for i in 1..=length {
+            if i > 1 {
+                self.out.push(b',');
+            }
+            self.newline(self.stack.len());
+            self.encode(table.get(i))?;
+        }
+        self.newline(self.stack.len() - 1);
+        self.out.push(b']');
+        Ok(())
+    }
+
+    fn encode_object(&mut self, table: Table<'gc>) -> Result<(), std::string::String> {
+        let mut entries = Vec::new();
+        let mut key = Value::Nil;
+        while let Some((k, v)) = table.next(key).map_err(|err| err.to_string())? {
+            let name = match k {
+                Value::String(s) => s.as_bytes().to_vec(),
+                Value::Integer(i) => i.to_string().into_bytes(),
+                k => {
+                    return Err(format!(
+                        "cannot encode a table key of type {} as JSON",
+                        k.type_name()
+                    ));
+                }
+            };
+            entries.push((name, v));
+            key = k;
+        }
+        if self.sort_keys {
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+        }
+
+        if entries.is_empty() {
+            self.out.extend_from_slice(b"{}");
+            return Ok(());
+        }
+        self.out.push(b'{');
+        for (i, (name, value)) in entries.into_iter().enumerate() {
+            if i > 0 {
+                self.out.push(b',');
+            }
+            self.newline(self.stack.len());
+            self.encode_string(&name)?;
+            self.out.push(b':');
+            if self.indent.is_some() {
+                self.out.push(b' ');
+            }
+            self.encode(value)?;
+        }
+        self.newline(self.stack.len() - 1);
+        self.out.push(b'}');
+        Ok(())
+    }
+
+    fn encode_string(&mut self, s: &[u8]) -> Result<(), std::string::String> {
+        let s = std::str::from_utf8(s)
+            .map_err(|_| "cannot encode a string that is not valid UTF-8 as JSON".to_owned())?;
+        self.out.push(b'"');
+        for c in s.chars() {
+            match c {
+                '"' => self.out.extend_from_slice(b"\\\""),
+                '\\' => self.out.extend_from_slice(b"\\\\"),
+                '\n' => self.out.extend_from_slice(b"\\n"),
+                '\r' => self.out.extend_from_slice(b"\\r"),
+                '\t' => self.out.extend_from_slice(b"\\t"),
+                '\u{8}' => self.out.extend_from_slice(b"\\b"),
+                '\u{c}' => self.out.extend_from_slice(b"\\f"),
+                c if (c as u32) < 0x20 || c == '\u{7f}' => self
+                    .out
+                    .extend_from_slice(format!("\\u{:04x}", c as u32).as_bytes()),
+                c => {
+                    let mut buf = [0; 4];
+                    self.out
+                        .extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                }
+            }
+        }
+        self.out.push(b'"');
+        Ok(())
+    }
+
+    // Starts a new line indented to the given depth, if pretty printing
+    fn newline(&mut self, depth: usize) {
+        if let Some(indent) = &self.indent {
+            self.out.push(b'\n');
+            for _ in 0..depth {
+                self.out.extend_from_slice(indent);
+            }
+        }
+    }
+}
+
+// The length of the table if it is an array, which is when its keys are exactly `1..n` for some
+// `n > 0`
+fn sequence_length<'gc>(table: Table<'gc>) -> Option<i64> {
+    let length = table.length();
+    if length == 0 {
+        return None;
+    }
+    let mut count = 0;
+    let mut key = Value::Nil;
+    while let Ok(Some((k, _))) = table.next(key) {
+        count += 1;
+        if count > length {
+            return None;
+        }
+        key = k;
+    }
+    if count == length {
+        Some(length)
+    } else {
+        None
+    }
+}
+
+struct Decoder<'a, 'gc> {
+    input: &'a [u8],
+    position: usize,
+    // The value JSON `null` decodes as
+    null: Value<'gc>,
+}
+
+impl<'a, 'gc> Decoder<'a, 'gc> {
+    fn decode(&mut self, mc: MutationContext<'gc, '_>) -> Result<Value<'gc>, std::string::String> {
+        let value = self.value(mc, 0)?;
+        self.skip_whitespace();
+        if self.position < self.input.len() {
+            return Err(self.error("unexpected data after the value"));
+        }
+        Ok(value)
+    }
+
+    fn value(
+        &mut self,
+        mc: MutationContext<'gc, '_>,
+        depth: usize,
+    ) -> Result<Value<'gc>, std::string::String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b'{') => {
+                if depth >= MAX_DEPTH {
+                    return Err(self.error("objects nested too deeply"));
+                }
+                self.position += 1;
+                let table = Table::new(mc);
+                self.skip_whitespace();
+                if self.peek() == Some(b'}') {
+                    self.position += 1;
+                    return Ok(Value::Table(table));
+                }
+                loop {
+                    self.skip_whitespace();
+                    if self.peek() != Some(b'"') {
+                        return Err(self.error("expected a string key"));
+                    }
+                    let key = self.string()?;
+                    self.skip_whitespace();
+                    self.expect(b':')?;
+                    let value = self.value(mc, depth + 1)?;
+                    table
+                        .set(mc, String::new(mc, &key), value)
+                        .map_err(|err| err.to_string())?;
+                    self.skip_whitespace();
+                    match self.next() {
+                        Some(b',') => {}
+                        Some(b'}') => break,
+                        _ => return Err(self.error("expected ',' or '}'")),
+                    }
+                }
+                Ok(Value::Table(table))
+            }
+            Some(b'[') => {
+                if depth >= MAX_DEPTH {
+                    return Err(self.error("arrays nested too deeply"));
+                }
+                self.position += 1;
+                let table = Table::new(mc);
+                self.skip_whitespace();
+                if self.peek() == Some(b']') {
+                    self.position += 1;
+                    return Ok(Value::Table(table));
+                }
+                let mut index = 1;
+                loop {
+                    let value = self.value(mc, depth + 1)?;
+                    table.set(mc, index, value).map_err(|err| err.to_string())?;
+                    index += 1;
+                    self.skip_whitespace();
+                    match self.next() {
+                        Some(b',') => {}
+                        Some(b']') => break,
+                        _ => return Err(self.error("expected ',' or ']'")),
+                    }
+                }
+                Ok(Value::Table(table))
+            }
+            Some(b'"') => Ok(Value::String(String::new(mc, &self.string()?))),
+            Some(b't') => self.literal(b"true", Value::Boolean(true)),
+            Some(b'f') => self.literal(b"false", Value::Boolean(false)),
+            Some(b'n') => self.literal(b"null", self.null),
+            Some(b'-') | Some(b'0'..=b'9') => self.number(),
+            Some(_) => Err(self.error("unexpected character")),
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    fn literal(
+        &mut self,
+        literal: &[u8],
+        value: Value<'gc>,
+    ) -> Result<Value<'gc>, std::string::String> {
+        if self.input[self.position..].starts_with(literal) {
+            self.position += literal.len();
+            Ok(value)
+        } else {
+            Err(self.error("unexpected character"))
+        }
+    }
+
+    fn number(&mut self) -> Result<Value<'gc>, std::string::String> {
+        let start = self.position;
+        let mut integral = true;
+        if self.peek() == Some(b'-') {
+            self.position += 1;
+        }
+        match self.peek() {
+            Some(b'0') => self.position += 1,
+            Some(b'1'..=b'9') => self.digits(),
+            _ => return Err(self.error("expected a digit")),
+        }
+        if self.peek() == Some(b'.') {
+            integral = false;
+            self.position += 1;
+            if !self.peek().map_or(false, |c| c.is_ascii_digit()) {
+                return Err(self.error("expected a digit"));
+            }
+            self.digits();
+        }
+        if let Some(b'e') | Some(b'E') = self.peek() {
+            integral = false;
+            self.position += 1;
+            if let Some(b'+') | Some(b'-') = self.peek() {
+                self.position += 1;
+            }
+            if !self.peek().map_or(false, |c| c.is_ascii_digit()) {
+                return Err(self.error("expected a digit"));
+            }
+            self.digits();
+        }
+
+        // The number is ASCII, so it is valid UTF-8
+        let number = std::str::from_utf8(&self.input[start..self.position]).unwrap();
+        if integral {
+            // Integers too large for Lua become floats, like integer literals that are too large
+            if let Ok(i) = number.parse::<i64>() {
+                return Ok(Value::Integer(i));
+            }
+        }
+        Ok(Value::Number(number.parse::<f64>().unwrap()))
+    }
+
+    fn digits(&mut self) {
+        while self.peek().map_or(false, |c| c.is_ascii_digit()) {
+            self.position += 1;
+        }
+    }
+
+    // Reads a string starting at its opening quote
+    fn string(&mut self) -> Result<Vec<u8>, std::string::String> {
+        self.position += 1;
+        let mut bytes = Vec::new();
+        loop {
+            match self.next() {
+                Some(b'"') => return Ok(bytes),
+                Some(b'\\') => {
+                    let c = match self.next() {
+                        Some(b'"') => '"',
+                        Some(b'\\') => '\\',
+                        Some(b'/') => '/',
+                        Some(b'b') => '\u{8}',
+                        Some(b'f') => '\u{c}',
+                        Some(b'n') => '\n',
+                        Some(b'r') => '\r',
+                        Some(b't') => '\t',
+                        Some(b'u') => self.unicode_escape()?,
+                        _ => return Err(self.error("invalid escape sequence")),
+                    };
+                    let mut buf = [0; 4];
+                    bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                }
+                Some(c) if c < 0x20 => return Err(self.error("control character in string")),
+                Some(c) => bytes.push(c),
+                None => return Err(self.error("unfinished string")),
+            }
+        }
+    }
+
+    // Reads the code point of a `\u` escape, along with the low surrogate of a surrogate pair
+    fn unicode_escape(&mut self) -> Result<char, std::string::String> {
+        let high = self.hex4()?;
+        let code = if (0xD800..0xDC00).contains(&high) {
+            if !self.input[self.position..].starts_with(b"\\u") {
+                return Err(self.error("unpaired surrogate in escape sequence"));
+            }
+            self.position += 2;
+            let low = self.hex4()?;
+            if !(0xDC00..0xE000).contains(&low) {
+                return Err(self.error("unpaired surrogate in escape sequence"));
+            }
+            0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+        } else {
+            high
+        };
+        std::char::from_u32(code).ok_or_else(|| self.error("unpaired surrogate in escape sequence"))
+    }
+
+    fn hex4(&mut self) -> Result<u32, std::string::String> {
+        let digits = self
+            .input
+            .get(self.position..self.position + 4)
+            .filter(|digits| digits.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|digits| u32::from_str_radix(std::str::from_utf8(digits).unwrap(), 16).ok())
+            .ok_or_else(|| self.error("invalid unicode escape sequence"))?;
+        self.position += 4;
+        Ok(digits)
+    }
+
+    fn expect(&mut self, c: u8) -> Result<(), std::string::String> {
+        if self.next() == Some(c) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{}'", c as char)))
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ') | Some(b'\t') | Some(b'\n') | Some(b'\r') = self.peek() {
+            self.position += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.input.get(self.position).cloned()
+    }
+
+    fn next(&mut self) -> Option<u8> {
+        let c = self.peek();
+        if c.is_some() {
+            self.position += 1;
+        }
+        c
+    }
+
+    fn error(&self, message: &str) -> std::string::String {
+        format!("{} at position {} in JSON", message, self.position + 1)
+    }
+}
+
+fn runtime_error<'gc>(mc: MutationContext<'gc, '_>, message: &str) -> Error<'gc> {
+    RuntimeError(Value::String(String::new(mc, message.as_bytes()))).into()
+}
@@ -1,30 +1,27 @@
 use gc_arena::MutationContext;
+use gc_sequence as sequence;
 
 use crate::{Callback, CallbackResult, Root, RuntimeError, String, Table, Value};
 
+use super::base::{bad_argument, check_integer, check_number};
+
 use rand::{FromEntropy, Rng, SeedableRng};
 use rand_xoshiro::Xoshiro256StarStar;
-use std::{cell::RefCell, ops::DerefMut, rc::Rc};
 
-pub fn load_math<'gc>(mc: MutationContext<'gc, '_>, _: Root<'gc>, env: Table<'gc>) {
+pub fn load_math<'gc>(mc: MutationContext<'gc, '_>, root: Root<'gc>, env: Table<'gc>) {
     let math = Table::new(mc);
-    let seeded_rng: Rc<RefCell<Xoshiro256StarStar>> =
-        Rc::new(RefCell::new(Xoshiro256StarStar::from_entropy()));
 
     math.set(
         mc,
         String::new_static(b"abs"),
-        Callback::new_immediate(mc, |args| {
-            match args.get(0).cloned().unwrap_or(Value::Nil) {
-                Value::Integer(a) => Ok(CallbackResult::Return(vec![Value::Integer(a.abs())])),
-                a => match a.to_number() {
-                    Some(f) => Ok(CallbackResult::Return(vec![Value::Number(f.abs())])),
-                    _ => Err(RuntimeError(Value::String(String::new_static(
-                        b"Bad argument to abs",
-                    )))
-                    .into()),
-                },
-            }
+        Callback::new_sequence(mc, |args| {
+            Ok(sequence::from_fn_with(args, |mc, args| {
+                let abs = match args.get(0).cloned().unwrap_or(Value::Nil) {
+                    Value::Integer(a) => Value::Integer(a.wrapping_abs()),
+                    _ => Value::Number(check_number(mc, &args, 0, "abs")?.abs()),
+                };
+                Ok(CallbackResult::Return(vec![abs]))
+            }))
         }),
     )
     .unwrap();
@@ -32,42 +29,21 @@ pub fn load_math<'gc>(mc: MutationContext<'gc, '_>, _: Root<'gc>, env: Table<'gc
     math.set(
         mc,
         String::new_static(b"acos"),
-        Callback::new_immediate(mc, |args| {
-            match args.get(0).cloned().unwrap_or(Value::Nil).to_number() {
-                Some(f) => Ok(CallbackResult::Return(vec![Value::Number(f.acos())])),
-                _ => Err(
-                    RuntimeError(Value::String(String::new_static(b"Bad argument to acos"))).into(),
-                ),
-            }
-        }),
+        float_function(mc, "acos", f64::acos),
     )
     .unwrap();
 
     math.set(
         mc,
         String::new_static(b"asin"),
-        Callback::new_immediate(mc, |args| {
-            match args.get(0).cloned().unwrap_or(Value::Nil).to_number() {
-                Some(f) => Ok(CallbackResult::Return(vec![Value::Number(f.asin())])),
-                _ => Err(
-                    RuntimeError(Value::String(String::new_static(b"Bad argument to asin"))).into(),
-                ),
-            }
-        }),
+        float_function(mc, "asin", f64::asin),
     )
     .unwrap();
 
     math.set(
         mc,
         String::new_static(b"atan"),
-        Callback::new_immediate(mc, |args| {
-            match args.get(0).cloned().unwrap_or(Value::Nil).to_number() {
-                Some(f) => Ok(CallbackResult::Return(vec![Value::Number(f.atan())])),
-                _ => Err(
-                    RuntimeError(Value::String(String::new_static(b"Bad argument to atan"))).into(),
-                ),
-            }
-        }),
+        float_function(mc, "atan", f64::atan),
     )
     .unwrap();
 
@@ -92,15 +68,14 @@ pub fn load_math<'gc>(mc: MutationContext<'gc, '_>, _: Root<'gc>, env: Table<'gc
     math.set(
         mc,
         String::new_static(b"ceil"),
-        Callback::new_immediate(mc, |args| {
-            match args.get(0).cloned().unwrap_or(Value::Nil).to_number() {
-                Some(f) => Ok(CallbackResult::Return(vec![
-                    Value::Integer(f.ceil() as i64),
-                ])),
-                _ => Err(
-                    RuntimeError(Value::String(String::new_static(b"Bad argument to ceil"))).into(),
-                ),
-            }
+        Callback::new_sequence(mc, |args| {
+            Ok(sequence::from_fn_with(args, |mc, args| {
+                let ceil = match args.get(0).cloned().unwrap_or(Value::Nil) {
+                    Value::Integer(a) => Value::Integer(a),
+                    _ => float_to_integer(check_number(mc, &args, 0, "ceil")?.ceil()),
+                };
+                Ok(CallbackResult::Return(vec![ceil]))
+            }))
         }),
     )
     .unwrap();
@@ -108,74 +83,42 @@ pub fn load_math<'gc>(mc: MutationContext<'gc, '_>, _: Root<'gc>, env: Table<'gc
     math.set(
         mc,
         String::new_static(b"cos"),
-        Callback::new_immediate(mc, |args| {
-            match args.get(0).cloned().unwrap_or(Value::Nil).to_number() {
-                Some(f) => Ok(CallbackResult::Return(vec![Value::Number(f.cos())])),
-                _ => Err(
-                    RuntimeError(Value::String(String::new_static(b"Bad argument to cos"))).into(),
-                ),
-            }
-        }),
+        float_function(mc, "cos", f64::cos),
     )
     .unwrap();
 
     math.set(
         mc,
         String::new_static(b"cosh"),
-        Callback::new_immediate(mc, |args| {
-            match args.get(0).cloned().unwrap_or(Value::Nil).to_number() {
-                Some(f) => Ok(CallbackResult::Return(vec![Value::Number(f.cosh())])),
-                _ => Err(
-                    RuntimeError(Value::String(String::new_static(b"Bad argument to cosh"))).into(),
-                ),
-            }
-        }),
+        float_function(mc, "cosh", f64::cosh),
     )
     .unwrap();
 
     math.set(
         mc,
         String::new_static(b"deg"),
-        Callback::new_immediate(mc, |args| {
-            match args.get(0).cloned().unwrap_or(Value::Nil).to_number() {
-                Some(f) => Ok(CallbackResult::Return(vec![Value::Number(f.to_degrees())])),
-                _ => Err(
-                    RuntimeError(Value::String(String::new_static(b"Bad argument to deg"))).into(),
-                ),
-            }
-        }),
+        float_function(mc, "deg", f64::to_degrees),
     )
     .unwrap();
 
     math.set(
         mc,
         String::new_static(b"exp"),
-        Callback::new_immediate(mc, |args| {
-            match args.get(0).cloned().unwrap_or(Value::Nil).to_number() {
-                Some(f) => Ok(CallbackResult::Return(vec![Value::Number(
-                    std::f64::consts::E.powf(f),
-                )])),
-                _ => Err(
-                    RuntimeError(Value::String(String::new_static(b"Bad argument to exp"))).into(),
-                ),
-            }
-        }),
+        float_function(mc, "exp", f64::exp),
     )
     .unwrap();
 
     math.set(
         mc,
         String::new_static(b"floor"),
-        Callback::new_immediate(mc, |args| {
-            match args.get(0).cloned().unwrap_or(Value::Nil).to_number() {
-                Some(f) => Ok(CallbackResult::Return(vec![Value::Integer(
-                    f.floor() as i64
-                )])),
-                _ => Err(
-                    RuntimeError(Value::String(String::new_static(b"Bad argument to floor")))
-                        .into(),
-                ),
-            }
+        Callback::new_sequence(mc, |args| {
+            Ok(sequence::from_fn_with(args, |mc, args| {
+                let floor = match args.get(0).cloned().unwrap_or(Value::Nil) {
+                    Value::Integer(a) => Value::Integer(a),
+                    _ => float_to_integer(check_number(mc, &args, 0, "floor")?.floor()),
+                };
+                Ok(CallbackResult::Return(vec![floor]))
+            }))
         }),
     )
     .unwrap();
@@ -183,23 +126,24 @@ pub fn load_math<'gc>(mc: MutationContext<'gc, '_>, _: Root<'gc>, env: Table<'gc
     math.set(
         mc,
         String::new_static(b"fmod"),
-        Callback::new_immediate(mc, |args| {
-            match (
-                args.get(0).cloned().unwrap_or(Value::Nil).to_number(),
-                args.get(1).cloned().unwrap_or(Value::Nil).to_number(),
-            ) {
-                (Some(f), Some(g)) => {
-                    let result = (f % g).abs();
-                    Ok(CallbackResult::Return(vec![Value::Number(if f < 0.0 {
-                        -result
-                    } else {
-                        result
-                    })]))
-                }
-                _ => Err(
-                    RuntimeError(Value::String(String::new_static(b"Bad argument to fmod"))).into(),
-                ),
-            }
+        Callback::new_sequence(mc, |args| {
+            Ok(sequence::from_fn_with(args, |mc, args| {
+                // The remainder is truncated towards zero, and is an Integer if both arguments are
+                let fmod = match (args.get(0), args.get(1)) {
+                    (Some(&Value::Integer(a)), Some(&Value::Integer(b))) => match b {
+                        0 => return Err(bad_argument(mc, 1, "fmod", "zero")),
+                        // Avoids overflowing on `i64::MIN % -1`
+                        -1 => Value::Integer(0),
+                        b => Value::Integer(a % b),
+                    },
+                    _ => {
+                        let a = check_number(mc, &args, 0, "fmod")?;
+                        let b = check_number(mc, &args, 1, "fmod")?;
+                        Value::Number(a % b)
+                    }
+                };
+                Ok(CallbackResult::Return(vec![fmod]))
+            }))
         }),
     )
     .unwrap();
@@ -266,13 +210,19 @@ pub fn load_math<'gc>(mc: MutationContext<'gc, '_>, _: Root<'gc>, env: Table<'gc
     math.set(
         mc,
         String::new_static(b"log"),
-        Callback::new_immediate(mc, |args| {
-            match args.get(0).cloned().unwrap_or(Value::Nil).to_number() {
-                Some(f) => Ok(CallbackResult::Return(vec![Value::Number(f.ln())])),
-                _ => Err(
-                    RuntimeError(Value::String(String::new_static(b"Bad argument to log"))).into(),
-                ),
-            }
+        Callback::new_sequence(mc, |args| {
+            Ok(sequence::from_fn_with(args, |mc, args| {
+                let x = check_number(mc, &args, 0, "log")?;
+                let log = match args.get(1).cloned().unwrap_or(Value::Nil) {
+                    Value::Nil => x.ln(),
+                    _ => match check_number(mc, &args, 1, "log")? {
+                        2.0 => x.log2(),
+                        10.0 => x.log10(),
+                        base => x.ln() / base.ln(),
+                    },
+                };
+                Ok(CallbackResult::Return(vec![Value::Number(log)]))
+            }))
         }),
     )
     .unwrap();
@@ -280,15 +230,7 @@ pub fn load_math<'gc>(mc: MutationContext<'gc, '_>, _: Root<'gc>, env: Table<'gc
     math.set(
         mc,
         String::new_static(b"log10"),
-        Callback::new_immediate(mc, |args| {
-            match args.get(0).cloned().unwrap_or(Value::Nil).to_number() {
-                Some(f) => Ok(CallbackResult::Return(vec![Value::Number(f.log10())])),
-                _ => Err(
-                    RuntimeError(Value::String(String::new_static(b"Bad argument to log10")))
-                        .into(),
-                ),
-            }
-        }),
+        float_function(mc, "log10", f64::log10),
     )
     .unwrap();
 
@@ -363,7 +305,7 @@ pub fn load_math<'gc>(mc: MutationContext<'gc, '_>, _: Root<'gc>, env: Table<'gc
         Callback::new_immediate(mc, |args| {
             match args.get(0).cloned().unwrap_or(Value::Nil).to_number() {
                 Some(f) => Ok(CallbackResult::Return(vec![
-                    Value::Integer(f as i64 / 1),
+                    Value::Integer(f as i64),
                     Value::Number(f % 1.0),
                 ])),
                 _ => Err(
@@ -384,68 +326,79 @@ pub fn load_math<'gc>(mc: MutationContext<'gc, '_>, _: Root<'gc>, env: Table<'gc
     math.set(
         mc,
         String::new_static(b"rad"),
-        Callback::new_immediate(mc, |args| {
-            match args.get(0).cloned().unwrap_or(Value::Nil).to_number() {
-                Some(f) => Ok(CallbackResult::Return(vec![Value::Number(f.to_radians())])),
-                _ => Err(
-                    RuntimeError(Value::String(String::new_static(b"Bad argument to rad"))).into(),
-                ),
-            }
-        }),
+        float_function(mc, "rad", f64::to_radians),
     )
     .unwrap();
 
-    // TODO: Random and Randomseed
-    let random_rng = seeded_rng.clone();
     math.set(
         mc,
         String::new_static(b"random"),
-        Callback::new_immediate(mc, move |args| {
-            let rng = &random_rng;
-            match (
-                args.get(0).cloned().unwrap_or(Value::Nil),
-                args.get(1).cloned().unwrap_or(Value::Nil),
-            ) {
-                (Value::Nil, Value::Nil) => Ok(CallbackResult::Return(vec![Value::Number(
-                    rng.borrow_mut().gen::<f64>(),
-                )])),
-                (a, b) => {
-                    if let (Some(first), Value::Nil) = (a.to_integer(), b) {
-                        Ok(CallbackResult::Return(vec![Value::Integer(
-                            rng.borrow_mut().gen_range(1, first + 1),
-                        )]))
-                    } else if let (Some(first), Some(second)) = (a.to_integer(), b.to_integer()) {
-                        Ok(CallbackResult::Return(vec![Value::Integer(
-                            rng.borrow_mut().gen_range(first, second + 1),
-                        )]))
-                    } else {
-                        Err(RuntimeError(Value::String(String::new_static(
-                            b"Bad argument to random",
+        Callback::new_sequence_with(mc, root.rng, |rng, args| {
+            Ok(sequence::from_fn_with((*rng, args), |mc, (rng, args)| {
+                let mut rng = rng.0.borrow_mut();
+                let (low, high) = match args.len() {
+                    0 => {
+                        return Ok(CallbackResult::Return(vec![Value::Number(
+                            rng.gen::<f64>(),
+                        )]));
+                    }
+                    1 => (1, check_integer(mc, &args, 0, "random")?),
+                    2 => (
+                        check_integer(mc, &args, 0, "random")?,
+                        check_integer(mc, &args, 1, "random")?,
+                    ),
+                    _ => {
+                        return Err(RuntimeError(Value::String(String::new_static(
+                            b"wrong number of arguments",
                         )))
-                        .into())
+                        .into());
                     }
+                };
+                if low > high {
+                    return Err(bad_argument(
+                        mc,
+                        args.len() - 1,
+                        "random",
+                        "interval is empty",
+                    ));
                 }
-            }
+
+                // The size of the interval minus one always fits in a u64, even when the
+                // interval covers every Integer.
+                let span = (high as u64).wrapping_sub(low as u64);
+                let offset = if span == u64::MAX {
+                    rng.gen::<u64>()
+                } else {
+                    rng.gen_range(0, span + 1)
+                };
+                Ok(CallbackResult::Return(vec![Value::Integer(
+                    low.wrapping_add(offset as i64),
+                )]))
+            }))
         }),
     )
     .unwrap();
 
-    let randomseed_rng = seeded_rng.clone();
     math.set(
         mc,
         String::new_static(b"randomseed"),
-        Callback::new_immediate(mc, move |args| {
-            let rng = &randomseed_rng;
-            match args.get(0).cloned().unwrap_or(Value::Nil).to_number() {
-                Some(f) => {
-                    *(rng.borrow_mut().deref_mut()) = Xoshiro256StarStar::seed_from_u64(f as u64);
-                    Ok(CallbackResult::Return(vec![]))
-                }
-                _ => Err(RuntimeError(Value::String(String::new_static(
-                    b"Bad argument to randomseed",
-                )))
-                .into()),
-            }
+        Callback::new_sequence_with(mc, root.rng, |rng, args| {
+            Ok(sequence::from_fn_with((*rng, args), |mc, (rng, args)| {
+                *rng.0.borrow_mut() = match args.get(0).cloned().unwrap_or(Value::Nil) {
+                    Value::Nil => Xoshiro256StarStar::from_entropy(),
+                    Value::Integer(seed) => Xoshiro256StarStar::seed_from_u64(seed as u64),
+                    _ => {
+                        let seed = check_number(mc, &args, 0, "randomseed")?;
+                        // Integral floats seed the same as the equal Integer
+                        let seed = match Value::Number(seed).to_integer() {
+                            Some(seed) => seed as u64,
+                            None => seed.to_bits(),
+                        };
+                        Xoshiro256StarStar::seed_from_u64(seed)
+                    }
+                };
+                Ok(CallbackResult::Return(vec![]))
+            }))
         }),
     )
     .unwrap();
@@ -453,42 +406,21 @@ pub fn load_math<'gc>(mc: MutationContext<'gc, '_>, _: Root<'gc>, env: Table<'gc
     math.set(
         mc,
         String::new_static(b"sin"),
-        Callback::new_immediate(mc, |args| {
-            match args.get(0).cloned().unwrap_or(Value::Nil).to_number() {
-                Some(f) => Ok(CallbackResult::Return(vec![Value::Number(f.sin())])),
-                _ => Err(
-                    RuntimeError(Value::String(String::new_static(b"Bad argument to sin"))).into(),
-                ),
-            }
-        }),
+        float_function(mc, "sin", f64::sin),
     )
     .unwrap();
 
     math.set(
         mc,
         String::new_static(b"sqrt"),
-        Callback::new_immediate(mc, |args| {
-            match args.get(0).cloned().unwrap_or(Value::Nil).to_number() {
-                Some(f) => Ok(CallbackResult::Return(vec![Value::Number(f.sqrt())])),
-                _ => Err(
-                    RuntimeError(Value::String(String::new_static(b"Bad argument to sqrt"))).into(),
-                ),
-            }
-        }),
+        float_function(mc, "sqrt", f64::sqrt),
     )
     .unwrap();
 
     math.set(
         mc,
         String::new_static(b"tan"),
-        Callback::new_immediate(mc, |args| {
-            match args.get(0).cloned().unwrap_or(Value::Nil).to_number() {
-                Some(f) => Ok(CallbackResult::Return(vec![Value::Number(f.tan())])),
-                _ => Err(
-                    RuntimeError(Value::String(String::new_static(b"Bad argument to tan"))).into(),
-                ),
-            }
-        }),
+        float_function(mc, "tan", f64::tan),
     )
     .unwrap();
 
@@ -496,10 +428,15 @@ pub fn load_math<'gc>(mc: MutationContext<'gc, '_>, _: Root<'gc>, env: Table<'gc
         mc,
         String::new_static(b"tointeger"),
         Callback::new_immediate(mc, |args| {
-            match args.get(0).cloned().unwrap_or(Value::Nil).to_integer() {
-                Some(f) => Ok(CallbackResult::Return(vec![Value::Integer(f)])),
-                _ => Ok(CallbackResult::Return(vec![Value::Nil])),
-            }
+            let integer = match args.get(0).cloned().unwrap_or(Value::Nil).to_numeric() {
+                Some(Value::Integer(i)) => Value::Integer(i),
+                Some(Value::Number(f)) if f.floor() == f => match float_to_integer(f) {
+                    Value::Integer(i) => Value::Integer(i),
+                    _ => Value::Nil,
+                },
+                _ => Value::Nil,
+            };
+            Ok(CallbackResult::Return(vec![integer]))
         }),
     )
     .unwrap();
@@ -542,3 +479,28 @@ pub fn load_math<'gc>(mc: MutationContext<'gc, '_>, _: Root<'gc>, env: Table<'gc
 
     env.set(mc, String::new_static(b"math"), math).unwrap();
 }
+
+// A function taking a single number argument and returning a float.
+fn float_function<'gc>(
+    mc: MutationContext<'gc, '_>,
+    name: &'static str,
+    f: fn(f64) -> f64,
+) -> Callback<'gc> {
+    Callback::new_sequence(mc, move |args| {
+        Ok(sequence::from_fn_with(args, move |mc, args| {
+            let x = check_number(mc, &args, 0, name)?;
+            Ok(CallbackResult::Return(vec![Value::Number(f(x))]))
+        }))
+    })
+}
+
+// Converts an integral float to an Integer if it is representable as one, like the results of
+// `floor` and `ceil` in PUC-Rio Lua 5.3.  Floats out of the Integer range, infinities and NaN are
+// left as floats.
+fn float_to_integer<'gc>(f: f64) -> Value<'gc> {
+    if f >= i64::MIN as f64 && f < -(i64::MIN as f64) {
+        Value::Integer(f as i64)
+    } else {
+        Value::Number(f)
+    }
+}
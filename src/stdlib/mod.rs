@@ -1,9 +1,108 @@
 mod base;
 mod coroutine;
+mod debug;
+mod format;
+mod gsub;
+mod io;
+#[cfg(feature = "json")]
+mod json;
 mod math;
+#[cfg(feature = "os")]
+mod os;
+mod pack;
+mod package;
+mod pattern;
+mod sort;
 mod string;
+mod table;
+mod utf8;
+
+use std::ops::{BitOr, BitOrAssign};
 
 pub use base::load_base;
+pub(crate) use base::{
+    bad_argument, check_any, check_integer, check_number, check_string, check_table,
+};
 pub use coroutine::load_coroutine;
+pub use debug::load_debug;
+pub use io::load_io;
+#[cfg(feature = "json")]
+pub use json::load_json;
 pub use math::load_math;
+#[cfg(feature = "os")]
+pub use os::load_os;
+pub(crate) use package::searcher_callback;
+pub use package::{load_package, Searcher};
 pub use string::load_string;
+pub use table::{load_table, load_table_extensions};
+pub use utf8::load_utf8;
+
+/// A set of standard libraries to load into a new `Lua`, built by combining the constants with `|`.
+/// Leaving libraries out of the set keeps their functions out of the globals table entirely, so
+/// that untrusted scripts only see the API they are meant to.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub struct StdlibSet(u32);
+
+impl StdlibSet {
+    /// The base library, `print`, `pcall`, `setmetatable` and the like.
+    pub const BASE: StdlibSet = StdlibSet(1 << 0);
+    pub const COROUTINE: StdlibSet = StdlibSet(1 << 1);
+    pub const MATH: StdlibSet = StdlibSet(1 << 2);
+    /// The `string` library, which is also the `__index` of the string metatable.
+    pub const STRING: StdlibSet = StdlibSet(1 << 3);
+    pub const TABLE: StdlibSet = StdlibSet(1 << 4);
+    pub const UTF8: StdlibSet = StdlibSet(1 << 5);
+    /// The `package` library and `require`.
+    pub const PACKAGE: StdlibSet = StdlibSet(1 << 6);
+    /// The `io` library, along with `dofile` and `loadfile` from the base library.
+    pub const IO: StdlibSet = StdlibSet(1 << 7);
+    pub const DEBUG: StdlibSet = StdlibSet(1 << 8);
+    #[cfg(feature = "os")]
+    pub const OS: StdlibSet = StdlibSet(1 << 9);
+    /// The `json` library, `json.encode` and `json.decode`.
+    #[cfg(feature = "json")]
+    pub const JSON: StdlibSet = StdlibSet(1 << 10);
+    /// `table.clone` and `table.equals`, which are not part of Lua 5.3.
+    pub const TABLE_EXTENSIONS: StdlibSet = StdlibSet(1 << 11);
+
+    /// Only the libraries that cannot reach outside of the interpreter: the base library without
+    /// `dofile` and `loadfile`, `coroutine`, `math`, `string`, `table` and `utf8`.
+    pub const SAFE: StdlibSet = StdlibSet(
+        StdlibSet::BASE.0
+            | StdlibSet::COROUTINE.0
+            | StdlibSet::MATH.0
+            | StdlibSet::STRING.0
+            | StdlibSet::TABLE.0
+            | StdlibSet::UTF8.0,
+    );
+
+    /// Every library, which is what `Lua::new` loads.
+    pub const ALL: StdlibSet = StdlibSet(!0);
+
+    pub const fn empty() -> StdlibSet {
+        StdlibSet(0)
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Whether every library in `other` is also in this set.
+    pub fn contains(self, other: StdlibSet) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for StdlibSet {
+    type Output = StdlibSet;
+
+    fn bitor(self, other: StdlibSet) -> StdlibSet {
+        StdlibSet(self.0 | other.0)
+    }
+}
+
+impl BitOrAssign for StdlibSet {
+    fn bitor_assign(&mut self, other: StdlibSet) {
+        self.0 |= other.0;
+    }
+}
@@ -0,0 +1,500 @@
+use std::env;
+use std::string::String as StdString;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use gc_arena::MutationContext;
+use gc_sequence as sequence;
+
+use crate::{Callback, CallbackResult, Error, Root, RuntimeError, String, Table, Value};
+
+use super::base::{bad_argument, bad_argument_type, check_integer, opt_integer};
+
+pub fn load_os<'gc>(mc: MutationContext<'gc, '_>, _: Root<'gc>, env: Table<'gc>) {
+    let os = Table::new(mc);
+
+    os.set(
+        mc,
+        String::new_static(b"time"),
+        Callback::new_sequence(mc, |args| {
+            Ok(sequence::from_fn_with(args, |mc, args| {
+                let time = match args.get(0).cloned().unwrap_or(Value::Nil) {
+                    Value::Nil => now(),
+                    Value::Table(date) => time_from_table(mc, date)?,
+                    _ => return Err(bad_argument_type(mc, &args, 0, "time", "table")),
+                };
+                Ok(CallbackResult::Return(vec![Value::Integer(time)]))
+            }))
+        }),
+    )
+    .unwrap();
+
+    let start = Instant::now();
+    os.set(
+        mc,
+        String::new_static(b"clock"),
+        Callback::new_immediate(mc, move |_| {
+            Ok(CallbackResult::Return(vec![Value::Number(clock(start))]))
+        }),
+    )
+    .unwrap();
+
+    os.set(
+        mc,
+        String::new_static(b"date"),
+        Callback::new_sequence(mc, |args| {
+            Ok(sequence::from_fn_with(args, |mc, args| {
+                let format = match args.get(0).cloned().unwrap_or(Value::Nil) {
+                    Value::Nil => String::new_static(b"%c"),
+                    format => format
+                        .to_string(mc)
+                        .ok_or_else(|| bad_argument_type(mc, &args, 0, "date", "string"))?,
+                };
+                let time = match args.get(1).cloned().unwrap_or(Value::Nil) {
+                    Value::Nil => now(),
+                    _ => opt_integer(mc, &args, 1, "date", 0)?,
+                };
+
+                let mut format = format.as_bytes();
+                let date = if format.first() == Some(&b'!') {
+                    format = &format[1..];
+                    DateTime::utc(time)
+                } else {
+                    DateTime::local(time).ok_or_else(|| {
+                        runtime_error(mc, "date result cannot be represented in this installation")
+                    })?
+                };
+
+                if format.starts_with(b"*t") {
+                    return Ok(CallbackResult::Return(vec![Value::Table(
+                        date.to_table(mc)?,
+                    )]));
+                }
+
+                let mut formatted = Vec::new();
+                date.format(format, &mut formatted).map_err(|specifier| {
+                    let message = format!(
+                        "invalid conversion specifier '%{}'",
+                        StdString::from_utf8_lossy(specifier)
+                    );
+                    bad_argument(mc, 0, "date", &message)
+                })?;
+                Ok(CallbackResult::Return(vec![Value::String(String::new(
+                    mc, &formatted,
+                ))]))
+            }))
+        }),
+    )
+    .unwrap();
+
+    os.set(
+        mc,
+        String::new_static(b"difftime"),
+        Callback::new_sequence(mc, |args| {
+            Ok(sequence::from_fn_with(args, |mc, args| {
+                let t2 = check_integer(mc, &args, 0, "difftime")?;
+                let t1 = check_integer(mc, &args, 1, "difftime")?;
+                Ok(CallbackResult::Return(vec![Value::Number(
+                    t2 as f64 - t1 as f64,
+                )]))
+            }))
+        }),
+    )
+    .unwrap();
+
+    os.set(
+        mc,
+        String::new_static(b"getenv"),
+        Callback::new_sequence(mc, |args| {
+            Ok(sequence::from_fn_with(args, |mc, args| {
+                let name = args
+                    .get(0)
+                    .cloned()
+                    .unwrap_or(Value::Nil)
+                    .to_string(mc)
+                    .ok_or_else(|| bad_argument_type(mc, &args, 0, "getenv", "string"))?;
+                let name = StdString::from_utf8_lossy(name.as_bytes());
+
+                // Names that can never be set are looked up by the standard library with a panic
+                if name.is_empty() || name.contains(|c| c == '=' || c == '\0') {
+                    return Ok(CallbackResult::Return(vec![Value::Nil]));
+                }
+                Ok(CallbackResult::Return(vec![match env::var_os(&*name) {
+                    Some(value) => Value::String(String::new(mc, &os_str_bytes(&value))),
+                    None => Value::Nil,
+                }]))
+            }))
+        }),
+    )
+    .unwrap();
+
+    env.set(mc, String::new_static(b"os"), os).unwrap();
+}
+
+// A broken down time, with fields numbered the same way as the C `struct tm`.
+struct DateTime {
+    year: i64,
+    // 0 to 11
+    month: i64,
+    // 1 to 31
+    day: i64,
+    hour: i64,
+    min: i64,
+    sec: i64,
+    // Days since Sunday, 0 to 6
+    wday: i64,
+    // Days since January 1st, 0 to 365
+    yday: i64,
+    isdst: bool,
+    utc_offset: i64,
+    zone: StdString,
+}
+
+const WEEKDAYS: [&str; 7] = [
+    "Sunday",
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+];
+
+const MONTHS: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+impl DateTime {
+    fn utc(time: i64) -> DateTime {
+        let days = time.div_euclid(86400);
+        let secs = time.rem_euclid(86400);
+        let (year, month, day) = civil_from_days(days);
+        DateTime {
+            year,
+            month: month - 1,
+            day,
+            hour: secs / 3600,
+            min: secs / 60 % 60,
+            sec: secs % 60,
+            // 1970-01-01 was a Thursday
+            wday: (days + 4).rem_euclid(7),
+            yday: days - days_from_civil(year, 1, 1),
+            isdst: false,
+            utc_offset: 0,
+            zone: "UTC".to_owned(),
+        }
+    }
+
+    #[cfg(unix)]
+    fn local(time: i64) -> Option<DateTime> {
+        use std::ffi::CStr;
+
+        let t = time as libc::time_t;
+        if t as i64 != time {
+            return None;
+        }
+        let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+        if unsafe { libc::localtime_r(&t, &mut tm) }.is_null() {
+            return None;
+        }
+
+        Some(DateTime {
+            year: tm.tm_year as i64 + 1900,
+            month: tm.tm_mon as i64,
+            day: tm.tm_mday as i64,
+            hour: tm.tm_hour as i64,
+            min: tm.tm_min as i64,
+            sec: tm.tm_sec as i64,
+            wday: tm.tm_wday as i64,
+            yday: tm.tm_yday as i64,
+            isdst: tm.tm_isdst > 0,
+            utc_offset: tm.tm_gmtoff as i64,
+            zone: if tm.tm_zone.is_null() {
+                StdString::new()
+            } else {
+                unsafe { CStr::from_ptr(tm.tm_zone) }
+                    .to_string_lossy()
+                    .into_owned()
+            },
+        })
+    }
+
+    // Without a portable way to find the local time zone, local time is UTC
+    #[cfg(not(unix))]
+    fn local(time: i64) -> Option<DateTime> {
+        Some(DateTime::utc(time))
+    }
+
+    fn to_table<'gc>(&self, mc: MutationContext<'gc, '_>) -> Result<Table<'gc>, Error<'gc>> {
+        let table = Table::new(mc);
+        for &(key, value) in &[
+            (&b"year"[..], self.year),
+            (b"month", self.month + 1),
+            (b"day", self.day),
+            (b"hour", self.hour),
+            (b"min", self.min),
+            (b"sec", self.sec),
+            (b"wday", self.wday + 1),
+            (b"yday", self.yday + 1),
+        ] {
+            table.set(mc, String::new_static(key), value)?;
+        }
+        table.set(mc, String::new_static(b"isdst"), self.isdst)?;
+        Ok(table)
+    }
+
+    // Formats the time like C `strftime` in the "C" locale, returning the conversion specifier that
+    // is not valid if there is one.
+    fn format<'a>(&self, format: &'a [u8], out: &mut Vec<u8>) -> Result<(), &'a [u8]> {
+        let mut i = 0;
+        while i < format.len() {
+            if format[i] != b'%' {
+                out.push(format[i]);
+                i += 1;
+                continue;
+            }
+
+            // The 'E' and 'O' modifiers select alternative representations, which are the same as
+            // the normal ones in the "C" locale
+            let (specifier, len) = match format.get(i + 1..i + 3) {
+                Some(&[b'E', c]) if b"cCxXyY".contains(&c) => (c, 3),
+                Some(&[b'O', c]) if b"deHImMSuUVwWy".contains(&c) => (c, 3),
+                _ => match format.get(i + 1) {
+                    Some(&c) if c != b'E' && c != b'O' => (c, 2),
+                    _ => return Err(&format[i + 1..(i + 3).min(format.len())]),
+                },
+            };
+            if !self.format_specifier(specifier, out) {
+                return Err(&format[i + 1..i + len]);
+            }
+            i += len;
+        }
+        Ok(())
+    }
+
+    fn format_specifier(&self, specifier: u8, out: &mut Vec<u8>) -> bool {
+        let formatted = match specifier {
+            b'a' => WEEKDAYS[self.wday as usize][..3].to_owned(),
+            b'A' => WEEKDAYS[self.wday as usize].to_owned(),
+            b'b' | b'h' => MONTHS[self.month as usize][..3].to_owned(),
+            b'B' => MONTHS[self.month as usize].to_owned(),
+            b'c' => return self.format_all(b"%a %b %e %H:%M:%S %Y", out),
+            b'C' => format!("{:02}", self.year.div_euclid(100)),
+            b'd' => format!("{:02}", self.day),
+            b'D' | b'x' => return self.format_all(b"%m/%d/%y", out),
+            b'e' => format!("{:2}", self.day),
+            b'F' => return self.format_all(b"%Y-%m-%d", out),
+            b'g' => format!("{:02}", self.iso_week().0.rem_euclid(100)),
+            b'G' => format!("{}", self.iso_week().0),
+            b'H' => format!("{:02}", self.hour),
+            b'I' => format!("{:02}", (self.hour + 11) % 12 + 1),
+            b'j' => format!("{:03}", self.yday + 1),
+            b'm' => format!("{:02}", self.month + 1),
+            b'M' => format!("{:02}", self.min),
+            b'n' => "\n".to_owned(),
+            b'p' => if self.hour < 12 { "AM" } else { "PM" }.to_owned(),
+            b'r' => return self.format_all(b"%I:%M:%S %p", out),
+            b'R' => return self.format_all(b"%H:%M", out),
+            b'S' => format!("{:02}", self.sec),
+            b't' => "\t".to_owned(),
+            b'T' | b'X' => return self.format_all(b"%H:%M:%S", out),
+            b'u' => format!("{}", (self.wday + 6) % 7 + 1),
+            b'U' => format!("{:02}", (self.yday + 7 - self.wday) / 7),
+            b'V' => format!("{:02}", self.iso_week().1),
+            b'w' => format!("{}", self.wday),
+            b'W' => format!("{:02}", (self.yday + 7 - (self.wday + 6) % 7) / 7),
+            b'y' => format!("{:02}", self.year.rem_euclid(100)),
+            b'Y' => format!("{}", self.year),
+            b'z' => {
+                let offset = self.utc_offset / 60;
+                let sign = if offset < 0 { '-' } else { '+' };
+                format!("{}{:02}{:02}", sign, offset.abs() / 60, offset.abs() % 60)
+            }
+            b'Z' => self.zone.clone(),
+            b'%' => "%".to_owned(),
+            _ => return false,
+        };
+        out.extend(formatted.as_bytes());
+        true
+    }
+
+    fn format_all(&self, format: &[u8], out: &mut Vec<u8>) -> bool {
+        self.format(format, out).is_ok()
+    }
+
+    // The ISO 8601 week-based year and week number, where weeks start on Monday and the first week
+    // of the year is the one containing its first Thursday.
+    fn iso_week(&self) -> (i64, i64) {
+        let weekday = (self.wday + 6) % 7;
+        let week = (self.yday - weekday + 10) / 7;
+        if week < 1 {
+            (self.year - 1, iso_weeks_in_year(self.year - 1))
+        } else if week > iso_weeks_in_year(self.year) {
+            (self.year + 1, 1)
+        } else {
+            (self.year, week)
+        }
+    }
+}
+
+fn iso_weeks_in_year(year: i64) -> i64 {
+    // The weekday of December 31st, with Monday as 1
+    let p = |y: i64| (y + y.div_euclid(4) - y.div_euclid(100) + y.div_euclid(400)).rem_euclid(7);
+    if p(year) == 4 || p(year - 1) == 3 {
+        53
+    } else {
+        52
+    }
+}
+
+// The number of days since 1970-01-01 of the given date in the proleptic Gregorian calendar, with
+// months from 1 to 12.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+// The inverse of `days_from_civil`.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+// Converts a table with the fields returned by `os.date("*t")` into a time, where out of range
+// fields are normalized the same way as by C `mktime`.
+fn time_from_table<'gc>(mc: MutationContext<'gc, '_>, date: Table<'gc>) -> Result<i64, Error<'gc>> {
+    let field = |key: &'static [u8], default: Option<i64>, delta: i64| {
+        let name = StdString::from_utf8_lossy(key);
+        match date.get(String::new_static(key)) {
+            Value::Nil => default.ok_or_else(|| {
+                runtime_error(mc, &format!("field '{}' missing in date table", name))
+            }),
+            value => match value.to_integer() {
+                // Like PUC-Rio Lua, fields must fit in a C int
+                Some(i) if i.checked_sub(delta).map_or(false, |i| i as i32 as i64 == i) => Ok(i),
+                Some(_) => Err(runtime_error(
+                    mc,
+                    &format!("field '{}' is out-of-bound", name),
+                )),
+                None => Err(runtime_error(
+                    mc,
+                    &format!("field '{}' is not an integer", name),
+                )),
+            },
+        }
+    };
+
+    let year = field(b"year", None, 1900)?;
+    let month = field(b"month", None, 1)?;
+    let day = field(b"day", None, 0)?;
+    let hour = field(b"hour", Some(12), 0)?;
+    let min = field(b"min", Some(0), 0)?;
+    let sec = field(b"sec", Some(0), 0)?;
+    let isdst = match date.get(String::new_static(b"isdst")) {
+        Value::Nil => None,
+        isdst => Some(isdst.to_bool()),
+    };
+
+    local_time(year, month - 1, day, hour, min, sec, isdst)
+        .ok_or_else(|| runtime_error(mc, "time result cannot be represented in this installation"))
+}
+
+#[cfg(unix)]
+fn local_time(
+    year: i64,
+    month: i64,
+    day: i64,
+    hour: i64,
+    min: i64,
+    sec: i64,
+    isdst: Option<bool>,
+) -> Option<i64> {
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    tm.tm_year = (year - 1900) as libc::c_int;
+    tm.tm_mon = month as libc::c_int;
+    tm.tm_mday = day as libc::c_int;
+    tm.tm_hour = hour as libc::c_int;
+    tm.tm_min = min as libc::c_int;
+    tm.tm_sec = sec as libc::c_int;
+    tm.tm_isdst = match isdst {
+        None => -1,
+        Some(isdst) => isdst as libc::c_int,
+    };
+    match unsafe { libc::mktime(&mut tm) } {
+        -1 => None,
+        time => Some(time),
+    }
+}
+
+#[cfg(not(unix))]
+fn local_time(
+    year: i64,
+    month: i64,
+    day: i64,
+    hour: i64,
+    min: i64,
+    sec: i64,
+    _: Option<bool>,
+) -> Option<i64> {
+    let days = days_from_civil(year + month.div_euclid(12), month.rem_euclid(12) + 1, 1) + day - 1;
+    Some(days * 86400 + hour * 3600 + min * 60 + sec)
+}
+
+fn now() -> i64 {
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(since) => since.as_secs() as i64,
+        Err(err) => -(err.duration().as_secs() as i64),
+    }
+}
+
+// The processor time used by the program in seconds.
+#[cfg(unix)]
+fn clock(_: Instant) -> f64 {
+    let mut time: libc::timespec = unsafe { std::mem::zeroed() };
+    unsafe { libc::clock_gettime(libc::CLOCK_PROCESS_CPUTIME_ID, &mut time) };
+    time.tv_sec as f64 + time.tv_nsec as f64 / 1e9
+}
+
+// Without a portable way to measure processor time, the time elapsed since the library was loaded
+#[cfg(not(unix))]
+fn clock(start: Instant) -> f64 {
+    start.elapsed().as_secs_f64()
+}
+
+#[cfg(unix)]
+fn os_str_bytes(s: &std::ffi::OsStr) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+    s.as_bytes().to_vec()
+}
+
+#[cfg(not(unix))]
+fn os_str_bytes(s: &std::ffi::OsStr) -> Vec<u8> {
+    s.to_string_lossy().into_owned().into_bytes()
+}
+
+fn runtime_error<'gc>(mc: MutationContext<'gc, '_>, message: &str) -> Error<'gc> {
+    RuntimeError(Value::String(String::new(mc, message.as_bytes()))).into()
+}
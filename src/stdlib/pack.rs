@@ -0,0 +1,479 @@
+use gc_arena::MutationContext;
+
+use crate::{CallbackResult, Error, RuntimeError, String, Value};
+
+use super::base::{bad_argument, check_integer, check_number, check_string, opt_integer};
+
+// The size in bytes of a Lua integer, and the largest size of an integer that can be packed.
+const INTEGER_SIZE: usize = 8;
+const MAX_INTEGER_SIZE: usize = 16;
+
+// The alignment used by '!' without a size, matching the strictest alignment of a C type.
+const NATIVE_ALIGN: usize = 8;
+
+// The largest total size of a format, which bounds the result of `packsize`.
+const MAX_SIZE: usize = i32::MAX as usize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Int { signed: bool },
+    Float,
+    Double,
+    // A fixed size string, 'c'
+    Char,
+    // A string preceded by its length, 's'
+    String,
+    // A zero terminated string, 'z'
+    ZString,
+    // A single padding byte, 'x'
+    Padding,
+    // Padding up to the alignment of the following option, 'X'
+    PadAlign,
+    // Options that only change the state of the format
+    Nop,
+}
+
+struct FormatOption {
+    kind: Kind,
+    size: usize,
+    align_padding: usize,
+}
+
+// Reads the options of a format string one at a time, keeping track of the endianness and maximum
+// alignment that the options select.
+struct Format<'a> {
+    format: &'a [u8],
+    position: usize,
+    little_endian: bool,
+    max_align: usize,
+}
+
+impl<'a> Format<'a> {
+    fn new(format: &'a [u8]) -> Format<'a> {
+        Format {
+            format,
+            position: 0,
+            little_endian: cfg!(target_endian = "little"),
+            max_align: 1,
+        }
+    }
+
+    // Returns the next option along with the padding needed to align it, given the number of bytes
+    // already produced by the format.
+    fn next<'gc>(
+        &mut self,
+        mc: MutationContext<'gc, '_>,
+        function: &str,
+        total_size: usize,
+    ) -> Result<Option<FormatOption>, Error<'gc>> {
+        let (kind, size) = match self.option(mc)? {
+            Some(option) => option,
+            None => return Ok(None),
+        };
+
+        let mut align = size;
+        if kind == Kind::PadAlign {
+            match self.option(mc)? {
+                Some((next, next_size)) if next != Kind::Char && next_size != 0 => {
+                    align = next_size
+                }
+                _ => {
+                    return Err(bad_argument(
+                        mc,
+                        0,
+                        function,
+                        "invalid next option for option 'X'",
+                    ))
+                }
+            }
+        }
+
+        let align_padding = if align <= 1 || kind == Kind::Char {
+            0
+        } else {
+            let align = align.min(self.max_align);
+            if !align.is_power_of_two() {
+                return Err(bad_argument(
+                    mc,
+                    0,
+                    function,
+                    "format asks for alignment not power of 2",
+                ));
+            }
+            (align - (total_size & (align - 1))) & (align - 1)
+        };
+
+        Ok(Some(FormatOption {
+            kind,
+            size,
+            align_padding,
+        }))
+    }
+
+    // Reads a single option and its size, without any alignment.
+    fn option<'gc>(
+        &mut self,
+        mc: MutationContext<'gc, '_>,
+    ) -> Result<Option<(Kind, usize)>, Error<'gc>> {
+        let c = match self.format.get(self.position) {
+            Some(&c) => c,
+            None => return Ok(None),
+        };
+        self.position += 1;
+
+        Ok(Some(match c {
+            b'b' => (Kind::Int { signed: true }, 1),
+            b'B' => (Kind::Int { signed: false }, 1),
+            b'h' => (Kind::Int { signed: true }, 2),
+            b'H' => (Kind::Int { signed: false }, 2),
+            b'l' | b'j' => (Kind::Int { signed: true }, 8),
+            b'L' | b'J' | b'T' => (Kind::Int { signed: false }, 8),
+            b'f' => (Kind::Float, 4),
+            b'd' | b'n' => (Kind::Double, 8),
+            b'i' => (Kind::Int { signed: true }, self.integer_size(mc, 4)?),
+            b'I' => (Kind::Int { signed: false }, self.integer_size(mc, 4)?),
+            b's' => (Kind::String, self.integer_size(mc, 8)?),
+            b'c' => match self.number() {
+                Some(size) => (Kind::Char, size),
+                None => return Err(runtime_error(mc, "missing size for format option 'c'")),
+            },
+            b'z' => (Kind::ZString, 0),
+            b'x' => (Kind::Padding, 1),
+            b'X' => (Kind::PadAlign, 0),
+            b' ' => (Kind::Nop, 0),
+            b'<' => {
+                self.little_endian = true;
+                (Kind::Nop, 0)
+            }
+            b'>' => {
+                self.little_endian = false;
+                (Kind::Nop, 0)
+            }
+            b'=' => {
+                self.little_endian = cfg!(target_endian = "little");
+                (Kind::Nop, 0)
+            }
+            b'!' => {
+                self.max_align = self.integer_size(mc, NATIVE_ALIGN)?;
+                (Kind::Nop, 0)
+            }
+            c => {
+                return Err(runtime_error(
+                    mc,
+                    &format!("invalid format option '{}'", c as char),
+                ))
+            }
+        }))
+    }
+
+    // Reads an optional decimal number following an option.
+    fn number(&mut self) -> Option<usize> {
+        let start = self.position;
+        let mut n: usize = 0;
+        while let Some(&c) = self.format.get(self.position) {
+            if !c.is_ascii_digit() || n > (MAX_SIZE - 9) / 10 {
+                break;
+            }
+            n = n * 10 + (c - b'0') as usize;
+            self.position += 1;
+        }
+        if self.position == start {
+            None
+        } else {
+            Some(n)
+        }
+    }
+
+    // Reads the optional size of an integer option, which must be between 1 and 16 bytes.
+    fn integer_size<'gc>(
+        &mut self,
+        mc: MutationContext<'gc, '_>,
+        default: usize,
+    ) -> Result<usize, Error<'gc>> {
+        let size = self.number().unwrap_or(default);
+        if size < 1 || size > MAX_INTEGER_SIZE {
+            return Err(runtime_error(
+                mc,
+                &format!(
+                    "integral size ({}) out of limits [1,{}]",
+                    size, MAX_INTEGER_SIZE
+                ),
+            ));
+        }
+        Ok(size)
+    }
+}
+
+/// Implements `string.pack`.
+pub(crate) fn string_pack<'gc>(
+    mc: MutationContext<'gc, '_>,
+    args: Vec<Value<'gc>>,
+) -> Result<CallbackResult<'gc>, Error<'gc>> {
+    let format = check_string(mc, &args, 0, "pack")?;
+    let mut format = Format::new(format.as_bytes());
+    let mut output = Vec::new();
+    let mut arg = 0;
+
+    while let Some(option) = format.next(mc, "pack", output.len())? {
+        output.resize(output.len() + option.align_padding, 0);
+        match option.kind {
+            Kind::Int { signed } => {
+                arg += 1;
+                let n = check_integer(mc, &args, arg, "pack")?;
+                if option.size < INTEGER_SIZE {
+                    let bits = option.size * 8;
+                    if signed {
+                        let limit = 1i64 << (bits - 1);
+                        if n < -limit || n >= limit {
+                            return Err(bad_argument(mc, arg, "pack", "integer overflow"));
+                        }
+                    } else if (n as u64) >= 1u64 << bits {
+                        return Err(bad_argument(mc, arg, "pack", "unsigned overflow"));
+                    }
+                }
+                pack_integer(
+                    &mut output,
+                    n,
+                    format.little_endian,
+                    option.size,
+                    signed && n < 0,
+                );
+            }
+            Kind::Float => {
+                arg += 1;
+                let n = check_number(mc, &args, arg, "pack")? as f32;
+                push_bytes(&mut output, &n.to_le_bytes(), format.little_endian);
+            }
+            Kind::Double => {
+                arg += 1;
+                let n = check_number(mc, &args, arg, "pack")?;
+                push_bytes(&mut output, &n.to_le_bytes(), format.little_endian);
+            }
+            Kind::Char => {
+                arg += 1;
+                let s = check_string(mc, &args, arg, "pack")?;
+                let s = s.as_bytes();
+                if s.len() > option.size {
+                    return Err(bad_argument(
+                        mc,
+                        arg,
+                        "pack",
+                        "string longer than given size",
+                    ));
+                }
+                output.extend_from_slice(s);
+                output.resize(output.len() + option.size - s.len(), 0);
+            }
+            Kind::String => {
+                arg += 1;
+                let s = check_string(mc, &args, arg, "pack")?;
+                let s = s.as_bytes();
+                if option.size < INTEGER_SIZE && (s.len() as u64) >= 1u64 << (option.size * 8) {
+                    return Err(bad_argument(
+                        mc,
+                        arg,
+                        "pack",
+                        "string length does not fit in given size",
+                    ));
+                }
+                pack_integer(
+                    &mut output,
+                    s.len() as i64,
+                    format.little_endian,
+                    option.size,
+                    false,
+                );
+                output.extend_from_slice(s);
+            }
+            Kind::ZString => {
+                arg += 1;
+                let s = check_string(mc, &args, arg, "pack")?;
+                let s = s.as_bytes();
+                if s.contains(&0) {
+                    return Err(bad_argument(mc, arg, "pack", "string contains zeros"));
+                }
+                output.extend_from_slice(s);
+                output.push(0);
+            }
+            Kind::Padding => output.push(0),
+            Kind::PadAlign | Kind::Nop => {}
+        }
+    }
+
+    Ok(CallbackResult::Return(vec![Value::String(String::new(
+        mc, &output,
+    ))]))
+}
+
+/// Implements `string.packsize`.
+pub(crate) fn string_packsize<'gc>(
+    mc: MutationContext<'gc, '_>,
+    args: Vec<Value<'gc>>,
+) -> Result<CallbackResult<'gc>, Error<'gc>> {
+    let format = check_string(mc, &args, 0, "packsize")?;
+    let mut format = Format::new(format.as_bytes());
+    let mut total_size = 0;
+
+    while let Some(option) = format.next(mc, "packsize", total_size)? {
+        if option.kind == Kind::String || option.kind == Kind::ZString {
+            return Err(bad_argument(mc, 0, "packsize", "variable-length format"));
+        }
+        let size = option.align_padding + option.size;
+        if total_size > MAX_SIZE - size {
+            return Err(bad_argument(mc, 0, "packsize", "format result too large"));
+        }
+        total_size += size;
+    }
+
+    Ok(CallbackResult::Return(vec![Value::Integer(
+        total_size as i64,
+    )]))
+}
+
+/// Implements `string.unpack`.  The values are followed by the position after the last byte read.
+pub(crate) fn string_unpack<'gc>(
+    mc: MutationContext<'gc, '_>,
+    args: Vec<Value<'gc>>,
+) -> Result<CallbackResult<'gc>, Error<'gc>> {
+    let format = check_string(mc, &args, 0, "unpack")?;
+    let data = check_string(mc, &args, 1, "unpack")?;
+    let data = data.as_bytes();
+    let init = opt_integer(mc, &args, 2, "unpack", 1)?;
+    let init = if init >= 0 {
+        init
+    } else if init.wrapping_neg() as u64 > data.len() as u64 {
+        0
+    } else {
+        data.len() as i64 + init + 1
+    };
+    if init < 1 || init - 1 > data.len() as i64 {
+        return Err(bad_argument(
+            mc,
+            2,
+            "unpack",
+            "initial position out of string",
+        ));
+    }
+
+    let mut format = Format::new(format.as_bytes());
+    let mut position = init as usize - 1;
+    let mut results = Vec::new();
+
+    while let Some(option) = format.next(mc, "unpack", position)? {
+        if option.align_padding + option.size > data.len() - position {
+            return Err(bad_argument(mc, 1, "unpack", "data string too short"));
+        }
+        position += option.align_padding;
+        let bytes = &data[position..position + option.size];
+
+        match option.kind {
+            Kind::Int { signed } => {
+                let n = unpack_integer(bytes, format.little_endian, signed).ok_or_else(|| {
+                    runtime_error(
+                        mc,
+                        &format!("{}-byte integer does not fit into Lua Integer", option.size),
+                    )
+                })?;
+                results.push(Value::Integer(n));
+            }
+            Kind::Float => {
+                let mut buf = [0; 4];
+                copy_bytes(&mut buf, bytes, format.little_endian);
+                results.push(Value::Number(f32::from_le_bytes(buf) as f64));
+            }
+            Kind::Double => {
+                let mut buf = [0; 8];
+                copy_bytes(&mut buf, bytes, format.little_endian);
+                results.push(Value::Number(f64::from_le_bytes(buf)));
+            }
+            Kind::Char => results.push(Value::String(String::new(mc, bytes))),
+            Kind::String => {
+                let len = unpack_integer(bytes, format.little_endian, false)
+                    .map(|len| len as u64)
+                    .filter(|&len| len <= (data.len() - position - option.size) as u64)
+                    .ok_or_else(|| bad_argument(mc, 1, "unpack", "data string too short"))?
+                    as usize;
+                let start = position + option.size;
+                results.push(Value::String(String::new(mc, &data[start..start + len])));
+                position += len;
+            }
+            Kind::ZString => {
+                let len = data[position..]
+                    .iter()
+                    .position(|&c| c == 0)
+                    .ok_or_else(|| {
+                        bad_argument(mc, 1, "unpack", "unfinished string for format 'z'")
+                    })?;
+                results.push(Value::String(String::new(
+                    mc,
+                    &data[position..position + len],
+                )));
+                position += len + 1;
+            }
+            Kind::Padding | Kind::PadAlign | Kind::Nop => {}
+        }
+        position += option.size;
+    }
+
+    results.push(Value::Integer(position as i64 + 1));
+    Ok(CallbackResult::Return(results))
+}
+
+// Appends an integer of the given size, extending negative numbers with 0xff bytes when the size is
+// larger than a Lua integer.
+fn pack_integer(output: &mut Vec<u8>, n: i64, little_endian: bool, size: usize, negative: bool) {
+    let mut bytes = [if negative { 0xff } else { 0 }; MAX_INTEGER_SIZE];
+    let len = size.min(INTEGER_SIZE);
+    bytes[..len].copy_from_slice(&n.to_le_bytes()[..len]);
+    push_bytes(output, &bytes[..size], little_endian);
+}
+
+// Reads an integer of any size.  Bytes beyond the size of a Lua integer must only extend its sign,
+// and the result is `None` otherwise.
+fn unpack_integer(bytes: &[u8], little_endian: bool, signed: bool) -> Option<i64> {
+    let mut buf = [0; MAX_INTEGER_SIZE];
+    copy_bytes(&mut buf[..bytes.len()], bytes, little_endian);
+    let size = bytes.len();
+
+    let mut n = [0; INTEGER_SIZE];
+    let len = size.min(INTEGER_SIZE);
+    n[..len].copy_from_slice(&buf[..len]);
+    let mut n = u64::from_le_bytes(n);
+
+    if size < INTEGER_SIZE {
+        if signed {
+            let shift = 64 - size * 8;
+            n = (((n << shift) as i64) >> shift) as u64;
+        }
+    } else if size > INTEGER_SIZE {
+        let extension = if signed && (n as i64) < 0 { 0xff } else { 0 };
+        if buf[INTEGER_SIZE..size].iter().any(|&b| b != extension) {
+            return None;
+        }
+    }
+    Some(n as i64)
+}
+
+// Appends little endian bytes in the requested byte order.
+fn push_bytes(output: &mut Vec<u8>, bytes: &[u8], little_endian: bool) {
+    if little_endian {
+        output.extend_from_slice(bytes);
+    } else {
+        output.extend(bytes.iter().rev());
+    }
+}
+
+// Copies bytes in the given byte order into a little endian buffer of the same length.
+fn copy_bytes(buf: &mut [u8], bytes: &[u8], little_endian: bool) {
+    if little_endian {
+        buf.copy_from_slice(bytes);
+    } else {
+        for (b, &c) in buf.iter_mut().zip(bytes.iter().rev()) {
+            *b = c;
+        }
+    }
+}
+
+fn runtime_error<'gc>(mc: MutationContext<'gc, '_>, message: &str) -> Error<'gc> {
+    RuntimeError(Value::String(String::new(mc, message.as_bytes()))).into()
+}
@@ -0,0 +1,430 @@
+use std::fs::File;
+use std::string::String as StdString;
+
+use gc_arena::{Gc, MutationContext, StaticCollect};
+use gc_sequence as sequence;
+
+use crate::{
+    Callback, CallbackResult, Continuation, Error, Function, Root, RuntimeError, String, Table,
+    TypeError, Value,
+};
+
+use super::base::{bad_argument_type, load_chunk, read_file};
+
+const DEFAULT_PATH: &[u8] = b"./?.lua;./?/init.lua";
+
+/// A source of modules for `require`, such as modules compiled into the program or stored in an
+/// archive.  Searchers are added to the end of `package.searchers` with `Lua::add_searcher`.
+///
+/// Modules that are Rust functions rather than Lua chunks do not need a searcher, they can be put in
+/// `package.preload`.
+pub trait Searcher {
+    /// Finds the module with the given name, returning the chunk name to compile it with and its
+    /// contents, which may be either source code or a precompiled chunk.  If the module is not found,
+    /// returns a message saying where it was looked for, such as "no file 'name.lua' in archive".
+    fn search(&self, name: &[u8]) -> Result<(StdString, Vec<u8>), StdString>;
+}
+
+pub fn load_package<'gc>(mc: MutationContext<'gc, '_>, root: Root<'gc>, env: Table<'gc>) {
+    let package = root.package;
+
+    package
+        .set(
+            mc,
+            String::new_static(b"path"),
+            String::new_static(DEFAULT_PATH),
+        )
+        .unwrap();
+    package
+        .set(
+            mc,
+            String::new_static(b"config"),
+            String::new_static(b"/\n;\n?\n!\n-\n"),
+        )
+        .unwrap();
+
+    let loaded = Table::new(mc);
+    loaded.set(mc, String::new_static(b"_G"), env).unwrap();
+    loaded
+        .set(mc, String::new_static(b"package"), package)
+        .unwrap();
+    for &name in &[&b"coroutine"[..], b"math", b"string", b"table"] {
+        if let Value::Table(library) = env.get(String::new_static(name)) {
+            loaded.set(mc, String::new_static(name), library).unwrap();
+        }
+    }
+    package
+        .set(mc, String::new_static(b"loaded"), loaded)
+        .unwrap();
+
+    package
+        .set(mc, String::new_static(b"preload"), Table::new(mc))
+        .unwrap();
+
+    let searchers = Table::new(mc);
+    searchers
+        .set(
+            mc,
+            1,
+            Callback::new_sequence_with(mc, package, |package, args| {
+                Ok(sequence::from_fn_with(
+                    (*package, args),
+                    |mc, (package, args)| {
+                        let name = check_name(mc, &args)?;
+                        let preload = match package.get(String::new_static(b"preload")) {
+                            Value::Table(preload) => preload,
+                            _ => {
+                                return Err(package_error(mc, "'package.preload' must be a table"))
+                            }
+                        };
+
+                        Ok(CallbackResult::Return(match preload.get(name) {
+                            Value::Nil => {
+                                let mut message = b"no field package.preload['".to_vec();
+                                message.extend(name.as_bytes());
+                                message.extend(b"']");
+                                vec![Value::String(String::new(mc, &message))]
+                            }
+                            loader => vec![loader, Value::String(String::new_static(b":preload:"))],
+                        }))
+                    },
+                ))
+            }),
+        )
+        .unwrap();
+    searchers
+        .set(
+            mc,
+            2,
+            Callback::new_sequence_with(mc, root, |root, args| {
+                Ok(sequence::from_fn_with((*root, args), |mc, (root, args)| {
+                    let name = check_name(mc, &args)?;
+                    let path = match root.package.get(String::new_static(b"path")) {
+                        Value::String(path) => path,
+                        _ => return Err(package_error(mc, "'package.path' must be a string")),
+                    };
+
+                    match search_path(name.as_bytes(), path.as_bytes(), b".", b"/") {
+                        Ok(filename) => {
+                            let filename = StdString::from_utf8_lossy(&filename).into_owned();
+                            let source = format!("file '{}'", filename);
+                            let chunk = read_file(&filename).map_err(|err| {
+                                loading_error(mc, name, &source, err.to_string().as_bytes())
+                            })?;
+                            let chunk_name = format!("@{}", filename);
+                            module_loader(mc, root, name, &chunk_name, &chunk, &source, &filename)
+                        }
+                        Err(message) => Ok(CallbackResult::Return(vec![Value::String(
+                            String::new(mc, &message),
+                        )])),
+                    }
+                }))
+            }),
+        )
+        .unwrap();
+    package
+        .set(mc, String::new_static(b"searchers"), searchers)
+        .unwrap();
+
+    package
+        .set(
+            mc,
+            String::new_static(b"searchpath"),
+            Callback::new_sequence(mc, |args| {
+                Ok(sequence::from_fn_with(args, |mc, args| {
+                    let check_string = |n: usize| {
+                        args.get(n)
+                            .cloned()
+                            .unwrap_or(Value::Nil)
+                            .to_string(mc)
+                            .ok_or_else(|| bad_argument_type(mc, &args, n, "searchpath", "string"))
+                    };
+                    let opt_string = |n: usize, default: &'static [u8]| match args.get(n) {
+                        None | Some(Value::Nil) => Ok(String::new_static(default)),
+                        Some(_) => check_string(n),
+                    };
+
+                    let name = check_string(0)?;
+                    let path = check_string(1)?;
+                    let sep = opt_string(2, b".")?;
+                    let rep = opt_string(3, b"/")?;
+
+                    Ok(CallbackResult::Return(
+                        match search_path(
+                            name.as_bytes(),
+                            path.as_bytes(),
+                            sep.as_bytes(),
+                            rep.as_bytes(),
+                        ) {
+                            Ok(filename) => vec![Value::String(String::new(mc, &filename))],
+                            Err(message) => {
+                                vec![Value::Nil, Value::String(String::new(mc, &message))]
+                            }
+                        },
+                    ))
+                }))
+            }),
+        )
+        .unwrap();
+
+    env.set(mc, String::new_static(b"package"), package)
+        .unwrap();
+
+    env.set(
+        mc,
+        String::new_static(b"require"),
+        Callback::new_sequence_with(mc, package, |package, args| {
+            Ok(sequence::from_fn_with(
+                (*package, args),
+                |mc, (package, args)| {
+                    let name = match args.get(0).cloned().unwrap_or(Value::Nil).to_string(mc) {
+                        Some(name) => name,
+                        None => return Err(bad_argument_type(mc, &args, 0, "require", "string")),
+                    };
+                    let loaded = match package.get(String::new_static(b"loaded")) {
+                        Value::Table(loaded) => loaded,
+                        _ => return Err(package_error(mc, "'package.loaded' must be a table")),
+                    };
+                    let module = loaded.get(name);
+                    if module.to_bool() {
+                        return Ok(CallbackResult::Return(vec![module]));
+                    }
+
+                    let searchers = match package.get(String::new_static(b"searchers")) {
+                        Value::Table(searchers) => searchers,
+                        _ => return Err(package_error(mc, "'package.searchers' must be a table")),
+                    };
+                    search(mc, loaded, searchers, name, 1, Vec::new())
+                },
+            ))
+        }),
+    )
+    .unwrap();
+}
+
+// Wraps a `Searcher` in a function that can be put in `package.searchers`.
+pub(crate) fn searcher_callback<'gc>(
+    mc: MutationContext<'gc, '_>,
+    root: Root<'gc>,
+    searcher: Box<dyn Searcher>,
+) -> Callback<'gc> {
+    let searcher = Gc::allocate(mc, StaticCollect(searcher));
+    Callback::new_sequence_with(mc, (root, searcher), |&(root, searcher), args| {
+        Ok(sequence::from_fn_with(
+            (root, searcher, args),
+            |mc, (root, searcher, args)| {
+                let name = check_name(mc, &args)?;
+                match searcher.0.search(name.as_bytes()) {
+                    Ok((chunk_name, chunk)) => {
+                        let source = format!("'{}'", chunk_name);
+                        module_loader(mc, root, name, &chunk_name, &chunk, &source, &chunk_name)
+                    }
+                    Err(message) => Ok(CallbackResult::Return(vec![Value::String(String::new(
+                        mc,
+                        message.as_bytes(),
+                    ))])),
+                }
+            },
+        ))
+    })
+}
+
+// Calls each searcher in turn, starting with the one at `index`, and then calls the loader returned
+// by the first one that finds the module.  The messages of the searchers that do not find it are
+// collected to be reported if none of them do.
+fn search<'gc>(
+    mc: MutationContext<'gc, '_>,
+    loaded: Table<'gc>,
+    searchers: Table<'gc>,
+    name: String<'gc>,
+    index: i64,
+    messages: Vec<u8>,
+) -> Result<CallbackResult<'gc>, Error<'gc>> {
+    let searcher = match searchers.get(index) {
+        Value::Function(searcher) => searcher,
+        Value::Nil => {
+            let mut message = b"module '".to_vec();
+            message.extend(name.as_bytes());
+            message.extend(b"' not found:");
+            message.extend(messages);
+            return Err(RuntimeError(Value::String(String::new(mc, &message))).into());
+        }
+        searcher => {
+            return Err(TypeError {
+                expected: "function",
+                found: searcher.type_name(),
+            }
+            .into());
+        }
+    };
+
+    Ok(CallbackResult::TailCall {
+        function: searcher,
+        args: vec![Value::String(name)],
+        continuation: Continuation::new_sequence_with(
+            (loaded, searchers, name, messages),
+            move |(loaded, searchers, name, messages), res| {
+                let res = res?;
+                Ok(sequence::from_fn_with(
+                    (loaded, searchers, name, messages, res),
+                    move |mc, (loaded, searchers, name, mut messages, res)| match res
+                        .get(0)
+                        .cloned()
+                        .unwrap_or(Value::Nil)
+                    {
+                        Value::Function(loader) => {
+                            let data = res.get(1).cloned().unwrap_or(Value::Nil);
+                            Ok(call_loader(loaded, name, loader, data))
+                        }
+                        Value::String(message) => {
+                            messages.extend(b"\n\t");
+                            messages.extend(message.as_bytes());
+                            search(mc, loaded, searchers, name, index + 1, messages)
+                        }
+                        _ => search(mc, loaded, searchers, name, index + 1, messages),
+                    },
+                ))
+            },
+        ),
+    })
+}
+
+// Calls a module loader with the module name and the extra value returned by its searcher, and
+// stores the module it returns in `package.loaded`.  Like PUC-Rio Lua, a loader that returns nothing
+// loads the module as `true`, unless it stored something in `package.loaded` itself.
+fn call_loader<'gc>(
+    loaded: Table<'gc>,
+    name: String<'gc>,
+    loader: Function<'gc>,
+    data: Value<'gc>,
+) -> CallbackResult<'gc> {
+    CallbackResult::TailCall {
+        function: loader,
+        args: vec![Value::String(name), data],
+        continuation: Continuation::new_sequence_with(
+            (loaded, name, data),
+            |(loaded, name, data), res| {
+                let module = res?.get(0).cloned().unwrap_or(Value::Nil);
+                Ok(sequence::from_fn_with(
+                    (loaded, name, data, module),
+                    |mc, (loaded, name, data, module)| {
+                        if module != Value::Nil {
+                            loaded.set(mc, name, module)?;
+                        }
+                        let module = match loaded.get(name) {
+                            Value::Nil => {
+                                loaded.set(mc, name, true)?;
+                                Value::Boolean(true)
+                            }
+                            module => module,
+                        };
+                        Ok(CallbackResult::Return(vec![module, data]))
+                    },
+                ))
+            },
+        ),
+    }
+}
+
+// Compiles a module found by a searcher, returning the loader function for it along with `data`,
+// which is passed to the loader after the module name.  `source` describes where the module was
+// found for error messages.
+fn module_loader<'gc>(
+    mc: MutationContext<'gc, '_>,
+    root: Root<'gc>,
+    name: String<'gc>,
+    chunk_name: &str,
+    chunk: &[u8],
+    source: &str,
+    data: &str,
+) -> Result<CallbackResult<'gc>, Error<'gc>> {
+    match load_chunk(
+        mc,
+        root,
+        chunk,
+        String::new(mc, chunk_name.as_bytes()),
+        String::new_static(b"bt"),
+        Value::Table(root.globals),
+    )
+    .as_slice()
+    {
+        [loader] => Ok(CallbackResult::Return(vec![
+            *loader,
+            Value::String(String::new(mc, data.as_bytes())),
+        ])),
+        [_, error] => {
+            let mut message = Vec::new();
+            error.display(&mut message)?;
+            Err(loading_error(mc, name, source, &message))
+        }
+        _ => unreachable!(),
+    }
+}
+
+fn loading_error<'gc>(
+    mc: MutationContext<'gc, '_>,
+    name: String<'gc>,
+    source: &str,
+    message: &[u8],
+) -> Error<'gc> {
+    let mut error = b"error loading module '".to_vec();
+    error.extend(name.as_bytes());
+    error.extend(format!("' from {}:\n\t", source).as_bytes());
+    error.extend(message);
+    RuntimeError(Value::String(String::new(mc, &error))).into()
+}
+
+fn package_error<'gc>(mc: MutationContext<'gc, '_>, message: &str) -> Error<'gc> {
+    RuntimeError(Value::String(String::new(mc, message.as_bytes()))).into()
+}
+
+// Returns the module name passed to a searcher.
+fn check_name<'gc>(
+    mc: MutationContext<'gc, '_>,
+    args: &[Value<'gc>],
+) -> Result<String<'gc>, Error<'gc>> {
+    args.get(0)
+        .cloned()
+        .unwrap_or(Value::Nil)
+        .to_string(mc)
+        .ok_or_else(|| bad_argument_type(mc, args, 0, "searcher", "string"))
+}
+
+// Looks for a readable file for the module `name` using each of the `;` separated templates in
+// `path`, after replacing each `sep` in the name with `rep`.  Returns the first file found, or a
+// message listing every file that was tried.
+fn search_path(name: &[u8], path: &[u8], sep: &[u8], rep: &[u8]) -> Result<Vec<u8>, Vec<u8>> {
+    let name = replace(name, sep, rep);
+    let mut message = Vec::new();
+    for template in path.split(|&c| c == b';').filter(|t| !t.is_empty()) {
+        let filename = replace(template, b"?", &name);
+        if File::open(&*StdString::from_utf8_lossy(&filename)).is_ok() {
+            return Ok(filename);
+        }
+        if !message.is_empty() {
+            message.extend(b"\n\t");
+        }
+        message.extend(b"no file '");
+        message.extend(&filename);
+        message.push(b'\'');
+    }
+    Err(message)
+}
+
+fn replace(s: &[u8], from: &[u8], to: &[u8]) -> Vec<u8> {
+    if from.is_empty() {
+        return s.to_vec();
+    }
+    let mut replaced = Vec::new();
+    let mut i = 0;
+    while i < s.len() {
+        if s[i..].starts_with(from) {
+            replaced.extend(to);
+            i += from.len();
+        } else {
+            replaced.push(s[i]);
+            i += 1;
+        }
+    }
+    replaced
+}
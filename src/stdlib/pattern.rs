@@ -0,0 +1,484 @@
+use std::error::Error as StdError;
+use std::fmt;
+
+// The most captures a pattern may have, the same limit as PUC-Rio Lua.
+const MAX_CAPTURES: usize = 32;
+
+// The deepest the matcher may recurse before giving up, so that patterns with many nested repetitions
+// produce an error rather than overflowing the stack.
+const MAX_MATCH_DEPTH: usize = 200;
+
+// The characters that make a pattern not match itself literally.
+const SPECIALS: &[u8] = b"^$*+?.([%-";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PatternError {
+    EndsWithPercent,
+    MissingBracket,
+    MissingBalanceArguments,
+    MissingFrontierBracket,
+    InvalidCaptureIndex(usize),
+    InvalidPatternCapture,
+    UnfinishedCapture,
+    TooManyCaptures,
+    TooComplex,
+}
+
+impl StdError for PatternError {}
+
+impl fmt::Display for PatternError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PatternError::EndsWithPercent => write!(fmt, "malformed pattern (ends with '%')"),
+            PatternError::MissingBracket => write!(fmt, "malformed pattern (missing ']')"),
+            PatternError::MissingBalanceArguments => {
+                write!(fmt, "malformed pattern (missing arguments to '%b')")
+            }
+            PatternError::MissingFrontierBracket => {
+                write!(fmt, "missing '[' after '%f' in pattern")
+            }
+            PatternError::InvalidCaptureIndex(i) => write!(fmt, "invalid capture index %{}", i),
+            PatternError::InvalidPatternCapture => write!(fmt, "invalid pattern capture"),
+            PatternError::UnfinishedCapture => write!(fmt, "unfinished capture"),
+            PatternError::TooManyCaptures => write!(fmt, "too many captures"),
+            PatternError::TooComplex => write!(fmt, "pattern too complex"),
+        }
+    }
+}
+
+/// A capture of a successful match, either a range of the source or a position capture `()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Capture {
+    Range(usize, usize),
+    Position(usize),
+}
+
+#[derive(Clone, Copy)]
+enum CaptureLen {
+    Unfinished,
+    Position,
+    Len(usize),
+}
+
+/// Matches a Lua pattern against a source string, following PUC-Rio Lua's `lstrlib.c`.  Positions
+/// are byte offsets from 0.
+pub(crate) struct Matcher<'a> {
+    source: &'a [u8],
+    pattern: &'a [u8],
+    anchored: bool,
+    level: usize,
+    captures: [(usize, CaptureLen); MAX_CAPTURES],
+    depth: usize,
+}
+
+impl<'a> Matcher<'a> {
+    pub(crate) fn new(source: &'a [u8], pattern: &'a [u8]) -> Matcher<'a> {
+        let anchored = pattern.first() == Some(&b'^');
+        Matcher {
+            source,
+            pattern: if anchored { &pattern[1..] } else { pattern },
+            anchored,
+            level: 0,
+            captures: [(0, CaptureLen::Unfinished); MAX_CAPTURES],
+            depth: 0,
+        }
+    }
+
+    /// A matcher that treats a leading '^' as an ordinary character, as `gmatch` does.
+    pub(crate) fn unanchored(source: &'a [u8], pattern: &'a [u8]) -> Matcher<'a> {
+        Matcher {
+            source,
+            pattern,
+            anchored: false,
+            level: 0,
+            captures: [(0, CaptureLen::Unfinished); MAX_CAPTURES],
+            depth: 0,
+        }
+    }
+
+    /// Whether the pattern started with a '^', which `match_at` ignores.
+    pub(crate) fn is_anchored(&self) -> bool {
+        self.anchored
+    }
+
+    /// Tries to match the pattern starting exactly at the given position, returning the end of the
+    /// match.  The captures of a successful match are then available from `captures`.
+    pub(crate) fn match_at(&mut self, start: usize) -> Result<Option<usize>, PatternError> {
+        self.level = 0;
+        self.depth = 0;
+        self.do_match(start, 0)
+    }
+
+    /// Searches for the first match starting at or after `init`, or only at `init` if the pattern
+    /// is anchored.  Returns the start and end of the match.
+    pub(crate) fn find(&mut self, init: usize) -> Result<Option<(usize, usize)>, PatternError> {
+        let mut start = init;
+        loop {
+            if let Some(end) = self.match_at(start)? {
+                return Ok(Some((start, end)));
+            }
+            start += 1;
+            if self.anchored || start > self.source.len() {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// The captures of the last successful match from `start` to `end`.  A pattern without
+    /// captures captures the whole match if `whole_match` is set, and nothing otherwise.
+    pub(crate) fn captures(
+        &self,
+        start: usize,
+        end: usize,
+        whole_match: bool,
+    ) -> Result<Vec<Capture>, PatternError> {
+        if self.level == 0 {
+            return Ok(if whole_match {
+                vec![Capture::Range(start, end)]
+            } else {
+                Vec::new()
+            });
+        }
+        (0..self.level).map(|i| self.capture(i)).collect()
+    }
+
+    /// The capture with the given index of the last successful match.
+    pub(crate) fn capture(&self, i: usize) -> Result<Capture, PatternError> {
+        match self.captures[i] {
+            (start, CaptureLen::Len(len)) => Ok(Capture::Range(start, start + len)),
+            (start, CaptureLen::Position) => Ok(Capture::Position(start)),
+            (_, CaptureLen::Unfinished) => Err(PatternError::UnfinishedCapture),
+        }
+    }
+
+    /// The number of captures in the last successful match.
+    pub(crate) fn capture_count(&self) -> usize {
+        self.level
+    }
+
+    fn do_match(&mut self, s: usize, p: usize) -> Result<Option<usize>, PatternError> {
+        self.depth += 1;
+        if self.depth > MAX_MATCH_DEPTH {
+            return Err(PatternError::TooComplex);
+        }
+        let result = self.match_loop(s, p);
+        self.depth -= 1;
+        result
+    }
+
+    fn match_loop(&mut self, mut s: usize, mut p: usize) -> Result<Option<usize>, PatternError> {
+        let pattern = self.pattern;
+        loop {
+            if p == pattern.len() {
+                return Ok(Some(s));
+            }
+
+            match pattern[p] {
+                b'(' => {
+                    return if pattern.get(p + 1) == Some(&b')') {
+                        self.start_capture(s, p + 2, CaptureLen::Position)
+                    } else {
+                        self.start_capture(s, p + 1, CaptureLen::Unfinished)
+                    };
+                }
+                b')' => return self.end_capture(s, p + 1),
+                b'$' if p + 1 == pattern.len() => {
+                    return Ok(if s == self.source.len() {
+                        Some(s)
+                    } else {
+                        None
+                    });
+                }
+                b'%' if pattern.get(p + 1) == Some(&b'b') => match self.match_balance(s, p + 2)? {
+                    Some(end) => {
+                        s = end;
+                        p += 4;
+                        continue;
+                    }
+                    None => return Ok(None),
+                },
+                b'%' if pattern.get(p + 1) == Some(&b'f') => {
+                    p += 2;
+                    if pattern.get(p) != Some(&b'[') {
+                        return Err(PatternError::MissingFrontierBracket);
+                    }
+                    let ep = self.class_end(p)?;
+                    let previous = if s == 0 { 0 } else { self.source[s - 1] };
+                    let current = self.source.get(s).cloned().unwrap_or(0);
+                    if !self.match_bracket_class(previous, p, ep - 1)
+                        && self.match_bracket_class(current, p, ep - 1)
+                    {
+                        p = ep;
+                        continue;
+                    }
+                    return Ok(None);
+                }
+                b'%' if pattern.get(p + 1).map_or(false, |c| c.is_ascii_digit()) => {
+                    match self.match_capture(s, pattern[p + 1])? {
+                        Some(end) => {
+                            s = end;
+                            p += 2;
+                            continue;
+                        }
+                        None => return Ok(None),
+                    }
+                }
+                _ => {}
+            }
+
+            let ep = self.class_end(p)?;
+            let repetition = pattern.get(ep).cloned();
+            if !self.single_match(s, p, ep) {
+                // A failed match is fine for repetitions that accept zero matches
+                match repetition {
+                    Some(b'*') | Some(b'?') | Some(b'-') => {
+                        p = ep + 1;
+                        continue;
+                    }
+                    _ => return Ok(None),
+                }
+            }
+
+            match repetition {
+                Some(b'?') => {
+                    if let Some(end) = self.do_match(s + 1, ep + 1)? {
+                        return Ok(Some(end));
+                    }
+                    p = ep + 1;
+                }
+                Some(b'+') => return self.max_expand(s + 1, p, ep),
+                Some(b'*') => return self.max_expand(s, p, ep),
+                Some(b'-') => return self.min_expand(s, p, ep),
+                _ => {
+                    s += 1;
+                    p = ep;
+                }
+            }
+        }
+    }
+
+    // Returns the end of the single character class starting at `p`.
+    fn class_end(&self, mut p: usize) -> Result<usize, PatternError> {
+        let pattern = self.pattern;
+        let c = pattern[p];
+        p += 1;
+        match c {
+            b'%' => {
+                if p == pattern.len() {
+                    Err(PatternError::EndsWithPercent)
+                } else {
+                    Ok(p + 1)
+                }
+            }
+            b'[' => {
+                if pattern.get(p) == Some(&b'^') {
+                    p += 1;
+                }
+                // Looks for the closing ']', which may not be the first character of the set
+                loop {
+                    if p >= pattern.len() {
+                        return Err(PatternError::MissingBracket);
+                    }
+                    let c = pattern[p];
+                    p += 1;
+                    if c == b'%' && p < pattern.len() {
+                        p += 1;
+                    }
+                    if pattern.get(p) == Some(&b']') {
+                        return Ok(p + 1);
+                    }
+                }
+            }
+            _ => Ok(p),
+        }
+    }
+
+    fn single_match(&self, s: usize, p: usize, ep: usize) -> bool {
+        match self.source.get(s) {
+            None => false,
+            Some(&c) => match self.pattern[p] {
+                b'.' => true,
+                b'%' => match_class(c, self.pattern[p + 1]),
+                b'[' => self.match_bracket_class(c, p, ep - 1),
+                pc => pc == c,
+            },
+        }
+    }
+
+    // Matches a character against the set starting with the '[' at `p` and ending with the ']' at
+    // `end`.
+    fn match_bracket_class(&self, c: u8, mut p: usize, end: usize) -> bool {
+        let pattern = self.pattern;
+        let mut matches = true;
+        if pattern[p + 1] == b'^' {
+            matches = false;
+            p += 1;
+        }
+        p += 1;
+        while p < end {
+            if pattern[p] == b'%' {
+                p += 1;
+                if match_class(c, pattern[p]) {
+                    return matches;
+                }
+            } else if pattern[p + 1] == b'-' && p + 2 < end {
+                if pattern[p] <= c && c <= pattern[p + 2] {
+                    return matches;
+                }
+                p += 2;
+            } else if pattern[p] == c {
+                return matches;
+            }
+            p += 1;
+        }
+        !matches
+    }
+
+    fn match_balance(&self, s: usize, p: usize) -> Result<Option<usize>, PatternError> {
+        if p + 1 >= self.pattern.len() {
+            return Err(PatternError::MissingBalanceArguments);
+        }
+        let (open, close) = (self.pattern[p], self.pattern[p + 1]);
+        if self.source.get(s) != Some(&open) {
+            return Ok(None);
+        }
+
+        let mut depth = 1;
+        for i in s + 1..self.source.len() {
+            let c = self.source[i];
+            if c == close {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok(Some(i + 1));
+                }
+            } else if c == open {
+                depth += 1;
+            }
+        }
+        Ok(None)
+    }
+
+    fn max_expand(&mut self, s: usize, p: usize, ep: usize) -> Result<Option<usize>, PatternError> {
+        let mut count = 0;
+        while self.single_match(s + count, p, ep) {
+            count += 1;
+        }
+        // Tries the rest of the pattern after the longest run first, backing off one at a time
+        loop {
+            if let Some(end) = self.do_match(s + count, ep + 1)? {
+                return Ok(Some(end));
+            }
+            if count == 0 {
+                return Ok(None);
+            }
+            count -= 1;
+        }
+    }
+
+    fn min_expand(
+        &mut self,
+        mut s: usize,
+        p: usize,
+        ep: usize,
+    ) -> Result<Option<usize>, PatternError> {
+        loop {
+            if let Some(end) = self.do_match(s, ep + 1)? {
+                return Ok(Some(end));
+            } else if self.single_match(s, p, ep) {
+                s += 1;
+            } else {
+                return Ok(None);
+            }
+        }
+    }
+
+    fn start_capture(
+        &mut self,
+        s: usize,
+        p: usize,
+        len: CaptureLen,
+    ) -> Result<Option<usize>, PatternError> {
+        if self.level >= MAX_CAPTURES {
+            return Err(PatternError::TooManyCaptures);
+        }
+        self.captures[self.level] = (s, len);
+        self.level += 1;
+        let result = self.do_match(s, p)?;
+        if result.is_none() {
+            self.level -= 1;
+        }
+        Ok(result)
+    }
+
+    fn end_capture(&mut self, s: usize, p: usize) -> Result<Option<usize>, PatternError> {
+        let open = (0..self.level)
+            .rev()
+            .find(|&i| match self.captures[i].1 {
+                CaptureLen::Unfinished => true,
+                _ => false,
+            })
+            .ok_or(PatternError::InvalidPatternCapture)?;
+        self.captures[open].1 = CaptureLen::Len(s - self.captures[open].0);
+        let result = self.do_match(s, p)?;
+        if result.is_none() {
+            self.captures[open].1 = CaptureLen::Unfinished;
+        }
+        Ok(result)
+    }
+
+    // Matches a back reference `%1` to `%9` to an earlier finished capture.
+    fn match_capture(&self, s: usize, digit: u8) -> Result<Option<usize>, PatternError> {
+        let index = (digit - b'0') as usize;
+        let (start, len) = match index.checked_sub(1).map(|i| (i, self.captures.get(i))) {
+            Some((i, Some(&(start, CaptureLen::Len(len))))) if i < self.level => (start, len),
+            _ => return Err(PatternError::InvalidCaptureIndex(index)),
+        };
+        let captured = &self.source[start..start + len];
+        if self.source[s..].starts_with(captured) {
+            Ok(Some(s + len))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+/// Whether a pattern has no special characters, and so only matches itself.
+pub(crate) fn is_plain(pattern: &[u8]) -> bool {
+    !pattern.iter().any(|c| SPECIALS.contains(c))
+}
+
+/// Finds the first occurrence of `needle` in `haystack` at or after `init`.
+pub(crate) fn find_plain(haystack: &[u8], needle: &[u8], init: usize) -> Option<usize> {
+    if needle.is_empty() {
+        return Some(init);
+    }
+    haystack[init..]
+        .windows(needle.len())
+        .position(|window| window == needle)
+        .map(|i| init + i)
+}
+
+// Matches a character against a class letter following a '%', which is matched literally if it is
+// not a class.  Classes follow the C locale, and upper case letters match the complement.
+fn match_class(c: u8, class: u8) -> bool {
+    let matches = match class.to_ascii_lowercase() {
+        b'a' => c.is_ascii_alphabetic(),
+        b'c' => c.is_ascii_control(),
+        b'd' => c.is_ascii_digit(),
+        b'g' => c.is_ascii_graphic(),
+        b'l' => c.is_ascii_lowercase(),
+        b'p' => c.is_ascii_punctuation(),
+        // Unlike `is_ascii_whitespace`, C also counts vertical tab as whitespace
+        b's' => c == b' ' || (b'\t'..=b'\r').contains(&c),
+        b'u' => c.is_ascii_uppercase(),
+        b'w' => c.is_ascii_alphanumeric(),
+        b'x' => c.is_ascii_hexdigit(),
+        b'z' => c == 0,
+        _ => return class == c,
+    };
+    if class.is_ascii_uppercase() {
+        !matches
+    } else {
+        matches
+    }
+}
@@ -0,0 +1,168 @@
+use std::mem;
+
+use gc_arena::{Collect, MutationContext};
+use gc_sequence as sequence;
+
+use crate::{
+    meta_ops::{self, MetaResult},
+    CallbackResult, Continuation, Error, Function, RuntimeError, String, Table, Value,
+};
+
+use super::base::{bad_argument, bad_argument_type, check_table};
+
+// The longest sequence `sort` accepts, the same limit as PUC-Rio Lua.
+const MAX_SORT_LEN: i64 = i32::MAX as i64;
+
+// A bottom-up merge sort of a copy of the sequence, suspended whenever a comparison has to call into
+// Lua.  Runs of `width` elements from `values` are merged pairwise into `merged`, then the two swap
+// roles and the width doubles.  Once sorted, every adjacent pair is checked against the order
+// function, so that an inconsistent order function raises an error rather than silently producing
+// an unsorted table.  The table itself is only written once everything has succeeded.
+#[derive(Collect)]
+#[collect(empty_drop)]
+struct Sort<'gc> {
+    list: Table<'gc>,
+    comparator: Option<Function<'gc>>,
+    values: Vec<Value<'gc>>,
+    merged: Vec<Value<'gc>>,
+    width: usize,
+    // The runs being merged are `values[left..middle]` and `values[right..end]`
+    left: usize,
+    middle: usize,
+    right: usize,
+    end: usize,
+    // The next element to check against the one before it, once merging has finished
+    checked: usize,
+}
+
+impl<'gc> Sort<'gc> {
+    fn merge_runs(&mut self, start: usize) {
+        let len = self.values.len();
+        self.left = start;
+        self.middle = (start + self.width).min(len);
+        self.right = self.middle;
+        self.end = (start + 2 * self.width).min(len);
+    }
+
+    // Advances until the next comparison is needed, returning the pair of values to test with
+    // `first < second`, or `None` if the sort is finished.
+    fn next_comparison(&mut self) -> Option<(Value<'gc>, Value<'gc>)> {
+        let len = self.values.len();
+        while self.width < len {
+            if self.left < self.middle && self.right < self.end {
+                return Some((self.values[self.right], self.values[self.left]));
+            }
+
+            // One of the runs is exhausted, so the rest of the other is already in order
+            self.merged
+                .extend_from_slice(&self.values[self.left..self.middle]);
+            self.merged
+                .extend_from_slice(&self.values[self.right..self.end]);
+            if self.end < len {
+                self.merge_runs(self.end);
+            } else {
+                mem::swap(&mut self.values, &mut self.merged);
+                self.merged.clear();
+                self.width *= 2;
+                self.merge_runs(0);
+            }
+        }
+
+        if self.checked < len {
+            Some((self.values[self.checked], self.values[self.checked - 1]))
+        } else {
+            None
+        }
+    }
+
+    // Applies the result of the comparison returned by `next_comparison`.
+    fn compared(&mut self, less: bool) -> Result<(), Error<'gc>> {
+        if self.width < self.values.len() {
+            // Taking the left element when the two are equal keeps the merge stable
+            if less {
+                self.merged.push(self.values[self.right]);
+                self.right += 1;
+            } else {
+                self.merged.push(self.values[self.left]);
+                self.left += 1;
+            }
+        } else if less {
+            return Err(RuntimeError(Value::String(String::new_static(
+                b"invalid order function for sorting",
+            )))
+            .into());
+        } else {
+            self.checked += 1;
+        }
+        Ok(())
+    }
+}
+
+/// Implements `table.sort`.  Comparisons that call an order function or a `__lt` metamethod
+/// suspend the sort and resume it from a continuation once the call returns.
+pub(crate) fn table_sort<'gc>(
+    mc: MutationContext<'gc, '_>,
+    args: Vec<Value<'gc>>,
+) -> Result<CallbackResult<'gc>, Error<'gc>> {
+    let list = check_table(mc, &args, 0, "sort")?;
+    let comparator = match args.get(1).cloned().unwrap_or(Value::Nil) {
+        Value::Nil => None,
+        Value::Function(f) => Some(f),
+        _ => return Err(bad_argument_type(mc, &args, 1, "sort", "function")),
+    };
+    let len = list.length();
+    if len >= MAX_SORT_LEN {
+        return Err(bad_argument(mc, 0, "sort", "array too big"));
+    }
+
+    let values: Vec<Value<'gc>> = (1..=len).map(|i| list.get(i)).collect();
+    let mut sort = Sort {
+        list,
+        comparator,
+        merged: Vec::with_capacity(values.len()),
+        values,
+        width: 1,
+        left: 0,
+        middle: 0,
+        right: 0,
+        end: 0,
+        checked: 1,
+    };
+    sort.merge_runs(0);
+    resume(mc, sort)
+}
+
+fn resume<'gc>(
+    mc: MutationContext<'gc, '_>,
+    mut sort: Sort<'gc>,
+) -> Result<CallbackResult<'gc>, Error<'gc>> {
+    while let Some((a, b)) = sort.next_comparison() {
+        let (function, args) = match sort.comparator {
+            Some(comparator) => (comparator, vec![a, b]),
+            None => match meta_ops::less_than(a, b)? {
+                MetaResult::Value(less) => {
+                    sort.compared(less.to_bool())?;
+                    continue;
+                }
+                MetaResult::Call(function, args) => (function, args),
+            },
+        };
+
+        return Ok(CallbackResult::TailCall {
+            function,
+            args,
+            continuation: Continuation::new_sequence_with(sort, |sort, res| {
+                let less = res?.get(0).cloned().unwrap_or(Value::Nil).to_bool();
+                Ok(sequence::from_fn_with(sort, move |mc, mut sort| {
+                    sort.compared(less)?;
+                    resume(mc, sort)
+                }))
+            }),
+        });
+    }
+
+    for (i, &value) in sort.values.iter().enumerate() {
+        sort.list.set(mc, i as i64 + 1, value)?;
+    }
+    Ok(CallbackResult::Return(Vec::new()))
+}
@@ -1,9 +1,22 @@
-use gc_arena::MutationContext;
+use gc_arena::{Collect, GcCell, MutationContext};
 use gc_sequence as sequence;
 
-use crate::{Callback, CallbackResult, Root, RuntimeError, String, Table, Value};
+use crate::{
+    dump_function, Callback, CallbackResult, Error, Function, Root, RuntimeError, String, Table,
+    Value,
+};
 
-pub fn load_string<'gc>(mc: MutationContext<'gc, '_>, _: Root<'gc>, env: Table<'gc>) {
+use super::base::{bad_argument, check_integer, check_string, opt_integer};
+use super::format::string_format;
+use super::gsub::string_gsub;
+use super::pack::{string_pack, string_packsize, string_unpack};
+use super::pattern::{find_plain, is_plain, Capture, Matcher, PatternError};
+
+// The longest string that `rep` will build.  Failing to allocate a string aborts rather than raising
+// an error, so much larger results are refused up front.
+const MAX_STRING_LEN: usize = i32::MAX as usize;
+
+pub fn load_string<'gc>(mc: MutationContext<'gc, '_>, root: Root<'gc>, env: Table<'gc>) {
     let string = Table::new(mc);
 
     string
@@ -24,5 +37,458 @@ pub fn load_string<'gc>(mc: MutationContext<'gc, '_>, _: Root<'gc>, env: Table<'
         )
         .unwrap();
 
+    string
+        .set(
+            mc,
+            String::new_static(b"dump"),
+            Callback::new_sequence(mc, |args| {
+                Ok(sequence::from_fn_with(args, |mc, args| {
+                    let strip = args.get(1).cloned().unwrap_or(Value::Nil).to_bool();
+                    match args.get(0).cloned().unwrap_or(Value::Nil) {
+                        Value::Function(Function::Closure(closure)) => {
+                            let mut buf = Vec::new();
+                            dump_function(&closure.0.proto, strip, &mut buf)?;
+                            Ok(CallbackResult::Return(vec![Value::String(String::new(
+                                mc, &buf,
+                            ))]))
+                        }
+                        Value::Function(Function::Callback(_)) => Err(RuntimeError(Value::String(
+                            String::new_static(b"unable to dump given function"),
+                        ))
+                        .into()),
+                        _ => Err(RuntimeError(Value::String(String::new_static(
+                            b"Bad argument to dump",
+                        )))
+                        .into()),
+                    }
+                }))
+            }),
+        )
+        .unwrap();
+
+    string
+        .set(
+            mc,
+            String::new_static(b"sub"),
+            Callback::new_sequence_with(mc, root.interned_strings, |interned_strings, args| {
+                Ok(sequence::from_fn_with(
+                    (*interned_strings, args),
+                    |mc, (interned_strings, args)| {
+                        let s = check_string(mc, &args, 0, "sub")?;
+                        let start = start_index(opt_integer(mc, &args, 1, "sub", 1)?, s.len());
+                        let end = end_index(opt_integer(mc, &args, 2, "sub", -1)?, s.len());
+                        let sub = if start <= end {
+                            &s.as_bytes()[start as usize - 1..end as usize]
+                        } else {
+                            &[]
+                        };
+                        Ok(CallbackResult::Return(vec![Value::String(
+                            interned_strings.new_string(mc, sub),
+                        )]))
+                    },
+                ))
+            }),
+        )
+        .unwrap();
+
+    string
+        .set(
+            mc,
+            String::new_static(b"rep"),
+            Callback::new_sequence_with(mc, root.interned_strings, |interned_strings, args| {
+                Ok(sequence::from_fn_with(
+                    (*interned_strings, args),
+                    |mc, (interned_strings, args)| {
+                        let s = check_string(mc, &args, 0, "rep")?;
+                        let n = check_integer(mc, &args, 1, "rep")?;
+                        let sep = match args.get(2).cloned().unwrap_or(Value::Nil) {
+                            Value::Nil => String::new_static(b""),
+                            _ => check_string(mc, &args, 2, "rep")?,
+                        };
+                        if n <= 0 {
+                            return Ok(CallbackResult::Return(vec![Value::String(
+                                String::new_static(b""),
+                            )]));
+                        }
+
+                        let (s, sep) = (s.as_bytes(), sep.as_bytes());
+                        let len = (n as usize)
+                            .checked_mul(s.len())
+                            .and_then(|len| {
+                                len.checked_add((n as usize - 1).checked_mul(sep.len())?)
+                            })
+                            .filter(|&len| len <= MAX_STRING_LEN)
+                            .ok_or_else(|| {
+                                RuntimeError(Value::String(String::new_static(
+                                    b"resulting string too large",
+                                )))
+                            })?;
+                        let mut rep = Vec::with_capacity(len);
+                        for i in 0..n {
+                            if i > 0 {
+                                rep.extend_from_slice(sep);
+                            }
+                            rep.extend_from_slice(s);
+                        }
+                        Ok(CallbackResult::Return(vec![Value::String(
+                            interned_strings.new_string(mc, &rep),
+                        )]))
+                    },
+                ))
+            }),
+        )
+        .unwrap();
+
+    string
+        .set(
+            mc,
+            String::new_static(b"upper"),
+            Callback::new_sequence_with(mc, root.interned_strings, |interned_strings, args| {
+                Ok(sequence::from_fn_with(
+                    (*interned_strings, args),
+                    |mc, (interned_strings, args)| {
+                        let s = check_string(mc, &args, 0, "upper")?;
+                        Ok(CallbackResult::Return(vec![Value::String(
+                            interned_strings.new_string(mc, &s.as_bytes().to_ascii_uppercase()),
+                        )]))
+                    },
+                ))
+            }),
+        )
+        .unwrap();
+
+    string
+        .set(
+            mc,
+            String::new_static(b"lower"),
+            Callback::new_sequence_with(mc, root.interned_strings, |interned_strings, args| {
+                Ok(sequence::from_fn_with(
+                    (*interned_strings, args),
+                    |mc, (interned_strings, args)| {
+                        let s = check_string(mc, &args, 0, "lower")?;
+                        Ok(CallbackResult::Return(vec![Value::String(
+                            interned_strings.new_string(mc, &s.as_bytes().to_ascii_lowercase()),
+                        )]))
+                    },
+                ))
+            }),
+        )
+        .unwrap();
+
+    string
+        .set(
+            mc,
+            String::new_static(b"reverse"),
+            Callback::new_sequence_with(mc, root.interned_strings, |interned_strings, args| {
+                Ok(sequence::from_fn_with(
+                    (*interned_strings, args),
+                    |mc, (interned_strings, args)| {
+                        let s = check_string(mc, &args, 0, "reverse")?;
+                        let reversed: Vec<u8> = s.as_bytes().iter().rev().cloned().collect();
+                        Ok(CallbackResult::Return(vec![Value::String(
+                            interned_strings.new_string(mc, &reversed),
+                        )]))
+                    },
+                ))
+            }),
+        )
+        .unwrap();
+
+    string
+        .set(
+            mc,
+            String::new_static(b"byte"),
+            Callback::new_sequence(mc, |args| {
+                Ok(sequence::from_fn_with(args, |mc, args| {
+                    let s = check_string(mc, &args, 0, "byte")?;
+                    let i = opt_integer(mc, &args, 1, "byte", 1)?;
+                    let start = start_index(i, s.len());
+                    let end = end_index(opt_integer(mc, &args, 2, "byte", i)?, s.len());
+                    let bytes = if start <= end {
+                        s.as_bytes()[start as usize - 1..end as usize]
+                            .iter()
+                            .map(|&b| Value::Integer(b as i64))
+                            .collect()
+                    } else {
+                        Vec::new()
+                    };
+                    Ok(CallbackResult::Return(bytes))
+                }))
+            }),
+        )
+        .unwrap();
+
+    string
+        .set(
+            mc,
+            String::new_static(b"char"),
+            Callback::new_sequence_with(mc, root.interned_strings, |interned_strings, args| {
+                Ok(sequence::from_fn_with(
+                    (*interned_strings, args),
+                    |mc, (interned_strings, args)| {
+                        let mut bytes = Vec::with_capacity(args.len());
+                        for n in 0..args.len() {
+                            let c = check_integer(mc, &args, n, "char")?;
+                            if c < 0 || c > 255 {
+                                return Err(bad_argument(mc, n, "char", "value out of range"));
+                            }
+                            bytes.push(c as u8);
+                        }
+                        Ok(CallbackResult::Return(vec![Value::String(
+                            interned_strings.new_string(mc, &bytes),
+                        )]))
+                    },
+                ))
+            }),
+        )
+        .unwrap();
+
+    string
+        .set(
+            mc,
+            String::new_static(b"format"),
+            Callback::new_sequence(mc, |args| {
+                Ok(sequence::from_fn_with(args, |mc, args| {
+                    string_format(mc, args)
+                }))
+            }),
+        )
+        .unwrap();
+
+    string
+        .set(
+            mc,
+            String::new_static(b"find"),
+            Callback::new_sequence(mc, |args| {
+                Ok(sequence::from_fn_with(args, |mc, args| {
+                    find_or_match(mc, &args, "find")
+                }))
+            }),
+        )
+        .unwrap();
+
+    string
+        .set(
+            mc,
+            String::new_static(b"match"),
+            Callback::new_sequence(mc, |args| {
+                Ok(sequence::from_fn_with(args, |mc, args| {
+                    find_or_match(mc, &args, "match")
+                }))
+            }),
+        )
+        .unwrap();
+
+    string
+        .set(
+            mc,
+            String::new_static(b"gmatch"),
+            Callback::new_sequence(mc, |args| {
+                Ok(sequence::from_fn_with(args, |mc, args| {
+                    let source = check_string(mc, &args, 0, "gmatch")?;
+                    let pattern = check_string(mc, &args, 1, "gmatch")?;
+                    let state = GcCell::allocate(
+                        mc,
+                        GmatchState {
+                            source,
+                            pattern,
+                            position: 0,
+                            last_match: None,
+                        },
+                    );
+                    Ok(CallbackResult::Return(vec![gmatch_iterator(mc, state)]))
+                }))
+            }),
+        )
+        .unwrap();
+
+    string
+        .set(
+            mc,
+            String::new_static(b"gsub"),
+            Callback::new_sequence_with(mc, root.string_metatable, |string_metatable, args| {
+                Ok(sequence::from_fn_with(
+                    (*string_metatable, args),
+                    |mc, (string_metatable, args)| string_gsub(mc, string_metatable, args),
+                ))
+            }),
+        )
+        .unwrap();
+
+    string
+        .set(
+            mc,
+            String::new_static(b"pack"),
+            Callback::new_sequence(mc, |args| {
+                Ok(sequence::from_fn_with(args, |mc, args| {
+                    string_pack(mc, args)
+                }))
+            }),
+        )
+        .unwrap();
+
+    string
+        .set(
+            mc,
+            String::new_static(b"packsize"),
+            Callback::new_sequence(mc, |args| {
+                Ok(sequence::from_fn_with(args, |mc, args| {
+                    string_packsize(mc, args)
+                }))
+            }),
+        )
+        .unwrap();
+
+    string
+        .set(
+            mc,
+            String::new_static(b"unpack"),
+            Callback::new_sequence(mc, |args| {
+                Ok(sequence::from_fn_with(args, |mc, args| {
+                    string_unpack(mc, args)
+                }))
+            }),
+        )
+        .unwrap();
+
+    root.string_metatable
+        .set(mc, String::new_static(b"__index"), string)
+        .unwrap();
+
     env.set(mc, String::new_static(b"string"), string).unwrap();
 }
+
+// Converts a possibly negative start position in a string of the given length to a position from 1,
+// clamped to the start of the string.
+fn start_index(i: i64, len: i64) -> i64 {
+    if i > 0 {
+        i
+    } else if i == 0 || i < -len {
+        1
+    } else {
+        len + i + 1
+    }
+}
+
+// Converts a possibly negative end position in a string of the given length to a position from 1,
+// clamped to the end of the string.  A position before the start of the string becomes 0.
+fn end_index(j: i64, len: i64) -> i64 {
+    if j > len {
+        len
+    } else if j >= 0 {
+        j
+    } else if j < -len {
+        0
+    } else {
+        len + j + 1
+    }
+}
+
+// Implements both `find` and `match`, which differ only in their handling of plain patterns and in
+// returning the position of the match.
+fn find_or_match<'gc>(
+    mc: MutationContext<'gc, '_>,
+    args: &[Value<'gc>],
+    function: &str,
+) -> Result<CallbackResult<'gc>, Error<'gc>> {
+    let s = check_string(mc, args, 0, function)?;
+    let pattern = check_string(mc, args, 1, function)?;
+    let (source, pattern) = (s.as_bytes(), pattern.as_bytes());
+    let init = start_index(opt_integer(mc, args, 2, function, 1)?, s.len()) - 1;
+    if init > s.len() {
+        return Ok(CallbackResult::Return(vec![Value::Nil]));
+    }
+    let init = init as usize;
+
+    let find = function == "find";
+    if find && (args.get(3).cloned().unwrap_or(Value::Nil).to_bool() || is_plain(pattern)) {
+        return Ok(CallbackResult::Return(
+            match find_plain(source, pattern, init) {
+                Some(start) => vec![
+                    Value::Integer(start as i64 + 1),
+                    Value::Integer((start + pattern.len()) as i64),
+                ],
+                None => vec![Value::Nil],
+            },
+        ));
+    }
+
+    let mut matcher = Matcher::new(source, pattern);
+    let (start, end) = match matcher.find(init).map_err(|e| pattern_error(mc, e))? {
+        Some(found) => found,
+        None => return Ok(CallbackResult::Return(vec![Value::Nil])),
+    };
+    let mut results = Vec::new();
+    if find {
+        results.push(Value::Integer(start as i64 + 1));
+        results.push(Value::Integer(end as i64));
+    }
+    let captures = matcher
+        .captures(start, end, !find)
+        .map_err(|e| pattern_error(mc, e))?;
+    results.extend(captures.into_iter().map(|c| capture_value(mc, source, c)));
+    Ok(CallbackResult::Return(results))
+}
+
+// The progress of a `gmatch` iterator through its source string.
+#[derive(Collect)]
+#[collect(empty_drop)]
+struct GmatchState<'gc> {
+    source: String<'gc>,
+    pattern: String<'gc>,
+    position: usize,
+    last_match: Option<usize>,
+}
+
+fn gmatch_iterator<'gc>(
+    mc: MutationContext<'gc, '_>,
+    state: GcCell<'gc, GmatchState<'gc>>,
+) -> Value<'gc> {
+    Callback::new_sequence_with(mc, state, |state, _| {
+        Ok(sequence::from_fn_with(*state, |mc, state| {
+            let mut state = state.write(mc);
+            let (source, pattern) = (state.source, state.pattern);
+            let mut matcher = Matcher::unanchored(source.as_bytes(), pattern.as_bytes());
+            for start in state.position..=source.len() as usize {
+                let end = match matcher.match_at(start).map_err(|e| pattern_error(mc, e))? {
+                    Some(end) => end,
+                    None => continue,
+                };
+                // An empty match directly after the previous match would repeat forever
+                if Some(end) == state.last_match {
+                    continue;
+                }
+                state.position = end;
+                state.last_match = Some(end);
+                let captures = matcher
+                    .captures(start, end, true)
+                    .map_err(|e| pattern_error(mc, e))?;
+                return Ok(CallbackResult::Return(
+                    captures
+                        .into_iter()
+                        .map(|c| capture_value(mc, source.as_bytes(), c))
+                        .collect(),
+                ));
+            }
+            state.position = source.len() as usize + 1;
+            Ok(CallbackResult::Return(vec![Value::Nil]))
+        }))
+    })
+    .into()
+}
+
+// Converts a capture of a match in `source` to a Lua value, a string or a position from 1.
+pub(crate) fn capture_value<'gc>(
+    mc: MutationContext<'gc, '_>,
+    source: &[u8],
+    capture: Capture,
+) -> Value<'gc> {
+    match capture {
+        Capture::Range(start, end) => Value::String(String::new(mc, &source[start..end])),
+        Capture::Position(position) => Value::Integer(position as i64 + 1),
+    }
+}
+
+pub(crate) fn pattern_error<'gc>(mc: MutationContext<'gc, '_>, error: PatternError) -> Error<'gc> {
+    RuntimeError(Value::String(String::new(mc, error.to_string().as_bytes()))).into()
+}
@@ -0,0 +1,262 @@
+use std::i64;
+
+use gc_arena::MutationContext;
+use gc_sequence as sequence;
+
+use crate::{Callback, CallbackResult, Root, RuntimeError, String, Table, Value};
+
+use super::base::{bad_argument, check_any, check_integer, check_string, check_table, opt_integer};
+use super::sort::table_sort;
+
+// The most values `unpack` will return, the same as the stack limit of PUC-Rio Lua.
+const MAX_UNPACK: i64 = 1_000_000;
+
+pub fn load_table<'gc>(mc: MutationContext<'gc, '_>, _: Root<'gc>, env: Table<'gc>) {
+    let table = Table::new(mc);
+
+    table
+        .set(
+            mc,
+            String::new_static(b"pack"),
+            Callback::new_sequence(mc, |args| {
+                Ok(sequence::from_fn_with(args, |mc, args| {
+                    let packed = Table::with_capacity(mc, args.len(), 1);
+                    for (i, &arg) in args.iter().enumerate() {
+                        packed.set(mc, Value::Integer(i as i64 + 1), arg)?;
+                    }
+                    packed.set(
+                        mc,
+                        String::new_static(b"n"),
+                        Value::Integer(args.len() as i64),
+                    )?;
+                    Ok(CallbackResult::Return(vec![Value::Table(packed)]))
+                }))
+            }),
+        )
+        .unwrap();
+
+    table
+        .set(
+            mc,
+            String::new_static(b"unpack"),
+            Callback::new_sequence(mc, |args| {
+                Ok(sequence::from_fn_with(args, |mc, args| {
+                    let list = check_table(mc, &args, 0, "unpack")?;
+                    let i = opt_integer(mc, &args, 1, "unpack", 1)?;
+                    let j = match args.get(2).cloned().unwrap_or(Value::Nil) {
+                        Value::Nil => list.length(),
+                        _ => opt_integer(mc, &args, 2, "unpack", 0)?,
+                    };
+
+                    if i > j {
+                        return Ok(CallbackResult::Return(Vec::new()));
+                    }
+                    // The count may overflow for extreme bounds
+                    match j.checked_sub(i) {
+                        Some(n) if n < MAX_UNPACK => Ok(CallbackResult::Return(
+                            (i..=j).map(|k| list.get(k)).collect(),
+                        )),
+                        _ => Err(RuntimeError(Value::String(String::new_static(
+                            b"too many results to unpack",
+                        )))
+                        .into()),
+                    }
+                }))
+            }),
+        )
+        .unwrap();
+
+    table
+        .set(
+            mc,
+            String::new_static(b"insert"),
+            Callback::new_sequence(mc, |args| {
+                Ok(sequence::from_fn_with(args, |mc, args| {
+                    let list = check_table(mc, &args, 0, "insert")?;
+                    let length = list.length();
+                    // The first empty position, which wraps around like in PUC-Rio Lua
+                    let end = length.wrapping_add(1);
+                    let (position, value) = match args.len() {
+                        2 => (end, args[1]),
+                        3 => {
+                            let position = check_integer(mc, &args, 1, "insert")?;
+                            if position < 1 || position > end {
+                                return Err(bad_argument(
+                                    mc,
+                                    1,
+                                    "insert",
+                                    "position out of bounds",
+                                ));
+                            }
+                            (position, args[2])
+                        }
+                        _ => {
+                            return Err(RuntimeError(Value::String(String::new_static(
+                                b"wrong number of arguments to 'insert'",
+                            )))
+                            .into())
+                        }
+                    };
+                    list.insert(mc, length, position, value)?;
+                    Ok(CallbackResult::Return(Vec::new()))
+                }))
+            }),
+        )
+        .unwrap();
+
+    table
+        .set(
+            mc,
+            String::new_static(b"remove"),
+            Callback::new_sequence(mc, |args| {
+                Ok(sequence::from_fn_with(args, |mc, args| {
+                    let list = check_table(mc, &args, 0, "remove")?;
+                    let length = list.length();
+                    let position = opt_integer(mc, &args, 1, "remove", length)?;
+                    // Only an explicit position is checked, so removing from an empty table is allowed
+                    if position != length && (position < 1 || position > length.wrapping_add(1)) {
+                        return Err(bad_argument(mc, 1, "remove", "position out of bounds"));
+                    }
+                    Ok(CallbackResult::Return(vec![
+                        list.remove(mc, length, position)?
+                    ]))
+                }))
+            }),
+        )
+        .unwrap();
+
+    table
+        .set(
+            mc,
+            String::new_static(b"concat"),
+            Callback::new_sequence(mc, |args| {
+                Ok(sequence::from_fn_with(args, |mc, args| {
+                    let list = check_table(mc, &args, 0, "concat")?;
+                    let sep = match args.get(1).cloned().unwrap_or(Value::Nil) {
+                        Value::Nil => String::new_static(b""),
+                        _ => check_string(mc, &args, 1, "concat")?,
+                    };
+                    let mut i = opt_integer(mc, &args, 2, "concat", 1)?;
+                    let j = match args.get(3).cloned().unwrap_or(Value::Nil) {
+                        Value::Nil => list.length(),
+                        _ => opt_integer(mc, &args, 3, "concat", 0)?,
+                    };
+
+                    // Every element is written into a single buffer, so the result is only
+                    // allocated once
+                    let mut buf = Vec::new();
+                    while i <= j {
+                        match list.get(i) {
+                            v @ Value::Integer(_) | v @ Value::Number(_) | v @ Value::String(_) => {
+                                v.display(&mut buf).unwrap()
+                            }
+                            _ => {
+                                let message =
+                                    format!("invalid value (at index {}) in table for 'concat'", i);
+                                return Err(RuntimeError(Value::String(String::new(
+                                    mc,
+                                    message.as_bytes(),
+                                )))
+                                .into());
+                            }
+                        }
+                        if i == j {
+                            break;
+                        }
+                        buf.extend_from_slice(sep.as_bytes());
+                        i += 1;
+                    }
+                    Ok(CallbackResult::Return(vec![Value::String(String::new(
+                        mc, &buf,
+                    ))]))
+                }))
+            }),
+        )
+        .unwrap();
+
+    table
+        .set(
+            mc,
+            String::new_static(b"sort"),
+            Callback::new_sequence(mc, |args| {
+                Ok(sequence::from_fn_with(args, |mc, args| {
+                    table_sort(mc, args)
+                }))
+            }),
+        )
+        .unwrap();
+
+    table
+        .set(
+            mc,
+            String::new_static(b"move"),
+            Callback::new_sequence(mc, |args| {
+                Ok(sequence::from_fn_with(args, |mc, args| {
+                    let f = check_integer(mc, &args, 1, "move")?;
+                    let e = check_integer(mc, &args, 2, "move")?;
+                    let t = check_integer(mc, &args, 3, "move")?;
+                    let source = check_table(mc, &args, 0, "move")?;
+                    let dest = match args.get(4).cloned().unwrap_or(Value::Nil) {
+                        Value::Nil => source,
+                        _ => check_table(mc, &args, 4, "move")?,
+                    };
+
+                    if e >= f {
+                        if f <= 0 && e >= i64::MAX + f {
+                            return Err(bad_argument(mc, 2, "move", "too many elements to move"));
+                        }
+                        let count = e - f + 1;
+                        if t > i64::MAX - count + 1 {
+                            return Err(bad_argument(mc, 3, "move", "destination wrap around"));
+                        }
+                        source.move_range(mc, f, count, dest, t)?;
+                    }
+                    Ok(CallbackResult::Return(vec![Value::Table(dest)]))
+                }))
+            }),
+        )
+        .unwrap();
+
+    env.set(mc, String::new_static(b"table"), table).unwrap();
+}
+
+/// Adds `table.clone(t)` and `table.equals(a, b)` to the `table` library, creating it if it is not
+/// loaded.  These are not part of Lua 5.3, and are built on `Table::deep_clone` and
+/// `Value::deep_eq`.
+pub fn load_table_extensions<'gc>(mc: MutationContext<'gc, '_>, _: Root<'gc>, env: Table<'gc>) {
+    let table = match env.get(String::new_static(b"table")) {
+        Value::Table(table) => table,
+        _ => {
+            let table = Table::new(mc);
+            env.set(mc, String::new_static(b"table"), table).unwrap();
+            table
+        }
+    };
+
+    table
+        .set(
+            mc,
+            String::new_static(b"clone"),
+            Callback::new_sequence(mc, |args| {
+                Ok(sequence::from_fn_with(args, |mc, args| {
+                    let t = check_table(mc, &args, 0, "clone")?;
+                    Ok(CallbackResult::Return(vec![Value::Table(t.deep_clone(mc))]))
+                }))
+            }),
+        )
+        .unwrap();
+
+    table
+        .set(
+            mc,
+            String::new_static(b"equals"),
+            Callback::new_sequence(mc, |args| {
+                Ok(sequence::from_fn_with(args, |mc, args| {
+                    let a = check_any(mc, &args, 0, "equals")?;
+                    let b = check_any(mc, &args, 1, "equals")?;
+                    Ok(CallbackResult::Return(vec![Value::Boolean(a.deep_eq(b))]))
+                }))
+            }),
+        )
+        .unwrap();
+}
@@ -0,0 +1,295 @@
+use gc_arena::MutationContext;
+use gc_sequence as sequence;
+
+use crate::{Callback, CallbackResult, Error, Root, RuntimeError, String, Table, Value};
+
+use super::base::{bad_argument, check_integer, check_string, opt_integer};
+
+// The largest code point accepted by `char` and produced when decoding, as in Lua 5.3.
+const MAX_UNICODE: u32 = 0x10FFFF;
+
+// Matches exactly one UTF-8 byte sequence, assuming the subject is valid UTF-8.
+const CHAR_PATTERN: &[u8] = b"[\0-\x7F\xC2-\xF4][\x80-\xBF]*";
+
+pub fn load_utf8<'gc>(mc: MutationContext<'gc, '_>, _: Root<'gc>, env: Table<'gc>) {
+    let utf8 = Table::new(mc);
+
+    utf8.set(
+        mc,
+        String::new_static(b"char"),
+        Callback::new_sequence(mc, |args| {
+            Ok(sequence::from_fn_with(args, |mc, args| {
+                let mut bytes = Vec::new();
+                for n in 0..args.len() {
+                    let code = check_integer(mc, &args, n, "char")?;
+                    if code < 0 || code > MAX_UNICODE as i64 {
+                        return Err(bad_argument(mc, n, "char", "value out of range"));
+                    }
+                    encode(code as u32, &mut bytes);
+                }
+                Ok(CallbackResult::Return(vec![Value::String(String::new(
+                    mc, &bytes,
+                ))]))
+            }))
+        }),
+    )
+    .unwrap();
+
+    utf8.set(
+        mc,
+        String::new_static(b"charpattern"),
+        String::new_static(CHAR_PATTERN),
+    )
+    .unwrap();
+
+    utf8.set(
+        mc,
+        String::new_static(b"codepoint"),
+        Callback::new_sequence(mc, |args| {
+            Ok(sequence::from_fn_with(args, |mc, args| {
+                let s = check_string(mc, &args, 0, "codepoint")?;
+                let s = s.as_bytes();
+                let len = s.len() as i64;
+                let i = relative_position(opt_integer(mc, &args, 1, "codepoint", 1)?, len);
+                let j = relative_position(opt_integer(mc, &args, 2, "codepoint", i)?, len);
+                if i < 1 {
+                    return Err(bad_argument(mc, 1, "codepoint", "out of range"));
+                }
+                if j > len {
+                    return Err(bad_argument(mc, 2, "codepoint", "out of range"));
+                }
+
+                let mut codes = Vec::new();
+                let mut position = i as usize - 1;
+                while position < j as usize {
+                    let (code, next) = decode(s, position)
+                        .ok_or_else(|| runtime_error(mc, "invalid UTF-8 code"))?;
+                    codes.push(Value::Integer(code as i64));
+                    position = next;
+                }
+                Ok(CallbackResult::Return(codes))
+            }))
+        }),
+    )
+    .unwrap();
+
+    utf8.set(
+        mc,
+        String::new_static(b"len"),
+        Callback::new_sequence(mc, |args| {
+            Ok(sequence::from_fn_with(args, |mc, args| {
+                let s = check_string(mc, &args, 0, "len")?;
+                let s = s.as_bytes();
+                let len = s.len() as i64;
+                let i = relative_position(opt_integer(mc, &args, 1, "len", 1)?, len);
+                let j = relative_position(opt_integer(mc, &args, 2, "len", -1)?, len);
+                if i < 1 || i - 1 > len {
+                    return Err(bad_argument(mc, 1, "len", "initial position out of string"));
+                }
+                if j - 1 >= len {
+                    return Err(bad_argument(mc, 2, "len", "final position out of string"));
+                }
+
+                let mut count = 0;
+                let mut position = i - 1;
+                while position < j {
+                    match decode(s, position as usize) {
+                        Some((_, next)) => position = next as i64,
+                        None => {
+                            return Ok(CallbackResult::Return(vec![
+                                Value::Nil,
+                                Value::Integer(position + 1),
+                            ]))
+                        }
+                    }
+                    count += 1;
+                }
+                Ok(CallbackResult::Return(vec![Value::Integer(count)]))
+            }))
+        }),
+    )
+    .unwrap();
+
+    utf8.set(
+        mc,
+        String::new_static(b"offset"),
+        Callback::new_sequence(mc, |args| {
+            Ok(sequence::from_fn_with(args, |mc, args| {
+                let s = check_string(mc, &args, 0, "offset")?;
+                let s = s.as_bytes();
+                let len = s.len() as i64;
+                let mut n = check_integer(mc, &args, 1, "offset")?;
+                let default = if n >= 0 { 1 } else { len + 1 };
+                let i = relative_position(opt_integer(mc, &args, 2, "offset", default)?, len);
+                if i < 1 || i - 1 > len {
+                    return Err(bad_argument(mc, 2, "offset", "position out of range"));
+                }
+
+                let is_continuation = |p: i64| s.get(p as usize).map_or(false, |&b| is_cont(b));
+                let mut position = i - 1;
+                if n == 0 {
+                    // Find the start of the byte sequence containing the position
+                    while position > 0 && is_continuation(position) {
+                        position -= 1;
+                    }
+                } else {
+                    if is_continuation(position) {
+                        return Err(runtime_error(mc, "initial position is a continuation byte"));
+                    }
+                    if n < 0 {
+                        while n < 0 && position > 0 {
+                            position -= 1;
+                            while position > 0 && is_continuation(position) {
+                                position -= 1;
+                            }
+                            n += 1;
+                        }
+                    } else {
+                        // The first character is the one at the initial position
+                        n -= 1;
+                        while n > 0 && position < len {
+                            position += 1;
+                            while is_continuation(position) {
+                                position += 1;
+                            }
+                            n -= 1;
+                        }
+                    }
+                }
+
+                Ok(CallbackResult::Return(vec![if n == 0 {
+                    Value::Integer(position + 1)
+                } else {
+                    Value::Nil
+                }]))
+            }))
+        }),
+    )
+    .unwrap();
+
+    let codes_iterator = Callback::new_sequence(mc, |args| {
+        Ok(sequence::from_fn_with(args, |mc, args| {
+            let s = check_string(mc, &args, 0, "for iterator")?;
+            let s = s.as_bytes();
+            let control = args
+                .get(1)
+                .and_then(|value| value.to_integer())
+                .unwrap_or(0);
+
+            // The control variable is the position of the previous character, skip past it
+            let mut position = if control <= 0 {
+                0
+            } else {
+                control as usize - 1
+            };
+            if control > 0 && position < s.len() {
+                position += 1;
+                while position < s.len() && is_cont(s[position]) {
+                    position += 1;
+                }
+            }
+            if position >= s.len() {
+                return Ok(CallbackResult::Return(Vec::new()));
+            }
+
+            match decode(s, position) {
+                Some((code, next)) if !s.get(next).map_or(false, |&b| is_cont(b)) => {
+                    Ok(CallbackResult::Return(vec![
+                        Value::Integer(position as i64 + 1),
+                        Value::Integer(code as i64),
+                    ]))
+                }
+                _ => Err(runtime_error(mc, "invalid UTF-8 code")),
+            }
+        }))
+    });
+
+    utf8.set(
+        mc,
+        String::new_static(b"codes"),
+        Callback::new_sequence_with(mc, codes_iterator, |codes_iterator, args| {
+            Ok(sequence::from_fn_with(
+                (*codes_iterator, args),
+                |mc, (codes_iterator, args)| {
+                    let s = check_string(mc, &args, 0, "codes")?;
+                    Ok(CallbackResult::Return(vec![
+                        codes_iterator.into(),
+                        Value::String(s),
+                        Value::Integer(0),
+                    ]))
+                },
+            ))
+        }),
+    )
+    .unwrap();
+
+    env.set(mc, String::new_static(b"utf8"), utf8).unwrap();
+}
+
+// Converts a possibly negative position in a string of the given length to a position from 1.  A
+// negative position before the start of the string becomes 0.
+fn relative_position(i: i64, len: i64) -> i64 {
+    if i >= 0 {
+        i
+    } else if i < -len {
+        0
+    } else {
+        len + i + 1
+    }
+}
+
+fn is_cont(b: u8) -> bool {
+    b & 0xC0 == 0x80
+}
+
+// Decodes the UTF-8 sequence starting at `position`, returning the code point and the position
+// after the sequence, or `None` if the sequence is invalid.  Overlong encodings and code points
+// above `MAX_UNICODE` are invalid, surrogates are accepted as in Lua 5.3.
+fn decode(s: &[u8], position: usize) -> Option<(u32, usize)> {
+    const LIMITS: [u32; 4] = [0xFF, 0x7F, 0x7FF, 0xFFFF];
+
+    let mut c = s[position] as u32;
+    if c < 0x80 {
+        return Some((c, position + 1));
+    }
+
+    let mut count = 0;
+    let mut code = 0;
+    while c & 0x40 != 0 {
+        count += 1;
+        let cc = *s.get(position + count)? as u32;
+        if cc & 0xC0 != 0x80 || count > 3 {
+            return None;
+        }
+        code = (code << 6) | (cc & 0x3F);
+        c <<= 1;
+    }
+    code |= (c & 0x7F) << (count * 5);
+    if code > MAX_UNICODE || code <= LIMITS[count] {
+        return None;
+    }
+    Some((code, position + count + 1))
+}
+
+// Appends the UTF-8 encoding of a code point no greater than `MAX_UNICODE`.
+fn encode(code: u32, bytes: &mut Vec<u8>) {
+    if code < 0x80 {
+        bytes.push(code as u8);
+    } else if code < 0x800 {
+        bytes.push(0xC0 | (code >> 6) as u8);
+        bytes.push(0x80 | (code & 0x3F) as u8);
+    } else if code < 0x10000 {
+        bytes.push(0xE0 | (code >> 12) as u8);
+        bytes.push(0x80 | ((code >> 6) & 0x3F) as u8);
+        bytes.push(0x80 | (code & 0x3F) as u8);
+    } else {
+        bytes.push(0xF0 | (code >> 18) as u8);
+        bytes.push(0x80 | ((code >> 12) & 0x3F) as u8);
+        bytes.push(0x80 | ((code >> 6) & 0x3F) as u8);
+        bytes.push(0x80 | (code & 0x3F) as u8);
+    }
+}
+
+fn runtime_error<'gc>(mc: MutationContext<'gc, '_>, message: &str) -> Error<'gc> {
+    RuntimeError(Value::String(String::new(mc, message.as_bytes()))).into()
+}
@@ -1,4 +1,4 @@
-use std::borrow::Borrow;
+use std::cell::RefCell;
 use std::error::Error as StdError;
 use std::fmt::{self, Debug};
 use std::hash::{Hash, Hasher};
@@ -6,11 +6,11 @@ use std::io::Write;
 use std::ops::Deref;
 use std::str;
 
-use rustc_hash::FxHashSet;
+use rustc_hash::{FxHashMap, FxHasher};
 
-use gc_arena::{Collect, Gc, GcCell, MutationContext};
+use gc_arena::{Collect, CollectionContext, Gc, MutationContext};
 
-use crate::Value;
+use crate::{value::number_to_string, Value};
 
 #[derive(Debug, Clone, Copy, Collect)]
 #[collect(require_static)]
@@ -24,26 +24,54 @@ impl StdError for StringError {}
 impl fmt::Display for StringError {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            StringError::Concat { bad_type } => write!(fmt, "cannot concat {}", bad_type),
+            StringError::Concat { bad_type } => {
+                write!(fmt, "attempt to concatenate a {} value", bad_type)
+            }
             StringError::TooLong => write!(fmt, "string is too long"),
         }
     }
 }
 
+// Strings up to this length are stored inline in the `String` value itself, rather than allocated.
+const INLINE_LEN: usize = 22;
+// Strings up to this length that are not stored inline are allocated with a fixed size buffer.
+const SHORT_LEN: usize = 32;
+
+/// A Lua string, which is an immutable sequence of bytes.
+///
+/// Strings short enough to fit are stored inline without allocating, and allocated strings cache
+/// their hash so that looking them up as table keys does not have to hash their bytes again.
 #[derive(Copy, Clone, Collect)]
 #[collect(require_copy)]
 pub enum String<'gc> {
-    Short8(u8, Gc<'gc, [u8; 8]>),
-    Short32(u8, Gc<'gc, [u8; 32]>),
-    Long(Gc<'gc, Box<[u8]>>),
+    Inline(u8, [u8; INLINE_LEN]),
+    Short(Gc<'gc, ShortString>),
+    Long(Gc<'gc, LongString>),
     Static(&'static [u8]),
 }
 
+/// The allocation of a `String::Short`.
+#[derive(Collect)]
+#[collect(require_static)]
+pub struct ShortString {
+    hash: u64,
+    len: u8,
+    bytes: [u8; SHORT_LEN],
+}
+
+/// The allocation of a `String::Long`.
+#[derive(Collect)]
+#[collect(require_static)]
+pub struct LongString {
+    hash: u64,
+    bytes: Box<[u8]>,
+}
+
 impl<'gc> Debug for String<'gc> {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            String::Short8(_, _) => fmt.write_str("Short8")?,
-            String::Short32(_, _) => fmt.write_str("Short32")?,
+            String::Inline(_, _) => fmt.write_str("Inline")?,
+            String::Short(_) => fmt.write_str("Short")?,
             String::Long(_) => fmt.write_str("Long")?,
             String::Static(_) => fmt.write_str("Static")?,
         }
@@ -61,16 +89,29 @@ impl<'gc> Debug for String<'gc> {
 impl<'gc> String<'gc> {
     pub fn new(mc: MutationContext<'gc, '_>, s: &[u8]) -> String<'gc> {
         let len = s.len();
-        if len <= 8 {
-            let mut b = [0; 8];
-            b[..len].copy_from_slice(s);
-            String::Short8(len as u8, Gc::allocate(mc, b))
-        } else if len <= 32 {
-            let mut b = [0; 32];
+        if len <= INLINE_LEN {
+            let mut b = [0; INLINE_LEN];
             b[..len].copy_from_slice(s);
-            String::Short32(len as u8, Gc::allocate(mc, b))
+            String::Inline(len as u8, b)
+        } else if len <= SHORT_LEN {
+            let mut bytes = [0; SHORT_LEN];
+            bytes[..len].copy_from_slice(s);
+            String::Short(Gc::allocate(
+                mc,
+                ShortString {
+                    hash: hash_bytes(s),
+                    len: len as u8,
+                    bytes,
+                },
+            ))
         } else {
-            String::Long(Gc::allocate(mc, s.to_vec().into_boxed_slice()))
+            String::Long(Gc::allocate(
+                mc,
+                LongString {
+                    hash: hash_bytes(s),
+                    bytes: s.to_vec().into_boxed_slice(),
+                },
+            ))
         }
     }
 
@@ -82,37 +123,41 @@ impl<'gc> String<'gc> {
         mc: MutationContext<'gc, '_>,
         values: &[Value<'gc>],
     ) -> Result<String<'gc>, StringError> {
-        let mut bytes = Vec::new();
-        for value in values {
-            match value {
-                Value::Nil => write!(&mut bytes, "nil").unwrap(),
-                Value::Boolean(b) => write!(&mut bytes, "{}", b).unwrap(),
-                Value::Integer(i) => write!(&mut bytes, "{}", i).unwrap(),
-                Value::Number(n) => write!(&mut bytes, "{}", n).unwrap(),
-                Value::String(s) => bytes.extend(s.as_bytes()),
-                Value::Table(_) => return Err(StringError::Concat { bad_type: "table" }),
-                Value::Function(_) => {
-                    return Err(StringError::Concat {
-                        bad_type: "function",
-                    });
-                }
-                Value::Thread(_) => {
-                    return Err(StringError::Concat { bad_type: "thread" });
-                }
-            }
-        }
-        Ok(String::new(mc, &bytes))
+        Ok(String::new(mc, &concat_bytes(values)?))
     }
 
     pub fn as_bytes(&self) -> &[u8] {
         match self {
-            String::Short8(l, b) => &b[0..*l as usize],
-            String::Short32(l, b) => &b[0..*l as usize],
-            String::Long(b) => b,
+            String::Inline(l, b) => &b[0..*l as usize],
+            String::Short(s) => &s.bytes[0..s.len as usize],
+            String::Long(s) => &s.bytes,
             String::Static(b) => b,
         }
     }
 
+    /// The hash of the string's bytes, which is cached for allocated strings.  Equal strings always
+    /// have equal hashes, whichever way they are stored.
+    pub fn hash_value(&self) -> u64 {
+        match self {
+            String::Inline(_, _) | String::Static(_) => hash_bytes(self.as_bytes()),
+            String::Short(s) => s.hash,
+            String::Long(s) => s.hash,
+        }
+    }
+
+    /// Compares two strings by their bytes, but first by pointer and by cached hash when both are
+    /// allocated, so that allocated strings rarely need their bytes compared.
+    pub(crate) fn equals(self, other: String<'gc>) -> bool {
+        match (self, other) {
+            (String::Short(a), String::Short(b)) => {
+                Gc::ptr_eq(a, b) || a.hash == b.hash && self.as_bytes() == other.as_bytes()
+            }
+            (String::Long(a), String::Long(b)) => {
+                Gc::ptr_eq(a, b) || a.hash == b.hash && self.as_bytes() == other.as_bytes()
+            }
+            _ => self.as_bytes() == other.as_bytes(),
+        }
+    }
     pub fn len(&self) -> i64 {
         fn as_i64(len: usize) -> i64 {
             if len <= std::i64::MAX as usize {
@@ -123,8 +168,9 @@ impl<'gc> String<'gc> {
         }
 
         match self {
-            String::Short8(l, _) | String::Short32(l, _) => *l as i64,
-            String::Long(b) => as_i64(b.len()),
+            String::Inline(l, _) => *l as i64,
+            String::Short(s) => s.len as i64,
+            String::Long(s) => as_i64(s.bytes.len()),
             String::Static(b) => as_i64(b.len()),
         }
     }
@@ -144,12 +190,6 @@ impl<'gc> AsRef<[u8]> for String<'gc> {
     }
 }
 
-impl<'gc> Borrow<[u8]> for String<'gc> {
-    fn borrow(&self) -> &[u8] {
-        self.as_bytes()
-    }
-}
-
 impl<'gc, T> PartialEq<T> for String<'gc>
 where
     T: AsRef<[u8]>,
@@ -161,28 +201,110 @@ where
 
 impl<'gc> Eq for String<'gc> {}
 
+// Hashing a string only writes its hash value, so that hashing an allocated string does not have to
+// read its bytes.
 impl<'gc> Hash for String<'gc> {
     fn hash<H: Hasher>(&self, state: &mut H) {
-        self.as_bytes().hash(state);
+        state.write_u64(self.hash_value());
     }
 }
 
+fn concat_bytes(values: &[Value]) -> Result<Vec<u8>, StringError> {
+    let mut bytes = Vec::new();
+    for value in values {
+        match value {
+            Value::Nil => write!(&mut bytes, "nil").unwrap(),
+            Value::Boolean(b) => write!(&mut bytes, "{}", b).unwrap(),
+            Value::Integer(i) => write!(&mut bytes, "{}", i).unwrap(),
+            Value::Number(n) => bytes.extend(number_to_string(*n).as_bytes()),
+            Value::String(s) => bytes.extend(s.as_bytes()),
+            Value::Table(_) => return Err(StringError::Concat { bad_type: "table" }),
+            Value::Function(_) => {
+                return Err(StringError::Concat {
+                    bad_type: "function",
+                });
+            }
+            Value::Thread(_) => {
+                return Err(StringError::Concat { bad_type: "thread" });
+            }
+            Value::UserData(_) => {
+                return Err(StringError::Concat {
+                    bad_type: "userdata",
+                });
+            }
+        }
+    }
+    Ok(bytes)
+}
+
+fn hash_bytes(bytes: &[u8]) -> u64 {
+    let mut hasher = FxHasher::default();
+    bytes.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// The set of short strings of a Lua instance, which are shared rather than allocated again each
+/// time they are created, like in PUC-Rio Lua.  It holds the constants of compiled chunks and the
+/// strings made by concatenation and by the string library.
+///
+/// The set does not keep its strings alive, those that become unreachable are removed from it at
+/// the end of each collection.  Strings short enough to be stored inline are never allocated, and
+/// long strings are rarely made twice, so neither are kept in the set.
 #[derive(Collect, Clone, Copy)]
 #[collect(require_copy)]
-pub struct InternedStringSet<'gc>(GcCell<'gc, FxHashSet<String<'gc>>>);
+pub struct InternedStringSet<'gc>(Gc<'gc, InternedStrings<'gc>>);
+
+// The strings of an `InternedStringSet` by hash, which are not traced so that they may become
+// unreachable.
+struct InternedStrings<'gc>(RefCell<FxHashMap<u64, Vec<String<'gc>>>>);
+
+unsafe impl<'gc> Collect for InternedStrings<'gc> {}
 
 impl<'gc> InternedStringSet<'gc> {
-    pub fn new(mc: MutationContext<'gc, '_>) -> InternedStringSet<'gc> {
-        InternedStringSet(GcCell::allocate(mc, FxHashSet::default()))
+    /// Creates the set of the arena, whose unreachable strings must be removed by calling
+    /// `InternedStringSet::clear_dead` at the end of every collection.
+    pub(crate) fn new(mc: MutationContext<'gc, '_>) -> InternedStringSet<'gc> {
+        InternedStringSet(Gc::allocate(
+            mc,
+            InternedStrings(RefCell::new(FxHashMap::default())),
+        ))
     }
 
     pub fn new_string(&self, mc: MutationContext<'gc, '_>, s: &[u8]) -> String<'gc> {
-        if let Some(found) = self.0.read().get(s) {
+        if s.len() <= INLINE_LEN || s.len() > SHORT_LEN {
+            return String::new(mc, s);
+        }
+
+        let hash = hash_bytes(s);
+        // Adding a string needs no write barrier, since the strings are not traced
+        let mut strings = (self.0).0.borrow_mut();
+        let bucket = strings.entry(hash).or_default();
+        if let Some(found) = bucket.iter().find(|found| found.as_bytes() == s) {
             return *found;
         }
 
-        let s = String::new(mc, s);
-        self.0.write(mc).insert(s);
-        s
+        let string = String::new(mc, s);
+        bucket.push(string);
+        string
+    }
+
+    /// Like `String::concat`, but interns the result.
+    pub fn concat(
+        &self,
+        mc: MutationContext<'gc, '_>,
+        values: &[Value<'gc>],
+    ) -> Result<String<'gc>, StringError> {
+        Ok(self.new_string(mc, &concat_bytes(values)?))
+    }
+
+    // Removes the strings that are about to be freed, once nothing else is traced by a collection.
+    pub(crate) fn clear_dead(self, cc: CollectionContext) {
+        (self.0).0.borrow_mut().retain(|_, bucket| {
+            bucket.retain(|string| match string {
+                String::Short(short) => !Gc::is_dead(cc, *short),
+                _ => true,
+            });
+            !bucket.is_empty()
+        });
     }
 }
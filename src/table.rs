@@ -1,13 +1,16 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::error::Error as StdError;
 use std::hash::{Hash, Hasher};
+use std::ops::Range;
 use std::{fmt, i64, mem};
 
 use num_traits::cast;
 use rustc_hash::FxHashMap;
 
-use gc_arena::{Collect, GcCell, MutationContext};
+use gc_arena::{Collect, CollectionContext, Gc, GcCell, MutationContext};
 
-use crate::Value;
+use crate::{Error, FromLua, Function, String, ToLua, Value};
 
 #[derive(Debug, Copy, Clone, Collect)]
 #[collect(require_copy)]
@@ -18,6 +21,8 @@ pub struct Table<'gc>(pub GcCell<'gc, TableState<'gc>>);
 pub enum InvalidTableKey {
     IsNaN,
     IsNil,
+    NotPresent,
+    ReadOnly,
 }
 
 impl StdError for InvalidTableKey {}
@@ -25,8 +30,10 @@ impl StdError for InvalidTableKey {}
 impl fmt::Display for InvalidTableKey {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            InvalidTableKey::IsNaN => write!(fmt, "table key is NaN"),
-            InvalidTableKey::IsNil => write!(fmt, "table key is Nil"),
+            InvalidTableKey::IsNaN => write!(fmt, "table index is NaN"),
+            InvalidTableKey::IsNil => write!(fmt, "table index is nil"),
+            InvalidTableKey::NotPresent => write!(fmt, "invalid key to 'next'"),
+            InvalidTableKey::ReadOnly => write!(fmt, "attempt to modify a read-only table"),
         }
     }
 }
@@ -50,6 +57,12 @@ impl<'gc> Table<'gc> {
         Table(GcCell::allocate(mc, TableState::default()))
     }
 
+    /// Creates a table with room for the keys `1..=narr` in its array part and `nhash` other
+    /// entries, so that filling it in does not need to resize it.
+    pub fn with_capacity(mc: MutationContext<'gc, '_>, narr: usize, nhash: usize) -> Table<'gc> {
+        Table(GcCell::allocate(mc, TableState::with_capacity(narr, nhash)))
+    }
+
     pub fn get<K: Into<Value<'gc>>>(&self, key: K) -> Value<'gc> {
         self.0.read().get(key.into())
     }
@@ -63,19 +76,387 @@ impl<'gc> Table<'gc> {
         self.0.write(mc).set(key.into(), value.into())
     }
 
+    /// Returns a border of the table without calling `__len`, the same border that PUC-Rio Lua
+    /// finds for `#` given the same array part.  See `TableState::length`.
     pub fn length(&self) -> i64 {
         self.0.read().length()
     }
+
+    /// Sets the value at `length + 1`, appending it to the sequence.
+    pub fn push<V: Into<Value<'gc>>>(
+        &self,
+        mc: MutationContext<'gc, '_>,
+        value: V,
+    ) -> Result<(), InvalidTableKey> {
+        let mut state = self.0.write(mc);
+        let length = state.length();
+        state.set(Value::Integer(length.wrapping_add(1)), value.into())?;
+        Ok(())
+    }
+
+    /// Grows the array part of the table to hold at least the keys `1..=len`, so that setting them
+    /// does not need to resize the table.
+    pub fn reserve_array(&self, mc: MutationContext<'gc, '_>, len: usize) {
+        self.0.write(mc).reserve_array(len)
+    }
+
+    /// The number of slots in the array part of the table, which holds the values for the keys
+    /// `1..=array_len()` without hashing them, some of which may be nil.
+    pub fn array_len(&self) -> usize {
+        self.0.read().array.len()
+    }
+
+    /// Inserts a value at `position` in the sequence from 1 to `length`, moving the elements from
+    /// `position` onwards up by one.
+    pub fn insert<V: Into<Value<'gc>>>(
+        &self,
+        mc: MutationContext<'gc, '_>,
+        length: i64,
+        position: i64,
+        value: V,
+    ) -> Result<(), InvalidTableKey> {
+        self.0.write(mc).insert(length, position, value.into())
+    }
+
+    /// Removes the value at `position` in the sequence from 1 to `length`, moving the elements after
+    /// it down by one.  Returns the removed value.
+    pub fn remove(
+        &self,
+        mc: MutationContext<'gc, '_>,
+        length: i64,
+        position: i64,
+    ) -> Result<Value<'gc>, InvalidTableKey> {
+        self.0.write(mc).remove(length, position)
+    }
+
+    /// Copies `count` values starting at the key `start` to the keys starting at `dest_start` in the
+    /// `dest` table, which may be this table.  Overlapping ranges are handled as if the values were
+    /// first copied to a temporary buffer.
+    pub fn move_range(
+        &self,
+        mc: MutationContext<'gc, '_>,
+        start: i64,
+        count: i64,
+        dest: Table<'gc>,
+        dest_start: i64,
+    ) -> Result<(), InvalidTableKey> {
+        if *self == dest {
+            self.0.write(mc).move_within(start, count, dest_start)
+        } else {
+            dest.0
+                .write(mc)
+                .copy_range(&self.0.read(), start, count, dest_start)
+        }
+    }
+
+    pub fn next<K: Into<Value<'gc>>>(
+        &self,
+        key: K,
+    ) -> Result<Option<(Value<'gc>, Value<'gc>)>, InvalidTableKey> {
+        self.0.read().next(key.into())
+    }
+
+    /// Creates a table with the values from `iter` in the sequence from 1 onwards.
+    pub fn from_iter<I>(mc: MutationContext<'gc, '_>, iter: I) -> Result<Table<'gc>, Error<'gc>>
+    where
+        I: IntoIterator,
+        I::Item: ToLua<'gc>,
+    {
+        let iter = iter.into_iter();
+        let table = Table::with_capacity(mc, iter.size_hint().0, 0);
+        for (i, v) in iter.enumerate() {
+            table.set(mc, i as i64 + 1, v.to_lua(mc)?)?;
+        }
+        Ok(table)
+    }
+
+    /// Creates a table with the key-value pairs from `iter`, where later pairs replace earlier
+    /// ones with the same key.
+    pub fn from_pairs<I, K, V>(
+        mc: MutationContext<'gc, '_>,
+        iter: I,
+    ) -> Result<Table<'gc>, Error<'gc>>
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: ToLua<'gc>,
+        V: ToLua<'gc>,
+    {
+        let table = Table::new(mc);
+        for (k, v) in iter {
+            table.set(mc, k.to_lua(mc)?, v.to_lua(mc)?)?;
+        }
+        Ok(table)
+    }
+
+    /// Converts the values in the sequence from 1 to `length`, failing if any of them does not
+    /// convert.
+    pub fn to_vec<T: FromLua<'gc>>(
+        &self,
+        mc: MutationContext<'gc, '_>,
+    ) -> Result<Vec<T>, Error<'gc>> {
+        self.array_iter().map(|v| T::from_lua(mc, v)).collect()
+    }
+
+    /// Converts every key-value pair in the table, failing if any key or value does not convert.
+    pub fn to_hash_map<K, V>(
+        &self,
+        mc: MutationContext<'gc, '_>,
+    ) -> Result<HashMap<K, V>, Error<'gc>>
+    where
+        K: FromLua<'gc> + Eq + Hash,
+        V: FromLua<'gc>,
+    {
+        self.iter()
+            .map(|(k, v)| Ok((K::from_lua(mc, k)?, V::from_lua(mc, v)?)))
+            .collect()
+    }
+
+    /// Creates a copy of this table in which every table reachable through keys and values is
+    /// also copied, preserving any sharing and cycles between them.  Metatables are shared with the
+    /// originals rather than copied, and other values are copied by reference.
+    pub fn deep_clone(&self, mc: MutationContext<'gc, '_>) -> Table<'gc> {
+        let mut clones = FxHashMap::default();
+        let mut pending = Vec::new();
+        let mut clone_value =
+            |value: Value<'gc>, pending: &mut Vec<(Table<'gc>, Table<'gc>)>| match value {
+                Value::Table(table) => Value::Table(*clones.entry(table).or_insert_with(|| {
+                    let clone = Table::new(mc);
+                    clone.set_metatable(mc, table.metatable());
+                    pending.push((table, clone));
+                    clone
+                })),
+                value => value,
+            };
+
+        let root = match clone_value(Value::Table(*self), &mut pending) {
+            Value::Table(root) => root,
+            _ => unreachable!(),
+        };
+        while let Some((table, clone)) = pending.pop() {
+            let mut key = Value::Nil;
+            while let Ok(Some((k, v))) = table.next(key) {
+                let (ck, cv) = (clone_value(k, &mut pending), clone_value(v, &mut pending));
+                clone.set(mc, ck, cv).unwrap();
+                key = k;
+            }
+        }
+        root
+    }
+
+    pub fn metatable(&self) -> Option<Table<'gc>> {
+        self.0.read().metatable
+    }
+
+    /// Sets the metatable for this table, returning the previous metatable.
+    pub fn set_metatable(
+        &self,
+        mc: MutationContext<'gc, '_>,
+        metatable: Option<Table<'gc>>,
+    ) -> Option<Table<'gc>> {
+        mem::replace(&mut self.0.write(mc).metatable, metatable)
+    }
+
+    /// Makes this table read-only, after which every attempt to change its entries fails with
+    /// `InvalidTableKey::ReadOnly`.  The table stays frozen for the rest of its life.
+    pub fn freeze(&self, mc: MutationContext<'gc, '_>) {
+        self.0.write(mc).frozen = true;
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        self.0.read().frozen
+    }
+
+    // Marks the table as registered with `Finalizers` or not, returning whether it was before.
+    pub(crate) fn set_finalize(&self, mc: MutationContext<'gc, '_>, finalize: bool) -> bool {
+        mem::replace(&mut self.0.write(mc).finalize, finalize)
+    }
+
+    // Sets whether the keys and values of the table are weak references, returning whether it has
+    // had weak keys or values before and so is already registered with `Finalizers`.
+    pub(crate) fn set_weak_mode(&self, mc: MutationContext<'gc, '_>, mode: WeakMode) -> bool {
+        let mut state = self.0.write(mc);
+        let registered = state.weak_mode.is_some();
+        if registered || mode.keys || mode.values {
+            state.weak_mode = Some(mode);
+        }
+        registered
+    }
+
+    /// Iterates over every key-value pair in the table in the same order as `next`.  Like `next`,
+    /// entries may be assigned or removed during iteration but not added, and iteration ends early
+    /// if the last key returned is no longer present.
+    pub fn iter(&self) -> TableIter<'gc> {
+        TableIter {
+            table: *self,
+            key: Some(Value::Nil),
+        }
+    }
+
+    /// Iterates over the values in the sequence from 1 to `length`, where the length is found once
+    /// when the iterator is created.
+    pub fn array_iter(&self) -> ArrayIter<'gc> {
+        ArrayIter {
+            table: *self,
+            range: 1..self.length() + 1,
+        }
+    }
+}
+
+/// An iterator over the entries of a table, returned by `Table::iter`.
+#[derive(Debug, Clone)]
+pub struct TableIter<'gc> {
+    table: Table<'gc>,
+    // The key to continue from, or None once iteration has finished
+    key: Option<Value<'gc>>,
+}
+
+impl<'gc> Iterator for TableIter<'gc> {
+    type Item = (Value<'gc>, Value<'gc>);
+
+    fn next(&mut self) -> Option<(Value<'gc>, Value<'gc>)> {
+        let entry = self.table.next(self.key?).ok().and_then(|entry| entry);
+        self.key = entry.map(|(k, _)| k);
+        entry
+    }
+}
+
+/// An iterator over the sequence part of a table, returned by `Table::array_iter`.
+#[derive(Debug, Clone)]
+pub struct ArrayIter<'gc> {
+    table: Table<'gc>,
+    range: Range<i64>,
+}
+
+impl<'gc> Iterator for ArrayIter<'gc> {
+    type Item = Value<'gc>;
+
+    fn next(&mut self) -> Option<Value<'gc>> {
+        self.range.next().map(|i| self.table.get(i))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.range.size_hint()
+    }
+}
+
+impl<'gc> DoubleEndedIterator for ArrayIter<'gc> {
+    fn next_back(&mut self) -> Option<Value<'gc>> {
+        self.range.next_back().map(|i| self.table.get(i))
+    }
 }
 
-#[derive(Debug, Collect, Default)]
-#[collect(empty_drop)]
+impl<'gc> ExactSizeIterator for ArrayIter<'gc> {}
+
+#[derive(Debug, Default)]
 pub struct TableState<'gc> {
+    // The array part holds the values of the integer keys `1..=array.len()`.  Like in PUC-Rio Lua,
+    // it only grows when a new key does not fit in the map part, and is then resized to the largest
+    // power of two that would be more than half full.
     array: Vec<Value<'gc>>,
-    map: FxHashMap<TableKey<'gc>, Value<'gc>>,
+    // The map part of the table is a list of entries in insertion order, along with an index of
+    // their positions by key.  Entries that are set to Nil are left in place until the map part is
+    // next resized, so that removing entries during traversal with `next` is allowed, just like in
+    // PUC-Rio Lua.
+    entries: Vec<(TableKey<'gc>, Value<'gc>)>,
+    index: FxHashMap<TableKey<'gc>, usize>,
+    metatable: Option<Table<'gc>>,
+    frozen: bool,
+    // Whether the table is registered with `Finalizers` to be finalized once it is unreachable
+    finalize: bool,
+    // Whether the keys and values of the table are weak references, or `None` if the table has
+    // never had weak keys or values.  Weak entries are not traced, and `Finalizers` clears the ones
+    // whose keys or values are unreachable.
+    weak_mode: Option<WeakMode>,
+}
+
+/// Which references a table holds weakly, set from the `__mode` field of its metatable.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct WeakMode {
+    pub(crate) keys: bool,
+    pub(crate) values: bool,
+}
+
+impl WeakMode {
+    /// The weak mode given by the `__mode` field of a metatable, which has weak keys if it is a
+    /// string containing 'k' and weak values if it contains 'v'.
+    pub(crate) fn from_mode(mode: Value) -> WeakMode {
+        match mode {
+            Value::String(mode) => WeakMode {
+                keys: mode.as_bytes().contains(&b'k'),
+                values: mode.as_bytes().contains(&b'v'),
+            },
+            _ => WeakMode::default(),
+        }
+    }
+}
+
+// Entries of a weak table are traced strongly unless they are one of the objects that can be
+// collected while a weak table refers to them.  Like in PUC-Rio Lua, strings are values rather than
+// objects, so they are never removed from weak tables.
+unsafe impl<'gc> Collect for TableState<'gc> {
+    fn trace(&self, cc: CollectionContext) {
+        let mode = self.weak_mode.unwrap_or_default();
+        self.metatable.trace(cc);
+        for &value in &self.array {
+            if !(mode.values && is_weak_reference(value)) {
+                value.trace(cc);
+            }
+        }
+        for &(key, value) in &self.entries {
+            // The value of an entry with a weak key is only traced once the key is known to be
+            // reachable, see `TableState::trace_ephemerons`
+            if !(mode.keys && is_weak_reference(key.0)) {
+                key.trace(cc);
+                if !(mode.values && is_weak_reference(value)) {
+                    value.trace(cc);
+                }
+            }
+        }
+    }
 }
 
 impl<'gc> TableState<'gc> {
+    pub fn with_capacity(narr: usize, nhash: usize) -> TableState<'gc> {
+        TableState {
+            array: vec![Value::Nil; narr],
+            entries: Vec::with_capacity(nhash),
+            index: FxHashMap::with_capacity_and_hasher(nhash, Default::default()),
+            metatable: None,
+            frozen: false,
+            finalize: false,
+            weak_mode: None,
+        }
+    }
+
+    /// Grows the array part to hold at least the keys `1..=len`, moving any of those keys that are
+    /// in the map part into it.
+    pub fn reserve_array(&mut self, len: usize) {
+        if len <= self.array.len() {
+            return;
+        }
+        self.array.resize(len, Value::Nil);
+        let array = &mut self.array;
+        let entries_len = self.entries.len();
+        self.entries.retain(|(k, v)| {
+            if k.0 == Value::Nil {
+                return false;
+            }
+            if let Some(i) = to_array_index(k.0) {
+                if i < array.len() {
+                    array[i] = *v;
+                    return false;
+                }
+            }
+            true
+        });
+        if self.entries.len() != entries_len {
+            self.index.clear();
+            for (i, (k, _)) in self.entries.iter().enumerate() {
+                self.index.insert(*k, i);
+            }
+        }
+    }
+
     pub fn get(&self, key: Value<'gc>) -> Value<'gc> {
         if let Some(index) = to_array_index(key) {
             if index < self.array.len() {
@@ -84,7 +465,7 @@ impl<'gc> TableState<'gc> {
         }
 
         if let Ok(key) = TableKey::new(key) {
-            self.map.get(&key).cloned().unwrap_or(Value::Nil)
+            self.get_entry(key)
         } else {
             Value::Nil
         }
@@ -95,6 +476,7 @@ impl<'gc> TableState<'gc> {
         key: Value<'gc>,
         value: Value<'gc>,
     ) -> Result<Value<'gc>, InvalidTableKey> {
+        self.check_writable()?;
         let index_key = to_array_index(key);
         if let Some(index) = index_key {
             if index < self.array.len() {
@@ -103,10 +485,13 @@ impl<'gc> TableState<'gc> {
         }
 
         let hash_key = TableKey::new(key)?;
-        if value == Value::Nil {
-            Ok(self.map.remove(&hash_key).unwrap_or(Value::Nil))
-        } else if self.map.len() < self.map.capacity() {
-            Ok(self.map.insert(hash_key, value).unwrap_or(Value::Nil))
+        if let Some(&i) = self.index.get(&hash_key) {
+            Ok(mem::replace(&mut self.entries[i].1, value))
+        } else if value == Value::Nil {
+            Ok(Value::Nil)
+        } else if self.entries.len() < self.entries.capacity() {
+            self.insert_entry(hash_key, value);
+            Ok(Value::Nil)
         } else {
             // If a new element does not fit in either the array or map part of the table, we need
             // to grow.  First, we find the total count of array candidate elements across the array
@@ -126,10 +511,12 @@ impl<'gc> TableState<'gc> {
                 }
             }
 
-            for k in self.map.keys() {
-                if let Some(i) = to_array_index(k.0) {
-                    array_counts[highest_bit(i)] += 1;
-                    array_total += 1;
+            for (k, v) in &self.entries {
+                if *v != Value::Nil {
+                    if let Some(i) = to_array_index(k.0) {
+                        array_counts[highest_bit(i)] += 1;
+                        array_total += 1;
+                    }
                 }
             }
 
@@ -157,45 +544,185 @@ impl<'gc> TableState<'gc> {
             }
 
             let old_array_size = self.array.len();
-            let old_map_size = self.map.len();
             if optimal_size > old_array_size {
                 // If we're growing the array part, we need to grow the array and take any newly valid
-                // array keys from the map part.
-
-                self.array.reserve(optimal_size - old_array_size);
-                let capacity = self.array.capacity();
-                self.array.resize(capacity, Value::Nil);
+                // array keys from the map part.  The array part is sized exactly like PUC-Rio Lua's,
+                // so that `#` finds the same borders.
+                self.array.resize(optimal_size, Value::Nil);
+            }
 
-                let array = &mut self.array;
-                self.map.retain(|k, v| {
-                    if let Some(i) = to_array_index(k.0) {
-                        if i < array.len() {
-                            array[i] = *v;
-                            return false;
-                        }
+            // Move any entries that now belong in the array part, and drop any entries that have
+            // been removed.  This changes entry positions, so the index must be rebuilt.
+            let array = &mut self.array;
+            self.entries.retain(|(k, v)| {
+                if *v == Value::Nil {
+                    return false;
+                }
+                if let Some(i) = to_array_index(k.0) {
+                    if i < array.len() {
+                        array[i] = *v;
+                        return false;
                     }
-                    true
-                });
-            } else {
-                // If we aren't growing the array, we're adding a new element to the map that won't
-                // fit in the advertised capacity.  The capacity of std::collections::HashMap is
-                // just a lower-bound, so we may actually be able to insert past the capacity
-                // without the advertised capacity growing, so to make sure that we don't try to
-                // grow repeatedly, we need to make sure the capacity actually increases.  We simply
-                // double the capacity here.
-                self.map.reserve(old_map_size);
+                }
+                true
+            });
+            self.index.clear();
+            for (i, (k, _)) in self.entries.iter().enumerate() {
+                self.index.insert(*k, i);
             }
 
-            // Now we can insert the new key value pair
+            // Now we can insert the new key value pair.  If the map part is still full, pushing the
+            // new entry grows its capacity, so that we don't try to resize again on every insert.
             if let Some(index) = index_key {
                 if index < self.array.len() {
                     return Ok(mem::replace(&mut self.array[index], value));
                 }
             }
-            Ok(self.map.insert(hash_key, value).unwrap_or(Value::Nil))
+            self.insert_entry(hash_key, value);
+            Ok(Value::Nil)
         }
     }
 
+    /// Inserts a value at `position` in the sequence from 1 to `length`, moving the elements from
+    /// `position` onwards up by one.  When the sequence is held in the array part, the elements are
+    /// moved in place rather than with a `get` and `set` for each one.
+    pub fn insert(
+        &mut self,
+        length: i64,
+        position: i64,
+        value: Value<'gc>,
+    ) -> Result<(), InvalidTableKey> {
+        if position <= length {
+            // Moving the last element first may grow the array part to hold the whole sequence
+            let last = self.get(Value::Integer(length));
+            self.set(Value::Integer(length.wrapping_add(1)), last)?;
+            if position >= 1 && length as u64 <= self.array.len() as u64 {
+                self.array[position as usize - 1..length as usize].rotate_right(1);
+            } else {
+                let mut i = length;
+                while i > position {
+                    let v = self.get(Value::Integer(i - 1));
+                    self.set(Value::Integer(i), v)?;
+                    i -= 1;
+                }
+            }
+        }
+        self.set(Value::Integer(position), value)?;
+        Ok(())
+    }
+
+    /// Removes the value at `position` in the sequence from 1 to `length`, moving the elements after
+    /// it down by one and clearing the last element.  Returns the removed value.
+    pub fn remove(&mut self, length: i64, position: i64) -> Result<Value<'gc>, InvalidTableKey> {
+        self.check_writable()?;
+        let removed = self.get(Value::Integer(position));
+        if position < length {
+            if position >= 1 && length as u64 <= self.array.len() as u64 {
+                self.array[position as usize - 1..length as usize].rotate_left(1);
+            } else {
+                for i in position..length {
+                    let v = self.get(Value::Integer(i + 1));
+                    self.set(Value::Integer(i), v)?;
+                }
+            }
+        }
+        self.set(Value::Integer(position.max(length)), Value::Nil)?;
+        Ok(removed)
+    }
+
+    /// Copies `count` values starting at the key `start` to the keys starting at `dest_start`, with
+    /// the same result as copying through a temporary buffer when the ranges overlap.  Ranges that
+    /// are both in the array part are copied directly.
+    pub fn move_within(
+        &mut self,
+        start: i64,
+        count: i64,
+        dest_start: i64,
+    ) -> Result<(), InvalidTableKey> {
+        self.check_writable()?;
+        if let (Some(source), Some(dest)) = (
+            self.array_range(start, count),
+            self.array_range(dest_start, count),
+        ) {
+            self.array.copy_within(source, dest.start);
+        } else if dest_start > start {
+            // Copying backwards never overwrites a value before it is read
+            for i in (0..count).rev() {
+                let v = self.get(Value::Integer(start + i));
+                self.set(Value::Integer(dest_start + i), v)?;
+            }
+        } else {
+            for i in 0..count {
+                let v = self.get(Value::Integer(start + i));
+                self.set(Value::Integer(dest_start + i), v)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Copies `count` values starting at the key `start` in another table to the keys starting at
+    /// `dest_start` in this one.  Ranges that are both in the array parts are copied directly.
+    pub fn copy_range(
+        &mut self,
+        source: &TableState<'gc>,
+        start: i64,
+        count: i64,
+        dest_start: i64,
+    ) -> Result<(), InvalidTableKey> {
+        self.check_writable()?;
+        if let (Some(source_range), Some(dest)) = (
+            source.array_range(start, count),
+            self.array_range(dest_start, count),
+        ) {
+            self.array[dest].copy_from_slice(&source.array[source_range]);
+        } else {
+            for i in 0..count {
+                self.set(
+                    Value::Integer(dest_start + i),
+                    source.get(Value::Integer(start + i)),
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the key and value of the entry that follows the given key in the traversal order of
+    /// the table, skipping over Nil values, or `None` if there are no more entries.  A Nil key
+    /// returns the first entry.
+    ///
+    /// Traversal is stable as long as no new keys are added to the table, existing keys may be
+    /// assigned or removed.
+    pub fn next(
+        &self,
+        key: Value<'gc>,
+    ) -> Result<Option<(Value<'gc>, Value<'gc>)>, InvalidTableKey> {
+        let (array_start, entries_start) = if key == Value::Nil {
+            (0, 0)
+        } else {
+            match to_array_index(key) {
+                Some(index) if index < self.array.len() => (index + 1, 0),
+                _ => match self.index.get(&TableKey::new(key)?) {
+                    Some(&i) => (self.array.len(), i + 1),
+                    None => return Err(InvalidTableKey::NotPresent),
+                },
+            }
+        };
+
+        for i in array_start..self.array.len() {
+            if self.array[i] != Value::Nil {
+                return Ok(Some((Value::Integer(i as i64 + 1), self.array[i])));
+            }
+        }
+
+        for (k, v) in &self.entries[entries_start..] {
+            if *v != Value::Nil {
+                return Ok(Some((k.0, *v)));
+            }
+        }
+
+        Ok(None)
+    }
+
     /// Returns a 'border' for this table.
     ///
     /// A 'border' for a table is any i >= 0 where:
@@ -223,40 +750,120 @@ impl<'gc> TableState<'gc> {
         if !self.array.is_empty() && self.array[array_len as usize - 1] == Value::Nil {
             // If the array part ends in a Nil, there must be a border inside it
             binary_search(0, array_len, |i| self.array[i as usize - 1] == Value::Nil)
-        } else if self.map.is_empty() {
+        } else if self.entries.is_empty() {
             // If there is no border in the arraay but the map part is empty, then the array length
             // is a border
             array_len
         } else {
             // Otherwise, we must check the map part for a border.  We need to find some nil value
-            // in the map part as the max for a binary search.
-            let min = array_len;
+            // in the map part as the max for a binary search, moving the min up to every non-nil
+            // value on the way, as PUC-Rio Lua's `unbound_search` does.
+            let is_nil = |i| self.get_entry(TableKey(Value::Integer(i))) == Value::Nil;
+            let mut min = array_len;
             let mut max = array_len.checked_add(1).unwrap();
-            while self.map.contains_key(&TableKey(Value::Integer(max))) {
-                if max == i64::MAX {
-                    // If we can't find a nil entry by doubling, then the table is pathalogical.  We
-                    // return the favor with a pathalogical answer: i64::MAX + 1 can't exist in the
-                    // table, therefore it is Nil, so since the table contains i64::MAX, i64::MAX is
-                    // a border.
-                    return i64::MAX;
-                } else if let Some(double_max) = max.checked_mul(2) {
-                    max = double_max;
-                } else {
-                    max = i64::MAX;
+            while !is_nil(max) {
+                min = max;
+                if max > i64::MAX / 2 {
+                    // If we can't find a nil entry by doubling, then the table is pathological,
+                    // and we fall back to a linear search from the start like PUC-Rio Lua.
+                    let mut i = 1;
+                    while !is_nil(i) {
+                        i += 1;
+                    }
+                    return i - 1;
                 }
+                max *= 2;
             }
 
             // We have found a max where table[max] == nil, so we can now binary search
-            binary_search(min, max, |i| {
-                !self.map.contains_key(&TableKey(Value::Integer(i)))
-            })
+            binary_search(min, max, is_nil)
+        }
+    }
+
+    /// While collecting garbage, traces the values of the entries whose weak keys have been found to
+    /// be reachable, returning whether any were traced.  The values are reachable through their
+    /// keys, and are traced again if they are found through other keys.
+    pub(crate) fn trace_ephemerons(&self, cc: CollectionContext) -> bool {
+        let mode = match self.weak_mode {
+            Some(mode) if mode.keys => mode,
+            _ => return false,
+        };
+        let mut traced = false;
+        for &(key, value) in &self.entries {
+            if is_weak_reference(key.0)
+                && !is_dead(cc, key.0)
+                && !(mode.values && is_weak_reference(value))
+                && is_dead(cc, value)
+            {
+                value.trace(cc);
+                traced = true;
+            }
+        }
+        traced
+    }
+
+    /// While collecting garbage, clears the entries whose weak values are about to be freed, and
+    /// also those whose weak keys are about to be freed if `keys` is true.  An entry is cleared by
+    /// removing its key along with its value, since the key will no longer be valid.
+    pub(crate) fn clear_weak(&mut self, cc: CollectionContext, keys: bool) {
+        let mode = self.weak_mode.unwrap_or_default();
+        if mode.values {
+            for value in &mut self.array {
+                if is_weak_reference(*value) && is_dead(cc, *value) {
+                    *value = Value::Nil;
+                }
+            }
+        }
+        for (key, value) in &mut self.entries {
+            if keys && mode.keys && is_weak_reference(key.0) && is_dead(cc, key.0) {
+                // The entry stays in place until the map part is next resized, like other removed
+                // entries, but with a Nil key that can never be looked up
+                self.index.remove(key);
+                *key = TableKey(Value::Nil);
+                *value = Value::Nil;
+            } else if mode.values && is_weak_reference(*value) && is_dead(cc, *value) {
+                *value = Value::Nil;
+            }
         }
     }
+
+    fn check_writable(&self) -> Result<(), InvalidTableKey> {
+        if self.frozen {
+            Err(InvalidTableKey::ReadOnly)
+        } else {
+            Ok(())
+        }
+    }
+
+    // Returns the positions in the array part of the `count` keys starting at `start`, if they are
+    // all in the array part.
+    fn array_range(&self, start: i64, count: i64) -> Option<Range<usize>> {
+        let first = usize::try_from(start.checked_sub(1)?).ok()?;
+        let end = first.checked_add(usize::try_from(count).ok()?)?;
+        if end <= self.array.len() {
+            Some(first..end)
+        } else {
+            None
+        }
+    }
+
+    fn get_entry(&self, key: TableKey<'gc>) -> Value<'gc> {
+        match self.index.get(&key) {
+            Some(&i) => self.entries[i].1,
+            None => Value::Nil,
+        }
+    }
+
+    // Adds an entry for a key that is not yet in the map part
+    fn insert_entry(&mut self, key: TableKey<'gc>, value: Value<'gc>) {
+        self.index.insert(key, self.entries.len());
+        self.entries.push((key, value));
+    }
 }
 
 // Value which implements Hash and Eq, and cannot contain Nil or NaN values.
-#[derive(Debug, Collect, PartialEq)]
-#[collect(empty_drop)]
+#[derive(Debug, Copy, Clone, Collect, PartialEq)]
+#[collect(require_copy)]
 struct TableKey<'gc>(Value<'gc>);
 
 impl<'gc> Eq for TableKey<'gc> {}
@@ -293,6 +900,10 @@ impl<'gc> Hash for TableKey<'gc> {
                 Hash::hash(&7, state);
                 t.hash(state);
             }
+            Value::UserData(u) => {
+                Hash::hash(&8, state);
+                u.hash(state);
+            }
         }
     }
 }
@@ -388,3 +999,26 @@ fn highest_bit(mut i: usize) -> usize {
 
     hb + LOG_2[i] as usize
 }
+
+// Whether the value is an object that may be freed while a weak table refers to it.
+fn is_weak_reference<'gc>(value: Value<'gc>) -> bool {
+    match value {
+        Value::Table(_) | Value::Function(_) | Value::Thread(_) | Value::UserData(_) => true,
+        _ => false,
+    }
+}
+
+// Whether the value is an object that the collector has not found to be reachable, see
+// `Gc::is_dead`.
+fn is_dead<'gc>(cc: CollectionContext, value: Value<'gc>) -> bool {
+    match value {
+        Value::String(String::Short(string)) => Gc::is_dead(cc, string),
+        Value::String(String::Long(string)) => Gc::is_dead(cc, string),
+        Value::Table(table) => GcCell::is_dead(cc, table.0),
+        Value::Function(Function::Closure(closure)) => Gc::is_dead(cc, closure.0),
+        Value::Function(Function::Callback(callback)) => Gc::is_dead(cc, callback.0),
+        Value::Thread(thread) => GcCell::is_dead(cc, thread.0),
+        Value::UserData(userdata) => GcCell::is_dead(cc, userdata.0),
+        _ => false,
+    }
+}
@@ -0,0 +1,320 @@
+use std::fmt::{self, Write};
+use std::string::String as StdString;
+
+use gc_arena::Collect;
+
+use crate::{
+    error::chunk_id, Closure, Constant, ConstantIndex8, FunctionProto, Location, OpCode,
+    RegisterIndex, UpValueIndex,
+};
+
+// Long tracebacks only show this many of the innermost and outermost functions, like PUC-Rio Lua
+const TRACEBACK_INNER: usize = 10;
+const TRACEBACK_OUTER: usize = 11;
+
+/// A function on a thread's call stack, as returned by `Thread::call_stack`.
+///
+/// Displays as a line of a traceback, like `file.lua:12: in function 'f'`.
+#[derive(Debug, Clone, Collect)]
+#[collect(empty_drop)]
+pub struct StackFrame<'gc> {
+    /// The Lua function running in this frame, or `None` if the frame is a callback.
+    pub closure: Option<Closure<'gc>>,
+    /// The chunk name and current line of a Lua function.
+    pub location: Option<Location>,
+    /// The name of the function, if it was called from Lua code that names it.
+    pub name: Option<FunctionName>,
+    /// Whether the function was tail called, so the function that called it is no longer on the
+    /// call stack.
+    pub is_tail_call: bool,
+}
+
+impl<'gc> StackFrame<'gc> {
+    /// Returns a copy of this frame that can be kept outside of the arena.
+    pub fn to_static(&self) -> TracebackFrame {
+        TracebackFrame {
+            location: self.location.clone(),
+            name: self.name.clone(),
+            description: self.description(),
+            is_tail_call: self.is_tail_call,
+        }
+    }
+
+    // Describes the function, like `function 'f'` or `main chunk`.
+    fn description(&self) -> StdString {
+        match (&self.name, self.closure) {
+            (Some(FunctionName::Global(name)), _) => format!("function '{}'", name),
+            (Some(name), _) => format!("{} '{}'", name.kind(), name.name()),
+            (None, Some(closure)) if closure.0.proto.line_defined == 0 => "main chunk".to_owned(),
+            (None, Some(closure)) => {
+                let proto = &closure.0.proto;
+                let chunk_name = match proto.chunk_name {
+                    Some(name) => chunk_id(&StdString::from_utf8_lossy(name.as_bytes())),
+                    None => "?".to_owned(),
+                };
+                format!("function <{}:{}>", chunk_name, proto.line_defined)
+            }
+            (None, None) => "?".to_owned(),
+        }
+    }
+}
+
+impl<'gc> fmt::Display for StackFrame<'gc> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt_frame(fmt, &self.location, &self.description())
+    }
+}
+
+/// The call stack of a thread at the point an error was raised that was not handled by any
+/// function on the thread, innermost function first.
+///
+/// Displays like PUC-Rio Lua's `debug.traceback`, starting with `stack traceback:`, with a
+/// `(...tail calls...)` line where the functions that made tail calls are missing.
+#[derive(Debug, Clone, PartialEq, Eq, Collect)]
+#[collect(require_static)]
+pub struct Traceback(pub Vec<TracebackFrame>);
+
+impl fmt::Display for Traceback {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{}", format_traceback(&self.0))
+    }
+}
+
+/// A function in a `Traceback`, which unlike `StackFrame` may be kept outside of the arena.
+///
+/// Displays as a line of a traceback, like `file.lua:12: in function 'f'`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Collect)]
+#[collect(require_static)]
+pub struct TracebackFrame {
+    /// The chunk name and current line of a Lua function, or `None` for a callback.
+    pub location: Option<Location>,
+    /// The name of the function, if it was called from Lua code that names it.
+    pub name: Option<FunctionName>,
+    /// How the function is shown in a traceback, like `function 'f'` or `main chunk`.
+    pub description: StdString,
+    /// Whether the function was tail called, shown in a traceback by a `(...tail calls...)` line
+    /// after the function.
+    pub is_tail_call: bool,
+}
+
+impl fmt::Display for TracebackFrame {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt_frame(fmt, &self.location, &self.description)
+    }
+}
+
+fn fmt_frame(
+    fmt: &mut fmt::Formatter,
+    location: &Option<Location>,
+    description: &str,
+) -> fmt::Result {
+    match location {
+        Some(location) => write!(fmt, "{}: in {}", location, description),
+        None => write!(fmt, "[C]: in {}", description),
+    }
+}
+
+/// How a function on the call stack was named by the code that called it, like the `name` and
+/// `namewhat` fields of `debug.getinfo`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Collect)]
+#[collect(require_static)]
+pub enum FunctionName {
+    Global(StdString),
+    Field(StdString),
+    Method(StdString),
+    UpValue(StdString),
+    /// The iterator function of a generic `for` loop
+    ForIterator,
+    /// A debug hook called by the VM
+    Hook,
+}
+
+impl FunctionName {
+    /// The kind of name, one of "global", "field", "method", "upvalue", "for iterator" or "hook".
+    pub fn kind(&self) -> &'static str {
+        match self {
+            FunctionName::Global(_) => "global",
+            FunctionName::Field(_) => "field",
+            FunctionName::Method(_) => "method",
+            FunctionName::UpValue(_) => "upvalue",
+            FunctionName::ForIterator => "for iterator",
+            FunctionName::Hook => "hook",
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            FunctionName::Global(name)
+            | FunctionName::Field(name)
+            | FunctionName::Method(name)
+            | FunctionName::UpValue(name) => name,
+            FunctionName::ForIterator => "for iterator",
+            FunctionName::Hook => "?",
+        }
+    }
+}
+
+// Formats a traceback of the given stack frames, innermost first.
+pub(crate) fn format_traceback(frames: &[TracebackFrame]) -> StdString {
+    let mut traceback = StdString::from("stack traceback:");
+    let elided = frames.len() > TRACEBACK_INNER + TRACEBACK_OUTER + 1;
+    for (i, frame) in frames.iter().enumerate() {
+        if elided && i >= TRACEBACK_INNER && i < frames.len() - TRACEBACK_OUTER {
+            if i == TRACEBACK_INNER {
+                traceback.push_str("\n\t...");
+            }
+            continue;
+        }
+        write!(traceback, "\n\t{}", frame).unwrap();
+        if frame.is_tail_call {
+            traceback.push_str("\n\t(...tail calls...)");
+        }
+    }
+    traceback
+}
+
+// Finds the name of the function called by the instruction at `pc`, from the instruction that
+// loaded the function, like PUC-Rio Lua's `getfuncname`.
+pub(crate) fn called_function_name(proto: &FunctionProto, pc: usize) -> Option<FunctionName> {
+    let register = match *proto.opcodes.get(pc)? {
+        OpCode::Call { func, .. } | OpCode::TailCall { func, .. } => func,
+        OpCode::GenericForCall { .. } => return Some(FunctionName::ForIterator),
+        _ => return None,
+    };
+
+    let constant_name = |key: ConstantIndex8| match proto.constants.get(key.0 as usize) {
+        Some(Constant::String(name)) => Some(StdString::from_utf8_lossy(name.as_bytes()).into()),
+        _ => None,
+    };
+    let upvalue_name = |upvalue: UpValueIndex| {
+        proto
+            .upvalue_names
+            .get(upvalue.0 as usize)
+            .map(|name| StdString::from_utf8_lossy(name.as_bytes()).into_owned())
+    };
+
+    match proto.opcodes[find_set_register(&proto.opcodes, pc, register)?] {
+        OpCode::GetUpTableC { table, key, .. } => {
+            let name = constant_name(key)?;
+            if upvalue_name(table).as_ref().map(|n| n.as_str()) == Some("_ENV") {
+                Some(FunctionName::Global(name))
+            } else {
+                Some(FunctionName::Field(name))
+            }
+        }
+        OpCode::GetTableC { key, .. } => Some(FunctionName::Field(constant_name(key)?)),
+        OpCode::SelfC { base, key, .. } if base == register => {
+            Some(FunctionName::Method(constant_name(key)?))
+        }
+        OpCode::GetUpValue { source, .. } => Some(FunctionName::UpValue(upvalue_name(source)?)),
+        _ => None,
+    }
+}
+
+// Finds the last instruction before `last_pc` that sets the given register, like PUC-Rio Lua's
+// `findsetreg`.  An instruction that a forward jump before `last_pc` may skip is not certain to
+// have set the register, so none is found.
+fn find_set_register(opcodes: &[OpCode], last_pc: usize, register: RegisterIndex) -> Option<usize> {
+    let mut found = None;
+    let mut jump_target = 0;
+    for (pc, &opcode) in opcodes[..last_pc].iter().enumerate() {
+        let offset = match opcode {
+            OpCode::Jump { offset, .. } => Some(offset),
+            mut opcode => opcode.fused_jump().map(|&mut jump| jump as i16),
+        };
+        if let Some(offset) = offset {
+            let target = pc as isize + 1 + offset as isize;
+            if target > pc as isize && target <= last_pc as isize {
+                jump_target = jump_target.max(target as usize);
+            }
+        } else if sets_register(opcode, register.0) {
+            found = if pc < jump_target { None } else { Some(pc) };
+        }
+    }
+    found
+}
+
+// Whether the given instruction may change the value of the given register.
+fn sets_register(opcode: OpCode, register: u8) -> bool {
+    let register = register as usize;
+    match opcode {
+        OpCode::LoadNil { dest, count } => {
+            register >= dest.0 as usize && register < dest.0 as usize + count as usize
+        }
+        OpCode::Call { func, .. } | OpCode::TailCall { func, .. } => register >= func.0 as usize,
+        OpCode::VarArgs { dest, .. } => register >= dest.0 as usize,
+        OpCode::GenericForCall { base, .. } => register >= base.0 as usize + 3,
+        OpCode::NumericForPrep { base, .. } | OpCode::NumericForLoop { base, .. } => {
+            register >= base.0 as usize && register <= base.0 as usize + 3
+        }
+        OpCode::GenericForLoop { base, .. } => register == base.0 as usize,
+        OpCode::SelfR { base, .. } | OpCode::SelfC { base, .. } => {
+            register == base.0 as usize || register == base.0 as usize + 1
+        }
+        OpCode::Move { dest, .. }
+        | OpCode::LoadConstant { dest, .. }
+        | OpCode::LoadBool { dest, .. }
+        | OpCode::NewTable { dest, .. }
+        | OpCode::GetTableR { dest, .. }
+        | OpCode::GetTableC { dest, .. }
+        | OpCode::GetUpTableR { dest, .. }
+        | OpCode::GetUpTableC { dest, .. }
+        | OpCode::TestSet { dest, .. }
+        | OpCode::Closure { dest, .. }
+        | OpCode::Concat { dest, .. }
+        | OpCode::GetUpValue { dest, .. }
+        | OpCode::Length { dest, .. }
+        | OpCode::Not { dest, .. }
+        | OpCode::Minus { dest, .. }
+        | OpCode::AddRR { dest, .. }
+        | OpCode::AddRC { dest, .. }
+        | OpCode::AddCR { dest, .. }
+        | OpCode::AddCC { dest, .. }
+        | OpCode::SubRR { dest, .. }
+        | OpCode::SubRC { dest, .. }
+        | OpCode::SubCR { dest, .. }
+        | OpCode::SubCC { dest, .. }
+        | OpCode::MulRR { dest, .. }
+        | OpCode::MulRC { dest, .. }
+        | OpCode::MulCR { dest, .. }
+        | OpCode::MulCC { dest, .. }
+        | OpCode::DivRR { dest, .. }
+        | OpCode::DivRC { dest, .. }
+        | OpCode::DivCR { dest, .. }
+        | OpCode::DivCC { dest, .. }
+        | OpCode::IDivRR { dest, .. }
+        | OpCode::IDivRC { dest, .. }
+        | OpCode::IDivCR { dest, .. }
+        | OpCode::IDivCC { dest, .. }
+        | OpCode::ModRR { dest, .. }
+        | OpCode::ModRC { dest, .. }
+        | OpCode::ModCR { dest, .. }
+        | OpCode::ModCC { dest, .. }
+        | OpCode::PowRR { dest, .. }
+        | OpCode::PowRC { dest, .. }
+        | OpCode::PowCR { dest, .. }
+        | OpCode::PowCC { dest, .. }
+        | OpCode::BitAndRR { dest, .. }
+        | OpCode::BitAndRC { dest, .. }
+        | OpCode::BitAndCR { dest, .. }
+        | OpCode::BitAndCC { dest, .. }
+        | OpCode::BitOrRR { dest, .. }
+        | OpCode::BitOrRC { dest, .. }
+        | OpCode::BitOrCR { dest, .. }
+        | OpCode::BitOrCC { dest, .. }
+        | OpCode::BitXorRR { dest, .. }
+        | OpCode::BitXorRC { dest, .. }
+        | OpCode::BitXorCR { dest, .. }
+        | OpCode::BitXorCC { dest, .. }
+        | OpCode::ShiftLeftRR { dest, .. }
+        | OpCode::ShiftLeftRC { dest, .. }
+        | OpCode::ShiftLeftCR { dest, .. }
+        | OpCode::ShiftLeftCC { dest, .. }
+        | OpCode::ShiftRightRR { dest, .. }
+        | OpCode::ShiftRightRC { dest, .. }
+        | OpCode::ShiftRightCR { dest, .. }
+        | OpCode::ShiftRightCC { dest, .. }
+        | OpCode::BitNot { dest, .. } => register == dest.0 as usize,
+        _ => false,
+    }
+}
@@ -0,0 +1,100 @@
+use gc_arena::Collect;
+
+use crate::{Function, HookContext};
+
+/// A debug hook set on a thread with `Thread::set_hook`, like a hook set with `debug.sethook`.
+///
+/// The hook function is called with the name of the event, followed by the new line number for line
+/// events.  Callbacks cause call and return events like Lua functions, but no line or count events.
+/// Hooks are not called while the hook function itself is running.
+#[derive(Debug, Copy, Clone, Collect)]
+#[collect(require_copy)]
+pub struct Hook<'gc> {
+    pub function: Function<'gc>,
+    /// Call the hook when a function is called, including through a tail call.
+    pub call: bool,
+    /// Call the hook when a function is about to return.
+    pub returns: bool,
+    /// Call the hook when a Lua function is about to start a new line, or jumps back in the code.
+    pub line: bool,
+    /// Call the hook after every `count` instructions, or never if this is 0.
+    pub count: u32,
+}
+
+/// An event that a `Hook` is called for.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum HookEvent {
+    Call,
+    Return,
+    Line(u64),
+    Count,
+}
+
+impl HookEvent {
+    /// The name of the event passed to the hook function.
+    pub fn name(self) -> &'static str {
+        match self {
+            HookEvent::Call => "call",
+            HookEvent::Return => "return",
+            HookEvent::Line(_) => "line",
+            HookEvent::Count => "count",
+        }
+    }
+}
+
+/// Observes a thread from Rust, set with `Thread::set_host_hook`.  Unlike a `Hook`, a host hook
+/// runs inside the VM rather than as a Lua function, so it cannot change the thread or raise
+/// errors, and it is called even while a Lua debug hook is running.
+///
+/// Unlike with `Hook`, only Lua functions cause call and return events.  A tail call is seen as a
+/// return from the calling function followed by a call of the new one, and functions that are left
+/// because of an error also cause a return event, so every call event is followed by a return
+/// event unless the thread is closed or reset first.  Coroutines created by code running in the
+/// thread get the same hook.
+pub trait HostHook {
+    /// Called when a Lua function is called, once its frame is the innermost in `context`.
+    fn call(&self, _context: &HookContext) {}
+
+    /// Called when a Lua function returns, while its frame is still the innermost in `context`.
+    fn returns(&self, _context: &HookContext) {}
+
+    /// How many instructions to run in-between calls to `count`, or 0 to never call it.  This is
+    /// read once when the hook is set.
+    fn count_interval(&self) -> u32 {
+        0
+    }
+
+    /// Called after every `count_interval` instructions, before the next instruction runs.
+    fn count(&self, _context: &HookContext) {}
+}
+
+// The events found for an instruction that the hook has not been called for yet, delivered in the
+// order call, count, line and return.
+#[derive(Debug, Copy, Clone, Default, Collect)]
+#[collect(require_static)]
+pub(crate) struct HookEvents {
+    pub call: bool,
+    pub count: bool,
+    pub line: Option<u64>,
+    pub returns: bool,
+}
+
+impl HookEvents {
+    // Removes and returns the next event to deliver.
+    pub fn take_next(&mut self) -> Option<HookEvent> {
+        if self.call {
+            self.call = false;
+            Some(HookEvent::Call)
+        } else if self.count {
+            self.count = false;
+            Some(HookEvent::Count)
+        } else if let Some(line) = self.line.take() {
+            Some(HookEvent::Line(line))
+        } else if self.returns {
+            self.returns = false;
+            Some(HookEvent::Return)
+        } else {
+            None
+        }
+    }
+}
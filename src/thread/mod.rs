@@ -1,9 +1,17 @@
+mod call_stack;
 mod error;
+mod hook;
 mod thread;
 mod vm;
 
+pub use call_stack::{FunctionName, StackFrame, Traceback, TracebackFrame};
 pub use error::{BadThreadMode, BinaryOperatorError, ThreadError};
-pub use thread::{Thread, ThreadMode, ThreadSequence};
+pub use hook::{Hook, HookEvent, HostHook};
+pub use thread::{
+    HookContext, ResumeResult, ResumeSequence, Thread, ThreadMode, ThreadSequence, ThreadStatus,
+};
 
-pub(crate) use thread::LuaFrame;
+pub(crate) use thread::{
+    CoroutineSequence, LuaFrame, LuaReturn, MetaReturn, SavedFrame, SavedThread,
+};
 pub(crate) use vm::run_vm;
@@ -1,14 +1,24 @@
-use std::collections::btree_map::Entry as BTreeEntry;
-use std::collections::BTreeMap;
+use std::cell::Cell;
 use std::fmt::{self, Debug};
 use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+use std::string::String as StdString;
 
-use gc_arena::{Collect, GcCell, MutationContext};
-use gc_sequence::Sequence;
+use gc_arena::{Collect, GcCell, MutationContext, StaticCollect};
+use gc_sequence::{self as sequence, Sequence};
 
 use crate::{
-    thread::run_vm, BadThreadMode, CallbackResult, CallbackReturn, Closure, Continuation, Error,
-    Function, RegisterIndex, ThreadError, TypeError, UpValue, UpValueState, Value, VarCount,
+    finalizers::Finalizers,
+    meta_ops,
+    thread::{
+        call_stack::{called_function_name, format_traceback},
+        hook::{HookEvents, HostHook},
+        run_vm, FunctionName, StackFrame, Traceback,
+    },
+    BadThreadMode, Callback, CallbackResult, CallbackReturn, Closure, Continuation, Error,
+    Function, Hook, HookEvent, InternedStringSet, Interrupt, Location, MetaOperatorError, OpCode,
+    RegisterIndex, RuntimeError, String, Table, ThreadError, UpValue, UpValueState, Value,
+    VarCount,
 };
 
 #[derive(Clone, Copy, Collect)]
@@ -45,20 +55,106 @@ pub enum ThreadMode {
     Running,
     // Thread has yielded and is waiting on being resumed
     Suspended,
+    // Thread has an active frame but has used up its fuel, and is waiting on more being given with
+    // `Thread::resume_with_fuel`
+    OutOfFuel,
+}
+
+/// The status of a thread used as a coroutine, as returned by `Thread::status`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThreadStatus {
+    // The thread has no function to run, either because it was never given one or because its
+    // function has returned or raised an error
+    Dead,
+    // The thread is waiting to be resumed, either before its function has started or after it has
+    // yielded
+    Suspended,
+    // The thread is running its function, which may be waiting on more fuel or on the results of
+    // another coroutine
+    Running,
+}
+
+/// What happened when a suspended thread was resumed, as returned by `Thread::take_resume_result`
+/// and `ResumeSequence`.
+// Safe, does not implement drop
+#[derive(Debug, Collect)]
+#[collect(unsafe_drop)]
+pub enum ResumeResult<'gc> {
+    // The thread yielded these values, and is suspended again
+    Yielded(Vec<Value<'gc>>),
+    // The thread's function returned these values, and the thread is dead
+    Returned(Vec<Value<'gc>>),
+    // The thread's function raised this error, and the thread is dead
+    Errored(Error<'gc>),
+}
+
+impl<'gc> ResumeResult<'gc> {
+    /// The values yielded or returned, or the error raised, without distinguishing a yield from a
+    /// return.
+    pub fn into_result(self) -> Result<Vec<Value<'gc>>, Error<'gc>> {
+        match self {
+            ResumeResult::Yielded(values) | ResumeResult::Returned(values) => Ok(values),
+            ResumeResult::Errored(error) => Err(error),
+        }
+    }
 }
 
 #[derive(Collect)]
 #[collect(empty_drop)]
 pub struct ThreadSequence<'gc>(pub Thread<'gc>);
 
+/// Steps a resumed thread until it yields, returns or raises an error.
+#[derive(Collect)]
+#[collect(empty_drop)]
+pub struct ResumeSequence<'gc>(pub Thread<'gc>);
+
+// Steps a coroutine resumed from another thread.  Unlike `ThreadSequence`, a coroutine that is out
+// of fuel waits for more rather than failing, since the thread that resumed it shares its fuel and
+// so is out of fuel as well.
+#[derive(Collect)]
+#[collect(empty_drop)]
+pub(crate) struct CoroutineSequence<'gc>(pub(crate) Thread<'gc>);
+
 #[derive(Collect)]
 #[collect(empty_drop)]
 pub(crate) struct ThreadState<'gc> {
     values: Vec<Value<'gc>>,
     frames: Vec<Frame<'gc>>,
-    open_upvalues: BTreeMap<usize, UpValue<'gc>>,
+    // Every open upvalue along with the stack index it refers to, ordered by stack index.  Since
+    // upvalues are opened and closed in stack order, the ones a frame or block needs to close are
+    // always found at the end.
+    open_upvalues: Vec<(usize, UpValue<'gc>)>,
+    // Stack indexes of all live to-be-closed variables, in the order they were declared
+    to_be_closed: Vec<usize>,
+    // Spare buffers for passing arguments to callbacks and results to continuations, which are
+    // returned here once the values in them have been copied back onto the stack.  This saves
+    // allocating for most calls to callbacks.
+    buffers: Vec<Vec<Value<'gc>>>,
+    // The metatable shared by all string values in this Lua instance
+    string_metatable: Table<'gc>,
     result: Option<Result<Vec<Value<'gc>>, Error<'gc>>>,
+    // The value of the error that the last function run by the thread raised, if it did, which is
+    // kept until the thread is closed or started again
+    error: Option<Value<'gc>>,
     allow_yield: bool,
+    hook: Option<Hook<'gc>>,
+    // Instructions left to run until the next count event
+    hook_count: u32,
+    // While the hook is running, the index of its frame
+    hook_depth: Option<usize>,
+    // The number of instructions left to run, if limited, which is shared with any coroutines the
+    // thread creates
+    fuel: StaticCollect<Rc<Cell<Option<u64>>>>,
+    host_hook: Option<StaticCollect<Rc<dyn HostHook>>>,
+    // Instructions left to run until the next host hook count event, or 0 if there are none
+    host_hook_count: u32,
+    interrupt: Option<StaticCollect<Interrupt>>,
+    // Where to find the tables waiting to be finalized, whose finalizers are called before the
+    // thread runs any more Lua code
+    finalizers: Option<Finalizers<'gc>>,
+    // While a finalizer is running, the number of frames below it, so that the finalizers of other
+    // tables wait for it to return
+    finalizer_depth: Option<usize>,
 }
 
 pub(crate) struct LuaFrame<'gc, 'a> {
@@ -71,10 +167,47 @@ pub(crate) struct LuaRegisters<'gc, 'a> {
     pub stack_frame: &'a mut [Value<'gc>],
     upper_stack: &'a mut [Value<'gc>],
     base: usize,
-    open_upvalues: &'a mut BTreeMap<usize, UpValue<'gc>>,
+    open_upvalues: &'a mut Vec<(usize, UpValue<'gc>)>,
+    to_be_closed: &'a mut Vec<usize>,
     thread: Thread<'gc>,
 }
 
+/// A view of the thread that a `HostHook` is called for.
+pub struct HookContext<'gc, 'a> {
+    pub(crate) state: &'a ThreadState<'gc>,
+}
+
+impl<'gc, 'a> HookContext<'gc, 'a> {
+    /// The Lua function running in the innermost frame of the thread, if it is a Lua frame.
+    pub fn closure(&self) -> Option<Closure<'gc>> {
+        match self.state.frames.last() {
+            Some(frame @ Frame::Lua { .. }) => Some(frame_closure(self.state, frame)),
+            _ => None,
+        }
+    }
+
+    /// The chunk name and current line of the innermost Lua function.
+    pub fn location(&self) -> Option<Location> {
+        frame_location(self.state, self.state.frames.last()?)
+    }
+
+    /// The number of functions on the thread's call stack, the same as the length of
+    /// `HookContext::call_stack` but without having to build it.
+    pub fn depth(&self) -> usize {
+        self.state
+            .frames
+            .iter()
+            .filter(|frame| frame.is_on_call_stack())
+            .count()
+    }
+
+    /// The functions on the thread's call stack, starting with the innermost, as returned by
+    /// `Thread::call_stack`.
+    pub fn call_stack(&self) -> Vec<StackFrame<'gc>> {
+        call_stack(self.state)
+    }
+}
+
 impl<'gc> ThreadSequence<'gc> {
     /// Thread must be `Stopped` in order to call a function on it.
     pub fn call_function(
@@ -107,16 +240,79 @@ impl<'gc> Sequence<'gc> for ThreadSequence<'gc> {
     }
 }
 
+impl<'gc> ResumeSequence<'gc> {
+    /// Thread must be `Suspended` in order to resume it.
+    pub fn resume(
+        mc: MutationContext<'gc, '_>,
+        thread: Thread<'gc>,
+        args: &[Value<'gc>],
+    ) -> Result<ResumeSequence<'gc>, BadThreadMode> {
+        thread.resume(mc, args)?;
+        Ok(ResumeSequence(thread))
+    }
+}
+
+impl<'gc> Sequence<'gc> for ResumeSequence<'gc> {
+    type Output = ResumeResult<'gc>;
+
+    fn step(&mut self, mc: MutationContext<'gc, '_>) -> Option<Self::Output> {
+        match self.0.mode() {
+            ThreadMode::Results => self.0.take_resume_result(mc),
+            ThreadMode::Running => {
+                self.0.step(mc).unwrap();
+                None
+            }
+            mode => Some(ResumeResult::Errored(
+                BadThreadMode {
+                    expected: None,
+                    found: mode,
+                }
+                .into(),
+            )),
+        }
+    }
+}
+
+impl<'gc> Sequence<'gc> for CoroutineSequence<'gc> {
+    type Output = Result<Vec<Value<'gc>>, Error<'gc>>;
+
+    fn step(&mut self, mc: MutationContext<'gc, '_>) -> Option<Self::Output> {
+        match self.0.mode() {
+            ThreadMode::OutOfFuel => None,
+            _ => ThreadSequence(self.0).step(mc),
+        }
+    }
+}
+
 impl<'gc> Thread<'gc> {
-    pub fn new(mc: MutationContext<'gc, '_>, allow_yield: bool) -> Thread<'gc> {
+    /// Creates a new, stopped thread.  String values indexed by code running in this thread use the
+    /// given string metatable, which should be the one shared by the whole Lua instance.
+    pub fn new(
+        mc: MutationContext<'gc, '_>,
+        string_metatable: Table<'gc>,
+        allow_yield: bool,
+    ) -> Thread<'gc> {
         Thread(GcCell::allocate(
             mc,
             ThreadState {
                 values: Vec::new(),
                 frames: Vec::new(),
-                open_upvalues: BTreeMap::new(),
+                open_upvalues: Vec::new(),
+                to_be_closed: Vec::new(),
+                buffers: Vec::new(),
+                string_metatable,
                 result: None,
+                error: None,
                 allow_yield,
+                hook: None,
+                hook_count: 0,
+                hook_depth: None,
+                fuel: StaticCollect(Rc::new(Cell::new(None))),
+                host_hook: None,
+                host_hook_count: 0,
+                interrupt: None,
+                finalizers: None,
+                finalizer_depth: None,
             },
         ))
     }
@@ -129,6 +325,78 @@ impl<'gc> Thread<'gc> {
         }
     }
 
+    /// The status of this thread as a coroutine.  A thread whose results have not yet been taken is
+    /// `Suspended` if it yielded them, and `Dead` if it returned them or raised an error.
+    pub fn status(self) -> ThreadStatus {
+        let state = match self.0.try_read() {
+            Ok(state) => state,
+            Err(_) => return ThreadStatus::Running,
+        };
+        match get_mode(&state) {
+            ThreadMode::Stopped => ThreadStatus::Dead,
+            ThreadMode::Results => match state.frames.last() {
+                Some(Frame::ResumeCoroutine) => ThreadStatus::Suspended,
+                _ => ThreadStatus::Dead,
+            },
+            ThreadMode::Running | ThreadMode::OutOfFuel => ThreadStatus::Running,
+            ThreadMode::Suspended => ThreadStatus::Suspended,
+        }
+    }
+
+    /// Whether code running in this thread may yield, which is true for threads created as
+    /// coroutines and false for the main thread.
+    pub fn is_yieldable(self) -> bool {
+        self.0.read().allow_yield
+    }
+
+    /// The number of VM instructions this thread may run before it is `OutOfFuel`, or `None` if it
+    /// is unlimited, which it is for new threads.
+    pub fn fuel(self) -> Option<u64> {
+        self.0.read().fuel.0.get()
+    }
+
+    /// Limits the number of VM instructions this thread may run, or removes the limit.  Once the
+    /// thread has run that many instructions it stops in-between instructions and is `OutOfFuel`,
+    /// so that code that never finishes can be preempted.
+    ///
+    /// Coroutines created by code running in this thread share its fuel, as do any coroutines they
+    /// create in turn, so changing the fuel of one changes it for all of them.
+    pub fn set_fuel(self, fuel: Option<u64>) {
+        self.0.read().fuel.0.set(fuel);
+    }
+
+    /// If this thread is `OutOfFuel`, gives it `fuel` more instructions to run so that it is
+    /// `Running` again, and it can then be stepped from where it stopped.
+    pub fn resume_with_fuel(self, fuel: u64) -> Result<(), BadThreadMode> {
+        let state = self.0.read();
+        check_mode(&state, ThreadMode::OutOfFuel)?;
+        state.fuel.0.set(Some(fuel));
+        Ok(())
+    }
+
+    // Makes this thread share the fuel, host hook, interrupt and finalizers of another, for
+    // coroutines created from it.
+    pub(crate) fn inherit_from(self, mc: MutationContext<'gc, '_>, from: Thread<'gc>) {
+        let (fuel, host_hook, interrupt, finalizers) = {
+            let from = from.0.read();
+            (
+                from.fuel.0.clone(),
+                from.host_hook.as_ref().map(|hook| hook.0.clone()),
+                from.interrupt.as_ref().map(|interrupt| interrupt.0.clone()),
+                from.finalizers,
+            )
+        };
+        self.0.write(mc).fuel = StaticCollect(fuel);
+        self.0.write(mc).interrupt = interrupt.map(StaticCollect);
+        self.0.write(mc).finalizers = finalizers;
+        self.set_host_hook(mc, host_hook);
+    }
+
+    // Makes this thread call the finalizers of unreachable tables before it runs any more Lua code.
+    pub(crate) fn set_finalizers(self, mc: MutationContext<'gc, '_>, finalizers: Finalizers<'gc>) {
+        self.0.write(mc).finalizers = Some(finalizers);
+    }
+
     /// If this thread is `Stopped`, start a new function with the given arguments.
     pub fn start(
         self,
@@ -138,6 +406,7 @@ impl<'gc> Thread<'gc> {
     ) -> Result<(), BadThreadMode> {
         let mut state = self.0.write(mc);
         check_mode(&state, ThreadMode::Stopped)?;
+        state.error = None;
         ext_call_function(self, &mut state, mc, function, args);
         Ok(())
     }
@@ -150,6 +419,7 @@ impl<'gc> Thread<'gc> {
     ) -> Result<(), BadThreadMode> {
         let mut state = self.0.write(mc);
         check_mode(&state, ThreadMode::Stopped)?;
+        state.error = None;
         state.frames.push(Frame::StartCoroutine(function));
         Ok(())
     }
@@ -162,7 +432,23 @@ impl<'gc> Thread<'gc> {
         self.0.write(mc).result.take()
     }
 
-    /// If the thread is in `Suspended` mode, resume it.
+    /// Take any results if they are available, telling apart values that were yielded from values
+    /// that were returned.
+    pub fn take_resume_result(self, mc: MutationContext<'gc, '_>) -> Option<ResumeResult<'gc>> {
+        let mut state = self.0.write(mc);
+        let yielded = match state.frames.last() {
+            Some(Frame::ResumeCoroutine) => true,
+            _ => false,
+        };
+        Some(match state.result.take()? {
+            Ok(values) if yielded => ResumeResult::Yielded(values),
+            Ok(values) => ResumeResult::Returned(values),
+            Err(error) => ResumeResult::Errored(error),
+        })
+    }
+
+    /// If the thread is in `Suspended` mode, resume it.  The thread must then be stepped until it
+    /// has results, which can be taken with `Thread::take_resume_result`, or use `ResumeSequence`.
     pub fn resume(
         self,
         mc: MutationContext<'gc, '_>,
@@ -189,7 +475,10 @@ impl<'gc> Thread<'gc> {
                     callback_return(self, &mut state, mc, ret);
                 }
                 Some(Frame::Lua { .. }) => {
-                    return_to_lua(&mut state, args);
+                    return_to_lua(self, &mut state, mc, args);
+                }
+                Some(Frame::HookedCallback { .. }) => {
+                    hooked_callback_return(self, &mut state, mc, args.to_vec());
                 }
                 None => {
                     state.result = Some(Ok(args.to_vec()));
@@ -201,6 +490,280 @@ impl<'gc> Thread<'gc> {
         Ok(())
     }
 
+    /// If the thread is in `Suspended` mode, abandon its call stack and call the `__close`
+    /// metamethods of any pending to-be-closed variables, like `coroutine.close` in Lua 5.4.
+    ///
+    /// The thread must then be stepped until it has results, which are empty if every variable was
+    /// closed without error.  Otherwise the result is the last error raised, which is also passed
+    /// to the metamethods of the variables closed after it.
+    pub fn close(self, mc: MutationContext<'gc, '_>) -> Result<(), BadThreadMode> {
+        let mut state = self.0.write(mc);
+        let state = &mut *state;
+        check_mode(state, ThreadMode::Suspended)?;
+
+        let values = &state.values;
+        let pending = state
+            .to_be_closed
+            .drain(..)
+            .map(|index| values[index])
+            .collect::<Vec<_>>();
+        close_upvalues(self, state, mc, 0);
+        state.values.clear();
+        state.frames.clear();
+        state.hook_depth = None;
+
+        let res = close_pending(mc, pending, None);
+        return_ext(self, state, mc, res);
+        Ok(())
+    }
+
+    /// Stops the thread, discarding its frames and any results without calling the `__close`
+    /// metamethods of its to-be-closed variables.  This recovers a thread that was left running
+    /// when the sequence stepping it was abandoned, such as by dropping `Lua::resume_async`.
+    pub fn reset(self, mc: MutationContext<'gc, '_>) {
+        let mut state = self.0.write(mc);
+        let state = &mut *state;
+        close_upvalues(self, state, mc, 0);
+        state.values.clear();
+        state.frames.clear();
+        state.to_be_closed.clear();
+        state.result = None;
+        state.error = None;
+        state.hook_depth = None;
+        state.finalizer_depth = None;
+    }
+
+    // Takes the value of the error that the last function run by this thread raised, for
+    // `coroutine.close` on a coroutine that died with an error.
+    pub(crate) fn take_error(self, mc: MutationContext<'gc, '_>) -> Option<Value<'gc>> {
+        self.0.write(mc).error.take()
+    }
+
+    // Returns the call stack of a thread that is `Stopped` or `Suspended`, for `Lua::snapshot`.
+    // Returns `None` if the thread is in any other mode, or if it is suspended with a callback or
+    // continuation frame on its call stack, which cannot be saved.
+    pub(crate) fn save(self) -> Option<SavedThread<'gc>> {
+        let state = self.0.try_read().ok()?;
+        match get_mode(&state) {
+            ThreadMode::Stopped | ThreadMode::Suspended => {}
+            _ => return None,
+        }
+
+        let mut frames = Vec::new();
+        for frame in &state.frames {
+            frames.push(match *frame {
+                Frame::Lua {
+                    bottom,
+                    base,
+                    is_variable,
+                    pc,
+                    stack_size,
+                    expected_returns,
+                    is_tail_call,
+                    ..
+                } => SavedFrame::Lua {
+                    bottom,
+                    base,
+                    is_variable,
+                    pc,
+                    stack_size,
+                    expected_returns,
+                    is_tail_call,
+                },
+                Frame::StartCoroutine(function) => SavedFrame::StartCoroutine(function),
+                Frame::ResumeCoroutine => SavedFrame::ResumeCoroutine,
+                Frame::Continuation { .. } | Frame::Callback(_) | Frame::HookedCallback { .. } => {
+                    return None
+                }
+            });
+        }
+
+        Some(SavedThread {
+            allow_yield: state.allow_yield,
+            values: state.values.clone(),
+            frames,
+            open_upvalues: state.open_upvalues.clone(),
+            to_be_closed: state.to_be_closed.clone(),
+        })
+    }
+
+    // Replaces the call stack of this thread with one returned by `Thread::save`, for
+    // `Lua::restore`.  Any open upvalues must already refer to this thread.
+    pub(crate) fn restore(self, mc: MutationContext<'gc, '_>, saved: SavedThread<'gc>) {
+        self.reset(mc);
+        let mut state = self.0.write(mc);
+        state.allow_yield = saved.allow_yield;
+        state.values = saved.values;
+        state.frames = saved
+            .frames
+            .into_iter()
+            .map(|frame| match frame {
+                SavedFrame::Lua {
+                    bottom,
+                    base,
+                    is_variable,
+                    pc,
+                    stack_size,
+                    expected_returns,
+                    is_tail_call,
+                } => Frame::Lua {
+                    bottom,
+                    base,
+                    is_variable,
+                    pc,
+                    stack_size,
+                    expected_returns,
+                    is_tail_call,
+                    // Like functions already running when a hook is set, restored functions only
+                    // see a line event once their line changes
+                    hook_pc: pc.checked_sub(1),
+                    hook_events: None,
+                },
+                SavedFrame::StartCoroutine(function) => Frame::StartCoroutine(function),
+                SavedFrame::ResumeCoroutine => Frame::ResumeCoroutine,
+            })
+            .collect();
+        state.open_upvalues = saved.open_upvalues;
+        state.to_be_closed = saved.to_be_closed;
+    }
+
+    /// Returns the functions on this thread's call stack, starting with the innermost.  Each
+    /// function called from Lua is named after the expression it was called through, where one can
+    /// be found.
+    ///
+    /// Panics if called by a callback running on this thread before the callback has returned,
+    /// while the thread is still borrowed.
+    pub fn call_stack(self) -> Vec<StackFrame<'gc>> {
+        call_stack(&self.0.read())
+    }
+
+    /// Formats the call stack like PUC-Rio Lua's `debug.traceback`, skipping the innermost `level`
+    /// functions.  Only the innermost and outermost functions of a very deep call stack are shown.
+    ///
+    /// Panics in the same cases as `Thread::call_stack`.
+    pub fn traceback(self, level: usize) -> StdString {
+        let frames = self
+            .call_stack()
+            .iter()
+            .skip(level)
+            .map(StackFrame::to_static)
+            .collect::<Vec<_>>();
+        format_traceback(&frames)
+    }
+
+    /// Returns the name and value of a local variable of the function at the given level of the
+    /// call stack, numbered like `Thread::call_stack`.  Local variables are numbered from 1 in the
+    /// order they were declared, counting only those in scope at the current instruction, and the
+    /// extra arguments of a variadic function are numbered from -1.
+    ///
+    /// Returns `None` if there is no such variable, which is always the case for callbacks and for
+    /// functions whose local variables have been stripped.
+    ///
+    /// Panics in the same cases as `Thread::call_stack`.
+    pub fn local(self, level: usize, n: i64) -> Option<(String<'gc>, Value<'gc>)> {
+        let state = self.0.read();
+        let (name, index) = find_local(&state, level, n)?;
+        Some((name, *state.values.get(index)?))
+    }
+
+    /// Sets the value of a local variable found as in `Thread::local`, returning its name, or
+    /// `None` if there is no such variable.
+    ///
+    /// Panics in the same cases as `Thread::call_stack`.
+    pub fn set_local(
+        self,
+        mc: MutationContext<'gc, '_>,
+        level: usize,
+        n: i64,
+        value: Value<'gc>,
+    ) -> Option<String<'gc>> {
+        let mut state = self.0.write(mc);
+        let (name, index) = find_local(&state, level, n)?;
+        *state.values.get_mut(index)? = value;
+        Some(name)
+    }
+
+    // Reads the stack slot of an open upvalue
+    pub(crate) fn stack_value(self, index: usize) -> Value<'gc> {
+        self.0.read().values[index]
+    }
+
+    // Writes the stack slot of an open upvalue
+    pub(crate) fn set_stack_value(
+        self,
+        mc: MutationContext<'gc, '_>,
+        index: usize,
+        value: Value<'gc>,
+    ) {
+        self.0.write(mc).values[index] = value;
+    }
+
+    /// Returns the debug hook set on this thread, if any.
+    pub fn hook(self) -> Option<Hook<'gc>> {
+        self.0.read().hook
+    }
+
+    /// Sets or removes the debug hook of this thread.  A hook that has no events set is removed.
+    ///
+    /// Panics if called by a callback running on this thread before the callback has returned,
+    /// while the thread is still borrowed.
+    pub fn set_hook(self, mc: MutationContext<'gc, '_>, hook: Option<Hook<'gc>>) {
+        let mut state = self.0.write(mc);
+        let hook = hook.filter(|hook| hook.call || hook.returns || hook.line || hook.count != 0);
+        state.hook = hook;
+        state.hook_count = hook.map(|hook| hook.count).unwrap_or(0);
+        if hook.is_none() {
+            state.hook_depth = None;
+        }
+        for frame in &mut state.frames {
+            if let Frame::Lua {
+                pc,
+                hook_pc,
+                hook_events,
+                ..
+            } = frame
+            {
+                if hook.is_none() {
+                    *hook_events = None;
+                }
+                // Functions that are already running only see a line event once their line changes
+                if hook_events.is_none() {
+                    *hook_pc = pc.checked_sub(1);
+                }
+            }
+        }
+    }
+
+    /// Returns the host hook set on this thread, if any.
+    pub fn host_hook(self) -> Option<Rc<dyn HostHook>> {
+        self.0.read().host_hook.as_ref().map(|hook| hook.0.clone())
+    }
+
+    /// Sets or removes the host hook of this thread, which is independent of any debug hook.
+    ///
+    /// Panics if called by a callback running on this thread before the callback has returned,
+    /// while the thread is still borrowed.
+    pub fn set_host_hook(self, mc: MutationContext<'gc, '_>, hook: Option<Rc<dyn HostHook>>) {
+        let mut state = self.0.write(mc);
+        state.host_hook_count = hook.as_ref().map(|hook| hook.count_interval()).unwrap_or(0);
+        state.host_hook = hook.map(StaticCollect);
+    }
+
+    /// Returns the interrupt set on this thread, if any.
+    pub fn interrupt(self) -> Option<Interrupt> {
+        self.0
+            .read()
+            .interrupt
+            .as_ref()
+            .map(|interrupt| interrupt.0.clone())
+    }
+
+    /// Sets or removes the interrupt that stops the Lua code running in this thread once triggered.
+    /// Coroutines created by code running in this thread afterwards share the same interrupt.
+    pub fn set_interrupt(self, mc: MutationContext<'gc, '_>, interrupt: Option<Interrupt>) {
+        self.0.write(mc).interrupt = interrupt.map(StaticCollect);
+    }
+
     /// If the thread is in `Running` mode, either run the Lua VM for a while or step any callback
     /// that we are waiting on.
     pub fn step(self, mc: MutationContext<'gc, '_>) -> Result<(), BadThreadMode> {
@@ -228,22 +791,75 @@ impl<'gc> Thread<'gc> {
                 }
             }
             Some(Frame::Lua { .. }) => {
-                const VM_GRANULARITY: u32 = 256;
+                const VM_GRANULARITY: u32 = 4096;
                 let mut instructions = VM_GRANULARITY;
 
                 loop {
+                    let fuel = state.fuel.0.get();
+                    if fuel == Some(0) {
+                        break;
+                    }
+
+                    let interrupted = state
+                        .interrupt
+                        .as_ref()
+                        .and_then(|interrupt| interrupt.0.take());
+                    if let Some(interrupted) = interrupted {
+                        let err = locate_error(&state, interrupted.into());
+                        unwind(self, &mut state, mc, err, false);
+                        break;
+                    }
+
+                    if let Some((finalizer, table)) = take_finalizer(&mut state, mc) {
+                        call_finalizer(self, &mut state, mc, finalizer, table);
+                        break;
+                    }
+
+                    // While there is a hook to call, instructions are run one at a time
+                    let hooked = hook_enabled(&mut state);
+                    if hooked && call_hook(self, &mut state, mc) {
+                        break;
+                    }
+
+                    let limit = match fuel {
+                        _ if hooked => 1,
+                        Some(fuel) if fuel < instructions as u64 => fuel as u32,
+                        _ => instructions,
+                    };
+                    let limit = match state.host_hook_count {
+                        0 => limit,
+                        count => limit.min(count),
+                    };
                     let lua_frame = LuaFrame {
                         state: &mut state,
                         thread: self,
                     };
-                    match run_vm(mc, lua_frame, instructions) {
+                    match run_vm(mc, lua_frame, limit) {
                         Err(err) => {
-                            unwind(self, &mut state, mc, err);
+                            let err = locate_error(&state, err);
+                            unwind(self, &mut state, mc, err, false);
                             break;
                         }
                         Ok(i) => {
+                            if let Some(fuel) = fuel {
+                                state.fuel.0.set(Some(fuel - (limit - i) as u64));
+                            }
+                            if state.host_hook_count != 0 {
+                                state.host_hook_count -= limit - i;
+                                if state.host_hook_count == 0 {
+                                    if let Some(hook) = state.host_hook.as_ref() {
+                                        let hook = hook.0.clone();
+                                        state.host_hook_count = hook.count_interval().max(1);
+                                        hook.count(&HookContext { state: &state });
+                                    }
+                                }
+                            }
                             if let Some(Frame::Lua { .. }) = state.frames.last() {
-                                instructions = i;
+                                instructions = if hooked {
+                                    instructions - 1
+                                } else {
+                                    instructions - (limit - i)
+                                };
                                 if instructions == 0 {
                                     break;
                                 }
@@ -273,6 +889,18 @@ impl<'gc, 'a> LuaFrame<'gc, 'a> {
         }
     }
 
+    // Returns the metatable shared by all string values
+    pub(crate) fn string_metatable(&self) -> Table<'gc> {
+        self.state.string_metatable
+    }
+
+    // Returns the set that strings made by the VM are interned in, if the thread has one
+    pub(crate) fn interned_strings(&self) -> Option<InternedStringSet<'gc>> {
+        self.state
+            .finalizers
+            .map(|finalizers| finalizers.interned_strings())
+    }
+
     // returns a view of the Lua frame's registers
     pub(crate) fn registers<'b>(&'b mut self) -> LuaRegisters<'gc, 'b> {
         match self.state.frames.last_mut() {
@@ -284,6 +912,7 @@ impl<'gc, 'a> LuaFrame<'gc, 'a> {
                     upper_stack,
                     base: *base,
                     open_upvalues: &mut self.state.open_upvalues,
+                    to_be_closed: &mut self.state.to_be_closed,
                     thread: self.thread,
                 }
             }
@@ -332,6 +961,65 @@ impl<'gc, 'a> LuaFrame<'gc, 'a> {
         Ok(())
     }
 
+    // Set the values starting at the register after `base` to the table at the given register, at
+    // consecutive integer keys starting from the integer in the `base` register.
+    pub(crate) fn set_list(
+        self,
+        mc: MutationContext<'gc, '_>,
+        table: RegisterIndex,
+        base: RegisterIndex,
+        count: VarCount,
+    ) -> Result<(), Error<'gc>> {
+        match self.state.frames.last_mut() {
+            Some(Frame::Lua {
+                base: frame_base,
+                is_variable,
+                stack_size,
+                ..
+            }) => {
+                if *is_variable != count.is_variable() {
+                    return Err(ThreadError::ExpectedVariable(*is_variable).into());
+                }
+
+                let table = match self.state.values[*frame_base + table.0 as usize] {
+                    Value::Table(table) => table,
+                    _ => panic!("set list target is not a table"),
+                };
+                let start = match self.state.values[*frame_base + base.0 as usize] {
+                    Value::Integer(start) => start,
+                    _ => panic!("set list start index is not an integer"),
+                };
+
+                let values_start = *frame_base + base.0 as usize + 1;
+                let count = count
+                    .to_constant()
+                    .map(|c| c as usize)
+                    .unwrap_or(self.state.values.len() - values_start);
+                // Constructors fill the sequence in batches from 1, so the array part can be sized
+                // for each batch up front
+                if start >= 1 && start as u64 <= table.array_len() as u64 + 1 {
+                    table.reserve_array(mc, start as usize - 1 + count);
+                }
+                for i in 0..count {
+                    table.set(
+                        mc,
+                        Value::Integer(start + i as i64),
+                        self.state.values[values_start + i],
+                    )?;
+                }
+
+                if *is_variable {
+                    self.state
+                        .values
+                        .resize(*frame_base + *stack_size, Value::Nil);
+                    *is_variable = false;
+                }
+                Ok(())
+            }
+            _ => panic!("top frame is not lua frame"),
+        }
+    }
+
     // Call the function at the given register with the given arguments.  On return, results will be
     // placed starting at the function register.
     pub(crate) fn call_function(
@@ -352,15 +1040,15 @@ impl<'gc, 'a> LuaFrame<'gc, 'a> {
                     return Err(ThreadError::ExpectedVariable(*is_variable));
                 }
 
-                *expected_returns = Some(returns);
+                *expected_returns = Some(LuaReturn::Normal(returns));
                 let function_index = *base + func.0 as usize;
-                let arg_count = args
+                let mut arg_count = args
                     .to_constant()
                     .map(|c| c as usize)
                     .unwrap_or(self.state.values.len() - function_index - 1);
 
-                match self.state.values[function_index] {
-                    Value::Function(Function::Closure(closure)) => {
+                match meta_call(&mut self.state.values, function_index, &mut arg_count)? {
+                    Function::Closure(closure) => {
                         let fixed_params = closure.0.proto.fixed_params as usize;
                         let stack_size = closure.0.proto.stack_size as usize;
 
@@ -381,22 +1069,22 @@ impl<'gc, 'a> LuaFrame<'gc, 'a> {
                             pc: 0,
                             stack_size,
                             expected_returns: None,
+                            is_tail_call: false,
+                            hook_pc: None,
+                            hook_events: None,
                         });
+                        host_hook_call(self.state);
                         Ok(())
                     }
-                    Value::Function(Function::Callback(callback)) => {
-                        let ret = callback.call(
-                            self.state.values[function_index + 1..function_index + 1 + arg_count]
-                                .to_vec(),
+                    Function::Callback(callback) => {
+                        let args = take_buffer(
+                            &mut self.state.buffers,
+                            &self.state.values[function_index + 1..function_index + 1 + arg_count],
                         );
                         self.state.values.resize(function_index, Value::Nil);
-                        callback_return(self.thread, &mut self.state, mc, ret);
+                        call_callback(self.thread, self.state, mc, callback, args);
                         Ok(())
                     }
-                    val => Err(ThreadError::BadCall(TypeError {
-                        expected: "function",
-                        found: val.type_name(),
-                    })),
                 }
             }
             _ => panic!("top frame is not lua frame"),
@@ -424,8 +1112,8 @@ impl<'gc, 'a> LuaFrame<'gc, 'a> {
                     return Err(ThreadError::ExpectedVariable(false));
                 }
 
-                let arg_count = arg_count as usize;
-                *expected_returns = Some(returns);
+                let mut arg_count = arg_count as usize;
+                *expected_returns = Some(LuaReturn::Normal(returns));
                 let given_function_index = *base + func.0 as usize;
                 let function_index = given_function_index + 1 + arg_count;
                 self.state
@@ -436,8 +1124,8 @@ impl<'gc, 'a> LuaFrame<'gc, 'a> {
                         self.state.values[given_function_index + i];
                 }
 
-                match self.state.values[function_index] {
-                    Value::Function(Function::Closure(closure)) => {
+                match meta_call(&mut self.state.values, function_index, &mut arg_count)? {
+                    Function::Closure(closure) => {
                         let fixed_params = closure.0.proto.fixed_params as usize;
                         let stack_size = closure.0.proto.stack_size as usize;
 
@@ -457,176 +1145,246 @@ impl<'gc, 'a> LuaFrame<'gc, 'a> {
                             pc: 0,
                             stack_size,
                             expected_returns: None,
+                            is_tail_call: false,
+                            hook_pc: None,
+                            hook_events: None,
                         });
+                        host_hook_call(self.state);
                         Ok(())
                     }
-                    Value::Function(Function::Callback(callback)) => {
-                        let ret = callback.call(
-                            self.state.values[function_index + 1..function_index + 1 + arg_count]
-                                .to_vec(),
+                    Function::Callback(callback) => {
+                        let args = take_buffer(
+                            &mut self.state.buffers,
+                            &self.state.values[function_index + 1..function_index + 1 + arg_count],
                         );
                         self.state.values.resize(function_index, Value::Nil);
-                        callback_return(self.thread, &mut self.state, mc, ret);
+                        call_callback(self.thread, self.state, mc, callback, args);
                         Ok(())
                     }
-                    val => Err(ThreadError::BadCall(TypeError {
-                        expected: "function",
-                        found: val.type_name(),
-                    })),
                 }
             }
             _ => panic!("top frame is not lua frame"),
         }
     }
 
-    // Tail-call the function at the given register with the given arguments.  Pops the current Lua
-    // frame, pushing a new frame for the given function.
-    pub(crate) fn tail_call_function(
+    // Calls the given function with the given arguments as part of a metamethod, placing the
+    // function and its arguments above the current frame's registers.  When the function returns,
+    // its first result is handled as specified by `meta_ret`.
+    pub(crate) fn call_meta_function(
         mut self,
         mc: MutationContext<'gc, '_>,
-        func: RegisterIndex,
-        args: VarCount,
+        func: Function<'gc>,
+        args: &[Value<'gc>],
+        meta_ret: MetaReturn,
     ) -> Result<(), ThreadError> {
-        match self.state.frames.pop() {
-            Some(Frame::Lua {
-                bottom,
-                base,
-                is_variable,
-                ..
-            }) => {
-                if is_variable != args.is_variable() {
-                    return Err(ThreadError::ExpectedVariable(is_variable));
+        match self.state.frames.last() {
+            Some(Frame::Lua { is_variable, .. }) => {
+                if *is_variable {
+                    return Err(ThreadError::ExpectedVariable(false));
                 }
+            }
+            _ => panic!("top frame is not lua frame"),
+        }
+        self.push_meta_call(mc, func, args, meta_ret);
+        Ok(())
+    }
 
-                close_upvalues(self.thread, self.state, mc, bottom);
-
-                let function_index = base + func.0 as usize;
-                let arg_count = args
-                    .to_constant()
-                    .map(|c| c as usize)
-                    .unwrap_or(self.state.values.len() - function_index - 1);
+    // Calls the `__close` metamethod of the most recently declared to-be-closed variable in this
+    // frame.  The calling instruction should be run again once the metamethod returns, to close any
+    // further variables.
+    pub(crate) fn close_variable(mut self, mc: MutationContext<'gc, '_>) -> Result<(), Error<'gc>> {
+        let index = self
+            .state
+            .to_be_closed
+            .pop()
+            .expect("no to-be-closed variable");
+        // The instruction is run again afterwards, without calling the hook for it again
+        if self.state.hook.is_some() {
+            if let Some(Frame::Lua { hook_events, .. }) = self.state.frames.last_mut() {
+                *hook_events = Some(HookEvents::default());
+            }
+        }
 
-                match self.state.values[function_index] {
-                    Value::Function(Function::Closure(closure)) => {
-                        self.state.values[bottom] = self.state.values[function_index];
-                        for i in 0..arg_count {
-                            self.state.values[bottom + 1 + i] =
-                                self.state.values[function_index + 1 + i];
-                        }
+        let value = self.state.values[index];
+        if let Some(close) = meta_ops::close(value)? {
+            // The frame may be variable here if it is returning a variable number of results, but
+            // the metamethod call is placed above them and they are left untouched.
+            self.push_meta_call(mc, close, &[value, Value::Nil], MetaReturn::None);
+        }
+        Ok(())
+    }
 
+    // Pushes a metamethod call above the top of the stack.  When it returns, the stack is truncated
+    // back to its current size.
+    fn push_meta_call(
+        &mut self,
+        mc: MutationContext<'gc, '_>,
+        func: Function<'gc>,
+        args: &[Value<'gc>],
+        meta_ret: MetaReturn,
+    ) {
+        match self.state.frames.last_mut() {
+            Some(Frame::Lua {
+                expected_returns, ..
+            }) => {
+                *expected_returns = Some(LuaReturn::Meta(meta_ret));
+                let function_index = self.state.values.len();
+                let arg_count = args.len();
+                self.state.values.push(Value::Function(func));
+                self.state.values.extend_from_slice(args);
+
+                match func {
+                    Function::Closure(closure) => {
                         let fixed_params = closure.0.proto.fixed_params as usize;
                         let stack_size = closure.0.proto.stack_size as usize;
 
                         let base = if arg_count > fixed_params {
-                            self.state.values.truncate(bottom + 1 + arg_count);
-                            self.state.values[bottom + 1..].rotate_left(fixed_params);
-                            bottom + 1 + (arg_count - fixed_params)
+                            self.state.values[function_index + 1..].rotate_left(fixed_params);
+                            function_index + 1 + (arg_count - fixed_params)
                         } else {
-                            bottom + 1
+                            function_index + 1
                         };
 
                         self.state.values.resize(base + stack_size, Value::Nil);
 
                         self.state.frames.push(Frame::Lua {
-                            bottom,
+                            bottom: function_index,
                             base,
                             is_variable: false,
                             pc: 0,
                             stack_size,
                             expected_returns: None,
+                            is_tail_call: false,
+                            hook_pc: None,
+                            hook_events: None,
                         });
-                        Ok(())
+                        host_hook_call(self.state);
                     }
-                    Value::Function(Function::Callback(callback)) => {
-                        let ret = callback.call(
-                            self.state.values[function_index + 1..function_index + 1 + arg_count]
-                                .to_vec(),
-                        );
-                        self.state.values.truncate(bottom);
-                        callback_return(self.thread, &mut self.state, mc, ret);
-                        Ok(())
+                    Function::Callback(callback) => {
+                        let args = take_buffer(&mut self.state.buffers, args);
+                        self.state.values.truncate(function_index);
+                        call_callback(self.thread, self.state, mc, callback, args);
                     }
-                    val => Err(ThreadError::BadCall(TypeError {
-                        expected: "function",
-                        found: val.type_name(),
-                    })),
                 }
             }
             _ => panic!("top frame is not lua frame"),
         }
     }
 
-    // Return to the upper frame with results starting at the given register index.
-    pub(crate) fn return_upper(
+    // Tail-call the function at the given register with the given arguments.  If the function is a
+    // Lua function, pops the current Lua frame, pushing a new frame for the given function.  A
+    // callback is called from the current frame like PUC-Rio Lua does for C functions, and the
+    // frame then returns whatever the callback returns.
+    pub(crate) fn tail_call_function(
         mut self,
         mc: MutationContext<'gc, '_>,
-        start: RegisterIndex,
-        count: VarCount,
+        func: RegisterIndex,
+        args: VarCount,
     ) -> Result<(), ThreadError> {
-        match self.state.frames.pop() {
+        let (bottom, base) = match self.state.frames.last() {
             Some(Frame::Lua {
                 bottom,
                 base,
                 is_variable,
                 ..
             }) => {
-                if is_variable != count.is_variable() {
-                    return Err(ThreadError::ExpectedVariable(is_variable));
+                if *is_variable != args.is_variable() {
+                    return Err(ThreadError::ExpectedVariable(*is_variable));
                 }
-                close_upvalues(self.thread, self.state, mc, bottom);
+                (*bottom, *base)
+            }
+            _ => panic!("top frame is not lua frame"),
+        };
 
-                let start = base + start.0 as usize;
-                let count = count
-                    .to_constant()
-                    .map(|c| c as usize)
-                    .unwrap_or(self.state.values.len() - start);
+        close_upvalues(self.thread, self.state, mc, bottom);
 
+        let function_index = base + func.0 as usize;
+        let mut arg_count = args
+            .to_constant()
+            .map(|c| c as usize)
+            .unwrap_or(self.state.values.len() - function_index - 1);
+
+        match meta_call(&mut self.state.values, function_index, &mut arg_count)? {
+            Function::Closure(closure) => {
+                host_hook_return(self.state);
+                self.state.frames.pop();
+
+                self.state.values[bottom] = self.state.values[function_index];
+                for i in 0..arg_count {
+                    self.state.values[bottom + 1 + i] = self.state.values[function_index + 1 + i];
+                }
+
+                let fixed_params = closure.0.proto.fixed_params as usize;
+                let stack_size = closure.0.proto.stack_size as usize;
+
+                let base = if arg_count > fixed_params {
+                    self.state.values.truncate(bottom + 1 + arg_count);
+                    self.state.values[bottom + 1..].rotate_left(fixed_params);
+                    bottom + 1 + (arg_count - fixed_params)
+                } else {
+                    bottom + 1
+                };
+
+                self.state.values.resize(base + stack_size, Value::Nil);
+
+                self.state.frames.push(Frame::Lua {
+                    bottom,
+                    base,
+                    is_variable: false,
+                    pc: 0,
+                    stack_size,
+                    expected_returns: None,
+                    is_tail_call: true,
+                    hook_pc: None,
+                    hook_events: None,
+                });
+                host_hook_call(self.state);
+                Ok(())
+            }
+            Function::Callback(callback) => {
                 match self.state.frames.last_mut() {
-                    Some(Frame::Continuation { continuation, .. }) => {
-                        let continuation = continuation.take().expect("continuation missing");
-                        let ret_vals = self.state.values[start..start + count].to_vec();
-                        self.state.values.truncate(bottom);
-                        let ret = continuation.call(Ok(ret_vals));
-                        self.state.frames.pop();
-                        callback_return(self.thread, &mut self.state, mc, ret);
-                    }
                     Some(Frame::Lua {
-                        expected_returns,
-                        is_variable,
-                        base,
-                        stack_size,
-                        ..
-                    }) => {
-                        let expected_returns =
-                            expected_returns.expect("no expected returns for upper lua frame");
-                        let returning = expected_returns
-                            .to_constant()
-                            .map(|c| c as usize)
-                            .unwrap_or(count);
-
-                        for i in 0..returning.min(count) {
-                            self.state.values[bottom + i] = self.state.values[start + i]
-                        }
-
-                        for i in count..returning {
-                            self.state.values[bottom + i] = Value::Nil;
-                        }
+                        expected_returns, ..
+                    }) => *expected_returns = Some(LuaReturn::TailCall),
+                    _ => unreachable!(),
+                }
+                let args = take_buffer(
+                    &mut self.state.buffers,
+                    &self.state.values[function_index + 1..function_index + 1 + arg_count],
+                );
+                self.state.values.resize(function_index, Value::Nil);
+                call_callback(self.thread, self.state, mc, callback, args);
+                Ok(())
+            }
+        }
+    }
 
-                        if expected_returns.is_variable() {
-                            self.state.values.truncate(bottom + returning);
-                            *is_variable = true;
-                        } else {
-                            self.state.values.resize(*base + *stack_size, Value::Nil);
-                            *is_variable = false;
-                        }
-                    }
-                    None => {
-                        let ret_vals = self.state.values[start..start + count].to_vec();
-                        self.state.result = Some(Ok(ret_vals));
-                        self.state.values.clear();
-                    }
-                    _ => panic!("lua frame must be above a continuation or lua frame"),
+    // Return to the upper frame with results starting at the given register index.
+    pub(crate) fn return_upper(
+        mut self,
+        mc: MutationContext<'gc, '_>,
+        start: RegisterIndex,
+        count: VarCount,
+    ) -> Result<(), ThreadError> {
+        host_hook_return(self.state);
+        match self.state.frames.pop() {
+            Some(Frame::Lua {
+                bottom,
+                base,
+                is_variable,
+                ..
+            }) => {
+                if is_variable != count.is_variable() {
+                    return Err(ThreadError::ExpectedVariable(is_variable));
                 }
+                close_upvalues(self.thread, self.state, mc, bottom);
+
+                let start = base + start.0 as usize;
+                let count = count
+                    .to_constant()
+                    .map(|c| c as usize)
+                    .unwrap_or(self.state.values.len() - start);
+                return_from_lua(self.thread, self.state, mc, bottom, start, count);
             }
             _ => panic!("top frame is not lua frame"),
         }
@@ -641,14 +1399,19 @@ impl<'gc, 'a> LuaRegisters<'gc, 'a> {
         reg: RegisterIndex,
     ) -> UpValue<'gc> {
         let ind = self.base + reg.0 as usize;
-        match self.open_upvalues.entry(ind) {
-            BTreeEntry::Occupied(occupied) => *occupied.get(),
-            BTreeEntry::Vacant(vacant) => {
-                let uv = UpValue(GcCell::allocate(mc, UpValueState::Open(self.thread, ind)));
-                vacant.insert(uv);
-                uv
-            }
+        // Closures usually capture the most recently declared locals, so search from the top
+        let pos = self
+            .open_upvalues
+            .iter()
+            .rposition(|&(i, _)| i <= ind)
+            .map(|p| p + 1)
+            .unwrap_or(0);
+        if pos > 0 && self.open_upvalues[pos - 1].0 == ind {
+            return self.open_upvalues[pos - 1].1;
         }
+        let uv = UpValue(GcCell::allocate(mc, UpValueState::Open(self.thread, ind)));
+        self.open_upvalues.insert(pos, (ind, uv));
+        uv
     }
 
     pub fn get_upvalue(&self, upvalue: UpValue<'gc>) -> Value<'gc> {
@@ -691,11 +1454,26 @@ impl<'gc, 'a> LuaRegisters<'gc, 'a> {
         }
     }
 
+    // Marks the variable in the given register as to-be-closed, if its value needs closing
+    pub fn mark_to_be_closed(&mut self, register: RegisterIndex) -> Result<(), MetaOperatorError> {
+        if meta_ops::close(self.stack_frame[register.0 as usize])?.is_some() {
+            self.to_be_closed.push(self.base + register.0 as usize);
+        }
+        Ok(())
+    }
+
+    // Returns true if there are any to-be-closed variables at or above the given register
+    pub fn has_to_be_closed(&self, register: RegisterIndex) -> bool {
+        match self.to_be_closed.last() {
+            Some(&index) => index >= self.base + register.0 as usize,
+            None => false,
+        }
+    }
+
     pub fn close_upvalues(&mut self, mc: MutationContext<'gc, '_>, register: RegisterIndex) {
-        for (_, upval) in self
-            .open_upvalues
-            .split_off(&(self.base + register.0 as usize))
-        {
+        let bottom = self.base + register.0 as usize;
+        while let Some(&(_, upval)) = self.open_upvalues.last().filter(|&&(i, _)| i >= bottom) {
+            self.open_upvalues.pop();
             let mut upval = upval.0.write(mc);
             if let UpValueState::Open(upvalue_thread, ind) = *upval {
                 assert!(upvalue_thread == self.thread);
@@ -709,6 +1487,56 @@ impl<'gc, 'a> LuaRegisters<'gc, 'a> {
     }
 }
 
+// How the results of a call made from a Lua frame should be handled once the call returns.
+#[derive(Debug, Copy, Clone, Collect)]
+#[collect(require_static)]
+pub(crate) enum LuaReturn {
+    // Normal function call, results are placed starting at the function register.
+    Normal(VarCount),
+    // Metamethod call, only the first result is used.
+    Meta(MetaReturn),
+    // Tail call of a callback, all results are returned from the calling frame.
+    TailCall,
+}
+
+/// What to do with the first result of a metamethod call once it returns to the calling Lua frame.
+#[derive(Debug, Copy, Clone, Collect)]
+#[collect(require_static)]
+pub(crate) enum MetaReturn {
+    // Ignore the result
+    None,
+    // Place the result in the given register
+    Register(RegisterIndex),
+    // Skip the next instruction if the result converted to a boolean is equal to the given value
+    SkipIf(bool),
+    // Jump by the given offset if the result converted to a boolean is equal to the given value
+    JumpIf(bool, i8),
+}
+
+// The state of a thread at rest, saved by `Thread::save`.
+pub(crate) struct SavedThread<'gc> {
+    pub allow_yield: bool,
+    pub values: Vec<Value<'gc>>,
+    pub frames: Vec<SavedFrame<'gc>>,
+    pub open_upvalues: Vec<(usize, UpValue<'gc>)>,
+    pub to_be_closed: Vec<usize>,
+}
+
+// A frame of a thread at rest, which is never a callback or continuation frame.
+pub(crate) enum SavedFrame<'gc> {
+    Lua {
+        bottom: usize,
+        base: usize,
+        is_variable: bool,
+        pc: usize,
+        stack_size: usize,
+        expected_returns: Option<LuaReturn>,
+        is_tail_call: bool,
+    },
+    StartCoroutine(Function<'gc>),
+    ResumeCoroutine,
+}
+
 #[derive(Collect)]
 #[collect(empty_drop)]
 enum Frame<'gc> {
@@ -718,17 +1546,64 @@ enum Frame<'gc> {
         is_variable: bool,
         pc: usize,
         stack_size: usize,
-        expected_returns: Option<VarCount>,
+        expected_returns: Option<LuaReturn>,
+        // Whether the function was tail called, replacing the frame of the function that called it
+        is_tail_call: bool,
+        // The pc of the last instruction checked for hook events, used to find line events
+        hook_pc: Option<usize>,
+        // The hook events left to deliver before the instruction at `pc` runs, or `None` if it has
+        // not been checked for hook events yet
+        hook_events: Option<HookEvents>,
     },
     Continuation {
         bottom: usize,
         continuation: Option<Continuation<'gc>>,
+        handler: Option<Function<'gc>>,
+        // Whether the continuation is seen as a callback on the call stack.  The continuation that
+        // turns the result of a message handler into an error stands in for the callback that raised
+        // the error, so it is hidden when the error was raised by Lua code instead.
+        is_on_call_stack: bool,
     },
     StartCoroutine(Function<'gc>),
     ResumeCoroutine,
     Callback(
         Option<Box<dyn Sequence<'gc, Output = Result<CallbackResult<'gc>, Error<'gc>>> + 'gc>>,
     ),
+    // A callback called while there is a hook for call or return events, which is called before and
+    // after the callback runs.  Holds the arguments of the callback until it is called, and then its
+    // results until they are returned.
+    HookedCallback {
+        callback: Callback<'gc>,
+        values: Vec<Value<'gc>>,
+        stage: HookedCallbackStage,
+    },
+}
+
+impl<'gc> Frame<'gc> {
+    // Whether the frame is a function on the call stack.  A hooked callback is only seen while the
+    // hook is being called for it, since while it runs it is either finished at once or seen by the
+    // frames it pushes.
+    fn is_on_call_stack(&self) -> bool {
+        match self {
+            Frame::StartCoroutine(_) | Frame::ResumeCoroutine => false,
+            Frame::Continuation {
+                is_on_call_stack, ..
+            } => *is_on_call_stack,
+            Frame::HookedCallback { stage, .. } => *stage != HookedCallbackStage::Running,
+            _ => true,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Collect)]
+#[collect(require_static)]
+enum HookedCallbackStage {
+    // The hook is being called for the call of the callback
+    Call,
+    // The callback is running
+    Running,
+    // The hook is being called for the return of the callback
+    Return,
 }
 
 fn get_mode<'gc>(state: &ThreadState<'gc>) -> ThreadMode {
@@ -740,15 +1615,15 @@ fn get_mode<'gc>(state: &ThreadState<'gc>) -> ThreadMode {
                 assert!(
                     state.values.is_empty()
                         && state.open_upvalues.is_empty()
+                        && state.to_be_closed.is_empty()
                         && state.result.is_none(),
                 );
                 ThreadMode::Stopped
             }
             Some(frame) => match frame {
-                Frame::Callback(_) | Frame::Continuation { .. } | Frame::Lua { .. } => {
-                    ThreadMode::Running
-                }
                 Frame::StartCoroutine(_) | Frame::ResumeCoroutine => ThreadMode::Suspended,
+                _ if state.fuel.0.get() == Some(0) => ThreadMode::OutOfFuel,
+                _ => ThreadMode::Running,
             },
         }
     }
@@ -793,7 +1668,7 @@ fn ext_call_function<'gc>(
                 state.values[base + i] = args.get(i).cloned().unwrap_or(Value::Nil);
             }
             for i in 0..var_params {
-                state.values[1 + i] = args[fixed_params + i]
+                state.values[bottom + 1 + i] = args[fixed_params + i]
             }
 
             state.frames.push(Frame::Lua {
@@ -803,47 +1678,606 @@ fn ext_call_function<'gc>(
                 pc: 0,
                 stack_size,
                 expected_returns: None,
+                is_tail_call: false,
+                hook_pc: None,
+                hook_events: None,
             });
+            host_hook_call(state);
         }
         Function::Callback(callback) => {
-            let ret = callback.call(args.to_vec());
+            let args = take_buffer(&mut state.buffers, args);
+            call_callback(thread, state, mc, callback, args);
+        }
+    }
+}
+
+// Find the function to call for the value at the given index.  If the value is not a function and
+// must be called through its `__call` metamethod, the metamethod replaces it and the value itself is
+// inserted as the first argument.
+fn meta_call<'gc>(
+    values: &mut Vec<Value<'gc>>,
+    function_index: usize,
+    arg_count: &mut usize,
+) -> Result<Function<'gc>, ThreadError> {
+    let value = values[function_index];
+    let function = meta_ops::call(value).map_err(ThreadError::BadCall)?;
+    match value {
+        Value::Function(_) => {}
+        value => {
+            values.insert(function_index + 1, value);
+            values[function_index] = Value::Function(function);
+            *arg_count += 1;
+        }
+    }
+    Ok(function)
+}
+
+// Passes the results at the given stack range of a Lua frame that has just been removed to the
+// frame above it, where `bottom` was the bottom of the removed frame.
+fn return_from_lua<'gc>(
+    thread: Thread<'gc>,
+    state: &mut ThreadState<'gc>,
+    mc: MutationContext<'gc, '_>,
+    bottom: usize,
+    start: usize,
+    count: usize,
+) {
+    match state.frames.last_mut() {
+        Some(Frame::Continuation { continuation, .. }) => {
+            let continuation = continuation.take().expect("continuation missing");
+            let ret_vals = take_buffer(&mut state.buffers, &state.values[start..start + count]);
+            state.values.truncate(bottom);
+            let ret = continuation.call(Ok(ret_vals));
+            state.frames.pop();
             callback_return(thread, state, mc, ret);
         }
+        Some(Frame::HookedCallback { .. }) => {
+            let ret_vals = take_buffer(&mut state.buffers, &state.values[start..start + count]);
+            state.values.truncate(bottom);
+            hooked_callback_return(thread, state, mc, ret_vals);
+        }
+        Some(Frame::Lua {
+            expected_returns,
+            is_variable,
+            base,
+            pc,
+            stack_size,
+            ..
+        }) => {
+            match expected_returns.expect("no expected returns for upper lua frame") {
+                LuaReturn::Normal(expected_returns) => {
+                    let returning = expected_returns
+                        .to_constant()
+                        .map(|c| c as usize)
+                        .unwrap_or(count);
+
+                    // The results of a callback may have been placed below the top of the frame
+                    if state.values.len() < bottom + returning {
+                        state.values.resize(bottom + returning, Value::Nil);
+                    }
+                    for i in 0..returning.min(count) {
+                        state.values[bottom + i] = state.values[start + i]
+                    }
+
+                    for i in count..returning {
+                        state.values[bottom + i] = Value::Nil;
+                    }
+
+                    if expected_returns.is_variable() {
+                        state.values.truncate(bottom + returning);
+                        *is_variable = true;
+                    } else {
+                        state.values.resize(*base + *stack_size, Value::Nil);
+                        *is_variable = false;
+                    }
+                }
+                LuaReturn::Meta(meta_ret) => {
+                    let ret = if count > 0 {
+                        state.values[start]
+                    } else {
+                        Value::Nil
+                    };
+                    meta_return(&mut state.values, *base, pc, meta_ret, ret);
+                    state.values.truncate(bottom);
+                }
+                LuaReturn::TailCall => panic!("lua frame returned to a tail call of a callback"),
+            }
+        }
+        None => {
+            let ret_vals = state.values[start..start + count].to_vec();
+            state.result = Some(Ok(ret_vals));
+            state.values.clear();
+        }
+        _ => panic!("lua frame must be above a continuation or lua frame"),
     }
 }
 
 // Return to the top Lua frame from an external call
-fn return_to_lua<'gc>(state: &mut ThreadState<'gc>, rets: &[Value<'gc>]) {
+fn return_to_lua<'gc>(
+    thread: Thread<'gc>,
+    state: &mut ThreadState<'gc>,
+    mc: MutationContext<'gc, '_>,
+    rets: &[Value<'gc>],
+) {
     match state.frames.last_mut() {
         Some(Frame::Lua {
             expected_returns,
             is_variable,
             base,
+            pc,
             stack_size,
             ..
         }) => {
-            let ret_count = expected_returns
+            match expected_returns
                 .take()
-                .expect("no expected returns for lua frame");
-            let return_len = ret_count
-                .to_constant()
-                .map(|c| c as usize)
-                .unwrap_or(rets.len());
+                .expect("no expected returns for lua frame")
+            {
+                LuaReturn::Normal(ret_count) => {
+                    let return_len = ret_count
+                        .to_constant()
+                        .map(|c| c as usize)
+                        .unwrap_or(rets.len());
+
+                    let bottom = state.values.len();
+                    state.values.resize(bottom + return_len, Value::Nil);
+
+                    for i in 0..return_len.min(rets.len()) {
+                        state.values[bottom + i] = rets[i];
+                    }
 
-            let bottom = state.values.len();
-            state.values.resize(bottom + return_len, Value::Nil);
+                    *is_variable = ret_count.is_variable();
+                    if !ret_count.is_variable() {
+                        state.values.resize(*base + *stack_size, Value::Nil);
+                    }
+                }
+                LuaReturn::Meta(meta_ret) => {
+                    let ret = rets.get(0).cloned().unwrap_or(Value::Nil);
+                    meta_return(&mut state.values, *base, pc, meta_ret, ret);
+                }
+                LuaReturn::TailCall => {
+                    // The frame returns the results of the callback it tail called
+                    host_hook_return(state);
+                    let bottom = match state.frames.pop() {
+                        Some(Frame::Lua { bottom, .. }) => bottom,
+                        _ => unreachable!(),
+                    };
+                    let start = state.values.len();
+                    state.values.extend_from_slice(rets);
+                    return_from_lua(thread, state, mc, bottom, start, rets.len());
+                }
+            }
+        }
+        _ => panic!("no lua frame to return to"),
+    };
+}
 
-            for i in 0..return_len.min(rets.len()) {
-                state.values[bottom + i] = rets[i];
+// Handle the first result of a metamethod call made from the Lua frame with the given base and pc.
+fn meta_return<'gc>(
+    values: &mut [Value<'gc>],
+    base: usize,
+    pc: &mut usize,
+    meta_ret: MetaReturn,
+    ret: Value<'gc>,
+) {
+    match meta_ret {
+        MetaReturn::None => {}
+        MetaReturn::Register(reg) => {
+            values[base + reg.0 as usize] = ret;
+        }
+        MetaReturn::SkipIf(skip_if) => {
+            if ret.to_bool() == skip_if {
+                *pc += 1;
+            }
+        }
+        MetaReturn::JumpIf(jump_if, jump) => {
+            if ret.to_bool() == jump_if {
+                *pc = (*pc as isize + jump as isize) as usize;
             }
+        }
+    }
+}
+
+// Finds the name and stack index of a local variable, as described in `Thread::local`.
+fn find_local<'gc>(state: &ThreadState<'gc>, level: usize, n: i64) -> Option<(String<'gc>, usize)> {
+    let frame = state
+        .frames
+        .iter()
+        .rev()
+        .filter(|frame| frame.is_on_call_stack())
+        .nth(level)?;
+    let (bottom, base, pc, hooked) = match frame {
+        Frame::Lua {
+            bottom,
+            base,
+            pc,
+            hook_events,
+            ..
+        } => (*bottom, *base, *pc, hook_events.is_some()),
+        _ => return None,
+    };
+    let proto = &frame_closure(state, frame).0.proto;
 
-            *is_variable = ret_count.is_variable();
-            if !ret_count.is_variable() {
-                state.values.resize(*base + *stack_size, Value::Nil);
+    if n < 0 {
+        // The extra arguments are kept between the function and its fixed parameters
+        let index = bottom.checked_add(n.checked_neg()? as usize)?;
+        if proto.has_varargs && index < base {
+            Some((String::new_static(b"(*vararg)"), index))
+        } else {
+            None
+        }
+    } else {
+        // The current instruction, as in `frame_location`
+        let pc = if hooked { pc } else { pc.saturating_sub(1) };
+        let variable = proto.local_variable(n as usize, pc)?;
+        Some((variable.name, base + variable.register.0 as usize))
+    }
+}
+
+// Returns true if there is a hook to call, which is not the case while the hook itself is running.
+fn hook_enabled<'gc>(state: &mut ThreadState<'gc>) -> bool {
+    if state.hook.is_none() {
+        return false;
+    }
+    match state.hook_depth {
+        Some(depth) if state.frames.len() > depth => false,
+        _ => {
+            state.hook_depth = None;
+            true
+        }
+    }
+}
+
+// Calls the hook for the next event of the instruction about to run in the top Lua frame, if there
+// is one.  The events of an instruction are found the first time it is checked, and the instruction
+// only runs once the hook has been called for all of them.
+fn call_hook<'gc>(
+    thread: Thread<'gc>,
+    state: &mut ThreadState<'gc>,
+    mc: MutationContext<'gc, '_>,
+) -> bool {
+    let hook = state.hook.expect("no hook to call");
+    let proto = frame_closure(state, state.frames.last().expect("no lua frame"))
+        .0
+        .proto;
+    let event = match state.frames.last_mut() {
+        Some(Frame::Lua {
+            pc,
+            hook_pc,
+            hook_events,
+            ..
+        }) => {
+            let pc = *pc;
+            let events = match hook_events {
+                Some(events) => events,
+                None => {
+                    let mut events = HookEvents::default();
+                    events.call = hook.call && pc == 0 && hook_pc.is_none();
+                    if hook.count != 0 {
+                        state.hook_count -= 1;
+                        if state.hook_count == 0 {
+                            state.hook_count = hook.count;
+                            events.count = true;
+                        }
+                    }
+                    if hook.line {
+                        // A new line starts when entering a function, when the line changes, and
+                        // when jumping backwards such as at the start of each iteration of a loop
+                        let line = proto.line_info.get(pc).cloned();
+                        let new_line = match *hook_pc {
+                            Some(last_pc) => {
+                                pc <= last_pc || proto.line_info.get(last_pc).cloned() != line
+                            }
+                            None => true,
+                        };
+                        if new_line {
+                            events.line = line;
+                        }
+                    }
+                    events.returns = hook.returns
+                        && match proto.opcodes[pc] {
+                            OpCode::Return { .. } => true,
+                            _ => false,
+                        };
+                    *hook_pc = Some(pc);
+                    hook_events.get_or_insert(events)
+                }
+            };
+            match events.take_next() {
+                Some(event) => event,
+                None => {
+                    *hook_events = None;
+                    return false;
+                }
             }
         }
-        _ => panic!("no lua frame to return to"),
+        _ => panic!("top frame is not lua frame"),
+    };
+
+    let mut args = vec![Value::String(String::new_static(event.name().as_bytes()))];
+    if let HookEvent::Line(line) = event {
+        args.push(Value::Integer(line as i64));
+    }
+    state.hook_depth = Some(state.frames.len());
+    LuaFrame { thread, state }.push_meta_call(mc, hook.function, &args, MetaReturn::None);
+    true
+}
+
+// Returns true if a callback called now must be called through a `HookedCallback` frame, which is
+// not the case while the hook itself is being called.
+fn callback_hooked<'gc>(state: &ThreadState<'gc>) -> bool {
+    match state.hook {
+        Some(hook) if hook.call || hook.returns => match state.hook_depth {
+            Some(depth) => state.frames.len() < depth,
+            None => true,
+        },
+        _ => false,
+    }
+}
+
+// Calls a callback with the given arguments, once the stack has been cut back to where its results
+// will be placed.
+fn call_callback<'gc>(
+    thread: Thread<'gc>,
+    state: &mut ThreadState<'gc>,
+    mc: MutationContext<'gc, '_>,
+    callback: Callback<'gc>,
+    args: Vec<Value<'gc>>,
+) {
+    if callback_hooked(state) {
+        state.frames.push(Frame::HookedCallback {
+            callback,
+            values: args,
+            stage: HookedCallbackStage::Call,
+        });
+        match state.hook {
+            Some(hook) if hook.call => call_hook_function(thread, state, mc, hook, HookEvent::Call),
+            _ => hooked_callback_return(thread, state, mc, Vec::new()),
+        }
+    } else {
+        let ret = callback.call(thread, args);
+        callback_return(thread, state, mc, ret);
+    }
+}
+
+// Moves the `HookedCallback` frame on top of the stack on to its next stage, once the hook or the
+// callback has returned the given results.  Once the hook has been called for the return of the
+// callback, the frame is removed and the callback's results are returned.
+fn hooked_callback_return<'gc>(
+    thread: Thread<'gc>,
+    state: &mut ThreadState<'gc>,
+    mc: MutationContext<'gc, '_>,
+    res: Vec<Value<'gc>>,
+) {
+    let (callback, values, stage) = match state.frames.last_mut() {
+        Some(Frame::HookedCallback {
+            callback,
+            values,
+            stage,
+        }) => (*callback, values, stage),
+        _ => panic!("top frame is not a hooked callback frame"),
     };
+    match *stage {
+        HookedCallbackStage::Call => {
+            *stage = HookedCallbackStage::Running;
+            let args = std::mem::take(values);
+            state.hook_depth = None;
+            recycle_buffer(&mut state.buffers, res);
+            let ret = callback.call(thread, args);
+            callback_return(thread, state, mc, ret);
+        }
+        HookedCallbackStage::Running => {
+            *stage = HookedCallbackStage::Return;
+            *values = res;
+            // The callback may have changed the hook
+            match state.hook {
+                Some(hook) if hook.returns => {
+                    call_hook_function(thread, state, mc, hook, HookEvent::Return)
+                }
+                _ => hooked_callback_return(thread, state, mc, Vec::new()),
+            }
+        }
+        HookedCallbackStage::Return => {
+            let values = std::mem::take(values);
+            state.hook_depth = None;
+            recycle_buffer(&mut state.buffers, res);
+            state.frames.pop();
+            return_ext(thread, state, mc, Ok(CallbackResult::Return(values)));
+        }
+    }
+}
+
+// Calls the hook function for an event of the callback in the `HookedCallback` frame on top of the
+// stack, whose results are then passed to `hooked_callback_return`.
+fn call_hook_function<'gc>(
+    thread: Thread<'gc>,
+    state: &mut ThreadState<'gc>,
+    mc: MutationContext<'gc, '_>,
+    hook: Hook<'gc>,
+    event: HookEvent,
+) {
+    let args = [Value::String(String::new_static(event.name().as_bytes()))];
+    state.hook_depth = Some(state.frames.len());
+    ext_call_function(thread, state, mc, hook.function, &args);
+}
+
+// Takes the next table to finalize along with its finalizer, unless a finalizer is already running.
+fn take_finalizer<'gc>(
+    state: &mut ThreadState<'gc>,
+    mc: MutationContext<'gc, '_>,
+) -> Option<(Function<'gc>, Table<'gc>)> {
+    match state.finalizer_depth {
+        Some(depth) if state.frames.len() > depth => None,
+        _ => {
+            state.finalizer_depth = None;
+            state.finalizers?.take_pending(mc)
+        }
+    }
+}
+
+// Calls the finalizer of an unreachable table from the top Lua frame, which continues as if nothing
+// happened once the finalizer returns.  Its results are discarded, and so are any errors it raises
+// except uncatchable ones.
+fn call_finalizer<'gc>(
+    thread: Thread<'gc>,
+    state: &mut ThreadState<'gc>,
+    mc: MutationContext<'gc, '_>,
+    finalizer: Function<'gc>,
+    table: Table<'gc>,
+) {
+    match state.frames.last_mut() {
+        Some(Frame::Lua {
+            expected_returns, ..
+        }) => *expected_returns = Some(LuaReturn::Meta(MetaReturn::None)),
+        _ => panic!("top frame is not lua frame"),
+    }
+    state.finalizer_depth = Some(state.frames.len());
+    let bottom = state.values.len();
+    state.frames.push(Frame::Continuation {
+        bottom,
+        continuation: Some(Continuation::new_immediate(|res| match res {
+            Err(err) if !err.is_catchable() => Err(err),
+            _ => Ok(CallbackResult::Return(Vec::new())),
+        })),
+        handler: None,
+        is_on_call_stack: true,
+    });
+    ext_call_function(thread, state, mc, finalizer, &[Value::Table(table)]);
+}
+
+// Calls the host hook, if any, for the Lua function in the top frame that has just been called.
+fn host_hook_call<'gc>(state: &ThreadState<'gc>) {
+    if let Some(hook) = &state.host_hook {
+        hook.0.call(&HookContext { state });
+    }
+}
+
+// Calls the host hook, if any, for the Lua function in the top frame that is about to return.
+fn host_hook_return<'gc>(state: &ThreadState<'gc>) {
+    if let Some(hook) = &state.host_hook {
+        hook.0.returns(&HookContext { state });
+    }
+}
+
+// Returns the functions on the call stack, see `Thread::call_stack`.
+fn call_stack<'gc>(state: &ThreadState<'gc>) -> Vec<StackFrame<'gc>> {
+    let mut call_stack = Vec::new();
+    for (i, frame) in state.frames.iter().enumerate() {
+        if !frame.is_on_call_stack() {
+            continue;
+        }
+        let (closure, is_tail_call) = match *frame {
+            Frame::Lua { is_tail_call, .. } => (Some(frame_closure(state, frame)), is_tail_call),
+            _ => (None, false),
+        };
+        // The calling Lua frame has advanced its pc past the instruction that made the call.  A
+        // tail called function has no name, since the frame below did not call it.
+        let caller = state.frames[..i]
+            .iter()
+            .rev()
+            .find(|frame| frame.is_on_call_stack());
+        let name = match caller {
+            _ if state.hook_depth == Some(i) => Some(FunctionName::Hook),
+            _ if is_tail_call => None,
+            Some(caller @ Frame::Lua { pc, .. }) => pc
+                .checked_sub(1)
+                .and_then(|pc| called_function_name(&frame_closure(state, caller).0.proto, pc)),
+            _ => None,
+        };
+        call_stack.push(StackFrame {
+            closure,
+            location: frame_location(state, frame),
+            name,
+            is_tail_call,
+        });
+    }
+    call_stack.reverse();
+    call_stack
+}
+
+// Adds the location of the current instruction of the top Lua frame to an error raised by the VM.
+fn locate_error<'gc>(state: &ThreadState<'gc>, error: Error<'gc>) -> Error<'gc> {
+    match state.frames.last() {
+        Some(frame) => match frame_location(state, frame) {
+            Some(location) => Error::LocatedError(location, Box::new(error)),
+            None => error,
+        },
+        None => error,
+    }
+}
+
+// Turns an error raised by `error` at the given level into a `RuntimeError` whose message is
+// prefixed with the location of the function at that level, if it is a Lua function.  Level 1 is
+// the function on top of the call stack.
+fn locate_leveled_error<'gc>(
+    state: &ThreadState<'gc>,
+    mc: MutationContext<'gc, '_>,
+    error: RuntimeError<'gc>,
+    level: usize,
+) -> Error<'gc> {
+    // Continuation frames and hooked callbacks are the callbacks in the call stack, which have no
+    // location
+    let frame = level.checked_sub(1).and_then(|level| {
+        state
+            .frames
+            .iter()
+            .rev()
+            .filter(|frame| match frame {
+                Frame::Lua { .. } => true,
+                Frame::Continuation { .. } | Frame::HookedCallback { .. } => {
+                    frame.is_on_call_stack()
+                }
+                _ => false,
+            })
+            .nth(level)
+    });
+
+    match (
+        frame.and_then(|frame| frame_location(state, frame)),
+        error.0,
+    ) {
+        (Some(location @ Location { line: Some(_), .. }), Value::String(message)) => {
+            let mut prefixed = format!("{}: ", location).into_bytes();
+            prefixed.extend(message.as_bytes());
+            RuntimeError(Value::String(String::new(mc, &prefixed))).into()
+        }
+        _ => error.into(),
+    }
+}
+
+// Returns the location of the current instruction of the given frame, if it is a Lua frame.
+fn frame_location<'gc>(state: &ThreadState<'gc>, frame: &Frame<'gc>) -> Option<Location> {
+    match frame {
+        Frame::Lua {
+            pc, hook_events, ..
+        } => {
+            let proto = &frame_closure(state, frame).0.proto;
+            // The pc has already been advanced past the current instruction, unless the hook is
+            // being called before it runs
+            let pc = if hook_events.is_some() {
+                Some(*pc)
+            } else {
+                pc.checked_sub(1)
+            };
+            Some(Location {
+                chunk_name: proto
+                    .chunk_name
+                    .map(|name| StdString::from_utf8_lossy(name.as_bytes()).into_owned()),
+                line: pc.and_then(|pc| proto.line_info.get(pc)).cloned(),
+            })
+        }
+        _ => None,
+    }
+}
+
+// Returns the closure running in the given Lua frame.
+fn frame_closure<'gc>(state: &ThreadState<'gc>, frame: &Frame<'gc>) -> Closure<'gc> {
+    match frame {
+        Frame::Lua { bottom, .. } => match state.values[*bottom] {
+            Value::Function(Function::Closure(closure)) => closure,
+            _ => panic!("thread bottom is not a closure"),
+        },
+        _ => panic!("not a lua frame"),
+    }
 }
 
 // TODO: `unwind`, `return_ext`, and `callback_return` have to be merged somehow, because otherwise
@@ -854,24 +2288,195 @@ fn unwind<'gc>(
     state: &mut ThreadState<'gc>,
     mc: MutationContext<'gc, '_>,
     error: Error<'gc>,
+    raised_by_callback: bool,
 ) {
-    while let Some(mut top_frame) = state.frames.pop() {
-        if let Frame::Continuation {
-            continuation,
+    // An error that no continuation will receive escapes the thread, and is given the traceback of
+    // the call stack before any frames are removed
+    let escapes = !error.is_catchable()
+        || state.frames.iter().all(|frame| match frame {
+            Frame::Continuation { .. } => false,
+            _ => true,
+        });
+    let error = match error {
+        error @ Error::TracedError(..) => error,
+        error if escapes && !state.frames.is_empty() => {
+            let traceback = call_stack(state)
+                .iter()
+                .map(StackFrame::to_static)
+                .collect();
+            Error::TracedError(Traceback(traceback), Box::new(error))
+        }
+        error => error,
+    };
+
+    // The message handler of the continuation that will receive the error is called before any
+    // frames are removed, and is only called once.  Uncatchable errors are not handled.
+    let handler = state.frames.iter_mut().rev().find_map(|frame| match frame {
+        Frame::Continuation { handler, .. } => Some(handler.take()),
+        _ => None,
+    });
+    let handler = handler.filter(|_| error.is_catchable());
+    if let Some(Some(handler)) = handler {
+        let error_value = error_value(mc, &error);
+        let bottom = state.values.len();
+        state.frames.push(Frame::Continuation {
             bottom,
-        } = &mut top_frame
-        {
-            close_upvalues(thread, state, mc, *bottom);
-            state.values.truncate(*bottom);
-            let continuation = continuation.take().expect("missing continuation");
-            let ret = continuation.call(Err(error));
-            callback_return(thread, state, mc, ret);
-            return;
+            continuation: Some(Continuation::new_immediate(|res| {
+                Err(match res {
+                    Ok(res) => RuntimeError(res.get(0).cloned().unwrap_or(Value::Nil)).into(),
+                    Err(err) => err,
+                })
+            })),
+            handler: None,
+            is_on_call_stack: raised_by_callback,
+        });
+        ext_call_function(thread, state, mc, handler, &[error_value]);
+        return;
+    }
+
+    loop {
+        let bottom = match state.frames.last() {
+            Some(Frame::Continuation { bottom, .. }) => *bottom,
+            Some(frame) => {
+                if let Frame::Lua { .. } = frame {
+                    host_hook_return(state);
+                }
+                state.frames.pop();
+                continue;
+            }
+            None => 0,
+        };
+
+        // Any to-be-closed variables in the unwound frames must be closed before the error reaches
+        // the continuation.
+        let error = match close_on_error(thread, state, mc, bottom, error) {
+            Some(error) => error,
+            None => return,
+        };
+
+        // The error may have escaped the hook, which can then be called again
+        if let Some(depth) = state.hook_depth {
+            if state.frames.len() <= depth {
+                state.hook_depth = None;
+            }
+        }
+
+        match state.frames.pop() {
+            Some(mut top_frame) => match &mut top_frame {
+                Frame::Continuation { continuation, .. } => {
+                    close_upvalues(thread, state, mc, bottom);
+                    state.values.truncate(bottom);
+                    let continuation = continuation.take().expect("missing continuation");
+                    let ret = continuation.call(Err(error));
+                    callback_return(thread, state, mc, ret);
+                }
+                _ => unreachable!(),
+            },
+            None => {
+                close_upvalues(thread, state, mc, 0);
+                state.values.clear();
+                if error.is_catchable() {
+                    state.error = Some(error_value(mc, &error));
+                }
+                state.result = Some(Err(error));
+            }
         }
+        return;
+    }
+}
+
+// If there are any to-be-closed variables at or above the given stack index, calls the `__close`
+// metamethod of the most recently declared one with the given error, and unwinding with the same
+// error continues once it returns.  Returns the error if there are no variables left to close.
+fn close_on_error<'gc>(
+    thread: Thread<'gc>,
+    state: &mut ThreadState<'gc>,
+    mc: MutationContext<'gc, '_>,
+    bottom: usize,
+    mut error: Error<'gc>,
+) -> Option<Error<'gc>> {
+    while let Some(&index) = state.to_be_closed.last() {
+        if index < bottom {
+            break;
+        }
+        state.to_be_closed.pop();
+
+        let value = state.values[index];
+        close_upvalues(thread, state, mc, index + 1);
+        state.values.truncate(index + 1);
+
+        match meta_ops::close(value) {
+            Ok(Some(close)) => {
+                let error_value = error_value(mc, &error);
+                state.frames.push(Frame::Continuation {
+                    bottom: index + 1,
+                    continuation: Some(Continuation::new_immediate_with(error, |error, res| {
+                        // An error raised by the metamethod replaces the original error, unless the
+                        // original is uncatchable
+                        Err(match res {
+                            Err(err) if error.is_catchable() => err,
+                            _ => error,
+                        })
+                    })),
+                    handler: None,
+                    is_on_call_stack: true,
+                });
+                ext_call_function(thread, state, mc, close, &[value, error_value]);
+                return None;
+            }
+            Ok(None) => {}
+            Err(err) => error = err.into(),
+        }
+    }
+    Some(error)
+}
+
+// Calls the `__close` metamethod of the last of the given values, followed by the rest in reverse
+// order once it returns, for a thread closed with `Thread::close`.  Each metamethod is given the
+// current error, if any, and an error it raises replaces the current one.
+fn close_pending<'gc>(
+    mc: MutationContext<'gc, '_>,
+    mut pending: Vec<Value<'gc>>,
+    mut error: Option<Error<'gc>>,
+) -> Result<CallbackResult<'gc>, Error<'gc>> {
+    while let Some(value) = pending.pop() {
+        match meta_ops::close(value) {
+            Ok(Some(close)) => {
+                let error_value = match &error {
+                    Some(error) => error_value(mc, error),
+                    None => Value::Nil,
+                };
+                return Ok(CallbackResult::TailCall {
+                    function: close,
+                    args: vec![value, error_value],
+                    continuation: Continuation::new_sequence_with(
+                        (pending, error),
+                        |(pending, error), res| {
+                            Ok(sequence::from_fn_with(
+                                (pending, res.err().or(error)),
+                                |mc, (pending, error)| close_pending(mc, pending, error),
+                            ))
+                        },
+                    ),
+                });
+            }
+            Ok(None) => {}
+            Err(err) => error = Some(err.into()),
+        }
+    }
+    match error {
+        Some(error) => Err(error),
+        None => Ok(CallbackResult::Return(Vec::new())),
+    }
+}
+
+// The value of an error as seen by Lua code that handles it.
+fn error_value<'gc>(mc: MutationContext<'gc, '_>, error: &Error<'gc>) -> Value<'gc> {
+    match error {
+        Error::RuntimeError(error) => error.0,
+        Error::TracedError(_, error) => error_value(mc, error),
+        error => Value::String(String::new(mc, error.to_string().as_bytes())),
     }
-    close_upvalues(thread, state, mc, 0);
-    state.values.clear();
-    state.result = Some(Err(error));
 }
 
 fn return_ext<'gc>(
@@ -881,15 +2486,19 @@ fn return_ext<'gc>(
     res: Result<CallbackResult<'gc>, Error<'gc>>,
 ) {
     match res {
+        Err(Error::LeveledError(err, level)) => {
+            let err = locate_leveled_error(state, mc, err, level);
+            unwind(thread, state, mc, err, true);
+        }
         Err(err) => {
-            unwind(thread, state, mc, err);
+            unwind(thread, state, mc, err, true);
         }
         Ok(CallbackResult::Yield(res)) => {
             if state.allow_yield {
                 state.frames.push(Frame::ResumeCoroutine);
                 state.result = Some(Ok(res));
             } else {
-                unwind(thread, state, mc, ThreadError::BadYield.into());
+                unwind(thread, state, mc, ThreadError::BadYield.into(), true);
             }
         }
         Ok(CallbackResult::Return(res)) => match state.frames.last_mut() {
@@ -900,7 +2509,11 @@ fn return_ext<'gc>(
                 callback_return(thread, state, mc, ret);
             }
             Some(Frame::Lua { .. }) => {
-                return_to_lua(state, &res);
+                return_to_lua(thread, state, mc, &res);
+                recycle_buffer(&mut state.buffers, res);
+            }
+            Some(Frame::HookedCallback { .. }) => {
+                hooked_callback_return(thread, state, mc, res);
             }
             None => {
                 state.result = Some(Ok(res));
@@ -916,9 +2529,59 @@ fn return_ext<'gc>(
             state.frames.push(Frame::Continuation {
                 continuation: Some(continuation),
                 bottom,
+                handler: None,
+                is_on_call_stack: true,
             });
             ext_call_function(thread, state, mc, function, &args);
+            recycle_buffer(&mut state.buffers, args);
         }
+        Ok(CallbackResult::TailCallWithHandler {
+            function,
+            args,
+            handler,
+            continuation,
+        }) => {
+            let bottom = state.values.len();
+            state.frames.push(Frame::Continuation {
+                continuation: Some(continuation),
+                bottom,
+                handler: Some(handler),
+                is_on_call_stack: true,
+            });
+            ext_call_function(thread, state, mc, function, &args);
+            recycle_buffer(&mut state.buffers, args);
+        }
+    }
+}
+
+// The most spare buffers a thread keeps, and the range of buffer sizes it keeps.  Buffers are
+// allocated with room for at least `MIN_BUFFER_CAPACITY` values, so that any spare buffer fits most
+// calls, and smaller buffers (such as the results that callbacks build themselves) are not kept
+// in their place.  Large buffers are not kept so that a single call with many arguments doesn't hold
+// on to memory for the life of the thread.
+const MAX_SPARE_BUFFERS: usize = 8;
+const MIN_BUFFER_CAPACITY: usize = 8;
+const MAX_BUFFER_CAPACITY: usize = 64;
+
+// Returns a buffer holding a copy of the given values, reusing a spare buffer if one is large
+// enough.  Growing a smaller spare buffer would cost more than allocating a new one.
+fn take_buffer<'gc>(buffers: &mut Vec<Vec<Value<'gc>>>, values: &[Value<'gc>]) -> Vec<Value<'gc>> {
+    let mut buffer = match buffers.last() {
+        Some(buffer) if buffer.capacity() >= values.len() => buffers.pop().unwrap(),
+        _ => Vec::with_capacity(values.len().max(MIN_BUFFER_CAPACITY)),
+    };
+    buffer.extend_from_slice(values);
+    buffer
+}
+
+// Keeps a buffer whose values are no longer needed to be reused by `take_buffer`.
+fn recycle_buffer<'gc>(buffers: &mut Vec<Vec<Value<'gc>>>, mut buffer: Vec<Value<'gc>>) {
+    if buffers.len() < MAX_SPARE_BUFFERS
+        && buffer.capacity() >= MIN_BUFFER_CAPACITY
+        && buffer.capacity() <= MAX_BUFFER_CAPACITY
+    {
+        buffer.clear();
+        buffers.push(buffer);
     }
 }
 
@@ -944,7 +2607,8 @@ fn close_upvalues<'gc>(
     mc: MutationContext<'gc, '_>,
     bottom: usize,
 ) {
-    for (_, upval) in state.open_upvalues.split_off(&bottom) {
+    while let Some(&(_, upval)) = state.open_upvalues.last().filter(|&&(i, _)| i >= bottom) {
+        state.open_upvalues.pop();
         let mut upval = upval.0.write(mc);
         if let UpValueState::Open(upvalue_thread, ind) = *upval {
             assert!(upvalue_thread == thread);
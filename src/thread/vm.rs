@@ -1,10 +1,15 @@
 use gc_arena::{Gc, MutationContext};
 
 use crate::{
-    thread::LuaFrame, BinaryOperatorError, Closure, ClosureState, Error, Function, OpCode,
-    RegisterIndex, String, Table, TypeError, UpValueDescriptor, Value, VarCount,
+    meta_ops::{self, MetaResult},
+    thread::{LuaFrame, MetaReturn},
+    BinaryOperatorError, Closure, ClosureState, Error, Function, OpCode, RegisterIndex, Table,
+    UpValueDescriptor, Value, VarCount,
 };
 
+// Size hints may come from untrusted precompiled chunks, so don't trust them past this size.
+const MAX_SIZE_HINT: usize = 1 << 16;
+
 // Runs the VM for the given number of instructions or until the current LuaFrame may have been
 // changed.  Returns the number of instructions that were not run, or 0 if all requested
 // instructions were run.
@@ -16,11 +21,26 @@ pub(crate) fn run_vm<'gc>(
     assert_ne!(instructions, 0);
 
     let current_function = lua_frame.closure();
+    // Borrowed once up front, rather than reached through the closure for every instruction
+    let opcodes = &current_function.0.proto.opcodes[..];
+    let constants = &current_function.0.proto.constants[..];
+    let upvalues = &current_function.0.upvalues[..];
+    let string_metatable = lua_frame.string_metatable();
+    let interned_strings = lua_frame.interned_strings();
     let mut registers = lua_frame.registers();
+    let coverage = current_function.0.proto.coverage.as_ref().map(|c| &c.0);
 
+    // The program counter is kept in a local so that dispatch never has to wait on a load of the
+    // value it just stored.  It is still written back to the frame after every fetch, so anything
+    // that stops the VM part way through an instruction sees the frame as it always has.
+    let mut pc = *registers.pc;
     loop {
-        let op = current_function.0.proto.opcodes[*registers.pc];
-        *registers.pc += 1;
+        let op = opcodes[pc];
+        if let Some(coverage) = coverage {
+            coverage.hit(pc);
+        }
+        pc += 1;
+        *registers.pc = pc;
 
         match op {
             OpCode::Move { dest, source } => {
@@ -28,8 +48,7 @@ pub(crate) fn run_vm<'gc>(
             }
 
             OpCode::LoadConstant { dest, constant } => {
-                registers.stack_frame[dest.0 as usize] =
-                    current_function.0.proto.constants[constant.0 as usize].to_value();
+                registers.stack_frame[dest.0 as usize] = constants[constant.0 as usize].to_value();
             }
 
             OpCode::LoadBool {
@@ -39,7 +58,7 @@ pub(crate) fn run_vm<'gc>(
             } => {
                 registers.stack_frame[dest.0 as usize] = Value::Boolean(value);
                 if skip_next {
-                    *registers.pc += 1;
+                    pc += 1;
                 }
             }
 
@@ -49,101 +68,157 @@ pub(crate) fn run_vm<'gc>(
                 }
             }
 
-            OpCode::NewTable { dest } => {
-                registers.stack_frame[dest.0 as usize] = Value::Table(Table::new(mc));
+            OpCode::NewTable {
+                dest,
+                array_size,
+                map_size,
+            } => {
+                registers.stack_frame[dest.0 as usize] = Value::Table(Table::with_capacity(
+                    mc,
+                    array_size.to_usize().min(MAX_SIZE_HINT),
+                    map_size.to_usize().min(MAX_SIZE_HINT),
+                ));
+            }
+
+            OpCode::SetList { table, base, count } => {
+                lua_frame.set_list(mc, table, base, count)?;
+                break;
             }
 
             OpCode::GetTableR { dest, table, key } => {
-                registers.stack_frame[dest.0 as usize] =
-                    get_table(registers.stack_frame[table.0 as usize])?
-                        .get(registers.stack_frame[key.0 as usize]);
+                let table = registers.stack_frame[table.0 as usize];
+                let key = registers.stack_frame[key.0 as usize];
+                match meta_ops::index(string_metatable, table, key)? {
+                    MetaResult::Value(v) => {
+                        registers.stack_frame[dest.0 as usize] = v;
+                    }
+                    MetaResult::Call(f, args) => {
+                        lua_frame.call_meta_function(mc, f, &args, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::GetTableC { dest, table, key } => {
-                registers.stack_frame[dest.0 as usize] =
-                    get_table(registers.stack_frame[table.0 as usize])?
-                        .get(current_function.0.proto.constants[key.0 as usize].to_value())
+                let table = registers.stack_frame[table.0 as usize];
+                let key = constants[key.0 as usize].to_value();
+                match meta_ops::index(string_metatable, table, key)? {
+                    MetaResult::Value(v) => {
+                        registers.stack_frame[dest.0 as usize] = v;
+                    }
+                    MetaResult::Call(f, args) => {
+                        lua_frame.call_meta_function(mc, f, &args, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::SetTableRR { table, key, value } => {
-                get_table(registers.stack_frame[table.0 as usize])?.set(
-                    mc,
-                    registers.stack_frame[key.0 as usize],
-                    registers.stack_frame[value.0 as usize],
-                )?;
+                let table = registers.stack_frame[table.0 as usize];
+                let key = registers.stack_frame[key.0 as usize];
+                let value = registers.stack_frame[value.0 as usize];
+                if let Some((f, args)) = meta_ops::new_index(mc, table, key, value)? {
+                    lua_frame.call_meta_function(mc, f, &args, MetaReturn::None)?;
+                    break;
+                }
             }
 
             OpCode::SetTableRC { table, key, value } => {
-                get_table(registers.stack_frame[table.0 as usize])?.set(
-                    mc,
-                    registers.stack_frame[key.0 as usize],
-                    current_function.0.proto.constants[value.0 as usize].to_value(),
-                )?;
+                let table = registers.stack_frame[table.0 as usize];
+                let key = registers.stack_frame[key.0 as usize];
+                let value = constants[value.0 as usize].to_value();
+                if let Some((f, args)) = meta_ops::new_index(mc, table, key, value)? {
+                    lua_frame.call_meta_function(mc, f, &args, MetaReturn::None)?;
+                    break;
+                }
             }
 
             OpCode::SetTableCR { table, key, value } => {
-                get_table(registers.stack_frame[table.0 as usize])?.set(
-                    mc,
-                    current_function.0.proto.constants[key.0 as usize].to_value(),
-                    registers.stack_frame[value.0 as usize],
-                )?;
+                let table = registers.stack_frame[table.0 as usize];
+                let key = constants[key.0 as usize].to_value();
+                let value = registers.stack_frame[value.0 as usize];
+                if let Some((f, args)) = meta_ops::new_index(mc, table, key, value)? {
+                    lua_frame.call_meta_function(mc, f, &args, MetaReturn::None)?;
+                    break;
+                }
             }
 
             OpCode::SetTableCC { table, key, value } => {
-                get_table(registers.stack_frame[table.0 as usize])?.set(
-                    mc,
-                    current_function.0.proto.constants[key.0 as usize].to_value(),
-                    current_function.0.proto.constants[value.0 as usize].to_value(),
-                )?;
+                let table = registers.stack_frame[table.0 as usize];
+                let key = constants[key.0 as usize].to_value();
+                let value = constants[value.0 as usize].to_value();
+                if let Some((f, args)) = meta_ops::new_index(mc, table, key, value)? {
+                    lua_frame.call_meta_function(mc, f, &args, MetaReturn::None)?;
+                    break;
+                }
             }
 
             OpCode::GetUpTableR { dest, table, key } => {
-                registers.stack_frame[dest.0 as usize] = get_table(
-                    registers.get_upvalue(current_function.0.upvalues[table.0 as usize]),
-                )?
-                .get(registers.stack_frame[key.0 as usize]);
+                let table = registers.get_upvalue(upvalues[table.0 as usize]);
+                let key = registers.stack_frame[key.0 as usize];
+                match meta_ops::index(string_metatable, table, key)? {
+                    MetaResult::Value(v) => {
+                        registers.stack_frame[dest.0 as usize] = v;
+                    }
+                    MetaResult::Call(f, args) => {
+                        lua_frame.call_meta_function(mc, f, &args, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::GetUpTableC { dest, table, key } => {
-                registers.stack_frame[dest.0 as usize] =
-                    get_table(registers.get_upvalue(current_function.0.upvalues[table.0 as usize]))?
-                        .get(current_function.0.proto.constants[key.0 as usize].to_value())
+                let table = registers.get_upvalue(upvalues[table.0 as usize]);
+                let key = constants[key.0 as usize].to_value();
+                match meta_ops::index(string_metatable, table, key)? {
+                    MetaResult::Value(v) => {
+                        registers.stack_frame[dest.0 as usize] = v;
+                    }
+                    MetaResult::Call(f, args) => {
+                        lua_frame.call_meta_function(mc, f, &args, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::SetUpTableRR { table, key, value } => {
-                get_table(registers.get_upvalue(current_function.0.upvalues[table.0 as usize]))?
-                    .set(
-                        mc,
-                        registers.stack_frame[key.0 as usize],
-                        registers.stack_frame[value.0 as usize],
-                    )?;
+                let table = registers.get_upvalue(upvalues[table.0 as usize]);
+                let key = registers.stack_frame[key.0 as usize];
+                let value = registers.stack_frame[value.0 as usize];
+                if let Some((f, args)) = meta_ops::new_index(mc, table, key, value)? {
+                    lua_frame.call_meta_function(mc, f, &args, MetaReturn::None)?;
+                    break;
+                }
             }
 
             OpCode::SetUpTableRC { table, key, value } => {
-                get_table(registers.get_upvalue(current_function.0.upvalues[table.0 as usize]))?
-                    .set(
-                        mc,
-                        registers.stack_frame[key.0 as usize],
-                        current_function.0.proto.constants[value.0 as usize].to_value(),
-                    )?;
+                let table = registers.get_upvalue(upvalues[table.0 as usize]);
+                let key = registers.stack_frame[key.0 as usize];
+                let value = constants[value.0 as usize].to_value();
+                if let Some((f, args)) = meta_ops::new_index(mc, table, key, value)? {
+                    lua_frame.call_meta_function(mc, f, &args, MetaReturn::None)?;
+                    break;
+                }
             }
 
             OpCode::SetUpTableCR { table, key, value } => {
-                get_table(registers.get_upvalue(current_function.0.upvalues[table.0 as usize]))?
-                    .set(
-                        mc,
-                        current_function.0.proto.constants[key.0 as usize].to_value(),
-                        registers.stack_frame[value.0 as usize],
-                    )?;
+                let table = registers.get_upvalue(upvalues[table.0 as usize]);
+                let key = constants[key.0 as usize].to_value();
+                let value = registers.stack_frame[value.0 as usize];
+                if let Some((f, args)) = meta_ops::new_index(mc, table, key, value)? {
+                    lua_frame.call_meta_function(mc, f, &args, MetaReturn::None)?;
+                    break;
+                }
             }
 
             OpCode::SetUpTableCC { table, key, value } => {
-                get_table(registers.get_upvalue(current_function.0.upvalues[table.0 as usize]))?
-                    .set(
-                        mc,
-                        current_function.0.proto.constants[key.0 as usize].to_value(),
-                        current_function.0.proto.constants[value.0 as usize].to_value(),
-                    )?;
+                let table = registers.get_upvalue(upvalues[table.0 as usize]);
+                let key = constants[key.0 as usize].to_value();
+                let value = constants[value.0 as usize].to_value();
+                if let Some((f, args)) = meta_ops::new_index(mc, table, key, value)? {
+                    lua_frame.call_meta_function(mc, f, &args, MetaReturn::None)?;
+                    break;
+                }
             }
 
             OpCode::Call {
@@ -161,6 +236,11 @@ pub(crate) fn run_vm<'gc>(
             }
 
             OpCode::Return { start, count } => {
+                if registers.has_to_be_closed(RegisterIndex(0)) {
+                    *registers.pc -= 1;
+                    lua_frame.close_variable(mc)?;
+                    break;
+                }
                 lua_frame.return_upper(mc, start, count)?;
                 break;
             }
@@ -174,16 +254,21 @@ pub(crate) fn run_vm<'gc>(
                 offset,
                 close_upvalues,
             } => {
-                *registers.pc = add_offset(*registers.pc, offset);
                 if let Some(r) = close_upvalues.to_u8() {
+                    if registers.has_to_be_closed(RegisterIndex(r)) {
+                        *registers.pc -= 1;
+                        lua_frame.close_variable(mc)?;
+                        break;
+                    }
                     registers.close_upvalues(mc, RegisterIndex(r));
                 }
+                pc = add_offset(pc, offset);
             }
 
             OpCode::Test { value, is_true } => {
                 let value = registers.stack_frame[value.0 as usize];
                 if value.to_bool() == is_true {
-                    *registers.pc += 1;
+                    pc += 1;
                 }
             }
 
@@ -194,7 +279,7 @@ pub(crate) fn run_vm<'gc>(
             } => {
                 let value = registers.stack_frame[value.0 as usize];
                 if value.to_bool() == is_true {
-                    *registers.pc += 1;
+                    pc += 1;
                 } else {
                     registers.stack_frame[dest.0 as usize] = value;
                 }
@@ -202,31 +287,41 @@ pub(crate) fn run_vm<'gc>(
 
             OpCode::Closure { proto, dest } => {
                 let proto = current_function.0.proto.prototypes[proto.0 as usize];
-                let mut upvalues = Vec::new();
+                let mut closure_upvalues = Vec::new();
                 for &desc in &proto.upvalues {
                     match desc {
                         UpValueDescriptor::Environment => {
                             panic!("_ENV upvalue is only allowed on top-level closure");
                         }
                         UpValueDescriptor::ParentLocal(reg) => {
-                            upvalues.push(registers.open_upvalue(mc, reg));
+                            closure_upvalues.push(registers.open_upvalue(mc, reg));
                         }
                         UpValueDescriptor::Outer(uvindex) => {
-                            upvalues.push(current_function.0.upvalues[uvindex.0 as usize]);
+                            closure_upvalues.push(upvalues[uvindex.0 as usize]);
                         }
                     }
                 }
 
-                let closure = Closure(Gc::allocate(mc, ClosureState { proto, upvalues }));
+                let closure = Closure(Gc::allocate(
+                    mc,
+                    ClosureState {
+                        proto,
+                        upvalues: closure_upvalues,
+                    },
+                ));
                 registers.stack_frame[dest.0 as usize] =
                     Value::Function(Function::Closure(closure));
             }
 
+            OpCode::ToBeClosed { value } => {
+                registers.mark_to_be_closed(value)?;
+            }
+
             OpCode::NumericForPrep { base, jump } => {
                 registers.stack_frame[base.0 as usize] = registers.stack_frame[base.0 as usize]
                     .subtract(registers.stack_frame[base.0 as usize + 2])
                     .ok_or(BinaryOperatorError::Subtract)?;
-                *registers.pc = add_offset(*registers.pc, jump);
+                pc = add_offset(pc, jump);
             }
 
             OpCode::NumericForLoop { base, jump } => {
@@ -245,7 +340,7 @@ pub(crate) fn run_vm<'gc>(
                             limit < index
                         };
                         if !past_end {
-                            *registers.pc = add_offset(*registers.pc, jump);
+                            pc = add_offset(pc, jump);
                             registers.stack_frame[base.0 as usize + 3] = Value::Integer(index);
                         }
                     }
@@ -262,7 +357,7 @@ pub(crate) fn run_vm<'gc>(
                                 limit < index
                             };
                             if !past_end {
-                                *registers.pc = add_offset(*registers.pc, jump);
+                                pc = add_offset(pc, jump);
                                 registers.stack_frame[base.0 as usize + 3] = Value::Number(index);
                             }
                         } else {
@@ -286,22 +381,38 @@ pub(crate) fn run_vm<'gc>(
                 if registers.stack_frame[base.0 as usize + 1].to_bool() {
                     registers.stack_frame[base.0 as usize] =
                         registers.stack_frame[base.0 as usize + 1];
-                    *registers.pc = add_offset(*registers.pc, jump);
+                    pc = add_offset(pc, jump);
                 }
             }
 
             OpCode::SelfR { base, table, key } => {
                 let table = registers.stack_frame[table.0 as usize];
-                let key = current_function.0.proto.constants[key.0 as usize].to_value();
+                let key = registers.stack_frame[key.0 as usize];
                 registers.stack_frame[base.0 as usize + 1] = table;
-                registers.stack_frame[base.0 as usize] = get_table(table)?.get(key);
+                match meta_ops::index(string_metatable, table, key)? {
+                    MetaResult::Value(v) => {
+                        registers.stack_frame[base.0 as usize] = v;
+                    }
+                    MetaResult::Call(f, args) => {
+                        lua_frame.call_meta_function(mc, f, &args, MetaReturn::Register(base))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::SelfC { base, table, key } => {
                 let table = registers.stack_frame[table.0 as usize];
-                let key = current_function.0.proto.constants[key.0 as usize].to_value();
+                let key = constants[key.0 as usize].to_value();
                 registers.stack_frame[base.0 as usize + 1] = table;
-                registers.stack_frame[base.0 as usize] = get_table(table)?.get(key);
+                match meta_ops::index(string_metatable, table, key)? {
+                    MetaResult::Value(v) => {
+                        registers.stack_frame[base.0 as usize] = v;
+                    }
+                    MetaResult::Call(f, args) => {
+                        lua_frame.call_meta_function(mc, f, &args, MetaReturn::Register(base))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::Concat {
@@ -309,32 +420,44 @@ pub(crate) fn run_vm<'gc>(
                 source,
                 count,
             } => {
-                registers.stack_frame[dest.0 as usize] = Value::String(
-                    String::concat(
-                        mc,
-                        &registers.stack_frame
-                            [source.0 as usize..source.0 as usize + count as usize],
-                    )
-                    .unwrap(),
-                );
+                match meta_ops::concat(
+                    mc,
+                    interned_strings,
+                    &registers.stack_frame[source.0 as usize..source.0 as usize + count as usize],
+                )? {
+                    MetaResult::Value(v) => {
+                        registers.stack_frame[dest.0 as usize] = v;
+                    }
+                    MetaResult::Call(f, args) => {
+                        lua_frame.call_meta_function(mc, f, &args, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::GetUpValue { source, dest } => {
                 registers.stack_frame[dest.0 as usize] =
-                    registers.get_upvalue(current_function.0.upvalues[source.0 as usize]);
+                    registers.get_upvalue(upvalues[source.0 as usize]);
             }
 
             OpCode::SetUpValue { source, dest } => {
                 registers.set_upvalue(
                     mc,
-                    current_function.0.upvalues[dest.0 as usize],
+                    upvalues[dest.0 as usize],
                     registers.stack_frame[source.0 as usize],
                 );
             }
 
             OpCode::Length { dest, source } => {
-                registers.stack_frame[dest.0 as usize] =
-                    Value::Integer(get_table(registers.stack_frame[source.0 as usize])?.length());
+                match meta_ops::len(registers.stack_frame[source.0 as usize])? {
+                    MetaResult::Value(v) => {
+                        registers.stack_frame[dest.0 as usize] = v;
+                    }
+                    MetaResult::Call(f, args) => {
+                        lua_frame.call_meta_function(mc, f, &args, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::EqRR {
@@ -344,8 +467,16 @@ pub(crate) fn run_vm<'gc>(
             } => {
                 let left = registers.stack_frame[left.0 as usize];
                 let right = registers.stack_frame[right.0 as usize];
-                if (left == right) == skip_if {
-                    *registers.pc += 1;
+                match meta_ops::equal(left, right) {
+                    MetaResult::Value(v) => {
+                        if v.to_bool() == skip_if {
+                            pc += 1;
+                        }
+                    }
+                    MetaResult::Call(f, args) => {
+                        lua_frame.call_meta_function(mc, f, &args, MetaReturn::SkipIf(skip_if))?;
+                        break;
+                    }
                 }
             }
 
@@ -355,9 +486,17 @@ pub(crate) fn run_vm<'gc>(
                 right,
             } => {
                 let left = registers.stack_frame[left.0 as usize];
-                let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                if (left == right) == skip_if {
-                    *registers.pc += 1;
+                let right = constants[right.0 as usize].to_value();
+                match meta_ops::equal(left, right) {
+                    MetaResult::Value(v) => {
+                        if v.to_bool() == skip_if {
+                            pc += 1;
+                        }
+                    }
+                    MetaResult::Call(f, args) => {
+                        lua_frame.call_meta_function(mc, f, &args, MetaReturn::SkipIf(skip_if))?;
+                        break;
+                    }
                 }
             }
 
@@ -366,10 +505,18 @@ pub(crate) fn run_vm<'gc>(
                 left,
                 right,
             } => {
-                let left = current_function.0.proto.constants[left.0 as usize].to_value();
+                let left = constants[left.0 as usize].to_value();
                 let right = registers.stack_frame[right.0 as usize];
-                if (left == right) == skip_if {
-                    *registers.pc += 1;
+                match meta_ops::equal(left, right) {
+                    MetaResult::Value(v) => {
+                        if v.to_bool() == skip_if {
+                            pc += 1;
+                        }
+                    }
+                    MetaResult::Call(f, args) => {
+                        lua_frame.call_meta_function(mc, f, &args, MetaReturn::SkipIf(skip_if))?;
+                        break;
+                    }
                 }
             }
 
@@ -378,10 +525,18 @@ pub(crate) fn run_vm<'gc>(
                 left,
                 right,
             } => {
-                let left = current_function.0.proto.constants[left.0 as usize].to_value();
-                let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                if (left == right) == skip_if {
-                    *registers.pc += 1;
+                let left = constants[left.0 as usize].to_value();
+                let right = constants[right.0 as usize].to_value();
+                match meta_ops::equal(left, right) {
+                    MetaResult::Value(v) => {
+                        if v.to_bool() == skip_if {
+                            pc += 1;
+                        }
+                    }
+                    MetaResult::Call(f, args) => {
+                        lua_frame.call_meta_function(mc, f, &args, MetaReturn::SkipIf(skip_if))?;
+                        break;
+                    }
                 }
             }
 
@@ -392,8 +547,16 @@ pub(crate) fn run_vm<'gc>(
             } => {
                 let left = registers.stack_frame[left.0 as usize];
                 let right = registers.stack_frame[right.0 as usize];
-                if (left.less_than(right).ok_or(BinaryOperatorError::LessThan)?) == skip_if {
-                    *registers.pc += 1;
+                match meta_ops::less_than(left, right)? {
+                    MetaResult::Value(v) => {
+                        if v.to_bool() == skip_if {
+                            pc += 1;
+                        }
+                    }
+                    MetaResult::Call(f, args) => {
+                        lua_frame.call_meta_function(mc, f, &args, MetaReturn::SkipIf(skip_if))?;
+                        break;
+                    }
                 }
             }
 
@@ -403,9 +566,17 @@ pub(crate) fn run_vm<'gc>(
                 right,
             } => {
                 let left = registers.stack_frame[left.0 as usize];
-                let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                if (left.less_than(right).ok_or(BinaryOperatorError::LessThan)?) == skip_if {
-                    *registers.pc += 1;
+                let right = constants[right.0 as usize].to_value();
+                match meta_ops::less_than(left, right)? {
+                    MetaResult::Value(v) => {
+                        if v.to_bool() == skip_if {
+                            pc += 1;
+                        }
+                    }
+                    MetaResult::Call(f, args) => {
+                        lua_frame.call_meta_function(mc, f, &args, MetaReturn::SkipIf(skip_if))?;
+                        break;
+                    }
                 }
             }
 
@@ -414,10 +585,18 @@ pub(crate) fn run_vm<'gc>(
                 left,
                 right,
             } => {
-                let left = current_function.0.proto.constants[left.0 as usize].to_value();
+                let left = constants[left.0 as usize].to_value();
                 let right = registers.stack_frame[right.0 as usize];
-                if (left.less_than(right).ok_or(BinaryOperatorError::LessThan)?) == skip_if {
-                    *registers.pc += 1;
+                match meta_ops::less_than(left, right)? {
+                    MetaResult::Value(v) => {
+                        if v.to_bool() == skip_if {
+                            pc += 1;
+                        }
+                    }
+                    MetaResult::Call(f, args) => {
+                        lua_frame.call_meta_function(mc, f, &args, MetaReturn::SkipIf(skip_if))?;
+                        break;
+                    }
                 }
             }
 
@@ -426,10 +605,18 @@ pub(crate) fn run_vm<'gc>(
                 left,
                 right,
             } => {
-                let left = current_function.0.proto.constants[left.0 as usize].to_value();
-                let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                if (left.less_than(right).ok_or(BinaryOperatorError::LessThan)?) == skip_if {
-                    *registers.pc += 1;
+                let left = constants[left.0 as usize].to_value();
+                let right = constants[right.0 as usize].to_value();
+                match meta_ops::less_than(left, right)? {
+                    MetaResult::Value(v) => {
+                        if v.to_bool() == skip_if {
+                            pc += 1;
+                        }
+                    }
+                    MetaResult::Call(f, args) => {
+                        lua_frame.call_meta_function(mc, f, &args, MetaReturn::SkipIf(skip_if))?;
+                        break;
+                    }
                 }
             }
 
@@ -440,12 +627,16 @@ pub(crate) fn run_vm<'gc>(
             } => {
                 let left = registers.stack_frame[left.0 as usize];
                 let right = registers.stack_frame[right.0 as usize];
-                if (left
-                    .less_equal(right)
-                    .ok_or(BinaryOperatorError::LessEqual)?)
-                    == skip_if
-                {
-                    *registers.pc += 1;
+                match meta_ops::less_equal(left, right)? {
+                    MetaResult::Value(v) => {
+                        if v.to_bool() == skip_if {
+                            pc += 1;
+                        }
+                    }
+                    MetaResult::Call(f, args) => {
+                        lua_frame.call_meta_function(mc, f, &args, MetaReturn::SkipIf(skip_if))?;
+                        break;
+                    }
                 }
             }
 
@@ -455,13 +646,17 @@ pub(crate) fn run_vm<'gc>(
                 right,
             } => {
                 let left = registers.stack_frame[left.0 as usize];
-                let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                if (left
-                    .less_equal(right)
-                    .ok_or(BinaryOperatorError::LessEqual)?)
-                    == skip_if
-                {
-                    *registers.pc += 1;
+                let right = constants[right.0 as usize].to_value();
+                match meta_ops::less_equal(left, right)? {
+                    MetaResult::Value(v) => {
+                        if v.to_bool() == skip_if {
+                            pc += 1;
+                        }
+                    }
+                    MetaResult::Call(f, args) => {
+                        lua_frame.call_meta_function(mc, f, &args, MetaReturn::SkipIf(skip_if))?;
+                        break;
+                    }
                 }
             }
 
@@ -470,14 +665,18 @@ pub(crate) fn run_vm<'gc>(
                 left,
                 right,
             } => {
-                let left = current_function.0.proto.constants[left.0 as usize].to_value();
+                let left = constants[left.0 as usize].to_value();
                 let right = registers.stack_frame[right.0 as usize];
-                if (left
-                    .less_equal(right)
-                    .ok_or(BinaryOperatorError::LessEqual)?)
-                    == skip_if
-                {
-                    *registers.pc += 1;
+                match meta_ops::less_equal(left, right)? {
+                    MetaResult::Value(v) => {
+                        if v.to_bool() == skip_if {
+                            pc += 1;
+                        }
+                    }
+                    MetaResult::Call(f, args) => {
+                        lua_frame.call_meta_function(mc, f, &args, MetaReturn::SkipIf(skip_if))?;
+                        break;
+                    }
                 }
             }
 
@@ -486,14 +685,270 @@ pub(crate) fn run_vm<'gc>(
                 left,
                 right,
             } => {
-                let left = current_function.0.proto.constants[left.0 as usize].to_value();
-                let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                if (left
-                    .less_equal(right)
-                    .ok_or(BinaryOperatorError::LessEqual)?)
-                    == skip_if
-                {
-                    *registers.pc += 1;
+                let left = constants[left.0 as usize].to_value();
+                let right = constants[right.0 as usize].to_value();
+                match meta_ops::less_equal(left, right)? {
+                    MetaResult::Value(v) => {
+                        if v.to_bool() == skip_if {
+                            pc += 1;
+                        }
+                    }
+                    MetaResult::Call(f, args) => {
+                        lua_frame.call_meta_function(mc, f, &args, MetaReturn::SkipIf(skip_if))?;
+                        break;
+                    }
+                }
+            }
+
+            OpCode::JumpIfEqRR { left, right, jump } => {
+                let left = registers.stack_frame[left.0 as usize];
+                let right = registers.stack_frame[right.0 as usize];
+                match meta_ops::equal(left, right) {
+                    MetaResult::Value(v) => {
+                        if v.to_bool() {
+                            pc = add_offset(pc, jump as i16);
+                        }
+                    }
+                    MetaResult::Call(f, args) => {
+                        lua_frame.call_meta_function(
+                            mc,
+                            f,
+                            &args,
+                            MetaReturn::JumpIf(true, jump),
+                        )?;
+                        break;
+                    }
+                }
+            }
+
+            OpCode::JumpIfEqRC { left, right, jump } => {
+                let left = registers.stack_frame[left.0 as usize];
+                let right = constants[right.0 as usize].to_value();
+                match meta_ops::equal(left, right) {
+                    MetaResult::Value(v) => {
+                        if v.to_bool() {
+                            pc = add_offset(pc, jump as i16);
+                        }
+                    }
+                    MetaResult::Call(f, args) => {
+                        lua_frame.call_meta_function(
+                            mc,
+                            f,
+                            &args,
+                            MetaReturn::JumpIf(true, jump),
+                        )?;
+                        break;
+                    }
+                }
+            }
+
+            OpCode::JumpIfNotEqRR { left, right, jump } => {
+                let left = registers.stack_frame[left.0 as usize];
+                let right = registers.stack_frame[right.0 as usize];
+                match meta_ops::equal(left, right) {
+                    MetaResult::Value(v) => {
+                        if !v.to_bool() {
+                            pc = add_offset(pc, jump as i16);
+                        }
+                    }
+                    MetaResult::Call(f, args) => {
+                        lua_frame.call_meta_function(
+                            mc,
+                            f,
+                            &args,
+                            MetaReturn::JumpIf(false, jump),
+                        )?;
+                        break;
+                    }
+                }
+            }
+
+            OpCode::JumpIfNotEqRC { left, right, jump } => {
+                let left = registers.stack_frame[left.0 as usize];
+                let right = constants[right.0 as usize].to_value();
+                match meta_ops::equal(left, right) {
+                    MetaResult::Value(v) => {
+                        if !v.to_bool() {
+                            pc = add_offset(pc, jump as i16);
+                        }
+                    }
+                    MetaResult::Call(f, args) => {
+                        lua_frame.call_meta_function(
+                            mc,
+                            f,
+                            &args,
+                            MetaReturn::JumpIf(false, jump),
+                        )?;
+                        break;
+                    }
+                }
+            }
+
+            OpCode::JumpIfLessRR { left, right, jump } => {
+                let left = registers.stack_frame[left.0 as usize];
+                let right = registers.stack_frame[right.0 as usize];
+                match meta_ops::less_than(left, right)? {
+                    MetaResult::Value(v) => {
+                        if v.to_bool() {
+                            pc = add_offset(pc, jump as i16);
+                        }
+                    }
+                    MetaResult::Call(f, args) => {
+                        lua_frame.call_meta_function(
+                            mc,
+                            f,
+                            &args,
+                            MetaReturn::JumpIf(true, jump),
+                        )?;
+                        break;
+                    }
+                }
+            }
+
+            OpCode::JumpIfLessRC { left, right, jump } => {
+                let left = registers.stack_frame[left.0 as usize];
+                let right = constants[right.0 as usize].to_value();
+                match meta_ops::less_than(left, right)? {
+                    MetaResult::Value(v) => {
+                        if v.to_bool() {
+                            pc = add_offset(pc, jump as i16);
+                        }
+                    }
+                    MetaResult::Call(f, args) => {
+                        lua_frame.call_meta_function(
+                            mc,
+                            f,
+                            &args,
+                            MetaReturn::JumpIf(true, jump),
+                        )?;
+                        break;
+                    }
+                }
+            }
+
+            OpCode::JumpIfNotLessRR { left, right, jump } => {
+                let left = registers.stack_frame[left.0 as usize];
+                let right = registers.stack_frame[right.0 as usize];
+                match meta_ops::less_than(left, right)? {
+                    MetaResult::Value(v) => {
+                        if !v.to_bool() {
+                            pc = add_offset(pc, jump as i16);
+                        }
+                    }
+                    MetaResult::Call(f, args) => {
+                        lua_frame.call_meta_function(
+                            mc,
+                            f,
+                            &args,
+                            MetaReturn::JumpIf(false, jump),
+                        )?;
+                        break;
+                    }
+                }
+            }
+
+            OpCode::JumpIfNotLessRC { left, right, jump } => {
+                let left = registers.stack_frame[left.0 as usize];
+                let right = constants[right.0 as usize].to_value();
+                match meta_ops::less_than(left, right)? {
+                    MetaResult::Value(v) => {
+                        if !v.to_bool() {
+                            pc = add_offset(pc, jump as i16);
+                        }
+                    }
+                    MetaResult::Call(f, args) => {
+                        lua_frame.call_meta_function(
+                            mc,
+                            f,
+                            &args,
+                            MetaReturn::JumpIf(false, jump),
+                        )?;
+                        break;
+                    }
+                }
+            }
+
+            OpCode::JumpIfLessEqRR { left, right, jump } => {
+                let left = registers.stack_frame[left.0 as usize];
+                let right = registers.stack_frame[right.0 as usize];
+                match meta_ops::less_equal(left, right)? {
+                    MetaResult::Value(v) => {
+                        if v.to_bool() {
+                            pc = add_offset(pc, jump as i16);
+                        }
+                    }
+                    MetaResult::Call(f, args) => {
+                        lua_frame.call_meta_function(
+                            mc,
+                            f,
+                            &args,
+                            MetaReturn::JumpIf(true, jump),
+                        )?;
+                        break;
+                    }
+                }
+            }
+
+            OpCode::JumpIfLessEqRC { left, right, jump } => {
+                let left = registers.stack_frame[left.0 as usize];
+                let right = constants[right.0 as usize].to_value();
+                match meta_ops::less_equal(left, right)? {
+                    MetaResult::Value(v) => {
+                        if v.to_bool() {
+                            pc = add_offset(pc, jump as i16);
+                        }
+                    }
+                    MetaResult::Call(f, args) => {
+                        lua_frame.call_meta_function(
+                            mc,
+                            f,
+                            &args,
+                            MetaReturn::JumpIf(true, jump),
+                        )?;
+                        break;
+                    }
+                }
+            }
+
+            OpCode::JumpIfNotLessEqRR { left, right, jump } => {
+                let left = registers.stack_frame[left.0 as usize];
+                let right = registers.stack_frame[right.0 as usize];
+                match meta_ops::less_equal(left, right)? {
+                    MetaResult::Value(v) => {
+                        if !v.to_bool() {
+                            pc = add_offset(pc, jump as i16);
+                        }
+                    }
+                    MetaResult::Call(f, args) => {
+                        lua_frame.call_meta_function(
+                            mc,
+                            f,
+                            &args,
+                            MetaReturn::JumpIf(false, jump),
+                        )?;
+                        break;
+                    }
+                }
+            }
+
+            OpCode::JumpIfNotLessEqRC { left, right, jump } => {
+                let left = registers.stack_frame[left.0 as usize];
+                let right = constants[right.0 as usize].to_value();
+                match meta_ops::less_equal(left, right)? {
+                    MetaResult::Value(v) => {
+                        if !v.to_bool() {
+                            pc = add_offset(pc, jump as i16);
+                        }
+                    }
+                    MetaResult::Call(f, args) => {
+                        lua_frame.call_meta_function(
+                            mc,
+                            f,
+                            &args,
+                            MetaReturn::JumpIf(false, jump),
+                        )?;
+                        break;
+                    }
                 }
             }
 
@@ -504,8 +959,15 @@ pub(crate) fn run_vm<'gc>(
 
             OpCode::Minus { dest, source } => {
                 let value = registers.stack_frame[source.0 as usize];
-                registers.stack_frame[dest.0 as usize] =
-                    value.negate().ok_or(BinaryOperatorError::UnaryNegate)?;
+                match meta_ops::negate(value)? {
+                    MetaResult::Value(v) => {
+                        registers.stack_frame[dest.0 as usize] = v;
+                    }
+                    MetaResult::Call(f, args) => {
+                        lua_frame.call_meta_function(mc, f, &args, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::BitNot { dest, source } => {
@@ -517,209 +979,393 @@ pub(crate) fn run_vm<'gc>(
             OpCode::AddRR { dest, left, right } => {
                 let left = registers.stack_frame[left.0 as usize];
                 let right = registers.stack_frame[right.0 as usize];
-                registers.stack_frame[dest.0 as usize] =
-                    left.add(right).ok_or(BinaryOperatorError::Add)?;
+                match meta_ops::add(left, right)? {
+                    MetaResult::Value(v) => {
+                        registers.stack_frame[dest.0 as usize] = v;
+                    }
+                    MetaResult::Call(f, args) => {
+                        lua_frame.call_meta_function(mc, f, &args, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::AddRC { dest, left, right } => {
                 let left = registers.stack_frame[left.0 as usize];
-                let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                registers.stack_frame[dest.0 as usize] =
-                    left.add(right).ok_or(BinaryOperatorError::Add)?;
+                let right = constants[right.0 as usize].to_value();
+                match meta_ops::add(left, right)? {
+                    MetaResult::Value(v) => {
+                        registers.stack_frame[dest.0 as usize] = v;
+                    }
+                    MetaResult::Call(f, args) => {
+                        lua_frame.call_meta_function(mc, f, &args, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::AddCR { dest, left, right } => {
-                let left = current_function.0.proto.constants[left.0 as usize].to_value();
+                let left = constants[left.0 as usize].to_value();
                 let right = registers.stack_frame[right.0 as usize];
-                registers.stack_frame[dest.0 as usize] =
-                    left.add(right).ok_or(BinaryOperatorError::Add)?;
+                match meta_ops::add(left, right)? {
+                    MetaResult::Value(v) => {
+                        registers.stack_frame[dest.0 as usize] = v;
+                    }
+                    MetaResult::Call(f, args) => {
+                        lua_frame.call_meta_function(mc, f, &args, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::AddCC { dest, left, right } => {
-                let left = current_function.0.proto.constants[left.0 as usize].to_value();
-                let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                registers.stack_frame[dest.0 as usize] =
-                    left.add(right).ok_or(BinaryOperatorError::Add)?;
+                let left = constants[left.0 as usize].to_value();
+                let right = constants[right.0 as usize].to_value();
+                match meta_ops::add(left, right)? {
+                    MetaResult::Value(v) => {
+                        registers.stack_frame[dest.0 as usize] = v;
+                    }
+                    MetaResult::Call(f, args) => {
+                        lua_frame.call_meta_function(mc, f, &args, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::SubRR { dest, left, right } => {
                 let left = registers.stack_frame[left.0 as usize];
                 let right = registers.stack_frame[right.0 as usize];
-                registers.stack_frame[dest.0 as usize] =
-                    left.subtract(right).ok_or(BinaryOperatorError::Add)?;
+                match meta_ops::subtract(left, right)? {
+                    MetaResult::Value(v) => {
+                        registers.stack_frame[dest.0 as usize] = v;
+                    }
+                    MetaResult::Call(f, args) => {
+                        lua_frame.call_meta_function(mc, f, &args, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::SubRC { dest, left, right } => {
                 let left = registers.stack_frame[left.0 as usize];
-                let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                registers.stack_frame[dest.0 as usize] =
-                    left.subtract(right).ok_or(BinaryOperatorError::Subtract)?;
+                let right = constants[right.0 as usize].to_value();
+                match meta_ops::subtract(left, right)? {
+                    MetaResult::Value(v) => {
+                        registers.stack_frame[dest.0 as usize] = v;
+                    }
+                    MetaResult::Call(f, args) => {
+                        lua_frame.call_meta_function(mc, f, &args, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::SubCR { dest, left, right } => {
-                let left = current_function.0.proto.constants[left.0 as usize].to_value();
+                let left = constants[left.0 as usize].to_value();
                 let right = registers.stack_frame[right.0 as usize];
-                registers.stack_frame[dest.0 as usize] =
-                    left.subtract(right).ok_or(BinaryOperatorError::Subtract)?;
+                match meta_ops::subtract(left, right)? {
+                    MetaResult::Value(v) => {
+                        registers.stack_frame[dest.0 as usize] = v;
+                    }
+                    MetaResult::Call(f, args) => {
+                        lua_frame.call_meta_function(mc, f, &args, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::SubCC { dest, left, right } => {
-                let left = current_function.0.proto.constants[left.0 as usize].to_value();
-                let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                registers.stack_frame[dest.0 as usize] =
-                    left.subtract(right).ok_or(BinaryOperatorError::Subtract)?;
+                let left = constants[left.0 as usize].to_value();
+                let right = constants[right.0 as usize].to_value();
+                match meta_ops::subtract(left, right)? {
+                    MetaResult::Value(v) => {
+                        registers.stack_frame[dest.0 as usize] = v;
+                    }
+                    MetaResult::Call(f, args) => {
+                        lua_frame.call_meta_function(mc, f, &args, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::MulRR { dest, left, right } => {
                 let left = registers.stack_frame[left.0 as usize];
                 let right = registers.stack_frame[right.0 as usize];
-                registers.stack_frame[dest.0 as usize] =
-                    left.multiply(right).ok_or(BinaryOperatorError::Multiply)?;
+                match meta_ops::multiply(left, right)? {
+                    MetaResult::Value(v) => {
+                        registers.stack_frame[dest.0 as usize] = v;
+                    }
+                    MetaResult::Call(f, args) => {
+                        lua_frame.call_meta_function(mc, f, &args, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::MulRC { dest, left, right } => {
                 let left = registers.stack_frame[left.0 as usize];
-                let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                registers.stack_frame[dest.0 as usize] =
-                    left.multiply(right).ok_or(BinaryOperatorError::Multiply)?;
+                let right = constants[right.0 as usize].to_value();
+                match meta_ops::multiply(left, right)? {
+                    MetaResult::Value(v) => {
+                        registers.stack_frame[dest.0 as usize] = v;
+                    }
+                    MetaResult::Call(f, args) => {
+                        lua_frame.call_meta_function(mc, f, &args, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::MulCR { dest, left, right } => {
-                let left = current_function.0.proto.constants[left.0 as usize].to_value();
+                let left = constants[left.0 as usize].to_value();
                 let right = registers.stack_frame[right.0 as usize];
-                registers.stack_frame[dest.0 as usize] =
-                    left.multiply(right).ok_or(BinaryOperatorError::Multiply)?;
+                match meta_ops::multiply(left, right)? {
+                    MetaResult::Value(v) => {
+                        registers.stack_frame[dest.0 as usize] = v;
+                    }
+                    MetaResult::Call(f, args) => {
+                        lua_frame.call_meta_function(mc, f, &args, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::MulCC { dest, left, right } => {
-                let left = current_function.0.proto.constants[left.0 as usize].to_value();
-                let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                registers.stack_frame[dest.0 as usize] =
-                    left.multiply(right).ok_or(BinaryOperatorError::Multiply)?;
+                let left = constants[left.0 as usize].to_value();
+                let right = constants[right.0 as usize].to_value();
+                match meta_ops::multiply(left, right)? {
+                    MetaResult::Value(v) => {
+                        registers.stack_frame[dest.0 as usize] = v;
+                    }
+                    MetaResult::Call(f, args) => {
+                        lua_frame.call_meta_function(mc, f, &args, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::DivRR { dest, left, right } => {
                 let left = registers.stack_frame[left.0 as usize];
                 let right = registers.stack_frame[right.0 as usize];
-                registers.stack_frame[dest.0 as usize] = left
-                    .float_divide(right)
-                    .ok_or(BinaryOperatorError::FloatDivide)?;
+                match meta_ops::float_divide(left, right)? {
+                    MetaResult::Value(v) => {
+                        registers.stack_frame[dest.0 as usize] = v;
+                    }
+                    MetaResult::Call(f, args) => {
+                        lua_frame.call_meta_function(mc, f, &args, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::DivRC { dest, left, right } => {
                 let left = registers.stack_frame[left.0 as usize];
-                let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                registers.stack_frame[dest.0 as usize] = left
-                    .float_divide(right)
-                    .ok_or(BinaryOperatorError::FloatDivide)?;
+                let right = constants[right.0 as usize].to_value();
+                match meta_ops::float_divide(left, right)? {
+                    MetaResult::Value(v) => {
+                        registers.stack_frame[dest.0 as usize] = v;
+                    }
+                    MetaResult::Call(f, args) => {
+                        lua_frame.call_meta_function(mc, f, &args, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::DivCR { dest, left, right } => {
-                let left = current_function.0.proto.constants[left.0 as usize].to_value();
+                let left = constants[left.0 as usize].to_value();
                 let right = registers.stack_frame[right.0 as usize];
-                registers.stack_frame[dest.0 as usize] = left
-                    .float_divide(right)
-                    .ok_or(BinaryOperatorError::FloatDivide)?;
+                match meta_ops::float_divide(left, right)? {
+                    MetaResult::Value(v) => {
+                        registers.stack_frame[dest.0 as usize] = v;
+                    }
+                    MetaResult::Call(f, args) => {
+                        lua_frame.call_meta_function(mc, f, &args, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::DivCC { dest, left, right } => {
-                let left = current_function.0.proto.constants[left.0 as usize].to_value();
-                let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                registers.stack_frame[dest.0 as usize] = left
-                    .float_divide(right)
-                    .ok_or(BinaryOperatorError::FloatDivide)?;
+                let left = constants[left.0 as usize].to_value();
+                let right = constants[right.0 as usize].to_value();
+                match meta_ops::float_divide(left, right)? {
+                    MetaResult::Value(v) => {
+                        registers.stack_frame[dest.0 as usize] = v;
+                    }
+                    MetaResult::Call(f, args) => {
+                        lua_frame.call_meta_function(mc, f, &args, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::IDivRR { dest, left, right } => {
                 let left = registers.stack_frame[left.0 as usize];
                 let right = registers.stack_frame[right.0 as usize];
-                registers.stack_frame[dest.0 as usize] = left
-                    .floor_divide(right)
-                    .ok_or(BinaryOperatorError::FloorDivide)?;
+                match meta_ops::floor_divide(left, right)? {
+                    MetaResult::Value(v) => {
+                        registers.stack_frame[dest.0 as usize] = v;
+                    }
+                    MetaResult::Call(f, args) => {
+                        lua_frame.call_meta_function(mc, f, &args, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::IDivRC { dest, left, right } => {
                 let left = registers.stack_frame[left.0 as usize];
-                let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                registers.stack_frame[dest.0 as usize] = left
-                    .floor_divide(right)
-                    .ok_or(BinaryOperatorError::FloorDivide)?;
+                let right = constants[right.0 as usize].to_value();
+                match meta_ops::floor_divide(left, right)? {
+                    MetaResult::Value(v) => {
+                        registers.stack_frame[dest.0 as usize] = v;
+                    }
+                    MetaResult::Call(f, args) => {
+                        lua_frame.call_meta_function(mc, f, &args, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::IDivCR { dest, left, right } => {
-                let left = current_function.0.proto.constants[left.0 as usize].to_value();
+                let left = constants[left.0 as usize].to_value();
                 let right = registers.stack_frame[right.0 as usize];
-                registers.stack_frame[dest.0 as usize] = left
-                    .floor_divide(right)
-                    .ok_or(BinaryOperatorError::FloorDivide)?;
+                match meta_ops::floor_divide(left, right)? {
+                    MetaResult::Value(v) => {
+                        registers.stack_frame[dest.0 as usize] = v;
+                    }
+                    MetaResult::Call(f, args) => {
+                        lua_frame.call_meta_function(mc, f, &args, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::IDivCC { dest, left, right } => {
-                let left = current_function.0.proto.constants[left.0 as usize].to_value();
-                let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                registers.stack_frame[dest.0 as usize] = left
-                    .floor_divide(right)
-                    .ok_or(BinaryOperatorError::FloorDivide)?;
+                let left = constants[left.0 as usize].to_value();
+                let right = constants[right.0 as usize].to_value();
+                match meta_ops::floor_divide(left, right)? {
+                    MetaResult::Value(v) => {
+                        registers.stack_frame[dest.0 as usize] = v;
+                    }
+                    MetaResult::Call(f, args) => {
+                        lua_frame.call_meta_function(mc, f, &args, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::ModRR { dest, left, right } => {
                 let left = registers.stack_frame[left.0 as usize];
                 let right = registers.stack_frame[right.0 as usize];
-                registers.stack_frame[dest.0 as usize] =
-                    left.modulo(right).ok_or(BinaryOperatorError::Modulo)?;
+                match meta_ops::modulo(left, right)? {
+                    MetaResult::Value(v) => {
+                        registers.stack_frame[dest.0 as usize] = v;
+                    }
+                    MetaResult::Call(f, args) => {
+                        lua_frame.call_meta_function(mc, f, &args, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::ModRC { dest, left, right } => {
                 let left = registers.stack_frame[left.0 as usize];
-                let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                registers.stack_frame[dest.0 as usize] =
-                    left.modulo(right).ok_or(BinaryOperatorError::Modulo)?;
+                let right = constants[right.0 as usize].to_value();
+                match meta_ops::modulo(left, right)? {
+                    MetaResult::Value(v) => {
+                        registers.stack_frame[dest.0 as usize] = v;
+                    }
+                    MetaResult::Call(f, args) => {
+                        lua_frame.call_meta_function(mc, f, &args, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::ModCR { dest, left, right } => {
-                let left = current_function.0.proto.constants[left.0 as usize].to_value();
+                let left = constants[left.0 as usize].to_value();
                 let right = registers.stack_frame[right.0 as usize];
-                registers.stack_frame[dest.0 as usize] =
-                    left.modulo(right).ok_or(BinaryOperatorError::Modulo)?;
+                match meta_ops::modulo(left, right)? {
+                    MetaResult::Value(v) => {
+                        registers.stack_frame[dest.0 as usize] = v;
+                    }
+                    MetaResult::Call(f, args) => {
+                        lua_frame.call_meta_function(mc, f, &args, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::ModCC { dest, left, right } => {
-                let left = current_function.0.proto.constants[left.0 as usize].to_value();
-                let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                registers.stack_frame[dest.0 as usize] =
-                    left.modulo(right).ok_or(BinaryOperatorError::Modulo)?;
+                let left = constants[left.0 as usize].to_value();
+                let right = constants[right.0 as usize].to_value();
+                match meta_ops::modulo(left, right)? {
+                    MetaResult::Value(v) => {
+                        registers.stack_frame[dest.0 as usize] = v;
+                    }
+                    MetaResult::Call(f, args) => {
+                        lua_frame.call_meta_function(mc, f, &args, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::PowRR { dest, left, right } => {
                 let left = registers.stack_frame[left.0 as usize];
                 let right = registers.stack_frame[right.0 as usize];
-                registers.stack_frame[dest.0 as usize] = left
-                    .exponentiate(right)
-                    .ok_or(BinaryOperatorError::Exponentiate)?;
+                match meta_ops::exponentiate(left, right)? {
+                    MetaResult::Value(v) => {
+                        registers.stack_frame[dest.0 as usize] = v;
+                    }
+                    MetaResult::Call(f, args) => {
+                        lua_frame.call_meta_function(mc, f, &args, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::PowRC { dest, left, right } => {
                 let left = registers.stack_frame[left.0 as usize];
-                let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                registers.stack_frame[dest.0 as usize] = left
-                    .exponentiate(right)
-                    .ok_or(BinaryOperatorError::Exponentiate)?;
+                let right = constants[right.0 as usize].to_value();
+                match meta_ops::exponentiate(left, right)? {
+                    MetaResult::Value(v) => {
+                        registers.stack_frame[dest.0 as usize] = v;
+                    }
+                    MetaResult::Call(f, args) => {
+                        lua_frame.call_meta_function(mc, f, &args, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::PowCR { dest, left, right } => {
-                let left = current_function.0.proto.constants[left.0 as usize].to_value();
+                let left = constants[left.0 as usize].to_value();
                 let right = registers.stack_frame[right.0 as usize];
-                registers.stack_frame[dest.0 as usize] = left
-                    .exponentiate(right)
-                    .ok_or(BinaryOperatorError::Exponentiate)?;
+                match meta_ops::exponentiate(left, right)? {
+                    MetaResult::Value(v) => {
+                        registers.stack_frame[dest.0 as usize] = v;
+                    }
+                    MetaResult::Call(f, args) => {
+                        lua_frame.call_meta_function(mc, f, &args, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::PowCC { dest, left, right } => {
-                let left = current_function.0.proto.constants[left.0 as usize].to_value();
-                let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                registers.stack_frame[dest.0 as usize] = left
-                    .exponentiate(right)
-                    .ok_or(BinaryOperatorError::Exponentiate)?;
+                let left = constants[left.0 as usize].to_value();
+                let right = constants[right.0 as usize].to_value();
+                match meta_ops::exponentiate(left, right)? {
+                    MetaResult::Value(v) => {
+                        registers.stack_frame[dest.0 as usize] = v;
+                    }
+                    MetaResult::Call(f, args) => {
+                        lua_frame.call_meta_function(mc, f, &args, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::BitAndRR { dest, left, right } => {
@@ -731,21 +1377,21 @@ pub(crate) fn run_vm<'gc>(
 
             OpCode::BitAndRC { dest, left, right } => {
                 let left = registers.stack_frame[left.0 as usize];
-                let right = current_function.0.proto.constants[right.0 as usize].to_value();
+                let right = constants[right.0 as usize].to_value();
                 registers.stack_frame[dest.0 as usize] =
                     left.bitwise_and(right).ok_or(BinaryOperatorError::BitAnd)?;
             }
 
             OpCode::BitAndCR { dest, left, right } => {
-                let left = current_function.0.proto.constants[left.0 as usize].to_value();
+                let left = constants[left.0 as usize].to_value();
                 let right = registers.stack_frame[right.0 as usize];
                 registers.stack_frame[dest.0 as usize] =
                     left.bitwise_and(right).ok_or(BinaryOperatorError::BitAnd)?;
             }
 
             OpCode::BitAndCC { dest, left, right } => {
-                let left = current_function.0.proto.constants[left.0 as usize].to_value();
-                let right = current_function.0.proto.constants[right.0 as usize].to_value();
+                let left = constants[left.0 as usize].to_value();
+                let right = constants[right.0 as usize].to_value();
                 registers.stack_frame[dest.0 as usize] =
                     left.bitwise_and(right).ok_or(BinaryOperatorError::BitAnd)?;
             }
@@ -759,21 +1405,21 @@ pub(crate) fn run_vm<'gc>(
 
             OpCode::BitOrRC { dest, left, right } => {
                 let left = registers.stack_frame[left.0 as usize];
-                let right = current_function.0.proto.constants[right.0 as usize].to_value();
+                let right = constants[right.0 as usize].to_value();
                 registers.stack_frame[dest.0 as usize] =
                     left.bitwise_or(right).ok_or(BinaryOperatorError::BitOr)?;
             }
 
             OpCode::BitOrCR { dest, left, right } => {
-                let left = current_function.0.proto.constants[left.0 as usize].to_value();
+                let left = constants[left.0 as usize].to_value();
                 let right = registers.stack_frame[right.0 as usize];
                 registers.stack_frame[dest.0 as usize] =
                     left.bitwise_or(right).ok_or(BinaryOperatorError::BitOr)?;
             }
 
             OpCode::BitOrCC { dest, left, right } => {
-                let left = current_function.0.proto.constants[left.0 as usize].to_value();
-                let right = current_function.0.proto.constants[right.0 as usize].to_value();
+                let left = constants[left.0 as usize].to_value();
+                let right = constants[right.0 as usize].to_value();
                 registers.stack_frame[dest.0 as usize] =
                     left.bitwise_or(right).ok_or(BinaryOperatorError::BitOr)?;
             }
@@ -787,21 +1433,21 @@ pub(crate) fn run_vm<'gc>(
 
             OpCode::BitXorRC { dest, left, right } => {
                 let left = registers.stack_frame[left.0 as usize];
-                let right = current_function.0.proto.constants[right.0 as usize].to_value();
+                let right = constants[right.0 as usize].to_value();
                 registers.stack_frame[dest.0 as usize] =
                     left.bitwise_xor(right).ok_or(BinaryOperatorError::BitXor)?;
             }
 
             OpCode::BitXorCR { dest, left, right } => {
-                let left = current_function.0.proto.constants[left.0 as usize].to_value();
+                let left = constants[left.0 as usize].to_value();
                 let right = registers.stack_frame[right.0 as usize];
                 registers.stack_frame[dest.0 as usize] =
                     left.bitwise_xor(right).ok_or(BinaryOperatorError::BitXor)?;
             }
 
             OpCode::BitXorCC { dest, left, right } => {
-                let left = current_function.0.proto.constants[left.0 as usize].to_value();
-                let right = current_function.0.proto.constants[right.0 as usize].to_value();
+                let left = constants[left.0 as usize].to_value();
+                let right = constants[right.0 as usize].to_value();
                 registers.stack_frame[dest.0 as usize] =
                     left.bitwise_xor(right).ok_or(BinaryOperatorError::BitXor)?;
             }
@@ -816,14 +1462,14 @@ pub(crate) fn run_vm<'gc>(
 
             OpCode::ShiftLeftRC { dest, left, right } => {
                 let left = registers.stack_frame[left.0 as usize];
-                let right = current_function.0.proto.constants[right.0 as usize].to_value();
+                let right = constants[right.0 as usize].to_value();
                 registers.stack_frame[dest.0 as usize] = left
                     .shift_left(right)
                     .ok_or(BinaryOperatorError::ShiftLeft)?;
             }
 
             OpCode::ShiftLeftCR { dest, left, right } => {
-                let left = current_function.0.proto.constants[left.0 as usize].to_value();
+                let left = constants[left.0 as usize].to_value();
                 let right = registers.stack_frame[right.0 as usize];
                 registers.stack_frame[dest.0 as usize] = left
                     .shift_left(right)
@@ -831,8 +1477,8 @@ pub(crate) fn run_vm<'gc>(
             }
 
             OpCode::ShiftLeftCC { dest, left, right } => {
-                let left = current_function.0.proto.constants[left.0 as usize].to_value();
-                let right = current_function.0.proto.constants[right.0 as usize].to_value();
+                let left = constants[left.0 as usize].to_value();
+                let right = constants[right.0 as usize].to_value();
                 registers.stack_frame[dest.0 as usize] = left
                     .shift_left(right)
                     .ok_or(BinaryOperatorError::ShiftLeft)?;
@@ -848,14 +1494,14 @@ pub(crate) fn run_vm<'gc>(
 
             OpCode::ShiftRightRC { dest, left, right } => {
                 let left = registers.stack_frame[left.0 as usize];
-                let right = current_function.0.proto.constants[right.0 as usize].to_value();
+                let right = constants[right.0 as usize].to_value();
                 registers.stack_frame[dest.0 as usize] = left
                     .shift_right(right)
                     .ok_or(BinaryOperatorError::ShiftRight)?;
             }
 
             OpCode::ShiftRightCR { dest, left, right } => {
-                let left = current_function.0.proto.constants[left.0 as usize].to_value();
+                let left = constants[left.0 as usize].to_value();
                 let right = registers.stack_frame[right.0 as usize];
                 registers.stack_frame[dest.0 as usize] = left
                     .shift_right(right)
@@ -863,34 +1509,24 @@ pub(crate) fn run_vm<'gc>(
             }
 
             OpCode::ShiftRightCC { dest, left, right } => {
-                let left = current_function.0.proto.constants[left.0 as usize].to_value();
-                let right = current_function.0.proto.constants[right.0 as usize].to_value();
+                let left = constants[left.0 as usize].to_value();
+                let right = constants[right.0 as usize].to_value();
                 registers.stack_frame[dest.0 as usize] = left
                     .shift_right(right)
                     .ok_or(BinaryOperatorError::ShiftRight)?;
             }
         }
 
+        instructions -= 1;
         if instructions == 0 {
+            *registers.pc = pc;
             break;
-        } else {
-            instructions -= 1
         }
     }
 
     Ok(instructions)
 }
 
-fn get_table<'gc>(value: Value<'gc>) -> Result<Table<'gc>, TypeError> {
-    match value {
-        Value::Table(t) => Ok(t),
-        val => Err(TypeError {
-            expected: "table",
-            found: val.type_name(),
-        }),
-    }
-}
-
 fn add_offset(pc: usize, offset: i16) -> usize {
     if offset > 0 {
         pc.checked_add(offset as usize).unwrap()
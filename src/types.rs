@@ -27,6 +27,47 @@ pub struct UpValueIndex(pub u8);
 #[collect(require_static)]
 pub struct PrototypeIndex(pub u8);
 
+/// A one byte size hint in the same "floating point byte" encoding that PUC-Rio Lua uses for
+/// `NEWTABLE`: `eeeeexxx` stands for `(1xxx) * 2^(eeeee - 1)` when `eeeee` is non-zero, and for
+/// `xxx` otherwise.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Collect)]
+#[collect(require_static)]
+pub struct SizeHint(pub u8);
+
+impl SizeHint {
+    /// Encodes the smallest representable hint that is at least `size`, saturating at the largest
+    /// representable hint.
+    pub fn new(size: usize) -> SizeHint {
+        if size < 8 {
+            return SizeHint(size as u8);
+        }
+        let mut e = 0;
+        let mut x = size;
+        while x >= 8 << 4 {
+            x = (x + 0xf) >> 4;
+            e += 4;
+        }
+        while x >= 8 << 1 {
+            x = (x + 1) >> 1;
+            e += 1;
+        }
+        if e + 1 > 0x1f {
+            SizeHint(0xff)
+        } else {
+            SizeHint((((e + 1) << 3) | (x - 8)) as u8)
+        }
+    }
+
+    pub fn to_usize(self) -> usize {
+        let e = (self.0 >> 3) & 0x1f;
+        if e == 0 {
+            self.0 as usize
+        } else {
+            ((self.0 as usize & 7) + 8) << (e - 1)
+        }
+    }
+}
+
 /// A one byte Option value that can either be Some(0-254) or None
 #[derive(Copy, Clone, Eq, PartialEq, Collect)]
 #[collect(require_static)]
@@ -0,0 +1,156 @@
+use std::any::{Any, TypeId};
+use std::cell::{Ref, RefMut};
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::mem;
+
+use gc_arena::{Collect, GcCell, MutationContext, StaticCollect};
+
+use crate::Table;
+
+/// A Rust type that can be held by userdata created with `AnyUserData::new_typed`, which gives all
+/// userdata of the type the same metatable.
+pub trait UserData: 'static {
+    /// Fills in the metatable shared by all userdata of this type, for example with an `__index`
+    /// table of methods.  This is called once per Lua instance, when userdata of this type is first
+    /// created.
+    fn metatable<'gc>(_mc: MutationContext<'gc, '_>, _metatable: Table<'gc>) {}
+}
+
+/// A Lua userdata value, holding arbitrary `'static` Rust data along with an optional metatable.
+/// The held data is dropped when the userdata is garbage collected.
+#[derive(Copy, Clone, Collect)]
+#[collect(require_copy)]
+pub struct AnyUserData<'gc>(pub GcCell<'gc, UserDataState<'gc>>);
+
+#[derive(Collect)]
+#[collect(empty_drop)]
+pub struct UserDataState<'gc> {
+    data: StaticCollect<Box<dyn Any>>,
+    metatable: Option<Table<'gc>>,
+}
+
+impl<'gc> fmt::Debug for AnyUserData<'gc> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_tuple("AnyUserData")
+            .field(&self.0.as_ptr())
+            .finish()
+    }
+}
+
+impl<'gc> PartialEq for AnyUserData<'gc> {
+    fn eq(&self, other: &AnyUserData<'gc>) -> bool {
+        GcCell::ptr_eq(self.0, other.0)
+    }
+}
+
+impl<'gc> Eq for AnyUserData<'gc> {}
+
+impl<'gc> Hash for AnyUserData<'gc> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.as_ptr().hash(state);
+    }
+}
+
+impl<'gc> AnyUserData<'gc> {
+    pub fn new<T: 'static>(mc: MutationContext<'gc, '_>, data: T) -> AnyUserData<'gc> {
+        AnyUserData(GcCell::allocate(
+            mc,
+            UserDataState {
+                data: StaticCollect(Box::new(data)),
+                metatable: None,
+            },
+        ))
+    }
+
+    /// Creates userdata holding a `UserData` type, with the metatable shared by all userdata of
+    /// that type, usually from `Root::userdata_metatables`.
+    pub fn new_typed<T: UserData>(
+        mc: MutationContext<'gc, '_>,
+        metatables: UserDataMetatables<'gc>,
+        data: T,
+    ) -> AnyUserData<'gc> {
+        let userdata = AnyUserData::new(mc, data);
+        userdata.set_metatable(mc, Some(metatables.get::<T>(mc)));
+        userdata
+    }
+
+    /// Whether the held data is of type `T`.
+    pub fn is<T: 'static>(&self) -> bool {
+        self.0.read().data.0.is::<T>()
+    }
+
+    /// Borrows the held data if it is of type `T`.
+    ///
+    /// Panics if the data is currently mutably borrowed.
+    pub fn borrow<T: 'static>(&self) -> Option<Ref<'_, T>> {
+        let state = self.0.read();
+        if state.data.0.is::<T>() {
+            Some(Ref::map(state, |state| {
+                state.data.0.downcast_ref::<T>().unwrap()
+            }))
+        } else {
+            None
+        }
+    }
+
+    /// Mutably borrows the held data if it is of type `T`.
+    ///
+    /// Panics if the data is currently borrowed.
+    pub fn borrow_mut<T: 'static>(&self, mc: MutationContext<'gc, '_>) -> Option<RefMut<'_, T>> {
+        let state = self.0.write(mc);
+        if state.data.0.is::<T>() {
+            Some(RefMut::map(state, |state| {
+                state.data.0.downcast_mut::<T>().unwrap()
+            }))
+        } else {
+            None
+        }
+    }
+
+    pub fn metatable(&self) -> Option<Table<'gc>> {
+        self.0.read().metatable
+    }
+
+    /// Sets the metatable for this userdata, returning the previous metatable.
+    pub fn set_metatable(
+        &self,
+        mc: MutationContext<'gc, '_>,
+        metatable: Option<Table<'gc>>,
+    ) -> Option<Table<'gc>> {
+        mem::replace(&mut self.0.write(mc).metatable, metatable)
+    }
+}
+
+/// The metatables shared by the userdata of each `UserData` type in a Lua instance, created as they
+/// are needed.
+#[derive(Copy, Clone, Collect)]
+#[collect(require_copy)]
+pub struct UserDataMetatables<'gc>(GcCell<'gc, HashMap<UserDataType, Table<'gc>>>);
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Collect)]
+#[collect(require_static)]
+struct UserDataType(TypeId);
+
+impl<'gc> UserDataMetatables<'gc> {
+    pub fn new(mc: MutationContext<'gc, '_>) -> UserDataMetatables<'gc> {
+        UserDataMetatables(GcCell::allocate(mc, HashMap::new()))
+    }
+
+    /// Returns the metatable for userdata of type `T`, calling `UserData::metatable` to fill it in
+    /// if it does not exist yet.
+    pub fn get<T: UserData>(self, mc: MutationContext<'gc, '_>) -> Table<'gc> {
+        let key = UserDataType(TypeId::of::<T>());
+        if let Some(&metatable) = self.0.read().get(&key) {
+            return metatable;
+        }
+
+        // The metatable is registered first, so that filling it in may create userdata of the same
+        // type
+        let metatable = Table::new(mc);
+        self.0.write(mc).insert(key, metatable);
+        T::metatable(mc, metatable);
+        metatable
+    }
+}
@@ -1,10 +1,14 @@
+use std::cmp::Ordering;
 use std::{f64, i64, io};
 
 use gc_arena::{Collect, Gc, GcCell, MutationContext};
+use gc_sequence::{Sequence, SequenceExt, SequenceResultExt};
+use rustc_hash::FxHashSet;
 
 use crate::{
-    lexer::{read_float, read_hex_float},
-    Callback, Closure, String, Table, Thread,
+    lexer::{is_reserved_word, read_float, read_hex_float},
+    AnyUserData, Callback, Closure, Error, FromMultiValue, Root, String, Table, Thread,
+    ThreadSequence, ToMultiValue,
 };
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Collect)]
@@ -14,6 +18,40 @@ pub enum Function<'gc> {
     Callback(Callback<'gc>),
 }
 
+impl<'gc> Function<'gc> {
+    /// Returns a sequence calling this function with the given arguments on a new thread, so that
+    /// it may be called whatever state the main thread is in.
+    pub fn call(
+        self,
+        mc: MutationContext<'gc, '_>,
+        root: Root<'gc>,
+        args: &[Value<'gc>],
+    ) -> ThreadSequence<'gc> {
+        let thread = Thread::new(mc, root.string_metatable, false);
+        thread.set_finalizers(mc, root.finalizers);
+        ThreadSequence::call_function(mc, thread, self, args).expect("new thread is not stopped")
+    }
+
+    /// Like `Function::call`, but converts the arguments with `ToMultiValue` and the results with
+    /// `FromMultiValue`.
+    pub fn call_typed<A, R>(
+        self,
+        mc: MutationContext<'gc, '_>,
+        root: Root<'gc>,
+        args: A,
+    ) -> Result<Box<dyn Sequence<'gc, Output = Result<R, Error<'gc>>> + 'gc>, Error<'gc>>
+    where
+        A: ToMultiValue<'gc>,
+        R: 'gc + FromMultiValue<'gc>,
+    {
+        let args = args.to_multi_value(mc)?;
+        Ok(self
+            .call(mc, root, &args)
+            .and_then(|mc, res| R::from_multi_value(mc, res).map_err(|err| *err.error))
+            .boxed())
+    }
+}
+
 #[derive(Debug, Copy, Clone, Collect)]
 #[collect(require_copy)]
 pub enum Value<'gc> {
@@ -25,6 +63,7 @@ pub enum Value<'gc> {
     Table(Table<'gc>),
     Function(Function<'gc>),
     Thread(Thread<'gc>),
+    UserData(AnyUserData<'gc>),
 }
 
 impl<'gc> PartialEq for Value<'gc> {
@@ -44,7 +83,7 @@ impl<'gc> PartialEq for Value<'gc> {
             (Value::Number(a), Value::Integer(b)) => b as f64 == a,
             (Value::Number(_), _) => false,
 
-            (Value::String(a), Value::String(b)) => a == b,
+            (Value::String(a), Value::String(b)) => a.equals(b),
             (Value::String(_), _) => false,
 
             (Value::Table(a), Value::Table(b)) => a == b,
@@ -55,6 +94,9 @@ impl<'gc> PartialEq for Value<'gc> {
 
             (Value::Thread(a), Value::Thread(b)) => a == b,
             (Value::Thread(_), _) => false,
+
+            (Value::UserData(a), Value::UserData(b)) => a == b,
+            (Value::UserData(_), _) => false,
         }
     }
 }
@@ -69,6 +111,7 @@ impl<'gc> Value<'gc> {
             Value::Table(_) => "table",
             Value::Function(_) => "function",
             Value::Thread(_) => "thread",
+            Value::UserData(_) => "userdata",
         }
     }
 
@@ -81,47 +124,38 @@ impl<'gc> Value<'gc> {
         }
     }
 
+    /// Interprets Numbers, Integers, and Strings as a Number or an Integer, if possible.  Strings
+    /// are converted following the same rules as Lua numerals, ignoring any leading or trailing
+    /// whitespace, and become an Integer if they are written as one.
+    pub fn to_numeric(self) -> Option<Value<'gc>> {
+        match self {
+            Value::Integer(_) | Value::Number(_) => Some(self),
+            Value::String(a) => read_numeric(&a),
+            _ => None,
+        }
+    }
+
     /// Interprets Numbers, Integers, and Strings as a Number, if possible.
     pub fn to_number(self) -> Option<f64> {
-        match self {
+        match self.to_numeric()? {
             Value::Integer(a) => Some(a as f64),
             Value::Number(a) => Some(a),
-            Value::String(a) => {
-                if let Some(f) = read_hex_float(&a) {
-                    Some(f)
-                } else {
-                    read_float(&a)
-                }
-            }
             _ => None,
         }
     }
 
     /// Interprets Numbers, Integers, and Strings as an Integer, if possible.
     pub fn to_integer(self) -> Option<i64> {
-        match self {
+        match self.to_numeric()? {
             Value::Integer(a) => Some(a),
+            // Checked against the range first, as `as` saturates floats out of range
             Value::Number(a) => {
-                if ((a as i64) as f64) == a {
+                if a >= i64::MIN as f64 && a < -(i64::MIN as f64) && a.floor() == a {
                     Some(a as i64)
                 } else {
                     None
                 }
             }
-            Value::String(a) => match if let Some(f) = read_hex_float(&a) {
-                Some(f)
-            } else {
-                read_float(&a)
-            } {
-                Some(f) => {
-                    if ((f as i64) as f64) == f {
-                        Some(f as i64)
-                    } else {
-                        None
-                    }
-                }
-                _ => None,
-            },
             _ => None,
         }
     }
@@ -141,28 +175,28 @@ impl<'gc> Value<'gc> {
     }
 
     // Mathematical operators
+    //
+    // Strings are converted with `to_numeric`, so a string written as an integer is treated as an
+    // Integer, like in PUC-Rio Lua.
 
     pub fn add(self, other: Value<'gc>) -> Option<Value<'gc>> {
-        if let (Value::Integer(a), Value::Integer(b)) = (self, other) {
-            Some(Value::Integer(a.wrapping_add(b)))
-        } else {
-            Some(Value::Number(self.to_number()? + other.to_number()?))
+        match (self.to_numeric()?, other.to_numeric()?) {
+            (Value::Integer(a), Value::Integer(b)) => Some(Value::Integer(a.wrapping_add(b))),
+            (a, b) => Some(Value::Number(a.to_number()? + b.to_number()?)),
         }
     }
 
     pub fn subtract(self, other: Value<'gc>) -> Option<Value<'gc>> {
-        if let (Value::Integer(a), Value::Integer(b)) = (self, other) {
-            Some(Value::Integer(a.wrapping_sub(b)))
-        } else {
-            Some(Value::Number(self.to_number()? - other.to_number()?))
+        match (self.to_numeric()?, other.to_numeric()?) {
+            (Value::Integer(a), Value::Integer(b)) => Some(Value::Integer(a.wrapping_sub(b))),
+            (a, b) => Some(Value::Number(a.to_number()? - b.to_number()?)),
         }
     }
 
     pub fn multiply(self, other: Value<'gc>) -> Option<Value<'gc>> {
-        if let (Value::Integer(a), Value::Integer(b)) = (self, other) {
-            Some(Value::Integer(a.wrapping_mul(b)))
-        } else {
-            Some(Value::Number(self.to_number()? * other.to_number()?))
+        match (self.to_numeric()?, other.to_numeric()?) {
+            (Value::Integer(a), Value::Integer(b)) => Some(Value::Integer(a.wrapping_mul(b))),
+            (a, b) => Some(Value::Number(a.to_number()? * b.to_number()?)),
         }
     }
 
@@ -174,31 +208,43 @@ impl<'gc> Value<'gc> {
     /// This operation returns an Integer only if both arguments are Integers.  Rounding is towards
     /// negative infinity.
     pub fn floor_divide(self, other: Value<'gc>) -> Option<Value<'gc>> {
-        if let (Value::Integer(a), Value::Integer(b)) = (self, other) {
-            if b == 0 {
-                None
-            } else {
-                Some(Value::Integer(a.wrapping_div(b)))
+        match (self.to_numeric()?, other.to_numeric()?) {
+            (Value::Integer(a), Value::Integer(b)) => {
+                if b == 0 {
+                    None
+                } else {
+                    let quotient = a.wrapping_div(b);
+                    if a.wrapping_rem(b) != 0 && (a < 0) != (b < 0) {
+                        Some(Value::Integer(quotient - 1))
+                    } else {
+                        Some(Value::Integer(quotient))
+                    }
+                }
             }
-        } else {
-            Some(Value::Number(
-                (self.to_number()? / other.to_number()?).floor(),
-            ))
+            (a, b) => Some(Value::Number((a.to_number()? / b.to_number()?).floor())),
         }
     }
 
     /// Computes the Lua modulus (`%`) operator.  This is unlike Rust's `%` operator which computes
     /// the remainder.
     pub fn modulo(self, other: Value<'gc>) -> Option<Value<'gc>> {
-        if let (Value::Integer(a), Value::Integer(b)) = (self, other) {
-            if b == 0 {
-                None
-            } else {
-                Some(Value::Integer(((a % b) + b) % b))
+        match (self.to_numeric()?, other.to_numeric()?) {
+            (Value::Integer(a), Value::Integer(b)) => {
+                if b == 0 {
+                    None
+                } else {
+                    let remainder = a.wrapping_rem(b);
+                    if remainder != 0 && (remainder < 0) != (b < 0) {
+                        Some(Value::Integer(remainder + b))
+                    } else {
+                        Some(Value::Integer(remainder))
+                    }
+                }
+            }
+            (a, b) => {
+                let (a, b) = (a.to_number()?, b.to_number()?);
+                Some(Value::Number(((a % b) + b) % b))
             }
-        } else {
-            let (a, b) = (self.to_number()?, other.to_number()?);
-            Some(Value::Number(((a % b) + b) % b))
         }
     }
 
@@ -208,7 +254,7 @@ impl<'gc> Value<'gc> {
     }
 
     pub fn negate(self) -> Option<Value<'gc>> {
-        match self {
+        match self.to_numeric()? {
             Value::Integer(a) => Some(Value::Integer(a.wrapping_neg())),
             Value::Number(a) => Some(Value::Number(-a)),
             _ => None,
@@ -270,14 +316,219 @@ impl<'gc> Value<'gc> {
             Value::Nil => write!(w, "nil"),
             Value::Boolean(b) => write!(w, "{}", b),
             Value::Integer(i) => write!(w, "{}", i),
-            Value::Number(f) => write!(w, "{}", f),
+            Value::Number(f) => w.write_all(number_to_string(f).as_bytes()),
             Value::String(s) => w.write_all(s.as_bytes()),
             Value::Table(t) => write!(w, "<table {:?}>", t.0.as_ptr()),
             Value::Function(Function::Closure(c)) => write!(w, "<function {:?}>", Gc::as_ptr(c.0)),
             Value::Function(Function::Callback(c)) => write!(w, "<function {:?}>", Gc::as_ptr(c.0)),
             Value::Thread(t) => write!(w, "<thread {:?}>", GcCell::as_ptr(t.0)),
+            Value::UserData(u) => write!(w, "<userdata {:?}>", GcCell::as_ptr(u.0)),
         }
     }
+
+    /// Compares two values structurally: tables are equal if they have the same keys with deeply
+    /// equal values, and other values are equal as they are for `==` without metamethods.  Keys are
+    /// matched by raw equality, so a table key only matches the same table.  Metatables are not
+    /// compared, and tables that contain themselves compare equal if their structure matches.
+    pub fn deep_eq(self, other: Value<'gc>) -> bool {
+        deep_eq(self, other, &mut FxHashSet::default())
+    }
+
+    /// Writes the value like `display`, except that strings are quoted and tables are written
+    /// with their contents, one entry per indented line with the keys sorted.  Metatables are not
+    /// consulted.  Tables nested more than `max_depth` levels deep are written as `{...}`, and a
+    /// table inside itself is written as `<cycle table 0x...>` where it recurs.
+    pub fn display_deep<W: io::Write>(self, mut w: W, max_depth: usize) -> Result<(), io::Error> {
+        display_deep(self, &mut w, max_depth, &mut Vec::new())
+    }
+}
+
+/// Converts a float to a string the way PUC-Rio Lua does, as the C format `%.14g` would, with `.0`
+/// appended to floats that would otherwise read back as integers.  Infinities are `inf` and
+/// `-inf`, and NaN is `nan` or `-nan` depending on its sign bit.
+pub(crate) fn number_to_string(n: f64) -> std::string::String {
+    const PRECISION: i32 = 14;
+
+    if n.is_nan() {
+        return if n.is_sign_negative() { "-nan" } else { "nan" }.to_owned();
+    } else if n.is_infinite() {
+        return if n < 0.0 { "-inf" } else { "inf" }.to_owned();
+    }
+
+    // Like `%g`, this uses the exponent form if the exponent after rounding to the precision is
+    // too small or too large, and the fixed form otherwise, both without trailing zeros
+    let exponent_form = format!("{:.*e}", PRECISION as usize - 1, n);
+    let e = exponent_form.find('e').unwrap();
+    let exponent: i32 = exponent_form[e + 1..].parse().unwrap();
+    let mut s = if exponent < -4 || exponent >= PRECISION {
+        let mantissa = trim_fraction(&exponent_form[..e]);
+        format!(
+            "{}e{}{:02}",
+            mantissa,
+            if exponent < 0 { '-' } else { '+' },
+            exponent.abs()
+        )
+    } else {
+        let fixed = format!("{:.*}", (PRECISION - 1 - exponent) as usize, n);
+        trim_fraction(&fixed).to_owned()
+    };
+
+    if s.bytes().all(|c| c == b'-' || c.is_ascii_digit()) {
+        s.push_str(".0");
+    }
+    s
+}
+
+// Removes trailing zeros after a decimal point, and the point itself if nothing follows it.
+fn trim_fraction(s: &str) -> &str {
+    if s.contains('.') {
+        s.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        s
+    }
+}
+
+// Pairs of tables already being compared are assumed to be equal, so that comparing cyclic
+// tables terminates.
+fn deep_eq<'gc>(
+    a: Value<'gc>,
+    b: Value<'gc>,
+    assumed: &mut FxHashSet<(Table<'gc>, Table<'gc>)>,
+) -> bool {
+    match (a, b) {
+        (Value::Table(a), Value::Table(b)) => {
+            if a == b || !assumed.insert((a, b)) {
+                return true;
+            }
+            let mut count = 0;
+            let mut key = Value::Nil;
+            while let Ok(Some((k, v))) = a.next(key) {
+                if !deep_eq(v, b.get(k), assumed) {
+                    return false;
+                }
+                count += 1;
+                key = k;
+            }
+            let mut key = Value::Nil;
+            while let Ok(Some((k, _))) = b.next(key) {
+                if count == 0 {
+                    return false;
+                }
+                count -= 1;
+                key = k;
+            }
+            count == 0
+        }
+        (a, b) => a == b,
+    }
+}
+
+fn display_deep<'gc, W: io::Write>(
+    value: Value<'gc>,
+    w: &mut W,
+    max_depth: usize,
+    stack: &mut Vec<Table<'gc>>,
+) -> Result<(), io::Error> {
+    match value {
+        Value::String(s) => display_quoted(w, s.as_bytes()),
+        Value::Table(t) => {
+            if stack.contains(&t) {
+                return write!(w, "<cycle table {:?}>", t.0.as_ptr());
+            }
+
+            let mut entries = Vec::new();
+            let mut key = Value::Nil;
+            while let Ok(Some((k, v))) = t.next(key) {
+                entries.push((k, v));
+                key = k;
+            }
+            if entries.is_empty() {
+                return write!(w, "{{}}");
+            }
+            if stack.len() >= max_depth {
+                return write!(w, "{{...}}");
+            }
+            entries.sort_by(|&(a, _), &(b, _)| compare_keys(a, b));
+
+            stack.push(t);
+            writeln!(w, "{{")?;
+            for (k, v) in entries {
+                for _ in 0..stack.len() {
+                    write!(w, "  ")?;
+                }
+                match k {
+                    Value::String(s) if is_name(s.as_bytes()) => w.write_all(s.as_bytes())?,
+                    k => {
+                        write!(w, "[")?;
+                        display_deep(k, w, max_depth, stack)?;
+                        write!(w, "]")?;
+                    }
+                }
+                write!(w, " = ")?;
+                display_deep(v, w, max_depth, stack)?;
+                writeln!(w, ",")?;
+            }
+            stack.pop();
+            for _ in 0..stack.len() {
+                write!(w, "  ")?;
+            }
+            write!(w, "}}")
+        }
+        value => value.display(w),
+    }
+}
+
+// Writes a string in double quotes, with the escapes that Lua would need to read it back.
+fn display_quoted<W: io::Write>(w: &mut W, s: &[u8]) -> Result<(), io::Error> {
+    write!(w, "\"")?;
+    for &b in s {
+        match b {
+            b'"' => write!(w, "\\\"")?,
+            b'\\' => write!(w, "\\\\")?,
+            b'\n' => write!(w, "\\n")?,
+            b'\r' => write!(w, "\\r")?,
+            b'\t' => write!(w, "\\t")?,
+            b if b < 0x20 || b == 0x7f => write!(w, "\\{:03}", b)?,
+            b => w.write_all(&[b])?,
+        }
+    }
+    write!(w, "\"")
+}
+
+// Whether a string key can be written as a bare name rather than in brackets.
+fn is_name(s: &[u8]) -> bool {
+    match s.first() {
+        Some(c) if c.is_ascii_alphabetic() || *c == b'_' => {}
+        _ => return false,
+    }
+    s.iter().all(|c| c.is_ascii_alphanumeric() || *c == b'_') && !is_reserved_word(s)
+}
+
+// Orders table keys with numbers first in numeric order, then strings, then booleans, then every
+// other type by name.
+fn compare_keys<'gc>(a: Value<'gc>, b: Value<'gc>) -> Ordering {
+    fn rank(value: Value) -> u8 {
+        match value {
+            Value::Integer(_) | Value::Number(_) => 0,
+            Value::String(_) => 1,
+            Value::Boolean(_) => 2,
+            _ => 3,
+        }
+    }
+
+    match (a, b) {
+        (Value::Integer(a), Value::Integer(b)) => a.cmp(&b),
+        (Value::String(a), Value::String(b)) => a.as_bytes().cmp(b.as_bytes()),
+        (Value::Boolean(a), Value::Boolean(b)) => a.cmp(&b),
+        _ => match (a.to_number(), b.to_number()) {
+            (Some(x), Some(y)) if rank(a) == 0 && rank(b) == 0 => {
+                x.partial_cmp(&y).unwrap_or(Ordering::Equal)
+            }
+            _ => rank(a)
+                .cmp(&rank(b))
+                .then_with(|| a.type_name().cmp(b.type_name())),
+        },
+    }
 }
 
 impl<'gc> From<bool> for Value<'gc> {
@@ -327,3 +578,78 @@ impl<'gc> From<Callback<'gc>> for Value<'gc> {
         Value::Function(Function::Callback(v))
     }
 }
+
+impl<'gc> From<AnyUserData<'gc>> for Value<'gc> {
+    fn from(v: AnyUserData<'gc>) -> Value<'gc> {
+        Value::UserData(v)
+    }
+}
+
+// Reads a string as an Integer or Number, like PUC-Rio Lua's `lua_stringtonumber`.  Decimal integers
+// that do not fit in an Integer are read as a Number, and hex integers wrap around.
+//
+// This is kept out of line so that arithmetic on numbers, which only reads strings when given one,
+// stays small enough to be inlined into the VM.
+#[inline(never)]
+fn read_numeric<'gc>(s: &[u8]) -> Option<Value<'gc>> {
+    let s = trim_whitespace(s);
+    // Rust also parses words like "inf" and "NaN" as floats, but Lua does not
+    if s.is_empty()
+        || !s
+            .iter()
+            .all(|&c| c.is_ascii_hexdigit() || b"+-.xXpP".contains(&c))
+    {
+        return None;
+    }
+
+    if let Some(i) = read_integer_wrapping(s) {
+        Some(Value::Integer(i))
+    } else if let Some(f) = read_hex_float(s) {
+        Some(Value::Number(f))
+    } else {
+        read_float(s).map(Value::Number)
+    }
+}
+
+fn read_integer_wrapping(s: &[u8]) -> Option<i64> {
+    let (is_neg, s) = match s.first() {
+        Some(b'-') => (true, &s[1..]),
+        Some(b'+') => (false, &s[1..]),
+        _ => (false, s),
+    };
+
+    if s.len() > 2 && s[0] == b'0' && (s[1] == b'x' || s[1] == b'X') {
+        let mut i: i64 = 0;
+        for &c in &s[2..] {
+            let d = (c as char).to_digit(16)?;
+            i = i.wrapping_mul(16).wrapping_add(d as i64);
+        }
+        Some(if is_neg { i.wrapping_neg() } else { i })
+    } else if !s.is_empty() {
+        let mut u: u64 = 0;
+        for &c in s {
+            let d = (c as char).to_digit(10)?;
+            u = u.checked_mul(10)?.checked_add(d as u64)?;
+        }
+        if is_neg && u <= i64::MAX as u64 + 1 {
+            Some((u as i64).wrapping_neg())
+        } else if u <= i64::MAX as u64 {
+            Some(u as i64)
+        } else {
+            None
+        }
+    } else {
+        None
+    }
+}
+
+/// Removes leading and trailing whitespace, as recognized by the C `isspace` function.
+pub(crate) fn trim_whitespace(s: &[u8]) -> &[u8] {
+    let is_space = |c: &u8| b" \t\n\x0b\x0c\r".contains(c);
+    let start = s.iter().position(|c| !is_space(c)).unwrap_or(s.len());
+    let end = s
+        .iter()
+        .rposition(|c| !is_space(c))
+        .map_or(start, |i| i + 1);
+    &s[start..end]
+}
@@ -0,0 +1,62 @@
+use gc_sequence as sequence;
+use luster::{Args, Callback, CallbackResult, Lua, StaticError, String, Table, Value};
+
+#[test]
+fn args() -> Result<(), StaticError> {
+    let mut lua = Lua::new();
+    lua.mutate(|mc, root| {
+        let callback = Callback::new_sequence(mc, |args| {
+            Ok(sequence::from_fn_with(args, |mc, args| {
+                let args = Args::new(mc, "fill", args);
+                let count = args.get::<i64>(0)?;
+                let table = args.opt::<Table>(1)?.unwrap_or_else(|| Table::new(mc));
+                let fill = args.check_string(2)?;
+                if count < 0 {
+                    return Err(args.error(0, "count must not be negative"));
+                }
+                for i in 1..=count {
+                    table.set(mc, i, fill)?;
+                }
+                Ok(CallbackResult::Return(vec![
+                    Value::Table(table),
+                    Value::Integer(args.len() as i64),
+                ]))
+            }))
+        });
+        root.globals
+            .set(mc, String::new_static(b"fill"), callback)
+            .unwrap();
+    });
+
+    assert_eq!(
+        lua.eval::<(i64, std::string::String, i64)>(
+            r#"
+                local t, n = fill(2, nil, 5)
+                return #t, t[2], n
+            "#
+        )?,
+        (2, "5".to_owned(), 3)
+    );
+    assert_eq!(
+        lua.eval::<(
+            std::string::String,
+            std::string::String,
+            std::string::String,
+            std::string::String
+        )>(
+            r#"
+                local function message(...)
+                    return select(2, pcall(fill, ...))
+                end
+                return message(), message(1, 2), message(1, {}), message(-1, {}, "x")
+            "#
+        )?,
+        (
+            "bad argument #1 to 'fill' (i64 expected, got no value)".to_owned(),
+            "bad argument #2 to 'fill' (table expected, got number)".to_owned(),
+            "bad argument #3 to 'fill' (string expected, got no value)".to_owned(),
+            "bad argument #1 to 'fill' (count must not be negative)".to_owned(),
+        )
+    );
+    Ok(())
+}
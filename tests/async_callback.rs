@@ -0,0 +1,160 @@
+use std::cell::Cell;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll, Waker};
+
+use gc_sequence::{self as sequence, Sequence, SequenceExt, SequenceResultExt};
+use luster::{
+    compile, Callback, Closure, Error, FromMultiValue, Function, Lua, Root, StaticError, String,
+    ThreadSequence, Variadic,
+};
+
+// A future that is pending until its flag is set, waking itself each time it is polled if
+// `wake_self` is true.
+struct Flag {
+    ready: Rc<Cell<bool>>,
+    wake_self: bool,
+    polls: Rc<Cell<usize>>,
+}
+
+impl Future for Flag {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<()> {
+        self.polls.set(self.polls.get() + 1);
+        if self.ready.get() {
+            Poll::Ready(())
+        } else {
+            if self.wake_self {
+                self.ready.set(true);
+                context.waker().wake_by_ref();
+            }
+            Poll::Pending
+        }
+    }
+}
+
+// Registers a `double` async callback which waits on a `Flag` future before returning twice its
+// argument, failing for negative arguments.  Unless the future wakes itself, the flag is shared by
+// every call.
+fn load_double<'gc>(
+    mc: gc_arena::MutationContext<'gc, '_>,
+    root: Root<'gc>,
+    ready: Rc<Cell<bool>>,
+    wake_self: bool,
+    polls: Rc<Cell<usize>>,
+) {
+    let double = Callback::new_async(mc, root, "double", move |_, n: i64| {
+        let ready = if wake_self {
+            Rc::new(Cell::new(false))
+        } else {
+            ready.clone()
+        };
+        let flag = Flag {
+            ready,
+            wake_self,
+            polls: polls.clone(),
+        };
+        Ok(async move {
+            flag.await;
+            if n < 0 {
+                Err(StaticError::RuntimeError("negative".to_owned()))
+            } else {
+                Ok(n * 2)
+            }
+        })
+    });
+    root.globals
+        .set(mc, String::new_static(b"double"), double)
+        .unwrap();
+}
+
+fn run<'gc>(
+    root: Root<'gc>,
+    source: &'static str,
+) -> Box<dyn Sequence<'gc, Output = Result<Vec<i64>, StaticError>> + 'gc> {
+    sequence::from_fn_with(root, move |mc, root| {
+        Ok(Closure::new(
+            mc,
+            compile(mc, root.interned_strings, "=test", source.as_bytes())?,
+            Some(root.globals),
+        )?)
+    })
+    .and_chain_with(root, |mc, root, closure| {
+        Ok(ThreadSequence::call_function(
+            mc,
+            root.main_thread,
+            Function::Closure(closure),
+            &[],
+        )?)
+    })
+    .and_then(|mc, res| {
+        Variadic::<i64>::from_multi_value(mc, res)
+            .map(|v| v.0)
+            .map_err(|err| *err.error)
+    })
+    .map_err(Error::to_static)
+    .boxed()
+}
+
+#[test]
+fn async_callback_blocking() -> Result<(), StaticError> {
+    let polls = Rc::new(Cell::new(0));
+    let mut lua = Lua::new();
+    {
+        let polls = polls.clone();
+        lua.mutate(move |mc, root| load_double(mc, root, Rc::new(Cell::new(false)), true, polls));
+    }
+
+    let res = lua.sequence(|root| {
+        run(
+            root,
+            r#"
+                local co = coroutine.wrap(function(n)
+                    coroutine.yield(double(n))
+                end)
+                local ok, err = pcall(double, -1)
+                assert(not ok and err == "negative")
+                return double(1), co(20)
+            "#,
+        )
+    })?;
+    assert_eq!(res, vec![2, 40]);
+    assert_eq!(polls.get(), 6);
+    Ok(())
+}
+
+#[test]
+fn resume_async() -> Result<(), StaticError> {
+    let ready = Rc::new(Cell::new(false));
+    let polls = Rc::new(Cell::new(0));
+    let mut lua = Lua::new();
+    {
+        let ready = ready.clone();
+        let polls = polls.clone();
+        lua.mutate(move |mc, root| load_double(mc, root, ready, false, polls));
+    }
+
+    let mut context = Context::from_waker(Waker::noop());
+    {
+        let mut future = lua.resume_async(|root| run(root, "return double(21)"));
+        assert!(Pin::new(&mut future).poll(&mut context).is_pending());
+        assert!(Pin::new(&mut future).poll(&mut context).is_pending());
+        ready.set(true);
+        match Pin::new(&mut future).poll(&mut context) {
+            Poll::Ready(res) => assert_eq!(res?, vec![42]),
+            Poll::Pending => panic!("future still pending"),
+        }
+    }
+    assert_eq!(polls.get(), 3);
+
+    // Dropping an unfinished future aborts the sequence, leaving `Lua` usable
+    ready.set(false);
+    {
+        let mut future = lua.resume_async(|root| run(root, "return double(1)"));
+        assert!(Pin::new(&mut future).poll(&mut context).is_pending());
+    }
+    assert_eq!(lua.eval::<i64>("return 1 + 1")?, 2);
+    Ok(())
+}
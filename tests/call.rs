@@ -0,0 +1,43 @@
+use gc_sequence::{self as sequence, SequenceExt, SequenceResultExt};
+use luster::{Error, Lua, StaticError, String, Table, Value};
+
+#[test]
+fn call_stashed() -> Result<(), StaticError> {
+    let mut lua = Lua::new();
+    lua.exec("function divmod(a, b) return a // b, a % b end")?;
+    let (divmod, not_function) = lua.mutate(|mc, root| {
+        (
+            root.registry
+                .stash(mc, root.globals.get(String::new_static(b"divmod"))),
+            root.registry.stash(mc, Value::Integer(1)),
+        )
+    });
+
+    assert_eq!(lua.call::<_, (i64, i64)>(&divmod, (17, 5))?, (3, 2));
+    assert!(lua.call::<_, i64>(&divmod, (1, 0)).is_err());
+    match lua.call::<_, ()>(&not_function, ()) {
+        Err(StaticError::TypeError(err)) => assert_eq!(err.found, "number"),
+        res => panic!("unexpected result {:?}", res),
+    }
+    Ok(())
+}
+
+#[test]
+fn call_typed() -> Result<(), StaticError> {
+    let mut lua = Lua::new();
+    lua.exec("function wrap(...) return {...} end")?;
+    let len = lua.sequence(|root| {
+        sequence::from_fn_with(root, |mc, root| {
+            match root.globals.get(String::new_static(b"wrap")) {
+                Value::Function(wrap) => wrap.call_typed(mc, root, (1, "two", true)),
+                _ => panic!("wrap is not a function"),
+            }
+        })
+        .flatten_ok()
+        .map_ok(|table: Table| table.length())
+        .map_err(Error::to_static)
+        .boxed()
+    })?;
+    assert_eq!(len, 3);
+    Ok(())
+}
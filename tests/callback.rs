@@ -1,7 +1,10 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
 use gc_sequence::{self as sequence, SequenceExt, SequenceResultExt};
 use luster::{
-    compile, Callback, CallbackResult, Closure, Error, Function, Lua, StaticError, String,
-    ThreadSequence, Value,
+    compile, Callback, CallbackResult, Closure, Error, Function, FunctionName, Hook, Lua,
+    StaticError, String, Table, ThreadSequence, Value,
 };
 
 #[test]
@@ -24,6 +27,7 @@ fn callback() -> Result<(), Box<StaticError>> {
                 compile(
                     mc,
                     root.interned_strings,
+                    "=test",
                     &br#"
                         local a, b, c = callback(1, 2)
                         return a == 1 and b == 2 and c == 42
@@ -68,6 +72,7 @@ fn tail_call_trivial_callback() -> Result<(), Box<StaticError>> {
                 compile(
                     mc,
                     root.interned_strings,
+                    "=test",
                     &br#"
                         return callback(1, 2)
                     "#[..],
@@ -95,3 +100,153 @@ fn tail_call_trivial_callback() -> Result<(), Box<StaticError>> {
 
     Ok(())
 }
+
+#[test]
+fn callback_call_stack() -> Result<(), Box<StaticError>> {
+    let mut lua = Lua::new();
+    lua.sequence(|root| {
+        sequence::from_fn_with(root, |mc, root| {
+            let callback = Callback::new_sequence_with_thread(mc, (), |_, thread, _| {
+                Ok(sequence::from_fn_with(thread, |mc, thread| {
+                    let call_stack = thread.call_stack();
+                    assert_eq!(call_stack.len(), 3);
+                    assert!(call_stack[0].closure.is_none());
+                    assert!(call_stack[0].location.is_none());
+                    assert_eq!(
+                        call_stack[0].name,
+                        Some(FunctionName::Field("callback".to_owned()))
+                    );
+                    assert_eq!(
+                        call_stack[1].location.as_ref().unwrap().to_string(),
+                        "test:3"
+                    );
+                    assert_eq!(
+                        call_stack[1].name,
+                        Some(FunctionName::Global("f".to_owned()))
+                    );
+                    assert_eq!(call_stack[2].to_string(), "test:6: in main chunk");
+                    Ok(CallbackResult::Return(vec![Value::String(String::new(
+                        mc,
+                        thread.traceback(1).as_bytes(),
+                    ))]))
+                }))
+            });
+            let table = Table::new(mc);
+            table.set(mc, String::new_static(b"callback"), callback)?;
+            root.globals.set(mc, String::new_static(b"t"), table)?;
+            Ok(())
+        })
+        .and_then_with(root, |mc, root, _| {
+            Ok(Closure::new(
+                mc,
+                compile(
+                    mc,
+                    root.interned_strings,
+                    "=test",
+                    &br#"
+                        function f()
+                            local traceback = t.callback()
+                            return traceback
+                        end
+                        local traceback = f()
+                        return traceback
+                    "#[..],
+                )?,
+                Some(root.globals),
+            )?)
+        })
+        .and_chain_with(root, |mc, root, closure| {
+            Ok(ThreadSequence::call_function(
+                mc,
+                root.main_thread,
+                Function::Closure(closure),
+                &[],
+            )?)
+        })
+        .map_ok(|b| match &b[..] {
+            [Value::String(traceback)] => assert_eq!(
+                traceback.as_bytes(),
+                &b"stack traceback:\n\ttest:3: in function 'f'\n\ttest:6: in main chunk"[..]
+            ),
+            _ => panic!("unexpected results {:?}", b),
+        })
+        .map_err(Error::to_static)
+        .boxed()
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn thread_hook() -> Result<(), Box<StaticError>> {
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let mut lua = Lua::new();
+    let hook_events = events.clone();
+    lua.sequence(move |root| {
+        sequence::from_fn_with(root, move |mc, root| {
+            let hook_events = hook_events.clone();
+            let function = Callback::new_immediate(mc, move |args| {
+                let event = match (&args[0], args.get(1)) {
+                    (Value::String(event), Some(Value::Integer(line))) => {
+                        format!(
+                            "{}:{}",
+                            std::str::from_utf8(event.as_bytes()).unwrap(),
+                            line
+                        )
+                    }
+                    (Value::String(event), _) => {
+                        std::str::from_utf8(event.as_bytes()).unwrap().to_owned()
+                    }
+                    _ => panic!("unexpected hook arguments {:?}", args),
+                };
+                hook_events.borrow_mut().push(event);
+                Ok(CallbackResult::Return(Vec::new()))
+            });
+            root.main_thread.set_hook(
+                mc,
+                Some(Hook {
+                    function: Function::Callback(function),
+                    call: true,
+                    returns: true,
+                    line: true,
+                    count: 0,
+                }),
+            );
+            Ok(root)
+        })
+        .and_then(|mc, root| {
+            Ok(Closure::new(
+                mc,
+                compile(
+                    mc,
+                    root.interned_strings,
+                    "=test",
+                    &br#"
+                        local function f()
+                            return 1
+                        end
+                        return f()
+                    "#[..],
+                )?,
+                Some(root.globals),
+            )?)
+        })
+        .and_chain_with(root, |mc, root, closure| {
+            Ok(ThreadSequence::call_function(
+                mc,
+                root.main_thread,
+                Function::Closure(closure),
+                &[],
+            )?)
+        })
+        .map_ok(|_| ())
+        .map_err(Error::to_static)
+        .boxed()
+    })?;
+
+    assert_eq!(
+        *events.borrow(),
+        vec!["call", "line:2", "line:5", "call", "line:3", "return"]
+    );
+    Ok(())
+}
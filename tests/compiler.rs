@@ -0,0 +1,23 @@
+use luster::{compile, Lua};
+
+fn stack_size(source: &str) -> u16 {
+    let mut lua = Lua::new();
+    lua.mutate(|mc, root| {
+        let proto = compile(mc, root.interned_strings, "=test", source.as_bytes()).unwrap();
+        proto.stack_size
+    })
+}
+
+#[test]
+fn stack_sizes() {
+    assert_eq!(stack_size("local a, b = 1, 2 a = a * b + a * (b - a)"), 4);
+    assert_eq!(
+        stack_size("local a = 1 for i = 1, 10 do a = a + i * 2 end"),
+        6
+    );
+    assert_eq!(stack_size("local a, b = 'a', 'b' a = a .. b .. a"), 5);
+    assert_eq!(
+        stack_size("local a = 1 local b = a + 1 local c = b + 1 return a + b + c"),
+        4
+    );
+}
@@ -0,0 +1,238 @@
+use std::collections::HashMap;
+
+use gc_sequence::{self as sequence, SequenceExt, SequenceResultExt};
+use luster::{
+    compile, Callback, Closure, Error, FromLua, FromMultiValue, Function, Lua, StaticError, String,
+    Table, ThreadSequence, ToLua, ToMultiValue, Value, Variadic,
+};
+
+#[test]
+fn numbers() {
+    let mut lua = Lua::new();
+    lua.mutate(|mc, _| {
+        assert_eq!(5u8.to_lua(mc).unwrap(), Value::Integer(5));
+        assert_eq!(
+            u64::max_value().to_lua(mc).unwrap(),
+            Value::Number(1.8446744073709552e19)
+        );
+        assert_eq!(1.5f32.to_lua(mc).unwrap(), Value::Number(1.5));
+
+        assert_eq!(i32::from_lua(mc, Value::Integer(-3)).unwrap(), -3);
+        assert_eq!(i32::from_lua(mc, Value::Number(4.0)).unwrap(), 4);
+        assert_eq!(
+            u16::from_lua(mc, Value::String(String::new_static(b" 0x10 "))).unwrap(),
+            16
+        );
+        assert!(i32::from_lua(mc, Value::Number(4.5)).is_err());
+        assert!(u8::from_lua(mc, Value::Integer(256)).is_err());
+        assert!(u32::from_lua(mc, Value::Integer(-1)).is_err());
+        assert!(i64::from_lua(mc, Value::Boolean(true)).is_err());
+        assert_eq!(f64::from_lua(mc, Value::Integer(2)).unwrap(), 2.0);
+        assert_eq!(
+            f64::from_lua(mc, Value::String(String::new_static(b"2.5"))).unwrap(),
+            2.5
+        );
+        assert!(f64::from_lua(mc, Value::Nil).is_err());
+    });
+}
+
+#[test]
+fn strings_and_booleans() {
+    let mut lua = Lua::new();
+    lua.mutate(|mc, _| {
+        assert_eq!(
+            "abc".to_lua(mc).unwrap(),
+            Value::String(String::new_static(b"abc"))
+        );
+        assert_eq!(
+            std::string::String::from_lua(mc, Value::Integer(12)).unwrap(),
+            "12"
+        );
+        assert_eq!(
+            String::from_lua(mc, Value::Number(1.5)).unwrap().as_bytes(),
+            b"1.5"
+        );
+        assert!(
+            std::string::String::from_lua(mc, Value::String(String::new_static(b"\xff"))).is_err()
+        );
+        assert!(String::from_lua(mc, Value::Boolean(true)).is_err());
+
+        assert_eq!(true.to_lua(mc).unwrap(), Value::Boolean(true));
+        assert_eq!(bool::from_lua(mc, Value::Nil).unwrap(), false);
+        assert_eq!(bool::from_lua(mc, Value::Integer(0)).unwrap(), true);
+    });
+}
+
+#[test]
+fn collections() {
+    let mut lua = Lua::new();
+    lua.mutate(|mc, _| {
+        let list = vec![1, 2, 3].to_lua(mc).unwrap();
+        match list {
+            Value::Table(t) => {
+                assert_eq!(t.length(), 3);
+                assert_eq!(t.get(2), Value::Integer(2));
+            }
+            _ => panic!("not a table"),
+        }
+        assert_eq!(Vec::<i64>::from_lua(mc, list).unwrap(), vec![1, 2, 3]);
+        assert!(Vec::<i64>::from_lua(mc, Value::Integer(1)).is_err());
+        assert!(Vec::<bool>::from_lua(mc, Table::new(mc).into())
+            .unwrap()
+            .is_empty());
+
+        let mut map = HashMap::new();
+        map.insert("one".to_owned(), 1);
+        map.insert("two".to_owned(), 2);
+        let table = map.clone().to_lua(mc).unwrap();
+        assert_eq!(
+            HashMap::<std::string::String, i32>::from_lua(mc, table).unwrap(),
+            map
+        );
+
+        assert_eq!(Option::<i64>::None.to_lua(mc).unwrap(), Value::Nil);
+        assert_eq!(Some(4).to_lua(mc).unwrap(), Value::Integer(4));
+        assert_eq!(Option::<i64>::from_lua(mc, Value::Nil).unwrap(), None);
+        assert_eq!(
+            Option::<i64>::from_lua(mc, Value::Integer(4)).unwrap(),
+            Some(4)
+        );
+        assert!(Option::<i64>::from_lua(mc, Value::Boolean(false)).is_err());
+    });
+}
+
+#[test]
+fn table_collections() {
+    let mut lua = Lua::new();
+    lua.mutate(|mc, _| {
+        let list = Table::from_iter(mc, vec!["a", "b", "c"]).unwrap();
+        assert_eq!(list.length(), 3);
+        assert_eq!(
+            list.to_vec::<std::string::String>(mc).unwrap(),
+            vec!["a", "b", "c"]
+        );
+        assert!(list.to_vec::<i64>(mc).is_err());
+        assert_eq!(
+            Table::from_iter(mc, 1..=4)
+                .unwrap()
+                .to_vec::<i64>(mc)
+                .unwrap(),
+            vec![1, 2, 3, 4]
+        );
+
+        let map = Table::from_pairs(mc, vec![("one", 1), ("two", 2), ("one", 3)]).unwrap();
+        let mut expected = HashMap::new();
+        expected.insert("one".to_owned(), 3);
+        expected.insert("two".to_owned(), 2);
+        assert_eq!(
+            map.to_hash_map::<std::string::String, i64>(mc).unwrap(),
+            expected
+        );
+        assert!(map.to_vec::<i64>(mc).unwrap().is_empty());
+        assert!(Table::from_pairs(mc, vec![(Value::Nil, 1)]).is_err());
+    });
+}
+
+#[test]
+fn multi_values() {
+    let mut lua = Lua::new();
+    lua.mutate(|mc, _| {
+        let values = (1, "two", Some(3.5)).to_multi_value(mc).unwrap();
+        assert_eq!(values.len(), 3);
+        let (a, b, c): (i32, std::string::String, Option<f64>) =
+            FromMultiValue::from_multi_value(mc, values).unwrap();
+        assert_eq!((a, b.as_str(), c), (1, "two", Some(3.5)));
+
+        let (a, b): (i32, Option<i32>) =
+            FromMultiValue::from_multi_value(mc, vec![Value::Integer(1)]).unwrap();
+        assert_eq!((a, b), (1, None));
+
+        assert!(().to_multi_value(mc).unwrap().is_empty());
+        assert_eq!(
+            (true, Variadic(vec![2, 3])).to_multi_value(mc).unwrap(),
+            vec![Value::Boolean(true), Value::Integer(2), Value::Integer(3)]
+        );
+        let (a, Variadic(rest)): (i64, Variadic<i64>) = FromMultiValue::from_multi_value(
+            mc,
+            vec![Value::Integer(1), Value::Integer(2), Value::Integer(3)],
+        )
+        .unwrap();
+        assert_eq!((a, rest), (1, vec![2, 3]));
+
+        let err = <(i64, i64, Variadic<i64>)>::from_multi_value(
+            mc,
+            vec![Value::Integer(1), Value::Integer(2), Value::Boolean(true)],
+        )
+        .unwrap_err();
+        assert_eq!((err.index, err.missing), (2, false));
+        let err = <(i64, i64)>::from_multi_value(mc, vec![Value::Integer(1)]).unwrap_err();
+        assert_eq!((err.index, err.missing), (1, true));
+    });
+}
+
+#[test]
+fn typed_callback() -> Result<(), Box<StaticError>> {
+    let mut lua = Lua::new();
+    lua.sequence(|root| {
+        sequence::from_fn_with(root, |mc, root| {
+            let repeat = Callback::new_typed(
+                mc,
+                "repeat",
+                |_, (n, s): (i64, Option<std::string::String>)| {
+                    let s = s.unwrap_or_else(|| "x".to_owned());
+                    Ok((n > 0, s.repeat(n.max(0) as usize)))
+                },
+            );
+            root.globals.set(mc, String::new_static(b"rep"), repeat)?;
+            Ok(Closure::new(
+                mc,
+                compile(
+                    mc,
+                    root.interned_strings,
+                    "=test",
+                    &br#"
+                        local a, b = rep(3, "ab")
+                        local c, d = rep(2)
+                        local _, e1 = pcall(rep)
+                        local _, e2 = pcall(rep, 1, {})
+                        return a, b, c, d, e1, e2
+                    "#[..],
+                )?,
+                Some(root.globals),
+            )?)
+        })
+        .and_chain_with(root, |mc, root, closure| {
+            Ok(ThreadSequence::call_function(
+                mc,
+                root.main_thread,
+                Function::Closure(closure),
+                &[],
+            )?)
+        })
+        .map_ok(|res| {
+            let strings = res
+                .iter()
+                .map(|v| match v {
+                    Value::String(s) => std::str::from_utf8(s.as_bytes()).unwrap().to_owned(),
+                    _ => std::string::String::new(),
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(res[0], Value::Boolean(true));
+            assert_eq!(strings[1], "ababab");
+            assert_eq!(res[2], Value::Boolean(true));
+            assert_eq!(strings[3], "xx");
+            assert_eq!(
+                strings[4],
+                "bad argument #1 to 'repeat' (i64 expected, got no value)"
+            );
+            assert_eq!(
+                strings[5],
+                "bad argument #2 to 'repeat' (string expected, got table)"
+            );
+        })
+        .map_err(Error::to_static)
+        .boxed()
+    })?;
+
+    Ok(())
+}
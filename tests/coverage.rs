@@ -0,0 +1,65 @@
+use luster::{Coverage, Lua, StaticError};
+
+const CODE: &str = r#"
+local function classify(n)
+    if n < 0 then
+        return "negative"
+    else
+        return "positive"
+    end
+end
+
+local total = 0
+for i = 1, 3 do
+    total = total + i
+end
+
+-- a comment
+return classify(total)
+"#;
+
+#[test]
+fn covered_lines() -> Result<(), StaticError> {
+    let mut lua = Lua::new();
+    let coverage = Coverage::new();
+    lua.set_coverage(Some(coverage.clone()));
+    assert_eq!(lua.eval::<String>(&CODE[1..])?, "positive");
+
+    let report = coverage.report();
+    assert_eq!(report.chunks.len(), 1);
+    let chunk = report.chunks.values().next().unwrap();
+    assert_eq!(chunk.uncovered_lines(), vec![3]);
+    assert!(chunk.covered_lines().contains(&5));
+    assert!(chunk.covered_lines().contains(&15));
+    assert!(!chunk.lines.contains_key(&14));
+    assert_eq!(chunk.lines[&11], 3);
+    assert!(chunk.ratio() > 0.5 && chunk.ratio() < 1.0);
+
+    coverage.reset();
+    let report = coverage.report();
+    let chunk = report.chunks.values().next().unwrap();
+    assert!(chunk.lines.values().all(|&hits| hits == 0));
+    assert_eq!(chunk.ratio(), 0.0);
+
+    Ok(())
+}
+
+#[test]
+fn loaded_chunks() -> Result<(), StaticError> {
+    let mut lua = Lua::new();
+    lua.exec("f = load('local x = ...\\nif x then\\nreturn 1\\nend\\nreturn 2', '=loaded')")?;
+
+    // Only chunks compiled while the coverage is set are covered
+    let coverage = Coverage::new();
+    lua.set_coverage(Some(coverage.clone()));
+    lua.exec("g = load('local x = ...\\nif x then\\nreturn 1\\nend\\nreturn 2', '=loaded')")?;
+    assert!(lua.set_coverage(None).is_some());
+    assert_eq!(lua.eval::<i64>("return f(true) + g(false)")?, 3);
+
+    let report = coverage.report();
+    let chunk = &report.chunks["=loaded"];
+    assert_eq!(chunk.covered_lines(), vec![1, 2, 5]);
+    assert_eq!(chunk.uncovered_lines(), vec![3]);
+
+    Ok(())
+}
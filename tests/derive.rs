@@ -0,0 +1,75 @@
+use luster::{FromLua, Lua, String, Table, ToLua, Value};
+
+#[derive(Debug, PartialEq, ToLua, FromLua)]
+struct Point {
+    x: i64,
+    y: i64,
+    label: Option<std::string::String>,
+}
+
+#[derive(Debug, PartialEq, ToLua, FromLua)]
+struct Pair<T>(T, T);
+
+#[derive(ToLua, FromLua)]
+struct Holder<'gc> {
+    table: Table<'gc>,
+    r#type: bool,
+}
+
+#[test]
+fn derive_struct() {
+    let mut lua = Lua::new();
+    lua.mutate(|mc, _| {
+        let point = Point {
+            x: 1,
+            y: -2,
+            label: Some("origin".to_owned()),
+        };
+        let value = point.to_lua(mc).unwrap();
+        match value {
+            Value::Table(t) => {
+                assert_eq!(t.get(String::new_static(b"x")), Value::Integer(1));
+                assert_eq!(t.get(String::new_static(b"y")), Value::Integer(-2));
+            }
+            _ => panic!("not a table"),
+        }
+        assert_eq!(
+            Point::from_lua(mc, value).unwrap(),
+            Point {
+                x: 1,
+                y: -2,
+                label: Some("origin".to_owned()),
+            }
+        );
+
+        let partial = Table::new(mc);
+        partial.set(mc, String::new_static(b"x"), 3).unwrap();
+        partial.set(mc, String::new_static(b"y"), 4.0).unwrap();
+        assert_eq!(
+            Point::from_lua(mc, partial.into()).unwrap(),
+            Point {
+                x: 3,
+                y: 4,
+                label: None
+            }
+        );
+        partial.set(mc, String::new_static(b"y"), true).unwrap();
+        assert!(Point::from_lua(mc, partial.into()).is_err());
+        assert!(Point::from_lua(mc, Value::Nil).is_err());
+
+        let pair = Pair(1.5, 2.5).to_lua(mc).unwrap();
+        assert_eq!(Vec::<f64>::from_lua(mc, pair).unwrap(), vec![1.5, 2.5]);
+        assert_eq!(Pair::<f64>::from_lua(mc, pair).unwrap(), Pair(1.5, 2.5));
+
+        let table = Table::new(mc);
+        let holder = Holder {
+            table,
+            r#type: true,
+        }
+        .to_lua(mc)
+        .unwrap();
+        let holder = Holder::from_lua(mc, holder).unwrap();
+        assert_eq!(holder.table, table);
+        assert!(holder.r#type);
+    });
+}
@@ -0,0 +1,74 @@
+use luster::{Lua, StaticError, String};
+
+fn display_global(lua: &mut Lua, name: &'static str, max_depth: usize) -> std::string::String {
+    lua.mutate(|_, root| {
+        let mut buf = Vec::new();
+        root.globals
+            .get(String::new_static(name.as_bytes()))
+            .display_deep(&mut buf, max_depth)
+            .unwrap();
+        std::string::String::from_utf8(buf).unwrap()
+    })
+}
+
+#[test]
+fn display_deep() -> Result<(), StaticError> {
+    let mut lua = Lua::new();
+    lua.exec(
+        r#"
+            value = {
+                "first", 2.5,
+                name = "a \"quoted\"\n\1string",
+                nested = { empty = {}, deeper = { x = 1 } },
+                [true] = false,
+                ["not a name"] = 1,
+                ["end"] = 2,
+                [-1] = "negative",
+            }
+            cyclic = { }
+            cyclic.self = cyclic
+        "#,
+    )?;
+
+    assert_eq!(
+        display_global(&mut lua, "value", 8),
+        r#"{
+  [-1] = "negative",
+  [1] = "first",
+  [2] = 2.5,
+  ["end"] = 2,
+  name = "a \"quoted\"\n\001string",
+  nested = {
+    deeper = {
+      x = 1,
+    },
+    empty = {},
+  },
+  ["not a name"] = 1,
+  [true] = false,
+}"#
+    );
+
+    assert_eq!(
+        display_global(&mut lua, "value", 1),
+        r#"{
+  [-1] = "negative",
+  [1] = "first",
+  [2] = 2.5,
+  ["end"] = 2,
+  name = "a \"quoted\"\n\001string",
+  nested = {...},
+  ["not a name"] = 1,
+  [true] = false,
+}"#
+    );
+
+    let cyclic = display_global(&mut lua, "cyclic", 8);
+    assert!(cyclic.starts_with("{\n  self = <cycle table "));
+    assert!(cyclic.ends_with(">,\n}"));
+
+    lua.exec("scalar = 'plain'")?;
+    assert_eq!(display_global(&mut lua, "scalar", 8), "\"plain\"");
+
+    Ok(())
+}
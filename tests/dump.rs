@@ -0,0 +1,436 @@
+use gc_sequence::{self as sequence, SequenceExt, SequenceResultExt};
+use luster::{
+    compile, dump_function, undump_function, Closure, Error, Function, Lua, OpCode, StaticError,
+    ThreadSequence, UndumpError, Value,
+};
+
+#[test]
+fn dump_undump() {
+    let mut lua = Lua::new();
+    lua.mutate(|mc, root| {
+        let proto = compile(
+            mc,
+            root.interned_strings,
+            "=test",
+            &b"local t = {1, 2.5, 'three'} return function(...) local n = #t return n, ... end"[..],
+        )
+        .unwrap();
+
+        let mut buf = Vec::new();
+        dump_function(&proto, false, &mut buf).unwrap();
+        let undumped = undump_function(mc, root.interned_strings, &buf[..]).unwrap();
+        assert_eq!(
+            format!("{:?}", proto.opcodes),
+            format!("{:?}", undumped.opcodes)
+        );
+        assert_eq!(proto.upvalues, undumped.upvalues);
+        assert_eq!(proto.prototypes.len(), undumped.prototypes.len());
+        assert_eq!(proto.chunk_name, undumped.chunk_name);
+        assert_eq!(proto.line_info, undumped.line_info);
+        assert_eq!(undumped.line_defined, 0);
+        assert_eq!(undumped.prototypes[0].line_defined, 1);
+        assert_eq!(undumped.last_line_defined, 0);
+        assert_eq!(undumped.prototypes[0].last_line_defined, 1);
+        assert_eq!(
+            undumped.prototypes[0].upvalue_names,
+            proto.prototypes[0].upvalue_names
+        );
+        assert_eq!(undumped.prototypes[0].upvalue_names[0], b"t");
+        assert_eq!(undumped.local_variables, proto.local_variables);
+        assert_eq!(undumped.local_variables[0].name, b"t");
+        assert_eq!(
+            undumped.prototypes[0].local_variables,
+            proto.prototypes[0].local_variables
+        );
+        assert_eq!(undumped.prototypes[0].local_variables[0].name, b"n");
+
+        let mut redumped = Vec::new();
+        dump_function(&undumped, false, &mut redumped).unwrap();
+        assert_eq!(buf, redumped);
+
+        let mut stripped = Vec::new();
+        dump_function(&proto, true, &mut stripped).unwrap();
+        let undumped = undump_function(mc, root.interned_strings, &stripped[..]).unwrap();
+        assert!(undumped.chunk_name.is_none());
+        assert!(undumped.line_info.is_empty());
+        assert!(undumped.prototypes[0].line_info.is_empty());
+        assert!(undumped.prototypes[0].upvalue_names.is_empty());
+        assert!(undumped.local_variables.is_empty());
+        assert_eq!(undumped.prototypes[0].line_defined, 1);
+    });
+}
+
+#[test]
+fn undump_errors() {
+    let mut lua = Lua::new();
+    lua.mutate(|mc, root| {
+        let proto = compile(mc, root.interned_strings, "=test", &b"return 1"[..]).unwrap();
+        let mut buf = Vec::new();
+        dump_function(&proto, false, &mut buf).unwrap();
+
+        let undump = |chunk: &[u8]| match undump_function(mc, root.interned_strings, chunk) {
+            Err(err) => err,
+            Ok(_) => panic!("undumping bad chunk did not error"),
+        };
+
+        match undump(b"return 1") {
+            Error::UndumpError(UndumpError::BadSignature) => {}
+            _ => panic!("wrong error for bad signature"),
+        }
+
+        let mut bad_version = buf.clone();
+        bad_version[11] += 1;
+        match undump(&bad_version) {
+            Error::UndumpError(UndumpError::VersionMismatch) => {}
+            _ => panic!("wrong error for bad version"),
+        }
+
+        let mut bad_format = buf.clone();
+        bad_format[12] = 4;
+        match undump(&bad_format) {
+            Error::UndumpError(UndumpError::FormatMismatch) => {}
+            _ => panic!("wrong error for bad format"),
+        }
+
+        match undump(&buf[..buf.len() - 1]) {
+            Error::UndumpError(UndumpError::Truncated) => {}
+            _ => panic!("wrong error for truncated chunk"),
+        }
+
+        // A huge string length is not allocated up front
+        let proto = compile(mc, root.interned_strings, "=test", &b"return 'abc'"[..]).unwrap();
+        let mut huge_string = Vec::new();
+        dump_function(&proto, false, &mut huge_string).unwrap();
+        // The length of the first constant follows the header, the parameters and stack size, the
+        // number of constants and the constant's tag
+        let len_start = 30 + 4 + 8 + 1;
+        huge_string[len_start..len_start + 8].copy_from_slice(&i64::MAX.to_le_bytes());
+        match undump(&huge_string) {
+            Error::UndumpError(UndumpError::Truncated) => {}
+            _ => panic!("wrong error for huge string length"),
+        }
+
+        let mut bad_constant =
+            compile(mc, root.interned_strings, "=test", &b"return 'abc'"[..]).unwrap();
+        bad_constant.constants.clear();
+        let mut bad_constant_chunk = Vec::new();
+        dump_function(&bad_constant, false, &mut bad_constant_chunk).unwrap();
+        match undump(&bad_constant_chunk) {
+            Error::UndumpError(UndumpError::BadOpCode) => {}
+            _ => panic!("wrong error for bad constant index"),
+        }
+
+        let mut bad_register = compile(
+            mc,
+            root.interned_strings,
+            "=test",
+            &b"local a = 1 return a"[..],
+        )
+        .unwrap();
+        bad_register.stack_size = 0;
+        let mut bad_register_chunk = Vec::new();
+        dump_function(&bad_register, false, &mut bad_register_chunk).unwrap();
+        match undump(&bad_register_chunk) {
+            Error::UndumpError(UndumpError::BadOpCode) => {}
+            _ => panic!("wrong error for bad register"),
+        }
+
+        let mut bad_jump = compile(
+            mc,
+            root.interned_strings,
+            "=test",
+            &b"while true do end"[..],
+        )
+        .unwrap();
+        for opcode in &mut bad_jump.opcodes {
+            if let OpCode::Jump { offset, .. } = opcode {
+                *offset = 100;
+            }
+        }
+        let mut bad_jump_chunk = Vec::new();
+        dump_function(&bad_jump, false, &mut bad_jump_chunk).unwrap();
+        match undump(&bad_jump_chunk) {
+            Error::UndumpError(UndumpError::BadOpCode) => {}
+            _ => panic!("wrong error for bad jump"),
+        }
+    });
+}
+
+// Builds PUC-Rio Lua 5.3 precompiled chunks with no debug information
+struct Luac53 {
+    big_endian: bool,
+}
+
+enum Luac53Constant {
+    Integer(i64),
+    String(&'static [u8]),
+}
+
+const RK_CONSTANT: u32 = 0x100;
+
+fn abc(op: u32, a: u32, b: u32, c: u32) -> u32 {
+    op | a << 6 | c << 14 | b << 23
+}
+
+fn abx(op: u32, a: u32, bx: u32) -> u32 {
+    op | a << 6 | bx << 14
+}
+
+fn asbx(op: u32, a: u32, sbx: i32) -> u32 {
+    abx(op, a, (sbx + 0x1ffff) as u32)
+}
+
+impl Luac53 {
+    fn put(&self, buf: &mut Vec<u8>, bytes: &[u8]) {
+        if self.big_endian {
+            buf.extend(bytes.iter().rev());
+        } else {
+            buf.extend(bytes);
+        }
+    }
+
+    fn chunk(&self, main: &[u8]) -> Vec<u8> {
+        let mut buf = b"\x1bLua\x53\x00\x19\x93\r\n\x1a\n\x04\x08\x04\x08\x08".to_vec();
+        self.put(&mut buf, &0x5678i64.to_le_bytes());
+        self.put(&mut buf, &370.5f64.to_bits().to_le_bytes());
+        buf.push(1);
+        buf.extend(main);
+        buf
+    }
+
+    fn function(
+        &self,
+        params: u8,
+        is_vararg: bool,
+        max_stack: u8,
+        code: &[u32],
+        constants: &[Luac53Constant],
+        upvalues: &[(u8, u8)],
+        prototypes: &[Vec<u8>],
+    ) -> Vec<u8> {
+        let mut buf = vec![0];
+        self.put(&mut buf, &0i32.to_le_bytes());
+        self.put(&mut buf, &0i32.to_le_bytes());
+        buf.extend(&[params, is_vararg as u8, max_stack]);
+
+        self.put(&mut buf, &(code.len() as i32).to_le_bytes());
+        for &i in code {
+            self.put(&mut buf, &i.to_le_bytes());
+        }
+
+        self.put(&mut buf, &(constants.len() as i32).to_le_bytes());
+        for constant in constants {
+            match constant {
+                Luac53Constant::Integer(i) => {
+                    buf.push(19);
+                    self.put(&mut buf, &i.to_le_bytes());
+                }
+                Luac53Constant::String(s) => {
+                    buf.push(4);
+                    buf.push(s.len() as u8 + 1);
+                    buf.extend(*s);
+                }
+            }
+        }
+
+        self.put(&mut buf, &(upvalues.len() as i32).to_le_bytes());
+        for &(in_stack, index) in upvalues {
+            buf.extend(&[in_stack, index]);
+        }
+
+        self.put(&mut buf, &(prototypes.len() as i32).to_le_bytes());
+        for proto in prototypes {
+            buf.extend(proto);
+        }
+
+        for _ in 0..3 {
+            self.put(&mut buf, &0i32.to_le_bytes());
+        }
+        buf
+    }
+
+    // local t = {...}
+    // local s = 0
+    // for i = 1, #t do
+    //     s = s + t[i]
+    // end
+    // return s, type(t)
+    fn sum_chunk(&self) -> Vec<u8> {
+        self.chunk(&self.function(
+            0,
+            true,
+            7,
+            &[
+                abc(11, 0, 0, 0),
+                abc(45, 1, 0, 0),
+                abc(43, 0, 0, 1),
+                abx(1, 1, 0),
+                abx(1, 2, 1),
+                abc(28, 3, 0, 0),
+                abx(1, 4, 1),
+                asbx(40, 2, 2),
+                abc(7, 6, 0, 5),
+                abc(13, 1, 1, 6),
+                asbx(39, 2, -3),
+                abc(6, 2, 0, RK_CONSTANT | 2),
+                abc(0, 3, 0, 0),
+                abc(36, 2, 2, 2),
+                abc(38, 1, 3, 0),
+                abc(38, 0, 1, 0),
+            ],
+            &[
+                Luac53Constant::Integer(0),
+                Luac53Constant::Integer(1),
+                Luac53Constant::String(b"type"),
+            ],
+            &[(1, 0)],
+            &[],
+        ))
+    }
+
+    // local n = 10
+    // local function f(x)
+    //     if x == "a" then
+    //         return n
+    //     end
+    //     return n + 1
+    // end
+    // return f("a"), f("b")
+    fn closure_chunk(&self) -> Vec<u8> {
+        let f = self.function(
+            1,
+            false,
+            2,
+            &[
+                abc(31, 0, 0, RK_CONSTANT),
+                asbx(30, 0, 2),
+                abc(5, 1, 0, 0),
+                abc(38, 1, 2, 0),
+                abc(5, 1, 0, 0),
+                abc(13, 1, 1, RK_CONSTANT | 1),
+                abc(38, 1, 2, 0),
+                abc(38, 0, 1, 0),
+            ],
+            &[Luac53Constant::String(b"a"), Luac53Constant::Integer(1)],
+            &[(1, 0)],
+            &[],
+        );
+        self.chunk(&self.function(
+            0,
+            true,
+            5,
+            &[
+                abx(1, 0, 0),
+                abx(44, 1, 0),
+                abc(0, 2, 1, 0),
+                abx(1, 3, 1),
+                abc(36, 2, 2, 2),
+                abc(0, 3, 1, 0),
+                abx(1, 4, 2),
+                abc(36, 3, 2, 0),
+                abc(38, 2, 0, 0),
+                abc(38, 0, 1, 0),
+            ],
+            &[
+                Luac53Constant::Integer(10),
+                Luac53Constant::String(b"a"),
+                Luac53Constant::String(b"b"),
+            ],
+            &[(1, 0)],
+            &[f],
+        ))
+    }
+}
+
+fn run_precompiled(
+    chunk: Vec<u8>,
+    args: Vec<i64>,
+    check: fn(&[Value]) -> bool,
+) -> Result<(), Box<StaticError>> {
+    let mut lua = Lua::new();
+    lua.sequence(move |root| {
+        sequence::from_fn_with(root, move |mc, root| {
+            let proto = undump_function(mc, root.interned_strings, &chunk[..])?;
+            Ok(Closure::new_precompiled(mc, proto, root.globals))
+        })
+        .and_chain_with(root, move |mc, root, closure| {
+            let args = args.iter().map(|&i| Value::Integer(i)).collect::<Vec<_>>();
+            Ok(ThreadSequence::call_function(
+                mc,
+                root.main_thread,
+                Function::Closure(closure),
+                &args,
+            )?)
+        })
+        .map_ok(move |res| assert!(check(&res)))
+        .map_err(Error::to_static)
+        .boxed()
+    })?;
+    Ok(())
+}
+
+#[test]
+fn luac53() -> Result<(), Box<StaticError>> {
+    for &big_endian in &[false, true] {
+        let luac = Luac53 { big_endian };
+        run_precompiled(luac.sum_chunk(), vec![1, 2, 3], |res| match res {
+            [Value::Integer(6), Value::String(s)] => s.as_bytes() == b"table",
+            _ => false,
+        })?;
+        run_precompiled(luac.closure_chunk(), vec![], |res| {
+            *res == [Value::Integer(10), Value::Integer(11)]
+        })?;
+    }
+    Ok(())
+}
+
+#[test]
+fn luac53_errors() {
+    let luac = Luac53 { big_endian: false };
+    let mut lua = Lua::new();
+    lua.mutate(|mc, root| {
+        let undump = |chunk: &[u8]| match undump_function(mc, root.interned_strings, chunk) {
+            Err(err) => err,
+            Ok(_) => panic!("undumping bad chunk did not error"),
+        };
+
+        // LOADK with no constants
+        let chunk = luac.chunk(&luac.function(
+            0,
+            true,
+            2,
+            &[abx(1, 0, 5), abc(38, 0, 1, 0)],
+            &[],
+            &[(1, 0)],
+            &[],
+        ));
+        match undump(&chunk) {
+            Error::UndumpError(UndumpError::BadOpCode) => {}
+            _ => panic!("wrong error for bad constant index"),
+        }
+
+        // GETUPVAL of a missing upvalue
+        let chunk = luac.chunk(&luac.function(
+            0,
+            true,
+            2,
+            &[abc(5, 0, 3, 0), abc(38, 0, 1, 0)],
+            &[],
+            &[(1, 0)],
+            &[],
+        ));
+        match undump(&chunk) {
+            Error::UndumpError(UndumpError::BadOpCode) => {}
+            _ => panic!("wrong error for bad upvalue index"),
+        }
+
+        // A source string claiming to be huge
+        let mut chunk = luac.chunk(&[]);
+        chunk.push(0xff);
+        chunk.extend(&u64::MAX.to_le_bytes());
+        match undump(&chunk) {
+            Error::UndumpError(UndumpError::Truncated) => {}
+            _ => panic!("wrong error for huge string length"),
+        }
+    });
+}
@@ -0,0 +1,123 @@
+use gc_sequence::{self as sequence, SequenceExt, SequenceResultExt};
+
+use luster::{
+    compile, Closure, Error, Function, Lua, StaticError, String, Table, ThreadSequence, Value,
+};
+
+#[test]
+fn set_environment() -> Result<(), StaticError> {
+    let mut lua = Lua::new();
+    let results = lua.sequence(|root| {
+        sequence::from_fn_with(root, |mc, root| {
+            let closure = Closure::new(
+                mc,
+                compile(
+                    mc,
+                    root.interned_strings,
+                    "env",
+                    &b"x = x + 1 return x, function() return x end"[..],
+                )?,
+                Some(root.globals),
+            )?;
+            assert_eq!(closure.environment(), Some(Value::Table(root.globals)));
+
+            let env = Table::new(mc);
+            env.set(mc, String::new_static(b"x"), 41)?;
+            assert!(closure.set_environment(mc, Value::Table(env)));
+            assert_eq!(closure.environment(), Some(Value::Table(env)));
+            Ok(closure)
+        })
+        .and_chain_with(root, |mc, root, closure| {
+            Ok(ThreadSequence::call_function(
+                mc,
+                root.main_thread,
+                Function::Closure(closure),
+                &[],
+            )?)
+        })
+        .and_then(|mc, results| {
+            let inner = match results[1] {
+                Value::Function(Function::Closure(inner)) => inner,
+                _ => panic!("expected a closure"),
+            };
+            // Inner functions find their `_ENV` upvalue by name
+            assert_eq!(
+                inner.environment().map(|env| env.type_name()),
+                Some("table")
+            );
+            assert!(inner.set_environment(mc, Value::Nil));
+            Ok(results[0])
+        })
+        .map_ok(|x| x == Value::Integer(42))
+        .map_err(Error::to_static)
+        .boxed()
+    })?;
+    assert!(results);
+
+    lua.mutate(|mc, root| {
+        let closure = Closure::new(
+            mc,
+            compile(mc, root.interned_strings, "no env", &b"return 1"[..]).unwrap(),
+            None,
+        )
+        .unwrap();
+        assert_eq!(closure.environment(), None);
+        assert!(!closure.set_environment(mc, Value::Nil));
+    });
+    Ok(())
+}
+
+#[test]
+fn globals_table() -> Result<(), StaticError> {
+    let mut lua = Lua::new();
+    assert!(lua.eval::<bool>("return _G == _ENV and _G._G == _G")?);
+    lua.exec("local _ENV = {y = 1} z = y + 1 assert = nil")?;
+    assert!(lua.eval::<bool>("return z == nil and assert ~= nil")?);
+    Ok(())
+}
+
+#[test]
+fn shared_proto() -> Result<(), StaticError> {
+    let mut lua = Lua::new();
+    let (first, second) = lua.mutate(|mc, root| {
+        let closure = Closure::new(
+            mc,
+            compile(
+                mc,
+                root.interned_strings,
+                "config",
+                &b"count = (count or 0) + 1 return name, count"[..],
+            )
+            .unwrap(),
+            Some(root.globals),
+        )
+        .unwrap();
+
+        let with_name = |name: &'static [u8]| {
+            let env = Table::new(mc);
+            env.set(mc, String::new_static(b"name"), String::new_static(name))
+                .unwrap();
+            let closure = closure.with_environment(mc, env);
+            root.registry
+                .stash(mc, Value::Function(Function::Closure(closure)))
+        };
+        (with_name(b"first"), with_name(b"second"))
+    });
+
+    assert_eq!(
+        lua.call::<_, (std::string::String, i64)>(&first, ())?,
+        ("first".to_owned(), 1)
+    );
+    assert_eq!(
+        lua.call::<_, (std::string::String, i64)>(&first, ())?,
+        ("first".to_owned(), 2)
+    );
+    assert_eq!(
+        lua.call::<_, (std::string::String, i64)>(&second, ())?,
+        ("second".to_owned(), 1)
+    );
+
+    // Neither environment shares anything with the original globals
+    assert!(lua.eval::<bool>("return count == nil and name == nil")?);
+    Ok(())
+}
@@ -1,5 +1,8 @@
 use gc_sequence::{self as sequence, SequenceExt, SequenceResultExt};
-use luster::{compile, Closure, Error, Function, Lua, StaticError, ThreadSequence};
+use luster::{
+    compile, Closure, CompilerError, Error, Function, Location, Lua, StaticError, SyntaxError,
+    SyntaxErrorKind, ThreadSequence,
+};
 
 #[test]
 fn error_unwind() -> Result<(), Box<StaticError>> {
@@ -11,6 +14,7 @@ fn error_unwind() -> Result<(), Box<StaticError>> {
                 compile(
                     mc,
                     root.interned_strings,
+                    "=test",
                     &br#"
                         function do_error()
                             error('test error')
@@ -29,7 +33,7 @@ fn error_unwind() -> Result<(), Box<StaticError>> {
                 Function::Closure(closure),
                 &[],
             )?
-            .map(|res| match res {
+            .map(|res| match res.map_err(Error::without_traceback) {
                 Err(Error::RuntimeError(_)) => Ok(()),
                 _ => panic!(),
             })
@@ -41,7 +45,7 @@ fn error_unwind() -> Result<(), Box<StaticError>> {
                     &[],
                 )?)
             })
-            .map(|res| match res {
+            .map(|res| match res.map_err(Error::without_traceback) {
                 Err(Error::RuntimeError(_)) => Ok(()),
                 _ => panic!(),
             }))
@@ -52,3 +56,264 @@ fn error_unwind() -> Result<(), Box<StaticError>> {
 
     Ok(())
 }
+
+#[test]
+fn assign_to_const() {
+    let mut lua = Lua::new();
+    lua.mutate(|mc, root| {
+        for code in &[
+            &b"local a <const> = 1; a = 2"[..],
+            &b"local a <close> = nil; a = 2"[..],
+            &b"local a <const> = 1; local function f() a = 2 end"[..],
+        ] {
+            match compile(mc, root.interned_strings, "=test", *code) {
+                Err(Error::SyntaxError(SyntaxError {
+                    kind: SyntaxErrorKind::Compiler(CompilerError::AssignToConst),
+                    ..
+                })) => {}
+                _ => panic!("assignment to const local did not error"),
+            }
+        }
+        assert!(compile(
+            mc,
+            root.interned_strings,
+            "=test",
+            &b"local a <const> = 1; local a = 2; a = 3"[..]
+        )
+        .is_ok());
+    });
+}
+
+#[test]
+fn goto_errors() {
+    let mut lua = Lua::new();
+    lua.mutate(|mc, root| {
+        let compile_error = |code: &[u8]| match compile(mc, root.interned_strings, "=test", code) {
+            Err(Error::SyntaxError(SyntaxError {
+                kind: SyntaxErrorKind::Compiler(err),
+                ..
+            })) => err.to_string(),
+            _ => panic!("code did not produce a compiler error"),
+        };
+
+        assert_eq!(
+            compile_error(b"goto missing"),
+            "no visible label 'missing' for goto"
+        );
+        assert_eq!(
+            compile_error(b"do ::inner:: end goto inner"),
+            "no visible label 'inner' for goto"
+        );
+        assert_eq!(
+            compile_error(b"::outer:: local function f() goto outer end"),
+            "no visible label 'outer' for goto"
+        );
+        assert_eq!(compile_error(b"::a:: ::a::"), "label 'a' already defined");
+        assert_eq!(
+            compile_error(b"::a:: do ::a:: end"),
+            "label 'a' already defined"
+        );
+        assert_eq!(
+            compile_error(b"goto skip local x = 1 ::skip:: print(x)"),
+            "goto 'skip' jumps into the scope of local 'x'"
+        );
+        assert_eq!(compile_error(b"break"), "break outside a loop");
+        assert_eq!(
+            compile_error(b"local function f() break end"),
+            "break outside a loop"
+        );
+
+        assert!(compile(
+            mc,
+            root.interned_strings,
+            "=test",
+            &b"do ::a:: end do ::a:: end goto skip local x = 1 ::skip::"[..]
+        )
+        .is_ok());
+    });
+}
+
+#[test]
+fn syntax_error_location() {
+    let mut lua = Lua::new();
+    lua.mutate(|mc, root| {
+        let error = |name: &str, code: &[u8]| match compile(mc, root.interned_strings, name, code) {
+            Err(Error::SyntaxError(err)) => err.to_string(),
+            _ => panic!("code did not produce a syntax error"),
+        };
+
+        assert_eq!(
+            error("@script.lua", b"local x = 1\nif x then\n  x = 2\n"),
+            "script.lua:4:1: 'end' expected near <eof>"
+        );
+        assert_eq!(
+            error("=test", b"local x = 1\nlocal y = x +* 2"),
+            "test:2:14: unexpected symbol near '*'"
+        );
+        assert_eq!(
+            error("=test", b"return 1 end"),
+            "test:1:10: '<eof>' expected near 'end'"
+        );
+        assert_eq!(
+            error("=test", b"for i 1.5 do end"),
+            "test:1:7: '=' or 'in' expected near '1.5'"
+        );
+        assert_eq!(
+            error("=test", b"local function f(1) end"),
+            "test:1:18: <name> or '...' expected near '1'"
+        );
+        assert_eq!(
+            error("=test", b"f(\"a\" \"b\")"),
+            "test:1:7: ')' expected near '\"b\"'"
+        );
+        assert_eq!(
+            error("=test", b"local x\n\n  goto missing\n"),
+            "test:3:3: no visible label 'missing' for goto"
+        );
+        assert_eq!(
+            error("=test", b"local x\n  x = 1\n  break"),
+            "test:3:3: break outside a loop"
+        );
+    });
+}
+
+#[test]
+fn runtime_error_location() {
+    fn run_error(chunk_name: &'static str, code: &'static str) -> StaticError {
+        let mut lua = Lua::new();
+        lua.sequence(move |root| {
+            sequence::from_fn_with(root, move |mc, root| {
+                Ok(Closure::new(
+                    mc,
+                    compile(mc, root.interned_strings, chunk_name, code.as_bytes())?,
+                    Some(root.globals),
+                )?)
+            })
+            .and_chain_with(root, |mc, root, closure| {
+                Ok(ThreadSequence::call_function(
+                    mc,
+                    root.main_thread,
+                    Function::Closure(closure),
+                    &[],
+                )?)
+            })
+            .map_ok(|_| ())
+            .map_err(Error::to_static)
+            .boxed()
+        })
+        .unwrap_err()
+    }
+
+    match run_error("@script.lua", "local t\n\nlocal x = t.field").without_traceback() {
+        StaticError::LocatedError(location, _) => assert_eq!(
+            location,
+            Location {
+                chunk_name: Some("@script.lua".to_owned()),
+                line: Some(3),
+            }
+        ),
+        _ => panic!("runtime error has no location"),
+    }
+
+    // A tail call of a callback keeps the frame of the calling function, so `error` is located
+    // there
+    match run_error(
+        "@j.lua",
+        "local function f()\n  return error('boom')\nend\n\n\n\n\nf()",
+    )
+    .without_traceback()
+    {
+        StaticError::RuntimeError(message) => assert_eq!(message, "j.lua:2: boom"),
+        _ => panic!("error is not a runtime error"),
+    }
+
+    // Concatenation errors are located in the concatenating function, with or without metamethods
+    assert_eq!(
+        run_error("@j.lua", "local t = {}\nlocal s = 'a' .. t .. 'b'")
+            .without_traceback()
+            .to_string(),
+        "j.lua:2: attempt to concatenate a table value"
+    );
+    match run_error(
+        "@j.lua",
+        concat!(
+            "local mt = {__concat = function() return {} end}\n",
+            "\n",
+            "local s = 'a' .. setmetatable({}, mt) .. 1",
+        ),
+    )
+    .without_traceback()
+    {
+        StaticError::RuntimeError(message) => {
+            assert_eq!(message, "j.lua:3: attempt to concatenate a table value")
+        }
+        _ => panic!("error is not a runtime error"),
+    }
+
+    fn located(chunk_name: &'static str, code: &'static str) -> String {
+        let error = run_error(chunk_name, code).to_string();
+        error[..error.find(": ").unwrap()].to_owned()
+    }
+    assert_eq!(
+        located(
+            "@script.lua",
+            "local function f(a)\n  return a + 1\nend\nf({})"
+        ),
+        "script.lua:2"
+    );
+    assert_eq!(located("=stdin", "local x\nx()"), "stdin:2");
+    assert_eq!(
+        located("local x\nx()", "local x\nx()"),
+        "[string \"local x...\"]:2"
+    );
+    assert_eq!(located("x = #nil", "x = #nil"), "[string \"x = #nil\"]:1");
+    assert_eq!(
+        located(
+            "@/a/very/long/path/to/a/script/in/some/deeply/nested/directory/script.lua",
+            "x = #nil"
+        ),
+        "...h/to/a/script/in/some/deeply/nested/directory/script.lua:1"
+    );
+}
+
+#[test]
+fn error_traceback() {
+    let mut lua = Lua::new();
+    let error = lua
+        .exec(
+            r#"local function inner()
+                error('oops')
+            end
+            function outer()
+                inner()
+            end
+            outer()"#,
+        )
+        .unwrap_err();
+    let traceback = error.traceback().expect("error has no traceback").clone();
+    let descriptions = traceback
+        .0
+        .iter()
+        .map(|frame| frame.description.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        descriptions,
+        vec!["upvalue 'inner'", "function 'outer'", "main chunk"]
+    );
+    assert_eq!(traceback.0[0].location.as_ref().unwrap().line, Some(2));
+    assert_eq!(traceback.0[1].location.as_ref().unwrap().line, Some(5));
+
+    let message = error.to_string();
+    let mut lines = message.lines();
+    assert!(lines.next().unwrap().ends_with(":2: oops"));
+    assert_eq!(lines.next(), Some("stack traceback:"));
+    assert!(lines.next().unwrap().ends_with(":2: in upvalue 'inner'"));
+    assert_eq!(lines.count(), 2);
+
+    // Errors caught by Lua code have no traceback
+    assert_eq!(
+        lua.eval::<String>("return select(2, pcall(error, 'caught', 0))")
+            .unwrap(),
+        "caught"
+    );
+}
@@ -0,0 +1,33 @@
+use luster::{Lua, StaticError, Variadic};
+
+#[test]
+fn eval() -> Result<(), StaticError> {
+    let mut lua = Lua::new();
+    lua.exec("x = 3")?;
+    assert_eq!(lua.eval::<i64>("return x * 2")?, 6);
+    assert_eq!(
+        lua.eval::<(bool, String)>("return x > 2, 'a' .. x")?,
+        (true, "a3".to_owned())
+    );
+    assert_eq!(
+        lua.eval::<Variadic<f64>>("return 1, 2.5")?,
+        Variadic(vec![1.0, 2.5])
+    );
+    assert_eq!(lua.eval::<Option<i64>>("local y = 1")?, None);
+    Ok(())
+}
+
+#[test]
+fn eval_errors() {
+    let mut lua = Lua::new();
+    match lua
+        .exec("error('boom', 0)")
+        .map_err(StaticError::without_traceback)
+    {
+        Err(StaticError::RuntimeError(message)) => assert_eq!(message, "boom"),
+        res => panic!("unexpected result {:?}", res),
+    }
+    assert!(lua.exec("return +").is_err());
+    assert!(lua.eval::<i64>("return {}").is_err());
+    assert_eq!(lua.eval::<i64>("return 1 + 1").unwrap(), 2);
+}
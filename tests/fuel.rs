@@ -0,0 +1,109 @@
+use gc_sequence::{self as sequence, SequenceExt, SequenceResultExt};
+use luster::{
+    compile, BadThreadMode, Closure, Error, Function, Lua, StaticError, ThreadMode, ThreadSequence,
+    Value,
+};
+
+// Runs the code on the main thread with the given fuel, refuelling it each time it runs out until
+// it has run out `max_refuels` times.  Returns the first result and the number of refuels.
+fn run_with_fuel(code: &'static str, fuel: u64, max_refuels: usize) -> (Option<i64>, usize) {
+    let mut lua = Lua::new();
+    let mut res = lua.sequence(move |root| {
+        sequence::from_fn_with(root, move |mc, root| {
+            let closure = Closure::new(
+                mc,
+                compile(mc, root.interned_strings, "=test", code.as_bytes())?,
+                Some(root.globals),
+            )?;
+            root.main_thread.set_fuel(Some(fuel));
+            Ok(ThreadSequence::call_function(
+                mc,
+                root.main_thread,
+                Function::Closure(closure),
+                &[],
+            )?)
+        })
+        .flatten_ok()
+        .map_ok(first_integer)
+        .map_err(Error::to_static)
+        .boxed()
+    });
+
+    let mut refuels = 0;
+    loop {
+        match res {
+            Ok(i) => return (i, refuels),
+            Err(StaticError::BadThreadMode(BadThreadMode {
+                found: ThreadMode::OutOfFuel,
+                ..
+            })) if refuels < max_refuels => {
+                refuels += 1;
+                res = lua.sequence(move |root| {
+                    assert_eq!(root.main_thread.fuel(), Some(0));
+                    root.main_thread.resume_with_fuel(fuel).unwrap();
+                    ThreadSequence(root.main_thread)
+                        .map_ok(first_integer)
+                        .map_err(Error::to_static)
+                        .boxed()
+                });
+            }
+            Err(StaticError::BadThreadMode(BadThreadMode {
+                found: ThreadMode::OutOfFuel,
+                ..
+            })) => return (None, refuels),
+            Err(err) => panic!("unexpected error {}", err),
+        }
+    }
+}
+
+fn first_integer(res: Vec<Value>) -> Option<i64> {
+    match res.get(0) {
+        Some(&Value::Integer(i)) => Some(i),
+        _ => None,
+    }
+}
+
+#[test]
+fn refuel() {
+    let (res, refuels) = run_with_fuel(
+        r#"
+            local n = 0
+            for i = 1, 10000 do
+                n = n + i
+            end
+            return n
+        "#,
+        1000,
+        1000,
+    );
+    assert_eq!(res, Some(50005000));
+    assert!(refuels >= 20);
+}
+
+#[test]
+fn coroutines_share_fuel() {
+    let (res, refuels) = run_with_fuel(
+        r#"
+            local co = coroutine.wrap(function()
+                local n = 0
+                for i = 1, 10000 do
+                    n = n + i
+                end
+                coroutine.yield(n)
+            end)
+            local n = co()
+            assert(coroutine.status(coroutine.running()) == "running")
+            return n
+        "#,
+        500,
+        1000,
+    );
+    assert_eq!(res, Some(50005000));
+    assert!(refuels >= 40);
+}
+
+#[test]
+fn preempt_infinite_loop() {
+    let (res, refuels) = run_with_fuel("while true do end", 10000, 3);
+    assert_eq!((res, refuels), (None, 3));
+}
@@ -0,0 +1,163 @@
+use std::cell::Cell;
+use std::rc::Rc;
+
+use luster::{GcParameters, GcPolicy, Lua, StdlibSet, Table};
+
+#[test]
+fn collector_controls() {
+    let mut lua = Lua::new();
+    assert!(lua.is_collector_running());
+    lua.set_collector_running(false);
+    assert!(!lua.is_collector_running());
+
+    let before = lua.total_allocated();
+    for _ in 0..100 {
+        lua.mutate(|mc, _| {
+            for _ in 0..100 {
+                Table::new(mc);
+            }
+        });
+    }
+    // Nothing is freed while the collector is stopped
+    let garbage = lua.total_allocated();
+    assert!(garbage > before);
+
+    lua.collect_all();
+    assert!(lua.total_allocated() < garbage);
+
+    lua.set_collector_running(true);
+    assert!(lua.is_collector_running());
+}
+
+fn allocate_garbage(lua: &mut Lua) {
+    for _ in 0..100 {
+        lua.mutate(|mc, _| {
+            for _ in 0..100 {
+                Table::new(mc);
+            }
+        });
+    }
+}
+
+#[test]
+fn gc_parameters() {
+    let parameters = GcParameters::default()
+        .set_pause(1.0)
+        .set_step_multiplier(4.0);
+    assert_eq!(parameters.pause(), 1.0);
+    assert_eq!(parameters.step_multiplier(), 4.0);
+
+    let mut lua = Lua::with_gc_parameters(StdlibSet::ALL, parameters);
+    assert_eq!(lua.gc_parameters(), parameters);
+    let before = lua.total_allocated();
+    allocate_garbage(&mut lua);
+    // An eager collector keeps the heap close to what is live
+    assert!(lua.total_allocated() < before * 2);
+
+    lua.set_gc_step_size(0);
+    assert_eq!(lua.gc_step_size(), 0);
+    allocate_garbage(&mut lua);
+    assert!(lua.total_allocated() < before * 2);
+}
+
+#[test]
+fn set_gc_parameters() {
+    let mut lua = Lua::new();
+    lua.set_gc_pause(1.0);
+    lua.set_step_multiplier(4.0);
+    assert_eq!(
+        lua.gc_parameters(),
+        GcParameters::default()
+            .set_pause(1.0)
+            .set_step_multiplier(4.0)
+    );
+
+    // The new pause is used once the current collection finishes
+    lua.collect_all();
+    let before = lua.total_allocated();
+    allocate_garbage(&mut lua);
+    assert!(lua.total_allocated() < before * 2);
+}
+
+struct Deferred {
+    calls: Rc<Cell<usize>>,
+}
+
+impl GcPolicy for Deferred {
+    fn should_collect(&mut self, total_allocated: usize, allocation_debt: f64) -> bool {
+        assert!(total_allocated > 0);
+        assert!(allocation_debt > 0.0);
+        self.calls.set(self.calls.get() + 1);
+        false
+    }
+}
+
+#[test]
+fn gc_policy() {
+    let mut lua = Lua::new();
+    let calls = Rc::new(Cell::new(0));
+    assert!(lua
+        .set_gc_policy(Some(Box::new(Deferred {
+            calls: calls.clone()
+        })))
+        .is_none());
+
+    let before = lua.total_allocated();
+    allocate_garbage(&mut lua);
+    allocate_garbage(&mut lua);
+    assert!(calls.get() > 0);
+    // All of the work the policy deferred is still owed
+    assert!(lua.allocation_debt() > 0.0);
+    let deferred = lua.total_allocated();
+    assert!(deferred > before);
+
+    lua.collect_debt();
+    assert!(lua.allocation_debt() <= 0.0);
+
+    // Sequences consult the policy in-between steps as well
+    let seen = calls.get();
+    lua.exec("local t = {} for i = 1, 10000 do t = {i} end")
+        .unwrap();
+    assert!(calls.get() > seen);
+
+    assert!(lua.set_gc_policy(None).is_some());
+    allocate_garbage(&mut lua);
+    assert!(lua.total_allocated() < deferred);
+}
+
+#[test]
+fn finalizers() {
+    let mut lua = Lua::new();
+    lua.exec(
+        r#"
+            finalized = false
+            local function new()
+                setmetatable({}, {__gc = function() finalized = true end})
+            end
+            new()
+        "#,
+    )
+    .unwrap();
+    lua.collect_all();
+    // Finalizers are called by the next thread to run Lua code
+    assert!(lua.eval::<bool>("return finalized").unwrap());
+}
+
+#[test]
+fn weak_tables() {
+    let mut lua = Lua::new();
+    lua.exec(
+        r#"
+            cache = setmetatable({}, {__mode = "v"})
+            local function fill()
+                for i = 1, 100 do
+                    cache[i] = {}
+                end
+            end
+            fill()
+        "#,
+    )
+    .unwrap();
+    lua.collect_all();
+    assert!(lua.eval::<bool>("return next(cache) == nil").unwrap());
+}
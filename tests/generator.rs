@@ -0,0 +1,71 @@
+use luster::{Lua, RegistryKey, StaticError, String};
+
+fn stash_global(lua: &mut Lua, name: &'static str) -> RegistryKey {
+    lua.mutate(move |mc, root| {
+        root.registry
+            .stash(mc, root.globals.get(String::new_static(name.as_bytes())))
+    })
+}
+
+#[test]
+fn generator_items() -> Result<(), StaticError> {
+    let mut lua = Lua::new();
+    lua.exec(
+        r#"
+            function range(from, to)
+                for i = from, to do
+                    coroutine.yield(i, i * i)
+                end
+                return "done"
+            end
+        "#,
+    )?;
+    let range = stash_global(&mut lua, "range");
+    let items = lua
+        .generator::<_, (i64, i64)>(&range, (2, 5))?
+        .collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(items, vec![(2, 4), (3, 9), (4, 16), (5, 25)]);
+
+    // Arena state can be used between items
+    let mut generator = lua.generator::<_, i64>(&range, (1, 3))?;
+    assert_eq!(generator.next().transpose()?, Some(1));
+    assert_eq!(generator.next().transpose()?, Some(2));
+    drop(generator);
+    lua.exec("x = 1")?;
+
+    Ok(())
+}
+
+#[test]
+fn generator_errors() -> Result<(), StaticError> {
+    let mut lua = Lua::new();
+    lua.exec(
+        r#"
+            function failing()
+                coroutine.yield(1)
+                error("failed")
+            end
+            function strings()
+                coroutine.yield("a")
+                coroutine.yield({})
+            end
+        "#,
+    )?;
+
+    let failing = stash_global(&mut lua, "failing");
+    let mut generator = lua.generator::<_, i64>(&failing, ())?;
+    assert_eq!(generator.next().transpose()?, Some(1));
+    assert!(generator.next().unwrap().is_err());
+    assert!(generator.next().is_none());
+
+    let strings = stash_global(&mut lua, "strings");
+    let mut generator = lua.generator::<_, std::string::String>(&strings, ())?;
+    assert_eq!(generator.next().transpose()?.as_deref(), Some("a"));
+    assert!(generator.next().unwrap().is_err());
+    assert!(generator.next().is_none());
+
+    let not_function = stash_global(&mut lua, "missing");
+    assert!(lua.generator::<_, ()>(&not_function, ()).is_err());
+
+    Ok(())
+}
@@ -0,0 +1,127 @@
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use luster::{HookContext, HostHook, Lua, StaticError};
+
+#[derive(Default)]
+struct Recorder {
+    events: RefCell<Vec<String>>,
+    count_interval: u32,
+    counts: Cell<u32>,
+    count_depths: RefCell<Vec<usize>>,
+}
+
+impl Recorder {
+    fn record(&self, event: &str, context: &HookContext) {
+        let line = context
+            .closure()
+            .map(|closure| closure.0.proto.line_defined)
+            .unwrap();
+        assert_eq!(context.depth(), context.call_stack().len());
+        self.events
+            .borrow_mut()
+            .push(format!("{} {} {}", event, line, context.depth()));
+    }
+}
+
+impl HostHook for Recorder {
+    fn call(&self, context: &HookContext) {
+        self.record("call", context);
+    }
+
+    fn returns(&self, context: &HookContext) {
+        self.record("return", context);
+    }
+
+    fn count_interval(&self) -> u32 {
+        self.count_interval
+    }
+
+    fn count(&self, context: &HookContext) {
+        self.counts.set(self.counts.get() + 1);
+        self.count_depths.borrow_mut().push(context.depth());
+    }
+}
+
+fn set_host_hook(lua: &mut Lua, hook: Rc<Recorder>) {
+    lua.mutate(move |mc, root| root.main_thread.set_host_hook(mc, Some(hook)));
+}
+
+#[test]
+fn calls_and_returns() -> Result<(), StaticError> {
+    let mut lua = Lua::new();
+    let recorder = Rc::new(Recorder::default());
+    set_host_hook(&mut lua, recorder.clone());
+    lua.exec(
+        &r#"
+            local function g() return 1 end
+            local function f(n)
+                if n == 0 then return 0 end
+                return f(n - 1)
+            end
+            local x = g() + f(1)
+            pcall(function() error("boom") end)
+        "#[1..],
+    )?;
+
+    assert_eq!(
+        *recorder.events.borrow(),
+        vec![
+            "call 0 1",
+            "call 1 2",
+            "return 1 2",
+            // A tail call is a return followed by a call
+            "call 2 2",
+            "return 2 2",
+            "call 2 2",
+            "return 2 2",
+            // Called by `pcall`, a callback that causes no events of its own
+            "call 7 3",
+            "return 7 3",
+            "return 0 1",
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn count_events() -> Result<(), StaticError> {
+    let mut lua = Lua::new();
+    let recorder = Rc::new(Recorder {
+        count_interval: 10,
+        ..Recorder::default()
+    });
+    set_host_hook(&mut lua, recorder.clone());
+    lua.exec("local i = 0 while i < 1000 do i = i + 1 end")?;
+    // Each iteration of the loop runs at least three instructions
+    assert!(recorder.counts.get() >= 300);
+    assert!(recorder.count_depths.borrow().iter().all(|&d| d == 1));
+
+    let counts = recorder.counts.get();
+    lua.mutate(|mc, root| root.main_thread.set_host_hook(mc, None));
+    lua.exec("local i = 0 while i < 1000 do i = i + 1 end")?;
+    assert_eq!(recorder.counts.get(), counts);
+    Ok(())
+}
+
+#[test]
+fn inherited_by_coroutines() -> Result<(), StaticError> {
+    let mut lua = Lua::new();
+    let recorder = Rc::new(Recorder::default());
+    set_host_hook(&mut lua, recorder.clone());
+    lua.exec(
+        &r#"
+            local co = coroutine.create(function()
+                coroutine.yield()
+            end)
+            coroutine.resume(co)
+            coroutine.resume(co)
+        "#[1..],
+    )?;
+
+    assert_eq!(
+        *recorder.events.borrow(),
+        vec!["call 0 1", "call 1 1", "return 1 1", "return 0 1"]
+    );
+    Ok(())
+}
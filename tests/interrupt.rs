@@ -0,0 +1,115 @@
+use std::string::String as StdString;
+use std::thread;
+use std::time::Duration;
+
+use luster::{Callback, CallbackResult, Interrupt, Interrupted, Lua, StaticError, String};
+
+fn interrupted(error: StaticError) -> Interrupted {
+    match error.without_traceback() {
+        StaticError::LocatedError(_, error) => match *error {
+            StaticError::Interrupted(interrupted) => interrupted,
+            error => panic!("wrong error {}", error),
+        },
+        error => panic!("wrong error {}", error),
+    }
+}
+
+// Sets a global `trigger` function that triggers the interrupt from inside a script.
+fn set_trigger(lua: &mut Lua, interrupt: Interrupt, uncatchable: bool) {
+    lua.mutate(move |mc, root| {
+        let trigger = Callback::new_immediate(mc, move |_| {
+            if uncatchable {
+                interrupt.trigger_uncatchable();
+            } else {
+                interrupt.trigger();
+            }
+            Ok(CallbackResult::Return(Vec::new()))
+        });
+        root.globals
+            .set(mc, String::new_static(b"trigger"), trigger)
+            .unwrap();
+    });
+}
+
+#[test]
+fn timeout() -> Result<(), StaticError> {
+    let mut lua = Lua::new();
+    let interrupt = Interrupt::new();
+    lua.set_interrupt(Some(interrupt.clone()));
+
+    let timer = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(50));
+        interrupt.trigger_uncatchable();
+    });
+    let error = lua.exec("while true do end").unwrap_err();
+    timer.join().unwrap();
+    assert!(!error.is_catchable());
+    assert_eq!(interrupted(error), Interrupted { catchable: false });
+
+    // The interrupt is only raised once for every trigger
+    assert_eq!(lua.eval::<i64>("return 1 + 1")?, 2);
+
+    Ok(())
+}
+
+#[test]
+fn catchable() -> Result<(), StaticError> {
+    let mut lua = Lua::new();
+    let interrupt = Interrupt::new();
+    lua.set_interrupt(Some(interrupt.clone()));
+    set_trigger(&mut lua, interrupt.clone(), false);
+
+    let (ok, message) = lua.eval::<(bool, StdString)>(
+        r#"
+            return pcall(function()
+                trigger()
+                while true do end
+            end)
+        "#,
+    )?;
+    assert!(!interrupt.is_triggered());
+    assert!(!ok);
+    assert!(message.ends_with("interrupted"));
+
+    interrupt.trigger();
+    assert!(interrupt.is_triggered());
+    interrupt.reset();
+    assert!(!interrupt.is_triggered());
+    assert_eq!(lua.eval::<i64>("return 3")?, 3);
+
+    interrupt.trigger();
+    let error = lua.exec("while true do end").unwrap_err();
+    assert!(error.is_catchable());
+    assert_eq!(interrupted(error), Interrupted { catchable: true });
+
+    Ok(())
+}
+
+#[test]
+fn uncatchable() -> Result<(), StaticError> {
+    let mut lua = Lua::new();
+    let interrupt = Interrupt::new();
+    lua.set_interrupt(Some(interrupt.clone()));
+    set_trigger(&mut lua, interrupt, true);
+    lua.exec("handled = false")?;
+    let error = lua
+        .exec(
+            r#"
+                local co = coroutine.create(function()
+                    xpcall(function()
+                        trigger()
+                        while true do end
+                    end, function()
+                        handled = true
+                    end)
+                end)
+                coroutine.resume(co)
+                error("unreachable")
+            "#,
+        )
+        .unwrap_err();
+    assert_eq!(interrupted(error), Interrupted { catchable: false });
+    assert!(!lua.eval::<bool>("return handled")?);
+
+    Ok(())
+}
@@ -0,0 +1,99 @@
+use std::fs;
+
+use luster::{Lua, StaticError, String, Value};
+
+#[test]
+fn io_files() -> Result<(), Box<StaticError>> {
+    let dir = std::env::temp_dir().join(format!("luster_io_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let dir_name = dir.to_str().unwrap().to_owned();
+
+    let mut lua = Lua::new();
+    lua.mutate(move |mc, root| {
+        root.globals
+            .set(
+                mc,
+                String::new_static(b"dir"),
+                Value::String(String::new(mc, dir_name.as_bytes())),
+            )
+            .unwrap();
+    });
+    let res = lua.exec(
+        r#"
+            local name = dir .. "/file.txt"
+            local f = assert(io.open(name, "w"))
+            assert(io.type(f) == "file" and type(f) == "userdata")
+            assert(f:write("first line\n", 42, " ", 1.5, "\n") == f)
+            assert(f:write("0x10 -3e2 rest\nlast") == f)
+            assert(f:close() == true)
+            assert(io.type(f) == "closed file" and tostring(f) == "file (closed)")
+            assert(not pcall(f.write, f, "more"))
+            assert(select(2, pcall(f.read, f)) == "attempt to use a closed file")
+
+            f = assert(io.open(name))
+            assert(f:read() == "first line")
+            local a, b = f:read("n", "n")
+            assert(math.type(a) == "integer" and a == 42 and math.type(b) == "float" and b == 1.5)
+            assert(f:read("L") == "\n")
+            assert(f:read("*n") == 16 and f:read("n") == -300)
+            assert(f:read(0) == "" and f:read(5) == " rest")
+            assert(f:seek("cur") == 32)
+            local line, rest = f:read("l", "a")
+            assert(line == "" and rest == "last")
+            assert(f:read("a") == "" and f:read("l") == nil and f:read(0) == nil)
+            assert(f:seek("set", 6) == 6 and f:read(4) == "line")
+            assert(f:seek("end") == 37)
+            local x, y = f:read("n", "l")
+            assert(x == nil and y == nil)
+            assert(not pcall(f.read, f, "x"))
+            f:close()
+
+            local lines = {}
+            for line in io.lines(name) do
+                lines[#lines + 1] = line
+            end
+            assert(#lines == 4 and lines[1] == "first line" and lines[4] == "last")
+
+            f = assert(io.open(name, "a+"))
+            f:write("\nappended")
+            f:seek("set")
+            local count = 0
+            for a, b in f:lines(1, "l") do
+                count = count + 1
+                assert(#a == 1)
+            end
+            assert(count == 5)
+            f:close()
+
+            f = assert(io.open(name, "r+"))
+            assert(f:read(5) == "first")
+            f:write("!")
+            f:seek("set")
+            assert(f:read() == "first!line")
+            f:close()
+
+            assert(io.output() == io.stdout and io.input() == io.stdin)
+            local out = io.output(dir .. "/out.txt")
+            assert(io.type(out) == "file" and io.output() == out)
+            assert(io.write("a", 1, "\n", "b") == out)
+            assert(io.close() == true)
+            io.output(io.stdout)
+            io.input(dir .. "/out.txt")
+            assert(io.read() == "a1" and io.read("L") == "b" and io.read() == nil)
+            io.input():close()
+            io.input(io.stdin)
+
+            local ok, err, code = io.open(dir .. "/missing.txt")
+            assert(ok == nil and err == dir .. "/missing.txt: No such file or directory")
+            assert(math.type(code) == "integer")
+            assert(not pcall(io.open, name, "rw"))
+            assert(not pcall(io.lines, dir .. "/missing.txt"))
+            assert(io.stdout:close() == nil)
+            assert(io.type(io.stdout) == "file" and io.type(42) == nil)
+            assert(getmetatable(io.stdout).__name == "FILE*")
+        "#,
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+    Ok(res?)
+}
@@ -1,6 +1,6 @@
 use std::f64;
 
-use luster::{Lexer, Token};
+use luster::{Lexer, LexerError, Token};
 
 fn test_tokens(source: &str, tokens: &[Token<Box<[u8]>>]) {
     let mut lexer = Lexer::new(source.as_bytes(), |s| s.to_vec().into_boxed_slice());
@@ -210,3 +210,75 @@ fn ops() {
         ],
     );
 }
+
+#[test]
+fn escapes() {
+    test_tokens(
+        r#"
+            "\a\b\f\n\r\t\v\\\"\'"
+            "\x41\x7a\x7E\xff"
+            "\65\066\0671\0\255"
+            "\u{41}\u{0000041}\u{e9}\u{20AC}\u{10FFFF}\u{D800}\u{7FFFFFFF}"
+            "a\z
+
+               b\z c"
+            "line\
+break"
+        "#,
+        &[
+            Token::String(b"\x07\x08\x0c\n\r\t\x0b\\\"'".to_vec().into_boxed_slice()),
+            Token::String(b"Az~\xff".to_vec().into_boxed_slice()),
+            Token::String(b"AB\x431\x00\xff".to_vec().into_boxed_slice()),
+            Token::String(
+                b"AA\xc3\xa9\xe2\x82\xac\xf4\x8f\xbf\xbf\xed\xa0\x80\xfd\xbf\xbf\xbf\xbf\xbf"
+                    .to_vec()
+                    .into_boxed_slice(),
+            ),
+            str_token("abc"),
+            str_token("line\nbreak"),
+        ],
+    );
+}
+
+#[test]
+fn escape_errors() {
+    fn error_at(source: &str) -> (LexerError, u64, u64) {
+        let mut lexer = Lexer::new(source.as_bytes(), |s| s.to_vec().into_boxed_slice());
+        loop {
+            match lexer.read_token() {
+                Ok(Some(_)) => {}
+                Ok(None) => panic!("no lexer error in {:?}", source),
+                Err(err) => return (err, lexer.line_number(), lexer.column_number()),
+            }
+        }
+    }
+
+    match error_at("x = \"\\q\"") {
+        (LexerError::InvalidEscape, 0, 6) => {}
+        err => panic!("wrong error {:?}", err),
+    }
+    match error_at("x = 1\ny = \"ab\\x4g\"") {
+        (LexerError::HexDigitExpected, 1, 10) => {}
+        err => panic!("wrong error {:?}", err),
+    }
+    match error_at("\"\\u41\"") {
+        (LexerError::EscapeUnicodeStart, 0, 3) => {}
+        err => panic!("wrong error {:?}", err),
+    }
+    match error_at("\"\\u{}\"") {
+        (LexerError::HexDigitExpected, 0, 4) => {}
+        err => panic!("wrong error {:?}", err),
+    }
+    match error_at("\"\\u{41\"") {
+        (LexerError::EscapeUnicodeEnd, 0, 6) => {}
+        err => panic!("wrong error {:?}", err),
+    }
+    match error_at("\"\\u{80000000}\"") {
+        (LexerError::EscapeUnicodeInvalid, 0, 11) => {}
+        err => panic!("wrong error {:?}", err),
+    }
+    match error_at("\"\\256\"") {
+        (LexerError::EscapeDecimalTooLarge, 0, 5) => {}
+        err => panic!("wrong error {:?}", err),
+    }
+}
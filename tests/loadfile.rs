@@ -0,0 +1,76 @@
+use std::fs;
+
+use luster::{Lua, StaticError, String, Value};
+
+#[test]
+fn loadfile_dofile() -> Result<(), Box<StaticError>> {
+    let dir = std::env::temp_dir().join(format!("luster_loadfile_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let script = dir.join("script.lua");
+    fs::write(
+        &script,
+        "#!/usr/bin/env lua\nlocal a, b = ...\nreturn (a or 1) + (b or 2), x\n",
+    )
+    .unwrap();
+    let failing = dir.join("failing.lua");
+    fs::write(&failing, "\n\nerror('failed')\n").unwrap();
+    let broken = dir.join("broken.lua");
+    fs::write(&broken, "return +").unwrap();
+
+    // Long chunk names are shortened in error messages
+    let failing_name = failing.to_str().unwrap();
+    let failing_location = if failing_name.len() <= 59 {
+        failing_name.to_owned()
+    } else {
+        format!("...{}", &failing_name[failing_name.len() - 56..])
+    };
+
+    let mut lua = Lua::new();
+    lua.mutate(|mc, root| {
+        root.globals
+            .set(
+                mc,
+                String::new_static(b"failing_location"),
+                Value::String(String::new(mc, failing_location.as_bytes())),
+            )
+            .unwrap();
+        for &(name, path) in &[
+            (&b"script"[..], &script),
+            (b"failing", &failing),
+            (b"broken", &broken),
+            (b"missing", &dir.join("missing.lua")),
+        ] {
+            root.globals
+                .set(
+                    mc,
+                    String::new_static(name),
+                    Value::String(String::new(mc, path.to_str().unwrap().as_bytes())),
+                )
+                .unwrap();
+        }
+    });
+
+    let res = lua.exec(
+        r#"
+            local f = loadfile(script)
+            assert(f() == 3 and f(10, 20) == 30)
+            local sum, x = loadfile(script, "t", { x = "env" })()
+            assert(sum == 3 and x == "env")
+            assert(loadfile(script, "b") == nil)
+            assert(dofile(script) == 3 and select('#', dofile(script)) == 2)
+
+            local f, err = loadfile(broken)
+            assert(f == nil and err ~= nil)
+            local f, err = loadfile(missing)
+            assert(f == nil and type(err) == "string")
+
+            local ok, err = pcall(dofile, failing)
+            assert(not ok and err == failing_location .. ":3: failed")
+            assert(not pcall(dofile, missing))
+            assert(not pcall(dofile, broken))
+        "#,
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+    Ok(res?)
+}
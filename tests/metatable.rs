@@ -0,0 +1,391 @@
+use gc_sequence::{self as sequence, SequenceExt, SequenceResultExt};
+use luster::{compile, Closure, Error, Function, Lua, StaticError, ThreadSequence, Value};
+
+// Runs the given code and checks that it returns true.
+fn run(code: &'static [u8]) -> Result<(), Box<StaticError>> {
+    let mut lua = Lua::new();
+    lua.sequence(move |root| {
+        sequence::from_fn_with(root, move |mc, root| {
+            Ok(Closure::new(
+                mc,
+                compile(mc, root.interned_strings, "=test", code)?,
+                Some(root.globals),
+            )?)
+        })
+        .and_chain_with(root, |mc, root, closure| {
+            Ok(ThreadSequence::call_function(
+                mc,
+                root.main_thread,
+                Function::Closure(closure),
+                &[],
+            )?)
+        })
+        .map_ok(|b| assert_eq!(b, vec![Value::Boolean(true)]))
+        .map_err(Error::to_static)
+        .boxed()
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn index() -> Result<(), Box<StaticError>> {
+    run(&br#"
+            local base = { a = 1 }
+            local derived = setmetatable({ b = 2 }, { __index = base })
+            local leaf = setmetatable({}, { __index = derived })
+
+            local computed = setmetatable({}, {
+                __index = function(t, k)
+                    return k .. "!"
+                end
+            })
+
+            local Class = {}
+            Class.__index = Class
+            function Class.new(v)
+                return setmetatable({ v = v }, Class)
+            end
+            function Class:get()
+                return self.v
+            end
+
+            local obj = Class.new(7)
+
+            return
+                derived.a == 1 and derived.b == 2 and derived.c == nil and
+                leaf.a == 1 and leaf.b == 2 and
+                computed.foo == "foo!" and computed[1] == "1!" and
+                obj:get() == 7
+        "#[..])
+}
+
+#[test]
+fn index_loop() -> Result<(), Box<StaticError>> {
+    run(&br#"
+            local t = {}
+            setmetatable(t, { __index = setmetatable({}, { __index = t }) })
+            local ok = pcall(function() return t.missing end)
+            return not ok
+        "#[..])
+}
+
+#[test]
+fn new_index() -> Result<(), Box<StaticError>> {
+    run(&br#"
+            local log = {}
+            local logged = setmetatable({ present = 1 }, {
+                __newindex = function(t, k, v)
+                    log[#log + 1] = k
+                    rawset(t, k, v * 2)
+                end
+            })
+            logged.present = 2
+            logged.a = 3
+            logged.a = 4
+
+            local store = {}
+            local proxy = setmetatable({}, { __newindex = store })
+            local outer = setmetatable({}, { __newindex = proxy })
+            outer.x = 5
+
+            local raw = setmetatable({}, { __newindex = function() error("called") end })
+            rawset(raw, "y", 6)
+
+            return
+                logged.present == 2 and logged.a == 4 and #log == 1 and log[1] == "a" and
+                store.x == 5 and proxy.x == nil and outer.x == nil and
+                raw.y == 6
+        "#[..])
+}
+
+#[test]
+fn arithmetic() -> Result<(), Box<StaticError>> {
+    run(&br#"
+            local V = {}
+            local function vec(x, y)
+                return setmetatable({ x = x, y = y }, V)
+            end
+            local function lift(a)
+                if type(a) == "number" then
+                    return vec(a, a)
+                end
+                return a
+            end
+
+            V.__add = function(a, b) a, b = lift(a), lift(b) return vec(a.x + b.x, a.y + b.y) end
+            V.__sub = function(a, b) a, b = lift(a), lift(b) return vec(a.x - b.x, a.y - b.y) end
+            V.__mul = function(a, b) a, b = lift(a), lift(b) return vec(a.x * b.x, a.y * b.y) end
+            V.__div = function(a, b) a, b = lift(a), lift(b) return vec(a.x / b.x, a.y / b.y) end
+            V.__mod = function(a, b) a, b = lift(a), lift(b) return vec(a.x % b.x, a.y % b.y) end
+            V.__idiv = function(a, b) a, b = lift(a), lift(b) return vec(a.x // b.x, a.y // b.y) end
+            V.__pow = function(a, b) a, b = lift(a), lift(b) return vec(a.x ^ b.x, a.y ^ b.y) end
+            V.__unm = function(a) return vec(-a.x, -a.y) end
+
+            local a = vec(6, 8)
+            local b = vec(2, 3)
+
+            local add = a + b
+            local sub = a - b
+            local mul = a * 2
+            local div = 12 / b
+            local mod = a % b
+            local idiv = a // b
+            local pow = b ^ 2
+            local unm = -a
+
+            local ok = pcall(function() return {} + 1 end)
+
+            return
+                add.x == 8 and add.y == 11 and
+                sub.x == 4 and sub.y == 5 and
+                mul.x == 12 and mul.y == 16 and
+                div.x == 6 and div.y == 4 and
+                mod.x == 0 and mod.y == 2 and
+                idiv.x == 3 and idiv.y == 2 and
+                pow.x == 4 and pow.y == 9 and
+                unm.x == -6 and unm.y == -8 and
+                not ok
+        "#[..])
+}
+
+#[test]
+fn call() -> Result<(), Box<StaticError>> {
+    run(&br#"
+            local Point = setmetatable({}, {
+                __call = function(cls, x, y)
+                    return setmetatable({ x = x, y = y }, cls)
+                end
+            })
+            Point.__index = Point
+
+            local p = Point(1, 2)
+
+            local counter = setmetatable({ n = 0 }, {
+                __call = function(self, inc, extra)
+                    self.n = self.n + inc
+                    return self.n, extra
+                end
+            })
+            local a, extra = counter(5, 2, 3)
+            local b = counter(2)
+
+            local function tail()
+                return counter(3)
+            end
+            local c = tail()
+
+            local sum = 0
+            local iter = setmetatable({}, {
+                __call = function(self, state, i)
+                    if i < 3 then
+                        return i + 1
+                    end
+                end
+            })
+            for i in iter, nil, 0 do
+                sum = sum + i
+            end
+
+            local ok = pcall(function() local t = {} t() end)
+
+            return
+                p.x == 1 and p.y == 2 and
+                a == 5 and extra == 2 and b == 7 and c == 10 and
+                sum == 6 and not ok
+        "#[..])
+}
+
+#[test]
+fn tostring() -> Result<(), Box<StaticError>> {
+    run(&br#"
+            local named = setmetatable({ name = "thing" }, {
+                __tostring = function(self)
+                    return "named " .. self.name
+                end
+            })
+            local bad = setmetatable({}, {
+                __tostring = function(self)
+                    return {}
+                end
+            })
+
+            print("print with __tostring:", named, 1, named)
+
+            return
+                tostring(named) == "named thing" and
+                not pcall(tostring, bad) and
+                not pcall(print, bad)
+        "#[..])
+}
+
+#[test]
+fn comparison() -> Result<(), Box<StaticError>> {
+    run(&br#"
+            local eq_calls = 0
+            local V = {}
+            V.__eq = function(a, b)
+                eq_calls = eq_calls + 1
+                return a.v == b.v
+            end
+            V.__lt = function(a, b)
+                return a.v < b.v
+            end
+            V.__le = function(a, b)
+                return a.v <= b.v
+            end
+
+            local function v(n)
+                return setmetatable({ v = n }, V)
+            end
+
+            local a, b, c = v(1), v(2), v(1)
+
+            local eq = a == c
+            local ne = a ~= b
+            local same = a == a
+            local other_type = a == 1
+
+            local lt = a < b
+            local gt = b > a
+            local le = a <= c
+            local ge = b >= a
+            local not_lt = b < a
+
+            local branch
+            if a < b and b >= c then
+                branch = true
+            else
+                branch = false
+            end
+
+            local plain = setmetatable({}, {})
+            local ok = pcall(function() return plain < plain end)
+
+            return
+                eq and ne and same and not other_type and eq_calls == 2 and
+                lt and gt and le and ge and not not_lt and
+                branch and not ok and {} ~= {}
+        "#[..])
+}
+
+#[test]
+fn len() -> Result<(), Box<StaticError>> {
+    run(&br#"
+            local backing = { 1, 2, 3 }
+            local proxy = setmetatable({}, {
+                __len = function(self)
+                    return #backing
+                end
+            })
+            local constant = setmetatable({ 1, 2 }, {
+                __len = function()
+                    return 42
+                end
+            })
+            local plain = setmetatable({ 1, 2 }, {})
+
+            return #proxy == 3 and #constant == 42 and #plain == 2 and #"abc" == 3
+        "#[..])
+}
+
+#[test]
+fn concat() -> Result<(), Box<StaticError>> {
+    run(&br#"
+            local order = {}
+            local S = {}
+            S.__concat = function(a, b)
+                local as = type(a) == "table" and a.s or a
+                local bs = type(b) == "table" and b.s or b
+                order[#order + 1] = as .. "+" .. bs
+                return setmetatable({ s = as .. bs }, S)
+            end
+            local function s(v)
+                return setmetatable({ s = v }, S)
+            end
+
+            local r1 = s("a") .. "b"
+            local r2 = "a" .. s("b") .. "c" .. "d"
+            local r3 = 1 .. s("x") .. 2
+
+            local ok1 = pcall(function() return "a" .. {} end)
+            local ok2 = pcall(function() return "a" .. nil end)
+
+            return
+                r1.s == "ab" and r2.s == "abcd" and r3.s == "1x2" and
+                order[2] == "b+cd" and order[3] == "a+bcd" and
+                not ok1 and not ok2
+        "#[..])
+}
+
+#[test]
+fn to_be_closed() -> Result<(), Box<StaticError>> {
+    run(&br#"
+            local log = {}
+            local function closable(name)
+                return setmetatable({}, {
+                    __close = function(self, err)
+                        log[#log + 1] = name
+                        if err ~= nil then
+                            log[#log + 1] = err
+                        end
+                    end
+                })
+            end
+            local function joined()
+                local s = ""
+                for i = 1, #log do
+                    s = s .. log[i] .. ";"
+                end
+                log = {}
+                return s
+            end
+
+            do
+                local a <close> = closable("a")
+                local b <close> = closable("b")
+                local c <close> = nil
+                local d <const> = 4
+            end
+            local scope = joined()
+
+            for i = 1, 3 do
+                local x <close> = closable("x" .. i)
+                if i == 2 then
+                    break
+                end
+            end
+            local loop = joined()
+
+            local function ret(...)
+                local r <close> = closable("r")
+                return ...
+            end
+            local r1, r2, r3 = ret(1, 2, 3)
+            local returned = joined()
+
+            local function tail()
+                local t <close> = closable("t")
+                return ret(4)
+            end
+            local t1 = tail()
+            local tailed = joined()
+
+            local ok, err = pcall(function()
+                local e <close> = closable("e")
+                error("boom", 0)
+            end)
+            local errored = joined()
+
+            local bad = pcall(function()
+                local v <close> = {}
+            end)
+
+            return
+                scope == "b;a;" and loop == "x1;x2;" and
+                r1 == 1 and r2 == 2 and r3 == 3 and returned == "r;" and
+                t1 == 4 and tailed == "r;t;" and
+                not ok and err == "boom" and errored == "e;boom;" and
+                not bad
+        "#[..])
+}
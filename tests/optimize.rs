@@ -0,0 +1,143 @@
+use gc_sequence::{self as sequence, SequenceExt, SequenceResultExt};
+use luster::{
+    optimize_opcodes, Closure, Constant, ConstantIndex16, Error, Function, FunctionProto, Lua,
+    OpCode, Opt254, RegisterIndex, StaticError, ThreadSequence, Value, VarCount,
+};
+
+fn run_opcodes(opcodes: Vec<OpCode>, args: Vec<i64>) -> Result<Vec<i64>, Box<StaticError>> {
+    let mut lua = Lua::new();
+    Ok(lua.sequence(move |root| {
+        sequence::from_fn_with(root, move |mc, _| {
+            Ok(Closure::new(
+                mc,
+                FunctionProto {
+                    fixed_params: 2,
+                    has_varargs: false,
+                    stack_size: 4,
+                    constants: vec![Constant::Integer(0), Constant::Integer(10)],
+                    opcodes,
+                    upvalues: Vec::new(),
+                    upvalue_names: Vec::new(),
+                    local_variables: Vec::new(),
+                    prototypes: Vec::new(),
+                    chunk_name: None,
+                    line_info: Vec::new(),
+                    line_defined: 0,
+                    last_line_defined: 0,
+                    coverage: None,
+                },
+                None,
+            )?)
+        })
+        .and_chain_with(root, move |mc, root, closure| {
+            let args = args.iter().map(|&i| Value::Integer(i)).collect::<Vec<_>>();
+            Ok(ThreadSequence::call_function(
+                mc,
+                root.main_thread,
+                Function::Closure(closure),
+                &args,
+            )?)
+        })
+        .map_ok(|res| {
+            res.iter()
+                .map(|v| match v {
+                    Value::Integer(i) => *i,
+                    _ => panic!("non-integer result"),
+                })
+                .collect()
+        })
+        .map_err(Error::to_static)
+        .boxed()
+    })?)
+}
+
+#[test]
+fn optimize() -> Result<(), Box<StaticError>> {
+    let r = RegisterIndex;
+    let k = ConstantIndex16;
+    let opcodes = vec![
+        OpCode::Move {
+            dest: r(2),
+            source: r(0),
+        },
+        // Repeats the previous move
+        OpCode::Move {
+            dest: r(2),
+            source: r(0),
+        },
+        // Undoes the previous move
+        OpCode::Move {
+            dest: r(0),
+            source: r(2),
+        },
+        OpCode::Move {
+            dest: r(3),
+            source: r(3),
+        },
+        // Dead store
+        OpCode::LoadConstant {
+            dest: r(3),
+            constant: k(0),
+        },
+        OpCode::LoadConstant {
+            dest: r(3),
+            constant: k(1),
+        },
+        OpCode::LessRR {
+            skip_if: false,
+            left: r(2),
+            right: r(1),
+        },
+        // Jump to a jump, which cannot be removed as it follows a skip
+        OpCode::Jump {
+            offset: 1,
+            close_upvalues: Opt254::none(),
+        },
+        OpCode::Jump {
+            offset: 2,
+            close_upvalues: Opt254::none(),
+        },
+        OpCode::Jump {
+            offset: 2,
+            close_upvalues: Opt254::none(),
+        },
+        // Unreachable
+        OpCode::LoadConstant {
+            dest: r(3),
+            constant: k(0),
+        },
+        OpCode::AddRR {
+            dest: r(3),
+            left: r(3),
+            right: r(2),
+        },
+        OpCode::Jump {
+            offset: 0,
+            close_upvalues: Opt254::none(),
+        },
+        OpCode::Return {
+            start: r(3),
+            count: VarCount::constant(1),
+        },
+    ];
+
+    let mut optimized = opcodes.clone();
+    optimize_opcodes(&mut optimized, &mut Vec::new(), &mut []);
+    assert_eq!(optimized.len(), 5);
+    // The comparison is fused with the threaded jump after it
+    match optimized[2] {
+        OpCode::JumpIfLessRR { jump: 1, .. } => {}
+        _ => panic!("jump to jump was not threaded and fused"),
+    }
+
+    for &args in &[[1, 2], [5, 2], [2, 2]] {
+        assert_eq!(
+            run_opcodes(opcodes.clone(), args.to_vec())?,
+            run_opcodes(optimized.clone(), args.to_vec())?
+        );
+    }
+    assert_eq!(run_opcodes(optimized.clone(), vec![1, 2])?, vec![10]);
+    assert_eq!(run_opcodes(optimized, vec![5, 2])?, vec![15]);
+
+    Ok(())
+}
@@ -0,0 +1,145 @@
+use std::collections::HashMap;
+use std::fs;
+
+use luster::{Lua, Searcher, StaticError, String, Value};
+
+struct MemorySearcher(HashMap<&'static str, &'static str>);
+
+impl Searcher for MemorySearcher {
+    fn search(&self, name: &[u8]) -> Result<(std::string::String, Vec<u8>), std::string::String> {
+        let name = std::str::from_utf8(name).unwrap();
+        match self.0.get(name) {
+            Some(source) => Ok((format!("=memory:{}", name), source.as_bytes().to_vec())),
+            None => Err(format!("no module '{}' in memory", name)),
+        }
+    }
+}
+
+#[test]
+fn require_preload() -> Result<(), Box<StaticError>> {
+    let mut lua = Lua::new();
+    lua.exec(
+        r#"
+            local calls = 0
+            package.preload["mod.a"] = function(name, data)
+                calls = calls + 1
+                assert(name == "mod.a" and data == ":preload:")
+                return { value = 42 }
+            end
+            package.preload.empty = function() end
+            package.preload.self_loading = function(name)
+                package.loaded[name] = "stored"
+            end
+
+            local a, data = require("mod.a")
+            assert(a.value == 42 and data == ":preload:")
+            assert(require("mod.a") == a and calls == 1)
+            assert(package.loaded["mod.a"] == a)
+            assert(require("empty") == true)
+            assert(require("self_loading") == "stored")
+            assert(require("string") == string and require("package") == package)
+        "#,
+    )?;
+    Ok(())
+}
+
+#[test]
+fn require_not_found() -> Result<(), Box<StaticError>> {
+    let mut lua = Lua::new();
+    lua.exec(
+        r#"
+            package.path = "./missing/?.lua;./missing/?/init.lua"
+            local ok, err = pcall(require, "a.b")
+            assert(not ok)
+            assert(err == "module 'a.b' not found:\n" ..
+                "\tno field package.preload['a.b']\n" ..
+                "\tno file './missing/a/b.lua'\n" ..
+                "\tno file './missing/a/b/init.lua'")
+
+            package.path = 1
+            assert(select(2, pcall(require, "a")) == "'package.path' must be a string")
+            package.searchers = nil
+            assert(select(2, pcall(require, "a")) == "'package.searchers' must be a table")
+        "#,
+    )?;
+    Ok(())
+}
+
+#[test]
+fn require_custom_searcher() -> Result<(), Box<StaticError>> {
+    let mut lua = Lua::new();
+    let mut modules = HashMap::new();
+    modules.insert(
+        "greet",
+        "local name = ... return function() return 'hello from ' .. name end",
+    );
+    modules.insert("uses_greet", "return require('greet')() .. '!'");
+    modules.insert("broken", "return +");
+    lua.add_searcher(MemorySearcher(modules));
+
+    lua.exec(
+        r#"
+            local greet, data = require("greet")
+            assert(greet() == "hello from greet" and data == "=memory:greet")
+            assert(require("uses_greet") == "hello from greet!")
+
+            local ok, err = pcall(require, "broken")
+            assert(not ok and err == "error loading module 'broken' from '=memory:broken':\n\t" ..
+                "memory:broken:1:8: unexpected symbol near '+'")
+
+            local ok, err = pcall(require, "nowhere")
+            assert(not ok and err == "module 'nowhere' not found:\n" ..
+                "\tno field package.preload['nowhere']\n" ..
+                "\tno file './nowhere.lua'\n" ..
+                "\tno file './nowhere/init.lua'\n" ..
+                "\tno module 'nowhere' in memory")
+        "#,
+    )?;
+    Ok(())
+}
+
+#[test]
+fn require_file() -> Result<(), Box<StaticError>> {
+    let dir = std::env::temp_dir().join(format!("luster_require_{}", std::process::id()));
+    fs::create_dir_all(dir.join("pkg")).unwrap();
+    fs::write(
+        dir.join("pkg").join("init.lua"),
+        "return { sub = require('pkg.sub') }",
+    )
+    .unwrap();
+    fs::write(dir.join("pkg").join("sub.lua"), "return select(2, ...)").unwrap();
+
+    let mut lua = Lua::new();
+    let path = format!("{0}/?.lua;{0}/?/init.lua", dir.display());
+    let sub_path = format!("{}/pkg/sub.lua", dir.display());
+    lua.mutate(|mc, root| {
+        root.package
+            .set(
+                mc,
+                String::new_static(b"path"),
+                Value::String(String::new(mc, path.as_bytes())),
+            )
+            .unwrap();
+        root.globals
+            .set(
+                mc,
+                String::new_static(b"sub_path"),
+                Value::String(String::new(mc, sub_path.as_bytes())),
+            )
+            .unwrap();
+    });
+    let res = lua.exec(
+        r#"
+            local pkg = require("pkg")
+            assert(pkg.sub == sub_path)
+            assert(package.searchpath("pkg.sub", package.path) == pkg.sub)
+            assert(package.searchpath("pkg_sub", package.path, "_") == pkg.sub)
+
+            local found, err = package.searchpath("none", "a/?.x;b/?.y")
+            assert(found == nil and err == "no file 'a/none.x'\n\tno file 'b/none.y'")
+        "#,
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+    Ok(res?)
+}
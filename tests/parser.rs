@@ -1,8 +1,20 @@
 use luster::parser::{
-    parse_chunk, Block, CallSuffix, Chunk, ConstructorField, Expression, FunctionCallStatement,
-    HeadExpression, PrimaryExpression, SimpleExpression, Statement, SuffixedExpression,
-    TableConstructor,
+    parse_chunk, walk_function_call_statement, walk_function_definition, walk_suffixed_expression,
+    Block, CallSuffix, Chunk, ConstructorField, Expression, FunctionCallStatement,
+    FunctionDefinition, HeadExpression, PrimaryExpression, SimpleExpression, Statement, SuffixPart,
+    SuffixedExpression, TableConstructor, Visitor,
 };
+use luster::{Position, Span};
+
+fn span(line: u64, start: u64, end: u64) -> Span {
+    Span {
+        start: Position {
+            line,
+            column: start,
+        },
+        end: Position { line, column: end },
+    }
+}
 
 #[test]
 fn test_function_call() {
@@ -14,66 +26,154 @@ fn test_function_call() {
         Chunk {
             block: Block {
                 statements: vec![
-                    Statement::FunctionCall(FunctionCallStatement {
-                        head: SuffixedExpression {
-                            primary: PrimaryExpression::Name(
-                                "print".as_bytes().to_vec().into_boxed_slice(),
-                            ),
-                            suffixes: vec![],
-                        },
-                        call: CallSuffix::Function(vec![
-                            Expression {
-                                head: Box::new(HeadExpression::Simple(SimpleExpression::Integer(
-                                    10,
-                                ))),
-                                tail: vec![],
+                    (
+                        Statement::FunctionCall(FunctionCallStatement {
+                            head: SuffixedExpression {
+                                primary: PrimaryExpression::Name(
+                                    "print".as_bytes().to_vec().into_boxed_slice(),
+                                ),
+                                suffixes: vec![],
+                            },
+                            call: CallSuffix::Function(vec![
+                                Expression {
+                                    head: Box::new(HeadExpression::Simple(
+                                        SimpleExpression::Integer(10,)
+                                    )),
+                                    tail: vec![],
+                                },
+                                Expression {
+                                    head: Box::new(HeadExpression::Simple(
+                                        SimpleExpression::Integer(20,)
+                                    )),
+                                    tail: vec![],
+                                },
+                            ]),
+                        }),
+                        span(0, 0, 13),
+                    ),
+                    (
+                        Statement::FunctionCall(FunctionCallStatement {
+                            head: SuffixedExpression {
+                                primary: PrimaryExpression::Name(
+                                    "print".as_bytes().to_vec().into_boxed_slice(),
+                                ),
+                                suffixes: vec![],
                             },
-                            Expression {
-                                head: Box::new(HeadExpression::Simple(SimpleExpression::Integer(
-                                    20,
+                            call: CallSuffix::Function(vec![Expression {
+                                head: Box::new(HeadExpression::Simple(SimpleExpression::String(
+                                    "foo".as_bytes().to_vec().into_boxed_slice(),
                                 ))),
                                 tail: vec![],
+                            },]),
+                        }),
+                        span(0, 14, 24),
+                    ),
+                    (
+                        Statement::FunctionCall(FunctionCallStatement {
+                            head: SuffixedExpression {
+                                primary: PrimaryExpression::Name(
+                                    "print".as_bytes().to_vec().into_boxed_slice(),
+                                ),
+                                suffixes: vec![],
                             },
-                        ]),
-                    }),
-                    Statement::FunctionCall(FunctionCallStatement {
-                        head: SuffixedExpression {
-                            primary: PrimaryExpression::Name(
-                                "print".as_bytes().to_vec().into_boxed_slice(),
-                            ),
-                            suffixes: vec![],
-                        },
-                        call: CallSuffix::Function(vec![Expression {
-                            head: Box::new(HeadExpression::Simple(SimpleExpression::String(
-                                "foo".as_bytes().to_vec().into_boxed_slice(),
-                            ))),
-                            tail: vec![],
-                        },]),
-                    }),
-                    Statement::FunctionCall(FunctionCallStatement {
-                        head: SuffixedExpression {
-                            primary: PrimaryExpression::Name(
-                                "print".as_bytes().to_vec().into_boxed_slice(),
-                            ),
-                            suffixes: vec![],
-                        },
-                        call: CallSuffix::Function(vec![Expression {
-                            head: Box::new(HeadExpression::Simple(
-                                SimpleExpression::TableConstructor(TableConstructor {
-                                    fields: vec![ConstructorField::Array(Expression {
-                                        head: Box::new(HeadExpression::Simple(
-                                            SimpleExpression::Float(30.0),
-                                        )),
-                                        tail: vec![],
-                                    }),],
-                                }),
-                            )),
-                            tail: vec![],
-                        },]),
-                    }),
+                            call: CallSuffix::Function(vec![Expression {
+                                head: Box::new(HeadExpression::Simple(
+                                    SimpleExpression::TableConstructor(TableConstructor {
+                                        fields: vec![ConstructorField::Array(Expression {
+                                            head: Box::new(HeadExpression::Simple(
+                                                SimpleExpression::Float(30.0),
+                                            )),
+                                            tail: vec![],
+                                        }),],
+                                    }),
+                                )),
+                                tail: vec![],
+                            },]),
+                        }),
+                        span(0, 25, 36),
+                    ),
                 ],
                 return_statement: None,
             },
         }
     );
 }
+
+#[test]
+fn test_visitor() {
+    // Collects the names of called functions and counts function definitions
+    #[derive(Default)]
+    struct Calls {
+        names: Vec<String>,
+        functions: usize,
+    }
+
+    impl Visitor<Box<[u8]>> for Calls {
+        fn visit_suffixed_expression(&mut self, suffixed: &SuffixedExpression<Box<[u8]>>) {
+            if let (PrimaryExpression::Name(name), Some(SuffixPart::Call(_))) =
+                (&suffixed.primary, suffixed.suffixes.first())
+            {
+                self.names.push(String::from_utf8_lossy(name).into_owned());
+            }
+            walk_suffixed_expression(self, suffixed);
+        }
+
+        fn visit_function_call_statement(
+            &mut self,
+            function_call: &FunctionCallStatement<Box<[u8]>>,
+        ) {
+            if let PrimaryExpression::Name(name) = &function_call.head.primary {
+                if function_call.head.suffixes.is_empty() {
+                    self.names.push(String::from_utf8_lossy(name).into_owned());
+                }
+            }
+            walk_function_call_statement(self, function_call);
+        }
+
+        fn visit_function_definition(&mut self, definition: &FunctionDefinition<Box<[u8]>>) {
+            self.functions += 1;
+            walk_function_definition(self, definition);
+        }
+    }
+
+    let chunk = parse_chunk(
+        &br#"
+            local function f(x)
+                return g(x) + 1
+            end
+            for i = 1, h() do
+                print({f(i), function() return k(i) end})
+            end
+            t.field = (m(1))[2]
+        "#[..],
+        |s| s.to_vec().into_boxed_slice(),
+    )
+    .unwrap();
+
+    let mut calls = Calls::default();
+    calls.visit_chunk(&chunk);
+    assert_eq!(calls.names, vec!["g", "h", "print", "f", "k", "m"]);
+    assert_eq!(calls.functions, 2);
+}
+
+#[test]
+fn test_incomplete() {
+    let is_incomplete = |source: &str| {
+        parse_chunk(source.as_bytes(), |s| s.to_vec().into_boxed_slice())
+            .unwrap_err()
+            .is_incomplete()
+    };
+
+    assert!(is_incomplete("function f()"));
+    assert!(is_incomplete("if x then print(1) else"));
+    assert!(is_incomplete("local t = {1, 2,"));
+    assert!(is_incomplete("x = 1 +"));
+    assert!(is_incomplete("s = [[long\nstring"));
+    assert!(is_incomplete("--[==[ long comment"));
+    assert!(is_incomplete("s = 'continued \\"));
+
+    assert!(!is_incomplete("x = = 1"));
+    assert!(!is_incomplete("end"));
+    assert!(!is_incomplete("s = 'short\nstring'"));
+    assert!(!is_incomplete("x = 1 + )"));
+}
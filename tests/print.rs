@@ -0,0 +1,45 @@
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use luster::{Lua, StaticError};
+
+#[derive(Clone, Default)]
+struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn print_output() -> Result<(), Box<StaticError>> {
+    let mut lua = Lua::new();
+    let buffer = SharedBuffer::default();
+    lua.set_output(Box::new(buffer.clone()));
+
+    lua.exec(
+        r#"
+            local obj = setmetatable({}, { __tostring = function() return "obj" end })
+            print(1, 2.5, "three", nil, true, obj)
+            print()
+        "#,
+    )?;
+    assert_eq!(
+        &buffer.0.borrow()[..],
+        &b"1\t2.5\tthree\tnil\ttrue\tobj\n\n"[..]
+    );
+
+    let other = SharedBuffer::default();
+    lua.set_output(Box::new(other.clone()));
+    lua.exec("print('other')")?;
+    assert_eq!(&other.0.borrow()[..], &b"other\n"[..]);
+    assert!(!buffer.0.borrow().ends_with(b"other\n"));
+
+    Ok(())
+}
@@ -0,0 +1,101 @@
+use std::rc::Rc;
+
+use luster::{Lua, Profiler, StaticError};
+
+const CODE: &str = r#"
+local function inner(n)
+    local x = 0
+    for i = 1, n do
+        x = x + i
+    end
+    return x
+end
+
+local function outer()
+    return inner(2000) + inner(2000)
+end
+
+for i = 1, 3 do
+    outer()
+end
+inner(100)
+"#;
+
+#[test]
+fn sample_by_instructions() -> Result<(), StaticError> {
+    let mut lua = Lua::new();
+    let profiler = Rc::new(Profiler::new(100));
+    let hook = profiler.clone();
+    lua.mutate(move |mc, root| root.main_thread.set_host_hook(mc, Some(hook)));
+    lua.exec(&CODE[1..])?;
+
+    let report = profiler.report();
+    assert!(report.samples > 100);
+    assert_eq!(
+        report.stacks.iter().map(|(_, n)| n).sum::<u64>(),
+        report.samples
+    );
+
+    // Every call of `inner` counts towards the same function, however it was called
+    let functions = report.functions();
+    let inner = &functions[0];
+    assert_eq!(inner.defined.as_ref().unwrap().line, Some(1));
+    assert!(inner
+        .function
+        .starts_with("function <[string \"local function inner"));
+    assert!(inner.self_samples > report.samples * 9 / 10);
+    assert_eq!(
+        functions
+            .iter()
+            .filter(|f| f.defined.as_ref().and_then(|d| d.line) == Some(1))
+            .count(),
+        1
+    );
+    let main = functions
+        .iter()
+        .find(|f| f.function == "main chunk")
+        .unwrap();
+    assert_eq!(main.total_samples, report.samples);
+
+    // The loop in `inner` is on lines 3 and 4
+    let lines = report.lines();
+    assert!(lines[..2]
+        .iter()
+        .all(|(location, _)| location.line == Some(3) || location.line == Some(4)));
+
+    let folded = report.folded();
+    assert!(folded.lines().any(
+        |line| line.starts_with("main chunk;function <") && line.contains(";upvalue 'inner' ")
+    ));
+    for line in folded.lines() {
+        let (stack, samples) = line.rsplit_once(' ').unwrap();
+        assert!(stack.starts_with("main chunk"));
+        assert!(samples.parse::<u64>().unwrap() > 0);
+    }
+
+    profiler.reset();
+    assert_eq!(profiler.report().samples, 0);
+    assert!(profiler.report().stacks.is_empty());
+    Ok(())
+}
+
+#[test]
+fn sample_on_signal() -> Result<(), StaticError> {
+    let mut lua = Lua::new();
+    let profiler = Rc::new(Profiler::on_signal(10));
+    let signal = profiler.signal().unwrap();
+    let hook = profiler.clone();
+    lua.mutate(move |mc, root| root.main_thread.set_host_hook(mc, Some(hook)));
+
+    lua.exec(&CODE[1..])?;
+    assert_eq!(profiler.report().samples, 0);
+
+    let thread_signal = signal.clone();
+    std::thread::spawn(move || thread_signal.trigger())
+        .join()
+        .unwrap();
+    lua.exec(&CODE[1..])?;
+    assert_eq!(profiler.report().samples, 1);
+    assert!(Profiler::new(10).signal().is_none());
+    Ok(())
+}
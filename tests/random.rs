@@ -0,0 +1,30 @@
+use luster::{Lua, StaticError};
+
+// The results of `RANDOM`
+type Random = (f64, i64, i64, i64);
+
+const RANDOM: &str = "return math.random(), math.random(100), math.random(-5, 5), \
+                        math.random(math.mininteger, math.maxinteger)";
+
+#[test]
+fn seed_random() -> Result<(), Box<StaticError>> {
+    let mut a = Lua::new();
+    let mut b = Lua::new();
+    a.seed_random(1234);
+    b.seed_random(1234);
+    let first = a.eval::<Random>(RANDOM)?;
+    assert_eq!(first, b.eval::<Random>(RANDOM)?);
+    assert_ne!(first, a.eval::<Random>(RANDOM)?);
+
+    b.seed_random(5678);
+    assert_ne!(first, b.eval::<Random>(RANDOM)?);
+
+    // Seeding from Rust and from Lua with the same integer is equivalent
+    let mut c = Lua::new();
+    c.exec("math.randomseed(1234)")?;
+    assert_eq!(first, c.eval::<Random>(RANDOM)?);
+    c.exec("math.randomseed(1234.0)")?;
+    assert_eq!(first, c.eval::<Random>(RANDOM)?);
+
+    Ok(())
+}
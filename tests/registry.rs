@@ -0,0 +1,85 @@
+use std::cell::Cell;
+use std::rc::Rc;
+
+use gc_sequence::{self as sequence, SequenceExt, SequenceResultExt};
+use luster::{AnyUserData, Error, Lua, StaticError, String, ThreadSequence, Value};
+
+struct DropFlag(Rc<Cell<bool>>);
+
+impl Drop for DropFlag {
+    fn drop(&mut self) {
+        self.0.set(true);
+    }
+}
+
+#[test]
+fn stash_function() -> Result<(), StaticError> {
+    let mut lua = Lua::new();
+    lua.exec("function add(a, b) return a + b end")?;
+    let add = lua.mutate(|mc, root| {
+        root.registry
+            .stash(mc, root.globals.get(String::new_static(b"add")))
+    });
+    lua.exec("add = nil")?;
+    lua.collect_all();
+
+    let res = lua.sequence(|root| {
+        let add = match root.registry.fetch(&add) {
+            Value::Function(function) => function,
+            value => panic!("unexpected value {:?}", value),
+        };
+        sequence::from_fn_with((root, add), |mc, (root, add)| {
+            Ok(ThreadSequence::call_function(
+                mc,
+                root.main_thread,
+                add,
+                &[Value::Integer(1), Value::Integer(2)],
+            )?)
+        })
+        .flatten_ok()
+        .map_ok(|res| res == vec![Value::Integer(3)])
+        .map_err(Error::to_static)
+        .boxed()
+    })?;
+    assert!(res);
+
+    lua.mutate(|mc, root| {
+        root.registry.set(mc, &add, Value::Boolean(true));
+        assert_eq!(root.registry.fetch(&add), Value::Boolean(true));
+        assert_eq!(root.registry.remove(mc, add), Value::Boolean(true));
+    });
+    Ok(())
+}
+
+#[test]
+fn dropped_keys() {
+    let dropped = Rc::new(Cell::new(false));
+    let mut lua = Lua::new();
+    let key = {
+        let dropped = dropped.clone();
+        lua.mutate(move |mc, root| {
+            let userdata = AnyUserData::new(mc, DropFlag(dropped));
+            root.registry.stash(mc, Value::UserData(userdata))
+        })
+    };
+    lua.collect_all();
+    assert!(!dropped.get());
+
+    // Dropped keys are freed the next time the registry is changed
+    drop(key);
+    let other = lua.mutate(|mc, root| root.registry.stash(mc, Value::Integer(1)));
+    lua.collect_all();
+    assert!(dropped.get());
+    lua.mutate(|_, root| assert_eq!(root.registry.fetch(&other), Value::Integer(1)));
+}
+
+#[test]
+#[should_panic(expected = "registry key used with a different registry")]
+fn foreign_key() {
+    let mut first = Lua::new();
+    let mut second = Lua::new();
+    let key = first.mutate(|mc, root| root.registry.stash(mc, Value::Integer(1)));
+    second.mutate(|_, root| {
+        root.registry.fetch(&key);
+    });
+}
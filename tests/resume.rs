@@ -0,0 +1,124 @@
+use gc_sequence::{self as sequence, SequenceExt};
+use luster::{Lua, ResumeResult, ResumeSequence, String, Thread, ThreadStatus, Value};
+
+#[derive(Debug, PartialEq)]
+enum Resumed {
+    Yielded(Vec<i64>),
+    Returned(Vec<i64>),
+    Errored(std::string::String),
+}
+
+fn integers(values: Vec<Value>) -> Vec<i64> {
+    values
+        .into_iter()
+        .map(|v| match v {
+            Value::Integer(i) => i,
+            v => panic!("not an integer: {:?}", v),
+        })
+        .collect()
+}
+
+// Resumes the thread in the global `co` with the given argument, and returns what happened along
+// with the thread's status afterwards.
+fn resume(lua: &mut Lua, arg: i64) -> (Resumed, ThreadStatus) {
+    lua.sequence(move |root| {
+        sequence::from_fn_with(root, move |mc, root| {
+            let thread = match root.globals.get(String::new_static(b"co")) {
+                Value::Thread(thread) => thread,
+                _ => panic!("not a thread"),
+            };
+            ResumeSequence::resume(mc, thread, &[Value::Integer(arg)])
+                .unwrap()
+                .map_with(thread, |thread, res| {
+                    let resumed = match res {
+                        ResumeResult::Yielded(values) => Resumed::Yielded(integers(values)),
+                        ResumeResult::Returned(values) => Resumed::Returned(integers(values)),
+                        ResumeResult::Errored(error) => {
+                            Resumed::Errored(error.to_string().lines().next().unwrap().to_owned())
+                        }
+                    };
+                    (resumed, thread.status())
+                })
+        })
+        .flatten()
+        .boxed()
+    })
+}
+
+fn create(lua: &mut Lua, code: &'static str) {
+    lua.exec(code).unwrap();
+    lua.mutate(|mc, root| {
+        let function = match root.globals.get(String::new_static(b"f")) {
+            Value::Function(function) => function,
+            _ => panic!("not a function"),
+        };
+        let thread = Thread::new(mc, root.string_metatable, true);
+        assert_eq!(thread.status(), ThreadStatus::Dead);
+        thread.start_suspended(mc, function).unwrap();
+        assert_eq!(thread.status(), ThreadStatus::Suspended);
+        root.globals
+            .set(mc, String::new_static(b"co"), Value::Thread(thread))
+            .unwrap();
+    });
+}
+
+#[test]
+fn resume_yield_return() {
+    let mut lua = Lua::new();
+    create(
+        &mut lua,
+        r#"
+            function f(a)
+                local b = coroutine.yield(a + 1, a + 2)
+                local c = coroutine.yield(b * 10)
+                return a + b + c
+            end
+        "#,
+    );
+    assert_eq!(
+        resume(&mut lua, 1),
+        (Resumed::Yielded(vec![2, 3]), ThreadStatus::Suspended)
+    );
+    assert_eq!(
+        resume(&mut lua, 2),
+        (Resumed::Yielded(vec![20]), ThreadStatus::Suspended)
+    );
+    assert_eq!(
+        resume(&mut lua, 3),
+        (Resumed::Returned(vec![6]), ThreadStatus::Dead)
+    );
+}
+
+#[test]
+fn resume_error() {
+    let mut lua = Lua::new();
+    create(
+        &mut lua,
+        r#"
+            function f(a)
+                coroutine.yield()
+                error("bad " .. a, 0)
+            end
+        "#,
+    );
+    assert_eq!(
+        resume(&mut lua, 1),
+        (Resumed::Yielded(vec![]), ThreadStatus::Suspended)
+    );
+    assert_eq!(
+        resume(&mut lua, 2),
+        (
+            Resumed::Errored("runtime error: bad 1".to_owned()),
+            ThreadStatus::Dead
+        )
+    );
+
+    // A dead thread cannot be resumed
+    lua.mutate(|mc, root| {
+        let thread = match root.globals.get(String::new_static(b"co")) {
+            Value::Thread(thread) => thread,
+            _ => panic!("not a thread"),
+        };
+        assert!(ResumeSequence::resume(mc, thread, &[]).is_err());
+    });
+}
@@ -0,0 +1,27 @@
+local function test1()
+    local a, b, c, d = assert(1, "unused", nil, 4)
+    local t = {}
+    local e = assert(t)
+    return a == 1 and b == "unused" and c == nil and d == 4 and e == t and
+        select('#', assert(true, nil, nil)) == 3
+end
+
+local function test2()
+    local t = {}
+    local ok1, e1 = pcall(assert, false)
+    local ok2, e2 = pcall(assert, nil, "custom message")
+    local ok3, e3 = pcall(assert, false, t)
+    local ok4, e4 = pcall(assert, false, nil)
+    local ok5, e5 = pcall(assert, nil, 42)
+    local ok6, e6 = pcall(assert)
+
+    return
+        ok1 == false and e1 == "assertion failed!" and
+        ok2 == false and e2 == "custom message" and
+        ok3 == false and e3 == t and
+        ok4 == false and e4 == nil and
+        ok5 == false and e5 == 42 and
+        ok6 == false and e6 == "bad argument #1 to 'assert' (value expected)"
+end
+
+return test1() and test2()
@@ -0,0 +1,32 @@
+local function test1()
+    local before = collectgarbage("count")
+    local t = {}
+    for i = 1, 10000 do
+        t[i] = {}
+    end
+    local during = collectgarbage("count")
+    t = nil
+    assert(collectgarbage() == 0)
+    local after = collectgarbage("count")
+
+    return math.type(before) == "float" and during > before and after < during
+end
+
+local function test2()
+    assert(collectgarbage("isrunning") == true)
+    collectgarbage("stop")
+    local stopped = collectgarbage("isrunning")
+    collectgarbage("restart")
+    return stopped == false and collectgarbage("isrunning") == true and
+        collectgarbage("step") == true
+end
+
+local function test3()
+    local ok1, e1 = pcall(collectgarbage, "bogus")
+    local ok2, e2 = pcall(collectgarbage, {})
+    return
+        ok1 == false and e1 == "bad argument #1 to 'collectgarbage' (invalid option 'bogus')" and
+        ok2 == false and e2 == "bad argument #1 to 'collectgarbage' (string expected, got table)"
+end
+
+return test1() and test2() and test3()
@@ -0,0 +1,112 @@
+-- Comparisons followed by a jump are fused into a single opcode by the optimizer, so check each
+-- comparison in both polarities, against registers and constants, with and without metamethods.
+local function branches(a, b)
+    local r = {}
+    if a == b then r[#r + 1] = "eq" end
+    if a ~= b then r[#r + 1] = "ne" end
+    if a < b then r[#r + 1] = "lt" end
+    if not (a < b) then r[#r + 1] = "nlt" end
+    if a <= b then r[#r + 1] = "le" end
+    if not (a <= b) then r[#r + 1] = "nle" end
+    return table.concat(r, " ")
+end
+
+local function constant_branches(a)
+    local r = {}
+    if a == 2 then r[#r + 1] = "eq" end
+    if a ~= 2 then r[#r + 1] = "ne" end
+    if a < 2 then r[#r + 1] = "lt" end
+    if not (a < 2) then r[#r + 1] = "nlt" end
+    if a <= 2 then r[#r + 1] = "le" end
+    if not (a <= 2) then r[#r + 1] = "nle" end
+    return table.concat(r, " ")
+end
+
+assert(branches(1, 2) == "ne lt le")
+assert(branches(2, 2) == "eq nlt le")
+assert(branches(3, 2) == "ne nlt nle")
+assert(branches(1.5, 2) == "ne lt le")
+assert(branches("a", "b") == "ne lt le")
+assert(constant_branches(1) == "ne lt le")
+assert(constant_branches(2) == "eq nlt le")
+assert(constant_branches(3) == "ne nlt nle")
+
+local nan = 0 / 0
+assert(branches(nan, nan) == "ne nlt nle")
+assert(constant_branches(nan) == "ne nlt nle")
+
+local mt = {
+    __eq = function(a, b) return a.v == b.v end,
+    __lt = function(a, b) return a.v < b.v end,
+    __le = function(a, b) return a.v <= b.v end,
+}
+local function box(v) return setmetatable({v = v}, mt) end
+assert(branches(box(1), box(2)) == "ne lt le")
+assert(branches(box(2), box(2)) == "eq nlt le")
+assert(branches(box(3), box(2)) == "ne nlt nle")
+
+-- Loop conditions, with the metamethod results deciding whether to jump back
+local i, n = 0, 10
+while i < n do i = i + 1 end
+assert(i == 10)
+repeat i = i - 1 until i <= 0
+assert(i == 0)
+local bi, bn = box(0), box(5)
+local count = 0
+while bi < bn do
+    bi = box(bi.v + 1)
+    count = count + 1
+end
+assert(count == 5)
+repeat
+    bi = box(bi.v - 1)
+    count = count + 1
+until bi <= box(0)
+assert(count == 10)
+
+-- Metamethods that yield in the middle of a fused comparison
+local ymt = {
+    __lt = function(a, b)
+        coroutine.yield()
+        return a.v < b.v
+    end,
+}
+local co = coroutine.wrap(function()
+    local a, b = setmetatable({v = 1}, ymt), setmetatable({v = 2}, ymt)
+    local r = {}
+    if a < b then r[#r + 1] = "lt" end
+    if b < a then r[#r + 1] = "gt" end
+    return table.concat(r, " ")
+end)
+co()
+co()
+assert(co() == "lt")
+
+-- A branch too long to be fused
+local function long_branch(x)
+    local y = 0
+    if x < 1 then
+        y = y + 1 y = y + 1 y = y + 1 y = y + 1 y = y + 1 y = y + 1 y = y + 1 y = y + 1
+        y = y + 1 y = y + 1 y = y + 1 y = y + 1 y = y + 1 y = y + 1 y = y + 1 y = y + 1
+        y = y + 1 y = y + 1 y = y + 1 y = y + 1 y = y + 1 y = y + 1 y = y + 1 y = y + 1
+        y = y + 1 y = y + 1 y = y + 1 y = y + 1 y = y + 1 y = y + 1 y = y + 1 y = y + 1
+        y = y + 1 y = y + 1 y = y + 1 y = y + 1 y = y + 1 y = y + 1 y = y + 1 y = y + 1
+        y = y + 1 y = y + 1 y = y + 1 y = y + 1 y = y + 1 y = y + 1 y = y + 1 y = y + 1
+        y = y + 1 y = y + 1 y = y + 1 y = y + 1 y = y + 1 y = y + 1 y = y + 1 y = y + 1
+        y = y + 1 y = y + 1 y = y + 1 y = y + 1 y = y + 1 y = y + 1 y = y + 1 y = y + 1
+        y = y + 1 y = y + 1 y = y + 1 y = y + 1 y = y + 1 y = y + 1 y = y + 1 y = y + 1
+        y = y + 1 y = y + 1 y = y + 1 y = y + 1 y = y + 1 y = y + 1 y = y + 1 y = y + 1
+        y = y + 1 y = y + 1 y = y + 1 y = y + 1 y = y + 1 y = y + 1 y = y + 1 y = y + 1
+        y = y + 1 y = y + 1 y = y + 1 y = y + 1 y = y + 1 y = y + 1 y = y + 1 y = y + 1
+        y = y + 1 y = y + 1 y = y + 1 y = y + 1 y = y + 1 y = y + 1 y = y + 1 y = y + 1
+        y = y + 1 y = y + 1 y = y + 1 y = y + 1 y = y + 1 y = y + 1 y = y + 1 y = y + 1
+        y = y + 1 y = y + 1 y = y + 1 y = y + 1 y = y + 1 y = y + 1 y = y + 1 y = y + 1
+        y = y + 1 y = y + 1 y = y + 1 y = y + 1 y = y + 1 y = y + 1 y = y + 1 y = y + 1
+        y = y + 1 y = y + 1 y = y + 1 y = y + 1 y = y + 1 y = y + 1 y = y + 1 y = y + 1
+    end
+    return y
+end
+assert(long_branch(0) == 136)
+assert(long_branch(1) == 0)
+
+return true
@@ -26,7 +26,7 @@ end
 function test2()
     local function test_coroutine()
         coroutine.yield(1)
-        error('test error')
+        error('test error', 0)
     end
 
     co = coroutine.create(test_coroutine)
@@ -41,6 +41,130 @@ function test2()
         e2 == false and r2 == 'test error' and s2 == "dead"
 end
 
+function test_wrap()
+    local gen = coroutine.wrap(function(a, b)
+        local c = coroutine.yield(a + b)
+        local d, e = coroutine.yield(c * 2)
+        return d + e
+    end)
+
+    local r1 = gen(1, 2)
+    local r2 = gen(10)
+    local r3 = gen(3, 4)
+    local dead_ok, dead_err = pcall(gen)
+
+    local failing = coroutine.wrap(function()
+        coroutine.yield(1)
+        error('wrap error', 0)
+    end)
+    local f1 = failing()
+    local f2_ok, f2_err = pcall(failing)
+
+    local t = {}
+    local thrown = coroutine.wrap(function() error(t) end)
+    local t_ok, t_err = pcall(thrown)
+
+    return
+        r1 == 3 and r2 == 20 and r3 == 7 and
+        dead_ok == false and dead_err == "cannot resume dead coroutine" and
+        f1 == 1 and f2_ok == false and f2_err == 'wrap error' and
+        t_ok == false and t_err == t and
+        not pcall(coroutine.wrap, 1)
+end
+
+function test_running()
+    local main, is_main = coroutine.running()
+    local main_yieldable = coroutine.isyieldable()
+
+    local co
+    local inner, inner_main, inner_yieldable, inner_status, outer_status
+    co = coroutine.create(function()
+        inner, inner_main = coroutine.running()
+        inner_yieldable = coroutine.isyieldable()
+        inner_status = coroutine.status(co)
+        outer_status = coroutine.status(main)
+    end)
+    coroutine.resume(co)
+
+    return
+        type(main) == "thread" and is_main == true and main_yieldable == false and
+        inner == co and inner_main == false and inner_yieldable == true and
+        inner_status == "running" and outer_status == "normal" and
+        coroutine.status(main) == "running" and coroutine.status(co) == "dead"
+end
+
+function test_close()
+    local closed = {}
+    local function closer(name)
+        return setmetatable({}, {__close = function(_, err)
+            closed[#closed + 1] = name .. ":" .. tostring(err)
+        end})
+    end
+
+    local co = coroutine.create(function()
+        local a <close> = closer("a")
+        local b <close> = closer("b")
+        coroutine.yield()
+    end)
+    coroutine.resume(co)
+    local ok = coroutine.close(co)
+    local status = coroutine.status(co)
+    local resumed, resumed_err = coroutine.resume(co)
+
+    local failing = coroutine.create(function()
+        local a <close> = closer("c")
+        local b <close> = setmetatable({}, {__close = function() error("close error", 0) end})
+        coroutine.yield()
+    end)
+    coroutine.resume(failing)
+    local failed, err = coroutine.close(failing)
+
+    local failed_again = coroutine.close(failing)
+
+    local dead = coroutine.create(function() end)
+    coroutine.resume(dead)
+
+    local errored = coroutine.create(function() error({code = 1}) end)
+    coroutine.resume(errored)
+    local errored_ok, errored_err = coroutine.close(errored)
+    local errored_again = coroutine.close(errored)
+
+    local running_ok = pcall(coroutine.close, coroutine.running())
+    local normal_err, outer
+    outer = coroutine.create(function()
+        local inner = coroutine.create(function()
+            normal_err = select(2, pcall(coroutine.close, outer))
+        end)
+        coroutine.resume(inner)
+    end)
+    coroutine.resume(outer)
+
+    local running_err = select(2, coroutine.resume(coroutine.running()))
+    local normal_resume_err
+    outer = coroutine.create(function()
+        coroutine.resume(coroutine.create(function()
+            normal_resume_err = select(2, coroutine.resume(outer))
+        end))
+    end)
+    coroutine.resume(outer)
+
+    return
+        ok == true and status == "dead" and resumed == false and
+        resumed_err == "cannot resume dead coroutine" and
+        running_err == "cannot resume non-suspended coroutine" and
+        normal_resume_err == "cannot resume non-suspended coroutine" and
+        closed[1] == "b:nil" and closed[2] == "a:nil" and
+        failed == false and err == "close error" and closed[3] == "c:close error" and
+        failed_again == true and
+        coroutine.close(dead) == true and
+        errored_ok == false and errored_err.code == 1 and errored_again == true and
+        coroutine.close(coroutine.create(print)) == true and
+        running_ok == false and normal_err == "cannot close a normal coroutine"
+end
+
 return
     test1() and
-    test2()
+    test2() and
+    test_wrap() and
+    test_running() and
+    test_close()
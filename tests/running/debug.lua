@@ -0,0 +1,384 @@
+local function has(s, pattern)
+    return string.find(s, pattern) ~= nil
+end
+
+local function lines(s)
+    local count = 0
+    for _ in string.gmatch(s, "\n") do
+        count = count + 1
+    end
+    return count
+end
+
+function global_traceback()
+    local s = debug.traceback("message")
+    return s
+end
+
+local t = {}
+function t.field()
+    local s = debug.traceback()
+    return s
+end
+function t:method()
+    local s = debug.traceback()
+    return s
+end
+
+local function inner()
+    local s = debug.traceback()
+    return s
+end
+local function outer()
+    local s = inner()
+    return s
+end
+
+local function recurse(n)
+    if n == 0 then
+        return debug.traceback()
+    end
+    local s = recurse(n - 1)
+    return s
+end
+
+local function tail_traceback()
+    local s = debug.traceback()
+    return s
+end
+
+local function tail_through()
+    return tail_traceback()
+end
+
+function test_tail_calls()
+    local s = tail_through()
+    return
+        has(s, "^stack traceback:\n\t[^\n]*:%d+: in function <[^\n]*:%d+>\n\t%(%.%.%.tail calls%.%.%.%)\n") and
+        has(s, "\n\t%(%.%.%.tail calls%.%.%.%)\n\t[^\n]*:%d+: in function 'test_tail_calls'\n") and
+        lines(s) == 4
+end
+
+function test_names()
+    local global = global_traceback()
+    local field = t.field()
+    local method = t:method()
+    local upvalue = outer()
+    local iterator
+    for _ in function() iterator = debug.traceback() end do end
+    return
+        has(global, "^message\nstack traceback:\n\t[^\n]*:14: in function 'global_traceback'\n") and
+        has(global, "\n\t[^\n]*:%d+: in function 'test_names'\n") and
+        has(global, "\n\t[^\n]*:%d+: in main chunk$") and
+        has(field, "^stack traceback:\n\t[^\n]*:20: in field 'field'\n") and
+        has(method, "^stack traceback:\n\t[^\n]*:24: in method 'method'\n") and
+        has(upvalue, "^stack traceback:\n\t[^\n]*:29: in upvalue 'inner'\n") and
+        has(upvalue, "\n\t[^\n]*:33: in upvalue 'outer'\n") and
+        has(iterator, "^stack traceback:\n\t[^\n]*:%d+: in for iterator 'for iterator'\n")
+end
+
+function test_callbacks()
+    local _, protected = pcall(function()
+        local s = debug.traceback()
+        return s
+    end)
+    local _, handled = xpcall(function() error("boom") end, debug.traceback)
+    -- An error raised by Lua code starts at the function that raised it
+    local _, arithmetic = xpcall(function() local x = nil; return x + 1 end, debug.traceback)
+    return
+        has(protected, "^stack traceback:\n\t[^\n]*:%d+: in function <[^\n]*:%d+>\n\t%[C%]: in function 'pcall'\n") and
+        has(handled, "^[^\n]*:%d+: boom\nstack traceback:\n\t%[C%]: in function 'error'\n") and
+        has(handled, "\n\t%[C%]: in function 'xpcall'\n") and
+        has(arithmetic, "\nstack traceback:\n\t[^\n]*:%d+: in function <[^\n]*:%d+>\n\t%[C%]: in function 'xpcall'\n") and
+        has(debug.traceback("x", 0), "^x\nstack traceback:\n\t%[C%]: in field 'traceback'\n")
+end
+
+function test_levels()
+    local long = recurse(40)
+    return
+        lines(long) == 22 and
+        has(long, "\n\t%.%.%.\n") and
+        has(long, "in upvalue 'recurse'\n\t%.%.%.\n\t[^\n]*in upvalue 'recurse'") and
+        has(long, "\n\t[^\n]*:%d+: in main chunk$") and
+        lines(recurse(19)) == 22 and
+        not has(recurse(19), "%.%.%.") and
+        lines(recurse(20)) == 22 and
+        has(recurse(20), "%.%.%.") and
+        debug.traceback("x", 100) == "x\nstack traceback:" and
+        debug.traceback("x", -1) == "x\nstack traceback:"
+end
+
+function test_threads()
+    local co = coroutine.create(function()
+        local function suspend()
+            coroutine.yield()
+        end
+        suspend()
+    end)
+    coroutine.resume(co)
+    local suspended = debug.traceback(co)
+    local message = debug.traceback(co, "message", 1)
+    local finished = coroutine.create(function() end)
+    coroutine.resume(finished)
+    return
+        lines(suspended) == 2 and
+        has(suspended, "^stack traceback:\n\t[^\n]*:%d+: in function") and
+        lines(message) == 2 and
+        has(message, "^message\nstack traceback:\n\t[^\n]*:%d+: in function") and
+        debug.traceback(finished) == "stack traceback:" and
+        debug.traceback(coroutine.create(print), "m") == "m\nstack traceback:"
+end
+
+function test_messages()
+    local t = {}
+    return
+        debug.traceback(t) == t and
+        debug.traceback(false) == false and
+        has(debug.traceback(12), "^12\nstack traceback:\n") and
+        has(debug.traceback(nil), "^stack traceback:\n") and
+        has(debug.traceback(), "^stack traceback:\n")
+end
+
+function info_named(a, b, ...)
+    local info = debug.getinfo(1)
+    return info
+end
+
+local captured = 1
+local function info_closure(p)
+    return captured + p
+end
+
+local function tail_info()
+    return debug.getinfo(1, "nSlt")
+end
+
+local function tail_called()
+    local info = debug.getinfo(1, "nt")
+    return info
+end
+
+local function tail_caller()
+    return tail_called()
+end
+
+function test_getinfo()
+    local named = info_named(1, 2)
+    local closure = debug.getinfo(info_closure)
+    local callback = debug.getinfo(print)
+    local main = debug.getinfo(2, "S")
+    local own = debug.getinfo(0)
+    local _, invalid = pcall(debug.getinfo, 1, "x")
+    local _, level = pcall(debug.getinfo, {})
+    local tail = tail_info()
+    local tailed = tail_caller()
+
+    return
+        named.name == "info_named" and named.namewhat == "global" and
+        named.what == "Lua" and named.func == info_named and
+        named.currentline == named.linedefined + 1 and
+        named.lastlinedefined == named.linedefined + 3 and
+        named.nparams == 2 and named.isvararg == true and named.nups == 1 and
+        named.short_src == "./tests/running/debug.lua" and
+        named.source == "@./tests/running/debug.lua" and
+        closure.name == nil and closure.namewhat == "" and closure.currentline == -1 and
+        closure.nparams == 1 and closure.isvararg == false and closure.nups == 1 and
+        closure.lastlinedefined == closure.linedefined + 2 and
+        callback.what == "C" and callback.source == "=[C]" and callback.short_src == "[C]" and
+        callback.linedefined == -1 and callback.func == print and
+        main.what == "main" and main.linedefined == 0 and main.currentline == nil and
+        own.what == "C" and own.name == "getinfo" and own.namewhat == "field" and
+        own.istailcall == false and named.istailcall == false and
+        tail.name == "tail_info" and tail.namewhat == "upvalue" and tail.istailcall == false and
+        tail.currentline == tail.linedefined + 1 and tail.what == "Lua" and
+        tailed.istailcall == true and tailed.name == nil and tailed.namewhat == "" and
+        debug.getinfo(100) == nil and debug.getinfo(-1) == nil and
+        string.find(invalid, "bad argument #2 to 'getinfo' %(invalid option%)") ~= nil and
+        string.find(level, "bad argument #1 to 'getinfo'") ~= nil
+end
+
+local function hooked_add(a)
+    local b = a + 1
+    return b
+end
+
+function test_sethook()
+    local events = {}
+    local function record(event, line)
+        events[#events + 1] = line and event .. ":" .. line or event
+    end
+    local start = debug.getinfo(1, "l").currentline
+    debug.sethook(record, "crl")
+    local x = hooked_add(1)
+    debug.sethook()
+    local hook, mask, count = debug.gethook()
+
+    local loop_lines = 0
+    debug.sethook(function() loop_lines = loop_lines + 1 end, "l")
+    for i = 1, 3 do x = x + i end
+    debug.sethook()
+
+    local counted = 0
+    debug.sethook(function(event) counted = counted + 1 end, "", 1)
+    x = x + 1
+    debug.sethook()
+
+    local nested = 0
+    debug.sethook(function()
+        nested = nested + 1
+        local ignored = hooked_add(nested)
+    end, "c")
+    hooked_add(1)
+    debug.sethook()
+
+    local traceback, info
+    debug.sethook(function()
+        traceback = traceback or debug.traceback()
+        info = info or debug.getinfo(2, "l")
+    end, "l")
+    local y = 1
+    debug.sethook()
+
+    local ok, err = pcall(function()
+        debug.sethook(function()
+            debug.sethook()
+            error("in hook", 0)
+        end, "l")
+        local z = 1
+    end)
+
+    local co = coroutine.create(function()
+        local a = 1
+        return a
+    end)
+    local co_lines = 0
+    debug.sethook(co, function() co_lines = co_lines + 1 end, "l")
+    coroutine.resume(co)
+    local co_hook, co_mask = debug.gethook(co)
+    local main_hook = debug.gethook()
+
+    return
+        events[1] == "line:" .. start + 2 and events[2] == "call" and
+        events[3] == "line:" .. start - 9 and events[4] == "line:" .. start - 8 and
+        events[5] == "return" and events[6] == "line:" .. start + 3 and events[7] == "call" and
+        #events == 7 and
+        hook == nil and mask == "" and count == 0 and
+        loop_lines == 5 and
+        counted == 4 and
+        nested == 2 and
+        string.find(traceback, "^stack traceback:\n\t[^\n]*: in hook '%?'\n\t[^\n]*:%d+: in function 'test_sethook'") ~= nil and
+        info.currentline == start + 29 and
+        ok == false and err == "in hook" and debug.gethook() == nil and
+        co_lines == 2 and type(co_hook) == "function" and co_mask == "l" and main_hook == nil and
+        not pcall(debug.sethook, 1)
+end
+
+function test_callback_hooks()
+    local events = {}
+    local function record(event)
+        local info = debug.getinfo(2, "nS")
+        events[#events + 1] = event .. ":" .. tostring(info.name) .. ":" .. info.what
+    end
+    debug.sethook(record, "cr")
+    local a = math.abs(-1)
+    pcall(error, "failed")
+    debug.sethook()
+
+    local yields = {}
+    local co = coroutine.wrap(function()
+        debug.sethook(function(event) yields[#yields + 1] = event end, "r")
+        local resumed = coroutine.yield(1)
+        debug.sethook()
+        return resumed
+    end)
+    co()
+    local resumed = co(2)
+
+    local ok, err = pcall(function()
+        debug.sethook(function()
+            debug.sethook()
+            error("in hook", 2)
+        end, "c")
+        local b = math.abs(-1)
+    end)
+
+    return
+        events[1] == "call:abs:C" and events[2] == "return:abs:C" and
+        events[3] == "call:pcall:C" and events[4] == "call:nil:C" and
+        events[5] == "return:pcall:C" and events[6] == "call:sethook:C" and #events == 6 and
+        yields[1] == "return" and #yields == 1 and resumed == 2 and
+        ok == false and err == "in hook"
+end
+
+local function locals_of(a, b, ...)
+    local c = a + b
+    local names = {}
+    local i = 1
+    while true do
+        local name, value = debug.getlocal(1, i)
+        if not name then break end
+        names[#names + 1] = name .. "=" .. tostring(value)
+        i = i + 1
+    end
+    local vararg, vararg_value = debug.getlocal(1, -2)
+    local missing = debug.getlocal(1, -3)
+    local set = debug.setlocal(1, 3, 100)
+    return table.concat(names, " "), vararg, vararg_value, missing, set, c
+end
+
+function test_getlocal()
+    local names, vararg, vararg_value, missing, set, c = locals_of(1, 2, "x", "y")
+    local _, level = pcall(debug.getlocal, 50, 1)
+
+    local up1, up2 = 10, 20
+    local function sum() return up1 + up2 end
+    local name1, value1 = debug.getupvalue(sum, 1)
+    local set_name = debug.setupvalue(sum, 2, 5)
+
+    local function outer()
+        local secret = "s"
+        local function peek()
+            local name, value = debug.getlocal(2, 1)
+            return name, value
+        end
+        local name, value = peek()
+        return name, value
+    end
+    local outer_name, outer_value = outer()
+
+    local co = coroutine.create(function(x)
+        local y = x * 2
+        coroutine.yield()
+    end)
+    coroutine.resume(co, 21)
+    local co_x, co_x_value = debug.getlocal(co, 0, 1)
+    local co_y, co_y_value = debug.getlocal(co, 0, 2)
+
+    return
+        string.find(names, "^a=1 b=2 c=3 names=<table [^ ]+> i=5$") ~= nil and
+        vararg == "(*vararg)" and vararg_value == "y" and missing == nil and
+        set == "c" and c == 100 and
+        string.find(level, "bad argument #1 to 'getlocal' %(level out of range%)") ~= nil and
+        debug.getlocal(locals_of, 1) == "a" and debug.getlocal(locals_of, 2) == "b" and
+        debug.getlocal(locals_of, 3) == nil and debug.getlocal(print, 1) == nil and
+        name1 == "up1" and value1 == 10 and
+        set_name == "up2" and up2 == 5 and sum() == 15 and
+        debug.getupvalue(sum, 3) == nil and debug.getupvalue(print, 1) == nil and
+        not pcall(debug.getupvalue, 1, 1) and
+        outer_name == "secret" and outer_value == "s" and
+        co_x == "x" and co_x_value == 21 and co_y == "y" and co_y_value == 42 and
+        debug.getlocal(load(string.dump(locals_of)), 2) == "b" and
+        debug.getlocal(load(string.dump(locals_of, true)), 1) == nil
+end
+
+return
+    test_getlocal() and
+    test_sethook() and
+    test_callback_hooks() and
+    test_getinfo() and
+    test_names() and
+    test_tail_calls() and
+    test_callbacks() and
+    test_levels() and
+    test_threads() and
+    test_messages()
@@ -0,0 +1,60 @@
+local function test1()
+    local function f(a, b, ...)
+        local t = {nil, true, false, 42, -7, 1.5, "str", "", "\0\1\255"}
+        local n = #{...}
+        return a + b, t[2], t[4], t[6], t[7], t[9], n
+    end
+
+    local g = load(string.dump(f))
+    local a, b, c, d, e, s, n = g(1, 2, 3, 4, 5)
+    return a == 3 and b == true and c == 42 and d == 1.5 and e == "str" and
+        s == "\0\1\255" and n == 3
+end
+
+local function test2()
+    local function f(n)
+        local function fact(n)
+            if n <= 1 then
+                return 1
+            end
+            return n * fact(n - 1)
+        end
+        local counter = 0
+        local function inc()
+            counter = counter + 1
+            return counter
+        end
+        inc()
+        inc()
+        return fact(n) + inc()
+    end
+
+    return load(string.dump(f))(5) == 123
+end
+
+local function test3()
+    local dumped = string.dump(load("x = 10 return x + 1"))
+    local env = {}
+    local f = load(dumped, "chunk", "b", env)
+    return f() == 11 and env.x == 10 and x == nil
+end
+
+local function test4()
+    local dumped = string.dump(function() return 1 end)
+    local f, err = load(dumped, "chunk", "t")
+    local g, err2 = load("return 1", "chunk", "b")
+    return f == nil and type(err) == "string" and g == nil and type(err2) == "string"
+end
+
+local function test5()
+    local ok = pcall(string.dump, print)
+    local f, err = load("\27Lua garbage")
+    return not ok and f == nil and type(err) == "string"
+end
+
+return
+    test1() and
+    test2() and
+    test3() and
+    test4() and
+    test5()
@@ -14,6 +14,66 @@ local function test2()
     return _ENV.i == 3
 end
 
+local function test3()
+    local print = print
+    local G = _ENV
+    do
+        local _ENV = {}
+        x = 1
+        if G.x ~= nil or _ENV.x ~= 1 then
+            return false
+        end
+    end
+    return x == nil
+end
+
+local function test4()
+    local function sandboxed(_ENV)
+        a = b + 1
+        return function() return a end
+    end
+    local env = {b = 2}
+    local get = sandboxed(env)
+    return env.a == 3 and get() == 3 and a == nil
+end
+
+local function test5()
+    local _ENV = setmetatable({}, {__index = _ENV})
+    local inner = function() y5 = tostring(5) return y5 end
+    return inner() == "5" and _G.y5 == nil and y5 == "5"
+end
+
+local function test6()
+    local f = load("return function() return q end", "q", "t", {q = 7})()
+    local g = load("return _ENV")
+    local env = {}
+    local h = load("return _ENV", "h", "t", env)
+    return f() == 7 and g() == _G and h() == env
+end
+
+local function test7()
+    local _ENV = {_ENV = _ENV}
+    local function f()
+        _ENV = {z = 1}
+    end
+    f()
+    return z == 1
+end
+
+local function test8()
+    local f = load("return x", "nil env", "t", nil)
+    local ok = pcall(f)
+    -- A string environment indexes through the string metatable
+    local len = load("return len", "string env", "t", "abc")()
+    return not ok and len == string.len and _G._G == _G
+end
+
 return
     test1() and
-    test2()
\ No newline at end of file
+    test2() and
+    test3() and
+    test4() and
+    test5() and
+    test6() and
+    test7() and
+    test8()
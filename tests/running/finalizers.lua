@@ -0,0 +1,117 @@
+-- Tables are created inside functions, so that no register still refers to them once they return
+
+local function test1()
+    local finalized = {}
+    local function new(name)
+        setmetatable({name = name}, {__gc = function(t) finalized[#finalized + 1] = t.name end})
+    end
+    new("a")
+    new("b")
+    new("c")
+    collectgarbage()
+    -- Finalizers are called in the reverse order that the tables were registered
+    return #finalized == 3 and finalized[1] == "c" and finalized[2] == "b" and finalized[3] == "a"
+end
+
+local function test2()
+    local resurrected
+    local function new()
+        local child = {value = 1}
+        setmetatable({child = child}, {__gc = function(t) resurrected = t end})
+    end
+    new()
+    collectgarbage()
+    -- Everything the table refers to is kept alive along with it
+    local ok = resurrected ~= nil and resurrected.child.value == 1
+
+    -- A resurrected table is not finalized again
+    local calls = 0
+    getmetatable(resurrected).__gc = function() calls = calls + 1 end
+    resurrected = nil
+    collectgarbage()
+    return ok and calls == 0
+end
+
+local function test3()
+    local calls = 0
+    local function new()
+        local t = setmetatable({}, {
+            __gc = function(t)
+                calls = calls + 1
+                if calls < 3 then
+                    -- Setting the metatable again registers the table again
+                    setmetatable(t, getmetatable(t))
+                end
+            end
+        })
+    end
+    new()
+    for i = 1, 5 do
+        collectgarbage()
+    end
+    return calls == 3
+end
+
+local function test4()
+    local called = false
+    local function added()
+        local mt = {}
+        setmetatable({}, mt)
+        mt.__gc = function() called = true end
+    end
+    local function removed()
+        local mt = {__gc = function() called = true end}
+        setmetatable({}, mt)
+        mt.__gc = nil
+    end
+    local replaced
+    local function changed()
+        local mt = {__gc = function() replaced = false end}
+        setmetatable({}, mt)
+        mt.__gc = function() replaced = true end
+    end
+    added()
+    removed()
+    changed()
+    collectgarbage()
+    -- Only a metatable with `__gc` when it is set registers the table, but the finalizer called is
+    -- the one it has when the table is collected
+    return called == false and replaced == true
+end
+
+local function test5()
+    local after = false
+    local function new()
+        setmetatable({}, {__gc = function() after = true end})
+        setmetatable({}, {__gc = function() error("finalizer error") end})
+    end
+    new()
+    collectgarbage()
+    -- Errors raised by finalizers are discarded
+    return after
+end
+
+local function test6()
+    local count = 0
+    local mt = {__gc = function(t) count = count + 1 end}
+    for i = 1, 10000 do
+        setmetatable({}, mt)
+    end
+    collectgarbage()
+    return count == 10000
+end
+
+local function test7()
+    local finalized = false
+    local co = coroutine.wrap(function()
+        local function new()
+            setmetatable({}, {__gc = function() finalized = true end})
+        end
+        new()
+        collectgarbage()
+        coroutine.yield(finalized)
+    end)
+    return co() == true
+end
+
+return test1() and test2() and test3() and test4() and test5() and test6() and test7()
@@ -30,6 +30,54 @@ function test2()
     goto start
 end
 
+function test3()
+    local sum = 0
+    for i = 1, 10 do
+        if i % 2 == 0 then
+            goto continue
+        end
+        local odd = i
+        sum = sum + odd
+        ::continue::
+    end
+
+    local n = 0
+    while n < 5 do
+        n = n + 1
+        if n == 3 then
+            goto continue
+        end
+        sum = sum + 100
+        ::continue::
+    end
+
+    return sum == 425
+end
+
+function test4()
+    local closures = {}
+    local i = 1
+    ::top::
+    do
+        local captured = i
+        closures[i] = function() return captured end
+    end
+    i = i + 1
+    if i <= 3 then
+        goto top
+    end
+
+    do
+        goto out
+    end
+    i = 100
+    ::out::
+
+    return closures[1]() == 1 and closures[2]() == 2 and closures[3]() == 3 and i == 4
+end
+
 return
     test1() and
-    test2()
\ No newline at end of file
+    test2() and
+    test3() and
+    test4()
\ No newline at end of file
@@ -0,0 +1,70 @@
+local function test1()
+    return
+        json.encode(nil) == "null" and
+        json.encode(true) == "true" and
+        json.encode(42) == "42" and
+        json.encode(1.5) == "1.5" and
+        json.encode(2.0) == "2.0" and
+        json.encode("a\"b\\c\n\1") == [["a\"b\\c\n\u0001"]] and
+        json.encode({}) == "{}" and
+        json.encode({ 1, "two", false }) == '[1,"two",false]' and
+        json.encode({ a = { b = 1 } }) == '{"a":{"b":1}}' and
+        json.encode({ [10] = "x" }) == '{"10":"x"}' and
+        json.encode({ 1, json.null, 3 }) == "[1,null,3]"
+end
+
+local function test2()
+    local s = json.encode({ b = { 1, 2 }, a = {}, c = "x" }, { pretty = true, sort_keys = true })
+    local expected = '{\n  "a": {},\n  "b": [\n    1,\n    2\n  ],\n  "c": "x"\n}'
+    return s == expected and
+        json.encode({ 1 }, { indent = "\t" }) == "[\n\t1\n]"
+end
+
+local function test3()
+    local t = json.decode([[
+        { "name": "luster", "list": [1, 2.5, -3e2, true, null, "é😀"],
+          "nested": { "empty": [], "big": 18446744073709551616 } }
+    ]])
+    return
+        t.name == "luster" and
+        math.type(t.list[1]) == "integer" and t.list[2] == 2.5 and t.list[3] == -300.0 and
+        t.list[4] == true and t.list[5] == nil and t.list[6] == "\u{e9}\u{1F600}" and
+        next(t.nested.empty) == nil and math.type(t.nested.big) == "float" and
+        json.decode("[null]", { null = json.null })[1] == json.null and
+        json.decode(' "\\/" ') == "/"
+end
+
+local function test4()
+    local t = { x = { 1, 2, { y = "z" } }, n = -0.25 }
+    local u = json.decode(json.encode(t))
+    return u.n == -0.25 and u.x[2] == 2 and u.x[3].y == "z"
+end
+
+local function test5()
+    local cyclic = {}
+    cyclic.self = cyclic
+    local function fails(f, ...)
+        return not pcall(f, ...)
+    end
+    return
+        fails(json.encode, cyclic) and
+        fails(json.encode, print) and
+        fails(json.encode, 0 / 0) and
+        fails(json.encode, { [true] = 1 }) and
+        fails(json.encode, "\xff") and
+        fails(json.decode, "") and
+        fails(json.decode, "[1,]") and
+        fails(json.decode, "{1: 2}") and
+        fails(json.decode, "[1] 2") and
+        fails(json.decode, '"\\ud800"') and
+        fails(json.decode, '"\\u+123"') and
+        fails(json.decode, "01") and
+        fails(json.decode, string.rep("[", 2000))
+end
+
+return
+    test1() and
+    test2() and
+    test3() and
+    test4() and
+    test5()
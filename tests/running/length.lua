@@ -0,0 +1,36 @@
+local function test1()
+    local t = { 1, 2, 3 }
+    t[4] = 4
+    t[8] = 8
+    local u = {}
+    u[1] = 1
+    u[2] = 2
+    u[4] = 4
+    return
+        #{} == 0 and
+        #{ 1, 2, 3 } == 3 and
+        #{ 1, nil, 3 } == 3 and
+        #{ nil, nil, 3 } == 3 and
+        #{ 1, 2, nil } == 2 and
+        #{ n = 1 } == 0 and
+        #t == 8 and
+        #u == 4
+end
+
+local function test2()
+    -- Either side of the hole is a valid border
+    local t = {}
+    for i = 1, 10 do
+        t[i] = i
+    end
+    t[5] = nil
+    local mt = setmetatable({}, { __len = function() return 42 end })
+    return
+        (#t == 4 or #t == 10) and
+        #mt == 42 and rawlen(mt) == 0 and
+        #"abc" == 3
+end
+
+return
+    test1() and
+    test2()
@@ -0,0 +1,85 @@
+local function test_string()
+    local add = load("local a, b = ... return a + b")
+    local bad, message = load("return +")
+    return
+        add(1, 2) == 3 and
+        load("return 7", "chunk")() == 7 and
+        load(12) == nil and
+        bad == nil and type(message) == "string"
+end
+
+local function test_reader()
+    local parts = {"local x = ", "4", "2 return x", "", "error()"}
+    local i = 0
+    local f = load(function()
+        i = i + 1
+        return parts[i]
+    end)
+
+    local calls = 0
+    local empty = load(function()
+        calls = calls + 1
+        return nil
+    end)
+
+    local bad_piece, piece_message = load(function() return {} end)
+    local erroring, error_message = load(function() error("reader failed", 0) end)
+    local erroring_value, error_value = load(function() error({}) end)
+
+    return
+        f() == 42 and i == 4 and
+        empty() == nil and calls == 1 and
+        bad_piece == nil and type(piece_message) == "string" and
+        erroring == nil and error_message == "reader failed" and
+        erroring_value == nil and type(error_value) == "table"
+end
+
+local function test_names()
+    local _, syntax_message = load("x = ")
+    local _, named_message = load("x = ", "=named")
+    local _, runtime_message = pcall(load("error('failed')"))
+    local _, long_message = pcall(load("error('failed')\nreturn"))
+    local _, reader_runtime_message = pcall(load(coroutine.wrap(function()
+        coroutine.yield("error('failed')")
+    end)))
+    return
+        syntax_message == '[string "x = "]:1:5: unexpected symbol near <eof>' and
+        named_message == "named:1:5: unexpected symbol near <eof>" and
+        runtime_message == '[string "error(\'failed\')"]:1: failed' and
+        long_message == '[string "error(\'failed\')..."]:1: failed' and
+        reader_runtime_message == "(load):1: failed"
+end
+
+local function test_env()
+    local env = {y = 10}
+    local f = load("y = y + 1 return y", "env", "t", env)
+    local g = load("return print")
+    return
+        f() == 11 and env.y == 11 and y == nil and
+        g() == print
+end
+
+local function test_mode()
+    local text, text_message = load("return 1", "mode", "b")
+    local binary, binary_message = load("\27Lua", "mode", "t")
+    local dumped = string.dump(function() return 1 end)
+    local truncated, truncated_message = load(dumped:sub(1, -2))
+    local cut, cut_message = load(dumped:sub(1, 6))
+    return
+        load("return 1", "mode", "t")() == 1 and
+        load("return 1", "mode", "bt")() == 1 and
+        text == nil and text_message == "attempt to load a text chunk (mode is 'b')" and
+        binary == nil and binary_message == "attempt to load a binary chunk (mode is 't')" and
+        truncated == nil and truncated_message:find("truncated precompiled chunk") and
+        cut == nil and cut_message:find("truncated precompiled chunk") and
+        not pcall(load) and
+        not pcall(load, {}) and
+        load("return 1", "chunk", "t", 1)() == 1
+end
+
+return
+    test_string() and
+    test_reader() and
+    test_names() and
+    test_env() and
+    test_mode()
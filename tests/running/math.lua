@@ -116,7 +116,13 @@ function test12()
            math.abs(math.fmod(-6.2,  3.4) + 2.8) < 1e-7 and
            math.abs(math.fmod( 6.2, -3.4) - 2.8) < 1e-7 and
            math.abs(math.fmod(-6.2, -3.4) + 2.8) < 1e-7 and
-       not is_integer(math.fmod(1.0, 1.0))
+       not is_integer(math.fmod(1.0, 1.0)) and
+           math.fmod(7, 3) == 1 and is_integer(math.fmod(7, 3)) and
+           math.fmod(-7, 3) == -1 and
+           math.fmod(math.mininteger, -1) == 0 and
+       not pcall(math.fmod, 1) and
+           select(2, pcall(math.fmod, 1, 0)) == "bad argument #2 to 'fmod' (zero)" and
+           is_nan(math.fmod(1, 0.0))
 end
 
 function test13()
@@ -348,7 +354,11 @@ function test25()
            math.tointeger(1.1) == nil and
            math.tointeger(-3.0) == -3 and
            math.tointeger(4.00000002) == nil and
-           is_integer(math.tointeger(8.0))
+           is_integer(math.tointeger(8.0)) and
+           math.tointeger(2^63) == nil and
+           math.tointeger(-2^63) == math.mininteger and
+           math.tointeger(math.huge) == nil and
+           math.tointeger(0/0) == nil
 end
 
 function test26()
@@ -363,6 +373,65 @@ function test27()
                math.ult(1, 2)
 end
 
+function test28()
+    return math.floor(math.maxinteger) == math.maxinteger and
+           math.type(math.floor(math.mininteger)) == "integer" and
+           math.type(math.floor(3.7)) == "integer" and
+           math.type(math.ceil("3.2")) == "integer" and math.ceil("3.2") == 4 and
+           math.type(math.floor(2^63)) == "float" and math.floor(2^63) == 2^63 and
+           math.type(math.floor(-2^63)) == "integer" and math.floor(-2^63) == math.mininteger and
+           math.type(math.ceil(-1e100)) == "float" and
+           math.floor(math.huge) == math.huge and
+           math.ceil(-math.huge) == -math.huge and
+           is_nan(math.floor(0/0)) and
+           math.abs(math.mininteger) == math.mininteger and
+           math.abs(-0.0) == 0.0 and math.abs("-2") == 2
+end
+
+function test29()
+    return math.log(8, 2) == 3.0 and
+           math.log(1000, 10) == 3.0 and
+           math.abs(math.log(27, 3) - 3) < 1e-7 and
+           math.exp(0) == 1.0 and math.type(math.exp(0)) == "float" and
+           math.sqrt(16) == 4.0 and math.type(math.sqrt(16)) == "float" and
+           math.sin("0") == 0.0 and
+           math.pi == 3.141592653589793 and
+           math.huge > math.maxinteger and -math.huge < math.mininteger
+end
+
+function test30()
+    local ok, err = pcall(math.floor)
+    local ok2, err2 = pcall(math.sqrt, "x")
+    local ok3, err3 = pcall(math.log, 2, {})
+    return not ok and err == "bad argument #1 to 'floor' (number expected, got no value)" and
+           not ok2 and err2 == "bad argument #1 to 'sqrt' (number expected, got string)" and
+           not ok3 and err3 == "bad argument #2 to 'log' (number expected, got table)"
+end
+
+function test31()
+    local full = true
+    for i = 1, 100 do
+        local r = math.random(math.mininteger, math.maxinteger)
+        full = full and math.type(r) == "integer"
+    end
+    local negative = true
+    for i = 1, 1000 do
+        local r = math.random(-3, -1)
+        negative = negative and r >= -3 and r <= -1
+    end
+    local ok1, err1 = pcall(math.random, 0)
+    local ok2, err2 = pcall(math.random, 2, 1)
+    local ok3, err3 = pcall(math.random, 1, 2, 3)
+    return full and negative and
+           math.random(3, 3) == 3 and
+           math.random(math.maxinteger, math.maxinteger) == math.maxinteger and
+           math.type(math.random(2.0)) == "integer" and
+           not pcall(math.random, 1.5) and
+           not ok1 and err1 == "bad argument #1 to 'random' (interval is empty)" and
+           not ok2 and err2 == "bad argument #2 to 'random' (interval is empty)" and
+           not ok3 and err3 == "wrong number of arguments"
+end
+
 return test1() and
        test2() and
        test3() and
@@ -389,4 +458,8 @@ return test1() and
        test24() and
        test25() and
        test26() and
-       test27()
+       test27() and
+       test28() and
+       test29() and
+       test30() and
+       test31()
@@ -0,0 +1,45 @@
+function test1()
+    local t = {}
+    local mt = {}
+    return
+        getmetatable(t) == nil and
+        setmetatable(t, mt) == t and
+        getmetatable(t) == mt and
+        setmetatable(t, nil) == t and
+        getmetatable(t) == nil and
+        getmetatable(1) == nil and
+        getmetatable(nil) == nil
+end
+
+function test2()
+    local t = setmetatable({}, { __metatable = "protected" })
+    local ok = pcall(setmetatable, t, {})
+    local ok_nil = pcall(setmetatable, t, nil)
+    return
+        getmetatable(t) == "protected" and
+        not ok and
+        not ok_nil and
+        getmetatable(t) == "protected"
+end
+
+function test3()
+    local function message(f, ...)
+        local ok, err = pcall(f, ...)
+        return not ok and err
+    end
+
+    return
+        message(setmetatable, 1, {}) ==
+            "bad argument #1 to 'setmetatable' (table expected, got number)" and
+        message(setmetatable, {}, 5) ==
+            "bad argument #2 to 'setmetatable' (nil or table expected)" and
+        message(setmetatable, {}) ==
+            "bad argument #2 to 'setmetatable' (nil or table expected)" and
+        message(setmetatable) ==
+            "bad argument #1 to 'setmetatable' (table expected, got no value)"
+end
+
+return
+    test1() and
+    test2() and
+    test3()
@@ -0,0 +1,75 @@
+local function test1()
+    local t = {}
+    for i = 1, 5 do
+        if i == 2 then
+            goto continue
+        elseif i == 4 then
+            break
+        else
+            t[#t + 1] = i
+        end
+        ::continue::
+    end
+    return #t == 2 and t[1] == 1 and t[2] == 3
+end
+
+local function test2()
+    local a, b = 1, 2
+    local t = a
+    a = b
+    b = t
+    t = b
+    b = a
+    a = t
+    local c = a
+    c = b
+    return a == 1 and b == 2 and c == 2
+end
+
+local function test3()
+    local n = 0
+    while true do
+        while true do
+            n = n + 1
+            if n % 2 == 0 then
+                break
+            end
+        end
+        if n > 6 then
+            break
+        end
+    end
+    return n == 8
+end
+
+local function test4()
+    local fs = {}
+    for i = 1, 3 do
+        local j = i
+        repeat
+            fs[#fs + 1] = function() return j end
+            if j > 1 then
+                break
+            end
+            j = j + 10
+        until j > 20
+    end
+    return fs[1]() == 11 and fs[2]() == 11 and fs[3]() == 2 and fs[4]() == 3
+end
+
+local function test5(x)
+    if x then
+        return 1
+    else
+        return 2
+    end
+    return 3
+end
+
+return
+    test1() and
+    test2() and
+    test3() and
+    test4() and
+    test5(true) == 1 and
+    test5(false) == 2
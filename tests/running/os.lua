@@ -0,0 +1,61 @@
+local function test1()
+    local t = 1234567890
+    return
+        os.date("!%c", t) == "Fri Feb 13 23:31:30 2009" and
+        os.date("!%Y-%m-%d %H:%M:%S", t) == "2009-02-13 23:31:30" and
+        os.date("!%a %A %b %B %h", t) == "Fri Friday Feb February Feb" and
+        os.date("!%j %U %W %V %G %g %u %w", t) == "044 06 06 07 2009 09 5 5" and
+        os.date("!%I %p %D %e %y %C %F %T %R %r", t) ==
+            "11 PM 02/13/09 13 09 20 2009-02-13 23:31:30 23:31 11:31:30 PM" and
+        os.date("!%Ec %Ey %OH %%%n%t", t) == "Fri Feb 13 23:31:30 2009 09 23 %\n\t" and
+        os.date("!%G-W%V-%u", 1609459200) == "2020-W53-5" and
+        os.date("!%Y-%m-%d %H:%M:%S", -1) == "1969-12-31 23:59:59" and
+        os.date("!%z", t) == "+0000"
+end
+
+local function test2()
+    local d = os.date("!*t", 1234567890)
+    return
+        d.year == 2009 and d.month == 2 and d.day == 13 and
+        d.hour == 23 and d.min == 31 and d.sec == 30 and
+        d.wday == 6 and d.yday == 44 and d.isdst == false
+end
+
+local function test3()
+    local t = os.time({ year = 2020, month = 6, day = 15, hour = 10, min = 30 })
+    local d = os.date("*t", t)
+    local noon = os.date("*t", os.time({ year = 2020, month = 6, day = 15 }))
+    return
+        math.type(t) == "integer" and
+        d.year == 2020 and d.month == 6 and d.day == 15 and d.hour == 10 and d.min == 30 and
+        d.sec == 0 and noon.hour == 12 and
+        os.time({ year = 2020, month = 13, day = 1 }) == os.time({ year = 2021, month = 1, day = 1 }) and
+        os.time({ year = 2020, month = 3, day = 0 }) == os.time({ year = 2020, month = 2, day = 29 }) and
+        math.type(os.time()) == "integer" and
+        os.date("*t").year >= 2020
+end
+
+local function test4()
+    local ok1, e1 = pcall(os.date, "%Q")
+    local ok2, e2 = pcall(os.date, "%E")
+    local ok3, e3 = pcall(os.time, { year = 2000, month = 1 })
+    local ok4, e4 = pcall(os.time, { year = 2000, month = 1, day = 1.5 })
+    local ok5, e5 = pcall(os.time, { year = 2000, month = 1, day = 1 << 40 })
+    return
+        ok1 == false and e1 == "bad argument #1 to 'date' (invalid conversion specifier '%Q')" and
+        ok2 == false and e2 == "bad argument #1 to 'date' (invalid conversion specifier '%E')" and
+        ok3 == false and e3 == "field 'day' missing in date table" and
+        ok4 == false and e4 == "field 'day' is not an integer" and
+        ok5 == false and e5 == "field 'day' is out-of-bound"
+end
+
+local function test5()
+    local c = os.clock()
+    return
+        math.type(c) == "float" and c >= 0 and
+        os.difftime(10, 4) == 6.0 and math.type(os.difftime(10, 4)) == "float" and
+        os.getenv("LUSTER_SURELY_UNSET_VARIABLE") == nil and
+        os.getenv("a=b") == nil
+end
+
+return test1() and test2() and test3() and test4() and test5()
@@ -0,0 +1,113 @@
+function test1()
+    local t = { 1, 2, 3, a = "a", b = "b", [4.5] = "f" }
+    local count = 0
+    local sum = 0
+    local seen = {}
+    for k, v in pairs(t) do
+        count = count + 1
+        seen[k] = v
+        if type(v) == "number" then
+            sum = sum + v
+        end
+    end
+    return
+        count == 6 and sum == 6 and
+        seen.a == "a" and seen.b == "b" and seen[4.5] == "f" and seen[3] == 3
+end
+
+function test2()
+    local t = {}
+    for i = 1, 100 do
+        t["k" .. i] = i
+    end
+
+    -- Removing entries during traversal is allowed
+    local count = 0
+    for k, v in pairs(t) do
+        count = count + 1
+        t[k] = nil
+    end
+
+    return count == 100 and next(t) == nil and next({}) == nil
+end
+
+function test3()
+    local t = { 10, x = 1 }
+    local k1, v1 = next(t)
+    local k2, v2 = next(t, k1)
+    local k3 = next(t, k2)
+    return
+        k1 == 1 and v1 == 10 and k2 == "x" and v2 == 1 and k3 == nil and
+        not pcall(next, t, "missing") and
+        not pcall(next, 1)
+end
+
+function test4()
+    local proxy = setmetatable({}, {
+        __pairs = function(self)
+            local i = 0
+            return function(state, control)
+                i = i + 1
+                if i <= 3 then
+                    return i, state * i
+                end
+            end, 10, nil
+        end
+    })
+
+    local keys = 0
+    local sum = 0
+    for k, v in pairs(proxy) do
+        keys = keys + k
+        sum = sum + v
+    end
+
+    local short = setmetatable({}, {
+        __pairs = function(self)
+            return next
+        end
+    })
+    local f, s, c = pairs(short)
+
+    return keys == 6 and sum == 60 and f == next and s == nil and c == nil
+end
+
+function test5()
+    local t = { 10, 20, 30, nil, 50, x = "x" }
+    local count = 0
+    local sum = 0
+    for i, v in ipairs(t) do
+        count = count + 1
+        sum = sum + i * v
+    end
+
+    local proxy = setmetatable({ 1 }, {
+        __index = function(t, i)
+            if i <= 4 then
+                return i * 10
+            end
+        end
+    })
+    local proxied = {}
+    for i, v in ipairs(proxy) do
+        proxied[i] = v
+    end
+
+    local f, s, c = ipairs(t)
+    local i1, v1 = f(s, c)
+
+    return
+        count == 3 and sum == 140 and
+        #proxied == 4 and proxied[1] == 1 and proxied[4] == 40 and
+        s == t and c == 0 and i1 == 1 and v1 == 10 and
+        f(t, 3) == nil and
+        not pcall(ipairs) and
+        not pcall(f, nil, 0)
+end
+
+return
+    test1() and
+    test2() and
+    test3() and
+    test4() and
+    test5()
@@ -1,6 +1,6 @@
 function test1()
     local function error_func(e)
-        error(e)
+        error(e, 0)
     end
     local function good_func()
         return "good"
@@ -41,6 +41,69 @@ function test2()
         e4 == true and r4 == nil and s4 == "dead"
 end
 
+function test3()
+    local inner, outer = load(
+        "local function inner(e, level)\n" ..
+        "    error(e, level)\n" ..
+        "end\n" ..
+        "local function outer(e, level)\n" ..
+        "    inner(e, level)\n" ..
+        "end\n" ..
+        "return inner, outer", "=chunk")()
+
+    local t = {}
+    local _, e1 = pcall(inner, "msg")
+    local _, e2 = pcall(inner, "msg", 2)
+    local _, e3 = pcall(outer, "msg", 2)
+    local _, e4 = pcall(outer, "msg", 3)
+    local _, e5 = pcall(inner, "msg", 0)
+    local _, e6 = pcall(inner, t)
+    local _, e7 = pcall(inner)
+    local _, e8 = pcall(load("local x = nil + 1", "=arith"))
+
+    return
+        e1 == "chunk:2: msg" and
+        e2 == "msg" and
+        e3 == "chunk:5: msg" and
+        e4 == "msg" and
+        e5 == "msg" and
+        e6 == t and
+        e7 == nil and
+        e8 ~= nil
+end
+
+function test4()
+    -- Errors unwind nested calls and leave the caller's stack intact
+    local a, b, c = 1, 2, 3
+    local function deep(n)
+        local x = n * 2
+        if n == 0 then
+            error("bottom", 0)
+        end
+        return deep(n - 1) + x
+    end
+    local function nested()
+        local ok, err = pcall(deep, 10)
+        local v = 5
+        return ok, err, v
+    end
+
+    local ok1, err1, v1 = nested()
+    local ok2, err2 = pcall(pcall, error, "inner")
+    local ok3, err3 = pcall(function()
+        local ok, err = pcall(error, "first", 0)
+        error(err .. " second", 0)
+    end)
+
+    return
+        a == 1 and b == 2 and c == 3 and
+        ok1 == false and err1 == "bottom" and v1 == 5 and
+        ok2 == true and err2 == false and
+        ok3 == false and err3 == "first second"
+end
+
 return
     test1() and
-    test2()
+    test2() and
+    test3() and
+    test4()
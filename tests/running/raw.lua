@@ -0,0 +1,44 @@
+function test1()
+    local log = {}
+    local t = setmetatable({ present = 1 }, {
+        __index = function() return "meta" end,
+        __newindex = function(t, k, v) log[#log + 1] = k end,
+        __len = function() return 42 end,
+        __eq = function() return true end,
+    })
+
+    rawset(t, "a", 2)
+    return
+        rawget(t, "missing") == nil and t.missing == "meta" and
+        rawget(t, "present") == 1 and
+        rawget(t, "a") == 2 and #log == 0 and
+        rawset(t, 1, "x") == t and
+        rawlen(t) == 1 and #t == 42 and
+        rawlen("abcd") == 4 and
+        rawequal(t, t) and
+        not rawequal(t, setmetatable({}, getmetatable(t))) and
+        rawequal(1, 1.0) and
+        not rawequal("a", "b")
+end
+
+function test2()
+    local function message(f, ...)
+        local ok, err = pcall(f, ...)
+        return not ok and err
+    end
+
+    return
+        message(rawget) == "bad argument #1 to 'rawget' (table expected, got no value)" and
+        message(rawget, 1, 2) == "bad argument #1 to 'rawget' (table expected, got number)" and
+        message(rawget, {}) == "bad argument #2 to 'rawget' (value expected)" and
+        rawget({}, nil) == nil and
+        message(rawset, {}, 1) == "bad argument #3 to 'rawset' (value expected)" and
+        message(rawset, nil, 1, 2) == "bad argument #1 to 'rawset' (table expected, got nil)" and
+        message(rawlen, 1) == "bad argument #1 to 'rawlen' (table or string expected, got number)" and
+        message(rawequal, 1) == "bad argument #2 to 'rawequal' (value expected)" and
+        rawequal(nil, nil)
+end
+
+return
+    test1() and
+    test2()
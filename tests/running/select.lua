@@ -0,0 +1,51 @@
+function test1()
+    local a, b, c = select(2, "a", "b", "c")
+    local d = select(3, "a", "b", "c")
+    return
+        a == "b" and b == "c" and c == nil and d == "c" and
+        select("#") == 0 and
+        select("#", nil, nil) == 2 and
+        select("#", select(5, 1, 2, 3)) == 0 and
+        select("#", select(4, 1, 2, 3)) == 0 and
+        select("#", select(1, 1, 2, 3)) == 3
+end
+
+function test2()
+    local a, b = select(-1, "a", "b", "c")
+    local c, d = select(-2, "a", "b", "c")
+    local e = select(-3, "a", "b", "c")
+    return
+        a == "c" and b == nil and
+        c == "b" and d == "c" and
+        e == "a" and
+        select("#", select(-3, "a", "b", "c")) == 3
+end
+
+function test3()
+    local function count(...)
+        return select("#", ...)
+    end
+    local function last(...)
+        return (select(-1, ...))
+    end
+    return count(1, nil, 3, nil) == 4 and last(1, 2, 3) == 3
+end
+
+function test4()
+    local function message(f, ...)
+        local ok, err = pcall(f, ...)
+        return not ok and err
+    end
+
+    return
+        message(select, 0, "a") == "bad argument #1 to 'select' (index out of range)" and
+        message(select, -2, "a") == "bad argument #1 to 'select' (index out of range)" and
+        message(select, "x") == "bad argument #1 to 'select' (number expected, got string)" and
+        message(select) == "bad argument #1 to 'select' (number expected, got no value)"
+end
+
+return
+    test1() and
+    test2() and
+    test3() and
+    test4()
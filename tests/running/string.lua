@@ -35,5 +35,97 @@ function test_len()
         string.len(-2147483648) == 11
 end
 
+function test_methods()
+    local s = "hello"
+    local mt = getmetatable("")
+    string.shout = function(s) return s .. "!" end
+    local shouted = s:shout()
+    string.shout = nil
+    return
+        s:len() == 5 and
+        ("abc"):len() == 3 and
+        shouted == "hello!" and
+        s.len == string.len and
+        s.missing == nil and
+        mt == getmetatable("other") and
+        mt.__index == string and
+        is_err(function() return s:missing() end) and
+        is_err(function() return (1):len() end)
+end
+
+function test_sub()
+    local s = "hello"
+    return
+        s:sub(2) == "ello" and
+        s:sub(2, 3) == "el" and
+        s:sub(-3) == "llo" and
+        s:sub(-3, -2) == "ll" and
+        s:sub(0) == "hello" and
+        s:sub(-100, 2) == "he" and
+        s:sub(2, 100) == "ello" and
+        s:sub(4, 2) == "" and
+        s:sub(6) == "" and
+        s:sub(1, 0) == "" and
+        s:sub(math.mininteger, math.maxinteger) == "hello" and
+        s:sub("2", 3.0) == "el" and
+        ("a\0b"):sub(2, 2) == "\0" and
+        string.sub(12345, 2, -2) == "234" and
+        is_err(function() return s:sub(1.5) end) and
+        is_err(function() return string.sub() end)
+end
+
+function test_rep()
+    return
+        string.rep("ab", 3) == "ababab" and
+        string.rep("ab", 3, ", ") == "ab, ab, ab" and
+        string.rep("ab", 1, ", ") == "ab" and
+        string.rep("ab", 0) == "" and
+        string.rep("ab", -1, "x") == "" and
+        string.rep("", 5) == "" and
+        string.rep("", 3, "x") == "xx" and
+        string.rep(1, 2) == "11" and
+        select(2, pcall(string.rep, "x", math.maxinteger)) == "resulting string too large" and
+        is_err(function() return string.rep("x") end)
+end
+
+function test_case()
+    return
+        string.upper("Hello, World! 123") == "HELLO, WORLD! 123" and
+        string.lower("Hello, World! 123") == "hello, world! 123" and
+        string.upper("\xe9t\xe9") == "\xe9T\xe9" and
+        string.reverse("abc") == "cba" and
+        string.reverse("") == "" and
+        string.reverse("a\0\xff") == "\xff\0a" and
+        ("abc"):upper() == "ABC"
+end
+
+function test_byte_char()
+    local a, b, c = string.byte("abc", 1, -1)
+    return
+        string.byte("abc") == 97 and
+        string.byte("abc", 2) == 98 and
+        string.byte("abc", -1) == 99 and
+        a == 97 and b == 98 and c == 99 and
+        select("#", string.byte("abc", 4)) == 0 and
+        select("#", string.byte("", 1)) == 0 and
+        select("#", string.byte("abc", 0)) == 0 and
+        select("#", string.byte("abc", -10, 10)) == 3 and
+        string.byte("\xff") == 255 and
+        string.char(97, 98, 99) == "abc" and
+        string.char() == "" and
+        string.char(0, 255) == "\0\xff" and
+        string.char("65") == "A" and
+        string.char(string.byte("hello", 1, -1)) == "hello" and
+        select(2, pcall(string.char, 65, 256)) ==
+            "bad argument #2 to 'char' (value out of range)" and
+        is_err(function() return string.char(-1) end) and
+        is_err(function() return string.char("x") end)
+end
+
 return test_concat() and
-       test_len()
+       test_len() and
+       test_methods() and
+       test_sub() and
+       test_rep() and
+       test_case() and
+       test_byte_char()
@@ -0,0 +1,73 @@
+local t = setmetatable({}, {__tostring = function() return "T!" end})
+
+local cases = {
+    {string.format("%d|%5d|%-5d|%05d|%+d|% d|%.3d|%.0d", 42, 42, 42, 42, 42, 42, 7, 0),
+        "42|   42|42   |00042|+42| 42|007|"},
+    {string.format("%i %d %d %d", -3, 3.0, "12", math.mininteger), "-3 3 12 -9223372036854775808"},
+    {string.format("%u %x %X %#x %o %#o %#o %08.3x", 3, 255, 255, 255, 8, 8, 0, 255),
+        "3 ff FF 0xff 10 010 0      0ff"},
+    {string.format("%x %u", -1, -1), "ffffffffffffffff 18446744073709551615"},
+    {string.format("%f %.2f %10.3f %-10.1f| %+.0f %#.0f", 1.5, 3.14159, -2.5, 1, 2.5, 3),
+        "1.500000 3.14     -2.500 1.0       | +2 3."},
+    {string.format("%e %.3E %010.2e %.0e", 12345.678, 0.00012, -5, 1e300),
+        "1.234568e+04 1.200E-04 -05.00e+00 1e+300"},
+    {string.format("%g %g %g %g %g %g %#g %G", 100000, 1000000, 0.0001, 0.00001, 1.5, 0, 1.5, 1e-10),
+        "100000 1e+06 0.0001 1e-05 1.5 0 1.50000 1E-10"},
+    {string.format("%.3g %.10g %g %.0g", 3.14159, 0.1, 2^53, 26), "3.14 0.1 9.0072e+15 3e+01"},
+    {string.format("%f %e %5.1f %F %05f", 1/0, -1/0, 1/0, 1/0, -1/0), "inf -inf   inf INF  -inf"},
+    {string.format("%s %s %s %s %.2s %5s %-5s|", "x", 1, true, nil, "abc", "ab", "ab"),
+        "x 1 true nil ab    ab ab   |"},
+    {string.format("%s and %s", t, "y"), "T! and y"},
+    {string.format("%c%c%c %3c", 76, 117, 97, 65), "Lua   A"},
+    {string.format("%q", 'a "quoted"\n\\ \0 \0001 \r \1x'),
+        '"a \\"quoted\\"\\\n\\\\ \\0 \\0001 \\r \\1x"'},
+    {string.format("%q %q %q %q %q %q %q", 1, math.mininteger, 0.1, 1/0, -1/0, nil, false),
+        "1 0x8000000000000000 0x1.999999999999ap-4 1e9999 -1e9999 nil false"},
+    {string.format("%q %q %q %q", 1.0, -0.0, 2^-1074, -1.5),
+        "0x1p+0 -0x0p+0 0x0.0000000000001p-1022 -0x1.8p+0"},
+    {string.format("%a %A %a %a %a", 1, 0.1, 0, -2.5, 2^-1074),
+        "0x1p+0 0X1.999999999999AP-4 0x0p+0 -0x1.4p+1 0x0.0000000000001p-1022"},
+    {string.format("%.0a %.0a %.1a %.1a %.2a %.15a", 1.5, 2.5, 0.1, 1.96875, 1.96875, 1),
+        "0x2p+0 0x1p+1 0x1.ap-4 0x2.0p+0 0x1.f8p+0 0x1.000000000000000p+0"},
+    {string.format("%#a %+a % a %12a|%-12a|%012a", 1, 1, 1, 1.5, 1.5, -1.5),
+        "0x1.p+0 +0x1p+0  0x1p+0     0x1.8p+0|0x1.8p+0    |-0x0001.8p+0"},
+    {(string.format("%a %A", 1/0, 0/0):gsub("-", "")), "inf NAN"},
+    {string.format("%%d 100%%"), "%d 100%"},
+    {string.format("%5.1s|", "abc"), "    a|"},
+    {string.format("no conversions"), "no conversions"},
+}
+
+for i, case in ipairs(cases) do
+    if case[1] ~= case[2] then
+        return false
+    end
+end
+
+local function err(...)
+    return select(2, pcall(string.format, ...))
+end
+
+local roundtrip = {
+    'a\n\0b\200"\r\0011', 0.1 + 0.2, 1e100, -0.0, 2^-1074, -1.5, math.mininteger, math.maxinteger
+}
+for _, v in ipairs(roundtrip) do
+    local r = load("return " .. string.format("%q", v))()
+    if r ~= v or math.type(r) ~= math.type(v) then
+        return false
+    end
+end
+
+return
+    err("%y", 1) == "invalid conversion '%y' to 'format'" and
+    err("%10q", 1) == "invalid conversion '%10q' to 'format'" and
+    err("%100d", 1) == "invalid conversion '%100d' to 'format'" and
+    err("%#d", 1) == "invalid conversion '%#d' to 'format'" and
+    err("%.3c", 1) == "invalid conversion '%.3c' to 'format'" and
+    err("%", 1) == "invalid conversion '%' to 'format'" and
+    err("%d", 1.5) == "bad argument #2 to 'format' (number has no integer representation)" and
+    err("%d %d", 1) == "bad argument #3 to 'format' (number expected, got no value)" and
+    err("%f", "x") == "bad argument #2 to 'format' (number expected, got string)" and
+    err("%s") == "bad argument #2 to 'format' (value expected)" and
+    err("%q", {}) == "bad argument #2 to 'format' (value has no literal form)" and
+    err(nil) == "bad argument #1 to 'format' (string expected, got nil)" and
+    ("%d-%s"):format(1, "a") == "1-a"
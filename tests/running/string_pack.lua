@@ -0,0 +1,157 @@
+local pack, unpack, packsize = string.pack, string.unpack, string.packsize
+
+local function message(f, ...)
+    local ok, err = pcall(f, ...)
+    return not ok and err
+end
+
+function test_integers()
+    local a, b = unpack("<i2", "\xfe\xff")
+    return
+        pack(">i4", 0x01020304) == "\1\2\3\4" and
+        pack("<i4", 0x01020304) == "\4\3\2\1" and
+        pack("<i2", -2) == "\xfe\xff" and
+        pack(">I3", 0xabcdef) == "\xab\xcd\xef" and
+        pack("<b B h H", -1, 255, -1, 65535) == "\xff\xff\xff\xff\xff\xff" and
+        pack("<i16", -1) == string.rep("\xff", 16) and
+        pack(">I9", 1) == string.rep("\0", 8) .. "\1" and
+        pack("<j", math.mininteger) == string.rep("\0", 7) .. "\x80" and
+        (pack("=i4", 1) == pack("<i4", 1) or pack("=i4", 1) == pack(">i4", 1)) and
+        a == -2 and b == 3 and
+        unpack("<I2", "\xfe\xff") == 65534 and
+        unpack(">i3", "\xff\xff\xfe") == -2 and
+        unpack("<i16", string.rep("\xff", 16)) == -1 and
+        unpack("<I16", "\1" .. string.rep("\0", 15)) == 1 and
+        unpack("<j", pack("<j", math.maxinteger)) == math.maxinteger and
+        unpack("<J", pack("<J", -1)) == -1 and
+        unpack(">i8", pack(">i8", math.mininteger)) == math.mininteger and
+        unpack("<i1", "\x80") == -128 and
+        unpack("<I1", "\x80") == 128
+end
+
+function test_floats()
+    local a, b = unpack("<d", pack("<d", 1.5))
+    return
+        pack("<f", 0.5) == "\0\0\0\x3f" and
+        pack(">f", 0.5) == "\x3f\0\0\0" and
+        pack(">d", -2) == "\xc0" .. string.rep("\0", 7) and
+        a == 1.5 and b == 9 and
+        unpack(">f", pack(">f", 0.25)) == 0.25 and
+        unpack("<n", pack("<n", math.pi)) == math.pi and
+        unpack("<d", pack("<d", 1 / 0)) == 1 / 0 and
+        math.type(unpack("<f", pack("<f", 3))) == "float" and
+        pack("<d", "2") == pack("<d", 2)
+end
+
+function test_strings()
+    local a, b, c, d = unpack("<z s1 c3", "ab\0\3xyzpq\0")
+    return
+        pack("<s1", "abc") == "\3abc" and
+        pack(">s2", "abc") == "\0\3abc" and
+        pack("<s", "") == string.rep("\0", 8) and
+        pack("z", "ab") == "ab\0" and
+        pack("c5", "ab") == "ab\0\0\0" and
+        pack("c0", "") == "" and
+        pack("c2", 12) == "12" and
+        a == "ab" and b == "xyz" and c == "pq\0" and d == 11 and
+        unpack("<s4", pack("<s4", "hello")) == "hello" and
+        unpack("z", "\0") == ""
+end
+
+function test_alignment()
+    return
+        packsize("i1 i8") == 9 and
+        packsize("!8 i1 i8") == 16 and
+        packsize("!4 i1 i8") == 12 and
+        packsize("! i1 d") == 16 and
+        packsize("!2 i1 i8") == 10 and
+        packsize("i3 !4 i4") == 8 and
+        packsize("!8 c1 i2") == 4 and
+        packsize("!8 i1 c3") == 4 and
+        packsize("!4 i1 Xi4") == 4 and
+        packsize("!4 i1 Xi2") == 2 and
+        packsize("!4 i4 Xi8") == 4 and
+        packsize("!3 i1") == 1 and
+        packsize("!8 i1 x i2") == 4 and
+        pack("<!4 i1 i4", 1, 2) == "\1\0\0\0\2\0\0\0" and
+        pack("<!4 i1 Xi4 i1", 1, 2) == "\1\0\0\0\2" and
+        unpack("<!4 i1 i4", "\1\0\0\0\2\0\0\0", 1) == 1 and
+        select(2, unpack("<!4 i1 i4", "\1\0\0\0\2\0\0\0")) == 2 and
+        select(3, unpack("<!4 i1 i4", "\1\0\0\0\2\0\0\0")) == 9
+end
+
+function test_sizes()
+    return
+        packsize("") == 0 and
+        packsize("b h i l j T f d n") == 1 + 2 + 4 + 8 + 8 + 8 + 4 + 8 + 8 and
+        packsize("i3 I16 c10 x") == 3 + 16 + 10 + 1 and
+        packsize("< > = ") == 0
+end
+
+function test_unpack_position()
+    local a, b = unpack("<i1", "\1\2", 2)
+    local c, d = unpack("<i1", "\1\2", -1)
+    local e = select("#", unpack("", "abc", 4))
+    return
+        a == 2 and b == 3 and
+        c == 2 and d == 3 and
+        e == 1 and unpack("", "abc", 4) == 4 and
+        unpack("i1 i1", "\1\2\3", 2) == 2 and
+        message(unpack, "<i1", "\1\2", 4) ==
+            "bad argument #3 to 'unpack' (initial position out of string)" and
+        message(unpack, "<i1", "\1\2", 0) ==
+            "bad argument #3 to 'unpack' (initial position out of string)" and
+        message(unpack, "<i1", "\1\2", -3) ==
+            "bad argument #3 to 'unpack' (initial position out of string)" and
+        message(unpack, "<i1", "\1\2", 3) ==
+            "bad argument #2 to 'unpack' (data string too short)"
+end
+
+function test_errors()
+    return
+        message(pack, "i1", 128) == "bad argument #2 to 'pack' (integer overflow)" and
+        message(pack, "i1", -129) == "bad argument #2 to 'pack' (integer overflow)" and
+        message(pack, "I1", 256) == "bad argument #2 to 'pack' (unsigned overflow)" and
+        message(pack, "I1", -1) == "bad argument #2 to 'pack' (unsigned overflow)" and
+        message(pack, "b b", 1, 1000) == "bad argument #3 to 'pack' (integer overflow)" and
+        message(pack, "i4", 1.5) ==
+            "bad argument #2 to 'pack' (number has no integer representation)" and
+        message(pack, "i4") == "bad argument #2 to 'pack' (number expected, got no value)" and
+        message(pack, "d", "x") == "bad argument #2 to 'pack' (number expected, got string)" and
+        message(pack, "c1", "ab") == "bad argument #2 to 'pack' (string longer than given size)" and
+        message(pack, "s1", string.rep("x", 256)) ==
+            "bad argument #2 to 'pack' (string length does not fit in given size)" and
+        message(pack, "z", "a\0b") == "bad argument #2 to 'pack' (string contains zeros)" and
+        message(pack, "c") == "missing size for format option 'c'" and
+        message(pack, "y") == "invalid format option 'y'" and
+        message(pack, "i17") == "integral size (17) out of limits [1,16]" and
+        message(pack, "i0") == "integral size (0) out of limits [1,16]" and
+        message(pack, "!17") == "integral size (17) out of limits [1,16]" and
+        message(packsize, "!3 i4") ==
+            "bad argument #1 to 'packsize' (format asks for alignment not power of 2)" and
+        message(packsize, "X") ==
+            "bad argument #1 to 'packsize' (invalid next option for option 'X')" and
+        message(packsize, "Xc1") ==
+            "bad argument #1 to 'packsize' (invalid next option for option 'X')" and
+        message(packsize, "X!") ==
+            "bad argument #1 to 'packsize' (invalid next option for option 'X')" and
+        message(packsize, "s") == "bad argument #1 to 'packsize' (variable-length format)" and
+        message(packsize, "z") == "bad argument #1 to 'packsize' (variable-length format)" and
+        message(unpack, "<i9", string.rep("\0", 8) .. "\1") ==
+            "9-byte integer does not fit into Lua Integer" and
+        message(unpack, "<i9", string.rep("\0", 7) .. "\x80\0") ==
+            "9-byte integer does not fit into Lua Integer" and
+        message(unpack, "z", "ab") ==
+            "bad argument #2 to 'unpack' (unfinished string for format 'z')" and
+        message(unpack, "<s1", "\5ab") == "bad argument #2 to 'unpack' (data string too short)" and
+        message(unpack, "i4", "abc") == "bad argument #2 to 'unpack' (data string too short)"
+end
+
+return
+    test_integers() and
+    test_floats() and
+    test_strings() and
+    test_alignment() and
+    test_sizes() and
+    test_unpack_position() and
+    test_errors()
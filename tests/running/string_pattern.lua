@@ -0,0 +1,319 @@
+local function message(f, ...)
+    local ok, err = pcall(f, ...)
+    return not ok and err
+end
+
+local function same(...)
+    local n = select("#", ...) // 2
+    local t = {...}
+    for i = 1, n do
+        if t[i] ~= t[n + i] then
+            return false
+        end
+    end
+    return select("#", ...) % 2 == 0
+end
+
+function test_find_plain()
+    return
+        same(2, 3, string.find("hello", "el")) and
+        same(nil, string.find("hello", "xyz")) and
+        same(1, 0, string.find("hello", "")) and
+        same(6, 5, string.find("hello", "", 6)) and
+        same(nil, string.find("hello", "", 7)) and
+        same(4, 4, string.find("hello", "l", 4)) and
+        same(4, 4, string.find("hello", "l", -2)) and
+        same(3, 3, string.find("hello", "l", -100)) and
+        same(4, 5, string.find("xab.b", ".b", 1, true)) and
+        same(2, 3, string.find("xab.b", ".b")) and
+        same(3, 4, string.find("a+(b", "(b", 1, true)) and
+        same(2, 3, string.find("a\0b", "\0b")) and
+        same(2, 3, string.find(12345, 23))
+end
+
+function test_find_pattern()
+    return
+        same(1, 3, string.find("abc", "%a+")) and
+        same(4, 6, string.find("abc123", "%d+")) and
+        same(2, 4, "abc", string.find("xabc", "(%a+)", 2)) and
+        same(1, 5, "he", "llo", string.find("hello world", "(h.)(l+o)")) and
+        same(nil, string.find("hello", "^ello")) and
+        same(1, 5, string.find("hello", "^hello$")) and
+        same(nil, string.find("hello!", "^hello$")) and
+        same(2, 3, string.find("hello", "^el", 2)) and
+        same(1, 2, string.find("a$b", "a$?")) and
+        same(2, 2, string.find("a$b", "%$")) and
+        same(3, 4, string.find("a^b^", "b^"))
+end
+
+function test_match_classes()
+    return
+        string.match("  key = value  ", "(%w+)%s*=%s*(%w+)") == "key" and
+        select(2, string.match("  key = value  ", "(%w+)%s*=%s*(%w+)")) == "value" and
+        string.match("hello", ".") == "h" and
+        string.match("abc", "%l+") == "abc" and
+        string.match("ABCdef", "%u+") == "ABC" and
+        string.match("0x1F!", "0x(%x+)") == "1F" and
+        string.match("a,b;c", "%p") == "," and
+        string.match("a\tb", "%c") == "\t" and
+        string.match("a\vb", "%s") == "\v" and
+        string.match("  x", "%g") == "x" and
+        string.match("abc123", "%D+") == "abc" and
+        string.match("abc123", "%A+") == "123" and
+        string.match("hello world", "%S+$") == "world" and
+        string.match("a.b", "%.") == "." and
+        string.match("50%", "%d+%%") == "50%" and
+        string.match("\xff\xfe", "%W+") == "\xff\xfe"
+end
+
+function test_match_sets()
+    return
+        string.match("hello123", "[a-z]+") == "hello" and
+        string.match("hello123", "[^a-z]+") == "123" and
+        string.match("x-y", "[a%-]") == "-" and
+        string.match("x-y", "[-]") == "-" and
+        string.match("x-y", "[y-]+") == "-y" and
+        string.match("a]b", "[]]") == "]" and
+        string.match("a]b", "[^]a]") == "b" and
+        string.match("a^b", "[b^]+") == "^b" and
+        string.match("tab\there", "[%s]") == "\t" and
+        string.match("Hello World", "[%u%d]+") == "H" and
+        string.match("abc", "[%a-z]+") == "abc" and
+        string.match("a-z", "[a-]+") == "a-" and
+        string.match("-]", "[%]]") == "]"
+end
+
+function test_match_repetition()
+    return
+        string.match("aaa", "a*") == "aaa" and
+        string.match("bbb", "a*") == "" and
+        string.match("aaab", "a+b") == "aaab" and
+        string.match("b", "a+b") == nil and
+        string.match("ab", "a?b") == "ab" and
+        string.match("b", "a?b") == "b" and
+        string.match("<a><b>", "<.->") == "<a>" and
+        string.match("<a><b>", "<.*>") == "<a><b>" and
+        string.match("aaa", "a-") == "" and
+        string.match("aaab", "a-b") == "aaab" and
+        string.match("int x; /* a */ y; /* b */", "/%*.-%*/") == "/* a */" and
+        string.match("abc", "a.-$") == "abc" and
+        string.match("xx", "x?x?x") == "xx"
+end
+
+function test_match_captures()
+    local a, b, c = string.match("hello world", "()ll()(o)")
+    local d, e = string.match("key=val", "((%w+)=%w+)")
+    return
+        a == 3 and b == 5 and c == "o" and
+        d == "key=val" and e == "key" and
+        string.match("hello", "()") == 1 and
+        string.match("hello", "()", 3) == 3 and
+        string.match("hello", "$()") == nil and
+        string.match("hello", "()$") == 6 and
+        string.match("", "()") == 1 and
+        string.match("abcabc", "(abc)%1") == "abc" and
+        string.match("abcabd", "(abc)%1") == nil and
+        string.match("say \"hi\" 'x'", "([\"'])(.-)%1", 5) == "\"" and
+        select(2, string.match("say \"hi\" 'x'", "([\"'])(.-)%1")) == "hi" and
+        string.match("aa", "((a)%2)") == "aa"
+end
+
+function test_match_balance_frontier()
+    return
+        string.match("f(a(b)c) d", "%b()") == "(a(b)c)" and
+        string.match("f(a(b c) d", "%b()") == "(b c)" and
+        string.match("x[[y]]", "%b[]") == "[[y]]" and
+        string.match("no parens", "%b()") == nil and
+        string.match("aXa", "%baa") == "aXa" and
+        string.match("THE (quick) fox", "%f[%a]%a+") == "THE" and
+        string.match("THE (quick) fox", "%f[%l]%a+") == "quick" and
+        string.match("hello", "%f[%z]") == "" and
+        string.match("hello", "o%f[%z]") == "o" and
+        string.match("hello", "%f[%a]()") == 1 and
+        string.match("the cat", "%f[%w]%w+$") == "cat" and
+        string.match("ab", "%f[^a]") == ""
+end
+
+function test_init()
+    return
+        string.match("hello", "l+", 4) == "l" and
+        string.match("hello", "l+", -2) == "l" and
+        string.match("hello", ".", 0) == "h" and
+        string.match("hello", ".", -10) == "h" and
+        string.match("hello", ".", 6) == nil and
+        string.match("hello", "", 6) == "" and
+        string.match("hello", "", 7) == nil and
+        string.match("hello", "^l", 3) == "l" and
+        string.match("hello", "^l", 2) == nil
+end
+
+function test_nul_and_bytes()
+    return
+        string.match("a\0b", "%z") == "\0" and
+        string.match("a\0b", ".%z.") == "a\0b" and
+        string.match("a\0b\0", "[%z]+") == "\0" and
+        string.match("\xe0\xff", "[\xe0-\xff]+") == "\xe0\xff" and
+        string.find("a\0b", "b", 1) == 3
+end
+
+function test_errors()
+    return
+        message(string.find, "a", "%") == "malformed pattern (ends with '%')" and
+        message(string.match, "a", "[a") == "malformed pattern (missing ']')" and
+        message(string.match, "a", "[]") == "malformed pattern (missing ']')" and
+        message(string.match, "a", "[a%") == "malformed pattern (missing ']')" and
+        message(string.match, "a", "%b") == "malformed pattern (missing arguments to '%b')" and
+        message(string.match, "a", "%bx") == "malformed pattern (missing arguments to '%b')" and
+        message(string.match, "a", "%f") == "missing '[' after '%f' in pattern" and
+        message(string.match, "a", "%fa") == "missing '[' after '%f' in pattern" and
+        message(string.match, "a", "%1") == "invalid capture index %1" and
+        message(string.match, "a", "(a)%2") == "invalid capture index %2" and
+        message(string.match, "a", "(a%1)") == "invalid capture index %1" and
+        message(string.match, "a", "a)") == "invalid pattern capture" and
+        message(string.match, "a", "(a") == "unfinished capture" and
+        message(string.find, "a", "(a") == "unfinished capture" and
+        message(string.match, "a", string.rep("()", 33)) == "too many captures" and
+        message(string.match, string.rep("a", 300), string.rep("a?", 300)) ==
+            "pattern too complex" and
+        message(string.find) == "bad argument #1 to 'find' (string expected, got no value)" and
+        message(string.match, "a") == "bad argument #2 to 'match' (string expected, got no value)" and
+        message(string.find, "a", "a", 1.5) ==
+            "bad argument #3 to 'find' (number has no integer representation)"
+end
+
+local function collect(s, p)
+    local results = ""
+    for a, b in string.gmatch(s, p) do
+        results = results .. "[" .. tostring(a) .. (b and "," .. tostring(b) or "") .. "]"
+    end
+    return results
+end
+
+function test_gmatch()
+    local count = 0
+    for _ in string.gmatch("", "x*") do
+        count = count + 1
+    end
+    local iterator = string.gmatch("ab", ".")
+    return
+        collect("hello world from lua", "%a+") == "[hello][world][from][lua]" and
+        collect("k1=v1, k2=v2", "(%w+)=(%w+)") == "[k1,v1][k2,v2]" and
+        collect("abc", "") == "[][][][]" and
+        collect("abc", "x*") == "[][][][]" and
+        collect("aab", "a*") == "[aa][]" and
+        collect("a,b,,c", "([^,]*)") == "[a][b][][c]" and
+        collect("abc", "()") == "[1][2][3][4]" and
+        collect("a1b2", "%a()") == "[2][4]" and
+        collect("^a^b", "^.") == "[^a][^b]" and
+        collect("f(x) g(y(z))", "%b()") == "[(x)][(y(z))]" and
+        collect("THE (quick) fox", "%f[%a]%a+") == "[THE][quick][fox]" and
+        collect("", "a") == "" and
+        collect(123, "%d") == "[1][2][3]" and
+        count == 1 and
+        iterator() == "a" and iterator() == "b" and iterator() == nil and iterator() == nil and
+        message(string.gmatch("a", "(")) == "unfinished capture" and
+        message(string.gmatch, "a") ==
+            "bad argument #2 to 'gmatch' (string expected, got no value)"
+end
+
+local function gsub(...)
+    local result, count = string.gsub(...)
+    return result .. "/" .. count
+end
+
+function test_gsub_string()
+    return
+        gsub("hello world", "o", "0") == "hell0 w0rld/2" and
+        gsub("hello world", "o", "0", 1) == "hell0 world/1" and
+        gsub("hello world", "o", "0", 0) == "hello world/0" and
+        gsub("hello world", "o", "0", -1) == "hello world/0" and
+        gsub("hello world", "(%w+)", "<%1>") == "<hello> <world>/2" and
+        gsub("hello world", "%w+", "%0 %0", 1) == "hello hello world/1" and
+        gsub("hello world", "(%w+) (%w+)", "%2 %1") == "world hello/1" and
+        gsub("abc", "%w", "%1%1") == "aabbcc/3" and
+        gsub("abc", "", "-") == "-a-b-c-/4" and
+        gsub("abc", "b*", "-") == "-a-c-/3" and
+        gsub("abc", "^a", "x") == "xbc/1" and
+        gsub("aaa", "^a", "x") == "xaa/1" and
+        gsub("bab", "^a", "x") == "bab/0" and
+        gsub("abc", "^", "x") == "xabc/1" and
+        gsub("abc", "$", "x") == "abcx/1" and
+        gsub("a.b", "%.", "%%") == "a%b/1" and
+        gsub("abc", "()b", "%1") == "a2c/1" and
+        gsub("abc", "b", 5) == "a5c/1" and
+        gsub(12321, 2, "x") == "1x3x1/2" and
+        gsub("a\0b", "\0", "%%0") == "a%0b/1" and
+        gsub("", "x*", "y") == "y/1" and
+        gsub("a(b", "(", "x") == "xax(xbx/4"
+end
+
+function test_gsub_table_function()
+    local t = {name = "lua", version = 5.3, off = false}
+    local fallback = setmetatable({}, {__index = function(_, k) return k:upper() end})
+    local calls = 0
+    local function count(...)
+        calls = calls + select("#", ...)
+    end
+    return
+        gsub("$name $version $missing $off", "%$(%w+)", t) ==
+            "lua 5.3 $missing $off/4" and
+        gsub("a b", "%w", fallback) == "A B/2" and
+        gsub("k=v", "(%w)=(%w)", {k = "key"}) == "key/1" and
+        gsub("hello", "l+", {}) == "hello/1" and
+        gsub("abc", "()", {[1] = "x", [3] = "y"}) == "xabyc/4" and
+        gsub("hello world", "%w+", string.upper) == "HELLO WORLD/2" and
+        gsub("hello world", "(%w+) (%w+)", function(a, b) return b .. " " .. a end) ==
+            "world hello/1" and
+        gsub("abc", "%w", function(c) if c == "b" then return false end return 1 end) ==
+            "1b1/3" and
+        gsub("abc", "%w", function() end) == "abc/3" and
+        gsub("a b c", "%w", count) == "a b c/3" and calls == 3 and
+        gsub("x = 1 + 2", "(%d) %+ (%d)", function(a, b) return a + b end) == "x = 3/1" and
+        gsub("abc", "b", function() return string.gsub("xyz", "y", "Y") end) == "axYzc/1" and
+        gsub(string.rep("a", 10000), "a", function() return "" end) == "/10000"
+end
+
+function test_gsub_errors()
+    return
+        message(string.gsub, "abc", "b", "%2") == "invalid capture index %2" and
+        message(string.gsub, "abc", "(b)", "%2") == "invalid capture index %2" and
+        message(string.gsub, "abc", "b", "%x") == "invalid use of '%' in replacement string" and
+        message(string.gsub, "abc", "b", "%") == "invalid use of '%' in replacement string" and
+        message(string.gsub, "abc", "b", {b = {}}) == "invalid replacement value (a table)" and
+        message(string.gsub, "abc", "b", function() return true end) ==
+            "invalid replacement value (a boolean)" and
+        message(string.gsub, "abc", "b") ==
+            "bad argument #3 to 'gsub' (string/function/table expected)" and
+        message(string.gsub, "abc", "b", true) ==
+            "bad argument #3 to 'gsub' (string/function/table expected)" and
+        message(string.gsub, "abc", "b", "x", 1.5) ==
+            "bad argument #4 to 'gsub' (number has no integer representation)" and
+        message(string.gsub, "abc", "(b", function() end) == "unfinished capture" and
+        message(string.gsub, "abc", "%", "x") == "malformed pattern (ends with '%')" and
+        message(string.gsub, "abc", "b", function() error("inner") end):find("inner$") ~= nil
+end
+
+function test_methods()
+    local s = "key = value"
+    return
+        s:find("=") == 5 and
+        s:match("%w+$") == "value" and
+        ("x"):rep(3):match("x+") == "xxx"
+end
+
+return
+    test_find_plain() and
+    test_find_pattern() and
+    test_match_classes() and
+    test_match_sets() and
+    test_match_repetition() and
+    test_match_captures() and
+    test_match_balance_frontier() and
+    test_init() and
+    test_nul_and_bytes() and
+    test_errors() and
+    test_gmatch() and
+    test_gsub_string() and
+    test_gsub_table_function() and
+    test_gsub_errors() and
+    test_methods()
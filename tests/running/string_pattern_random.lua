@@ -0,0 +1,82 @@
+-- Matches many random patterns against random subjects, checking that matching never fails other
+-- than with a pattern error and that `find` and `match` agree with each other.
+
+local tokens = {
+    "a", "b", "(", ")", ".", "%a", "%d", "%s", "%A", "[ab]", "[^a]", "[a-c]", "*", "+", "-", "?",
+    "()", "%b()", "%f[a]", "%f[^b]", "%1", "^", "$", "%", "[", "%%", "1",
+}
+local letters = {"a", "b", "c", "(", ")", " ", "1", "%"}
+
+local function random_string(parts, max_len)
+    local s = ""
+    for _ = 1, math.random(0, max_len) do
+        s = s .. parts[math.random(#parts)]
+    end
+    return s
+end
+
+local function check_pattern(s, p)
+    -- `find` searches for patterns without special characters literally, while `match` still
+    -- interprets them and may raise an error
+    if not p:find("[%^%$%*%+%?%.%(%[%%%-]") then
+        return true
+    end
+
+    local found = table.pack(pcall(string.find, s, p))
+    local matched = table.pack(pcall(string.match, s, p))
+    if found[1] ~= matched[1] then
+        return false
+    end
+    if not found[1] then
+        return type(found[2]) == "string" and found[2] == matched[2]
+    end
+
+    local start, finish = found[2], found[3]
+    if start == nil then
+        return found.n == 2 and matched.n == 2 and matched[2] == nil
+    end
+    if start < 1 or finish < start - 1 or finish > #s then
+        return false
+    end
+
+    -- `match` returns the same captures as `find`, or the whole match without captures
+    if found.n == 3 then
+        if matched.n ~= 2 or matched[2] ~= s:sub(start, finish) then
+            return false
+        end
+    else
+        if matched.n + 2 ~= found.n then
+            return false
+        end
+        for i = 4, found.n do
+            if found[i] ~= matched[i - 2] then
+                return false
+            end
+        end
+    end
+
+    -- An anchored search from the start of the match finds the same match
+    if p:sub(1, 1) ~= "^" then
+        local again_start, again_finish = string.find(s, "^" .. p, start)
+        if again_start ~= start or again_finish ~= finish then
+            return false
+        end
+    end
+    return true
+end
+
+local function check_plain(s, p)
+    local a, b = string.find(s, p)
+    local c, d = string.find(s, p, 1, true)
+    return a == c and b == d
+end
+
+math.randomseed(1234)
+for _ = 1, 3000 do
+    local s = random_string(letters, 12)
+    if not check_pattern(s, random_string(tokens, 6)) or
+        not check_plain(s, random_string({"a", "b", "c"}, 3)) then
+        return false
+    end
+end
+return true
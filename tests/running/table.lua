@@ -94,9 +94,18 @@ function test5()
     return t[1] == 1 and t[2] == 2 and t[3] == 3 and t.a == "a"
 end
 
+function test6()
+    local t = {1}
+    t = {t, t[1] + 1, n = t}
+    local s = "a"
+    s = s .. s .. s
+    return t[1][1] == 1 and t[2] == 2 and t.n == t[1] and s == "aaa"
+end
+
 return
     test1() and
     test2() and
     test3() and
     test4() and
-    test5()
+    test5() and
+    test6()
@@ -0,0 +1,38 @@
+local function test1()
+    local shared = { 1, 2 }
+    local mt = { __index = function() return "default" end }
+    local t = setmetatable({ a = shared, b = shared, s = "x", [shared] = true }, mt)
+    t.self = t
+    local c = table.clone(t)
+    return
+        c ~= t and c.a ~= shared and c.a == c.b and c.self == c and
+        c.a[2] == 2 and c.s == "x" and c[c.a] == true and rawget(c, shared) == nil and
+        getmetatable(c) == mt and c.missing == "default"
+end
+
+local function test2()
+    local a = { 1, { x = 2, y = { 3 } }, z = "z" }
+    local b = { 1, { x = 2, y = { 3 } }, z = "z" }
+    local c = { 1, { x = 2, y = { 4 } }, z = "z" }
+    local d = { 1, { x = 2, y = { 3 } }, z = "z", extra = false }
+    return
+        table.equals(a, b) and not table.equals(a, c) and
+        not table.equals(a, d) and not table.equals(d, a) and
+        table.equals(1, 1.0) and not table.equals("1", 1) and
+        table.equals(a, table.clone(a))
+end
+
+local function test3()
+    local a = { name = "a" }
+    a.next = { name = "b", next = a }
+    local b = { name = "a" }
+    b.next = { name = "b", next = b }
+    local c = { name = "a" }
+    c.next = { name = "c", next = c }
+    return table.equals(a, b) and not table.equals(a, c)
+end
+
+return
+    test1() and
+    test2() and
+    test3()
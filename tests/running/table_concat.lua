@@ -0,0 +1,48 @@
+local function message(f, ...)
+    local ok, err = pcall(f, ...)
+    return not ok and err
+end
+
+function test_concat()
+    local big = {}
+    for i = 1, 10000 do
+        big[i] = "x"
+    end
+    return
+        table.concat({}) == "" and
+        table.concat({"a", "b", "c"}) == "abc" and
+        table.concat({"a", "b", "c"}, ", ") == "a, b, c" and
+        table.concat({"a", "b", "c"}, ", ", 2) == "b, c" and
+        table.concat({"a", "b", "c"}, ", ", 2, 2) == "b" and
+        table.concat({"a", "b", "c"}, ", ", 3, 2) == "" and
+        table.concat({"a", "b", "c"}, nil, 1, 2) == "ab" and
+        table.concat({1, 2, 3}, "-") == "1-2-3" and
+        table.concat({1, 2, 3}, 0) == "10203" and
+        table.concat({"a", nil, "c"}, "", 3, 3) == "c" and
+        table.concat({[-1] = "x", [0] = "y", [1] = "z"}, "", -1, 1) == "xyz" and
+        table.concat({"a\0b", "c"}, "\0") == "a\0b\0c" and
+        #table.concat(big) == 10000 and
+        #table.concat(big, "ab") == 10000 + 9999 * 2 and
+        table.concat({[math.maxinteger] = "m"}, ",", math.maxinteger, math.maxinteger) == "m" and
+        table.concat({}, ",", math.maxinteger, math.maxinteger - 1) == ""
+end
+
+function test_errors()
+    return
+        message(table.concat, {"a", {}, "c"}) ==
+            "invalid value (at index 2) in table for 'concat'" and
+        message(table.concat, {"a", true}) ==
+            "invalid value (at index 2) in table for 'concat'" and
+        message(table.concat, {"a"}, "", 1, 2) ==
+            "invalid value (at index 2) in table for 'concat'" and
+        message(table.concat, "abc") ==
+            "bad argument #1 to 'concat' (table expected, got string)" and
+        message(table.concat, {}, {}) ==
+            "bad argument #2 to 'concat' (string expected, got table)" and
+        message(table.concat, {}, "", 1.5) ==
+            "bad argument #3 to 'concat' (number has no integer representation)"
+end
+
+return
+    test_concat() and
+    test_errors()
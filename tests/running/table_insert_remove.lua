@@ -0,0 +1,106 @@
+local function message(f, ...)
+    local ok, err = pcall(f, ...)
+    return not ok and err
+end
+
+local function join(t)
+    local len = #t
+    local s = ""
+    for i = 1, len do
+        s = s .. tostring(t[i]) .. (i < len and "," or "")
+    end
+    return s
+end
+
+-- A sequence whose elements are all in the map part of the table
+local function hashed(n)
+    local t = {}
+    for i = n, 1, -1 do
+        t[i] = i
+    end
+    return t
+end
+
+function test_insert()
+    local t = {}
+    table.insert(t, "a")
+    table.insert(t, "b")
+    table.insert(t, 1, "c")
+    table.insert(t, 2, "d")
+    table.insert(t, 5, "e")
+    local h = hashed(5)
+    table.insert(h, 1, 0)
+    table.insert(h, 4, "x")
+    local full = {1, 2, 3, 4}
+    table.insert(full, 1, 0)
+    local big = {}
+    for i = 1, 1000 do
+        table.insert(big, 1, i)
+    end
+    return
+        join(t) == "c,d,a,b,e" and
+        join(h) == "0,1,2,x,3,4,5" and
+        join(full) == "0,1,2,3,4" and
+        #big == 1000 and big[1] == 1000 and big[1000] == 1 and big[500] == 501 and
+        select("#", table.insert({}, 1)) == 0
+end
+
+function test_remove()
+    local t = {"a", "b", "c", "d"}
+    local r1 = table.remove(t)
+    local r2 = table.remove(t, 1)
+    local h = hashed(5)
+    local r3 = table.remove(h, 2)
+    local empty = {}
+    local r4 = table.remove(empty)
+    local r5 = table.remove(empty, 0)
+    local r6 = table.remove({}, 1)
+    local e = {n = 1}
+    local r7 = table.remove(e, #e + 1)
+    local one = {"x"}
+    local r8 = table.remove(one, 2)
+    local zero = {[0] = "z"}
+    local r9 = table.remove(zero, 0)
+    local big = {}
+    for i = 1, 1000 do
+        big[i] = i
+    end
+    for _ = 1, 999 do
+        table.remove(big, 1)
+    end
+    return
+        r1 == "d" and r2 == "a" and join(t) == "b,c" and t[3] == nil and t[4] == nil and
+        r3 == 2 and join(h) == "1,3,4,5" and h[5] == nil and
+        r4 == nil and r5 == nil and r6 == nil and r7 == nil and
+        r8 == nil and join(one) == "x" and
+        r9 == "z" and zero[0] == nil and
+        #big == 1 and big[1] == 1000
+end
+
+function test_errors()
+    return
+        message(table.insert, {1, 2}, 0, "x") ==
+            "bad argument #2 to 'insert' (position out of bounds)" and
+        message(table.insert, {1, 2}, 4, "x") ==
+            "bad argument #2 to 'insert' (position out of bounds)" and
+        message(table.insert, {}, 1, "x", "y") == "wrong number of arguments to 'insert'" and
+        message(table.insert, {}) == "wrong number of arguments to 'insert'" and
+        message(table.insert, {}, 1.5, "x") ==
+            "bad argument #2 to 'insert' (number has no integer representation)" and
+        message(table.insert, nil, 1) ==
+            "bad argument #1 to 'insert' (table expected, got nil)" and
+        message(table.remove, {1, 2}, 10) ==
+            "bad argument #2 to 'remove' (position out of bounds)" and
+        message(table.remove, {1, 2}, 4) ==
+            "bad argument #2 to 'remove' (position out of bounds)" and
+        message(table.remove, {1, 2}, -1) ==
+            "bad argument #2 to 'remove' (position out of bounds)" and
+        message(table.remove, {}, -1) ==
+            "bad argument #2 to 'remove' (position out of bounds)" and
+        message(table.remove, "x") == "bad argument #1 to 'remove' (table expected, got string)"
+end
+
+return
+    test_insert() and
+    test_remove() and
+    test_errors()
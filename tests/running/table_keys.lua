@@ -0,0 +1,41 @@
+local function message(f, ...)
+    local ok, err = pcall(f, ...)
+    return not ok and err:gsub("^.-:%d+: ", "")
+end
+
+local function test1()
+    local t = {}
+    t[1.0] = "a"
+    t[2] = "b"
+    t[-0.0] = "z"
+    t[2^53] = "big"
+    t[0.5] = "half"
+    local keys = 0
+    for k in pairs(t) do
+        if k ~= 0.5 then
+            keys = keys + (math.type(k) == "integer" and 1 or 0)
+        end
+    end
+    return
+        t[1] == "a" and t[2.0] == "b" and t[0] == "z" and #t == 2 and
+        rawget(t, 1.0) == "a" and t[2^53 | 0] == "big" and t[0.5] == "half" and
+        keys == 4 and
+        math.type(next({ [3.0] = true })) == "integer"
+end
+
+local function test2()
+    local t = {}
+    return
+        t[nil] == nil and t[0/0] == nil and rawget(t, 0/0) == nil and
+        message(function() t[nil] = 1 end) == "table index is nil" and
+        message(function() t[0/0] = 1 end) == "table index is NaN" and
+        message(rawset, t, nil, 1) == "table index is nil" and
+        message(rawset, t, 0/0, 1) == "table index is NaN" and
+        message(function() return { [0/0] = 1 } end) == "table index is NaN" and
+        message(next, t, "missing") == "invalid key to 'next'" and
+        next(t) == nil
+end
+
+return
+    test1() and
+    test2()
@@ -0,0 +1,82 @@
+local function message(f, ...)
+    local ok, err = pcall(f, ...)
+    return not ok and err
+end
+
+local function join(t, first, last)
+    local s = ""
+    for i = first, last do
+        s = s .. tostring(t[i]) .. (i < last and "," or "")
+    end
+    return s
+end
+
+-- A sequence whose elements are all in the map part of the table
+local function hashed(n)
+    local t = {}
+    for i = n, 1, -1 do
+        t[i] = i
+    end
+    return t
+end
+
+function test_move()
+    local a = {1, 2, 3, 4, 5}
+    local b = table.move(a, 1, 3, 3)
+    local c = {1, 2, 3, 4, 5}
+    table.move(c, 3, 5, 1)
+    local d = {1, 2, 3}
+    local e = table.move(d, 1, 3, 2, {})
+    local f = {1, 2, 3}
+    table.move(f, 1, 3, 1)
+    local g = {1, 2, 3}
+    table.move(g, 2, 1, 5)
+    local h = hashed(6)
+    table.move(h, 1, 4, 3)
+    local i = hashed(6)
+    table.move(i, 3, 6, 1)
+    local j = {1, nil, 3}
+    table.move(j, 1, 3, 4)
+    local k = {}
+    table.move({"a", "b"}, 1, 2, 10, k)
+    local l = {}
+    for n = 1, 100 do
+        l[n] = n
+    end
+    table.move(l, 1, 100, 51)
+    local m = {[-1] = "x", [0] = "y"}
+    table.move(m, -1, 0, 1)
+    return
+        b == a and join(a, 1, 5) == "1,2,1,2,3" and
+        join(c, 1, 5) == "3,4,5,4,5" and
+        e ~= d and join(e, 1, 4) == "nil,1,2,3" and join(d, 1, 3) == "1,2,3" and
+        join(f, 1, 3) == "1,2,3" and
+        join(g, 1, 5) == "1,2,3,nil,nil" and
+        join(h, 1, 6) == "1,2,1,2,3,4" and
+        join(i, 1, 6) == "3,4,5,6,5,6" and
+        join(j, 1, 6) == "1,nil,3,1,nil,3" and
+        join(k, 9, 11) == "nil,a,b" and
+        l[51] == 1 and l[150] == 100 and l[100] == 50 and l[50] == 50 and
+        m[1] == "x" and m[2] == "y"
+end
+
+function test_errors()
+    return
+        message(table.move, {}, 0, math.maxinteger, 1) ==
+            "bad argument #3 to 'move' (too many elements to move)" and
+        message(table.move, {}, 1, 3, math.maxinteger - 1) ==
+            "bad argument #4 to 'move' (destination wrap around)" and
+        message(table.move, {}, 1, 2) ==
+            "bad argument #4 to 'move' (number expected, got no value)" and
+        message(table.move, nil, 1, 2, 3) ==
+            "bad argument #1 to 'move' (table expected, got nil)" and
+        message(table.move, {}, 1, 2, 3, "x") ==
+            "bad argument #5 to 'move' (table expected, got string)" and
+        table.move({}, math.maxinteger, math.maxinteger - 1, 1) ~= nil and
+        table.move({}, math.mininteger, math.mininteger + 1, math.maxinteger - 1)[math.maxinteger] ==
+            nil
+end
+
+return
+    test_move() and
+    test_errors()
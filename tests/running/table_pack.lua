@@ -0,0 +1,41 @@
+local function test1()
+    local t = table.pack(1, nil, "three", nil)
+    local e = table.pack()
+    return t.n == 4 and t[1] == 1 and t[2] == nil and t[3] == "three" and t[4] == nil and
+        e.n == 0 and next(e, "n") == nil
+end
+
+local function test2()
+    local t = {1, 2, 3, 4, 5}
+    local a, b, c = table.unpack(t)
+    local d, e = table.unpack(t, 4)
+    local f, g, h = table.unpack(t, 2, 3)
+    local i, j = table.unpack(t, -1, 0)
+
+    return
+        a == 1 and b == 2 and c == 3 and
+        d == 4 and e == 5 and
+        f == 2 and g == 3 and h == nil and
+        i == nil and j == nil and
+        select('#', table.unpack(t)) == 5 and
+        select('#', table.unpack(t, 4, 7)) == 4 and
+        select('#', table.unpack(t, 3, 2)) == 0 and
+        select('#', table.unpack(table.pack(1, nil, nil), 1, 3)) == 3
+end
+
+local function test3()
+    local ok1, e1 = pcall(table.unpack, {}, 1, 1e7)
+    local ok2, e2 = pcall(table.unpack, {}, math.mininteger, math.maxinteger)
+    local ok3 = pcall(table.unpack, {}, math.maxinteger, math.maxinteger)
+    local ok4, e4 = pcall(table.unpack, nil)
+    local ok5, e5 = pcall(table.unpack, {}, 1.5)
+
+    return
+        ok1 == false and e1 == "too many results to unpack" and
+        ok2 == false and e2 == "too many results to unpack" and
+        ok3 == true and
+        ok4 == false and e4 == "bad argument #1 to 'unpack' (table expected, got nil)" and
+        ok5 == false and e5 == "bad argument #2 to 'unpack' (number has no integer representation)"
+end
+
+return test1() and test2() and test3()
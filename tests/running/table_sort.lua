@@ -0,0 +1,120 @@
+local function message(f, ...)
+    local ok, err = pcall(f, ...)
+    return not ok and err
+end
+
+local function join(t)
+    local len = #t
+    local s = ""
+    for i = 1, len do
+        s = s .. tostring(t[i]) .. (i < len and "," or "")
+    end
+    return s
+end
+
+local function is_sorted(t, lt)
+    for i = 2, #t do
+        if lt(t[i], t[i - 1]) then
+            return false
+        end
+    end
+    return true
+end
+
+function test_default_order()
+    local numbers = {5, 2, 8, 1, 9, 3, 7, 4, 6, 0}
+    table.sort(numbers)
+    local strings = {"pear", "apple", "fig", "banana", "Cherry"}
+    table.sort(strings)
+    local mixed = {3, 1.5, -2, 2.25, 1}
+    table.sort(mixed)
+    local empty = {}
+    table.sort(empty)
+    local one = {"x"}
+    table.sort(one)
+    local random = {}
+    math.randomseed(42)
+    for i = 1, 500 do
+        random[i] = math.random(1, 100)
+    end
+    table.sort(random)
+    return
+        join(numbers) == "0,1,2,3,4,5,6,7,8,9" and
+        join(strings) == "Cherry,apple,banana,fig,pear" and
+        join(mixed) == "-2,1,1.5,2.25,3" and
+        #empty == 0 and
+        join(one) == "x" and
+        #random == 500 and is_sorted(random, function(a, b) return a < b end)
+end
+
+function test_comparator()
+    local numbers = {5, 2, 8, 1, 9, 3}
+    table.sort(numbers, function(a, b) return a > b end)
+    local calls = 0
+    local counted = {3, 1, 2}
+    table.sort(counted, function(a, b)
+        calls = calls + 1
+        return a < b
+    end)
+    local records = {
+        {name = "b", age = 30},
+        {name = "a", age = 25},
+        {name = "c", age = 30},
+        {name = "d", age = 25},
+    }
+    table.sort(records, function(x, y) return x.age < y.age end)
+    local names = ""
+    for i = 1, #records do
+        names = names .. records[i].name
+    end
+    local big = {}
+    for i = 1, 2000 do
+        big[i] = (i * 7919) % 2000
+    end
+    table.sort(big, function(a, b) return a > b end)
+    return
+        join(numbers) == "9,8,5,3,2,1" and
+        join(counted) == "1,2,3" and calls > 0 and
+        names == "adbc" and
+        big[1] == 1999 and big[2000] == 0 and is_sorted(big, function(a, b) return a > b end)
+end
+
+function test_metamethods()
+    local mt = {__lt = function(a, b) return a.v < b.v end}
+    local t = {}
+    for i, v in ipairs({4, 1, 3, 2}) do
+        t[i] = setmetatable({v = v}, mt)
+    end
+    table.sort(t)
+    return t[1].v == 1 and t[2].v == 2 and t[3].v == 3 and t[4].v == 4
+end
+
+function test_errors()
+    local t = {3, 1, 2}
+    local partial = {3, 2, 1}
+    local calls = 0
+    local failed = message(table.sort, partial, function(a, b)
+        calls = calls + 1
+        if calls == 2 then
+            error("stop")
+        end
+        return a < b
+    end)
+    return
+        message(table.sort, {1, 2, 3}, function() return true end) ==
+            "invalid order function for sorting" and
+        message(table.sort, {1, 1}, function(a, b) return a <= b end) ==
+            "invalid order function for sorting" and
+        type(message(table.sort, {1, "x"})) == "string" and
+        type(message(table.sort, {{}, {}})) == "string" and
+        message(table.sort, t, 1) == "bad argument #2 to 'sort' (function expected, got number)" and
+        message(table.sort, nil) == "bad argument #1 to 'sort' (table expected, got nil)" and
+        failed:find("stop$") ~= nil and join(partial) == "3,2,1" and
+        join(t) == "3,1,2"
+end
+
+return
+    test_default_order() and
+    test_comparator() and
+    test_metamethods() and
+    test_errors()
@@ -24,6 +24,54 @@ function test2()
     return a == 1 and b == 2 and c == 3
 end
 
+function test3()
+    local is_even, is_odd
+    function is_even(n)
+        if n == 0 then
+            return true
+        end
+        return is_odd(n - 1)
+    end
+    function is_odd(n)
+        if n == 0 then
+            return false
+        end
+        return is_even(n - 1)
+    end
+
+    return is_even(200000) and is_odd(200001) and not is_even(200001)
+end
+
+function test4()
+    local counter = {}
+    function counter:count(n, acc)
+        if n == 0 then
+            return acc
+        end
+        return self:count(n - 1, acc + 1)
+    end
+
+    local callable = setmetatable({}, {
+        __call = function(self, n)
+            if n == 0 then
+                return "done"
+            end
+            return self(n - 1)
+        end
+    })
+
+    local function to_callback(v)
+        return tostring(v)
+    end
+
+    return
+        counter:count(200000, 0) == 200000 and
+        callable(200000) == "done" and
+        to_callback(12) == "12"
+end
+
 return
     test1() and
-    test2()
+    test2() and
+    test3() and
+    test4()
@@ -0,0 +1,84 @@
+function test1()
+    return
+        tonumber(10) == 10 and math.type(tonumber(10)) == "integer" and
+        tonumber(2.5) == 2.5 and
+        tonumber("10") == 10 and math.type(tonumber("10")) == "integer" and
+        tonumber("  -7  ") == -7 and
+        tonumber("\t0x10\n") == 16 and math.type(tonumber("0x10")) == "integer" and
+        tonumber("0xffffffffffffffff") == -1 and
+        tonumber("1e2") == 100.0 and math.type(tonumber("1e2")) == "float" and
+        tonumber(".5") == 0.5 and
+        tonumber("5.") == 5.0 and
+        tonumber("0x1p4") == 16.0 and
+        tonumber("0x.8") == 0.5 and
+        tonumber("9223372036854775808") == 9223372036854775808.0 and
+        math.type(tonumber("-9223372036854775808")) == "integer"
+end
+
+function test2()
+    return
+        tonumber("") == nil and
+        tonumber("  ") == nil and
+        tonumber("10x") == nil and
+        tonumber("1 2") == nil and
+        tonumber("0x") == nil and
+        tonumber("1e") == nil and
+        tonumber("inf") == nil and
+        tonumber("nan") == nil and
+        tonumber(nil) == nil and
+        tonumber({}) == nil and
+        tonumber(true) == nil
+end
+
+function test3()
+    return
+        tonumber("ff", 16) == 255 and
+        tonumber("  FF  ", 16) == 255 and
+        tonumber("-101", 2) == -5 and
+        tonumber("zz", 36) == 1295 and
+        tonumber("777", 8) == 511 and
+        tonumber("8", 8) == nil and
+        tonumber("", 10) == nil and
+        tonumber("-", 10) == nil and
+        tonumber("1.5", 10) == nil and
+        tonumber("10", 10) == 10
+end
+
+function test4()
+    local function message(f, ...)
+        local ok, err = pcall(f, ...)
+        return not ok and err
+    end
+
+    return
+        message(tonumber) == "bad argument #1 to 'tonumber' (value expected)" and
+        message(tonumber, "10", 1) == "bad argument #2 to 'tonumber' (base out of range)" and
+        message(tonumber, "10", 37) == "bad argument #2 to 'tonumber' (base out of range)" and
+        message(tonumber, 10, 16) == "bad argument #1 to 'tonumber' (string expected, got number)"
+end
+
+function test5()
+    -- Strings in arithmetic keep the integer subtype they are written with
+    local function values(...)
+        return ...
+    end
+    local ten, two, three, half = values("10", "2", "3", "0.5")
+    return
+        math.type(ten * two) == "integer" and ten * two == 20 and
+        math.type(three + 4) == "integer" and three + 4 == 7 and
+        math.type(ten - 1) == "integer" and
+        math.type(-ten) == "integer" and -ten == -10 and
+        math.type(ten // three) == "integer" and ten // three == 3 and
+        math.type(ten % three) == "integer" and ten % three == 1 and
+        math.type(half + 1) == "float" and half + 1 == 1.5 and
+        math.type(ten / two) == "float" and
+        math.type("10" * "2") == "integer" and
+        -7 // 2 == -4 and -7 % 2 == 1 and 7 // -2 == -4 and 7 % -2 == -1
+end
+
+return
+    test1() and
+    test2() and
+    test3() and
+    test4() and
+    test5()
@@ -0,0 +1,46 @@
+function test1()
+    return
+        tostring(nil) == "nil" and
+        tostring(true) == "true" and
+        tostring(false) == "false" and
+        tostring(1) == "1" and
+        tostring(-12) == "-12" and
+        tostring("str") == "str"
+end
+
+function test2()
+    return
+        type(tostring({})) == "string" and
+        type(tostring(print)) == "string" and
+        type(tostring(function() end)) == "string" and
+        tostring(print) == tostring(print)
+end
+
+function test3()
+    return not pcall(tostring)
+end
+
+function test4()
+    return
+        tostring(1.0) == "1.0" and
+        tostring(-2.0) == "-2.0" and
+        tostring(0.1) == "0.1" and
+        tostring(1/3) == "0.33333333333333" and
+        tostring(100000000000000.0) == "1e+14" and
+        tostring(2^53) == "9.007199254741e+15" and
+        tostring(1.5e-7) == "1.5e-07" and
+        tostring(0.0001) == "0.0001" and
+        tostring(1/0) == "inf" and
+        tostring(-1/0) == "-inf" and
+        tostring(-(0/0)) == "nan" and
+        tostring(-0.0) == "-0.0" and
+        1.5 .. "" == "1.5" and
+        2.0 .. "x" == "2.0x" and
+        string.format("%s", 3.0) == "3.0"
+end
+
+return
+    test1() and
+    test2() and
+    test3() and
+    test4()
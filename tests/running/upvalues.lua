@@ -42,7 +42,33 @@ local function test3()
     return i == 12
 end
 
+local function test4()
+    local a = 1
+    local b = 2
+    local getb = function() return b end
+    local seta = function(v) a = v end
+    local geta = function() return a end
+    local setb = function(v) b = v end
+    seta(10)
+    setb(20)
+    return geta() == 10 and getb() == 20 and a == 10 and b == 20
+end
+
+local function test5()
+    local gets = {}
+    local sets = {}
+    for i = 1, 3 do
+        local v = i
+        gets[i] = function() return v end
+        sets[i] = function(n) v = n end
+    end
+    sets[2](20)
+    return gets[1]() == 1 and gets[2]() == 20 and gets[3]() == 3
+end
+
 return
     test1() and
     test2() and
-    test3()
+    test3() and
+    test4() and
+    test5()
@@ -0,0 +1,115 @@
+local function message(f, ...)
+    local ok, err = pcall(f, ...)
+    return not ok and err
+end
+
+local function codes(s)
+    local out = ""
+    for position, code in utf8.codes(s) do
+        out = out .. position .. ":" .. code .. ","
+    end
+    return out
+end
+
+-- "hä€!", seven bytes holding four characters
+local s = "h\xC3\xA4\xE2\x82\xAC!"
+
+function test_char()
+    local a, b, c, d = utf8.codepoint(utf8.char(0x7FF, 0x800, 0xFFFF, 0x10000), 1, -1)
+    return
+        utf8.char(72, 228, 8364, 128512) == "H\xC3\xA4\xE2\x82\xAC\xF0\x9F\x98\x80" and
+        utf8.char(72, 228, 8364, 128512) == "H\u{E4}\u{20AC}\u{1F600}" and
+        utf8.char() == "" and
+        utf8.char(0) == "\0" and
+        utf8.char(0x10FFFF) == "\xF4\x8F\xBF\xBF" and
+        utf8.char("65") == "A" and
+        a == 0x7FF and b == 0x800 and c == 0xFFFF and d == 0x10000
+end
+
+function test_codepoint()
+    local a, b, c, d = utf8.codepoint(s, 1, -1)
+    return
+        utf8.codepoint(s) == 104 and
+        utf8.codepoint(s, 2) == 228 and
+        utf8.codepoint(s, -1) == 33 and
+        a == 104 and b == 228 and c == 8364 and d == 33 and
+        select("#", utf8.codepoint(s, 5, 4)) == 0 and
+        select("#", utf8.codepoint(s, 1, 2)) == 2 and
+        utf8.codepoint("\xED\xA0\x80") == 0xD800
+end
+
+function test_len()
+    local n, position = utf8.len(s, 3)
+    local bad, bad_position = utf8.len("ab\xFF")
+    local overlong, overlong_position = utf8.len("\xC0\x80")
+    local truncated, truncated_position = utf8.len("a\xE2\x82")
+    return
+        utf8.len(s) == 4 and
+        utf8.len(s, 2) == 3 and
+        utf8.len(s, -1) == 1 and
+        utf8.len(s, 8) == 0 and
+        utf8.len(s, 1, 2) == 2 and
+        utf8.len("") == 0 and
+        n == nil and position == 3 and
+        bad == nil and bad_position == 3 and
+        overlong == nil and overlong_position == 1 and
+        truncated == nil and truncated_position == 2 and
+        utf8.len("\xF4\x90\x80\x80") == nil
+end
+
+function test_offset()
+    return
+        utf8.offset(s, 1) == 1 and
+        utf8.offset(s, 3) == 4 and
+        utf8.offset(s, 4) == 7 and
+        utf8.offset(s, 5) == 8 and
+        utf8.offset(s, 6) == nil and
+        utf8.offset(s, -1) == 7 and
+        utf8.offset(s, -2) == 4 and
+        utf8.offset(s, -4) == 1 and
+        utf8.offset(s, -5) == nil and
+        utf8.offset(s, 0, 3) == 2 and
+        utf8.offset(s, 0, 6) == 4 and
+        utf8.offset(s, 0, 8) == 8 and
+        utf8.offset(s, 2, 4) == 7 and
+        utf8.offset("", 1) == 1
+end
+
+function test_codes()
+    local count = 0
+    for _ in string.gmatch(s, utf8.charpattern) do
+        count = count + 1
+    end
+    return
+        codes(s) == "1:104,2:228,4:8364,7:33," and
+        codes("") == "" and
+        utf8.charpattern == "[\0-\x7F\xC2-\xF4][\x80-\xBF]*" and
+        count == 4
+end
+
+function test_errors()
+    return
+        message(utf8.char, -1) == "bad argument #1 to 'char' (value out of range)" and
+        message(utf8.char, 65, 0x110000) == "bad argument #2 to 'char' (value out of range)" and
+        message(utf8.char, "x") == "bad argument #1 to 'char' (number expected, got string)" and
+        message(utf8.codepoint, s, 3) == "invalid UTF-8 code" and
+        message(utf8.codepoint, s, 0) == "bad argument #2 to 'codepoint' (out of range)" and
+        message(utf8.codepoint, s, 1, 8) == "bad argument #3 to 'codepoint' (out of range)" and
+        message(utf8.len, s, 9) == "bad argument #2 to 'len' (initial position out of string)" and
+        message(utf8.len, s, -100) == "bad argument #2 to 'len' (initial position out of string)" and
+        message(utf8.len, s, 1, 8) == "bad argument #3 to 'len' (final position out of string)" and
+        message(utf8.offset, s, 1, 3) == "initial position is a continuation byte" and
+        message(utf8.offset, s, 1, 9) == "bad argument #3 to 'offset' (position out of range)" and
+        message(utf8.offset, s) == "bad argument #2 to 'offset' (number expected, got no value)" and
+        message(codes, "a\xFF") == "invalid UTF-8 code" and
+        message(codes, "\xC3\xA4\x80") == "invalid UTF-8 code" and
+        message(utf8.codes, nil) == "bad argument #1 to 'codes' (string expected, got nil)"
+end
+
+return
+    test_char() and
+    test_codepoint() and
+    test_len() and
+    test_offset() and
+    test_codes() and
+    test_errors()
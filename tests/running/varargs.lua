@@ -20,6 +20,72 @@ local function test2()
         varargs(0, 1, 1, 2, 3, 5) == 4
 end
 
+local function test3()
+    local function pass(...)
+        return ...
+    end
+    local function count(...)
+        local t = {...}
+        return #t
+    end
+
+    local obj = { values = function(self, ...) return ... end }
+    local a, b, c = obj:values(1, 2, 3)
+
+    return
+        count(pass(1, 2, 3)) == 3 and
+        count(pass(1, 2, 3), 4) == 2 and
+        count(0, pass(1, 2, 3)) == 4 and
+        count(obj:values(1, 2, 3)) == 3 and
+        count((pass(1, 2, 3))) == 1 and
+        count(pass()) == 0 and
+        a == 1 and b == 2 and c == 3 and
+        #{pass(1, 2, 3)} == 3 and
+        #{pass(1, 2, 3), 4} == 2 and
+        #{0, pass(1, 2, 3)} == 4 and
+        #{x = 1, pass(1, 2, 3)} == 3 and
+        #{(pass(1, 2, 3))} == 1 and
+        #{obj:values(1, 2)} == 2
+end
+
+local function test4()
+    local function first(...)
+        return (...)
+    end
+    local function wrap(...)
+        return {...}, ...
+    end
+    local function again(...)
+        return ..., ...
+    end
+    local function nest(...)
+        local t = {..., n = 0}
+        local u = {n = 0, ...}
+        return #t, #u
+    end
+
+    local t, x, y = wrap(5, 6)
+    local p, q, r = again(1, 2)
+    local tn, un = nest(7, 8, 9)
+
+    local function grow(n, ...)
+        if n == 0 then
+            return ...
+        end
+        return grow(n - 1, n, ...)
+    end
+    local big = {grow(300)}
+
+    return
+        first(1, 2, 3) == 1 and first() == nil and
+        #t == 2 and x == 5 and y == 6 and
+        p == 1 and q == 1 and r == 2 and
+        tn == 1 and un == 3 and
+        #big == 300 and big[1] == 1 and big[300] == 300
+end
+
 return
     test1() and
-    test2()
+    test2() and
+    test3() and
+    test4()
@@ -0,0 +1,124 @@
+-- Objects are created inside functions, so that no register still refers to them once they return
+
+local function count(t)
+    local n = 0
+    for _ in pairs(t) do
+        n = n + 1
+    end
+    return n
+end
+
+local function test1()
+    local t = setmetatable({}, {__mode = "k"})
+    local kept = {}
+    local function fill()
+        t[kept] = 1
+        t[{}] = 2
+        t[function() end] = 3
+        -- Strings are values rather than objects, so they are never removed
+        t["string"] = 4
+        t[5] = {}
+    end
+    fill()
+    collectgarbage()
+    return count(t) == 3 and t[kept] == 1 and t["string"] == 4 and t[5] ~= nil
+end
+
+local function test2()
+    local t = setmetatable({}, {__mode = "v"})
+    local kept = {}
+    local function fill()
+        t[1] = kept
+        t[2] = {}
+        t.a = {}
+        t.b = "string"
+        t[{}] = kept
+    end
+    fill()
+    collectgarbage()
+    return count(t) == 3 and t[1] == kept and t[2] == nil and t.a == nil and t.b == "string"
+end
+
+local function test3()
+    local t = setmetatable({}, {__mode = "kv"})
+    local kept = {}
+    local function fill()
+        t[kept] = {}
+        t[{}] = kept
+        t.a = kept
+    end
+    fill()
+    collectgarbage()
+    return count(t) == 1 and t.a == kept
+end
+
+local function test4()
+    -- The values of weak keys only keep other keys alive while their own keys are reachable
+    local t = setmetatable({}, {__mode = "k"})
+    local first = {}
+    local function fill()
+        local second, third = {}, {}
+        t[first] = second
+        t[second] = third
+        t[third] = {}
+        -- A value that refers to its own key does not keep the entry alive
+        local cycle = {}
+        t[cycle] = {cycle}
+    end
+    fill()
+    collectgarbage()
+    local ok = count(t) == 3 and t[t[t[first]]] ~= nil
+    first = nil
+    collectgarbage()
+    return ok and count(t) == 0
+end
+
+local function test5()
+    -- Objects kept alive to be finalized are removed from weak values beforehand, but stay as weak
+    -- keys until they are freed
+    local values = setmetatable({}, {__mode = "v"})
+    local keys = setmetatable({}, {__mode = "k"})
+    local resurrected
+    local function new()
+        local object = setmetatable({}, {__gc = function(o) resurrected = o end})
+        values[1] = object
+        keys[object] = true
+    end
+    new()
+    collectgarbage()
+    return values[1] == nil and resurrected ~= nil and keys[resurrected] == true
+end
+
+local function test6()
+    -- Setting a metatable without `__mode` makes the table strong again
+    local t = setmetatable({}, {__mode = "v"})
+    setmetatable(t, nil)
+    local function fill()
+        t[1] = {}
+    end
+    fill()
+    collectgarbage()
+    return t[1] ~= nil
+end
+
+local function test7()
+    -- Tables keep working after their entries are cleared
+    local t = setmetatable({}, {__mode = "k"})
+    local function fill()
+        for i = 1, 100 do
+            t[{}] = i
+        end
+    end
+    for i = 1, 3 do
+        fill()
+        collectgarbage()
+    end
+    local kept = {}
+    t[kept] = 1
+    for i = 1, 100 do
+        t[i] = i
+    end
+    return count(t) == 101 and next(t) ~= nil and t[kept] == 1 and t[100] == 100
+end
+
+return test1() and test2() and test3() and test4() and test5() and test6() and test7()
@@ -0,0 +1,73 @@
+local function test1()
+    local function handler(e)
+        return "handled: " .. e
+    end
+
+    local ok1, a, b = xpcall(function(x, y) return x + y, x * y end, handler, 3, 4)
+    local ok2, e2 = xpcall(error, handler, "fail", 0)
+    local ok3, e3 = xpcall(function() error({}) end, function(e) return type(e) end)
+    local ok4, e4 = xpcall(function() local x = nil + 1 end, function(e) return type(e) end)
+    local ok5, e5 = xpcall(error, function() end, "dropped")
+
+    return
+        ok1 == true and a == 7 and b == 12 and
+        ok2 == false and e2 == "handled: fail" and
+        ok3 == false and e3 == "table" and
+        ok4 == false and e4 == "string" and
+        ok5 == false and e5 == nil
+end
+
+local function test2()
+    -- The handler is called at the point of the error, before to-be-closed variables are closed
+    local events = {}
+    local closer = setmetatable({}, {
+        __close = function()
+            events[#events + 1] = "close"
+        end
+    })
+    local function handler(e)
+        events[#events + 1] = "handler"
+        return e
+    end
+    local ok, e = xpcall(function()
+        local c <close> = closer
+        error("boom", 0)
+    end, handler)
+
+    return ok == false and e == "boom" and events[1] == "handler" and events[2] == "close"
+end
+
+local function test3()
+    -- Errors caught by an inner pcall never reach the handler
+    local called = 0
+    local function handler(e)
+        called = called + 1
+        return e
+    end
+    local ok, inner_ok, inner_e = xpcall(function()
+        return pcall(error, "inner", 0)
+    end, handler)
+
+    local ok2, e2 = xpcall(function()
+        xpcall(error, function(e) return "inner " .. e end, "first", 0)
+        error("second", 0)
+    end, handler)
+
+    local ok3, e3 = xpcall(error, function(e) error("in handler", 0) end, "first", 0)
+
+    return
+        ok == true and inner_ok == false and inner_e == "inner" and
+        ok2 == false and e2 == "second" and called == 1 and
+        ok3 == false and e3 == "in handler"
+end
+
+local function test4()
+    local ok, e = pcall(xpcall, error)
+    return ok == false and e == "bad argument #2 to 'xpcall' (function expected, got no value)"
+end
+
+return
+    test1() and
+    test2() and
+    test3() and
+    test4()
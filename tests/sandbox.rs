@@ -0,0 +1,88 @@
+use luster::{InvalidTableKey, Lua, StaticError, StdlibSet, Table};
+
+#[test]
+fn selective_stdlib() -> Result<(), StaticError> {
+    let mut lua = Lua::with_stdlib(StdlibSet::BASE | StdlibSet::MATH);
+    assert_eq!(
+        lua.eval::<(bool, bool, bool)>("return print ~= nil, math ~= nil, string ~= nil")?,
+        (true, true, false)
+    );
+    assert_eq!(
+        lua.eval::<(bool, bool, bool)>("return io == nil, dofile == nil, loadfile == nil")?,
+        (true, true, true)
+    );
+    assert_eq!(lua.eval::<i64>("return math.max(1, 3)")?, 3);
+
+    let mut lua = Lua::with_stdlib(StdlibSet::SAFE);
+    assert_eq!(
+        lua.eval::<(bool, bool, bool)>("return require == nil, debug == nil, io == nil")?,
+        (true, true, true)
+    );
+    assert_eq!(lua.eval::<String>("return ('abc'):upper()")?, "ABC");
+
+    let mut lua = Lua::with_stdlib(StdlibSet::empty());
+    assert_eq!(lua.eval::<bool>("return print == nil")?, true);
+
+    assert!(StdlibSet::ALL.contains(StdlibSet::SAFE | StdlibSet::IO));
+    assert!(!StdlibSet::SAFE.contains(StdlibSet::IO));
+    assert!(StdlibSet::empty().is_empty());
+    Ok(())
+}
+
+#[test]
+fn frozen_globals() -> Result<(), StaticError> {
+    let mut lua = Lua::with_stdlib(StdlibSet::SAFE);
+    lua.exec("api = { value = 1 }")?;
+    lua.freeze_globals();
+
+    for source in &[
+        "x = 1",
+        "api = nil",
+        "rawset(_G, 'x', 1)",
+        "setmetatable(_G, {})",
+    ] {
+        assert!(lua.exec(source).is_err(), "{} should fail", source);
+    }
+    match lua.exec("x = 1").map_err(StaticError::without_traceback) {
+        Err(StaticError::LocatedError(_, error)) => match *error {
+            StaticError::InvalidTableKey(InvalidTableKey::ReadOnly) => {}
+            error => panic!("unexpected error {:?}", error),
+        },
+        res => panic!("unexpected result {:?}", res),
+    }
+
+    // Only the globals table itself is frozen, not the tables stored in it
+    lua.exec("api.value = 2")?;
+    assert_eq!(lua.eval::<i64>("return api.value")?, 2);
+    assert_eq!(lua.eval::<i64>("local x = 3 return x")?, 3);
+    Ok(())
+}
+
+#[test]
+fn frozen_table() {
+    let mut lua = Lua::new();
+    lua.mutate(|mc, _| {
+        let table = Table::new(mc);
+        table.set(mc, 1, 1).unwrap();
+        table.set(mc, 2, 2).unwrap();
+        assert!(!table.is_frozen());
+        table.freeze(mc);
+        assert!(table.is_frozen());
+        assert!(table.set(mc, 1, 3).is_err());
+        assert!(table.insert(mc, 2, 1, 0).is_err());
+        assert!(table.remove(mc, 2, 1).is_err());
+        assert!(table.move_range(mc, 1, 2, table, 2).is_err());
+        assert_eq!(table.get(1), luster::Value::Integer(1));
+        assert_eq!(table.length(), 2);
+    });
+}
+
+#[test]
+fn table_extensions() -> Result<(), StaticError> {
+    let mut lua = Lua::with_stdlib(StdlibSet::SAFE);
+    assert!(lua.eval::<bool>("return table.clone == nil and table.equals == nil")?);
+
+    let mut lua = Lua::with_stdlib(StdlibSet::BASE | StdlibSet::TABLE_EXTENSIONS);
+    assert!(lua.eval::<bool>("return table.equals(table.clone({ { 1 } }), { { 1 } })")?);
+    Ok(())
+}
@@ -0,0 +1,38 @@
+use std::cell::RefCell;
+
+use luster::{Lua, StaticError, String, Value};
+
+#[test]
+fn scoped_callback() -> Result<(), StaticError> {
+    let mut lua = Lua::new();
+    let pushed = RefCell::new(Vec::new());
+
+    let res = lua.scope(|lua, scope| {
+        lua.mutate(|mc, root| {
+            let push = scope.callback(mc, |_, args| {
+                let mut pushed = pushed.borrow_mut();
+                for arg in args {
+                    if let Value::Integer(i) = arg {
+                        pushed.push(i);
+                    }
+                }
+                Ok(vec![Value::Integer(pushed.len() as i64)])
+            });
+            root.globals
+                .set(mc, String::new_static(b"push"), push)
+                .unwrap();
+        });
+        lua.eval::<i64>("push(1, 2) return push(3)")
+    })?;
+    assert_eq!(res, 3);
+    assert_eq!(*pushed.borrow(), vec![1, 2, 3]);
+
+    match lua.exec("push(4)").map_err(StaticError::without_traceback) {
+        Err(StaticError::RuntimeError(message)) => {
+            assert_eq!(message, "scoped callback called after its scope ended")
+        }
+        res => panic!("unexpected result {:?}", res),
+    }
+    assert_eq!(*pushed.borrow(), vec![1, 2, 3]);
+    Ok(())
+}
@@ -0,0 +1,117 @@
+use std::string::String as StdString;
+
+use serde::{Deserialize, Serialize};
+
+use luster::{from_value, to_value, Lua, SerdeError, StaticError, String};
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+enum Shape {
+    Point,
+    Circle(f64),
+    Rect { w: i64, h: i64 },
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Config {
+    name: StdString,
+    retries: u32,
+    ratio: f64,
+    tags: Vec<StdString>,
+    shapes: Vec<Shape>,
+    parent: Option<Box<Config>>,
+}
+
+fn set_global<T: Serialize + 'static>(lua: &mut Lua, name: &'static str, value: T) {
+    lua.mutate(move |mc, root| {
+        let value = to_value(mc, &value).unwrap();
+        root.globals
+            .set(mc, String::new_static(name.as_bytes()), value)
+            .unwrap();
+    });
+}
+
+fn get_global<T: for<'de> Deserialize<'de> + 'static>(
+    lua: &mut Lua,
+    name: &'static str,
+) -> Result<T, SerdeError> {
+    lua.mutate(move |_, root| from_value(root.globals.get(String::new_static(name.as_bytes()))))
+}
+
+#[test]
+fn round_trip() -> Result<(), StaticError> {
+    let mut lua = Lua::new();
+    set_global(
+        &mut lua,
+        "config",
+        Config {
+            name: "main".to_owned(),
+            retries: 3,
+            ratio: 0.5,
+            tags: vec!["a".to_owned(), "b".to_owned()],
+            shapes: vec![Shape::Point, Shape::Circle(1.5), Shape::Rect { w: 2, h: 3 }],
+            parent: None,
+        },
+    );
+    assert!(lua.eval::<bool>(
+        r#"
+            return config.name == "main" and config.tags[2] == "b" and
+                config.shapes[1] == "Point" and config.shapes[2].Circle == 1.5 and
+                config.shapes[3].Rect.h == 3 and config.parent == nil
+        "#
+    )?);
+
+    lua.exec(
+        r#"
+            config.retries = config.retries + 1
+            config.ratio = 2
+            config.shapes[3].Rect.w = 4.0
+            table.insert(config.tags, "c")
+            config.parent = { name = "base", retries = 0, ratio = 1, tags = {}, shapes = {} }
+        "#,
+    )?;
+    let config: Config = get_global(&mut lua, "config").unwrap();
+    assert_eq!(
+        config,
+        Config {
+            name: "main".to_owned(),
+            retries: 4,
+            ratio: 2.0,
+            tags: vec!["a".to_owned(), "b".to_owned(), "c".to_owned()],
+            shapes: vec![Shape::Point, Shape::Circle(1.5), Shape::Rect { w: 4, h: 3 },],
+            parent: Some(Box::new(Config {
+                name: "base".to_owned(),
+                retries: 0,
+                ratio: 1.0,
+                tags: Vec::new(),
+                shapes: Vec::new(),
+                parent: None,
+            })),
+        }
+    );
+
+    Ok(())
+}
+
+#[test]
+fn errors() -> Result<(), StaticError> {
+    let mut lua = Lua::new();
+    lua.exec(
+        r#"
+            cyclic = { name = "cyclic", retries = 0, ratio = 0, tags = {}, shapes = {} }
+            cyclic.parent = cyclic
+            with_function = { print }
+            wrong_type = { name = 1 }
+        "#,
+    )?;
+    match get_global::<Config>(&mut lua, "cyclic") {
+        Err(SerdeError::Cycle) => {}
+        result => panic!("wrong result {:?}", result),
+    }
+    match get_global::<Vec<i64>>(&mut lua, "with_function") {
+        Err(SerdeError::Unsupported("function")) => {}
+        result => panic!("wrong result {:?}", result),
+    }
+    assert!(get_global::<Config>(&mut lua, "wrong_type").is_err());
+
+    Ok(())
+}
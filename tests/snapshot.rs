@@ -0,0 +1,159 @@
+use luster::{Callback, CallbackResult, Lua, SnapshotError, StaticError, String, Value};
+
+fn snapshot_error(error: StaticError) -> SnapshotError {
+    match error {
+        StaticError::SnapshotError(error) => error,
+        error => panic!("wrong error {}", error),
+    }
+}
+
+#[test]
+fn round_trip() -> Result<(), StaticError> {
+    let mut lua = Lua::new();
+    lua.exec(
+        r#"
+            data = { name = "world", list = { 1, 2.5, true, "x" } }
+            data.self = data
+            setmetatable(data, { __index = function(t, k) return k .. "!" end })
+
+            local count = 0
+            function increment()
+                count = count + 1
+                return count
+            end
+            function current()
+                return count
+            end
+            increment()
+
+            greet = function(name) return string.format("hello %s", name) end
+            upper = string.upper
+            out = io.stdout
+        "#,
+    )?;
+    let snapshot = lua.snapshot()?;
+
+    let mut restored = Lua::new();
+    restored.restore(&snapshot)?;
+    assert!(restored.eval::<bool>(
+        r#"
+            return data.self == data and data.list[2] == 2.5 and data.list[3] == true and
+                data.missing == "missing!"
+        "#
+    )?);
+    // The two closures still share the same upvalue
+    assert_eq!(restored.eval::<i64>("increment() return current()")?, 2);
+    assert_eq!(
+        restored.eval::<std::string::String>("return greet(data.name):upper()")?,
+        "HELLO WORLD"
+    );
+    assert!(restored.eval::<bool>("return upper == string.upper and out == io.stdout")?);
+
+    // The original is unaffected
+    assert_eq!(lua.eval::<i64>("return current()")?, 1);
+
+    Ok(())
+}
+
+#[test]
+fn suspended_coroutine() -> Result<(), StaticError> {
+    let mut lua = Lua::new();
+    lua.exec(
+        r#"
+            local function numbers(n)
+                local i = 0
+                local step = function() i = i + 1 end
+                while true do
+                    step()
+                    coroutine.yield(i * n)
+                end
+            end
+            co = coroutine.create(numbers)
+            coroutine.resume(co, 10)
+            coroutine.resume(co)
+            fresh = coroutine.create(numbers)
+        "#,
+    )?;
+    let snapshot = lua.snapshot()?;
+
+    let mut restored = Lua::new();
+    restored.restore(&snapshot)?;
+    assert_eq!(
+        restored.eval::<(bool, i64)>("return coroutine.resume(co)")?,
+        (true, 30)
+    );
+    assert_eq!(
+        restored.eval::<(bool, i64)>("return coroutine.resume(fresh, 2)")?,
+        (true, 2)
+    );
+    assert_eq!(
+        restored.eval::<std::string::String>("return coroutine.status(co)")?,
+        "suspended"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn host_callbacks() -> Result<(), StaticError> {
+    fn add_callback(lua: &mut Lua, result: i64) {
+        lua.mutate(|mc, root| {
+            let callback = Callback::new_immediate(mc, move |_| {
+                Ok(CallbackResult::Return(vec![Value::Integer(result)]))
+            });
+            root.globals
+                .set(mc, String::new_static(b"host"), callback)
+                .unwrap();
+            root.name_host_values(mc);
+        });
+    }
+
+    let mut lua = Lua::new();
+    add_callback(&mut lua, 1);
+    lua.exec("local host = host function call() return host() end")?;
+    let snapshot = lua.snapshot()?;
+
+    let mut restored = Lua::new();
+    add_callback(&mut restored, 2);
+    restored.restore(&snapshot)?;
+    assert_eq!(restored.eval::<i64>("return call()")?, 2);
+
+    let mut missing = Lua::new();
+    match snapshot_error(missing.restore(&snapshot).unwrap_err()) {
+        SnapshotError::UnknownName(name) => assert_eq!(name, "host"),
+        error => panic!("wrong error {}", error),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn unsupported() -> Result<(), StaticError> {
+    let mut lua = Lua::new();
+    lua.exec("wrapped = coroutine.wrap(function() end)")?;
+    match snapshot_error(lua.snapshot().unwrap_err()) {
+        SnapshotError::UnnamedCallback => {}
+        error => panic!("wrong error {}", error),
+    }
+
+    let mut lua = Lua::new();
+    lua.exec(
+        r#"
+            co = coroutine.create(function()
+                pcall(coroutine.yield)
+            end)
+            coroutine.resume(co)
+        "#,
+    )?;
+    match snapshot_error(lua.snapshot().unwrap_err()) {
+        SnapshotError::ThreadNotAtRest => {}
+        error => panic!("wrong error {}", error),
+    }
+
+    match snapshot_error(Lua::new().restore(b"not a snapshot").unwrap_err()) {
+        SnapshotError::BadSignature => {}
+        error => panic!("wrong error {}", error),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,110 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use luster::{Lua, String, Table, Value};
+
+fn hash<T: Hash>(t: T) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    t.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[test]
+fn string_storage() {
+    let mut lua = Lua::new();
+    lua.mutate(|mc, root| {
+        let tiny = String::new(mc, b"tiny");
+        let short = String::new(mc, b"a string that is not so short");
+        let long = String::new(mc, &[b'x'; 100]);
+        assert!(format!("{:?}", tiny).starts_with("Inline("));
+        assert!(format!("{:?}", short).starts_with("Short("));
+        assert!(format!("{:?}", long).starts_with("Long("));
+
+        // Equal strings hash the same however they are stored
+        for &(s, bytes) in &[
+            (tiny, &b"tiny"[..]),
+            (short, &b"a string that is not so short"[..]),
+            (long, &[b'x'; 100][..]),
+        ] {
+            assert_eq!(s.as_bytes(), bytes);
+            assert_eq!(s.len(), bytes.len() as i64);
+            let copy = String::new(mc, bytes);
+            assert_eq!(Value::String(s), Value::String(copy));
+            assert_eq!(s.hash_value(), copy.hash_value());
+            assert_eq!(hash(s), hash(copy));
+        }
+        assert_eq!(String::new_static(b"tiny").hash_value(), tiny.hash_value());
+        assert_ne!(Value::String(short), Value::String(long));
+
+        // Table keys are found whichever way they are stored
+        let t = Table::new(mc);
+        t.set(mc, short, 1).unwrap();
+        t.set(mc, long, 2).unwrap();
+        t.set(mc, String::new_static(b"tiny"), 3).unwrap();
+        assert_eq!(
+            t.get(String::new(mc, b"a string that is not so short")),
+            Value::Integer(1)
+        );
+        assert_eq!(t.get(String::new(mc, &[b'x'; 100])), Value::Integer(2));
+        assert_eq!(t.get(tiny), Value::Integer(3));
+
+        // Interned short strings are shared
+        let a = root.interned_strings.new_string(mc, &[b'y'; 30]);
+        let b = root.interned_strings.new_string(mc, &[b'y'; 30]);
+        match (a, b) {
+            (String::Short(a), String::Short(b)) => assert!(gc_arena::Gc::ptr_eq(a, b)),
+            _ => panic!("not short strings"),
+        }
+    });
+}
+
+fn is_same_string<'gc>(a: Value<'gc>, b: Value<'gc>) -> bool {
+    match (a, b) {
+        (Value::String(String::Short(a)), Value::String(String::Short(b))) => {
+            gc_arena::Gc::ptr_eq(a, b)
+        }
+        _ => false,
+    }
+}
+
+#[test]
+fn interned_strings() {
+    let mut lua = Lua::new();
+    lua.exec(
+        r#"
+            local s = "a string that is not so short"
+            concatenated = s .. "!"
+            repeated = ("a string that is not so short!"):sub(1, -1)
+            constant = "a string that is not so short!"
+        "#,
+    )
+    .unwrap();
+    lua.mutate(|_, root| {
+        let get = |name: &'static str| root.globals.get(String::new_static(name.as_bytes()));
+        assert!(is_same_string(get("concatenated"), get("repeated")));
+        assert!(is_same_string(get("concatenated"), get("constant")));
+    });
+
+    // Strings in the set are freed once they are unreachable, and the reachable ones are kept
+    lua.set_collector_running(false);
+    lua.collect_all();
+    let before = lua.total_allocated();
+    lua.mutate(|mc, root| {
+        for i in 0..1000 {
+            root.interned_strings
+                .new_string(mc, format!("a string that is not short {}", i).as_bytes());
+        }
+    });
+    assert!(lua.total_allocated() > before);
+    lua.collect_all();
+    assert!(lua.total_allocated() <= before);
+    lua.exec("assert(concatenated .. '' == 'a string that is not so short!')")
+        .unwrap();
+    lua.mutate(|mc, root| {
+        let string = root
+            .interned_strings
+            .new_string(mc, b"a string that is not so short!");
+        let global = root.globals.get(String::new_static(b"concatenated"));
+        assert!(is_same_string(Value::String(string), global));
+    });
+}
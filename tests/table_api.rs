@@ -0,0 +1,152 @@
+use luster::{InvalidTableKey, Lua, StaticError, String, Table, Value};
+
+#[test]
+fn table_iter() -> Result<(), StaticError> {
+    let mut lua = Lua::new();
+    lua.exec("t = { 10, 20, 30, nil, 50, x = 'x', y = 'y' }")?;
+    lua.mutate(|mc, root| {
+        let t = match root.globals.get(String::new_static(b"t")) {
+            Value::Table(t) => t,
+            _ => panic!("not a table"),
+        };
+
+        let mut entries = t.iter().collect::<Vec<_>>();
+        assert_eq!(entries.len(), 6);
+        let mut key = Value::Nil;
+        for &(k, v) in &entries {
+            assert_eq!(t.next(key).unwrap(), Some((k, v)));
+            key = k;
+        }
+        entries.retain(|(k, _)| k.type_name() == "string");
+        assert_eq!(entries.len(), 2);
+
+        // Entries may be removed while iterating
+        for (k, _) in t.iter() {
+            t.set(mc, k, Value::Nil).unwrap();
+        }
+        assert_eq!(t.iter().count(), 0);
+        assert_eq!(Table::new(mc).iter().next(), None);
+    });
+
+    lua.exec("s = { 'a', 'b', 'c' }")?;
+    lua.mutate(|_, root| {
+        let s = match root.globals.get(String::new_static(b"s")) {
+            Value::Table(s) => s,
+            _ => panic!("not a table"),
+        };
+        let values = s.array_iter().collect::<Vec<_>>();
+        assert_eq!(values.len(), 3);
+        assert_eq!(values[1], s.get(2));
+        assert_eq!(s.array_iter().len(), 3);
+        assert_eq!(s.array_iter().rev().next(), Some(s.get(3)));
+    });
+    Ok(())
+}
+
+#[test]
+fn table_capacity() -> Result<(), StaticError> {
+    let mut lua = Lua::new();
+    lua.mutate(|mc, _| {
+        let t = Table::with_capacity(mc, 4, 2);
+        assert_eq!(t.array_len(), 4);
+        assert_eq!(t.length(), 0);
+        for i in 1..=6 {
+            t.push(mc, i * 10).unwrap();
+        }
+        assert_eq!(t.length(), 6);
+        assert_eq!(t.get(6), Value::Integer(60));
+        assert!(t.array_len() >= 4);
+
+        let t = Table::new(mc);
+        t.set(mc, 3, String::new_static(b"c")).unwrap();
+        t.set(mc, 1, String::new_static(b"a")).unwrap();
+        t.reserve_array(mc, 8);
+        assert_eq!(t.array_len(), 8);
+        assert_eq!(t.get(3), Value::String(String::new_static(b"c")));
+        assert_eq!(t.iter().count(), 2);
+
+        let frozen = Table::new(mc);
+        frozen.freeze(mc);
+        assert!(frozen.push(mc, 1).is_err());
+    });
+
+    lua.exec("t = { 1, 2, 3, 4, 5, 6, 7, 8, 9, 10 }")?;
+    lua.mutate(|_, root| match root.globals.get(String::new_static(b"t")) {
+        Value::Table(t) => assert!(t.array_len() >= 10),
+        _ => panic!("not a table"),
+    });
+    Ok(())
+}
+
+#[test]
+fn table_keys() {
+    let mut lua = Lua::new();
+    lua.mutate(|mc, _| {
+        let t = Table::new(mc);
+        t.set(mc, Value::Number(1.0), 1).unwrap();
+        t.set(mc, Value::Number(1e10), 2).unwrap();
+        assert_eq!(t.get(Value::Integer(1)), Value::Integer(1));
+        assert_eq!(t.get(Value::Integer(10_000_000_000)), Value::Integer(2));
+        assert_eq!(t.next(Value::Nil).unwrap().unwrap().0, Value::Integer(1));
+        match t.set(mc, Value::Nil, 1) {
+            Err(InvalidTableKey::IsNil) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+        match t.set(mc, Value::Number(std::f64::NAN), 1) {
+            Err(InvalidTableKey::IsNaN) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+        assert_eq!(t.get(Value::Number(std::f64::NAN)), Value::Nil);
+    });
+}
+
+#[test]
+fn table_length() -> Result<(), StaticError> {
+    let mut lua = Lua::new();
+    lua.exec("t = { 1, nil, 3 } setmetatable(t, { __len = function() return 42 end })")?;
+    lua.mutate(|mc, root| {
+        let t = match root.globals.get(String::new_static(b"t")) {
+            Value::Table(t) => t,
+            _ => panic!("not a table"),
+        };
+        // `length` never calls `__len`, and finds the same border as `#` would without it
+        assert_eq!(t.length(), 3);
+
+        // A sequence entirely in the map part is found by the unbound search
+        let t = Table::with_capacity(mc, 0, 8);
+        for i in 1..=5 {
+            t.set(mc, i, i).unwrap();
+        }
+        assert_eq!(t.array_len(), 0);
+        assert_eq!(t.length(), 5);
+    });
+    Ok(())
+}
+
+#[test]
+fn table_array_part() -> Result<(), StaticError> {
+    let mut lua = Lua::new();
+    lua.exec(
+        r#"
+            appended = {}
+            for i = 1, 1000 do appended[#appended + 1] = i end
+            reversed = {}
+            for i = 1000, 1, -1 do reversed[i] = i end
+            sparse = {}
+            for i = 1, 1000 do sparse[i * 1000] = i end
+        "#,
+    )?;
+    lua.mutate(|_, root| {
+        let get = |name: &'static str| match root.globals.get(String::new_static(name.as_bytes())) {
+            Value::Table(t) => t,
+            _ => panic!("not a table"),
+        };
+        // Dense integer keys end up in the array part however they are inserted, just as in
+        // PUC-Rio Lua
+        assert_eq!(get("appended").array_len(), 1024);
+        assert_eq!(get("reversed").array_len(), 1024);
+        assert_eq!(get("reversed").length(), 1000);
+        assert!(get("sparse").array_len() <= 1);
+    });
+    Ok(())
+}
@@ -0,0 +1,150 @@
+use std::cell::Cell;
+use std::rc::Rc;
+
+use gc_arena::MutationContext;
+use gc_sequence::{self as sequence, SequenceExt, SequenceResultExt};
+use luster::{
+    compile, AnyUserData, Callback, CallbackResult, Closure, Error, Function, Lua, StaticError,
+    String, Table, ThreadSequence, UserData, Value,
+};
+
+struct Counter(i64);
+
+impl UserData for Counter {
+    fn metatable<'gc>(mc: MutationContext<'gc, '_>, metatable: Table<'gc>) {
+        let methods = Table::new(mc);
+        methods
+            .set(
+                mc,
+                String::new_static(b"add"),
+                Callback::new_sequence(mc, |args| {
+                    Ok(sequence::from_fn_with(args, |mc, args| {
+                        match (args.get(0), args.get(1)) {
+                            (Some(Value::UserData(u)), Some(&Value::Integer(n))) => {
+                                u.borrow_mut::<Counter>(mc).unwrap().0 += n;
+                            }
+                            _ => panic!("bad arguments to add"),
+                        }
+                        Ok(CallbackResult::Return(vec![]))
+                    }))
+                }),
+            )
+            .unwrap();
+        methods
+            .set(
+                mc,
+                String::new_static(b"get"),
+                Callback::new_immediate(mc, |args| match args.get(0) {
+                    Some(Value::UserData(u)) => Ok(CallbackResult::Return(vec![Value::Integer(
+                        u.borrow::<Counter>().unwrap().0,
+                    )])),
+                    _ => panic!("bad arguments to get"),
+                }),
+            )
+            .unwrap();
+        metatable
+            .set(mc, String::new_static(b"__index"), methods)
+            .unwrap();
+    }
+}
+
+struct Other;
+
+impl UserData for Other {}
+
+#[test]
+fn typed_userdata() -> Result<(), Box<StaticError>> {
+    let mut lua = Lua::new();
+    lua.sequence(|root| {
+        sequence::from_fn_with(root, |mc, root| {
+            let first = AnyUserData::new_typed(mc, root.userdata_metatables, Counter(0));
+            let second = AnyUserData::new_typed(mc, root.userdata_metatables, Counter(10));
+            let other = AnyUserData::new_typed(mc, root.userdata_metatables, Other);
+            assert!(first.is::<Counter>() && !first.is::<Other>());
+            assert!(first.borrow::<Other>().is_none());
+            assert_eq!(first.metatable(), second.metatable());
+            assert_ne!(first.metatable(), other.metatable());
+            assert_eq!(
+                first.metatable(),
+                Some(root.userdata_metatables.get::<Counter>(mc))
+            );
+
+            root.globals.set(mc, String::new_static(b"first"), first)?;
+            root.globals
+                .set(mc, String::new_static(b"second"), second)?;
+            Ok(())
+        })
+        .and_then_with(root, |mc, root, _| {
+            Ok(Closure::new(
+                mc,
+                compile(
+                    mc,
+                    root.interned_strings,
+                    "=test",
+                    &br#"
+                        first:add(2)
+                        first:add(3)
+                        second:add(1)
+                        return first:get(), second:get(),
+                            getmetatable(first) == getmetatable(second)
+                    "#[..],
+                )?,
+                Some(root.globals),
+            )?)
+        })
+        .and_chain_with(root, |mc, root, closure| {
+            Ok(ThreadSequence::call_function(
+                mc,
+                root.main_thread,
+                Function::Closure(closure),
+                &[],
+            )?)
+        })
+        .map_ok(|res| {
+            assert_eq!(
+                res,
+                vec![Value::Integer(5), Value::Integer(11), Value::Boolean(true)]
+            )
+        })
+        .map_err(Error::to_static)
+        .boxed()
+    })?;
+
+    Ok(())
+}
+
+struct DropFlag(Rc<Cell<bool>>);
+
+impl Drop for DropFlag {
+    fn drop(&mut self) {
+        self.0.set(true);
+    }
+}
+
+impl UserData for DropFlag {}
+
+#[test]
+fn userdata_dropped() {
+    let dropped = Rc::new(Cell::new(false));
+    let kept = Rc::new(Cell::new(false));
+
+    let mut lua = Lua::new();
+    {
+        let dropped = dropped.clone();
+        let kept = kept.clone();
+        lua.mutate(move |mc, root| {
+            AnyUserData::new_typed(mc, root.userdata_metatables, DropFlag(dropped));
+            let kept = AnyUserData::new_typed(mc, root.userdata_metatables, DropFlag(kept));
+            root.globals
+                .set(mc, String::new_static(b"kept"), kept)
+                .unwrap();
+        });
+    }
+
+    lua.collect_all();
+    assert!(dropped.get());
+    assert!(!kept.get());
+
+    drop(lua);
+    assert!(kept.get());
+}